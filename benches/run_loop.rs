@@ -0,0 +1,85 @@
+//! Benchmarks for the synchronous run loop.
+//!
+//! Compare runs with and without the `diagnostics` feature to measure its overhead.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use std::{io::Cursor, ops::ControlFlow};
+use vinezombie::{
+    client::{
+        channel::{ChannelSpec, ClosedSender, Sender, SenderRef, SyncChannels},
+        conn::Bidir,
+        queue::QueueEditGuard,
+        Client, ClientState, Handler, SelfMadeHandler,
+    },
+    ircmsg::ServerMsg,
+};
+
+const MSGS: usize = 1000;
+
+/// Handler that finishes after receiving a set number of messages.
+struct Count(usize);
+
+impl Handler for Count {
+    type Value = ();
+
+    fn handle(
+        &mut self,
+        _: &ServerMsg<'_>,
+        _: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        _: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        self.0 -= 1;
+        if self.0 == 0 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+impl SelfMadeHandler for Count {
+    type Receiver<Spec: ChannelSpec> = ();
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        _: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        (Box::<ClosedSender<()>>::default(), ())
+    }
+}
+
+fn input() -> Vec<u8> {
+    let msg = "@time=2024-01-01T00:00:00.000Z :nick!user@host PRIVMSG #chan :hello world\r\n";
+    msg.repeat(MSGS).into_bytes()
+}
+
+fn run_loop(c: &mut Criterion) {
+    let input = input();
+    let mut group = c.benchmark_group("run_loop");
+    group.throughput(Throughput::Elements(MSGS as u64));
+    for handlers in [1usize, 8] {
+        group.bench_function(format!("{handlers}_handlers"), |b| {
+            b.iter_batched(
+                || {
+                    let io = Bidir(Cursor::new(input.clone()), std::io::sink());
+                    let mut client = Client::new(io, SyncChannels);
+                    for _ in 0..handlers {
+                        let _ = client.add((), Count(MSGS));
+                    }
+                    client
+                },
+                |mut client| {
+                    client.run().unwrap();
+                    client
+                },
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, run_loop);
+criterion_main!(benches);
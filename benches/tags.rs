@@ -0,0 +1,46 @@
+//! Benchmarks for parsing message tags.
+//!
+//! Compares parsing every tag up front against the lazy parsing done by the codec,
+//! for messages whose tags are never read and for messages where one tag is looked up.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+use vinezombie::{
+    ircmsg::{ServerMsg, Tags, TagsRef},
+    string::Line,
+};
+
+const MSG: &str = concat!(
+    r"@account=someone;batch=abc123;+draft/reply=msgid123;label=xyz;",
+    r"msgid=63E1033A051D4B41B1AB1FA3CF4B243E;+typing=active;",
+    r"+example/escaped=a\sb\:c\\d;time=2024-01-01T00:00:00.000Z ",
+    ":nick!user@host PRIVMSG #chan :hello world"
+);
+
+fn tags(c: &mut Criterion) {
+    let line = Line::from_str(MSG);
+    let word = TagsRef::from_msg(&line).as_word().clone();
+    let mut group = c.benchmark_group("tags");
+    group.bench_function("eager_parse", |b| {
+        b.iter(|| TagsRef::new(black_box(word.clone())).to_tags());
+    });
+    group.bench_function("eager_get", |b| {
+        b.iter(|| TagsRef::new(black_box(word.clone())).to_tags().get("time").cloned());
+    });
+    group.bench_function("lazy_parse", |b| {
+        b.iter(|| Tags::parse(black_box(word.clone())));
+    });
+    group.bench_function("lazy_get", |b| {
+        b.iter(|| Tags::parse(black_box(word.clone())).get("time").cloned());
+    });
+    group.bench_function("ref_get", |b| {
+        b.iter(|| TagsRef::new(black_box(word.clone())).get("time"));
+    });
+    group.bench_function("msg_parse", |b| {
+        b.iter(|| ServerMsg::parse(black_box(line.clone())).unwrap());
+    });
+    group.finish();
+}
+
+criterion_group!(benches, tags);
+criterion_main!(benches);
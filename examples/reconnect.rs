@@ -24,6 +24,7 @@ use vinezombie::{
 //
 // WARNING: This example does NOT implement progressively less-frequent reconnections.
 // This is strongly recommended to do robust usecase.
+// `client::reconnect::Reconnector` implements this, and is a good choice for most usecases.
 
 fn make_sock(
     tls_config: &mut Option<TlsConfig>,
@@ -0,0 +1,263 @@
+//! A line-based tool for poking at IRC servers.
+//!
+//! Usage: `cargo run --example vzdebug -- [ircs://|irc://]host[:port]`
+//!
+//! Connects to the provided server (using TLS unless the address starts with `irc://`),
+//! registers, then reads raw IRC messages from standard input and sends them to the server.
+//! Every message received from the server is printed with its parts colored and
+//! a timestamp relative to when the connection was opened.
+//! Closing standard input sends a QUIT and exits.
+//!
+//! The following environment variables are also read:
+//! * `VZ_NICK`: The nickname to use. Defaults to a random one.
+//! * `VZ_SASL_USER` and `VZ_SASL_PASS`: Credentials for SASL PLAIN authentication.
+//! * `NO_COLOR`: If set, disables colored output.
+
+use std::{
+    borrow::Borrow,
+    sync::mpsc::{Receiver, TryRecvError},
+    time::{Duration, Instant},
+};
+use vinezombie::{
+    client::{
+        self,
+        auth::{sasl::Password, Clear, Secret},
+        channel::SyncChannels,
+        conn::{DroppedLine, ServerAddr},
+        handlers::{AutoPong, YieldAll},
+        register::{register_as_client, Options},
+        state::{ClientSource, ConnectTimes, RegTimings},
+        Client,
+    },
+    info::SessionInfo,
+    ircmsg::{ClientMsg, ServerMsg},
+    names::cmd::QUIT,
+    string::{Line, Nick, NoNul},
+};
+
+const RESET: &str = "\x1b[0m";
+const DIM: &str = "\x1b[2m";
+const TAGS: &str = "\x1b[35m";
+const SOURCE: &str = "\x1b[36m";
+const KIND: &str = "\x1b[1;33m";
+const ERROR: &str = "\x1b[31m";
+
+/// Messages whose arguments should never be printed.
+const REDACTED: [&[u8]; 3] = [b"AUTHENTICATE", b"OPER", b"PASS"];
+
+struct Printer {
+    start: Instant,
+    color: bool,
+}
+
+impl Printer {
+    fn color(&self, color: &'static str) -> &'static str {
+        if self.color {
+            color
+        } else {
+            ""
+        }
+    }
+    fn stamp(&self) -> String {
+        let elapsed = self.start.elapsed();
+        format!(
+            "{}[{:>4}.{:03}]{}",
+            self.color(DIM),
+            elapsed.as_secs(),
+            elapsed.subsec_millis(),
+            self.color(RESET)
+        )
+    }
+    fn recv(&self, msg: &ServerMsg<'_>) {
+        let mut line = self.stamp();
+        line.push_str(" <- ");
+        let tags = msg.tags.to_string();
+        if !tags.is_empty() {
+            line.push_str(&format!("{}{tags}{} ", self.color(TAGS), self.color(RESET)));
+        }
+        if let Some(source) = &msg.source {
+            line.push_str(&format!("{}:{source}{} ", self.color(SOURCE), self.color(RESET)));
+        }
+        let kind: &[u8] = msg.kind.borrow();
+        let kind_str = String::from_utf8_lossy(kind);
+        line.push_str(&format!("{}{kind_str}{}", self.color(KIND), self.color(RESET)));
+        if !msg.args.is_empty() {
+            if REDACTED.contains(&kind) {
+                line.push_str(" <redacted>");
+            } else {
+                line.push_str(&format!(" {}", msg.args));
+            }
+        }
+        println!("{line}");
+    }
+    fn send(&self, msg: &ClientMsg<'_>) {
+        let args = if msg.args.is_empty() {
+            String::new()
+        } else if REDACTED.contains(&msg.cmd.as_bytes()) {
+            " <redacted>".to_owned()
+        } else {
+            format!(" {}", msg.args)
+        };
+        println!(
+            "{} -> {}{}{}{}",
+            self.stamp(),
+            self.color(KIND),
+            msg.cmd,
+            self.color(RESET),
+            args
+        );
+    }
+    fn note(&self, note: impl std::fmt::Display) {
+        println!("{} -- {note}", self.stamp());
+    }
+    fn error(&self, error: impl std::fmt::Display) {
+        println!("{} !! {}{error}{}", self.stamp(), self.color(ERROR), self.color(RESET));
+    }
+}
+
+/// Parses a server address of the form `[ircs://|irc://]host[:port]`.
+fn parse_addr(arg: &str) -> Result<ServerAddr<'static>, String> {
+    let (tls, rest) = if let Some(rest) = arg.strip_prefix("ircs://") {
+        (true, rest)
+    } else if let Some(rest) = arg.strip_prefix("irc://") {
+        (false, rest)
+    } else {
+        (true, arg)
+    };
+    let rest = rest.trim_end_matches('/');
+    let (host, port) = match rest.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+            let port = port.parse::<u16>().map_err(|e| format!("invalid port: {e}"))?;
+            (host, Some(port))
+        }
+        _ => (rest, None),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let mut addr =
+        ServerAddr::from_host(host.to_owned()).map_err(|e| format!("invalid host: {e}"))?;
+    addr.tls = tls;
+    addr.port = port;
+    Ok(addr)
+}
+
+fn make_options() -> std::io::Result<Options<Clear>> {
+    let mut options: Options<Clear> = Options::new();
+    options.realname = Some(Line::from_str("vinezombie debugging tool"));
+    if let Ok(nick) = std::env::var("VZ_NICK") {
+        options.nicks.push(Nick::from_bytes(nick)?);
+    }
+    if let (Ok(user), Ok(pass)) = (std::env::var("VZ_SASL_USER"), std::env::var("VZ_SASL_PASS")) {
+        let user: NoNul<'static> = user.try_into()?;
+        let pass: NoNul<'static> = pass.try_into()?;
+        options.add_sasl(Password::new(user, Secret::new(pass)));
+    }
+    Ok(options)
+}
+
+/// Spawns a thread that forwards lines from standard input.
+/// The channel disconnects on EOF.
+fn spawn_stdin() -> Receiver<String> {
+    let (send, recv) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if send.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    recv
+}
+
+fn main() -> std::io::Result<()> {
+    let Some(arg) = std::env::args().nth(1) else {
+        eprintln!("usage: vzdebug [ircs://|irc://]host[:port]");
+        std::process::exit(2);
+    };
+    let address =
+        parse_addr(&arg).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let options = make_options()?;
+    let printer = Printer { start: Instant::now(), color: std::env::var_os("NO_COLOR").is_none() };
+    printer.note(format!(
+        "{}; features: {}",
+        vinezombie::build_info(),
+        vinezombie::info::FEATURES.join(",")
+    ));
+    let sock = address.connect(|| client::tls::TlsConfigOptions::default().build())?;
+    let connect_timings = sock.get_ref().connect_timings();
+    let mut client = Client::new(sock, SyncChannels);
+    if let Some(timings) = connect_timings {
+        client.state_mut().insert::<ConnectTimes>(timings);
+    }
+    let drop_printer = Printer { start: printer.start, color: printer.color };
+    client.set_drop_fn(Some(move |dropped: &DroppedLine<'_>| drop_printer.error(dropped)));
+    // Add this first so that registration traffic is also displayed.
+    let (_, msgs) = client.add((), YieldAll).unwrap();
+    let (reg_id, reg_result) = client.add(&register_as_client(), &options).unwrap();
+    let _ = client.add((), AutoPong);
+    loop {
+        let done = client.run()?.is_some_and(|(_, finished)| finished.contains(&reg_id));
+        while let Ok(msg) = msgs.try_recv() {
+            printer.recv(&msg);
+        }
+        if done {
+            break;
+        }
+    }
+    match reg_result.0.recv_now() {
+        Some(Ok(())) => (),
+        Some(Err(e)) => {
+            printer.error(format!("registration failed: {e}"));
+            return Ok(());
+        }
+        None => {
+            printer.error("registration handler finished without a result");
+            return Ok(());
+        }
+    }
+    let nick = client.state().get::<ClientSource>().unwrap().nick.clone();
+    printer.note(format!("registered as {nick}; reading messages from stdin"));
+    printer.note(SessionInfo::capture(client.state()));
+    if let Some(timings) = client.state().get::<RegTimings>() {
+        printer.note(format!("registration timings: {timings}"));
+    }
+    // Short read timeouts let us check standard input between messages from the server.
+    client.set_read_timeout(Some(Duration::from_millis(100)));
+    let input = spawn_stdin();
+    'outer: loop {
+        loop {
+            match input.try_recv() {
+                Ok(line) if line.trim().is_empty() => (),
+                Ok(line) => match ClientMsg::parse(line) {
+                    Ok(msg) => {
+                        printer.send(&msg);
+                        client.queue_mut().edit().push(msg);
+                    }
+                    Err(e) => printer.error(format!("invalid message: {e}")),
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => break 'outer,
+            }
+        }
+        if client.run()?.is_some() {
+            while let Ok(msg) = msgs.try_recv() {
+                printer.recv(&msg);
+            }
+        }
+    }
+    let quit = ClientMsg::new(QUIT);
+    printer.send(&quit);
+    client.queue_mut().edit().push(quit);
+    while let Some(wait_for) = client.flush_partial()? {
+        std::thread::sleep(wait_for);
+    }
+    // Display whatever the server sends back until it closes the connection.
+    while let Ok(Some(_)) = client.run() {
+        while let Ok(msg) = msgs.try_recv() {
+            printer.recv(&msg);
+        }
+    }
+    Ok(())
+}
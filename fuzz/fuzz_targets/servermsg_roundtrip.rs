@@ -0,0 +1,6 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vinezombie::ircmsg::ServerMsg;
+
+fuzz_target!(|msg: ServerMsg<'static>| vinezombie::ircmsg::fuzz::server_msg_roundtrip(&msg));
@@ -3,11 +3,15 @@
 pub mod auth;
 pub mod cap;
 pub mod conn;
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics;
 mod handler;
 pub mod handlers;
 mod logic;
+pub mod msg;
 pub mod nick;
 pub mod queue;
+pub mod reconnect;
 pub mod register;
 mod sink;
 pub mod state;
@@ -58,6 +62,38 @@ impl<C, S: ChannelSpec> Client<C, S> {
         let (send, recv) = M::make_channel(&self.spec);
         Ok((self.add_with_sender(send, make_handler, value)?, recv))
     }
+    /// Adds a handler with the provided priority.
+    /// Creates a new channel using the internal [`ChannelSpec`].
+    ///
+    /// Handlers with higher priorities process each message before handlers with lower ones.
+    /// Handlers added using the other `add` methods have a priority of `0`.
+    /// See [`Handler`]'s documentation for more information on ordering.
+    ///
+    /// Returns the handler id and the receiver half of the channel.
+    pub fn add_with_priority<T, M: MakeHandler<T>>(
+        &mut self,
+        priority: i32,
+        make_handler: M,
+        value: T,
+    ) -> Result<(usize, M::Receiver<S>), M::Error> {
+        let (send, recv) = M::make_channel(&self.spec);
+        let id = self.logic.add_with_sender_and_priority(send, priority, make_handler, value)?;
+        Ok((id, recv))
+    }
+    /// Adds a handler that expires after `timeout` if it has not finished by then.
+    /// Creates a new channel using the internal [`ChannelSpec`].
+    ///
+    /// See [`ClientLogic::add_with_spec_timeout`].
+    ///
+    /// Returns the handler id and the receiver half of the channel.
+    pub fn add_with_timeout<T, M: MakeHandler<T>>(
+        &mut self,
+        timeout: std::time::Duration,
+        make_handler: M,
+        value: T,
+    ) -> Result<(usize, M::Receiver<S>), M::Error> {
+        self.logic.add_with_spec_timeout(&self.spec, timeout, make_handler, value)
+    }
 }
 
 impl<C, S> Client<C, S> {
@@ -73,9 +109,38 @@ impl<C, S> Client<C, S> {
     pub fn with_conn<C2>(self, conn: C2) -> Client<C2, S> {
         let Self { conn: old, spec, mut logic, on_timeout } = self;
         logic.timeout.require_update();
-        let conn = conn::MsgIo { conn, buf_i: old.buf_i, buf_o: old.buf_o };
+        let conn = conn::MsgIo { conn, buf_i: old.buf_i, buf_o: old.buf_o, discarding: false };
         Client { conn, logic, spec, on_timeout }
     }
+    /// Uses the provided connection instead of the current one, returning the old connection.
+    ///
+    /// Unlike [`reset_with_conn`][Client::reset_with_conn], this preserves all
+    /// [`ClientLogic`] state, including handlers, [shared state][ClientState],
+    /// the [queue][Queue], and read statistics.
+    /// Any partially-read or partially-written lines are discarded,
+    /// and the connection's IO timeouts are updated on the next run.
+    ///
+    /// Handlers that are waiting on replies from the server remain valid only if
+    /// the new connection is to the same server session, such as when it is the old
+    /// connection wrapped in TLS. Otherwise, consider using
+    /// [`reset_with_conn`][Client::reset_with_conn] instead.
+    pub fn replace_conn(&mut self, conn: C) -> C {
+        let retval = std::mem::replace(&mut self.conn.conn, conn);
+        self.logic.timeout.require_update();
+        self.conn.reset();
+        retval
+    }
+    /// Uses the provided connection of a different type instead of the current one,
+    /// returning the new [`Client`] and the old connection.
+    ///
+    /// This preserves the same state as [`replace_conn`][Client::replace_conn].
+    pub fn swap_conn<C2>(self, conn: C2) -> (Client<C2, S>, C) {
+        let Self { conn: old, spec, mut logic, on_timeout } = self;
+        logic.timeout.require_update();
+        let mut new = conn::MsgIo { conn, buf_i: old.buf_i, buf_o: old.buf_o, discarding: false };
+        new.reset();
+        (Client { conn: new, logic, spec, on_timeout }, old.conn)
+    }
     /// Uses the provided [`ChannelSpec`] for `self`.
     /// This changes the type of channels returned by [`add`][Client::add].
     pub fn with_spec<S2: ChannelSpec>(self, spec: S2) -> Client<C, S2> {
@@ -117,6 +182,34 @@ impl<C, S> Client<C, S> {
             self.on_timeout = None;
         }
     }
+    /// Returns counters for the lines received from the server.
+    ///
+    /// See [`ClientLogic::read_stats`].
+    pub fn read_stats(&self) -> &conn::ReadStats {
+        self.logic.read_stats()
+    }
+    /// Sets the closure that is called for each line from the server that is dropped.
+    ///
+    /// See [`ClientLogic::set_drop_fn`].
+    pub fn set_drop_fn(&mut self, f: Option<impl FnMut(&conn::DroppedLine<'_>) + 'static + Send>) {
+        self.logic.set_drop_fn(f);
+    }
+    /// Sets how lines from the server that are too long are handled.
+    ///
+    /// See [`ClientLogic::set_overlong_policy`].
+    pub fn set_overlong_policy(&mut self, policy: crate::ircmsg::OverlongPolicy) {
+        self.logic.set_overlong_policy(policy);
+    }
+    /// Lets handlers act on a read timeout by calling [`Handler::handle_timeout`] on each of them.
+    ///
+    /// This is intended to be called after a `run` method returns `Ok(None)`.
+    /// Any messages queued by handlers are sent on the next run.
+    ///
+    /// Returns the IDs of the handlers that yielded or finished, respectively.
+    pub fn handle_timeout(&mut self) -> (&[usize], &[usize]) {
+        let finished_at = self.logic.run_timeout();
+        self.logic.handlers.last_run_results(finished_at)
+    }
     /// Changes the upper limit on how long an I/O operation may take to receive one message.
     /// A timeout of `None` means no limit.
     ///
@@ -145,6 +238,31 @@ impl<C, S> Client<C, S> {
     ) -> Result<(usize, M::Receiver<S2>), M::Error> {
         self.logic.add_with_spec(chanspec, make_handler, value)
     }
+    /// Adds a handler that expires after `timeout` if it has not finished by then.
+    /// Creates a new channel using the provided [`ChannelSpec`].
+    ///
+    /// See [`ClientLogic::add_with_spec_timeout`].
+    ///
+    /// Returns the handler id and the receiver half of the channel.
+    pub fn add_with_spec_timeout<T, M: MakeHandler<T>, S2: ChannelSpec>(
+        &mut self,
+        chanspec: &S2,
+        timeout: std::time::Duration,
+        make_handler: M,
+        value: T,
+    ) -> Result<(usize, M::Receiver<S2>), M::Error> {
+        self.logic.add_with_spec_timeout(chanspec, timeout, make_handler, value)
+    }
+    /// Makes a handler without adding it.
+    ///
+    /// See [`ClientLogic::make_handler`].
+    pub fn make_handler<T, M: MakeHandler<T>>(
+        &mut self,
+        make_handler: M,
+        value: T,
+    ) -> Result<Box<dyn Handler<Value = M::Value>>, M::Error> {
+        self.logic.make_handler(make_handler, value)
+    }
     /// Adds a handler using an existing channel.
     ///
     /// Returns the handler id.
@@ -156,6 +274,18 @@ impl<C, S> Client<C, S> {
     ) -> Result<usize, M::Error> {
         self.logic.add_with_sender(sender, make_handler, value)
     }
+    /// Cancels the handler with the provided id.
+    ///
+    /// See [`ClientLogic::cancel_handler`].
+    pub fn cancel_handler(&mut self, id: usize) -> bool {
+        self.logic.cancel_handler(id)
+    }
+    /// Returns information about every active handler.
+    ///
+    /// See [`ClientLogic::handler_info`].
+    pub fn handler_info(&self) -> impl Iterator<Item = (usize, &str, bool)> + '_ {
+        self.logic.handler_info()
+    }
 
     /// Resets client state to when the connection was just opened.
     ///
@@ -183,4 +313,23 @@ impl<C, S> Client<C, S> {
     pub fn needs_run(&self) -> bool {
         self.logic.needs_run()
     }
+
+    /// Marks the client as intentionally disconnecting.
+    ///
+    /// See [`ClientLogic::begin_shutdown`].
+    pub fn begin_shutdown(&mut self, reason: Option<crate::string::Line<'static>>) {
+        self.logic.begin_shutdown(reason);
+    }
+    /// Queues a QUIT message and marks the client as intentionally disconnecting.
+    ///
+    /// See [`ClientLogic::quit`].
+    /// To send the QUIT immediately and wait for the server to close the connection,
+    /// use `disconnect` or `disconnect_tokio` instead.
+    pub fn quit(&mut self, reason: Option<crate::string::Line<'static>>) {
+        self.logic.quit(reason);
+    }
+    /// Returns `true` if the client is intentionally disconnecting.
+    pub fn is_shutting_down(&self) -> bool {
+        self.logic.is_shutting_down()
+    }
 }
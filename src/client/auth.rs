@@ -4,6 +4,8 @@
 
 #[cfg(feature = "base64")]
 mod handler;
+#[cfg(feature = "base64")]
+mod reauth;
 pub mod sasl;
 mod secret;
 #[cfg(test)]
@@ -11,12 +13,24 @@ mod tests;
 
 #[cfg(feature = "base64")]
 pub use handler::*;
+#[cfg(feature = "base64")]
+pub use reauth::*;
 pub use secret::*;
 
 use crate::{
     ircmsg::ClientMsg,
-    string::{Arg, SecretBuf},
+    string::{Arg, SecretBuf, Word},
 };
+use std::sync::Arc;
+
+/// The default length of `AUTHENTICATE` chunks, as specified by the SASL extension.
+pub const DEFAULT_CHUNK_LEN: usize = 400;
+
+/// The default maximum size in bytes of a decoded server challenge.
+pub const DEFAULT_MAX_LEN: usize = 16384;
+
+/// The default maximum number of `AUTHENTICATE` messages accepted per mechanism.
+pub const DEFAULT_MAX_ROUNDS: u16 = 20;
 
 /// Returns the [`ClientMsg`] for aborting authentication.
 pub fn msg_abort() -> ClientMsg<'static> {
@@ -53,20 +67,64 @@ pub trait SaslLogic: Send + 'static {
 /// This is separate from [`SaslLogic`] with the idea that these types may be
 /// (de)serializeable, where the actual state for implementing these mechanisms may be
 /// significantly more complex.
-/// Additionally, the construction of these types is meant to be responsible for
+/// Additionally, these types are meant to be responsible for
 /// loading the necessary secrets into memory, so that the actual authenticator logic
 /// doesn't have to worry about it.
 pub trait Sasl {
     /// Returns the logic for this mechanism as a [`SaslLogic`].
     ///
     /// Some `Sasl` implementations represent configuration for a collection of mechanisms,
-    fn logic(&self) -> Vec<Box<dyn SaslLogic>>;
+    /// and so may return more than one.
+    ///
+    /// Errors if a [deferred][LoadSecret::defer] secret could not be loaded.
+    /// This method may block while loading secrets.
+    fn logic(&self) -> std::io::Result<Vec<Box<dyn SaslLogic>>>;
+
+    /// Returns the logic for this mechanism as a [`MechanismFamily`], if it is one.
+    ///
+    /// [`SaslQueue::push`] prefers this over [`logic`][Sasl::logic] so that the logic for
+    /// each mechanism is only created if that mechanism is attempted.
+    /// The default implementation returns `Ok(None)`.
+    ///
+    /// Errors if a [deferred][LoadSecret::defer] secret could not be loaded.
+    /// This method may block while loading secrets.
+    fn family(&self) -> std::io::Result<Option<Arc<dyn MechanismFamily>>> {
+        Ok(None)
+    }
+}
+
+/// A group of SASL mechanisms that share one credential.
+///
+/// The logic for each mechanism is created on demand,
+/// so that no work is done for mechanisms that are never attempted.
+pub trait MechanismFamily: Send + Sync + 'static {
+    /// Returns the names of the mechanisms in this family, most preferred first.
+    fn names(&self) -> Vec<Arg<'static>>;
+
+    /// Returns the logic for the mechanism named `name`,
+    /// or `None` if it is not part of this family.
+    fn logic(&self, name: &Arg<'_>) -> Option<Box<dyn SaslLogic>>;
+}
+
+/// One entry of a [`SaslQueue`].
+enum SaslEntry {
+    Logic(Box<dyn SaslLogic>),
+    Family(Arg<'static>, Arc<dyn MechanismFamily>),
+}
+
+impl SaslEntry {
+    fn name(&self) -> Arg<'static> {
+        match self {
+            SaslEntry::Logic(logic) => logic.name(),
+            SaslEntry::Family(name, _) => name.clone(),
+        }
+    }
 }
 
 /// A queue of SASL authenticators to try in order.
 #[derive(Default)]
 pub struct SaslQueue {
-    queue: std::collections::VecDeque<Box<dyn SaslLogic>>,
+    queue: std::collections::VecDeque<SaslEntry>,
 }
 
 impl SaslQueue {
@@ -85,15 +143,55 @@ impl SaslQueue {
         self.queue.len()
     }
 
+    /// Creates a list of SASL authenticators from an iterator of [`Sasl`] implementations.
+    ///
+    /// Errors if any of them fail to load their secrets.
+    pub fn try_from_iter<'a, S: Sasl + ?Sized + 'a>(
+        iter: impl IntoIterator<Item = &'a S>,
+    ) -> std::io::Result<Self> {
+        let iter = iter.into_iter();
+        let mut retval = Self::new();
+        retval.queue.reserve(iter.size_hint().0);
+        for sasl in iter {
+            retval.push(sasl)?;
+        }
+        Ok(retval)
+    }
+
     /// Adds a SASL authenticator to the end of the list.
-    pub fn push(&mut self, sasl: &(impl Sasl + ?Sized)) {
-        let mut vec_deque: std::collections::VecDeque<_> = sasl.logic().into();
-        self.queue.append(&mut vec_deque);
+    ///
+    /// If `sasl` is a [`MechanismFamily`], adds each of its mechanisms.
+    ///
+    /// Errors if `sasl` fails to load its secrets, in which case `self` is unchanged.
+    pub fn push(&mut self, sasl: &(impl Sasl + ?Sized)) -> std::io::Result<()> {
+        if let Some(family) = sasl.family()? {
+            self.push_family(family);
+        } else {
+            self.queue.extend(sasl.logic()?.into_iter().map(SaslEntry::Logic));
+        }
+        Ok(())
+    }
+
+    /// Adds every mechanism in a [`MechanismFamily`] to the end of the list.
+    pub fn push_family(&mut self, family: Arc<dyn MechanismFamily>) {
+        for name in family.names() {
+            self.queue.push_back(SaslEntry::Family(name, family.clone()));
+        }
     }
 
     /// Returns the next SASL authenticator to attempt.
     pub fn pop(&mut self) -> Option<Box<dyn SaslLogic>> {
-        self.queue.pop_front()
+        while let Some(entry) = self.queue.pop_front() {
+            match entry {
+                SaslEntry::Logic(logic) => return Some(logic),
+                SaslEntry::Family(name, family) => {
+                    if let Some(logic) = family.logic(&name) {
+                        return Some(logic);
+                    }
+                }
+            }
+        }
+        None
     }
 
     /// Retains only SASL authenticators for which
@@ -102,27 +200,61 @@ impl SaslQueue {
         self.queue.retain(|l| supported(&l.name()));
     }
 
+    /// Reorders SASL authenticators to match the order of the server's mechanism list.
+    ///
+    /// `mechs` should be uppercase, as from
+    /// [`SASL::mechs_in_order`][crate::names::cap::SASL::mechs_in_order].
+    /// Authenticators for mechanisms not in `mechs` are moved to the end of the queue.
+    ///
+    /// Mechanisms from the same [`MechanismFamily`] are kept together in the family's
+    /// own order, at the position of whichever of them the server lists first.
+    /// This way, the server's list can choose between credentials,
+    /// but cannot cause a weaker mechanism to be attempted before a stronger one
+    /// for the same credential.
+    pub fn sort_by_server_preference(&mut self, mechs: &[Word<'_>]) {
+        let position = |entry: &SaslEntry| {
+            let name = entry.name();
+            mechs.iter().position(|mech| mech.as_bytes() == name.as_bytes()).unwrap_or(mechs.len())
+        };
+        let positions: Vec<usize> = self.queue.iter().map(position).collect();
+        let keys: Vec<usize> = self
+            .queue
+            .iter()
+            .zip(positions.iter().copied())
+            .map(|(entry, mut key)| {
+                if let SaslEntry::Family(_, family) = entry {
+                    for (other, pos) in self.queue.iter().zip(positions.iter().copied()) {
+                        match other {
+                            SaslEntry::Family(_, other) if Arc::ptr_eq(family, other) => {
+                                key = key.min(pos);
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                key
+            })
+            .collect();
+        let mut entries: Vec<_> = keys.into_iter().zip(self.queue.drain(..)).collect();
+        // Stable, so entries with equal keys keep their relative order.
+        entries.sort_by_key(|(key, _)| *key);
+        self.queue = entries.into_iter().map(|(_, entry)| entry).collect();
+    }
+
+    /// Returns an iterator over the names of the SASL authenticators in `self`.
+    pub fn names(&self) -> impl Iterator<Item = Arg<'static>> + '_ {
+        self.queue.iter().map(SaslEntry::name)
+    }
+
     /// Cleares the queue.
     pub fn clear(&mut self) {
         self.queue.clear();
     }
 }
 
-impl<'a, S: Sasl + ?Sized> std::iter::FromIterator<&'a S> for SaslQueue {
-    fn from_iter<T: IntoIterator<Item = &'a S>>(iter: T) -> Self {
-        let iter = iter.into_iter();
-        let mut retval = Self::new();
-        retval.queue.reserve(iter.size_hint().0);
-        for sasl in iter {
-            retval.push(sasl);
-        }
-        retval
-    }
-}
-
 impl From<Vec<Box<dyn SaslLogic>>> for SaslQueue {
     fn from(value: Vec<Box<dyn SaslLogic>>) -> Self {
-        SaslQueue { queue: value.into() }
+        SaslQueue { queue: value.into_iter().map(SaslEntry::Logic).collect() }
     }
 }
 
@@ -149,13 +281,20 @@ impl<S: LoadSecret + std::fmt::Debug> std::fmt::Debug for AnySasl<S> {
     }
 }
 
-impl<S: LoadSecret + 'static> Sasl for AnySasl<S> {
-    fn logic(&self) -> Vec<Box<dyn SaslLogic>> {
+impl<S: LoadSecret + Clone + 'static> Sasl for AnySasl<S> {
+    fn logic(&self) -> std::io::Result<Vec<Box<dyn SaslLogic>>> {
         match self {
             AnySasl::External(s) => s.logic(),
             AnySasl::Password(s) => s.logic(),
         }
     }
+
+    fn family(&self) -> std::io::Result<Option<Arc<dyn MechanismFamily>>> {
+        match self {
+            AnySasl::External(s) => s.family(),
+            AnySasl::Password(s) => s.family(),
+        }
+    }
 }
 
 impl<S: LoadSecret> From<sasl::External> for AnySasl<S> {
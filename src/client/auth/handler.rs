@@ -1,4 +1,4 @@
-use super::{Sasl, SaslLogic, SaslQueue};
+use super::{Sasl, SaslLogic, SaslQueue, DEFAULT_CHUNK_LEN, DEFAULT_MAX_LEN, DEFAULT_MAX_ROUNDS};
 use crate::{
     client::{auth::msg_abort, ClientMsgSink, NoHandler},
     ircmsg::ClientMsg,
@@ -11,6 +11,9 @@ pub struct Handler {
     queue: SaslQueue,
     logic: Box<dyn SaslLogic>,
     decoder: crate::string::base64::ChunkDecoder,
+    rounds: u16,
+    max_rounds: u16,
+    max_len: usize,
 }
 
 /// All the possible errors that can occur during SASL authentication.
@@ -22,6 +25,10 @@ pub enum HandlerError {
     Unsupported,
     /// The last available authenticator failed, or the account is frozen.
     Fail(Line<'static>),
+    /// The server sent more `AUTHENTICATE` messages than allowed for one mechanism.
+    TooManyRounds(Arg<'static>),
+    /// The server sent a challenge that is larger than allowed.
+    TooLong(Arg<'static>),
 }
 
 impl From<HandlerError> for std::io::Error {
@@ -31,6 +38,8 @@ impl From<HandlerError> for std::io::Error {
             HandlerError::Fail(e) => Error::new(ErrorKind::PermissionDenied, e.to_utf8_lossy()),
             HandlerError::Broken(_) => Error::new(ErrorKind::InvalidData, value.to_string()),
             HandlerError::Unsupported => Error::new(ErrorKind::Unsupported, value.to_string()),
+            HandlerError::TooManyRounds(_) => Error::new(ErrorKind::InvalidData, value.to_string()),
+            HandlerError::TooLong(_) => Error::new(ErrorKind::InvalidData, value.to_string()),
         }
     }
 }
@@ -41,6 +50,8 @@ impl std::fmt::Display for HandlerError {
             HandlerError::Fail(reason) => write!(f, "login failed: {reason}"),
             HandlerError::Unsupported => write!(f, "no supported mechanisms"),
             HandlerError::Broken(m) => write!(f, "server has broken {m} implementation"),
+            HandlerError::TooManyRounds(m) => write!(f, "too many {m} challenges"),
+            HandlerError::TooLong(m) => write!(f, "{m} challenge too long"),
         }
     }
 }
@@ -61,8 +72,8 @@ impl crate::client::MakeHandler<SaslQueue> for crate::names::cmd::AUTHENTICATE {
         mut sasl_queue: SaslQueue,
     ) -> Result<Box<dyn crate::client::Handler<Value = Self::Value>>, Self::Error> {
         let sasl = sasl_queue.pop().ok_or(NoHandler)?;
-        let retval = Handler::new(sasl, sasl_queue);
-        queue.push(retval.auth_msg());
+        let retval = Handler::new(sasl, sasl_queue, DEFAULT_CHUNK_LEN);
+        queue.push_urgent(retval.auth_msg());
         Ok(Box::new(retval))
     }
 
@@ -77,7 +88,7 @@ impl crate::client::MakeHandler<SaslQueue> for crate::names::cmd::AUTHENTICATE {
 impl<'a, T: Sasl> crate::client::MakeHandler<&'a T> for crate::names::cmd::AUTHENTICATE {
     type Value = Result<(), HandlerError>;
 
-    type Error = crate::client::handler::NoHandler;
+    type Error = std::io::Error;
 
     type Receiver<Spec: crate::client::channel::ChannelSpec> = Spec::Oneshot<Self::Value>;
 
@@ -87,10 +98,12 @@ impl<'a, T: Sasl> crate::client::MakeHandler<&'a T> for crate::names::cmd::AUTHE
         mut queue: crate::client::queue::QueueEditGuard<'_>,
         sasl: &'a T,
     ) -> Result<Box<dyn crate::client::Handler<Value = Self::Value>>, Self::Error> {
-        let mut sasl_queue: SaslQueue = sasl.logic().into();
-        let sasl = sasl_queue.pop().ok_or(NoHandler)?;
-        let retval = Handler::new(sasl, sasl_queue);
-        queue.push(retval.auth_msg());
+        let mut sasl_queue: SaslQueue = sasl.logic()?.into();
+        let sasl = sasl_queue
+            .pop()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, NoHandler))?;
+        let retval = Handler::new(sasl, sasl_queue, DEFAULT_CHUNK_LEN);
+        queue.push_urgent(retval.auth_msg());
         Ok(Box::new(retval))
     }
 
@@ -105,15 +118,60 @@ impl<'a, T: Sasl> crate::client::MakeHandler<&'a T> for crate::names::cmd::AUTHE
 impl Handler {
     /// Creates a new authenticator from a [`SaslLogic`] implementation and a
     /// (potentially empty) queue.
-    /// Additionally returns the message to send to initiate authentication.
-    pub fn new(logic: Box<dyn SaslLogic>, queue: SaslQueue) -> Self {
-        Handler { queue, logic, decoder: crate::string::base64::ChunkDecoder::new(400) }
+    ///
+    /// `chunk_len` is the length of `AUTHENTICATE` chunks, both sent and received.
+    /// This should almost always be [`DEFAULT_CHUNK_LEN`],
+    /// but some servers accept longer chunks.
+    /// A length of zero is treated as one.
+    pub fn new(logic: Box<dyn SaslLogic>, queue: SaslQueue, chunk_len: usize) -> Self {
+        Handler {
+            queue,
+            logic,
+            decoder: crate::string::base64::ChunkDecoder::new(chunk_len.max(1)),
+            rounds: 0,
+            max_rounds: DEFAULT_MAX_ROUNDS,
+            max_len: DEFAULT_MAX_LEN,
+        }
+    }
+    /// Sets the length of `AUTHENTICATE` chunks, both sent and received.
+    ///
+    /// See [`Handler::new`]. This discards any partially-received challenge,
+    /// so it should be called before authentication starts.
+    /// A length of zero is treated as one.
+    pub fn set_chunk_len(&mut self, chunk_len: usize) {
+        self.decoder = crate::string::base64::ChunkDecoder::new(chunk_len.max(1));
+    }
+    /// Sets the maximum size in bytes of a decoded server challenge.
+    ///
+    /// Receiving a larger challenge aborts authentication with [`HandlerError::TooLong`].
+    /// The default is [`DEFAULT_MAX_LEN`].
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.max_len = max_len;
+    }
+    /// Sets the maximum number of `AUTHENTICATE` messages that will be accepted
+    /// from the server for each mechanism.
+    ///
+    /// Exceeding this aborts authentication with [`HandlerError::TooManyRounds`].
+    /// The default is [`DEFAULT_MAX_ROUNDS`].
+    pub fn set_max_rounds(&mut self, max_rounds: u16) {
+        self.max_rounds = max_rounds;
+    }
+    fn set_logic(&mut self, logic: Box<dyn SaslLogic>) {
+        self.logic = logic;
+        self.rounds = 0;
+        self.decoder.clear();
     }
     /// Attempts to create a new authenticator directly from a [`SaslQueue`].
     /// Returns `None` if the queue is empty.
-    pub fn from_queue(mut queue: SaslQueue) -> Option<Self> {
+    ///
+    /// See [`Handler::new`] for the meaning of `chunk_len`.
+    pub fn from_queue(mut queue: SaslQueue, chunk_len: usize) -> Option<Self> {
         let logic = queue.pop()?;
-        Some(Self::new(logic, queue))
+        Some(Self::new(logic, queue, chunk_len))
+    }
+    /// Returns the name of the current mechanism.
+    pub fn mechanism(&self) -> Arg<'static> {
+        self.logic.name()
     }
     /// Creates an auth message for the current [`SaslLogic`].
     ///
@@ -138,7 +196,7 @@ impl Handler {
         if supported(&self.logic.name()) {
             None
         } else if let Some(new_logic) = self.queue.pop() {
-            self.logic = new_logic;
+            self.set_logic(new_logic);
             Some(true)
         } else {
             Some(false)
@@ -156,7 +214,18 @@ impl Handler {
         use crate::string::base64::ChunkEncoder;
         match msg.kind.as_str() {
             "AUTHENTICATE" => {
+                if self.rounds >= self.max_rounds {
+                    sink.send(msg_abort());
+                    return Err(HandlerError::TooManyRounds(self.logic.name()));
+                }
+                self.rounds += 1;
                 let res = if let Some(first) = msg.args.words().first() {
+                    // Every 4 bytes of base64 decode to at most 3 bytes.
+                    if (self.decoder.len() + first.len()) / 4 * 3 > self.max_len {
+                        self.decoder.clear();
+                        sink.send(msg_abort());
+                        return Err(HandlerError::TooLong(self.logic.name()));
+                    }
                     self.decoder.add(first.as_bytes())
                 } else {
                     Some(self.decoder.decode())
@@ -177,7 +246,7 @@ impl Handler {
                         let name = self.logic.name();
                         self.queue.retain(&|ln| name != *ln);
                         return if let Some(new_logic) = self.queue.pop() {
-                            self.logic = new_logic;
+                            self.set_logic(new_logic);
                             // We can continue, but we need to wait for the server to
                             // acknowledge that we're stopping before sending AUTHENTICATE.
                             Ok(false)
@@ -185,7 +254,7 @@ impl Handler {
                             Err(HandlerError::Broken(name))
                         };
                     }
-                    for chunk in ChunkEncoder::new(buf, 400, true) {
+                    for chunk in ChunkEncoder::new(buf, self.decoder.chunk_len(), true) {
                         let mut msg = ClientMsg::new(AUTHENTICATE);
                         msg.args.edit().add_word(chunk);
                         sink.send(msg);
@@ -198,7 +267,7 @@ impl Handler {
                 // In a more account-aware system, could purge all authenticators that are
                 // meant to log in to the same account on a 902.
                 if let Some(next_logic) = self.queue.pop() {
-                    self.logic = next_logic;
+                    self.set_logic(next_logic);
                     sink.send(self.auth_msg());
                     Ok(false)
                 } else {
@@ -255,10 +324,10 @@ impl crate::client::Handler for Handler {
         mut queue: crate::client::queue::QueueEditGuard<'_>,
         mut channel: crate::client::channel::SenderRef<'_, Self::Value>,
     ) -> std::ops::ControlFlow<()> {
-        match self.handle(msg, &mut queue) {
+        match self.handle(msg, |reply| queue.push_urgent(reply)) {
             Ok(false) => std::ops::ControlFlow::Continue(()),
             v => {
-                channel.send(v.and(Ok(())));
+                let _ = channel.send(v.and(Ok(())));
                 std::ops::ControlFlow::Break(())
             }
         }
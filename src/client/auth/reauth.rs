@@ -0,0 +1,194 @@
+use super::{Handler, HandlerError, Sasl, SaslQueue};
+use crate::{
+    client::{
+        cap::{self, SubCmd},
+        channel::{ChannelSpec, Sender, SenderRef},
+        queue::QueueEditGuard,
+        state::{Account, Caps, ClientSource, ServerSource},
+        ClientState, SelfMadeHandler,
+    },
+    ircmsg::ServerMsg,
+    names::{cap::SASL, Cap, NameMap},
+    string::Word,
+};
+use std::ops::ControlFlow;
+
+/// Handler that re-authenticates when the `sasl` capability becomes available again
+/// after registration.
+///
+/// Some networks remove and re-add `sasl` using `CAP DEL` and `CAP NEW`
+/// when their services restart, which requires `cap-notify`.
+/// When `sasl` is added, this handler requests it and,
+/// once it is acknowledged, authenticates using a [`SaslQueue`] from the provided function,
+/// filtered to the mechanisms the server advertises.
+/// The result of each attempt is yielded.
+///
+/// This handler keeps the `sasl` entry in [`Caps`] up to date and
+/// updates [`Account`] on `RPL_LOGGEDIN` (900) and `RPL_LOGGEDOUT` (901).
+/// It does nothing until the [`Caps`] state has been set, usually by registration,
+/// so it does not interfere with authentication during registration.
+/// It finishes if the function returns an empty queue.
+pub struct Reauthenticate {
+    make_queue: Box<dyn FnMut() -> SaslQueue + Send>,
+    chunk_len: usize,
+    state: ReauthState,
+}
+
+enum ReauthState {
+    Idle,
+    Requested(Word<'static>),
+    Authenticating(Box<Handler>),
+}
+
+impl Reauthenticate {
+    /// Creates a new `Reauthenticate` handler that calls `make_queue` for every attempt.
+    pub fn new(make_queue: impl FnMut() -> SaslQueue + Send + 'static) -> Self {
+        Reauthenticate {
+            make_queue: Box::new(make_queue),
+            chunk_len: super::DEFAULT_CHUNK_LEN,
+            state: ReauthState::Idle,
+        }
+    }
+    /// Creates a new `Reauthenticate` handler that authenticates using `sasl`.
+    ///
+    /// If `sasl` fails to load its secrets, the handler finishes.
+    pub fn from_sasl(sasl: impl Sasl + Send + 'static) -> Self {
+        Self::new(move || match sasl.logic() {
+            Ok(logic) => logic.into(),
+            Err(_e) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("failed to load secrets for reauthentication: {_e}");
+                SaslQueue::new()
+            }
+        })
+    }
+    /// Sets the length of `AUTHENTICATE` chunks for future attempts.
+    ///
+    /// See [`Handler::set_chunk_len`].
+    pub fn set_chunk_len(&mut self, chunk_len: usize) {
+        self.chunk_len = chunk_len;
+    }
+    fn start(
+        &mut self,
+        mechs: &Word<'static>,
+        mut queue: QueueEditGuard<'_>,
+    ) -> ControlFlow<(), ReauthState> {
+        let mut sasl_queue = (self.make_queue)();
+        if !mechs.is_empty() {
+            let mechs = SASL::mechs_in_order(mechs);
+            sasl_queue.retain(&|mech| mechs.iter().any(|m| m.as_bytes() == mech.as_bytes()));
+            sasl_queue.sort_by_server_preference(&mechs);
+        }
+        let Some(auth) = Handler::from_queue(sasl_queue, self.chunk_len) else {
+            return ControlFlow::Break(());
+        };
+        queue.push_urgent(auth.auth_msg());
+        ControlFlow::Continue(ReauthState::Authenticating(Box::new(auth)))
+    }
+}
+
+/// Applies `f` to the [`Caps`] state, if any.
+fn update_caps(state: &mut ClientState, f: impl FnOnce(&mut NameMap<Cap, bool>)) {
+    if let Some(mut caps) = state.get::<Caps>().cloned() {
+        f(&mut caps);
+        state.insert::<Caps>(caps);
+    }
+}
+
+impl crate::client::Handler for Reauthenticate {
+    type Value = Result<(), HandlerError>;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        mut queue: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        if state.get::<Caps>().is_none() {
+            return ControlFlow::Continue(());
+        }
+        match msg.kind.as_str() {
+            // RPL_LOGGEDIN
+            "900" => {
+                if let Some(account) = msg.args.split_last().0.last() {
+                    state.insert::<Account>(Some(account.clone().owning()));
+                }
+            }
+            // RPL_LOGGEDOUT
+            "901" => state.insert::<Account>(None),
+            "CAP" => {
+                let Ok(cap_msg) = cap::ServerMsgArgs::parse(&msg.args) else {
+                    return ControlFlow::Continue(());
+                };
+                let Some(value) = cap_msg.caps.get(SASL::NAME.as_bytes()) else {
+                    return ControlFlow::Continue(());
+                };
+                let value = value.clone().owning();
+                match cap_msg.subcmd {
+                    SubCmd::New => {
+                        update_caps(state, |caps| {
+                            let enabled = caps.get_extra(SASL).copied().unwrap_or_default();
+                            caps.edit().insert((SASL::NAME, value.clone()), enabled);
+                        });
+                        if matches!(self.state, ReauthState::Idle) {
+                            let me = state.get::<ClientSource>().map(|src| src.nick.clone());
+                            let server = state.get::<ServerSource>();
+                            cap::req([SASL::NAME], me.map(Into::into), server, &mut queue);
+                            self.state = ReauthState::Requested(value);
+                        }
+                    }
+                    SubCmd::Ack => {
+                        update_caps(state, |caps| match caps.get_extra_mut(SASL) {
+                            Some(enabled) => *enabled = true,
+                            None => {
+                                caps.edit().insert((SASL::NAME, Word::default()), true);
+                            }
+                        });
+                        if let ReauthState::Requested(mechs) =
+                            std::mem::replace(&mut self.state, ReauthState::Idle)
+                        {
+                            self.state = self.start(&mechs, queue)?;
+                        }
+                    }
+                    SubCmd::Nak => {
+                        if matches!(self.state, ReauthState::Requested(_)) {
+                            self.state = ReauthState::Idle;
+                        }
+                    }
+                    SubCmd::Del => {
+                        update_caps(state, |caps| {
+                            caps.edit().remove(SASL);
+                        });
+                        self.state = ReauthState::Idle;
+                    }
+                    SubCmd::Ls | SubCmd::List => (),
+                }
+                return ControlFlow::Continue(());
+            }
+            _ => (),
+        }
+        if let ReauthState::Authenticating(auth) = &mut self.state {
+            let result = match auth.handle(msg, |reply| queue.push_urgent(reply)) {
+                Ok(false) => return ControlFlow::Continue(()),
+                Ok(true) => Ok(()),
+                Err(e) => Err(e),
+            };
+            self.state = ReauthState::Idle;
+            let _ = channel.send(result);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+impl SelfMadeHandler for Reauthenticate {
+    type Receiver<Spec: ChannelSpec> = Spec::Queue<Self::Value>;
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_queue()
+    }
+}
@@ -2,5 +2,9 @@
 
 mod external;
 mod password;
+#[cfg(all(feature = "crypto", feature = "base64"))]
+mod scram;
 
+#[cfg(all(feature = "crypto", feature = "base64"))]
+pub use scram::*;
 pub use {external::*, password::*};
@@ -39,7 +39,7 @@ impl SaslLogic for ExternalLogic {
 }
 
 impl Sasl for External {
-    fn logic(&self) -> Vec<Box<dyn SaslLogic>> {
-        vec![Box::new(ExternalLogic(self.0.clone()))]
+    fn logic(&self) -> std::io::Result<Vec<Box<dyn SaslLogic>>> {
+        Ok(vec![Box::new(ExternalLogic(self.0.clone()))])
     }
 }
@@ -1,8 +1,8 @@
 use crate::{
-    client::auth::{LoadSecret, Sasl, SaslLogic, Secret},
+    client::auth::{LoadSecret, MechanismFamily, Sasl, SaslLogic, Secret},
     string::{Arg, NoNul, SecretBuf},
 };
-use std::collections::BTreeSet;
+use std::{collections::BTreeSet, sync::Arc};
 
 static SASL_PLAIN_NAME: Arg = Arg::from_str("PLAIN");
 
@@ -11,24 +11,52 @@ static SASL_PLAIN_NAME: Arg = Arg::from_str("PLAIN");
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
 #[cfg_attr(feature = "serde", derive(serde_derive::Deserialize))]
 pub enum PasswordMechanism {
+    // Mechanisms are attempted in the order they are declared here.
+    #[cfg(all(feature = "crypto", feature = "base64"))]
+    /// The [SCRAM](https://datatracker.ietf.org/doc/html/rfc5802) mechanism with SHA-512.
+    ScramSha512,
+    #[cfg(all(feature = "crypto", feature = "base64"))]
+    /// The [SCRAM](https://datatracker.ietf.org/doc/html/rfc5802) mechanism with SHA-256.
+    ScramSha256,
     /// The [PLAIN](https://datatracker.ietf.org/doc/html/rfc4616) mechanism.
     #[default]
     Plain,
-    // Commenting this out for now pending an actual implementation of SCRAM.
-    // #[cfg(feature = "crypto")]
-    // /// The [SCRAM](https://datatracker.ietf.org/doc/html/rfc5802) mechanism with SHA-256.
-    // ScramSha256,
-    // #[cfg(feature = "crypto")]
-    // /// The [SCRAM](https://datatracker.ietf.org/doc/html/rfc5802) mechanism with SHA-512.
-    // ScramSha512,
 }
 
 impl PasswordMechanism {
     pub(self) fn full_set() -> BTreeSet<PasswordMechanism> {
-        [PasswordMechanism::Plain].into_iter().collect()
+        [
+            #[cfg(all(feature = "crypto", feature = "base64"))]
+            PasswordMechanism::ScramSha512,
+            #[cfg(all(feature = "crypto", feature = "base64"))]
+            PasswordMechanism::ScramSha256,
+            PasswordMechanism::Plain,
+        ]
+        .into_iter()
+        .collect()
+    }
+    /// Returns the SASL name of this mechanism.
+    pub fn name(&self) -> Arg<'static> {
+        match self {
+            #[cfg(all(feature = "crypto", feature = "base64"))]
+            PasswordMechanism::ScramSha512 => Arg::from_str("SCRAM-SHA-512"),
+            #[cfg(all(feature = "crypto", feature = "base64"))]
+            PasswordMechanism::ScramSha256 => Arg::from_str("SCRAM-SHA-256"),
+            PasswordMechanism::Plain => SASL_PLAIN_NAME.clone(),
+        }
     }
     pub(self) fn logic(&self, authzid: &[u8], authcid: &[u8], passwd: &[u8]) -> Box<dyn SaslLogic> {
+        #[cfg(all(feature = "crypto", feature = "base64"))]
+        use super::{ScramHash, ScramLogic};
         match self {
+            #[cfg(all(feature = "crypto", feature = "base64"))]
+            PasswordMechanism::ScramSha512 => {
+                Box::new(ScramLogic::new(ScramHash::Sha512, authzid, authcid, passwd))
+            }
+            #[cfg(all(feature = "crypto", feature = "base64"))]
+            PasswordMechanism::ScramSha256 => {
+                Box::new(ScramLogic::new(ScramHash::Sha256, authzid, authcid, passwd))
+            }
             PasswordMechanism::Plain => Box::new(PlainLogic::new(authzid, authcid, passwd)),
         }
     }
@@ -78,13 +106,53 @@ impl<S> Password<S> {
     }
 }
 
-impl<S> Sasl for Password<S> {
-    fn logic(&self) -> Vec<Box<dyn SaslLogic>> {
-        PasswordMechanism::full_set()
-            .difference(&self.deny_methods)
-            .copied()
-            .map(|mech| mech.logic(&self.authzid, &self.authcid, &self.passwd))
-            .collect()
+impl<S: LoadSecret + Clone> Password<S> {
+    fn load(&self) -> std::io::Result<PasswordFamily> {
+        let passwd = self.passwd.get()?;
+        let mut secret = SecretBuf::with_capacity(passwd.len());
+        secret.push_slice(&passwd);
+        Ok(PasswordFamily {
+            mechs: PasswordMechanism::full_set().difference(&self.deny_methods).copied().collect(),
+            authzid: self.authzid.clone(),
+            authcid: self.authcid.clone(),
+            passwd: secret,
+        })
+    }
+}
+
+impl<S: LoadSecret + Clone> Sasl for Password<S> {
+    fn logic(&self) -> std::io::Result<Vec<Box<dyn SaslLogic>>> {
+        let family = self.load()?;
+        Ok(family.mechs.iter().map(|mech| family.mech_logic(*mech)).collect())
+    }
+
+    fn family(&self) -> std::io::Result<Option<Arc<dyn MechanismFamily>>> {
+        Ok(Some(Arc::new(self.load()?)))
+    }
+}
+
+/// The mechanisms allowed by a [`Password`] along with its loaded password.
+struct PasswordFamily {
+    mechs: Vec<PasswordMechanism>,
+    authzid: NoNul<'static>,
+    authcid: NoNul<'static>,
+    passwd: SecretBuf,
+}
+
+impl PasswordFamily {
+    fn mech_logic(&self, mech: PasswordMechanism) -> Box<dyn SaslLogic> {
+        mech.logic(&self.authzid, &self.authcid, self.passwd.as_ref())
+    }
+}
+
+impl MechanismFamily for PasswordFamily {
+    fn names(&self) -> Vec<Arg<'static>> {
+        self.mechs.iter().map(PasswordMechanism::name).collect()
+    }
+
+    fn logic(&self, name: &Arg<'_>) -> Option<Box<dyn SaslLogic>> {
+        let mech = self.mechs.iter().find(|mech| mech.name() == *name)?;
+        Some(self.mech_logic(*mech))
     }
 }
 
@@ -120,12 +188,12 @@ impl<S> Plain<S> {
     }
 }
 
-impl<S: LoadSecret + 'static> Sasl for Plain<S> {
-    fn logic(&self) -> Vec<Box<dyn SaslLogic>> {
+impl<S: LoadSecret + Clone + 'static> Sasl for Plain<S> {
+    fn logic(&self) -> std::io::Result<Vec<Box<dyn SaslLogic>>> {
         let authzid = self.authzid.as_bytes();
         let authcid = self.authcid.as_bytes();
-        let passwd = self.passwd.as_bytes();
-        vec![Box::new(PlainLogic::new(authzid, authcid, passwd))]
+        let passwd = self.passwd.get()?;
+        Ok(vec![Box::new(PlainLogic::new(authzid, authcid, passwd.as_bytes()))])
     }
 }
 
@@ -0,0 +1,344 @@
+use crate::{
+    client::auth::{LoadSecret, Sasl, SaslLogic, Secret},
+    string::{Arg, NoNul, SecretBuf},
+};
+use base64::{engine::general_purpose::STANDARD as ENGINE, Engine};
+use ring::{digest, hmac, pbkdf2};
+use std::num::NonZeroU32;
+
+static SASL_SCRAM_SHA_256_NAME: Arg = Arg::from_str("SCRAM-SHA-256");
+static SASL_SCRAM_SHA_512_NAME: Arg = Arg::from_str("SCRAM-SHA-512");
+
+/// The maximum length of the output of any supported hash function.
+const MAX_HASH_LEN: usize = 64;
+
+/// The fewest PBKDF2 iterations accepted from a server, as recommended by RFC 7677.
+///
+/// Fewer iterations make the client proof cheap to brute-force if it is intercepted.
+const MIN_ITERATIONS: u32 = 4096;
+
+/// The most PBKDF2 iterations accepted from a server.
+///
+/// Without a limit, a hostile server could stall registration for a very long time.
+const MAX_ITERATIONS: u32 = 1_000_000;
+
+/// The hash functions that SCRAM can be used with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum ScramHash {
+    Sha256,
+    Sha512,
+}
+
+impl ScramHash {
+    fn name(self) -> Arg<'static> {
+        match self {
+            ScramHash::Sha256 => SASL_SCRAM_SHA_256_NAME.clone(),
+            ScramHash::Sha512 => SASL_SCRAM_SHA_512_NAME.clone(),
+        }
+    }
+    fn len(self) -> usize {
+        match self {
+            ScramHash::Sha256 => 32,
+            ScramHash::Sha512 => 64,
+        }
+    }
+    fn pbkdf2(self) -> pbkdf2::Algorithm {
+        match self {
+            ScramHash::Sha256 => pbkdf2::PBKDF2_HMAC_SHA256,
+            ScramHash::Sha512 => pbkdf2::PBKDF2_HMAC_SHA512,
+        }
+    }
+    fn hmac(self) -> hmac::Algorithm {
+        match self {
+            ScramHash::Sha256 => hmac::HMAC_SHA256,
+            ScramHash::Sha512 => hmac::HMAC_SHA512,
+        }
+    }
+    fn digest(self) -> &'static digest::Algorithm {
+        match self {
+            ScramHash::Sha256 => &digest::SHA256,
+            ScramHash::Sha512 => &digest::SHA512,
+        }
+    }
+}
+
+/// Errors specific to [SCRAM](https://datatracker.ietf.org/doc/html/rfc5802) authentication.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum ScramError {
+    /// A message from the server could not be parsed.
+    Malformed(&'static str),
+    /// The server's nonce does not start with the client's nonce.
+    NonceMismatch,
+    /// The server sent an error instead of its signature.
+    Server(String),
+    /// The server's signature does not match the expected one.
+    ///
+    /// This means that the server does not know the password,
+    /// and it is likely that some party is impersonating it.
+    BadServerSignature,
+}
+
+impl std::fmt::Display for ScramError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScramError::Malformed(what) => write!(f, "malformed server message: {what}"),
+            ScramError::NonceMismatch => write!(f, "server nonce does not extend client nonce"),
+            ScramError::Server(e) => write!(f, "server error: {e}"),
+            ScramError::BadServerSignature => write!(f, "server signature does not verify"),
+        }
+    }
+}
+
+impl std::error::Error for ScramError {}
+
+/// Configuration for SCRAM-SHA-256 authentication.
+///
+/// It is generally recommended to use [`Password`][super::Password] instead.
+///
+/// Does not transmit the password, and verifies that the server also knows it.
+/// Passwords are not normalized with SASLprep,
+/// so non-ASCII passwords may fail to authenticate.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(deserialize = "'de: 'static, S: LoadSecret + serde::de::Deserialize<'de>"))
+)]
+pub struct Scram<S> {
+    /// Who to log in as, or empty to log in as the user specified in `authcid`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub authzid: NoNul<'static>,
+    /// Whose credentials to use for logging in.
+    pub authcid: NoNul<'static>,
+    /// The password.
+    pub passwd: Secret<NoNul<'static>, S>,
+}
+
+impl<S> Scram<S> {
+    /// Creates `self` from a username and password combination.
+    ///
+    /// This type has fields that are typically not required for normal use.
+    /// This function initializes those fields accordingly.
+    pub const fn new(username: NoNul<'static>, passwd: Secret<NoNul<'static>, S>) -> Self {
+        Scram { authzid: NoNul::empty(), authcid: username, passwd }
+    }
+}
+
+impl<S: LoadSecret + Clone + 'static> Sasl for Scram<S> {
+    fn logic(&self) -> std::io::Result<Vec<Box<dyn SaslLogic>>> {
+        let authzid = self.authzid.as_bytes();
+        let authcid = self.authcid.as_bytes();
+        let passwd = self.passwd.get()?;
+        Ok(vec![Box::new(ScramLogic::new(ScramHash::Sha256, authzid, authcid, passwd.as_bytes()))])
+    }
+}
+
+/// Appends `name` to `buf`, escaping it as a SCRAM `saslname`.
+fn push_saslname(buf: &mut Vec<u8>, name: &[u8]) {
+    for byte in name.iter().copied() {
+        match byte {
+            b'=' => buf.extend_from_slice(b"=3D"),
+            b',' => buf.extend_from_slice(b"=2C"),
+            byte => buf.push(byte),
+        }
+    }
+}
+
+/// Returns a random printable nonce.
+fn make_nonce() -> Vec<u8> {
+    use ring::rand::SecureRandom;
+    let mut bytes = [0u8; 18];
+    // If the system's RNG fails there is not much else that can be done.
+    ring::rand::SystemRandom::new().fill(&mut bytes).expect("system RNG failed");
+    ENGINE.encode(bytes).into_bytes()
+}
+
+/// Splits a SCRAM message into its `<letter>=<value>` attributes.
+fn attrs(msg: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    msg.split(|b| *b == b',').filter_map(|attr| match attr {
+        [key, b'=', value @ ..] => Some((*key, value)),
+        _ => None,
+    })
+}
+
+fn xor(a: &mut [u8], b: &[u8]) {
+    for (a, b) in a.iter_mut().zip(b) {
+        *a ^= b;
+    }
+}
+
+enum ScramState {
+    /// Nothing has been sent yet.
+    Start,
+    /// The client-first message has been sent.
+    ClientFirst,
+    /// The client-final message has been sent.
+    ClientFinal {
+        /// The key for verifying the server's signature.
+        server_key: hmac::Key,
+        /// The message the server is expected to have signed.
+        auth_msg: Vec<u8>,
+    },
+    /// The server's signature has been verified.
+    Done,
+}
+
+pub(crate) struct ScramLogic {
+    hash: ScramHash,
+    /// The GS2 header, which includes the authzid.
+    gs2_header: Vec<u8>,
+    /// The client-first message without the GS2 header.
+    client_first_bare: Vec<u8>,
+    /// The length of the client nonce at the end of `client_first_bare`.
+    nonce_len: usize,
+    passwd: SecretBuf,
+    state: ScramState,
+}
+
+impl ScramLogic {
+    pub fn new(hash: ScramHash, authzid: &[u8], authcid: &[u8], passwd: &[u8]) -> Self {
+        Self::with_nonce(hash, authzid, authcid, passwd, &make_nonce())
+    }
+    pub(crate) fn with_nonce(
+        hash: ScramHash,
+        authzid: &[u8],
+        authcid: &[u8],
+        passwd: &[u8],
+        nonce: &[u8],
+    ) -> Self {
+        // No channel binding.
+        let mut gs2_header = b"n,".to_vec();
+        if !authzid.is_empty() {
+            gs2_header.extend_from_slice(b"a=");
+            push_saslname(&mut gs2_header, authzid);
+        }
+        gs2_header.push(b',');
+        let mut client_first_bare = b"n=".to_vec();
+        push_saslname(&mut client_first_bare, authcid);
+        client_first_bare.extend_from_slice(b",r=");
+        client_first_bare.extend_from_slice(nonce);
+        let mut pw = SecretBuf::with_capacity(passwd.len());
+        pw.push_slice(passwd);
+        ScramLogic {
+            hash,
+            gs2_header,
+            client_first_bare,
+            nonce_len: nonce.len(),
+            passwd: pw,
+            state: ScramState::Start,
+        }
+    }
+    fn client_nonce(&self) -> &[u8] {
+        &self.client_first_bare[self.client_first_bare.len() - self.nonce_len..]
+    }
+    fn client_final(
+        &mut self,
+        server_first: &[u8],
+        output: &mut SecretBuf,
+    ) -> Result<ScramState, ScramError> {
+        let (mut nonce, mut salt, mut iters) = (None, None, None);
+        for (key, value) in attrs(server_first) {
+            match key {
+                b'm' => return Err(ScramError::Malformed("unsupported mandatory extension")),
+                b'r' => nonce = Some(value),
+                b's' => salt = Some(value),
+                b'i' => iters = Some(value),
+                _ => (),
+            }
+        }
+        let nonce = nonce.ok_or(ScramError::Malformed("missing nonce"))?;
+        let salt = salt.ok_or(ScramError::Malformed("missing salt"))?;
+        let salt = ENGINE.decode(salt).map_err(|_| ScramError::Malformed("invalid salt"))?;
+        let iters: NonZeroU32 = iters
+            .and_then(|i| std::str::from_utf8(i).ok()?.parse().ok())
+            .ok_or(ScramError::Malformed("missing or invalid iteration count"))?;
+        if iters.get() < MIN_ITERATIONS {
+            return Err(ScramError::Malformed("iteration count too low"));
+        } else if iters.get() > MAX_ITERATIONS {
+            return Err(ScramError::Malformed("iteration count too high"));
+        }
+        if nonce.len() <= self.nonce_len || !nonce.starts_with(self.client_nonce()) {
+            return Err(ScramError::NonceMismatch);
+        }
+        let (hash_len, hmac_algo) = (self.hash.len(), self.hash.hmac());
+        let mut salted = [0u8; MAX_HASH_LEN];
+        let salted = &mut salted[..hash_len];
+        pbkdf2::derive(self.hash.pbkdf2(), iters, &salt, self.passwd.as_ref(), salted);
+        let salted = hmac::Key::new(hmac_algo, salted);
+        let client_key = hmac::sign(&salted, b"Client Key");
+        let stored_key = digest::digest(self.hash.digest(), client_key.as_ref());
+        let server_key = hmac::sign(&salted, b"Server Key");
+        let mut msg = b"c=".to_vec();
+        msg.extend_from_slice(ENGINE.encode(&self.gs2_header).as_bytes());
+        msg.extend_from_slice(b",r=");
+        msg.extend_from_slice(nonce);
+        let mut auth_msg = self.client_first_bare.clone();
+        auth_msg.push(b',');
+        auth_msg.extend_from_slice(server_first);
+        auth_msg.push(b',');
+        auth_msg.extend_from_slice(&msg);
+        let stored_key = hmac::Key::new(hmac_algo, stored_key.as_ref());
+        let mut proof = [0u8; MAX_HASH_LEN];
+        let proof = &mut proof[..hash_len];
+        proof.copy_from_slice(client_key.as_ref());
+        xor(proof, hmac::sign(&stored_key, &auth_msg).as_ref());
+        let server_key = hmac::Key::new(hmac_algo, server_key.as_ref());
+        output.push_slice(&msg);
+        output.push_slice(b",p=");
+        output.push_slice(ENGINE.encode(proof).as_bytes());
+        Ok(ScramState::ClientFinal { server_key, auth_msg })
+    }
+}
+
+impl SaslLogic for ScramLogic {
+    fn name(&self) -> Arg<'static> {
+        self.hash.name()
+    }
+
+    fn reply<'a>(
+        &'a mut self,
+        data: &[u8],
+        output: &mut SecretBuf,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+        match std::mem::replace(&mut self.state, ScramState::Done) {
+            ScramState::Start => {
+                if !data.is_empty() {
+                    return Err("non-empty server message".into());
+                }
+                output.push_slice(&self.gs2_header);
+                output.push_slice(&self.client_first_bare);
+                self.state = ScramState::ClientFirst;
+            }
+            ScramState::ClientFirst => {
+                self.state = self.client_final(data, output)?;
+            }
+            ScramState::ClientFinal { server_key, auth_msg } => {
+                let mut verifier = None;
+                for (key, value) in attrs(data) {
+                    match key {
+                        b'e' => {
+                            let error = String::from_utf8_lossy(value).into_owned();
+                            return Err(ScramError::Server(error).into());
+                        }
+                        b'v' => verifier = Some(value),
+                        _ => (),
+                    }
+                }
+                let verifier = verifier.ok_or(ScramError::Malformed("missing verifier"))?;
+                let verifier = ENGINE
+                    .decode(verifier)
+                    .map_err(|_| ScramError::Malformed("invalid verifier"))?;
+                if hmac::verify(&server_key, &auth_msg, &verifier).is_err() {
+                    return Err(ScramError::BadServerSignature.into());
+                }
+            }
+            ScramState::Done => return Err("already finished authentication".into()),
+        }
+        Ok(())
+    }
+
+    fn size_hint(&self) -> usize {
+        self.gs2_header.len() + self.client_first_bare.len()
+    }
+}
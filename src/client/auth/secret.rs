@@ -13,10 +13,15 @@ pub trait LoadSecret {
     ///
     /// This implementation may block.
     fn load_secret(self, data: &mut SecretBuf) -> std::io::Result<()>;
+    /// Returns `true` if a deserialized [`Secret`] should keep this loader
+    /// and only load the secret when it is first needed.
+    ///
+    /// Defaults to `false`, meaning the secret is loaded during deserialization.
+    fn defer(&self) -> bool {
+        false
+    }
 }
 
-/// Trait for types that
-
 /// Guaranteed-to-fail implementation of [`LoadSecret`].
 ///
 /// For use when the loader in a [`Secret`] doesn't matter
@@ -31,29 +36,35 @@ impl LoadSecret for () {
 }
 
 /// A [`Deserialize`][serde::Deserialize] implementation for sensitive byte strings
-/// that loads secrets at deserialization time.
+/// that loads secrets at deserialization time, or when first needed.
+///
+/// Whether a deserialized secret is loaded immediately is determined by
+/// [`LoadSecret::defer`]. Deferred secrets are loaded every time they are used,
+/// such as when [`Sasl::logic`][super::Sasl::logic] is called.
 ///
-/// Deserialization using this type may block on user input.
+/// Loading secrets may block on user input.
 /// Take care when using this type in async contexts.
-#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-pub struct Secret<T, L>(T, std::marker::PhantomData<L>);
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Secret<T, L>(SecretState<T, L>);
 
-impl<T: Clone, L> Clone for Secret<T, L> {
-    fn clone(&self) -> Self {
-        Secret(self.0.clone(), std::marker::PhantomData)
-    }
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+enum SecretState<T, L> {
+    Loaded(T),
+    Deferred(L),
 }
 
-impl<T: Copy, L> Copy for Secret<T, L> {}
-
 impl<T, L> Secret<T, L> {
     /// Wraps a value, returning `self`.
     pub fn new(value: T) -> Self {
-        Secret(value, std::marker::PhantomData)
+        Secret(SecretState::Loaded(value))
+    }
+    /// Wraps a [`LoadSecret`] implementation to be used when the secret is needed.
+    pub fn deferred(loader: L) -> Self {
+        Secret(SecretState::Deferred(loader))
     }
-    /// Unwraps `self` into its contents.
-    pub fn into_inner(this: Self) -> T {
-        this.0
+    /// Returns `true` if the secret has already been loaded.
+    pub fn is_loaded(&self) -> bool {
+        matches!(self.0, SecretState::Loaded(_))
     }
 }
 
@@ -66,28 +77,43 @@ where
     ///
     /// This method may block on user input.
     pub fn load(value: L) -> Result<Self, std::io::Error> {
-        let mut buf = SecretBuf::with_capacity(value.size_hint());
-        value.load_secret(&mut buf)?;
-        let loaded = buf
-            .into_bytes()
-            .try_into()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-        Ok(Secret::new(loaded))
+        Ok(Secret::new(load_with(value)?))
+    }
+    /// Unwraps `self` into its contents, loading the secret if it was deferred.
+    ///
+    /// This method may block on user input.
+    pub fn into_inner(this: Self) -> Result<T, std::io::Error> {
+        match this.0 {
+            SecretState::Loaded(value) => Ok(value),
+            SecretState::Deferred(loader) => load_with(loader),
+        }
     }
 }
 
-impl<T, L> std::ops::Deref for Secret<T, L> {
-    type Target = T;
-
-    fn deref(&self) -> &Self::Target {
-        &self.0
+impl<'a, T: Clone, L: LoadSecret + Clone> Secret<T, L>
+where
+    T: TryFrom<Bytes<'a>>,
+    T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// Returns the secret, loading it if it was deferred.
+    ///
+    /// This method may block on user input.
+    pub fn get(&self) -> Result<std::borrow::Cow<'_, T>, std::io::Error> {
+        match &self.0 {
+            SecretState::Loaded(value) => Ok(std::borrow::Cow::Borrowed(value)),
+            SecretState::Deferred(loader) => load_with(loader.clone()).map(std::borrow::Cow::Owned),
+        }
     }
 }
 
-impl<T, L> std::ops::DerefMut for Secret<T, L> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
-    }
+fn load_with<'a, T, L: LoadSecret>(loader: L) -> Result<T, std::io::Error>
+where
+    T: TryFrom<Bytes<'a>>,
+    T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let mut buf = SecretBuf::with_capacity(loader.size_hint());
+    loader.load_secret(&mut buf)?;
+    buf.into_bytes().try_into().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
 }
 
 /// Zero-added-security implementation of [`LoadSecret`].
@@ -110,6 +136,112 @@ impl LoadSecret for Clear {
     }
 }
 
+/// Implementation of [`LoadSecret`] that reads a secret from an environment variable.
+///
+/// Secrets loaded using this type are [deferred][LoadSecret::defer].
+/// The variable's value must be valid UTF-8.
+///
+/// If the `serde` feature is enabled, `EnvSecret` can be deserialized
+/// from the name of the variable.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Deserialize), serde(transparent))]
+pub struct EnvSecret(pub String);
+
+impl LoadSecret for EnvSecret {
+    fn load_secret(self, data: &mut SecretBuf) -> std::io::Result<()> {
+        use std::{
+            env::VarError,
+            io::{Error, ErrorKind},
+        };
+        let value = std::env::var(&self.0).map_err(|e| match e {
+            VarError::NotPresent => Error::new(
+                ErrorKind::NotFound,
+                format!("environment variable {} is not set", self.0),
+            ),
+            VarError::NotUnicode(_) => Error::new(
+                ErrorKind::InvalidData,
+                format!("environment variable {} is not valid UTF-8", self.0),
+            ),
+        })?;
+        let value = crate::string::Bytes::from_secret(value.into_bytes());
+        data.push_slice(value.as_bytes());
+        Ok(())
+    }
+    fn defer(&self) -> bool {
+        true
+    }
+}
+
+/// Implementation of [`LoadSecret`] that reads a secret from a file.
+///
+/// Secrets loaded using this type are [deferred][LoadSecret::defer].
+/// One trailing line ending is removed from the file's contents, if present.
+///
+/// If the `serde` feature is enabled, `FileSecret` can be deserialized from a path.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Deserialize), serde(transparent))]
+pub struct FileSecret(pub std::path::PathBuf);
+
+impl LoadSecret for FileSecret {
+    fn load_secret(self, data: &mut SecretBuf) -> std::io::Result<()> {
+        let contents = std::fs::read(&self.0).map_err(|e| {
+            std::io::Error::new(
+                e.kind(),
+                format!("failed to read secret from {}: {e}", self.0.display()),
+            )
+        })?;
+        let contents = crate::string::Bytes::from_secret(contents);
+        let mut bytes = contents.as_bytes();
+        bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+        bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+        data.push_slice(bytes);
+        Ok(())
+    }
+    fn defer(&self) -> bool {
+        true
+    }
+}
+
+/// Enum of included [`LoadSecret`] implementations.
+///
+/// If the `serde` feature is enabled, this can be deserialized from a map with one key,
+/// such as `{ env = "IRC_PASS" }` or `{ file = "/run/secrets/irc" }` in TOML.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Deserialize), serde(rename_all = "lowercase"))]
+#[allow(missing_docs)]
+#[non_exhaustive]
+pub enum AnySecret {
+    Env(EnvSecret),
+    File(FileSecret),
+}
+
+impl LoadSecret for AnySecret {
+    fn load_secret(self, data: &mut SecretBuf) -> std::io::Result<()> {
+        match self {
+            AnySecret::Env(s) => s.load_secret(data),
+            AnySecret::File(s) => s.load_secret(data),
+        }
+    }
+    fn defer(&self) -> bool {
+        match self {
+            AnySecret::Env(s) => s.defer(),
+            AnySecret::File(s) => s.defer(),
+        }
+    }
+}
+
+impl From<EnvSecret> for AnySecret {
+    fn from(value: EnvSecret) -> Self {
+        AnySecret::Env(value)
+    }
+}
+
+impl From<FileSecret> for AnySecret {
+    fn from(value: FileSecret) -> Self {
+        AnySecret::File(value)
+    }
+}
+
 #[cfg(all(feature = "serde", feature = "base64"))]
 impl<'a> serde::Deserialize<'a> for Clear {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
@@ -136,9 +268,12 @@ where
         D: serde::Deserializer<'a>,
     {
         use serde::de::Error;
-        let loaded = S::deserialize(deserializer)?;
-        let mut buf = SecretBuf::with_capacity(loaded.size_hint());
-        loaded.load_secret(&mut buf).map_err(D::Error::custom)?;
+        let loader = S::deserialize(deserializer)?;
+        if loader.defer() {
+            return Ok(Secret::deferred(loader));
+        }
+        let mut buf = SecretBuf::with_capacity(loader.size_hint());
+        loader.load_secret(&mut buf).map_err(D::Error::custom)?;
         let bytes = buf.into_bytes().try_into().map_err(D::Error::custom)?;
         Ok(Secret::new(bytes))
     }
@@ -8,10 +8,327 @@ fn sasl_plain() {
     use super::sasl::Plain;
     let sasl =
         Plain::<Clear>::new(NoNul::from_str("foobar"), Secret::new(NoNul::from_str("12345")));
-    let mut logic = sasl.logic().pop().expect("SASL PLAIN should always have logic");
+    let mut logic = sasl.logic().unwrap().pop().expect("SASL PLAIN should always have logic");
     let mut buf = SecretBuf::with_capacity(logic.size_hint());
     logic.reply(b"", &mut buf).expect("SASL auth should not fail");
-    assert_eq!(buf.as_bytes(), b"\0foobar\012345");
+    assert_eq!(buf.as_bytes(), b"\0foobar\x0012345");
+}
+
+/// Mechanism that records every challenge and replies with a fixed response.
+#[cfg(feature = "base64")]
+struct Recorder(std::sync::Arc<std::sync::Mutex<Vec<Vec<u8>>>>, Vec<u8>);
+
+#[cfg(feature = "base64")]
+impl super::SaslLogic for Recorder {
+    fn name(&self) -> crate::string::Arg<'static> {
+        crate::string::Arg::from_str("X-RECORD")
+    }
+
+    fn reply(
+        &mut self,
+        input: &[u8],
+        output: &mut SecretBuf,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.0.lock().unwrap().push(input.to_vec());
+        output.push_slice(&self.1);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "base64")]
+#[test]
+fn sasl_chunked() {
+    use super::{Handler, HandlerError, SaslQueue};
+    use crate::{ircmsg::ServerMsg, string::base64::ChunkEncoder};
+    let challenge: Vec<u8> = (0..900u32).map(|n| n as u8).collect();
+    let lines: Vec<String> = ChunkEncoder::new(&challenge, 400, false)
+        .map(|chunk| format!("AUTHENTICATE {chunk}"))
+        .collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[3], "AUTHENTICATE +");
+    let run = |handler: &mut Handler| {
+        let mut sent = Vec::new();
+        let mut result = Ok(false);
+        for line in lines.iter() {
+            let msg = ServerMsg::parse(line.as_str()).unwrap();
+            result = handler.handle(&msg, |msg: crate::ircmsg::ClientMsg<'static>| {
+                sent.push(String::from_utf8_lossy(msg.args.words()[0].as_bytes()).into_owned());
+            });
+            if result.is_err() {
+                break;
+            }
+        }
+        (result, sent)
+    };
+    let challenges = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let logic = Box::new(Recorder(challenges.clone(), vec![b'a'; 450]));
+    let mut handler = Handler::new(logic, SaslQueue::new(), 500);
+    // The server's chunks are shorter than expected, so each one is a full challenge.
+    let (_, sent) = run(&mut handler);
+    assert_eq!(challenges.lock().unwrap().len(), 4);
+    // The response is also split into chunks of the configured length.
+    assert_eq!(sent.iter().map(String::len).take(2).collect::<Vec<_>>(), [500, 100]);
+    challenges.lock().unwrap().clear();
+    let logic = Box::new(Recorder(challenges.clone(), Vec::new()));
+    let mut handler = Handler::new(logic, SaslQueue::new(), super::DEFAULT_CHUNK_LEN);
+    let (result, sent) = run(&mut handler);
+    assert_eq!(result, Ok(false));
+    assert_eq!(*challenges.lock().unwrap(), [challenge]);
+    assert_eq!(sent, ["+"]);
+    let logic = Box::new(Recorder(challenges.clone(), Vec::new()));
+    let mut handler = Handler::new(logic, SaslQueue::new(), super::DEFAULT_CHUNK_LEN);
+    handler.set_max_len(600);
+    let (result, sent) = run(&mut handler);
+    assert_eq!(result, Err(HandlerError::TooLong(crate::string::Arg::from_str("X-RECORD"))));
+    assert_eq!(sent, ["*"]);
+}
+
+#[cfg(feature = "base64")]
+#[test]
+fn reauthenticate() {
+    use super::{sasl::External, Reauthenticate, SaslQueue};
+    use crate::{
+        client::{
+            channel::SyncChannels,
+            conn::Bidir,
+            state::{Account, Caps, ClientSource},
+            Client,
+        },
+        ircmsg::Source,
+        names::{cap::SASL, NameMap},
+        string::Nick,
+    };
+    use std::io::Cursor;
+    let msgs = concat!(
+        "CAP me NEW :sasl\r\n",
+        ":server 900 me me!u@h acct :Logged in\r\n",
+        "CAP me DEL :sasl\r\n",
+        ":server 901 me me!u@h :Logged out\r\n",
+        "CAP me NEW :sasl=EXTERNAL\r\n",
+        "CAP me ACK :sasl\r\n",
+        "AUTHENTICATE +\r\n",
+        ":server 900 me me!u@h acct :Logged in\r\n",
+        ":server 903 me :SASL authentication successful\r\n",
+        "CAP me DEL :sasl\r\n",
+        "CAP me NEW :sasl\r\n",
+        "CAP me ACK :sasl\r\n",
+    );
+    let mut client = Client::new(Bidir(Cursor::new(msgs.as_bytes()), Vec::new()), SyncChannels);
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1);
+    let challenges = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut attempts = 0;
+    let reauth = Reauthenticate::new(move || {
+        attempts += 1;
+        if attempts > 1 {
+            return SaslQueue::new();
+        }
+        let mut queue: SaslQueue =
+            vec![Box::new(Recorder(challenges.clone(), Vec::new())) as _].into();
+        queue.push(&External::default()).unwrap();
+        queue
+    });
+    let (reauth_id, results) = client.add((), reauth).unwrap();
+    let _msgs = client.add((), crate::client::handlers::YieldAll).unwrap();
+    // Registration has not happened yet, so the first messages are ignored.
+    client.run().unwrap();
+    client.run().unwrap();
+    assert!(client.state().get::<Account>().is_none());
+    let nick = Nick::from_str("me");
+    client.state_mut().insert::<ClientSource>(Source::new_server(nick));
+    let mut caps = NameMap::new();
+    caps.edit().insert((SASL::NAME, Default::default()), true);
+    client.state_mut().insert::<Caps>(caps);
+    // Runs until the empty queue makes the handler give up.
+    while !client.run().unwrap().is_some_and(|(_, finished)| finished.contains(&reauth_id)) {}
+    assert_eq!(results.try_recv(), Ok(Ok(())));
+    assert!(results.try_recv().is_err());
+    assert_eq!(client.state().get::<Account>(), Some(&Some(crate::string::Arg::from_str("acct"))));
+    assert_eq!(client.state().get::<Caps>().unwrap().get_extra(SASL), Some(&true));
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    assert_eq!(sent, "CAP REQ sasl\r\nAUTHENTICATE EXTERNAL\r\nAUTHENTICATE +\r\nCAP REQ sasl\r\n");
+}
+
+/// Test vectors from RFC 7677, the SHA-256 counterpart to RFC 5802.
+#[cfg(all(feature = "crypto", feature = "base64"))]
+#[test]
+fn sasl_scram_sha256() {
+    use super::{
+        sasl::{ScramError, ScramHash, ScramLogic},
+        SaslLogic,
+    };
+    const SERVER_FIRST: &[u8] =
+        b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+    let new_logic = || {
+        ScramLogic::with_nonce(ScramHash::Sha256, b"", b"user", b"pencil", b"rOprNGfwEbeRWgbNEkqO")
+    };
+    let mut logic = new_logic();
+    assert_eq!(logic.name(), "SCRAM-SHA-256");
+    let mut buf = SecretBuf::with_capacity(logic.size_hint());
+    logic.reply(b"", &mut buf).unwrap();
+    assert_eq!(buf.as_bytes(), b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO");
+    let mut buf = SecretBuf::with_capacity(0);
+    logic.reply(SERVER_FIRST, &mut buf).unwrap();
+    assert_eq!(
+        buf.as_bytes(),
+        b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+        p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+            .as_slice()
+    );
+    let mut buf = SecretBuf::with_capacity(0);
+    logic.reply(b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=", &mut buf).unwrap();
+    assert!(buf.is_empty());
+    // A server that does not know the password.
+    let mut logic = new_logic();
+    logic.reply(b"", &mut SecretBuf::with_capacity(0)).unwrap();
+    logic.reply(SERVER_FIRST, &mut SecretBuf::with_capacity(0)).unwrap();
+    let error = logic
+        .reply(b"v=AAAATRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=", &mut SecretBuf::with_capacity(0))
+        .unwrap_err();
+    assert_eq!(error.downcast_ref(), Some(&ScramError::BadServerSignature));
+    // A server that does not extend the client nonce.
+    let mut logic = new_logic();
+    logic.reply(b"", &mut SecretBuf::with_capacity(0)).unwrap();
+    let error = logic
+        .reply(b"r=abcdef,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096", &mut SecretBuf::with_capacity(0))
+        .unwrap_err();
+    assert_eq!(error.downcast_ref(), Some(&ScramError::NonceMismatch));
+    // Servers that ask for too few or too many iterations.
+    for (iters, what) in
+        [(4095, "iteration count too low"), (1_000_001, "iteration count too high")]
+    {
+        let mut logic = new_logic();
+        logic.reply(b"", &mut SecretBuf::with_capacity(0)).unwrap();
+        let msg = format!("r=rOprNGfwEbeRWgbNEkqO%hvY,s=W22ZaJ0SNY7soEsUEjb6gQ==,i={iters}");
+        let error = logic.reply(msg.as_bytes(), &mut SecretBuf::with_capacity(0)).unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&ScramError::Malformed(what)));
+    }
+}
+
+/// Test vectors from draft-melnikov-scram-sha-512.
+#[cfg(all(feature = "crypto", feature = "base64"))]
+#[test]
+fn sasl_scram_sha512() {
+    use super::{
+        sasl::{ScramHash, ScramLogic},
+        SaslLogic,
+    };
+    let mut logic =
+        ScramLogic::with_nonce(ScramHash::Sha512, b"", b"user", b"pencil", b"rOprNGfwEbeRWgbNEkqO");
+    assert_eq!(logic.name(), "SCRAM-SHA-512");
+    let mut buf = SecretBuf::with_capacity(logic.size_hint());
+    logic.reply(b"", &mut buf).unwrap();
+    assert_eq!(buf.as_bytes(), b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO");
+    let mut buf = SecretBuf::with_capacity(0);
+    logic
+        .reply(
+            b"r=rOprNGfwEbeRWgbNEkqO02431b08-2f89-4bad-a4e6-80c0564ec865,\
+            s=Yin2FuHTt/M0kJWb0t9OI32n2VmOGi3m+JfjOvuDF88=,i=4096",
+            &mut buf,
+        )
+        .unwrap();
+    assert_eq!(
+        buf.as_bytes(),
+        b"c=biws,r=rOprNGfwEbeRWgbNEkqO02431b08-2f89-4bad-a4e6-80c0564ec865,\
+        p=Hc5yec3NmCD7t+kFRw4/3yD6/F3SQHc7AVYschRja+Bc3sbdjlA0eH1OjJc0DD4ghn1tnXN5/Wr6qm9xmaHt4A=="
+            .as_slice()
+    );
+    let mut buf = SecretBuf::with_capacity(0);
+    logic
+        .reply(
+            b"v=BQuhnKHqYDwQWS5jAw4sZed+C9KFUALsbrq81bB0mh+bcUUbbMPNNmBIupnS2AmyyDnG5CTBQtkjJ9kyY4kzmw==",
+            &mut buf,
+        )
+        .unwrap();
+    assert!(buf.is_empty());
+}
+
+/// Mechanism that only has a name.
+struct Named(crate::string::Arg<'static>);
+
+impl super::SaslLogic for Named {
+    fn name(&self) -> crate::string::Arg<'static> {
+        self.0.clone()
+    }
+
+    fn reply(
+        &mut self,
+        _: &[u8],
+        _: &mut SecretBuf,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
+}
+
+/// Family that counts how many times it has created logic for its mechanisms.
+struct Counted(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+impl super::MechanismFamily for Counted {
+    fn names(&self) -> Vec<crate::string::Arg<'static>> {
+        use crate::string::Arg;
+        vec![Arg::from_str("X-STRONG"), Arg::from_str("X-WEAK")]
+    }
+
+    fn logic(&self, name: &crate::string::Arg<'_>) -> Option<Box<dyn super::SaslLogic>> {
+        self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Some(Box::new(Named(name.clone().owning())))
+    }
+}
+
+#[test]
+fn sasl_queue_server_order() {
+    use super::{sasl::External, SaslQueue};
+    use crate::string::Word;
+    use std::sync::{atomic::Ordering, Arc};
+    let created = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let mut queue = SaslQueue::new();
+    queue.push_family(Arc::new(Counted(created.clone())));
+    queue.push(&External::default()).unwrap();
+    let names = |queue: &SaslQueue| queue.names().map(|n| n.to_string()).collect::<Vec<_>>();
+    assert_eq!(names(&queue), ["X-STRONG", "X-WEAK", "EXTERNAL"]);
+    queue.sort_by_server_preference(&[Word::from_str("EXTERNAL"), Word::from_str("X-WEAK")]);
+    assert_eq!(names(&queue), ["EXTERNAL", "X-STRONG", "X-WEAK"]);
+    // The family stays together in its own order, even if the server lists a weaker one first.
+    let server = [Word::from_str("X-WEAK"), Word::from_str("EXTERNAL"), Word::from_str("X-STRONG")];
+    queue.sort_by_server_preference(&server);
+    assert_eq!(names(&queue), ["X-STRONG", "X-WEAK", "EXTERNAL"]);
+    queue.retain(&|name| name != "X-STRONG");
+    // Logic is only created for mechanisms that are popped.
+    assert_eq!(created.load(Ordering::Relaxed), 0);
+    assert_eq!(queue.pop().unwrap().name(), "X-WEAK");
+    assert_eq!(created.load(Ordering::Relaxed), 1);
+    assert_eq!(queue.pop().unwrap().name(), "EXTERNAL");
+}
+
+#[test]
+fn file_secret() {
+    use super::{FileSecret, LoadSecret};
+    let path = std::env::temp_dir().join(format!("vinezombie-secret-{}", std::process::id()));
+    let secret: Secret<NoNul<'static>, FileSecret> = Secret::deferred(FileSecret(path.clone()));
+    assert!(!secret.is_loaded());
+    let err = secret.get().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    assert!(err.to_string().contains(&*path.to_string_lossy()));
+    std::fs::write(&path, "hunter2\r\n").unwrap();
+    assert_eq!(secret.get().unwrap().as_bytes(), b"hunter2");
+    assert!(FileSecret(path.clone()).defer());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(all(feature = "crypto", feature = "base64"))]
+#[test]
+fn sasl_scram_escaping() {
+    use super::sasl::Scram;
+    let mut sasl =
+        Scram::<Clear>::new(NoNul::from_str("a=b,c"), Secret::new(NoNul::from_str("12345")));
+    sasl.authzid = NoNul::from_str("z,z");
+    let mut logic = sasl.logic().unwrap().pop().unwrap();
+    let mut buf = SecretBuf::with_capacity(logic.size_hint());
+    logic.reply(b"", &mut buf).unwrap();
+    let first = buf.as_bytes();
+    assert!(first.starts_with(b"n,a=z=2Cz,n=a=3Db=2Cc,r="), "{first:?}");
+    // The nonce is random and printable.
+    assert!(first.len() > 30);
+    assert!(!first[24..].contains(&b','));
 }
 
 #[cfg(feature = "serde")]
@@ -24,6 +341,33 @@ mod serde {
         let string = serde_json::Value::String("aHVudGVyMg==".to_owned());
         let clear: Secret<Line<'static>, Clear> =
             serde_json::from_value(string).expect("deserialization should not fail");
-        assert_eq!(clear.as_bytes(), b"hunter2");
+        assert!(clear.is_loaded());
+        assert_eq!(Secret::into_inner(clear).unwrap().as_bytes(), b"hunter2");
+    }
+
+    #[test]
+    fn de_deferred() {
+        use crate::client::{
+            auth::{AnySasl, AnySecret},
+            register::Options,
+        };
+        const VAR: &str = "VINEZOMBIE_TEST_DE_DEFERRED";
+        std::env::remove_var(VAR);
+        let json = serde_json::json!({
+            "pass": { "env": VAR },
+            "sasl": [{ "Password": { "authcid": "x", "passwd": { "env": VAR } } }],
+        });
+        // Deserialization does not read the environment variable.
+        let opts: Options<AnySecret, AnySasl<AnySecret>> =
+            serde_json::from_value(json).expect("deserialization should not fail");
+        let err = opts.auths().err().expect("loading an unset variable should fail");
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        assert!(err.to_string().contains(VAR));
+        std::env::set_var(VAR, "hunter2");
+        let (queue, required) = opts.auths().unwrap();
+        assert!(!queue.is_empty());
+        assert!(required);
+        assert_eq!(opts.pass().unwrap().unwrap(), "hunter2");
+        std::env::remove_var(VAR);
     }
 }
@@ -16,6 +16,7 @@ type LineBuilder = Builder<Line<'static>>;
 /// `client` and `server` are used to evaluate the maximum message length for
 /// the purpose of ensuring replies will fit on a single line.
 ///
+/// Capabilities that do not fit on one line are split across multiple `CAP REQ` messages.
 /// This function makes a best effort to remain within the 512 byte limit.
 /// Absurd lengths may cause it to emit an over-long message.
 pub fn req<'a>(
@@ -1,16 +1,32 @@
 //! Options for connecting to IRC servers.
 
+#[cfg(all(feature = "tokio", feature = "tokio-codec"))]
+mod framed;
+mod proxy;
+#[cfg(feature = "tokio")]
+mod split;
+mod stats;
 mod sync;
+#[cfg(test)]
+mod tests;
 mod time;
 #[cfg(feature = "tokio")]
 mod tokio;
+#[cfg(feature = "websocket-tokio")]
+pub mod websocket;
 
 #[cfg(feature = "tokio")]
-pub use self::tokio::*;
+pub use self::{split::*, tokio::*};
+pub use proxy::*;
+pub use stats::*;
 pub use sync::*;
 pub use time::*;
 
-use crate::string::{Builder, Word};
+use crate::{
+    error::InvalidString,
+    string::{Builder, Host, Line, Word},
+};
+use std::net::SocketAddr;
 
 /// Smallest power of two larger than the largest IRCv3 message.
 const BUFSIZE: usize = 16384;
@@ -19,8 +35,12 @@ const BUFSIZE: usize = 16384;
 ///
 /// This subset of options is typically all that is trivially configurable
 /// when using WebSocket gateways and bouncers.
+///
+/// This struct may gain new fields, so it cannot be constructed with a struct literal
+/// outside of this crate. Use one of its constructors and set fields afterwards.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde_derive::Serialize, serde_derive::Deserialize))]
+#[non_exhaustive]
 pub struct ServerAddr<'a> {
     /// The address to connect to.
     pub address: Word<'a>,
@@ -28,6 +48,12 @@ pub struct ServerAddr<'a> {
     pub tls: bool,
     /// An optional port number if a non-default one should be used.
     pub port: Option<u16>,
+    /// How long to wait for a TCP connection to be established, if limited.
+    ///
+    /// This covers every address the server's hostname resolves to,
+    /// but not name resolution, the TLS handshake, or connecting to a proxy.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub connect_timeout: Option<std::time::Duration>,
 }
 
 impl<'a> PartialEq for ServerAddr<'a> {
@@ -49,20 +75,56 @@ impl<'a> std::hash::Hash for ServerAddr<'a> {
 }
 
 impl<'a> ServerAddr<'a> {
-    fn utf8_address(&self) -> std::io::Result<&str> {
-        self.address.to_utf8().ok_or_else(|| {
-            std::io::Error::new(std::io::ErrorKind::InvalidInput, "non-utf8 address")
+    /// As [`host`][ServerAddr::host], but returns an I/O error for use when connecting.
+    fn checked_host(&self) -> std::io::Result<Host<'a>> {
+        self.host().map_err(|e| {
+            let msg = format!("invalid server address {}: {e}", self.address);
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, msg)
         })
     }
+    /// Creates a new `ServerAddr`, checking that `host` is a valid hostname or IP address.
+    ///
+    /// With the `idna` feature, Unicode hostnames can be converted first using
+    /// [`Host::from_unicode`].
+    pub fn new<H: TryInto<Host<'a>>>(
+        host: H,
+        port: Option<u16>,
+        tls: bool,
+    ) -> Result<Self, H::Error> {
+        let host = host.try_into()?;
+        Ok(Self { address: host.into(), tls, port, connect_timeout: None })
+    }
+    /// Returns [`address`][ServerAddr::address] as a [`Host`].
+    ///
+    /// Errors if it is not a valid hostname or IP address.
+    /// If the `idna` feature is enabled, non-ASCII addresses
+    /// are converted using [`Host::from_unicode`].
+    pub fn host(&self) -> Result<Host<'a>, InvalidString> {
+        #[cfg(feature = "idna")]
+        if !self.address.is_ascii() {
+            let Some(name) = self.address.to_utf8() else {
+                let byte = self.address.iter().find(|b| !b.is_ascii()).copied();
+                return Err(InvalidString::Byte(byte.unwrap_or_default()));
+            };
+            return Host::from_unicode(name);
+        }
+        Host::from_super(self.address.clone())
+    }
     /// Creates a new `ServerAddr` with `tls = true` and a default port number.
     pub fn from_host<A: TryInto<Word<'a>>>(address: A) -> Result<Self, A::Error> {
         let address = address.try_into()?;
-        Ok(Self { address, tls: true, port: None })
+        Ok(Self { address, tls: true, port: None, connect_timeout: None })
     }
     /// As [`ServerAddr::from_host`] but is `const` and panics on invalid input.
     pub const fn from_host_str(address: &'a str) -> Self {
         let address = Word::from_str(address);
-        Self { address, tls: true, port: None }
+        Self { address, tls: true, port: None, connect_timeout: None }
+    }
+    /// Returns a version of `self` with the provided
+    /// [`connect_timeout`][ServerAddr::connect_timeout].
+    pub fn with_connect_timeout(mut self, timeout: Option<std::time::Duration>) -> Self {
+        self.connect_timeout = timeout;
+        self
     }
     /// Returns a string representation of self.
     pub fn to_word(&self) -> Word<'static> {
@@ -90,6 +152,96 @@ impl<'a> ServerAddr<'a> {
     }
 }
 
+/// Orders resolved addresses so that connection attempts alternate between address families,
+/// starting with the family of the first address, as RFC 8305 recommends.
+fn interleave_addrs(addrs: impl IntoIterator<Item = SocketAddr>) -> Vec<SocketAddr> {
+    let mut first_family = None;
+    let (mut same, mut other) = (Vec::new(), Vec::new());
+    for addr in addrs {
+        let is_ipv6 = addr.is_ipv6();
+        if *first_family.get_or_insert(is_ipv6) == is_ipv6 {
+            same.push(addr);
+        } else {
+            other.push(addr);
+        }
+    }
+    let mut retval = Vec::with_capacity(same.len() + other.len());
+    let (mut same, mut other) = (same.into_iter(), other.into_iter());
+    loop {
+        match (same.next(), other.next()) {
+            (None, None) => return retval,
+            (a, b) => retval.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+/// Returns the error for when no connection could be established before a timeout.
+fn connect_timed_out() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out connecting to server")
+}
+
+/// How long each part of establishing a connection took.
+///
+/// These are measured by [`ServerAddr`]'s `connect` methods and can be retrieved
+/// from the resulting stream. Saving them as the
+/// [`ConnectTimes`][crate::client::state::ConnectTimes] client state before registration
+/// includes them in the [registration timings][crate::client::register::RegistrationTimings].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct ConnectTimings {
+    /// How long it took to resolve the server's hostname.
+    ///
+    /// This is `None` when connecting through a proxy, which resolves the hostname itself.
+    pub resolve: Option<std::time::Duration>,
+    /// How long it took to establish a TCP connection, including any proxy handshake.
+    pub tcp: std::time::Duration,
+    /// How long the TLS handshake took, if TLS was used.
+    pub tls: Option<std::time::Duration>,
+}
+
+/// Error for when a connection was closed after the client
+/// [began shutting down][crate::client::ClientLogic::begin_shutdown]
+/// or after the server sent an `ERROR` message.
+///
+/// This is returned wrapped in an [`std::io::Error`] with the kind
+/// [`ConnectionAborted`][std::io::ErrorKind::ConnectionAborted],
+/// and should generally not be treated as a failure.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ConnectionClosed {
+    /// Whether the server closed the connection without the client asking it to.
+    pub by_server: bool,
+    /// The reason provided when shutdown began, or the server's `ERROR` message.
+    pub reason: Option<Line<'static>>,
+}
+
+impl ConnectionClosed {
+    /// Returns the `ConnectionClosed` wrapped in `error`, if any.
+    pub fn from_io(error: &std::io::Error) -> Option<&Self> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl std::fmt::Display for ConnectionClosed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.by_server {
+            f.write_str("connection closed by server")?;
+        } else {
+            f.write_str("connection closed")?;
+        }
+        if let Some(reason) = &self.reason {
+            write!(f, ": {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConnectionClosed {}
+
+impl From<ConnectionClosed> for std::io::Error {
+    fn from(value: ConnectionClosed) -> Self {
+        std::io::Error::new(std::io::ErrorKind::ConnectionAborted, value)
+    }
+}
+
 /// A pair of unidirectional I/O streams, merged to create a bidirectional stream.
 #[derive(Clone, Debug, Default)]
 pub struct Bidir<R, W>(pub R, pub W);
@@ -99,6 +251,8 @@ pub(super) struct MsgIo<C> {
     pub conn: C,
     pub buf_i: Vec<u8>,
     pub buf_o: Vec<u8>,
+    /// Whether the rest of an over-long line needs to be discarded before the next read.
+    pub discarding: bool,
 }
 
 impl<C> MsgIo<C> {
@@ -110,10 +264,34 @@ impl<C> MsgIo<C> {
             // Aside from being the size of the largest IRCv2 message,
             // this also fits just under 4 old-Twitter-sized messages.
             buf_o: Vec::with_capacity(512),
+            discarding: false,
         }
     }
     pub fn reset(&mut self) {
         self.buf_i.clear();
         self.buf_o.clear();
+        self.discarding = false;
+    }
+    /// Drops the line in the input buffer if `e` was caused by its contents,
+    /// updating `logic`'s read statistics. Otherwise, returns `e`.
+    pub fn drop_line(
+        &mut self,
+        e: std::io::Error,
+        logic: &mut super::ClientLogic,
+    ) -> std::io::Result<()> {
+        let Some(reason) = DropReason::from_io(&e) else {
+            return Err(e);
+        };
+        logic.reads.record_drop(reason);
+        let excerpt = &self.buf_i[..std::cmp::min(EXCERPT_LEN, self.buf_i.len())];
+        let dropped = DroppedLine { reason, excerpt, line: logic.reads.lines() - 1 };
+        #[cfg(feature = "tracing")]
+        tracing::warn!(target: "vinezombie::recv", "{dropped}");
+        if let Some(on_drop) = &mut logic.on_drop {
+            on_drop(&dropped);
+        }
+        self.buf_i.clear();
+        self.discarding = reason == DropReason::TooLong;
+        Ok(())
     }
 }
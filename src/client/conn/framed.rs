@@ -0,0 +1,124 @@
+use super::{timed_io, DropReason, DroppedLine};
+use crate::{
+    client::ClientLogic,
+    ircmsg::{ClientMsg, ServerMsg},
+};
+use futures_core::Stream;
+use futures_sink::Sink;
+use std::{future::Future, pin::Pin, time::Duration};
+
+/// Awaits `fut`, failing with [`TimedOut`][std::io::ErrorKind::TimedOut]
+/// if `timeout` passes first.
+async fn write_timed<T>(
+    fut: impl Future<Output = std::io::Result<T>>,
+    timeout: Option<Duration>,
+) -> std::io::Result<T> {
+    let Some(timeout) = timeout else {
+        return fut.await;
+    };
+    tokio::time::timeout(timeout, fut)
+        .await
+        .unwrap_or_else(|_| Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "write timeout")))
+}
+
+impl ClientLogic {
+    /// Runs handlers off of a stream of messages until any of them yield or finish.
+    ///
+    /// This is intended for connections that are already framed, such as a
+    /// [`Framed`](tokio_util::codec::Framed) using [`ClientCodec`][crate::ircmsg::ClientCodec],
+    /// and otherwise behaves like [`Client::run_tokio`][crate::client::Client::run_tokio].
+    /// Queued messages are sent into `stream` as the queue's rate limit allows,
+    /// and the read and write timeouts apply to receiving from and sending into `stream`.
+    ///
+    /// Errors yielded by `stream` for lines that are too long or cannot be parsed
+    /// are reported to the [drop function][ClientLogic::set_drop_fn] with an empty excerpt.
+    /// Many streams, including `Framed`, end after yielding any error.
+    /// The end of `stream` is reported as an [`UnexpectedEof`][std::io::ErrorKind::UnexpectedEof].
+    pub async fn run_on_stream<S>(
+        &mut self,
+        stream: &mut S,
+    ) -> std::io::Result<Option<(&[usize], &[usize])>>
+    where
+        S: Stream<Item = std::io::Result<ServerMsg<'static>>>
+            + Sink<ClientMsg<'static>, Error = std::io::Error>
+            + Unpin,
+    {
+        let finished_at = loop {
+            let wait_for = self.flush_partial_stream(stream).await?;
+            let expired_at = self.expire_handlers();
+            if self.handlers.has_results(expired_at) {
+                break expired_at;
+            }
+            let wait_for = self.wait_for_deadline(wait_for);
+            if self.handlers.is_empty() {
+                if let Some(wait_for) = wait_for {
+                    tokio::time::sleep(wait_for).await;
+                    continue;
+                }
+                return Ok(Some((Default::default(), Default::default())));
+            }
+            let next = async {
+                std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx))
+                    .await
+                    .unwrap_or_else(|| {
+                        Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "end of stream"))
+                    })
+            };
+            let msg_result = timed_io(next, wait_for, self.timeout.read_timeout()).await;
+            let msg_result = match msg_result {
+                Err(e) => match DropReason::from_io(&e) {
+                    Some(reason) => {
+                        self.reads.record_drop(reason);
+                        let dropped =
+                            DroppedLine { reason, excerpt: &[], line: self.reads.lines() - 1 };
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(target: "vinezombie::recv", "{dropped}");
+                        if let Some(on_drop) = &mut self.on_drop {
+                            on_drop(&dropped);
+                        }
+                        continue;
+                    }
+                    None => Err(e),
+                },
+                Ok(msg) => Ok(msg),
+            };
+            let msg = match msg_result.map_err(|e| self.filter_io_error(e))? {
+                Ok(m) => m,
+                Err(true) => continue,
+                Err(false) => return Ok(None),
+            };
+            #[cfg(feature = "tracing")]
+            tracing::debug!(target: "vinezombie::recv", "{}", msg.redacted());
+            self.reads.parsed += 1;
+            let finished_at = self.run_once(&msg);
+            if self.handlers.has_results(finished_at) {
+                self.flush_partial_stream(stream).await?;
+                break finished_at;
+            }
+        };
+        Ok(Some(self.handlers.last_run_results(finished_at)))
+    }
+    /// Sends queued messages into `sink` until the queue is empty or hits rate limits.
+    async fn flush_partial_stream<S>(&mut self, sink: &mut S) -> std::io::Result<Option<Duration>>
+    where
+        S: Sink<ClientMsg<'static>, Error = std::io::Error> + Unpin,
+    {
+        if self.queue.is_empty() {
+            return Ok(None);
+        }
+        let mut timeout = None;
+        let queue = &mut self.queue;
+        let send = async {
+            while let Some(popped) = queue.pop(|new_timeout| timeout = new_timeout) {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(target: "vinezombie::send", "{}", popped.redacted());
+                std::future::poll_fn(|cx| Pin::new(&mut *sink).poll_ready(cx)).await?;
+                Pin::new(&mut *sink).start_send(popped)?;
+            }
+            std::future::poll_fn(|cx| Pin::new(&mut *sink).poll_flush(cx)).await
+        };
+        let result = write_timed(send, self.timeout.write_timeout()).await;
+        result.map_err(|e| self.filter_io_error(e))?;
+        Ok(timeout)
+    }
+}
@@ -0,0 +1,313 @@
+use crate::string::{NoNul, Word};
+use std::io::{Error, ErrorKind};
+
+/// A proxy to connect to IRC servers through.
+///
+/// See [`ServerAddr::connect_via`][super::ServerAddr::connect_via]
+/// and [`ServerAddr::connect_tokio_via`][super::ServerAddr::connect_tokio_via].
+/// In both cases, the server's address is resolved by the proxy,
+/// which allows connecting to hostnames that only the proxy can resolve,
+/// such as Tor onion services.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize, serde_derive::Deserialize))]
+pub enum Proxy {
+    /// A SOCKS5 proxy, as described in RFC 1928.
+    Socks5 {
+        /// The address of the proxy.
+        addr: Word<'static>,
+        /// The port number of the proxy.
+        port: u16,
+        /// Credentials for username/password authentication (RFC 1929), if required.
+        auth: Option<ProxyAuth>,
+    },
+    /// An HTTP proxy that supports the `CONNECT` method.
+    ///
+    /// Authenticating to the proxy requires the `base64` feature.
+    HttpConnect {
+        /// The address of the proxy.
+        addr: Word<'static>,
+        /// The port number of the proxy.
+        port: u16,
+        /// Credentials for basic authentication, if required.
+        auth: Option<ProxyAuth>,
+    },
+}
+
+/// Credentials for authenticating to a [`Proxy`].
+///
+/// The password is omitted from this type's `Debug` output.
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize, serde_derive::Deserialize))]
+pub struct ProxyAuth {
+    /// The username.
+    pub username: NoNul<'static>,
+    /// The password.
+    pub password: NoNul<'static>,
+}
+
+impl std::fmt::Debug for ProxyAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxyAuth")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+impl Proxy {
+    fn utf8_address(&self) -> std::io::Result<(&str, u16)> {
+        let (Proxy::Socks5 { addr, port, .. } | Proxy::HttpConnect { addr, port, .. }) = self;
+        let addr = addr
+            .to_utf8()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "non-utf8 proxy address"))?;
+        Ok((addr, *port))
+    }
+    /// Connects to `host` on `port` through this proxy.
+    pub(super) fn connect(&self, host: &str, port: u16) -> std::io::Result<std::net::TcpStream> {
+        use std::io::{Read, Write};
+        let mut handshake = Handshake::new(self, host, port)?;
+        let mut sock = std::net::TcpStream::connect(self.utf8_address()?)?;
+        let mut input = Vec::new();
+        while let Some((output, read_len)) = handshake.next(&input)? {
+            sock.write_all(&output)?;
+            input.resize(read_len, 0);
+            sock.read_exact(&mut input).map_err(eof_to_invalid_data)?;
+        }
+        Ok(sock)
+    }
+    /// Asynchronously connects to `host` on `port` through this proxy.
+    #[cfg(feature = "tokio")]
+    pub(super) async fn connect_tokio(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> std::io::Result<tokio::net::TcpStream> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let mut handshake = Handshake::new(self, host, port)?;
+        let mut sock = tokio::net::TcpStream::connect(self.utf8_address()?).await?;
+        let mut input = Vec::new();
+        while let Some((output, read_len)) = handshake.next(&input)? {
+            sock.write_all(&output).await?;
+            input.resize(read_len, 0);
+            sock.read_exact(&mut input).await.map_err(eof_to_invalid_data)?;
+        }
+        Ok(sock)
+    }
+}
+
+fn eof_to_invalid_data(e: Error) -> Error {
+    if e.kind() == ErrorKind::UnexpectedEof {
+        Error::new(ErrorKind::InvalidData, "proxy closed the connection during handshake")
+    } else {
+        e
+    }
+}
+
+/// The longest HTTP response header that will be accepted from a proxy.
+const MAX_HTTP_HEADER: usize = 8192;
+
+#[derive(Clone, Copy)]
+enum HandshakeState {
+    Start,
+    Socks5Method,
+    Socks5Auth,
+    Socks5Reply,
+    Socks5BoundAddr,
+    HttpResponse,
+}
+
+/// Transport-agnostic proxy handshake logic.
+struct Handshake<'a> {
+    proxy: &'a Proxy,
+    host: &'a str,
+    port: u16,
+    state: HandshakeState,
+    /// The HTTP response header read so far.
+    header: Vec<u8>,
+}
+
+impl<'a> Handshake<'a> {
+    fn new(proxy: &'a Proxy, host: &'a str, port: u16) -> std::io::Result<Self> {
+        if matches!(proxy, Proxy::Socks5 { .. }) && host.len() > 255 {
+            return Err(Error::new(ErrorKind::InvalidInput, "address too long for socks5 proxy"));
+        }
+        Ok(Handshake { proxy, host, port, state: HandshakeState::Start, header: Vec::new() })
+    }
+    /// Processes the bytes read from the proxy.
+    ///
+    /// Returns the bytes to send and how many bytes to read next,
+    /// or `None` if the handshake is complete.
+    fn next(&mut self, input: &[u8]) -> std::io::Result<Option<(Vec<u8>, usize)>> {
+        let next = match (self.state, self.proxy) {
+            (HandshakeState::Start, Proxy::Socks5 { auth, .. }) => {
+                self.state = HandshakeState::Socks5Method;
+                if auth.is_some() {
+                    (vec![5, 2, 0, 2], 2)
+                } else {
+                    (vec![5, 1, 0], 2)
+                }
+            }
+            (HandshakeState::Socks5Method, Proxy::Socks5 { auth, .. }) => {
+                if input[0] != 5 {
+                    return Err(socks5_invalid("unexpected version in method selection"));
+                }
+                match (input[1], auth) {
+                    (0, _) => self.socks5_request(),
+                    (2, Some(auth)) => {
+                        let username = auth.username.as_bytes();
+                        let password = auth.password.as_bytes();
+                        if username.len() > 255 || password.len() > 255 {
+                            return Err(Error::new(
+                                ErrorKind::InvalidInput,
+                                "credentials too long for socks5 proxy",
+                            ));
+                        }
+                        let mut msg = Vec::with_capacity(3 + username.len() + password.len());
+                        msg.push(1);
+                        msg.push(username.len() as u8);
+                        msg.extend_from_slice(username);
+                        msg.push(password.len() as u8);
+                        msg.extend_from_slice(password);
+                        self.state = HandshakeState::Socks5Auth;
+                        (msg, 2)
+                    }
+                    (0xFF, _) => {
+                        return Err(socks5_refused("no acceptable authentication methods"))
+                    }
+                    _ => return Err(socks5_invalid("proxy chose an unoffered method")),
+                }
+            }
+            (HandshakeState::Socks5Auth, _) => {
+                if input[1] != 0 {
+                    return Err(socks5_refused("authentication failed"));
+                }
+                self.socks5_request()
+            }
+            (HandshakeState::Socks5Reply, _) => {
+                if input[0] != 5 {
+                    return Err(socks5_invalid("unexpected version in reply"));
+                }
+                if input[1] != 0 {
+                    let reason = match input[1] {
+                        1 => "general server failure",
+                        2 => "connection not allowed by ruleset",
+                        3 => "network unreachable",
+                        4 => "host unreachable",
+                        5 => "connection refused",
+                        6 => "TTL expired",
+                        7 => "command not supported",
+                        8 => "address type not supported",
+                        _ => "unknown error",
+                    };
+                    return Err(socks5_refused(reason));
+                }
+                // The last byte read is the first byte of the bound address.
+                // Read and discard the rest of it, followed by the bound port.
+                let rest = match input[3] {
+                    1 => 3 + 2,
+                    3 => input[4] as usize + 2,
+                    4 => 15 + 2,
+                    _ => return Err(socks5_invalid("unknown address type in reply")),
+                };
+                self.state = HandshakeState::Socks5BoundAddr;
+                (Vec::new(), rest)
+            }
+            (HandshakeState::Socks5BoundAddr, _) => return Ok(None),
+            (HandshakeState::Start, Proxy::HttpConnect { auth, .. }) => {
+                let target = if self.host.contains(':') {
+                    format!("[{}]:{}", self.host, self.port)
+                } else {
+                    format!("{}:{}", self.host, self.port)
+                };
+                let mut msg = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+                if let Some(auth) = auth {
+                    msg.push_str("Proxy-Authorization: Basic ");
+                    msg.push_str(&basic_auth(auth)?);
+                    msg.push_str("\r\n");
+                }
+                msg.push_str("\r\n");
+                self.state = HandshakeState::HttpResponse;
+                (msg.into_bytes(), 1)
+            }
+            (HandshakeState::HttpResponse, _) => {
+                let header = &mut self.header;
+                header.extend_from_slice(input);
+                if !header.ends_with(b"\r\n\r\n") {
+                    if header.len() >= MAX_HTTP_HEADER {
+                        return Err(http_invalid("response header too long"));
+                    }
+                    return Ok(Some((Vec::new(), 1)));
+                }
+                let status_line = header.split(|b| *b == b'\n').next().unwrap_or_default();
+                let status_line = String::from_utf8_lossy(status_line);
+                let status_line = status_line.trim_end();
+                let mut parts = status_line.split(' ');
+                if !parts.next().is_some_and(|v| v.starts_with("HTTP/")) {
+                    return Err(http_invalid("malformed status line"));
+                }
+                let Some(status) = parts.next().and_then(|s| s.parse::<u16>().ok()) else {
+                    return Err(http_invalid("malformed status line"));
+                };
+                if !(200..300).contains(&status) {
+                    return Err(Error::new(
+                        ErrorKind::ConnectionRefused,
+                        format!("http proxy: {status_line}"),
+                    ));
+                }
+                return Ok(None);
+            }
+            (_, Proxy::HttpConnect { .. }) => unreachable!(),
+        };
+        Ok(Some(next))
+    }
+    fn socks5_request(&mut self) -> (Vec<u8>, usize) {
+        let mut msg = vec![5, 1, 0];
+        match self.host.parse::<std::net::IpAddr>() {
+            Ok(std::net::IpAddr::V4(ip)) => {
+                msg.push(1);
+                msg.extend_from_slice(&ip.octets());
+            }
+            Ok(std::net::IpAddr::V6(ip)) => {
+                msg.push(4);
+                msg.extend_from_slice(&ip.octets());
+            }
+            Err(_) => {
+                // Let the proxy resolve the hostname.
+                msg.push(3);
+                msg.push(self.host.len() as u8);
+                msg.extend_from_slice(self.host.as_bytes());
+            }
+        }
+        msg.extend_from_slice(&self.port.to_be_bytes());
+        self.state = HandshakeState::Socks5Reply;
+        // Read up to and including the first byte of the bound address,
+        // which is the length of the address if it is a hostname.
+        (msg, 5)
+    }
+}
+
+fn socks5_invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("socks5 proxy: {msg}"))
+}
+
+fn socks5_refused(msg: &str) -> Error {
+    Error::new(ErrorKind::ConnectionRefused, format!("socks5 proxy: {msg}"))
+}
+
+fn http_invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("http proxy: {msg}"))
+}
+
+#[cfg(feature = "base64")]
+fn basic_auth(auth: &ProxyAuth) -> std::io::Result<String> {
+    use base64::{engine::general_purpose::STANDARD as ENGINE, Engine};
+    let mut creds = auth.username.as_bytes().to_vec();
+    creds.push(b':');
+    creds.extend_from_slice(auth.password.as_bytes());
+    Ok(ENGINE.encode(creds))
+}
+
+#[cfg(not(feature = "base64"))]
+fn basic_auth(_: &ProxyAuth) -> std::io::Result<String> {
+    Err(Error::new(ErrorKind::Unsupported, "http proxy authentication requires base64"))
+}
@@ -0,0 +1,407 @@
+use super::{timed_io, Bidir, MsgIo};
+use crate::{
+    client::{channel::ChannelSpec, Client, ClientLogic, MakeHandler},
+    ircmsg::ClientCodec,
+};
+use std::{
+    ops::ControlFlow,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncWrite},
+    sync::Notify,
+};
+
+type TimeoutFn = Box<dyn FnMut(&mut ClientLogic) -> ControlFlow<()> + Send>;
+
+/// The state shared between a [`ClientReader`] and a [`ClientWriter`].
+struct Shared {
+    logic: Mutex<Box<ClientLogic>>,
+    /// Wakes the writer when messages are queued or the reader is dropped.
+    notify: Notify,
+    /// Set when the reader is dropped, before the writer is woken.
+    closed: AtomicBool,
+    /// How many times the writer has woken up.
+    #[cfg(test)]
+    wakeups: std::sync::atomic::AtomicUsize,
+}
+
+impl Shared {
+    fn lock(&self) -> LogicGuard<'_> {
+        self.lock_with(Some(&self.notify))
+    }
+    /// Locks without waking the writer, for use by the writer itself.
+    ///
+    /// Otherwise, the writer would wake itself up whenever it leaves rate-limited messages
+    /// in the queue, and spin until they can be sent.
+    fn lock_writer(&self) -> LogicGuard<'_> {
+        self.lock_with(None)
+    }
+    fn lock_with<'a>(&'a self, notify: Option<&'a Notify>) -> LogicGuard<'a> {
+        // A panicking handler leaves nothing half-updated that the other half cares about.
+        let guard = self.logic.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let queued = guard.queue.len();
+        LogicGuard { guard, notify, queued }
+    }
+}
+
+/// Exclusive access to the [`ClientLogic`] shared by a split client.
+///
+/// If any messages are queued while this guard is held, the [`ClientWriter`] is woken
+/// when it is dropped.
+/// This guard cannot be held across `.await`s in a spawned task, as it is not `Send`,
+/// and it should be dropped quickly as it blocks the other half of the client.
+pub struct LogicGuard<'a> {
+    guard: MutexGuard<'a, Box<ClientLogic>>,
+    notify: Option<&'a Notify>,
+    /// The length of the queue when this guard was created.
+    queued: usize,
+}
+
+impl std::ops::Deref for LogicGuard<'_> {
+    type Target = ClientLogic;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl std::ops::DerefMut for LogicGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl Drop for LogicGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(notify) = self.notify.filter(|_| self.guard.queue.len() > self.queued) {
+            notify.notify_one();
+        }
+    }
+}
+
+/// Wakes the writer when the reader is dropped so that it can stop.
+struct ReaderHandle(Arc<Shared>);
+
+impl Drop for ReaderHandle {
+    fn drop(&mut self) {
+        // The writer may wake before this handle's `Arc` is released,
+        // so it checks this flag instead of the reference count.
+        self.0.closed.store(true, Ordering::Release);
+        self.0.notify.notify_one();
+    }
+}
+
+/// The half of a [split][Client::split] client that reads messages and runs handlers.
+pub struct ClientReader<R, S> {
+    conn: MsgIo<R>,
+    spec: S,
+    shared: ReaderHandle,
+    on_timeout: Option<TimeoutFn>,
+    yielded: Vec<usize>,
+    finished: Vec<usize>,
+}
+
+/// The half of a [split][Client::split] client that sends queued messages.
+pub struct ClientWriter<W> {
+    conn: W,
+    buf_o: Vec<u8>,
+    shared: Arc<Shared>,
+}
+
+impl<R, W, S> Client<Bidir<R, W>, S>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    /// Splits `self` into halves that read and write independently,
+    /// for use in separate tasks.
+    ///
+    /// The [`ClientWriter`] sends messages as soon as they are queued,
+    /// subject to the queue's rate limits, instead of only between reads.
+    /// The [`ClientReader`] runs handlers, whose messages (such as `PONG`s) are picked up
+    /// by the writer once the handlers are done with the message.
+    ///
+    /// Both halves share one [`ClientLogic`] behind a mutex,
+    /// which is locked only briefly and never held across an `.await` by either half.
+    /// Handlers run with this lock held, so they should not block,
+    /// and nothing the halves expose should be called while holding a [`LogicGuard`].
+    ///
+    /// Streams like [`TcpStream`][tokio::net::TcpStream] can be split beforehand using
+    /// [`into_split`][tokio::net::TcpStream::into_split] or [`tokio::io::split`],
+    /// with the read half wrapped in a [`BufReader`][tokio::io::BufReader] to form a [`Bidir`].
+    /// The halves can be recombined using [`ClientReader::reunite`].
+    pub fn split(self) -> (ClientReader<R, S>, ClientWriter<W>) {
+        let Bidir(read, write) = self.conn.conn;
+        let shared = Arc::new(Shared {
+            logic: Mutex::new(self.logic),
+            notify: Notify::new(),
+            closed: AtomicBool::new(false),
+            #[cfg(test)]
+            wakeups: Default::default(),
+        });
+        if !shared.lock().queue.is_empty() {
+            shared.notify.notify_one();
+        }
+        let reader = ClientReader {
+            conn: MsgIo {
+                conn: read,
+                buf_i: self.conn.buf_i,
+                buf_o: Vec::new(),
+                discarding: self.conn.discarding,
+            },
+            spec: self.spec,
+            shared: ReaderHandle(shared.clone()),
+            on_timeout: self.on_timeout,
+            yielded: Vec::new(),
+            finished: Vec::new(),
+        };
+        let writer = ClientWriter { conn: write, buf_o: self.conn.buf_o, shared };
+        (reader, writer)
+    }
+}
+
+impl<R, S> ClientReader<R, S> {
+    /// Locks the shared [`ClientLogic`].
+    ///
+    /// See [`LogicGuard`] for more information.
+    pub fn lock(&self) -> LogicGuard<'_> {
+        self.shared.0.lock()
+    }
+    /// Recombines `self` with `writer` into one client.
+    ///
+    /// Fails if `writer` was not split from the same client as `self`.
+    #[allow(clippy::type_complexity)]
+    pub fn reunite<W>(
+        self,
+        writer: ClientWriter<W>,
+    ) -> Result<Client<Bidir<R, W>, S>, Box<ReuniteError<R, S, W>>> {
+        if !Arc::ptr_eq(&self.shared.0, &writer.shared) {
+            return Err(Box::new(ReuniteError { reader: self, writer }));
+        }
+        let ClientReader { conn, spec, shared, on_timeout, .. } = self;
+        std::mem::drop(shared);
+        let Ok(logic) = Arc::try_unwrap(writer.shared) else {
+            unreachable!("split client has more than two halves");
+        };
+        let logic = logic.logic.into_inner().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let conn = MsgIo {
+            conn: Bidir(conn.conn, writer.conn),
+            buf_i: conn.buf_i,
+            buf_o: writer.buf_o,
+            discarding: conn.discarding,
+        };
+        Ok(Client { conn, spec, logic, on_timeout })
+    }
+    /// Sets the function to call on read timeout. See [`Client::set_timeout_fn`].
+    pub fn set_timeout_fn(
+        &mut self,
+        f: Option<impl FnMut(&mut ClientLogic) -> ControlFlow<()> + 'static + Send>,
+    ) {
+        if let Some(f) = f {
+            self.on_timeout = Some(Box::new(f));
+        } else {
+            self.on_timeout = None;
+        }
+    }
+}
+
+/// Error returned by [`ClientReader::reunite`] if the halves are from different clients.
+pub struct ReuniteError<R, S, W> {
+    /// The reader that was passed to `reunite`.
+    pub reader: ClientReader<R, S>,
+    /// The writer that was passed to `reunite`.
+    pub writer: ClientWriter<W>,
+}
+
+impl<R, S, W> std::fmt::Debug for ReuniteError<R, S, W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReuniteError").finish_non_exhaustive()
+    }
+}
+
+impl<R, S, W> std::fmt::Display for ReuniteError<R, S, W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("tried to reunite halves of different clients")
+    }
+}
+
+impl<R, S, W> std::error::Error for ReuniteError<R, S, W> {}
+
+impl<R, S: ChannelSpec> ClientReader<R, S> {
+    /// Adds a handler. Creates a new channel using the internal [`ChannelSpec`].
+    ///
+    /// Any messages queued by the handler are sent by the [`ClientWriter`].
+    /// Returns the handler id and the receiver half of the channel.
+    pub fn add<T, M: MakeHandler<T>>(
+        &mut self,
+        make_handler: M,
+        value: T,
+    ) -> Result<(usize, M::Receiver<S>), M::Error> {
+        self.lock().add_with_spec(&self.spec, make_handler, value)
+    }
+}
+
+impl<R: AsyncBufRead + Unpin, S> ClientReader<R, S> {
+    /// Runs handlers off of the connection until any of them yield or finish.
+    ///
+    /// Behaves like [`Client::run_tokio`], except that it never sends anything.
+    /// If there are no handlers to run, returns immediately.
+    pub async fn run(&mut self) -> std::io::Result<Option<(&[usize], &[usize])>> {
+        self.yielded.clear();
+        self.finished.clear();
+        loop {
+            let (wait_for, read_timeout, owning, codec) = {
+                let mut logic = self.shared.0.lock();
+                let expired_at = logic.expire_handlers();
+                if logic.handlers.has_results(expired_at) {
+                    take_results(&mut self.yielded, &mut self.finished, &logic, expired_at);
+                    break;
+                }
+                if logic.handlers.is_empty() {
+                    break;
+                }
+                let owning = logic.handlers.wants_owning() && logic.on_drop.is_none();
+                let timeout = logic.timeout.read_timeout();
+                (logic.wait_for_deadline(None), timeout, owning, logic.read_codec())
+            };
+            let (conn, buf, discarding) =
+                (&mut self.conn.conn, &mut self.conn.buf_i, &mut self.conn.discarding);
+            let msg_result = if owning {
+                let fut = async {
+                    super::skip_line_tokio(conn, discarding).await?;
+                    codec.read_owning_tokio(conn, buf).await
+                };
+                timed_io(fut, wait_for, read_timeout).await
+            } else {
+                let fut = async {
+                    super::skip_line_tokio(conn, discarding).await?;
+                    codec.read_borrowing_tokio(conn, buf).await
+                };
+                timed_io(fut, wait_for, read_timeout).await
+            };
+            let mut logic = self.shared.0.lock();
+            let msg_result = match msg_result {
+                Ok(msg) => Ok(msg),
+                Err(e) => match self.conn.drop_line(e, &mut logic) {
+                    Ok(()) => continue,
+                    Err(e) => Err(e),
+                },
+            };
+            let msg = match msg_result.map_err(|e| logic.filter_io_error(e))? {
+                Ok(m) => m,
+                Err(true) => continue,
+                Err(false) => {
+                    return if let Some(timeout_fn) = &mut self.on_timeout {
+                        if timeout_fn(&mut logic).is_continue() {
+                            continue;
+                        }
+                        Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "read timeout"))
+                    } else {
+                        Ok(None)
+                    }
+                }
+            };
+            #[cfg(feature = "tracing")]
+            tracing::debug!(target: "vinezombie::recv", "{}", msg.redacted());
+            logic.reads.parsed += 1;
+            let finished_at = logic.run_once(&msg);
+            self.conn.buf_i.clear();
+            if logic.handlers.has_results(finished_at) {
+                take_results(&mut self.yielded, &mut self.finished, &logic, finished_at);
+                break;
+            }
+        }
+        Ok(Some((&self.yielded, &self.finished)))
+    }
+}
+
+fn take_results(
+    yielded: &mut Vec<usize>,
+    finished: &mut Vec<usize>,
+    logic: &ClientLogic,
+    finished_at: usize,
+) {
+    let (new_yielded, new_finished) = logic.handlers.last_run_results(finished_at);
+    yielded.extend_from_slice(new_yielded);
+    finished.extend_from_slice(new_finished);
+}
+
+impl<W> ClientWriter<W> {
+    /// Locks the shared [`ClientLogic`].
+    ///
+    /// See [`LogicGuard`] for more information.
+    pub fn lock(&self) -> LogicGuard<'_> {
+        self.shared.lock()
+    }
+    /// Returns how many times [`run`][Self::run] has woken up.
+    #[cfg(test)]
+    pub(super) fn wakeups(&self) -> usize {
+        self.shared.wakeups.load(Ordering::Relaxed)
+    }
+}
+
+impl<W: AsyncWrite + Unpin> ClientWriter<W> {
+    /// Sends queued messages as they are queued, until the [`ClientReader`] is dropped
+    /// and the queue is empty.
+    ///
+    /// Messages are sent no faster than the queue's rate limits allow.
+    /// I/O failure should be considered non-recoverable.
+    /// If the client is [shutting down][ClientLogic::begin_shutdown]
+    /// or the server sent `ERROR`, I/O failure is instead reported as a
+    /// [`ConnectionClosed`][super::ConnectionClosed].
+    pub async fn run(&mut self) -> std::io::Result<()> {
+        loop {
+            let wait_for = self.flush_partial().await?;
+            let closed = self.shared.closed.load(Ordering::Acquire);
+            if closed && self.shared.lock_writer().queue.is_empty() {
+                return Ok(());
+            }
+            let notified = self.shared.notify.notified();
+            match wait_for {
+                Some(wait_for) => {
+                    let _ = tokio::time::timeout(wait_for, notified).await;
+                }
+                None => notified.await,
+            }
+            #[cfg(test)]
+            self.shared.wakeups.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    /// Sends queued messages until the queue is empty or hits rate limits.
+    ///
+    /// Returns how long to wait until more messages can be sent, if any are still queued.
+    /// If the `tracing` feature is enabled, logs messages at the debug level.
+    pub async fn flush_partial(&mut self) -> std::io::Result<Option<Duration>> {
+        use tokio::io::AsyncWriteExt;
+        let (timeout, write_timeout) = {
+            let mut logic = self.shared.lock_writer();
+            let mut timeout = None;
+            while let Some(popped) = logic.queue.pop(|new_timeout| timeout = new_timeout) {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(target: "vinezombie::send", "{}", popped.redacted());
+                let _ = ClientCodec::write_to(&popped, &mut self.buf_o);
+                self.buf_o.extend_from_slice(b"\r\n");
+            }
+            (timeout, logic.timeout.write_timeout())
+        };
+        if self.buf_o.is_empty() {
+            return Ok(timeout);
+        }
+        let write = async {
+            self.conn.write_all(&self.buf_o).await?;
+            self.conn.flush().await
+        };
+        let result = match write_timeout {
+            Some(dur) => tokio::time::timeout(dur, write).await.unwrap_or_else(|e| Err(e.into())),
+            None => write.await,
+        };
+        self.buf_o.clear();
+        result.map_err(|e| self.shared.lock_writer().filter_io_error(e))?;
+        Ok(timeout)
+    }
+}
@@ -0,0 +1,174 @@
+use crate::error::{InvalidString, ParseError};
+
+/// The maximum length of [`DroppedLine::excerpt`].
+pub const EXCERPT_LEN: usize = 64;
+
+/// Reasons why a line received from the server may be dropped.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub enum DropReason {
+    /// The line was longer than the maximum message length.
+    ///
+    /// The rest of the line is discarded up to the next line ending.
+    TooLong,
+    /// The line contained bytes that are not allowed in IRC messages, such as NUL.
+    InvalidBytes,
+    /// The line could not be parsed as a message.
+    DecodeError,
+}
+
+impl DropReason {
+    /// Returns the reason a line would be dropped due to the provided error,
+    /// or `None` if the error is not caused by the contents of the line.
+    pub fn from_io(e: &std::io::Error) -> Option<Self> {
+        if e.kind() != std::io::ErrorKind::InvalidData {
+            return None;
+        }
+        let inner = e.get_ref()?;
+        if let Some(e) = inner.downcast_ref::<ParseError>() {
+            Some(match e {
+                ParseError::TooLong => DropReason::TooLong,
+                ParseError::InvalidLine(_) => DropReason::InvalidBytes,
+                _ => DropReason::DecodeError,
+            })
+        } else {
+            inner.downcast_ref::<InvalidString>().map(|_| DropReason::InvalidBytes)
+        }
+    }
+}
+
+impl std::fmt::Display for DropReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DropReason::TooLong => write!(f, "line too long"),
+            DropReason::InvalidBytes => write!(f, "invalid bytes"),
+            DropReason::DecodeError => write!(f, "unparseable message"),
+        }
+    }
+}
+
+/// Information about a line received from the server that was dropped.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct DroppedLine<'a> {
+    /// Why the line was dropped.
+    pub reason: DropReason,
+    /// Up to [`EXCERPT_LEN`] bytes from the start of the line.
+    ///
+    /// This may be empty if the line's contents were not available.
+    pub excerpt: &'a [u8],
+    /// The number of lines received from the server before this one,
+    /// including dropped lines.
+    pub line: u64,
+}
+
+impl std::fmt::Display for DroppedLine<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "dropped line {} ({}): {}", self.line, self.reason, self.excerpt.escape_ascii())
+    }
+}
+
+/// Counters for the lines received from the server.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct ReadStats {
+    /// The number of lines that were successfully parsed.
+    pub parsed: u64,
+    /// The number of lines that were dropped because they were too long.
+    pub too_long: u64,
+    /// The number of lines that were dropped because they contained invalid bytes.
+    pub invalid_bytes: u64,
+    /// The number of lines that were dropped because they could not be parsed.
+    pub decode_error: u64,
+}
+
+impl ReadStats {
+    /// Returns the number of lines that were dropped for the provided reason.
+    pub const fn dropped_for(&self, reason: DropReason) -> u64 {
+        match reason {
+            DropReason::TooLong => self.too_long,
+            DropReason::InvalidBytes => self.invalid_bytes,
+            DropReason::DecodeError => self.decode_error,
+        }
+    }
+    /// Returns the total number of lines that were dropped.
+    pub const fn dropped(&self) -> u64 {
+        self.too_long + self.invalid_bytes + self.decode_error
+    }
+    /// Returns the total number of lines that were received.
+    pub const fn lines(&self) -> u64 {
+        self.parsed + self.dropped()
+    }
+    pub(crate) fn record_drop(&mut self, reason: DropReason) {
+        match reason {
+            DropReason::TooLong => self.too_long += 1,
+            DropReason::InvalidBytes => self.invalid_bytes += 1,
+            DropReason::DecodeError => self.decode_error += 1,
+        }
+    }
+}
+
+impl std::fmt::Display for ReadStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} parsed, {} dropped ({} too long, {} invalid bytes, {} unparseable)",
+            self.parsed,
+            self.dropped(),
+            self.too_long,
+            self.invalid_bytes,
+            self.decode_error
+        )
+    }
+}
+
+/// Returns `true` if `msg` is an `ERROR` message, which servers send before closing the connection.
+pub(super) fn is_error(msg: &crate::ircmsg::ServerMsg<'_>) -> bool {
+    use crate::ircmsg::ServerMsgKindRaw;
+    matches!(&msg.kind, ServerMsgKindRaw::Cmd(cmd) if *cmd == crate::names::cmd::ERROR)
+}
+
+/// Discards input from `read` up to and including the next line feed.
+///
+/// `discarding` is cleared once the line feed is found,
+/// allowing this to be resumed after non-blocking I/O errors.
+pub(super) fn skip_line(
+    read: &mut (impl std::io::BufRead + ?Sized),
+    discarding: &mut bool,
+) -> std::io::Result<()> {
+    while *discarding {
+        let buf = read.fill_buf()?;
+        if buf.is_empty() {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        let (amt, found) = match buf.iter().position(|b| *b == b'\n') {
+            Some(idx) => (idx + 1, true),
+            None => (buf.len(), false),
+        };
+        read.consume(amt);
+        *discarding = !found;
+    }
+    Ok(())
+}
+
+/// Asynchronously discards input from `read` up to and including the next line feed.
+///
+/// See [`skip_line`].
+#[cfg(feature = "tokio")]
+pub(super) async fn skip_line_tokio(
+    read: &mut (impl tokio::io::AsyncBufRead + ?Sized + Unpin),
+    discarding: &mut bool,
+) -> std::io::Result<()> {
+    use tokio::io::AsyncBufReadExt;
+    while *discarding {
+        let buf = read.fill_buf().await?;
+        if buf.is_empty() {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        let (amt, found) = match buf.iter().position(|b| *b == b'\n') {
+            Some(idx) => (idx + 1, true),
+            None => (buf.len(), false),
+        };
+        read.consume(amt);
+        *discarding = !found;
+    }
+    Ok(())
+}
@@ -1,17 +1,68 @@
-use super::{filter_time_error, ReadTimeout, TimeLimitedSync, WriteTimeout};
+use super::{filter_time_error, ConnectTimings, ReadTimeout, TimeLimitedSync, WriteTimeout};
+#[cfg(feature = "diagnostics")]
+use crate::client::diagnostics::LoopPhase;
 use crate::ircmsg::ClientCodec;
 use std::{
     io::{BufRead, BufReader, Read, Write},
     net::TcpStream,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 impl<'a> super::ServerAddr<'a> {
     /// Creates a synchronous connection, ignoring the `tls` flag.
     pub fn connect_no_tls(&self) -> std::io::Result<BufReader<Stream>> {
-        let string = self.utf8_address()?;
-        let sock = std::net::TcpStream::connect((string, self.port_num()))?;
-        Ok(BufReader::with_capacity(super::BUFSIZE, Stream(StreamInner::Tcp(sock))))
+        let host = self.checked_host()?;
+        let string = host.as_unbracketed_str();
+        let mut timings = ConnectTimings::default();
+        let sock = self.connect_tcp(string, &mut timings)?;
+        let stream = Stream(StreamInner::Tcp(sock), Some(timings));
+        Ok(BufReader::with_capacity(super::BUFSIZE, stream))
+    }
+    /// Creates a synchronous connection through a proxy, ignoring the `tls` flag.
+    pub fn connect_no_tls_via(&self, proxy: &super::Proxy) -> std::io::Result<BufReader<Stream>> {
+        let host = self.checked_host()?;
+        let string = host.as_unbracketed_str();
+        let start = Instant::now();
+        let sock = proxy.connect(string, self.port_num())?;
+        let timings = ConnectTimings { tcp: start.elapsed(), ..Default::default() };
+        let stream = Stream(StreamInner::Tcp(sock), Some(timings));
+        Ok(BufReader::with_capacity(super::BUFSIZE, stream))
+    }
+    /// Resolves `host` and connects to one of the addresses it resolves to,
+    /// recording how long each step took in `timings`.
+    fn connect_tcp(&self, host: &str, timings: &mut ConnectTimings) -> std::io::Result<TcpStream> {
+        use std::net::ToSocketAddrs;
+        let start = Instant::now();
+        let addrs = super::interleave_addrs((host, self.port_num()).to_socket_addrs()?);
+        let resolved = Instant::now();
+        timings.resolve = Some(resolved.saturating_duration_since(start));
+        let sock = self.connect_addrs(&addrs)?;
+        timings.tcp = resolved.elapsed();
+        Ok(sock)
+    }
+    /// Connects to each of `addrs` in turn until one succeeds.
+    ///
+    /// If there is a [`connect_timeout`][Self::connect_timeout],
+    /// the remaining time is split evenly between the remaining addresses
+    /// so that one unresponsive address cannot use all of it.
+    fn connect_addrs(&self, addrs: &[std::net::SocketAddr]) -> std::io::Result<TcpStream> {
+        let Some(timeout) = self.connect_timeout.filter(|_| !addrs.is_empty()) else {
+            return TcpStream::connect(addrs);
+        };
+        let deadline = Instant::now() + timeout;
+        let mut last_error = None;
+        for (idx, addr) in addrs.iter().enumerate() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let attempt_timeout = remaining / (addrs.len() - idx) as u32;
+            if attempt_timeout.is_zero() {
+                return Err(super::connect_timed_out());
+            }
+            match TcpStream::connect_timeout(addr, attempt_timeout) {
+                Ok(sock) => return Ok(sock),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(super::connect_timed_out))
     }
     /// Creates a synchronous connection.
     ///
@@ -22,30 +73,66 @@ impl<'a> super::ServerAddr<'a> {
     pub fn connect(
         &self,
         tls_fn: impl FnOnce() -> std::io::Result<crate::client::tls::TlsConfig>,
+    ) -> std::io::Result<BufReader<Stream>> {
+        self.connect_impl(None, tls_fn)
+    }
+    /// Creates a synchronous connection through a proxy.
+    ///
+    /// The proxy handshake is performed before TLS, if any.
+    /// Refusals by the proxy are reported as
+    /// [`ConnectionRefused`][std::io::ErrorKind::ConnectionRefused] errors,
+    /// and malformed responses from it as [`InvalidData`][std::io::ErrorKind::InvalidData].
+    /// See [`connect`][Self::connect] for more information.
+    #[cfg(feature = "tls")]
+    pub fn connect_via(
+        &self,
+        proxy: &super::Proxy,
+        tls_fn: impl FnOnce() -> std::io::Result<crate::client::tls::TlsConfig>,
+    ) -> std::io::Result<BufReader<Stream>> {
+        self.connect_impl(Some(proxy), tls_fn)
+    }
+    #[cfg(feature = "tls")]
+    fn connect_impl(
+        &self,
+        proxy: Option<&super::Proxy>,
+        tls_fn: impl FnOnce() -> std::io::Result<crate::client::tls::TlsConfig>,
     ) -> std::io::Result<BufReader<Stream>> {
         use std::io::{Error, ErrorKind};
-        let string = self.utf8_address()?;
+        let host = self.checked_host()?;
+        let string = host.as_unbracketed_str();
+        let mut timings = ConnectTimings::default();
+        let connect = |timings: &mut ConnectTimings| match proxy {
+            Some(proxy) => {
+                let start = Instant::now();
+                let sock = proxy.connect(string, self.port_num())?;
+                timings.tcp = start.elapsed();
+                Ok(sock)
+            }
+            None => self.connect_tcp(string, timings),
+        };
         let stream = if self.tls {
             let name = rustls::pki_types::ServerName::try_from(string)
                 .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
             let config = tls_fn()?;
             let conn = rustls::ClientConnection::new(config, name.to_owned())
                 .map_err(|e| Error::new(ErrorKind::Other, e))?;
-            let sock = std::net::TcpStream::connect((string, self.port_num()))?;
+            let sock = connect(&mut timings)?;
             let mut tls = rustls::StreamOwned { conn, sock };
+            // Completes the handshake.
+            let start = Instant::now();
             tls.flush()?;
+            timings.tls = Some(start.elapsed());
             StreamInner::Tls(Box::new(tls))
         } else {
-            let sock = std::net::TcpStream::connect((string, self.port_num()))?;
-            StreamInner::Tcp(sock)
+            StreamInner::Tcp(connect(&mut timings)?)
         };
-        Ok(BufReader::with_capacity(super::BUFSIZE, Stream(stream)))
+        Ok(BufReader::with_capacity(super::BUFSIZE, Stream(stream, Some(timings))))
     }
 }
 
 /// An abstraction of common I/O stream types.
 #[derive(Debug)]
-pub struct Stream(StreamInner);
+pub struct Stream(StreamInner, Option<ConnectTimings>);
 
 #[derive(Debug, Default)]
 enum StreamInner {
@@ -57,6 +144,21 @@ enum StreamInner {
 }
 
 impl Stream {
+    /// Returns how long it took to establish this stream,
+    /// if it was created by one of [`ServerAddr`][super::ServerAddr]'s `connect` methods.
+    pub fn connect_timings(&self) -> Option<ConnectTimings> {
+        self.1
+    }
+    /// Returns the underlying [`TcpStream`] if this stream is not using TLS,
+    /// such as to wrap it in TLS.
+    ///
+    /// Otherwise, returns `self`.
+    pub fn into_inner(self) -> Result<TcpStream, Self> {
+        match self.0 {
+            StreamInner::Tcp(s) => Ok(s),
+            inner => Err(Stream(inner, self.1)),
+        }
+    }
     /// Shuts down the read, write, or both halves of this connection,
     /// as [`TcpStream::shutdown`].
     pub fn shutdown(&self, how: std::net::Shutdown) -> std::io::Result<()> {
@@ -114,6 +216,19 @@ impl Stream {
     }
 }
 
+impl From<TcpStream> for Stream {
+    fn from(value: TcpStream) -> Self {
+        Stream(StreamInner::Tcp(value), None)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl From<rustls::StreamOwned<rustls::ClientConnection, TcpStream>> for Stream {
+    fn from(value: rustls::StreamOwned<rustls::ClientConnection, TcpStream>) -> Self {
+        Stream(StreamInner::Tls(Box::new(value)), None)
+    }
+}
+
 impl Read for Stream {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         match &mut self.0 {
@@ -163,6 +278,9 @@ impl WriteTimeout for TcpStream {
     fn set_write_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
         Self::set_write_timeout(self, timeout)
     }
+    fn close(&mut self) -> std::io::Result<()> {
+        Self::shutdown(self, std::net::Shutdown::Both)
+    }
 }
 
 impl ReadTimeout for Stream {
@@ -174,6 +292,9 @@ impl WriteTimeout for Stream {
     fn set_write_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
         Self::set_write_timeout(self, timeout)
     }
+    fn close(&mut self) -> std::io::Result<()> {
+        Self::shutdown(self, std::net::Shutdown::Both)
+    }
 }
 
 #[cfg(feature = "tls")]
@@ -200,6 +321,9 @@ impl<
     fn set_write_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
         self.sock.set_write_timeout(timeout)
     }
+    fn close(&mut self) -> std::io::Result<()> {
+        self.sock.close()
+    }
 }
 
 #[cfg(feature = "tls")]
@@ -224,6 +348,9 @@ impl<
     fn set_write_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
         self.sock.set_write_timeout(timeout)
     }
+    fn close(&mut self) -> std::io::Result<()> {
+        self.sock.close()
+    }
 }
 
 /// Types that are usable as synchronous connections.
@@ -265,6 +392,9 @@ impl<T: WriteTimeout> WriteTimeout for BufReader<T> {
     fn set_write_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
         self.get_mut().set_write_timeout(timeout)
     }
+    fn close(&mut self) -> std::io::Result<()> {
+        self.get_mut().close()
+    }
 }
 
 impl<T: ReadTimeout + WriteTimeout + Read + Write> Connection for BufReader<T> {
@@ -286,29 +416,62 @@ impl<C: Connection, S> crate::client::Client<C, S> {
     ///
     /// Returns the IDs of the handlers that yielded or finished, respectively.
     /// Read timeouts are indicated by a return value of `Ok(None)`.
+    /// Lines that are too long or cannot be parsed are dropped;
+    /// see [`ClientLogic::set_drop_fn`][crate::client::ClientLogic::set_drop_fn].
     /// I/O failure should be considered non-recoverable.
+    /// If the client is [shutting down][crate::client::ClientLogic::begin_shutdown]
+    /// or the server sent `ERROR`, I/O failure is instead reported as a
+    /// [`ConnectionClosed`][super::ConnectionClosed].
     ///
-    /// Handlers are not guaranteed to run in the order they were added.
+    /// Handlers run in the order described in [`Handler`][crate::client::Handler]'s documentation.
+    /// Handlers added with a [timeout][crate::client::Client::add_with_timeout]
+    /// that has passed are expired and reported as finished.
     /// If there are no handlers to run, fully flushes the queue.
     /// If the `tracing` feature is enabled, logs messages at the debug level.
     pub fn run(&mut self) -> std::io::Result<Option<(&[usize], &[usize])>> {
         let finished_at = loop {
+            #[cfg(feature = "diagnostics")]
+            self.logic.timings.finish_iteration();
             let wait_for = self.flush_partial()?;
+            let expired_at = self.logic.expire_handlers();
+            if self.logic.handlers.has_results(expired_at) {
+                break expired_at;
+            }
+            let wait_for = self.logic.wait_for_deadline(wait_for);
             if self.logic.handlers.is_empty() {
                 if let Some(wait_for) = wait_for {
+                    #[cfg(feature = "diagnostics")]
+                    let start = std::time::Instant::now();
                     std::thread::sleep(wait_for);
+                    #[cfg(feature = "diagnostics")]
+                    self.logic.timings.record(LoopPhase::Sleep, start);
                     continue;
                 }
                 return Ok(Some((Default::default(), Default::default())));
             }
+            #[cfg(feature = "diagnostics")]
+            let start = std::time::Instant::now();
             let (mut conn, rto_from_queue) =
-                TimeLimitedSync::new(&mut self.conn.conn, &mut self.logic.timeout, wait_for)?;
-            let msg = if self.logic.handlers.wants_owning() {
-                ClientCodec::read_owning_from(&mut conn, &mut self.conn.buf_i)
-            } else {
-                ClientCodec::read_borrowing_from(&mut conn, &mut self.conn.buf_i)
+                TimeLimitedSync::new(&mut self.conn.conn, &mut self.logic.timeout, wait_for)
+                    .map_err(|e| self.logic.filter_io_error(e))?;
+            let owning = self.logic.handlers.wants_owning() && self.logic.on_drop.is_none();
+            let codec = self.logic.read_codec();
+            let msg = match super::skip_line(&mut conn, &mut self.conn.discarding) {
+                Ok(()) if owning => codec.read_owning(&mut conn, &mut self.conn.buf_i),
+                Ok(()) => codec.read_borrowing(&mut conn, &mut self.conn.buf_i),
+                Err(e) => Err(e),
+            };
+            #[cfg(feature = "diagnostics")]
+            self.logic.timings.record(LoopPhase::Read, start);
+            let msg = match msg {
+                Ok(msg) => Ok(msg),
+                Err(e) => match self.conn.drop_line(e, &mut self.logic) {
+                    Ok(()) => continue,
+                    Err(e) => Err(e),
+                },
             };
-            let Some(msg) = filter_time_error(msg)? else {
+            let Some(msg) = filter_time_error(msg).map_err(|e| self.logic.filter_io_error(e))?
+            else {
                 if rto_from_queue {
                     // If we're here, the actual read timeout was determined by the queue,
                     // not the configured read timeout, and we're ready to write another message.
@@ -324,7 +487,8 @@ impl<C: Connection, S> crate::client::Client<C, S> {
                 };
             };
             #[cfg(feature = "tracing")]
-            tracing::debug!(target: "vinezombie::recv", "{}", msg);
+            tracing::debug!(target: "vinezombie::recv", "{}", msg.redacted());
+            self.logic.reads.parsed += 1;
             let finished_at = self.logic.run_once(&msg);
             self.conn.buf_i.clear();
             if self.logic.handlers.has_results(finished_at) {
@@ -333,8 +497,69 @@ impl<C: Connection, S> crate::client::Client<C, S> {
                 break finished_at;
             }
         };
+        #[cfg(feature = "diagnostics")]
+        self.logic.timings.finish_iteration();
         Ok(Some(self.logic.handlers.last_run_results(finished_at)))
     }
+    /// Gracefully disconnects from the server.
+    ///
+    /// Cancels all handlers, discards all queued messages, and immediately sends a QUIT
+    /// with an optional reason, ignoring rate limits.
+    /// Then reads and discards messages until the server closes the connection or sends an
+    /// `ERROR`, waiting no longer than the read timeout in total (or 10 seconds if none is set),
+    /// before [closing][WriteTimeout::close] the connection.
+    ///
+    /// Returns the server's `ERROR` message, if one was received.
+    /// I/O errors while sending the QUIT or waiting for the server are not reported,
+    /// as the connection is being closed anyway.
+    pub fn disconnect(
+        &mut self,
+        reason: Option<crate::string::Line<'static>>,
+    ) -> std::io::Result<Option<crate::ircmsg::ServerMsg<'static>>> {
+        let quit = self.logic.begin_disconnect(reason);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(target: "vinezombie::send", "{}", quit.redacted());
+        let _ = ClientCodec::write_to(&quit, &mut self.conn.buf_o);
+        self.conn.buf_o.extend_from_slice(b"\r\n");
+        let result = self.conn.conn.as_write().write_all(&self.conn.buf_o);
+        self.conn.buf_o.clear();
+        let mut error = None;
+        if result.and_then(|_| self.conn.conn.as_write().flush()).is_ok() {
+            let deadline = Instant::now() + self.logic.disconnect_timeout();
+            while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                let Ok((mut conn, _)) = TimeLimitedSync::new(
+                    &mut self.conn.conn,
+                    &mut self.logic.timeout,
+                    Some(remaining),
+                ) else {
+                    break;
+                };
+                let msg = super::skip_line(&mut conn, &mut self.conn.discarding)
+                    .and_then(|_| ClientCodec::read_owning_from(&mut conn, &mut self.conn.buf_i));
+                self.conn.buf_i.clear();
+                match msg {
+                    Ok(msg) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(target: "vinezombie::recv", "{}", msg.redacted());
+                        self.logic.reads.parsed += 1;
+                        if super::is_error(&msg) {
+                            error = Some(msg);
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        if self.conn.drop_line(e, &mut self.logic).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        match self.conn.conn.close() {
+            Err(e) if e.kind() != std::io::ErrorKind::NotConnected => Err(e),
+            _ => Ok(error),
+        }
+    }
     /// Flushes the queue until it's empty or hits rate limits.
     ///
     /// I/O failure should be considered non-recoverable,
@@ -345,17 +570,22 @@ impl<C: Connection, S> crate::client::Client<C, S> {
         if self.logic.queue.is_empty() {
             return Ok(None);
         }
+        #[cfg(feature = "diagnostics")]
+        let start = std::time::Instant::now();
         let mut timeout = None;
         while let Some(popped) = self.logic.queue.pop(|new_timeout| timeout = new_timeout) {
             #[cfg(feature = "tracing")]
-            tracing::debug!(target: "vinezombie::send", "{}", popped);
+            tracing::debug!(target: "vinezombie::send", "{}", popped.redacted());
             let _ = ClientCodec::write_to(&popped, &mut self.conn.buf_o);
             self.conn.buf_o.extend_from_slice(b"\r\n");
         }
         let result = self.conn.conn.as_write().write_all(&self.conn.buf_o);
         self.conn.buf_o.clear();
-        result?;
-        self.conn.conn.as_write().flush()?;
+        result
+            .and_then(|_| self.conn.conn.as_write().flush())
+            .map_err(|e| self.logic.filter_io_error(e))?;
+        #[cfg(feature = "diagnostics")]
+        self.logic.timings.record(LoopPhase::Flush, start);
         Ok(timeout)
     }
 }
@@ -0,0 +1,678 @@
+use super::{Bidir, ConnectionClosed, DropReason, ReadStats, EXCERPT_LEN};
+use crate::{
+    client::{channel::SyncChannels, handlers::YieldAll, Client},
+    string::Line,
+};
+use std::{
+    io::{Cursor, ErrorKind},
+    sync::mpsc::TryRecvError,
+};
+
+#[test]
+fn eof_without_shutdown() {
+    let io = Bidir(Cursor::new(Vec::new()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let _ = client.add((), YieldAll).unwrap();
+    let e = client.run().unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    assert!(ConnectionClosed::from_io(&e).is_none());
+}
+
+#[test]
+fn eof_after_shutdown() {
+    let io = Bidir(Cursor::new(b":server 372 * :hello\r\n".to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let (_, msgs) = client.add((), YieldAll).unwrap();
+    client.run().unwrap();
+    assert!(msgs.try_recv().is_ok());
+    client.begin_shutdown(Some(Line::from_str("bye")));
+    assert!(client.is_shutting_down());
+    let e = client.run().unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::ConnectionAborted);
+    let closed = ConnectionClosed::from_io(&e).expect("error should be ConnectionClosed");
+    assert_eq!(closed, &ConnectionClosed { by_server: false, reason: Some(Line::from_str("bye")) });
+    assert_eq!(msgs.try_recv().unwrap_err(), TryRecvError::Disconnected);
+    assert!(!client.needs_run());
+    client.reset();
+    assert!(!client.is_shutting_down());
+}
+
+#[test]
+fn eof_after_error() {
+    let input = b"ERROR :Closing link (K-lined)\r\n";
+    let io = Bidir(Cursor::new(input.to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let (_, msgs) = client.add((), YieldAll).unwrap();
+    client.run().unwrap();
+    let e = client.run().unwrap_err();
+    let closed = ConnectionClosed::from_io(&e).expect("error should be ConnectionClosed");
+    let reason = Some(Line::from_str("Closing link (K-lined)"));
+    assert_eq!(closed, &ConnectionClosed { by_server: true, reason });
+    assert!(msgs.try_recv().is_ok());
+    assert_eq!(msgs.try_recv().unwrap_err(), TryRecvError::Disconnected);
+}
+
+/// Tracing layer that counts error-level events.
+#[cfg(feature = "tracing")]
+struct CountErrors(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+#[cfg(feature = "tracing")]
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for CountErrors {
+    fn on_event(&self, event: &tracing::Event<'_>, _: tracing_subscriber::layer::Context<'_, S>) {
+        if *event.metadata().level() == tracing::Level::ERROR {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn quiet_shutdown() {
+    use crate::client::{handlers::wait_for_state, state::Shutdown};
+    use tracing_subscriber::layer::SubscriberExt;
+    let errors: std::sync::Arc<std::sync::atomic::AtomicUsize> = Default::default();
+    let subscriber = tracing_subscriber::registry().with(CountErrors(errors.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        // Abrupt EOF without an ERROR message.
+        let io = Bidir(Cursor::new(b":server 372 * :hello\r\n".to_vec()), Vec::new());
+        let mut client = Client::new(io, SyncChannels);
+        let (_, msgs) = client.add((), YieldAll).unwrap();
+        let (_, waiting) = client.add((), wait_for_state(|_| None::<()>)).unwrap();
+        client.run().unwrap();
+        client.quit(Some(Line::from_str("bye")));
+        let e = client.run().unwrap_err();
+        let closed = ConnectionClosed::from_io(&e).expect("error should be ConnectionClosed");
+        assert!(!closed.by_server);
+        assert!(msgs.try_recv().is_ok());
+        assert_eq!(msgs.try_recv().unwrap_err(), TryRecvError::Disconnected);
+        assert!(waiting.0.recv_now().is_none());
+        assert_eq!(client.state().get::<Shutdown>(), Some(&Some(Line::from_str("bye"))));
+    });
+    assert_eq!(errors.load(std::sync::atomic::Ordering::Relaxed), 0);
+}
+
+#[test]
+fn quit() {
+    let io = Bidir(Cursor::new(Vec::new()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    let _ = client.add((), YieldAll).unwrap();
+    client.quit(Some(Line::from_str("going away")));
+    let e = client.run().unwrap_err();
+    assert!(ConnectionClosed::from_io(&e).is_some());
+    assert_eq!(client.take_conn().1, b"QUIT :going away\r\n");
+}
+
+#[test]
+fn disconnect() {
+    let input = b":server 372 * :hello\r\nERROR :Closing link\r\n:server 372 * :unread\r\n";
+    let io = Bidir(Cursor::new(input.to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    let (_, msgs) = client.add((), YieldAll).unwrap();
+    client.queue_mut().edit().push(crate::ircmsg::ClientMsg::new(crate::names::cmd::PING));
+    let error = client.disconnect(Some(Line::from_str("going away"))).unwrap();
+    assert_eq!(error.unwrap().to_string(), "ERROR :Closing link");
+    assert!(client.is_shutting_down());
+    assert!(!client.needs_run());
+    assert_eq!(msgs.try_recv().unwrap_err(), TryRecvError::Disconnected);
+    assert_eq!(client.take_conn().1, b"QUIT :going away\r\n");
+}
+
+#[test]
+fn disconnect_eof() {
+    let io = Bidir(Cursor::new(b":server 372 * :hello\r\n".to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    assert!(client.disconnect(None).unwrap().is_none());
+    assert_eq!(client.take_conn().1, b"QUIT\r\n");
+}
+
+#[test]
+fn replace_conn() {
+    let io = Bidir(Cursor::new(b":server 372 * :hello\r\n:server 372 * :par".to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    let (_, msgs) = client.add((), YieldAll).unwrap();
+    client.run().unwrap();
+    assert_eq!(msgs.try_recv().unwrap().args.split_last().1.unwrap(), "hello");
+    assert!(client.run().is_err());
+    // The partial line from the old connection is discarded.
+    let io = Bidir(Cursor::new(b":server 372 * :world\r\n".to_vec()), Vec::new());
+    let _ = client.replace_conn(io);
+    client.queue_mut().edit().push(crate::ircmsg::ClientMsg::new(crate::names::cmd::PING));
+    client.run().unwrap();
+    assert_eq!(msgs.try_recv().unwrap().args.split_last().1.unwrap(), "world");
+    let (client, old) = client.swap_conn(Bidir(Cursor::new(Vec::<u8>::new()), std::io::sink()));
+    assert_eq!(old.1, b"PING\r\n");
+    assert!(client.needs_run());
+}
+
+fn bad_stream() -> Vec<u8> {
+    let mut stream = b":a PRIVMSG #c :one\r\n".to_vec();
+    stream.extend(std::iter::repeat(b'x').take(9000));
+    stream.extend_from_slice(b"\r\n:a PRIVMSG #c :two\r\n");
+    stream.extend_from_slice(b":a PRIVMSG #c :th\0ree\r\n:a PRIVMSG #c :four\r\n");
+    stream.extend_from_slice(b":src\r\n:a PRIVMSG #c :five\r\n");
+    stream
+}
+
+type Drops = std::sync::Arc<std::sync::Mutex<Vec<(DropReason, Vec<u8>, u64)>>>;
+
+fn check_bad_stream<C, S>(client: &Client<C, S>, texts: Vec<String>, drops: &Drops) {
+    assert_eq!(texts, ["one", "two", "four", "five"]);
+    let expected = ReadStats { parsed: 4, too_long: 1, invalid_bytes: 1, decode_error: 1 };
+    assert_eq!(*client.read_stats(), expected);
+    let drops = drops.lock().unwrap();
+    let reasons: Vec<_> = drops.iter().map(|(reason, _, line)| (*reason, *line)).collect();
+    assert_eq!(
+        reasons,
+        [(DropReason::TooLong, 1), (DropReason::InvalidBytes, 3), (DropReason::DecodeError, 5)]
+    );
+    assert_eq!(drops[0].1, [b'x'; EXCERPT_LEN]);
+    assert_eq!(drops[2].1, b":src");
+}
+
+fn drop_fn(drops: &Drops) -> Option<impl FnMut(&super::DroppedLine<'_>) + Send + 'static> {
+    let drops = drops.clone();
+    Some(move |dropped: &super::DroppedLine<'_>| {
+        drops.lock().unwrap().push((dropped.reason, dropped.excerpt.to_vec(), dropped.line));
+    })
+}
+
+#[test]
+fn dropped_lines() {
+    let io = Bidir(Cursor::new(bad_stream()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let drops = Drops::default();
+    client.set_drop_fn(drop_fn(&drops));
+    let (_, msgs) = client.add((), YieldAll).unwrap();
+    let mut texts = Vec::new();
+    let e = loop {
+        match client.run() {
+            Ok(_) => (),
+            Err(e) => break e,
+        }
+        while let Ok(msg) = msgs.try_recv() {
+            texts.push(msg.args.split_last().1.unwrap().to_string());
+        }
+    };
+    assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    check_bad_stream(&client, texts, &drops);
+}
+
+#[test]
+fn dropped_lines_owning() {
+    // Without a drop callback, owning reads are used, so excerpts may be missing,
+    // but the counts must still be correct.
+    let io = Bidir(Cursor::new(bad_stream()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let (_, msgs) = client.add((), YieldAll).unwrap();
+    while client.run().is_ok() {}
+    let texts = msgs.try_iter().map(|msg| msg.args.split_last().1.unwrap().to_string());
+    assert_eq!(texts.collect::<Vec<_>>(), ["one", "two", "four", "five"]);
+    assert_eq!(client.read_stats().parsed, 4);
+    assert_eq!(client.read_stats().dropped(), 3);
+}
+
+#[test]
+fn overlong_truncate() {
+    use crate::ircmsg::OverlongPolicy;
+    let mut stream = b":a PRIVMSG #c :".to_vec();
+    stream.extend(std::iter::repeat(b'x').take(20_000));
+    stream.extend_from_slice(b"\r\n:a PRIVMSG #c :after\r\n");
+    let io = Bidir(Cursor::new(stream), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    client.set_overlong_policy(OverlongPolicy::Truncate);
+    let (_, msgs) = client.add((), YieldAll).unwrap();
+    while client.run().is_ok() {}
+    let texts: Vec<_> = msgs.try_iter().map(|msg| msg.args.split_last().1.unwrap().len()).collect();
+    assert_eq!(texts.len(), 2);
+    assert_eq!(texts[1], 5);
+    assert_eq!(client.read_stats().parsed, 2);
+    assert_eq!(client.read_stats().dropped(), 0);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn dropped_lines_tokio() {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let io = Bidir(Cursor::new(bad_stream()), tokio::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let drops = Drops::default();
+    client.set_drop_fn(drop_fn(&drops));
+    let (_, msgs) = client.add((), YieldAll).unwrap();
+    let mut texts = Vec::new();
+    let e = runtime.block_on(async {
+        loop {
+            match client.run_tokio().await {
+                Ok(_) => (),
+                Err(e) => break e,
+            }
+            while let Ok(msg) = msgs.try_recv() {
+                texts.push(msg.args.split_last().1.unwrap().to_string());
+            }
+        }
+    });
+    assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    check_bad_stream(&client, texts, &drops);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn commands_tokio() {
+    use crate::{ircmsg::ClientMsg, names::cmd::PRIVMSG, string::Arg};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let (conn, server) = tokio::io::duplex(1024);
+    let mut server = tokio::io::BufReader::new(server);
+    let mut client = Client::new(tokio::io::BufReader::new(conn), SyncChannels);
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1);
+    let (_, msgs) = client.add((), YieldAll).unwrap();
+    let (send, mut cmds) = tokio::sync::mpsc::channel(4);
+    let mut msg = ClientMsg::new(PRIVMSG);
+    msg.args.edit().add_word(Arg::from_str("#chan"));
+    msg.args.edit().add(Line::from_str("hello world"));
+    runtime.block_on(async {
+        send.send(msg).await.unwrap();
+        let server_fut = async {
+            let mut line = String::new();
+            server.read_line(&mut line).await.unwrap();
+            assert_eq!(line, "PRIVMSG #chan :hello world\r\n");
+            server.get_mut().write_all(b":a!b@c PRIVMSG me :hi\r\n").await.unwrap();
+        };
+        let (result, ()) = tokio::join!(client.run_with_commands_tokio(&mut cmds), server_fut);
+        assert!(result.unwrap().is_some());
+    });
+    assert_eq!(msgs.try_recv().unwrap().args.split_last().1.unwrap().as_bytes(), b"hi");
+    // A closed channel should not stop handlers from running.
+    drop(send);
+    runtime.block_on(async {
+        let server_fut = async {
+            tokio::task::yield_now().await;
+            server.get_mut().write_all(b":a!b@c PRIVMSG me :bye\r\n").await.unwrap();
+        };
+        let (result, ()) = tokio::join!(client.run_with_commands_tokio(&mut cmds), server_fut);
+        assert!(result.unwrap().is_some());
+    });
+    assert_eq!(msgs.try_recv().unwrap().args.split_last().1.unwrap().as_bytes(), b"bye");
+}
+
+#[cfg(all(feature = "tokio", feature = "tokio-codec"))]
+#[test]
+fn run_on_stream() {
+    use crate::{
+        client::ClientLogic,
+        ircmsg::{ClientCodec, ClientMsg},
+        names::cmd::PRIVMSG,
+        string::Arg,
+    };
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let (conn, server) = tokio::io::duplex(1024);
+    let mut server = tokio::io::BufReader::new(server);
+    let mut framed = tokio_util::codec::Framed::new(conn, ClientCodec::new());
+    let mut logic = ClientLogic::new();
+    logic.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1);
+    let (_, msgs) = logic.add_with_spec(&SyncChannels, (), YieldAll).unwrap();
+    let mut msg = ClientMsg::new(PRIVMSG);
+    msg.args.edit().add_word(Arg::from_str("#chan"));
+    msg.args.edit().add(Line::from_str("hello world"));
+    logic.queue_mut().edit().push(msg);
+    runtime.block_on(async {
+        let server_fut = async {
+            let mut line = String::new();
+            server.read_line(&mut line).await.unwrap();
+            assert_eq!(line, "PRIVMSG #chan :hello world\r\n");
+            server.get_mut().write_all(b":a!b@c PRIVMSG me :hi\r\n").await.unwrap();
+        };
+        let (result, ()) = tokio::join!(logic.run_on_stream(&mut framed), server_fut);
+        assert!(result.unwrap().is_some());
+    });
+    assert_eq!(msgs.try_recv().unwrap().args.split_last().1.unwrap().as_bytes(), b"hi");
+    drop(server);
+    let e = runtime.block_on(logic.run_on_stream(&mut framed)).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+}
+
+/// Spawns a proxy that runs `f` on the first connection to it.
+#[cfg(feature = "tls")]
+fn spawn_proxy(
+    f: impl FnOnce(std::net::TcpStream) -> std::io::Result<()> + Send + 'static,
+) -> (u16, std::thread::JoinHandle<std::io::Result<()>>) {
+    let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    (port, std::thread::spawn(move || f(listener.accept()?.0)))
+}
+
+#[cfg(feature = "tls")]
+#[test]
+fn proxy_socks5() {
+    use super::{Proxy, ProxyAuth, ServerAddr};
+    use std::io::{BufRead, Read, Write};
+    let (port, proxy) = spawn_proxy(|mut sock| {
+        let mut buf = [0u8; 4];
+        sock.read_exact(&mut buf)?;
+        assert_eq!(buf, [5, 2, 0, 2]);
+        sock.write_all(&[5, 2])?;
+        let mut buf = [0u8; 11];
+        sock.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"\x01\x04user\x04pass");
+        sock.write_all(&[1, 0])?;
+        let mut buf = [0u8; 22];
+        sock.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"\x05\x01\x00\x03\x0firc.example.com\x1a\x0b");
+        sock.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0])?;
+        sock.write_all(b":irc.example.com NOTICE * :hello\r\n")
+    });
+    let proxy_cfg = Proxy::Socks5 {
+        addr: "127.0.0.1".try_into().unwrap(),
+        port,
+        auth: Some(ProxyAuth {
+            username: "user".try_into().unwrap(),
+            password: "pass".try_into().unwrap(),
+        }),
+    };
+    let addr = ServerAddr {
+        address: "irc.example.com".try_into().unwrap(),
+        tls: false,
+        port: None,
+        connect_timeout: None,
+    };
+    let mut conn = addr.connect_via(&proxy_cfg, || unreachable!()).unwrap();
+    let mut line = String::new();
+    conn.read_line(&mut line).unwrap();
+    assert_eq!(line, ":irc.example.com NOTICE * :hello\r\n");
+    proxy.join().unwrap().unwrap();
+}
+
+#[cfg(feature = "tls")]
+#[test]
+fn proxy_http_refused() {
+    use super::{Proxy, ServerAddr};
+    use std::io::{BufRead, BufReader, Write};
+    let (port, proxy) = spawn_proxy(|sock| {
+        let mut sock = BufReader::new(sock);
+        let mut line = String::new();
+        sock.read_line(&mut line)?;
+        assert_eq!(line, "CONNECT irc.example.com:6667 HTTP/1.1\r\n");
+        while line != "\r\n" {
+            line.clear();
+            sock.read_line(&mut line)?;
+        }
+        sock.get_mut().write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n")
+    });
+    let proxy_cfg = Proxy::HttpConnect { addr: "127.0.0.1".try_into().unwrap(), port, auth: None };
+    let addr = ServerAddr {
+        address: "irc.example.com".try_into().unwrap(),
+        tls: false,
+        port: None,
+        connect_timeout: None,
+    };
+    let e = addr.connect_via(&proxy_cfg, || unreachable!()).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::ConnectionRefused);
+    assert_eq!(e.to_string(), "http proxy: HTTP/1.1 403 Forbidden");
+    proxy.join().unwrap().unwrap();
+}
+
+#[test]
+fn server_addr_host() {
+    use super::ServerAddr;
+    use crate::{error::InvalidString, string::Word};
+    let addr = ServerAddr::new("[::1]", Some(6697), true).unwrap();
+    assert_eq!(addr.address, "[::1]");
+    assert_eq!(addr.host().unwrap().as_unbracketed_str(), "::1");
+    assert_eq!(ServerAddr::new("irc..example", None, true).unwrap_err(), InvalidString::Byte(b'.'));
+    let addr = ServerAddr {
+        address: Word::from_str("irc_example"),
+        tls: false,
+        port: None,
+        connect_timeout: None,
+    };
+    let e = addr.connect_no_tls().unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    assert_eq!(e.to_string(), "invalid server address irc_example: invalid byte '_'");
+    #[cfg(feature = "idna")]
+    {
+        let addr = ServerAddr::from_host_str("libera.\u{4e2d}\u{56fd}");
+        assert_eq!(addr.host().unwrap(), "libera.xn--fiqs8s");
+    }
+}
+
+#[test]
+fn connect_and_register() {
+    use crate::{
+        client::{
+            auth::Clear,
+            register::{register_as_bot, Options},
+            state::ClientSource,
+        },
+        names::cmd::{NICK, QUIT},
+        testutils::MockServer,
+    };
+    let mut server = MockServer::bind().unwrap();
+    let addr = server.server_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        server.accept().unwrap();
+        let received = server.play_registration().unwrap();
+        assert!(received.iter().any(|msg| msg.cmd == NICK));
+        server.recv().unwrap()
+    });
+    let sock = addr.connect_no_tls().unwrap();
+    let mut client = Client::new(sock, SyncChannels);
+    client.set_read_timeout(Some(std::time::Duration::from_secs(10)));
+    let mut options: Options<Clear> = Options::new();
+    options.nicks = vec![crate::string::Nick::from_str("Me")];
+    let (_, reg_result) = client.add(&register_as_bot(), &options).unwrap();
+    client.run().unwrap();
+    reg_result.0.recv_now().unwrap().unwrap();
+    assert_eq!(client.state().get::<ClientSource>().unwrap().nick, "Me");
+    client.queue_mut().edit().push(crate::ircmsg::ClientMsg::new(QUIT));
+    client.run().unwrap();
+    assert_eq!(server.join().unwrap().cmd, QUIT);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn connect_and_register_tokio() {
+    use crate::{
+        client::{
+            auth::Clear,
+            channel::TokioChannels,
+            register::{register_as_bot, Options},
+        },
+        testutils::MockServerTokio,
+    };
+    let mut server = MockServerTokio::bind().await.unwrap();
+    let addr = server.server_addr().unwrap();
+    let server = tokio::spawn(async move {
+        server.accept().await.unwrap();
+        server.play_registration().await.unwrap()
+    });
+    let sock = addr.connect_tokio_no_tls().await.unwrap();
+    let mut client = Client::new(sock, TokioChannels);
+    let mut options: Options<Clear> = Options::new();
+    options.nicks = vec![crate::string::Nick::from_str("Me")];
+    let (_, reg_result) = client.add(&register_as_bot(), &options).unwrap();
+    client.run_tokio().await.unwrap();
+    reg_result.await.unwrap().unwrap();
+    assert!(!server.await.unwrap().is_empty());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn split_tokio() {
+    use crate::{
+        client::{
+            auth::Clear,
+            channel::TokioChannels,
+            handlers::AutoPong,
+            register::{register_as_bot, Options},
+        },
+        ircmsg::ServerMsg,
+        names::cmd::PONG,
+        testutils::MockServerTokio,
+    };
+    let mut server = MockServerTokio::bind().await.unwrap();
+    let addr = server.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        server.accept().await.unwrap();
+        server.play_registration().await.unwrap();
+        server.send(&ServerMsg::parse("PING :split").unwrap()).await.unwrap();
+        server.recv().await.unwrap()
+    });
+    let (read, write) = tokio::net::TcpStream::connect(addr).await.unwrap().into_split();
+    let client = Client::new(Bidir(tokio::io::BufReader::new(read), write), TokioChannels);
+    let (reader, writer) = client.split();
+    let Ok(client) = reader.reunite(writer) else { panic!("failed to reunite") };
+    let (mut reader, mut writer) = client.split();
+    let writer = tokio::spawn(async move { writer.run().await });
+    let mut options: Options<Clear> = Options::new();
+    options.nicks = vec![crate::string::Nick::from_str("Me")];
+    let (_, reg_result) = reader.add(&register_as_bot(), &options).unwrap();
+    reader.run().await.unwrap();
+    reg_result.await.unwrap().unwrap();
+    reader.add((), AutoPong).unwrap();
+    // The server hangs up after receiving the PONG.
+    assert!(reader.run().await.is_err());
+    let pong = server.await.unwrap();
+    assert_eq!(pong.cmd, PONG);
+    assert_eq!(pong.args.split_last().1.unwrap(), "split");
+    // The writer stops once the reader is gone and the queue is empty.
+    std::mem::drop(reader);
+    writer.await.unwrap().unwrap();
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn split_drop_reader_idle() {
+    use crate::client::channel::TokioChannels;
+    for _ in 0..100 {
+        let (client_io, _server_io) = tokio::io::duplex(64);
+        let (read, write) = tokio::io::split(client_io);
+        let client = Client::new(Bidir(tokio::io::BufReader::new(read), write), TokioChannels);
+        let (reader, mut writer) = client.split();
+        let writer = tokio::spawn(async move { writer.run().await });
+        // Let the writer start waiting with nothing to send.
+        tokio::task::yield_now().await;
+        std::mem::drop(reader);
+        let result = tokio::time::timeout(std::time::Duration::from_secs(5), writer).await;
+        result.expect("writer did not stop").unwrap().unwrap();
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn split_writer_rate_limited() {
+    use crate::{client::channel::TokioChannels, ircmsg::ClientMsg, names::cmd::PING};
+    const DELAY: std::time::Duration = std::time::Duration::from_millis(20);
+    let io = Bidir(tokio::io::BufReader::new(tokio::io::empty()), tokio::io::sink());
+    let client = Client::new(io, TokioChannels);
+    let (reader, mut writer) = client.split();
+    {
+        let mut logic = reader.lock();
+        logic.queue_mut().set_rate_limit(DELAY, 0);
+        for _ in 0..6 {
+            logic.queue_mut().edit().push(ClientMsg::new(PING));
+        }
+    }
+    std::mem::drop(reader);
+    let start = std::time::Instant::now();
+    writer.run().await.unwrap();
+    assert!(start.elapsed() >= DELAY * 5);
+    // The writer wakes once per message, not continuously while waiting on the rate limit.
+    assert!(writer.wakeups() <= 12, "writer woke {} times", writer.wakeups());
+}
+
+#[cfg(feature = "websocket-tokio")]
+#[tokio::test]
+async fn websocket_tokio() {
+    use super::websocket::WebSocketTokio;
+    use crate::client::{channel::TokioChannels, handlers::AutoPong};
+    use futures_core::Stream;
+    use futures_sink::Sink;
+    use std::{future::poll_fn, pin::Pin};
+    use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+    async fn send(ws: &mut WebSocketStream<tokio::io::DuplexStream>, msg: Message) {
+        poll_fn(|cx| Pin::new(&mut *ws).poll_ready(cx)).await.unwrap();
+        Pin::new(&mut *ws).start_send(msg).unwrap();
+        poll_fn(|cx| Pin::new(&mut *ws).poll_flush(cx)).await.unwrap();
+    }
+    let (client_io, server_io) = tokio::io::duplex(4096);
+    let server = tokio::spawn(async move {
+        let mut ws = tokio_tungstenite::accept_async(server_io).await.unwrap();
+        send(&mut ws, Message::Ping(b"ws".to_vec())).await;
+        send(&mut ws, Message::Text("PING :abc".to_owned())).await;
+        send(&mut ws, Message::Binary(b"PING :def\r\n".to_vec())).await;
+        let mut received = Vec::new();
+        while received.iter().filter(|msg| matches!(msg, Message::Text(_))).count() < 2 {
+            received.push(poll_fn(|cx| Pin::new(&mut ws).poll_next(cx)).await.unwrap().unwrap());
+        }
+        // The server hangs up after receiving both PONGs.
+        received
+    });
+    let conn = WebSocketTokio::handshake("ws://localhost/", client_io).await.unwrap();
+    assert!(!conn.is_binary());
+    let mut client = Client::new(conn, TokioChannels);
+    client.add((), AutoPong).unwrap();
+    assert!(client.run_tokio().await.is_err());
+    let received = server.await.unwrap();
+    assert!(received.contains(&Message::Pong(b"ws".to_vec())), "{received:?}");
+    let texts: Vec<_> = received.iter().filter(|msg| matches!(msg, Message::Text(_))).collect();
+    assert_eq!(
+        texts,
+        [&Message::Text("PONG abc".to_owned()), &Message::Text("PONG def".to_owned())]
+    );
+}
+
+#[test]
+fn interleave_addrs() {
+    use std::net::SocketAddr;
+    let addrs = ["[::1]:1", "[::1]:2", "[::1]:3", "127.0.0.1:1", "127.0.0.2:1"];
+    let addrs = addrs.map(|addr| addr.parse::<SocketAddr>().unwrap());
+    let sorted = super::interleave_addrs(addrs);
+    let expected = ["[::1]:1", "127.0.0.1:1", "[::1]:2", "127.0.0.2:1", "[::1]:3"];
+    assert_eq!(sorted, expected.map(|addr| addr.parse::<SocketAddr>().unwrap()));
+}
+
+#[test]
+fn connect_timeout() {
+    use super::ServerAddr;
+    use std::time::Duration;
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let mut addr = ServerAddr::new("localhost", Some(port), false)
+        .unwrap()
+        .with_connect_timeout(Some(Duration::from_secs(10)));
+    addr.connect_no_tls().unwrap();
+    std::mem::drop(listener);
+    let e = addr.connect_no_tls().unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::ConnectionRefused);
+    addr.connect_timeout = Some(Duration::ZERO);
+    let e = addr.connect_no_tls().unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::TimedOut);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn race_connect() {
+    use super::tokio::race_connect;
+    use tokio::net::TcpListener;
+    let refused = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+    let v4 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let v4_addr = v4.local_addr().unwrap();
+    let sock = race_connect(vec![refused, v4_addr]).await.unwrap();
+    assert_eq!(sock.peer_addr().unwrap(), v4_addr);
+    // IPv6 may be unavailable, in which case its attempts fail immediately.
+    if let Ok(v6) = TcpListener::bind("[::1]:0").await {
+        let v6_addr = v6.local_addr().unwrap();
+        let sock = race_connect(vec![v6_addr, v4_addr]).await.unwrap();
+        assert_eq!(sock.peer_addr().unwrap(), v6_addr);
+        std::mem::drop(v6);
+        let sock = race_connect(vec![v6_addr, v4_addr]).await.unwrap();
+        assert_eq!(sock.peer_addr().unwrap(), v4_addr);
+    }
+    let e = race_connect(vec![refused]).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::ConnectionRefused);
+    let e = race_connect(Vec::new()).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    let mut addr = super::ServerAddr::new("localhost", Some(v4_addr.port()), false).unwrap();
+    addr.connect_timeout = Some(std::time::Duration::from_secs(10));
+    addr.connect_tokio_no_tls().await.unwrap();
+}
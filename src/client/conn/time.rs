@@ -14,6 +14,12 @@ pub trait WriteTimeout {
     ///
     /// May error if a duration of zero is provided to `timeout`.
     fn set_write_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()>;
+    /// Shuts down this connection, such that no more data can be sent or received.
+    ///
+    /// Does nothing by default, which is correct for types that are not network connections.
+    fn close(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
 impl ReadTimeout for std::io::Empty {
@@ -61,6 +67,9 @@ impl<R, W: WriteTimeout> WriteTimeout for super::Bidir<R, W> {
     fn set_write_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
         self.1.set_write_timeout(timeout)
     }
+    fn close(&mut self) -> std::io::Result<()> {
+        self.1.close()
+    }
 }
 
 /// Wrapper that allows any type to implement [`ReadTimeout`] and [`WriteTimeout`] by ignoring
@@ -121,6 +130,10 @@ impl TimeLimits {
     pub fn read_timeout(&self) -> Option<Duration> {
         self.read
     }
+    #[cfg(feature = "tokio")]
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.write
+    }
     pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
         self.read = timeout.map(|t| std::cmp::max(t, Duration::from_secs(1)));
         self
@@ -1,17 +1,65 @@
-use super::{timed_io, Bidir, TimeLimitedTokio};
-use crate::ircmsg::ClientCodec;
-use std::{pin::Pin, time::Duration};
+use super::{timed_io, Bidir, ConnectTimings, TimeLimitedTokio};
+#[cfg(feature = "diagnostics")]
+use crate::client::diagnostics::LoopPhase;
+use crate::ircmsg::{ClientCodec, ClientMsg};
+use std::{
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    task::Poll,
+    time::{Duration, Instant},
+};
 use tokio::{
     io::{AsyncBufRead, AsyncWrite, BufReader},
     net::TcpStream,
+    sync::mpsc::Receiver,
 };
 
 impl<'a> super::ServerAddr<'a> {
     /// Creates an asynchronous connection, ignoring the `tls` flag.
     pub async fn connect_tokio_no_tls(&self) -> std::io::Result<BufReader<StreamTokio>> {
-        let string = self.utf8_address()?;
-        let sock = tokio::net::TcpStream::connect((string, self.port_num())).await?;
-        Ok(BufReader::with_capacity(super::BUFSIZE, StreamTokio { stream: StreamInner::Tcp(sock) }))
+        let host = self.checked_host()?;
+        let string = host.as_unbracketed_str();
+        let mut timings = ConnectTimings::default();
+        let sock = self.connect_tcp_tokio(string, &mut timings).await?;
+        let stream = StreamTokio { stream: StreamInner::Tcp(sock), timings: Some(timings) };
+        Ok(BufReader::with_capacity(super::BUFSIZE, stream))
+    }
+    /// Creates an asynchronous connection through a proxy, ignoring the `tls` flag.
+    pub async fn connect_tokio_no_tls_via(
+        &self,
+        proxy: &super::Proxy,
+    ) -> std::io::Result<BufReader<StreamTokio>> {
+        let host = self.checked_host()?;
+        let string = host.as_unbracketed_str();
+        let start = Instant::now();
+        let sock = proxy.connect_tokio(string, self.port_num()).await?;
+        let timings = ConnectTimings { tcp: start.elapsed(), ..Default::default() };
+        let stream = StreamTokio { stream: StreamInner::Tcp(sock), timings: Some(timings) };
+        Ok(BufReader::with_capacity(super::BUFSIZE, stream))
+    }
+    /// Asynchronously connects to `host`, racing connection attempts to the addresses
+    /// it resolves to as described by RFC 8305 ("Happy Eyeballs").
+    ///
+    /// Records how long each step took in `timings`.
+    async fn connect_tcp_tokio(
+        &self,
+        host: &str,
+        timings: &mut ConnectTimings,
+    ) -> std::io::Result<TcpStream> {
+        let start = Instant::now();
+        let addrs = tokio::net::lookup_host((host, self.port_num())).await?;
+        let resolved = Instant::now();
+        timings.resolve = Some(resolved.saturating_duration_since(start));
+        let attempts = race_connect(super::interleave_addrs(addrs));
+        let sock = match self.connect_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, attempts)
+                .await
+                .unwrap_or_else(|_| Err(super::connect_timed_out())),
+            None => attempts.await,
+        }?;
+        timings.tcp = resolved.elapsed();
+        Ok(sock)
     }
     /// Creates an asynchronous connection.
     ///
@@ -22,22 +70,110 @@ impl<'a> super::ServerAddr<'a> {
     pub async fn connect_tokio(
         &self,
         tls_fn: impl FnOnce() -> std::io::Result<crate::client::tls::TlsConfig>,
+    ) -> std::io::Result<BufReader<StreamTokio>> {
+        self.connect_tokio_impl(None, tls_fn).await
+    }
+    /// Creates an asynchronous connection through a proxy.
+    ///
+    /// See [`connect_via`][Self::connect_via] and
+    /// [`connect_tokio`][Self::connect_tokio] for more information.
+    #[cfg(feature = "tls-tokio")]
+    pub async fn connect_tokio_via(
+        &self,
+        proxy: &super::Proxy,
+        tls_fn: impl FnOnce() -> std::io::Result<crate::client::tls::TlsConfig>,
+    ) -> std::io::Result<BufReader<StreamTokio>> {
+        self.connect_tokio_impl(Some(proxy), tls_fn).await
+    }
+    /// Connects to `host`, either directly or through `proxy`.
+    #[cfg(feature = "tls-tokio")]
+    async fn connect_sock_tokio(
+        &self,
+        proxy: Option<&super::Proxy>,
+        host: &str,
+        timings: &mut ConnectTimings,
+    ) -> std::io::Result<TcpStream> {
+        match proxy {
+            Some(proxy) => {
+                let start = Instant::now();
+                let sock = proxy.connect_tokio(host, self.port_num()).await?;
+                timings.tcp = start.elapsed();
+                Ok(sock)
+            }
+            None => self.connect_tcp_tokio(host, timings).await,
+        }
+    }
+    #[cfg(feature = "tls-tokio")]
+    async fn connect_tokio_impl(
+        &self,
+        proxy: Option<&super::Proxy>,
+        tls_fn: impl FnOnce() -> std::io::Result<crate::client::tls::TlsConfig>,
     ) -> std::io::Result<BufReader<StreamTokio>> {
         use std::io::{Error, ErrorKind};
-        let string = self.utf8_address()?;
+        let host = self.checked_host()?;
+        let string = host.as_unbracketed_str();
+        let mut timings = ConnectTimings::default();
         let stream = if self.tls {
             let name = rustls::pki_types::ServerName::try_from(string)
                 .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
             let config = tls_fn()?;
             let conn: tokio_rustls::TlsConnector = config.into();
-            let sock = tokio::net::TcpStream::connect((string, self.port_num())).await?;
+            let sock = self.connect_sock_tokio(proxy, string, &mut timings).await?;
+            let start = Instant::now();
             let tls = conn.connect(name.to_owned(), sock).await?;
-            StreamInner::Tls(tls)
+            timings.tls = Some(start.elapsed());
+            StreamInner::Tls(Box::new(tls))
         } else {
-            let sock = tokio::net::TcpStream::connect((string, self.port_num())).await?;
-            StreamInner::Tcp(sock)
+            StreamInner::Tcp(self.connect_sock_tokio(proxy, string, &mut timings).await?)
         };
-        Ok(BufReader::with_capacity(super::BUFSIZE, StreamTokio { stream }))
+        Ok(BufReader::with_capacity(super::BUFSIZE, StreamTokio { stream, timings: Some(timings) }))
+    }
+}
+
+/// How long to wait for a connection attempt before starting the next one, per RFC 8305.
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+type Attempt = Pin<Box<dyn Future<Output = std::io::Result<TcpStream>> + Send>>;
+
+/// Connects to the first of `addrs` that accepts a connection.
+///
+/// Attempts are started in order, each one either [`ATTEMPT_DELAY`] after the previous one
+/// or as soon as the previous one fails, and earlier attempts are not cancelled.
+pub(super) async fn race_connect(addrs: Vec<SocketAddr>) -> std::io::Result<TcpStream> {
+    let mut addrs = addrs.into_iter();
+    let mut attempts: Vec<Attempt> = Vec::new();
+    let mut last_error = None;
+    let mut delay = Box::pin(tokio::time::sleep(ATTEMPT_DELAY));
+    loop {
+        if attempts.is_empty() && addrs.len() == 0 {
+            return Err(last_error.unwrap_or_else(|| {
+                let msg = "could not resolve to any addresses";
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, msg)
+            }));
+        }
+        let has_next = addrs.len() != 0;
+        let outcome = std::future::poll_fn(|cx| {
+            for idx in 0..attempts.len() {
+                if let Poll::Ready(result) = attempts[idx].as_mut().poll(cx) {
+                    drop(attempts.swap_remove(idx));
+                    return Poll::Ready(Some(result));
+                }
+            }
+            if attempts.is_empty() || (has_next && delay.as_mut().poll(cx).is_ready()) {
+                return Poll::Ready(None);
+            }
+            Poll::Pending
+        })
+        .await;
+        match outcome {
+            Some(Ok(sock)) => return Ok(sock),
+            Some(Err(e)) => last_error = Some(e),
+            None => (),
+        }
+        if let Some(addr) = addrs.next() {
+            attempts.push(Box::pin(TcpStream::connect(addr)));
+            delay.as_mut().reset(tokio::time::Instant::now() + ATTEMPT_DELAY);
+        }
     }
 }
 
@@ -45,6 +181,7 @@ impl<'a> super::ServerAddr<'a> {
 #[derive(Debug, Default)]
 pub struct StreamTokio {
     stream: StreamInner,
+    timings: Option<ConnectTimings>,
 }
 
 #[derive(Debug, Default)]
@@ -53,7 +190,38 @@ enum StreamInner {
     Closed,
     Tcp(TcpStream),
     #[cfg(feature = "tls-tokio")]
-    Tls(tokio_rustls::client::TlsStream<TcpStream>),
+    Tls(Box<tokio_rustls::client::TlsStream<TcpStream>>),
+}
+
+impl StreamTokio {
+    /// Returns how long it took to establish this stream,
+    /// if it was created by one of [`ServerAddr`][super::ServerAddr]'s `connect` methods.
+    pub fn connect_timings(&self) -> Option<ConnectTimings> {
+        self.timings
+    }
+    /// Returns the underlying [`TcpStream`] if this stream is not using TLS,
+    /// such as to wrap it in TLS.
+    ///
+    /// Otherwise, returns `self`.
+    pub fn into_inner(self) -> Result<TcpStream, Self> {
+        match self.stream {
+            StreamInner::Tcp(s) => Ok(s),
+            stream => Err(StreamTokio { stream, timings: self.timings }),
+        }
+    }
+}
+
+impl From<TcpStream> for StreamTokio {
+    fn from(value: TcpStream) -> Self {
+        StreamTokio { stream: StreamInner::Tcp(value), timings: None }
+    }
+}
+
+#[cfg(feature = "tls-tokio")]
+impl From<tokio_rustls::client::TlsStream<TcpStream>> for StreamTokio {
+    fn from(value: tokio_rustls::client::TlsStream<TcpStream>) -> Self {
+        StreamTokio { stream: StreamInner::Tls(Box::new(value)), timings: None }
+    }
 }
 
 impl tokio::io::AsyncRead for StreamTokio {
@@ -110,6 +278,27 @@ impl tokio::io::AsyncWrite for StreamTokio {
     }
 }
 
+/// Awaits `fut`, or a message from `cmds` if one arrives first.
+///
+/// Returns `Err(None)` if `cmds` is closed.
+async fn or_recv<T>(
+    fut: impl Future<Output = T>,
+    cmds: &mut Option<&mut Receiver<ClientMsg<'static>>>,
+) -> Result<T, Option<ClientMsg<'static>>> {
+    let Some(cmds) = cmds else {
+        return Ok(fut.await);
+    };
+    let mut fut = std::pin::pin!(fut);
+    std::future::poll_fn(|cx| {
+        // Poll the connection first so that a busy command stream cannot starve it.
+        if let Poll::Ready(value) = fut.as_mut().poll(cx) {
+            return Poll::Ready(Ok(value));
+        }
+        cmds.poll_recv(cx).map(Err)
+    })
+    .await
+}
+
 // Using named &muts instead of Pins here because it means less an Unpin dance is needed
 // to use this in run_handler_tokio.
 
@@ -158,30 +347,100 @@ impl<C: ConnectionTokio, S> crate::client::Client<C, S> {
     ///
     /// Returns the IDs of the handlers that yielded or finished, respectively.
     /// Read timeouts are indicated by a return value of `Ok(None)`.
+    /// Lines that are too long or cannot be parsed are dropped;
+    /// see [`ClientLogic::set_drop_fn`][crate::client::ClientLogic::set_drop_fn].
     /// I/O failure should be considered non-recoverable.
+    /// If the client is [shutting down][crate::client::ClientLogic::begin_shutdown]
+    /// or the server sent `ERROR`, I/O failure is instead reported as a
+    /// [`ConnectionClosed`][super::ConnectionClosed].
     ///
-    /// Handlers are not guaranteed to run in the order they were added.
+    /// Handlers run in the order described in [`Handler`][crate::client::Handler]'s documentation.
+    /// Handlers added with a [timeout][crate::client::Client::add_with_timeout]
+    /// that has passed are expired and reported as finished.
     /// If there are no handlers to run, fully flushes the queue.
     /// If the `tracing` feature is enabled, logs messages at the debug level.
     pub async fn run_tokio(&mut self) -> std::io::Result<Option<(&[usize], &[usize])>> {
+        self.run_tokio_impl(None).await
+    }
+    /// Runs handlers off of the connection until any of them yield or finish,
+    /// while also sending messages received from `cmds`.
+    ///
+    /// Behaves like [`run_tokio`][Self::run_tokio], except that while waiting for
+    /// a message from the server, messages received from `cmds` are pushed onto the queue
+    /// and sent as soon as the queue's rate limit allows.
+    /// Receiving a message restarts the read timeout, as sending queued messages does.
+    ///
+    /// Once `cmds` is closed, it is no longer polled and handlers continue to run.
+    /// As with `run_tokio`, this returns once the queue is flushed if there are no handlers.
+    pub async fn run_with_commands_tokio(
+        &mut self,
+        cmds: &mut Receiver<ClientMsg<'static>>,
+    ) -> std::io::Result<Option<(&[usize], &[usize])>> {
+        self.run_tokio_impl(Some(cmds)).await
+    }
+    async fn run_tokio_impl(
+        &mut self,
+        mut cmds: Option<&mut Receiver<ClientMsg<'static>>>,
+    ) -> std::io::Result<Option<(&[usize], &[usize])>> {
         let finished_at = loop {
+            #[cfg(feature = "diagnostics")]
+            self.logic.timings.finish_iteration();
             let wait_for = self.flush_partial_tokio().await?;
+            let expired_at = self.logic.expire_handlers();
+            if self.logic.handlers.has_results(expired_at) {
+                break expired_at;
+            }
+            let wait_for = self.logic.wait_for_deadline(wait_for);
             if self.logic.handlers.is_empty() {
                 if let Some(wait_for) = wait_for {
+                    #[cfg(feature = "diagnostics")]
+                    let start = std::time::Instant::now();
                     tokio::time::sleep(wait_for).await;
+                    #[cfg(feature = "diagnostics")]
+                    self.logic.timings.record(LoopPhase::Sleep, start);
                     continue;
                 }
                 return Ok(Some((Default::default(), Default::default())));
             }
+            #[cfg(feature = "diagnostics")]
+            let start = std::time::Instant::now();
             let mut conn = TimeLimitedTokio::new(&mut self.conn.conn, &self.logic.timeout);
-            let msg_result = if self.logic.handlers.wants_owning() {
-                let fut = ClientCodec::read_owning_from_tokio(&mut conn, &mut self.conn.buf_i);
-                timed_io(fut, wait_for, self.logic.timeout.read_timeout()).await?
+            let (buf, discarding) = (&mut self.conn.buf_i, &mut self.conn.discarding);
+            let codec = self.logic.read_codec();
+            let msg_result = if self.logic.handlers.wants_owning() && self.logic.on_drop.is_none() {
+                let fut = async {
+                    super::skip_line_tokio(&mut conn, discarding).await?;
+                    codec.read_owning_tokio(&mut conn, buf).await
+                };
+                or_recv(timed_io(fut, wait_for, self.logic.timeout.read_timeout()), &mut cmds).await
             } else {
-                let fut = ClientCodec::read_borrowing_from_tokio(&mut conn, &mut self.conn.buf_i);
-                timed_io(fut, wait_for, self.logic.timeout.read_timeout()).await?
+                let fut = async {
+                    super::skip_line_tokio(&mut conn, discarding).await?;
+                    codec.read_borrowing_tokio(&mut conn, buf).await
+                };
+                or_recv(timed_io(fut, wait_for, self.logic.timeout.read_timeout()), &mut cmds).await
+            };
+            let msg_result = match msg_result {
+                Ok(msg_result) => msg_result,
+                Err(Some(cmd)) => {
+                    self.logic.queue.edit().push(cmd);
+                    continue;
+                }
+                Err(None) => {
+                    cmds = None;
+                    continue;
+                }
+            };
+            #[cfg(feature = "diagnostics")]
+            self.logic.timings.record(LoopPhase::Read, start);
+            let msg_result = match msg_result {
+                Ok(msg) => Ok(msg),
+                Err(e) => match self.conn.drop_line(e, &mut self.logic) {
+                    Ok(()) => continue,
+                    Err(e) => Err(e),
+                },
             };
-            let msg = match msg_result {
+            let msg = match msg_result.map_err(|e| self.logic.filter_io_error(e))? {
                 Ok(m) => m,
                 Err(true) => continue,
                 Err(false) => {
@@ -196,7 +455,8 @@ impl<C: ConnectionTokio, S> crate::client::Client<C, S> {
                 }
             };
             #[cfg(feature = "tracing")]
-            tracing::debug!(target: "vinezombie::recv", "{}", msg);
+            tracing::debug!(target: "vinezombie::recv", "{}", msg.redacted());
+            self.logic.reads.parsed += 1;
             let finished_at = self.logic.run_once(&msg);
             self.conn.buf_i.clear();
             if self.logic.handlers.has_results(finished_at) {
@@ -205,8 +465,76 @@ impl<C: ConnectionTokio, S> crate::client::Client<C, S> {
                 break finished_at;
             }
         };
+        #[cfg(feature = "diagnostics")]
+        self.logic.timings.finish_iteration();
         Ok(Some(self.logic.handlers.last_run_results(finished_at)))
     }
+    /// Gracefully disconnects from the server.
+    ///
+    /// Cancels all handlers, discards all queued messages, and immediately sends a QUIT
+    /// with an optional reason, ignoring rate limits.
+    /// Then reads and discards messages until the server closes the connection or sends an
+    /// `ERROR`, waiting no longer than the read timeout in total (or 10 seconds if none is set),
+    /// before shutting down the connection.
+    ///
+    /// Returns the server's `ERROR` message, if one was received.
+    /// I/O errors while sending the QUIT or waiting for the server are not reported,
+    /// as the connection is being closed anyway.
+    pub async fn disconnect_tokio(
+        &mut self,
+        reason: Option<crate::string::Line<'static>>,
+    ) -> std::io::Result<Option<crate::ircmsg::ServerMsg<'static>>> {
+        use tokio::io::AsyncWriteExt;
+        let quit = self.logic.begin_disconnect(reason);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(target: "vinezombie::send", "{}", quit.redacted());
+        let _ = ClientCodec::write_to(&quit, &mut self.conn.buf_o);
+        self.conn.buf_o.extend_from_slice(b"\r\n");
+        let mut conn = TimeLimitedTokio::new(&mut self.conn.conn, &self.logic.timeout);
+        let mut result = conn.write_all(&self.conn.buf_o).await;
+        self.conn.buf_o.clear();
+        if result.is_ok() {
+            result = conn.flush().await;
+        }
+        let mut error = None;
+        if result.is_ok() {
+            let wait_for = self.logic.disconnect_timeout();
+            let (conn, logic) = (&mut self.conn, &mut self.logic);
+            let drain = async {
+                loop {
+                    let mut io = TimeLimitedTokio::new(&mut conn.conn, &logic.timeout);
+                    let msg = match super::skip_line_tokio(&mut io, &mut conn.discarding).await {
+                        Ok(()) => {
+                            ClientCodec::read_owning_from_tokio(&mut io, &mut conn.buf_i).await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    conn.buf_i.clear();
+                    match msg {
+                        Ok(msg) => {
+                            #[cfg(feature = "tracing")]
+                            tracing::debug!(target: "vinezombie::recv", "{}", msg.redacted());
+                            logic.reads.parsed += 1;
+                            if super::is_error(&msg) {
+                                error = Some(msg);
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            if conn.drop_line(e, logic).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            };
+            let _ = tokio::time::timeout(wait_for, drain).await;
+        }
+        match self.conn.conn.as_write().shutdown().await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotConnected => Err(e),
+            _ => Ok(error),
+        }
+    }
     /// Flushes the queue until it's empty or hits rate limits.
     ///
     /// I/O failure should be considered non-recoverable,
@@ -218,18 +546,24 @@ impl<C: ConnectionTokio, S> crate::client::Client<C, S> {
         if self.logic.queue.is_empty() {
             return Ok(None);
         }
+        #[cfg(feature = "diagnostics")]
+        let start = std::time::Instant::now();
         let mut timeout = None;
         while let Some(popped) = self.logic.queue.pop(|new_timeout| timeout = new_timeout) {
             #[cfg(feature = "tracing")]
-            tracing::debug!(target: "vinezombie::send", "{}", popped);
+            tracing::debug!(target: "vinezombie::send", "{}", popped.redacted());
             let _ = ClientCodec::write_to(&popped, &mut self.conn.buf_o);
             self.conn.buf_o.extend_from_slice(b"\r\n");
         }
         let mut conn = TimeLimitedTokio::new(&mut self.conn.conn, &self.logic.timeout);
-        let result = conn.write_all(&self.conn.buf_o).await;
+        let mut result = conn.write_all(&self.conn.buf_o).await;
         self.conn.buf_o.clear();
-        result?;
-        conn.flush().await?;
+        if result.is_ok() {
+            result = conn.flush().await;
+        }
+        result.map_err(|e| self.logic.filter_io_error(e))?;
+        #[cfg(feature = "diagnostics")]
+        self.logic.timings.record(LoopPhase::Flush, start);
         Ok(timeout)
     }
 }
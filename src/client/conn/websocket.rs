@@ -0,0 +1,221 @@
+//! IRC over WebSocket, as used by web clients and some gateways.
+//!
+//! Each WebSocket message carries exactly one IRC message without a trailing line ending.
+//! [`WebSocketTokio`] adapts this to the line-based I/O that [`Client`][crate::client::Client]
+//! expects, so that it can be used anywhere a [`ConnectionTokio`] is.
+//!
+//! No subprotocol is requested during the handshake, so servers assume `text.ircv3.net`
+//! and outgoing messages are sent as text, replacing non-UTF-8 bytes as needed.
+//! Connections that have negotiated `binary.ircv3.net` some other way can be wrapped
+//! using [`WebSocketTokio::new`].
+//! WebSocket pings are answered while reading.
+
+use super::ConnectionTokio;
+use futures_core::Stream;
+use futures_sink::Sink;
+use std::{
+    io::{Error, ErrorKind},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, Message},
+    WebSocketStream,
+};
+
+fn ws_error(e: tokio_tungstenite::tungstenite::Error) -> Error {
+    use tokio_tungstenite::tungstenite::Error as WsError;
+    match e {
+        WsError::Io(e) => e,
+        WsError::ConnectionClosed | WsError::AlreadyClosed => ErrorKind::NotConnected.into(),
+        e => Error::new(ErrorKind::Other, e),
+    }
+}
+
+/// Connects to an IRC server over WebSocket.
+///
+/// `url` must use the `ws` or `wss` scheme.
+/// `tls_fn` is called if a TLS client configuration is needed.
+#[cfg(feature = "tls-tokio")]
+pub async fn connect_ws_tokio(
+    url: &str,
+    tls_fn: impl FnOnce() -> std::io::Result<crate::client::tls::TlsConfig>,
+) -> std::io::Result<WebSocketTokio<super::StreamTokio>> {
+    use tokio_tungstenite::tungstenite::http;
+    let uri: http::Uri = url.parse().map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    let tls = match uri.scheme_str() {
+        Some("ws") => false,
+        Some("wss") => true,
+        _ => {
+            let msg = format!("not a WebSocket URL: {url}");
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+    };
+    let host = uri.host().ok_or_else(|| {
+        Error::new(ErrorKind::InvalidInput, format!("WebSocket URL has no host: {url}"))
+    })?;
+    let address = crate::string::Word::from_str(host);
+    let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+    let addr = super::ServerAddr { address, tls, port: Some(port), connect_timeout: None };
+    let stream = addr.connect_tokio(tls_fn).await?.into_inner();
+    WebSocketTokio::handshake(url, stream).await
+}
+
+/// A WebSocket connection to an IRC server, usable as a [`ConnectionTokio`].
+///
+/// Reading yields the content of each text or binary message followed by CRLF.
+/// Written bytes are buffered until a complete line has been written,
+/// which is then sent as one message without its line ending.
+/// Lines are only guaranteed to be sent once the connection is flushed.
+pub struct WebSocketTokio<S> {
+    ws: WebSocketStream<S>,
+    binary: bool,
+    buf_i: Vec<u8>,
+    pos_i: usize,
+    buf_o: Vec<u8>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> WebSocketTokio<S> {
+    /// Performs the WebSocket handshake over an already-established connection.
+    ///
+    /// `url` is used for the handshake request; the connection is not checked against it.
+    pub async fn handshake(url: &str, stream: S) -> std::io::Result<Self> {
+        let request = url.into_client_request().map_err(ws_error)?;
+        let (ws, _) = tokio_tungstenite::client_async(request, stream).await.map_err(ws_error)?;
+        Ok(Self::new(ws, false))
+    }
+    /// Wraps a WebSocket stream that has already completed its handshake.
+    ///
+    /// If `binary` is `true`, outgoing messages are sent as binary messages.
+    /// Otherwise, they are sent as text.
+    pub fn new(ws: WebSocketStream<S>, binary: bool) -> Self {
+        WebSocketTokio { ws, binary, buf_i: Vec::new(), pos_i: 0, buf_o: Vec::new() }
+    }
+    /// Returns `true` if outgoing messages are sent as binary messages.
+    pub fn is_binary(&self) -> bool {
+        self.binary
+    }
+    /// Returns a shared reference to the underlying WebSocket stream.
+    pub fn get_ref(&self) -> &WebSocketStream<S> {
+        &self.ws
+    }
+    /// Returns the underlying WebSocket stream.
+    ///
+    /// Any buffered data that has not been read or sent is discarded.
+    pub fn into_inner(self) -> WebSocketStream<S> {
+        self.ws
+    }
+    /// Sends every complete line in the output buffer.
+    fn poll_send_lines(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        while let Some(end) = self.buf_o.iter().position(|b| *b == b'\n') {
+            std::task::ready!(Pin::new(&mut self.ws).poll_ready(cx)).map_err(ws_error)?;
+            let mut line: Vec<u8> = self.buf_o.drain(..=end).collect();
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            if line.is_empty() {
+                continue;
+            }
+            let msg = if self.binary {
+                Message::Binary(line)
+            } else {
+                match String::from_utf8(line) {
+                    Ok(string) => Message::Text(string),
+                    Err(e) => Message::Text(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+                }
+            };
+            Pin::new(&mut self.ws).start_send(msg).map_err(ws_error)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncBufRead for WebSocketTokio<S> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<&[u8]>> {
+        let this = self.get_mut();
+        while this.pos_i >= this.buf_i.len() {
+            let mut data = match std::task::ready!(Pin::new(&mut this.ws).poll_next(cx)) {
+                Some(Ok(Message::Text(text))) => text.into_bytes(),
+                Some(Ok(Message::Binary(data))) => data,
+                // Pings are answered by the WebSocket stream itself.
+                Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => continue,
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(&[])),
+                Some(Err(e)) => return Poll::Ready(Err(ws_error(e))),
+            };
+            // Tolerate servers that send line endings anyway.
+            while matches!(data.last(), Some(b'\r' | b'\n')) {
+                data.pop();
+            }
+            if data.is_empty() {
+                continue;
+            }
+            data.extend_from_slice(b"\r\n");
+            this.buf_i = data;
+            this.pos_i = 0;
+        }
+        Poll::Ready(Ok(&this.buf_i[this.pos_i..]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.pos_i = std::cmp::min(this.pos_i + amt, this.buf_i.len());
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for WebSocketTokio<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let data = std::task::ready!(self.as_mut().poll_fill_buf(cx))?;
+        let len = std::cmp::min(data.len(), buf.remaining());
+        buf.put_slice(&data[..len]);
+        self.consume(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for WebSocketTokio<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        this.buf_o.extend_from_slice(buf);
+        // Sending now is an optimization; flushing will finish the job if this can't.
+        if let Poll::Ready(Err(e)) = this.poll_send_lines(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        std::task::ready!(this.poll_send_lines(cx))?;
+        Pin::new(&mut this.ws).poll_flush(cx).map_err(ws_error)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        std::task::ready!(this.poll_send_lines(cx))?;
+        Pin::new(&mut this.ws).poll_close(cx).map_err(ws_error)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> ConnectionTokio for WebSocketTokio<S> {
+    type AsyncBufRead = Self;
+
+    type AsyncWrite = Self;
+
+    fn as_bufread(&mut self) -> Pin<&mut Self::AsyncBufRead> {
+        Pin::new(self)
+    }
+
+    fn as_write(&mut self) -> &mut Self::AsyncWrite {
+        self
+    }
+}
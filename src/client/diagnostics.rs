@@ -0,0 +1,265 @@
+//! Coarse timing information for the client run loops.
+//!
+//! When the `diagnostics` feature is enabled,
+//! [`Client::run`][super::Client::run] and `Client::run_tokio` record how long each iteration
+//! of their loops spent in each [phase][LoopPhase], as well as how long each handler took
+//! to process each message. Only a fixed number of recent samples are kept.
+//! A summary of these samples can be obtained using [`ClientLogic::loop_timings`].
+//!
+//! This is not a replacement for a real profiler. It exists to help narrow down
+//! where a client is spending its time without needing to attach one.
+//!
+//! [`ClientLogic::loop_timings`]: super::ClientLogic::loop_timings
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// The default number of samples kept by the run loop for each phase and handler.
+pub const DEFAULT_SAMPLES: usize = 256;
+
+/// The phases of one iteration of a client's run loop.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum LoopPhase {
+    /// Waiting on a message from the server and parsing it.
+    Read,
+    /// Running handlers on a message, including queue adjustment.
+    Dispatch,
+    /// Writing queued messages to the connection.
+    Flush,
+    /// Sleeping for the queue's rate limits to allow more messages to be sent.
+    Sleep,
+}
+
+impl LoopPhase {
+    /// Every phase, in the order they're reported in.
+    pub const ALL: [LoopPhase; 4] =
+        [LoopPhase::Read, LoopPhase::Dispatch, LoopPhase::Flush, LoopPhase::Sleep];
+
+    /// Returns a short lowercase name for this phase.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            LoopPhase::Read => "read",
+            LoopPhase::Dispatch => "dispatch",
+            LoopPhase::Flush => "flush",
+            LoopPhase::Sleep => "sleep",
+        }
+    }
+}
+
+impl std::fmt::Display for LoopPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Summary statistics for a set of durations.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Percentiles {
+    /// The number of samples.
+    pub count: usize,
+    /// The median duration.
+    pub p50: Duration,
+    /// The 90th-percentile duration.
+    pub p90: Duration,
+    /// The 99th-percentile duration.
+    pub p99: Duration,
+    /// The longest duration.
+    pub max: Duration,
+    /// The sum of all durations.
+    pub total: Duration,
+}
+
+impl Percentiles {
+    /// Computes percentiles using the nearest-rank method.
+    fn from_samples(samples: &mut [Duration]) -> Self {
+        let Some(max) = samples.iter().max().copied() else {
+            return Percentiles::default();
+        };
+        samples.sort_unstable();
+        let count = samples.len();
+        let rank = |pct: usize| samples[((count * pct + 99) / 100).saturating_sub(1)];
+        Percentiles {
+            count,
+            p50: rank(50),
+            p90: rank(90),
+            p99: rank(99),
+            max,
+            total: samples.iter().sum(),
+        }
+    }
+}
+
+impl std::fmt::Display for Percentiles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "n={} p50={:?} p90={:?} p99={:?} max={:?} total={:?}",
+            self.count, self.p50, self.p90, self.p99, self.max, self.total
+        )
+    }
+}
+
+/// Timing information for one handler.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct HandlerTimings {
+    /// The handler's id.
+    pub id: usize,
+    /// The handler's name, as returned by [`Handler::name`].
+    ///
+    /// [`Handler::name`]: super::Handler::name
+    pub name: &'static str,
+    /// How long the handler took to process each message.
+    pub times: Percentiles,
+}
+
+/// A summary of a client's recent run loop iterations, as returned by
+/// [`ClientLogic::loop_timings`][super::ClientLogic::loop_timings].
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct LoopTimings {
+    /// The number of loop iterations that were sampled.
+    pub iterations: usize,
+    phases: [Percentiles; 4],
+    handlers: Vec<HandlerTimings>,
+}
+
+impl LoopTimings {
+    /// Returns the statistics for one phase of the run loop.
+    pub fn phase(&self, phase: LoopPhase) -> &Percentiles {
+        &self.phases[phase as usize]
+    }
+    /// Returns the sum of the time spent in every phase.
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|p| p.total).sum()
+    }
+    /// Returns timing information for up to `n` handlers,
+    /// sorted from slowest to fastest by their 99th-percentile time.
+    ///
+    /// This includes handlers that have since finished,
+    /// unless their samples were discarded to make room for newer handlers.
+    pub fn slowest_handlers(&self, n: usize) -> &[HandlerTimings] {
+        &self.handlers[..std::cmp::min(n, self.handlers.len())]
+    }
+    /// Returns timing information for the handler with the provided id, if any.
+    pub fn handler(&self, id: usize) -> Option<&HandlerTimings> {
+        self.handlers.iter().find(|handler| handler.id == id)
+    }
+}
+
+impl std::fmt::Display for LoopTimings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} iterations", self.iterations)?;
+        for phase in LoopPhase::ALL {
+            write!(f, "\n{phase}: {}", self.phase(phase))?;
+        }
+        for handler in self.slowest_handlers(5) {
+            write!(f, "\nhandler {} ({}): {}", handler.id, handler.name, handler.times)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct HandlerSamples {
+    name: &'static str,
+    samples: VecDeque<Duration>,
+}
+
+/// Ring buffers of recent run loop timings.
+#[derive(Clone, Debug)]
+pub(crate) struct LoopRecorder {
+    capacity: usize,
+    current: Option<[Duration; 4]>,
+    iterations: VecDeque<[Duration; 4]>,
+    /// Keyed by handler id.
+    handlers: BTreeMap<usize, HandlerSamples>,
+}
+
+impl Default for LoopRecorder {
+    fn default() -> Self {
+        LoopRecorder::new(DEFAULT_SAMPLES)
+    }
+}
+
+fn push_bounded<T>(buf: &mut VecDeque<T>, capacity: usize, value: T) {
+    if buf.len() >= capacity {
+        buf.pop_front();
+    }
+    buf.push_back(value);
+}
+
+impl LoopRecorder {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = std::cmp::max(capacity, 1);
+        LoopRecorder {
+            capacity,
+            current: None,
+            iterations: VecDeque::with_capacity(capacity),
+            handlers: BTreeMap::new(),
+        }
+    }
+    /// Adds the time elapsed since `start` to the current iteration.
+    #[inline]
+    pub fn record(&mut self, phase: LoopPhase, start: Instant) {
+        let elapsed = start.elapsed();
+        self.current.get_or_insert_with(Default::default)[phase as usize] += elapsed;
+    }
+    /// Records how long the handler with the provided id took to handle one message.
+    #[inline]
+    pub fn record_handler(&mut self, id: usize, start: Instant) {
+        let elapsed = start.elapsed();
+        if let Some(handler) = self.handlers.get_mut(&id) {
+            push_bounded(&mut handler.samples, self.capacity, elapsed);
+        }
+    }
+    /// Starts tracking a new handler.
+    ///
+    /// If samples are already being kept for as many handlers as samples per buffer,
+    /// discards the samples of the oldest handler for which `is_live` returns `false`.
+    pub fn add_handler(&mut self, id: usize, name: &'static str, is_live: impl Fn(usize) -> bool) {
+        if self.handlers.len() >= self.capacity {
+            if let Some(old) = self.handlers.keys().copied().find(|id| !is_live(*id)) {
+                self.handlers.remove(&old);
+            }
+        }
+        self.handlers.insert(id, HandlerSamples { name, samples: VecDeque::new() });
+    }
+    /// Ends the current iteration, if any time was recorded for it.
+    #[inline]
+    pub fn finish_iteration(&mut self) {
+        if let Some(current) = self.current.take() {
+            push_bounded(&mut self.iterations, self.capacity, current);
+        }
+    }
+    /// Discards all samples.
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.iterations.clear();
+        self.handlers.clear();
+    }
+    /// Summarizes the samples for the handler with the provided id.
+    pub fn summarize_handler(&self, id: usize) -> Option<HandlerTimings> {
+        let handler = self.handlers.get(&id)?;
+        let mut samples: Vec<_> = handler.samples.iter().copied().collect();
+        let times = Percentiles::from_samples(&mut samples);
+        Some(HandlerTimings { id, name: handler.name, times })
+    }
+    pub fn summarize(&self) -> LoopTimings {
+        let mut samples = Vec::with_capacity(self.iterations.len() + 1);
+        let iterations = self.iterations.iter().chain(self.current.iter());
+        let mut phases = [Percentiles::default(); 4];
+        for phase in LoopPhase::ALL {
+            samples.clear();
+            samples.extend(iterations.clone().map(|it| it[phase as usize]));
+            phases[phase as usize] = Percentiles::from_samples(&mut samples);
+        }
+        let handlers = self.handlers.keys().filter_map(|id| self.summarize_handler(*id));
+        let mut handlers: Vec<_> = handlers.collect();
+        handlers.sort_by(|a, b| b.times.p99.cmp(&a.times.p99).then(b.times.max.cmp(&a.times.max)));
+        LoopTimings { iterations: iterations.count(), phases, handlers }
+    }
+}
@@ -0,0 +1,114 @@
+use super::LoopPhase;
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef, SyncChannels},
+        conn::Bidir,
+        queue::QueueEditGuard,
+        Client, ClientState, Handler, SelfMadeHandler,
+    },
+    ircmsg::ServerMsg,
+};
+use std::{
+    io::Cursor,
+    ops::ControlFlow,
+    time::{Duration, Instant},
+};
+
+const MSGS: &str = concat!(
+    ":example.com NOTICE * :one\r\n",
+    ":example.com NOTICE * :two\r\n",
+    ":example.com NOTICE * :three\r\n",
+    ":example.com NOTICE * :four\r\n",
+);
+
+/// Handler that takes `self.1` to process each message and finishes after `self.0` messages.
+struct Sleepy(usize, Duration);
+
+impl Handler for Sleepy {
+    type Value = ();
+
+    fn handle(
+        &mut self,
+        _: &ServerMsg<'_>,
+        _: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        _: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        std::thread::sleep(self.1);
+        self.0 = self.0.saturating_sub(1);
+        if self.0 == 0 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+impl SelfMadeHandler for Sleepy {
+    type Receiver<Spec: ChannelSpec> = ();
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        _: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        (Box::<crate::client::channel::ClosedSender<()>>::default(), ())
+    }
+}
+
+fn client() -> Client<Bidir<Cursor<Vec<u8>>, std::io::Sink>, SyncChannels> {
+    let io = Bidir(Cursor::new(MSGS.as_bytes().to_vec()), std::io::sink());
+    Client::new(io, SyncChannels)
+}
+
+#[test]
+fn buckets_sum_to_wall_time() {
+    let mut client = client();
+    let (id, _) = client.add((), Sleepy(4, Duration::from_millis(5))).unwrap();
+    let start = Instant::now();
+    client.run().unwrap();
+    let wall = start.elapsed();
+    let timings = client.logic.loop_timings();
+    assert_eq!(timings.iterations, 4);
+    for phase in LoopPhase::ALL {
+        assert_eq!(timings.phase(phase).count, 4, "wrong sample count for {phase}");
+    }
+    let handler = timings.handler(id).unwrap();
+    assert_eq!(handler.times.count, 4);
+    // Each measurement is nested within the next.
+    let dispatch = timings.phase(LoopPhase::Dispatch).total;
+    let total = timings.total();
+    assert!(Duration::from_millis(20) <= handler.times.total);
+    assert!(handler.times.total <= dispatch, "handler {handler:?} exceeds dispatch {dispatch:?}");
+    assert!(dispatch <= total, "dispatch {dispatch:?} exceeds total {total:?}");
+    assert!(total <= wall, "recorded {total:?} exceeds wall time {wall:?}");
+    assert_eq!(client.logic.handler_timings(id).as_ref(), Some(handler));
+}
+
+#[test]
+fn slow_handler_is_top_offender() {
+    let mut client = client();
+    let (fast, _) = client.add((), Sleepy(usize::MAX, Duration::ZERO)).unwrap();
+    let (slow, _) = client.add((), Sleepy(3, Duration::from_millis(10))).unwrap();
+    client.run().unwrap();
+    let timings = client.logic.loop_timings();
+    let slowest = timings.slowest_handlers(2);
+    assert_eq!(slowest.len(), 2);
+    assert_eq!(slowest[0].id, slow);
+    assert_eq!(slowest[1].id, fast);
+    assert!(slowest[0].name.ends_with("Sleepy"));
+    assert_eq!(slowest[0].times.count, 3);
+    assert!(slowest[0].times.p99 >= Duration::from_millis(10));
+}
+
+#[test]
+fn finished_handlers_evicted() {
+    let mut recorder = super::LoopRecorder::new(2);
+    recorder.add_handler(0, "live", |_| true);
+    recorder.add_handler(1, "done", |_| true);
+    recorder.add_handler(2, "new", |id| id == 0);
+    let timings = recorder.summarize();
+    let ids: Vec<_> = timings.slowest_handlers(3).iter().map(|handler| handler.id).collect();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.contains(&0) && ids.contains(&2));
+}
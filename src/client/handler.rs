@@ -1,23 +1,58 @@
 pub mod channel;
+pub mod combinator;
 
-use std::ops::ControlFlow;
+use std::{ops::ControlFlow, time::Instant};
 
 use super::{
-    queue::{Queue, QueueEditGuard},
+    queue::{Producer, Queue, QueueEditGuard},
     ClientState,
 };
-use crate::ircmsg::ServerMsg;
+use crate::{
+    ircmsg::ServerMsg,
+    names::cmd::BATCH,
+    string::{Arg, NoNul},
+};
 
 use channel::*;
 
 /// Generic message handlers, typically intended to handle one expected batch of messages
 /// and parse them into a more-useful form.
+///
+/// # Ordering
+///
+/// Each message is processed by handlers one at a time in a stable order:
+/// handlers with a higher priority (see [`Client::add_with_priority`][super::Client::add_with_priority])
+/// run first, and handlers with the same priority run in the order they were added.
+/// Handler ids are never reused and increase in the order handlers are added,
+/// so handlers with the same priority run in ascending order of id.
+/// Handlers finishing or being cancelled does not change the order of the remaining ones.
+///
+/// Within one message, each handler observes every change made to the [`ClientState`]
+/// by the handlers that ran before it, and none of the changes made by handlers that run after it.
+/// The same applies to messages pushed onto the queue,
+/// though none of them are sent until every handler has processed the message.
+///
+/// The ids of handlers that yielded or finished while processing a message
+/// are reported in ascending order.
+///
+/// Responses to labeled messages can be given to only the handler that sent them,
+/// bypassing every other handler; see [`QueueEditGuard::route_label`].
 pub trait Handler: 'static + Send {
     /// The type of values produced by this handler.
     type Value: 'static;
     /// Processes one message.
     ///
     /// Returns [`ControlFlow::Break`] if this handler is finished processing messages.
+    /// Finished handlers are dropped along with their channel's sender,
+    /// so a handler that finishes without sending a value closes its channel
+    /// instead of leaving the receiver waiting forever.
+    /// Channels can instead receive a stand-in value in this case;
+    /// see [`OrIncomplete`][crate::client::channel::OrIncomplete].
+    ///
+    /// A handler must not [push][QueueEditGuard::push] messages in the call where it finishes,
+    /// as its receiver would get its value before they are sent.
+    /// [Urgent][QueueEditGuard::push_urgent] messages, such as aborting SASL, are allowed.
+    /// Debug builds assert this.
     fn handle(
         &mut self,
         msg: &ServerMsg<'_>,
@@ -33,6 +68,88 @@ pub trait Handler: 'static + Send {
     fn wants_owning(&self) -> bool {
         false
     }
+
+    /// Returns a human-readable name for this handler.
+    ///
+    /// This is used for diagnostics and should not be relied on for anything else.
+    /// The default implementation returns the name of this handler's type.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Called after a read from the connection times out.
+    ///
+    /// This is not called automatically by the `run` methods;
+    /// see [`Client::handle_timeout`][super::Client::handle_timeout].
+    /// This can be used to act on the passage of time while no messages are received.
+    /// The return value has the same meaning as that of [`handle`][Handler::handle].
+    /// The default implementation does nothing.
+    fn handle_timeout(
+        &mut self,
+        state: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+        channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        let _ = (state, queue, channel);
+        ControlFlow::Continue(())
+    }
+
+    /// Called when this handler is cancelled before it finished.
+    ///
+    /// This can be used to yield partial results before the channel is closed.
+    /// The default implementation does nothing.
+    fn cancel(&mut self, channel: SenderRef<'_, Self::Value>) {
+        let _ = channel;
+    }
+
+    /// Called when this handler's deadline passes before it finished.
+    ///
+    /// See [`Client::add_with_timeout`][super::Client::add_with_timeout].
+    /// This can be used to send a value indicating that the handler timed out
+    /// before the channel is closed.
+    /// The default implementation calls [`cancel`][Handler::cancel].
+    fn expire(&mut self, channel: SenderRef<'_, Self::Value>) {
+        self.cancel(channel);
+    }
+}
+
+impl<T: 'static> Handler for Box<dyn Handler<Value = T>> {
+    type Value = T;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+        channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        (**self).handle(msg, state, queue, channel)
+    }
+
+    fn wants_owning(&self) -> bool {
+        (**self).wants_owning()
+    }
+
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn handle_timeout(
+        &mut self,
+        state: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+        channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        (**self).handle_timeout(state, queue, channel)
+    }
+
+    fn cancel(&mut self, channel: SenderRef<'_, Self::Value>) {
+        (**self).cancel(channel);
+    }
+
+    fn expire(&mut self, channel: SenderRef<'_, Self::Value>) {
+        (**self).expire(channel);
+    }
 }
 
 /// Marker indicating no handler was returned because none is needed.
@@ -122,8 +239,26 @@ impl<T: SelfMadeHandler> MakeHandler<T> for () {
     }
 }
 
-type BoxHandler =
-    Box<dyn FnMut(&ServerMsg<'_>, &mut ClientState, QueueEditGuard<'_>) -> HandlerStatus + Send>;
+/// Type-erased handler and sender pair.
+trait ErasedHandler: Send {
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+    ) -> HandlerStatus;
+    fn handle_timeout(
+        &mut self,
+        state: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+    ) -> HandlerStatus;
+    fn cancel(&mut self);
+    fn expire(&mut self);
+    fn name(&self) -> &'static str;
+    fn wants_owning(&self) -> bool;
+}
+
+type BoxHandler = Box<dyn ErasedHandler>;
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum HandlerStatus {
@@ -131,26 +266,150 @@ enum HandlerStatus {
     Done { yielded: bool },
 }
 
-fn box_handler<T: 'static>(
-    mut handler: Box<dyn Handler<Value = T>>,
-    mut sender: Box<dyn Sender<Value = T> + Send>,
-) -> BoxHandler {
-    Box::new(move |msg, state, queue| {
+struct HandlerPair<T> {
+    handler: Box<dyn Handler<Value = T>>,
+    sender: Box<dyn Sender<Value = T> + Send>,
+    /// Whether the sender stopped accepting values, and why.
+    ended: Option<Sent>,
+    /// Whether the handler has ever sent a value.
+    sent: bool,
+}
+
+impl<T: 'static> HandlerPair<T> {
+    fn run(
+        &mut self,
+        f: impl FnOnce(&mut dyn Handler<Value = T>, SenderRef<'_, T>) -> ControlFlow<()>,
+    ) -> HandlerStatus {
         let mut yielded = false;
-        let sr = SenderRef { sender: &mut *sender, flag: &mut yielded };
-        if handler.handle(msg, state, queue, sr).is_break() {
+        let sr =
+            SenderRef { sender: &mut *self.sender, flag: &mut yielded, ended: &mut self.ended };
+        let result = f(&mut *self.handler, sr);
+        self.sent |= yielded;
+        if result.is_break() {
+            if !self.sent && self.ended.is_none() && self.sender.may_send() {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("{} finished without sending a value", self.handler.name());
+                self.sender.finish_unsent();
+            }
             HandlerStatus::Done { yielded }
         } else {
-            HandlerStatus::Keep { yielded, wants_owning: handler.wants_owning() }
+            HandlerStatus::Keep { yielded, wants_owning: self.handler.wants_owning() }
         }
-    })
+    }
+}
+
+impl<T: 'static> ErasedHandler for HandlerPair<T> {
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+    ) -> HandlerStatus {
+        self.run(|handler, sr| handler.handle(msg, state, queue, sr))
+    }
+
+    fn handle_timeout(
+        &mut self,
+        state: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+    ) -> HandlerStatus {
+        self.run(|handler, sr| handler.handle_timeout(state, queue, sr))
+    }
+
+    fn cancel(&mut self) {
+        let mut yielded = false;
+        let sr =
+            SenderRef { sender: &mut *self.sender, flag: &mut yielded, ended: &mut self.ended };
+        self.handler.cancel(sr);
+    }
+
+    fn expire(&mut self) {
+        let mut yielded = false;
+        let sr =
+            SenderRef { sender: &mut *self.sender, flag: &mut yielded, ended: &mut self.ended };
+        self.handler.expire(sr);
+    }
+
+    fn name(&self) -> &'static str {
+        self.handler.name()
+    }
+
+    fn wants_owning(&self) -> bool {
+        self.handler.wants_owning()
+    }
+}
+
+struct Entry {
+    handler: BoxHandler,
+    id: usize,
+    priority: i32,
+    /// When this handler should be expired if it has not finished yet.
+    deadline: Option<Instant>,
+}
+
+/// Responses to labeled messages that should only go to one handler.
+#[derive(Default)]
+struct Routes {
+    /// Labels awaiting a response, and the id of the handler they're routed to.
+    labels: Vec<(NoNul<'static>, usize)>,
+    /// Reference tags of open labeled batches including the leading `+`,
+    /// and the id of the handler they're routed to.
+    batches: Vec<(Arg<'static>, usize)>,
+}
+
+impl Routes {
+    fn find_batch(&self, reference: &[u8]) -> Option<usize> {
+        self.batches.iter().position(|(open, _)| &open.as_bytes()[1..] == reference)
+    }
+
+    /// Returns the id of the handler that `msg` should be routed to, if any.
+    fn route(&mut self, msg: &ServerMsg<'_>) -> Option<usize> {
+        let reference = if msg.kind == BATCH { msg.args.words().first() } else { None };
+        if let Some(label) = msg.tags.get("label") {
+            let idx = self.labels.iter().position(|(l, _)| l.as_bytes() == label.as_bytes());
+            if let Some(idx) = idx {
+                // One response per label, be it one message, a batch, or an ACK.
+                let (_, id) = self.labels.swap_remove(idx);
+                if let Some(reference) = reference.filter(|r| r.first() == Some(&b'+')) {
+                    self.batches.push((reference.clone().owning(), id));
+                }
+                return Some(id);
+            }
+        }
+        if let Some(reference) = reference.filter(|r| r.first() == Some(&b'-')) {
+            let idx = self.find_batch(&reference.as_bytes()[1..])?;
+            return Some(self.batches.swap_remove(idx).1);
+        }
+        let idx = self.find_batch(msg.tags.get("batch")?.as_bytes())?;
+        let id = self.batches[idx].1;
+        if let Some(reference) = reference.filter(|r| r.first() == Some(&b'+')) {
+            // Nested batch.
+            self.batches.push((reference.clone().owning(), id));
+        }
+        Some(id)
+    }
+
+    fn remove_handler(&mut self, id: usize) {
+        self.labels.retain(|(_, route_id)| *route_id != id);
+        self.batches.retain(|(_, route_id)| *route_id != id);
+    }
+
+    fn clear(&mut self) {
+        self.labels.clear();
+        self.batches.clear();
+    }
 }
 
 pub(crate) struct Handlers {
-    handlers: Vec<(BoxHandler, usize)>,
+    /// Sorted by descending priority, then by insertion order.
+    handlers: Vec<Entry>,
     yielded: Vec<usize>,
+    /// The ids of handlers that finished during the last run.
     finished: Vec<usize>,
+    /// The id of the next handler to be added.
+    next_id: usize,
     wants_owning: bool,
+    routes: Routes,
 }
 
 impl Default for Handlers {
@@ -161,7 +420,9 @@ impl Default for Handlers {
             yielded: Vec::new(),
             // Registration handler finishes, and will be used in most cases.
             finished: Vec::with_capacity(1),
+            next_id: 0,
             wants_owning: false,
+            routes: Routes::default(),
         }
     }
 }
@@ -171,36 +432,103 @@ impl Handlers {
         &mut self,
         handler: Box<dyn Handler<Value = T>>,
         sender: Box<dyn Sender<Value = T> + Send>,
+        priority: i32,
     ) -> usize {
         self.wants_owning |= handler.wants_owning();
-        let id = self.finished.pop().unwrap_or(self.handlers.len());
-        let boxed = box_handler(handler, sender);
-        self.handlers.push((boxed, id));
+        let id = self.next_id;
+        self.next_id += 1;
+        let handler = Box::new(HandlerPair { handler, sender, ended: None, sent: false });
+        let idx = self.handlers.partition_point(|entry| entry.priority >= priority);
+        self.handlers.insert(idx, Entry { handler, id, priority, deadline: None });
         id
     }
 
+    /// Returns the id that the next added handler will have.
+    pub fn next_id(&self) -> usize {
+        self.next_id
+    }
+
     pub fn is_empty(&self) -> bool {
         self.handlers.is_empty()
     }
 
+    /// Returns `true` if there is a handler with the provided id.
+    #[allow(unused)]
+    pub fn contains(&self, id: usize) -> bool {
+        self.handlers.iter().any(|entry| entry.id == id)
+    }
+
     #[allow(unused)]
     pub fn wants_owning(&self) -> bool {
         self.wants_owning
     }
 
-    #[allow(unused)]
-    pub fn cancel_one(&mut self, id: usize) {
-        if let Some(idx) = self.handlers.iter().position(|(_, id2)| id == *id2) {
-            self.handlers.swap_remove(idx);
-            self.finished.push(id);
-            self.wants_owning &= !self.handlers.is_empty();
-        } else {
-            panic!("attemped to cancel handler with invalid id")
+    /// Returns the id, name, and whether each handler wants owning messages,
+    /// in the order that they process messages.
+    pub fn iter_info(&self) -> impl Iterator<Item = (usize, &str, bool)> + '_ {
+        self.handlers
+            .iter()
+            .map(|entry| (entry.id, entry.handler.name(), entry.handler.wants_owning()))
+    }
+
+    /// Cancels the handler with the provided id.
+    ///
+    /// Returns `false` if there is no such handler.
+    pub fn cancel_id(&mut self, id: usize) -> bool {
+        let Some(idx) = self.handlers.iter().position(|entry| entry.id == id) else {
+            return false;
+        };
+        self.handlers.remove(idx).handler.cancel();
+        self.routes.remove_handler(id);
+        self.wants_owning &= !self.handlers.is_empty();
+        true
+    }
+
+    /// Sets the deadline of the handler with the provided id.
+    ///
+    /// Returns `false` if there is no such handler.
+    pub fn set_deadline(&mut self, id: usize, deadline: Option<Instant>) -> bool {
+        let Some(entry) = self.handlers.iter_mut().find(|entry| entry.id == id) else {
+            return false;
+        };
+        entry.deadline = deadline;
+        true
+    }
+
+    /// Returns the earliest deadline of any handler.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.handlers.iter().filter_map(|entry| entry.deadline).min()
+    }
+
+    /// Expires every handler whose deadline is at or before `now`.
+    ///
+    /// Expired handlers are reported as finished.
+    pub fn expire(&mut self, now: Instant) -> usize {
+        self.yielded.clear();
+        self.finished.clear();
+        let finished_at = self.finished.len();
+        let mut i = 0usize;
+        while let Some(entry) = self.handlers.get_mut(i) {
+            if entry.deadline.is_some_and(|deadline| deadline <= now) {
+                entry.handler.expire();
+                self.finished.push(entry.id);
+                self.routes.remove_handler(entry.id);
+                let _ = self.handlers.remove(i);
+            } else {
+                i += 1;
+            }
         }
+        self.finished[finished_at..].sort_unstable();
+        self.wants_owning &= !self.handlers.is_empty();
+        finished_at
     }
 
     pub fn cancel(&mut self) {
+        for entry in &mut self.handlers {
+            entry.handler.cancel();
+        }
         self.handlers.clear();
+        self.routes.clear();
         self.finished.clear();
         self.yielded.clear();
         self.wants_owning = false;
@@ -220,13 +548,78 @@ impl Handlers {
         msg: &ServerMsg<'_>,
         state: &mut ClientState,
         queue: &mut Queue,
+        #[cfg(feature = "diagnostics")] timings: &mut super::diagnostics::LoopRecorder,
     ) -> usize {
-        self.wants_owning = false;
+        for (label, id) in queue.take_routes() {
+            if self.handlers.iter().any(|entry| entry.id == id) {
+                self.routes.labels.push((label, id));
+            }
+        }
+        let only = self.routes.route(msg);
+        self.dispatch(
+            queue,
+            only,
+            #[cfg(feature = "diagnostics")]
+            timings,
+            |handler, queue| handler.handle(msg, state, queue),
+        )
+    }
+
+    pub fn handle_timeout(
+        &mut self,
+        state: &mut ClientState,
+        queue: &mut Queue,
+        #[cfg(feature = "diagnostics")] timings: &mut super::diagnostics::LoopRecorder,
+    ) -> usize {
+        self.dispatch(
+            queue,
+            None,
+            #[cfg(feature = "diagnostics")]
+            timings,
+            |handler, queue| handler.handle_timeout(state, queue),
+        )
+    }
+
+    /// Runs `f` on every handler, or only the one with id `only` if provided.
+    fn dispatch(
+        &mut self,
+        queue: &mut Queue,
+        only: Option<usize>,
+        #[cfg(feature = "diagnostics")] timings: &mut super::diagnostics::LoopRecorder,
+        mut f: impl FnMut(&mut BoxHandler, QueueEditGuard<'_>) -> HandlerStatus,
+    ) -> usize {
+        // Skipped handlers can't report whether they want owning messages.
+        self.wants_owning &= only.is_some();
         self.yielded.clear();
+        self.finished.clear();
         let finished_at = self.finished.len();
         let mut i = 0usize;
-        while let Some((handler, id)) = self.handlers.get_mut(i) {
-            match (handler)(msg, state, queue.edit()) {
+        while let Some(Entry { handler, id, .. }) = self.handlers.get_mut(i) {
+            if only.is_some_and(|only| only != *id) {
+                i += 1;
+                continue;
+            }
+            #[cfg(feature = "diagnostics")]
+            let start = std::time::Instant::now();
+            let queued = queue.len() - queue.len_urgent();
+            let status = f(handler, queue.edit_as(Producer::Handler(*id)));
+            debug_assert!(
+                matches!(status, HandlerStatus::Keep { .. })
+                    || queue.len() - queue.len_urgent() == queued,
+                "handler {} queued a non-urgent message in the call where it finished",
+                handler.name()
+            );
+            #[cfg(feature = "diagnostics")]
+            timings.record_handler(*id, start);
+            #[cfg(feature = "tracing")]
+            tracing::trace!(
+                target: "vinezombie::handler",
+                id = *id,
+                name = handler.name(),
+                finished = matches!(status, HandlerStatus::Done { .. }),
+                "handler ran"
+            );
+            match status {
                 HandlerStatus::Keep { yielded, wants_owning } => {
                     if yielded {
                         self.yielded.push(*id);
@@ -238,11 +631,24 @@ impl Handlers {
                     if yielded {
                         self.yielded.push(*id);
                     }
+                    #[cfg(all(feature = "diagnostics", feature = "tracing"))]
+                    if let Some(summary) = timings.summarize_handler(*id) {
+                        tracing::debug!(
+                            target: "vinezombie::handler",
+                            id = *id,
+                            name = summary.name,
+                            "handler finished: {}",
+                            summary.times
+                        );
+                    }
                     self.finished.push(*id);
-                    let _ = self.handlers.swap_remove(i);
+                    self.routes.remove_handler(*id);
+                    let _ = self.handlers.remove(i);
                 }
             }
         }
+        self.yielded.sort_unstable();
+        self.finished[finished_at..].sort_unstable();
         finished_at
     }
 }
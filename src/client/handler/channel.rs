@@ -48,12 +48,95 @@ pub trait Sender {
     fn may_send(&self) -> bool {
         true
     }
+
+    /// Called when the handler using this sender finishes without having sent a value.
+    ///
+    /// Senders may use this to send a value in place of the missing one.
+    /// By default, this does nothing,
+    /// and the channel is closed once the sender is dropped.
+    fn finish_unsent(&mut self) {}
+}
+
+impl<S: Sender + ?Sized> Sender for Box<S> {
+    type Value = S::Value;
+
+    fn send(&mut self, value: Self::Value) -> ControlFlow<Sent> {
+        (**self).send(value)
+    }
+
+    fn may_send(&self) -> bool {
+        (**self).may_send()
+    }
+
+    fn finish_unsent(&mut self) {
+        (**self).finish_unsent();
+    }
+}
+
+/// Error for when a handler finished without sending a value.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Incomplete;
+
+impl std::fmt::Display for Incomplete {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("handler finished without a result")
+    }
+}
+
+impl std::error::Error for Incomplete {}
+
+/// A [`Sender`] of `Result`s that sends an [`Incomplete`] error
+/// if its handler finishes without sending anything.
+///
+/// Handlers can opt into this by wrapping the senders created by their
+/// [`make_channel`][crate::client::MakeHandler::make_channel] implementation.
+#[derive(Debug)]
+pub struct OrIncomplete<S> {
+    sender: S,
+    finished: bool,
+}
+
+impl<S> OrIncomplete<S> {
+    /// Wraps `sender`.
+    pub const fn new(sender: S) -> Self {
+        OrIncomplete { sender, finished: false }
+    }
+    /// Returns the wrapped sender.
+    pub fn into_inner(self) -> S {
+        self.sender
+    }
+}
+
+impl<T, E: From<Incomplete>, S: Sender<Value = Result<T, E>>> Sender for OrIncomplete<S> {
+    type Value = Result<T, E>;
+
+    fn send(&mut self, value: Self::Value) -> ControlFlow<Sent> {
+        debug_assert!(!self.finished, "handler sent a value after it finished");
+        self.sender.send(value)
+    }
+
+    fn may_send(&self) -> bool {
+        !self.finished && self.sender.may_send()
+    }
+
+    fn finish_unsent(&mut self) {
+        debug_assert!(!self.finished, "handler finished twice");
+        self.finished = true;
+        let _ = self.sender.send(Err(Incomplete.into()));
+    }
 }
 
 /// The sender half of channel as provided to a handler.
+///
+/// Once a send returns [`ControlFlow::Break`], later sends are not passed on to
+/// the underlying channel, and instead return `ControlFlow::Break(Sent::Closed)`.
+/// If the channel stopped accepting values because of a successful send,
+/// such as with oneshot channels, these later sends are also logged as warnings,
+/// as they indicate a bug in the handler.
 pub struct SenderRef<'a, T> {
     pub(super) sender: &'a mut dyn Sender<Value = T>,
     pub(super) flag: &'a mut bool,
+    pub(super) ended: &'a mut Option<Sent>,
 }
 
 impl<'a, T> SenderRef<'a, T> {
@@ -67,20 +150,39 @@ impl<'a, T> SenderRef<'a, T> {
     /// otherwise returns [`ControlFlow::Break`].
     /// This return value can often be safely ignored.
     pub fn send(&mut self, value: T) -> ControlFlow<Sent> {
+        if let Some(_ended) = *self.ended {
+            #[cfg(feature = "tracing")]
+            if _ended == Sent::Ok {
+                tracing::warn!(
+                    "handler sent to a channel that already received its last value ({})",
+                    std::any::type_name::<T>()
+                );
+            }
+            return ControlFlow::Break(Sent::Closed);
+        }
         let result = self.sender.send(value);
         *self.flag |= !matches!(result, ControlFlow::Break(Sent::Closed));
+        if let ControlFlow::Break(sent) = result {
+            *self.ended = Some(sent);
+        }
         result
     }
     /// Returns `false` if a later send operation is guaranteed to fail.
     pub fn may_send(&self) -> bool {
-        self.sender.may_send()
+        self.ended.is_none() && self.sender.may_send()
     }
 }
 
 /// A [`Sender`] that is always closed.
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug)]
 pub struct ClosedSender<T>(std::marker::PhantomData<fn(T)>);
 
+impl<T> Default for ClosedSender<T> {
+    fn default() -> Self {
+        ClosedSender(std::marker::PhantomData)
+    }
+}
+
 impl<T> Sender for ClosedSender<T> {
     type Value = T;
 
@@ -146,6 +248,41 @@ impl<T> Sender for tokio::sync::mpsc::WeakUnboundedSender<T> {
     }
 }
 
+#[cfg(feature = "futures-channel")]
+impl<T> Sender for Option<futures_channel::oneshot::Sender<T>> {
+    type Value = T;
+
+    fn send(&mut self, value: Self::Value) -> ControlFlow<Sent> {
+        if let Some(sender) = self.take() {
+            if sender.send(value).is_ok() {
+                return ControlFlow::Break(Sent::Ok);
+            }
+        }
+        ControlFlow::Break(Sent::Closed)
+    }
+
+    fn may_send(&self) -> bool {
+        self.as_ref().is_some_and(|sender| !sender.is_canceled())
+    }
+}
+
+#[cfg(feature = "futures-channel")]
+impl<T> Sender for futures_channel::mpsc::UnboundedSender<T> {
+    type Value = T;
+
+    fn send(&mut self, value: T) -> ControlFlow<Sent> {
+        if self.unbounded_send(value).is_ok() {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(Sent::Closed)
+        }
+    }
+
+    fn may_send(&self) -> bool {
+        !self.is_closed()
+    }
+}
+
 /// Specifications for channel types.
 ///
 /// All of the type members are considered to be the receiver side of the channel.
@@ -169,6 +306,11 @@ pub struct SyncChannels;
 #[cfg(feature = "tokio")]
 /// [`ChannelSpec`] for Tokio channels.
 pub struct TokioChannels;
+#[cfg(feature = "futures-channel")]
+/// [`ChannelSpec`] for channels from the `futures-channel` crate.
+///
+/// These work with any async runtime.
+pub struct FuturesChannels;
 
 impl ChannelSpec for SyncChannels {
     type Oneshot<T> = (self::oneshot::Receiver<T>, self::parker::Parker);
@@ -207,3 +349,22 @@ impl ChannelSpec for TokioChannels {
         (Box::new(send), recv)
     }
 }
+
+#[cfg(feature = "futures-channel")]
+impl ChannelSpec for FuturesChannels {
+    type Oneshot<T> = futures_channel::oneshot::Receiver<T>;
+
+    type Queue<T> = futures_channel::mpsc::UnboundedReceiver<T>;
+
+    fn new_oneshot<T: 'static + Send>(
+        &self,
+    ) -> (Box<dyn Sender<Value = T> + Send>, Self::Oneshot<T>) {
+        let (send, recv) = futures_channel::oneshot::channel();
+        (Box::new(Some(send)), recv)
+    }
+
+    fn new_queue<T: 'static + Send>(&self) -> (Box<dyn Sender<Value = T> + Send>, Self::Queue<T>) {
+        let (send, recv) = futures_channel::mpsc::unbounded();
+        (Box::new(send), recv)
+    }
+}
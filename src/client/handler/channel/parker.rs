@@ -13,6 +13,13 @@ use std::thread::Thread;
 /// Global location whose address we can use to indicate that a [`Parker`] should skip parking.
 static mut SKIP_PARKING: std::mem::MaybeUninit<Thread> = std::mem::MaybeUninit::uninit();
 
+/// Returns the address of [`SKIP_PARKING`] without creating a reference to it.
+#[inline(always)]
+#[allow(unused_unsafe)] // Older compilers require `unsafe` here.
+fn skip_parking() -> *mut Thread {
+    unsafe { std::ptr::addr_of_mut!(SKIP_PARKING) }.cast()
+}
+
 /// A wrapped [`Sender`][super::Sender] that can unpark a thread blocked by a [`Parker`].
 #[derive(Clone, Debug, Default)]
 pub struct Unparker<S>(S, ManuallyDrop<Arc<AtomicPtr<Thread>>>);
@@ -35,7 +42,7 @@ impl<S> Drop for Unparker<S> {
             return;
         };
         let ptr = arc.into_inner();
-        if ptr != unsafe { SKIP_PARKING.as_mut_ptr() } {
+        if ptr != skip_parking() {
             if let Some(th) = unsafe { ptr.as_ref() } {
                 th.clone().unpark();
             }
@@ -49,8 +56,8 @@ impl<S> Unparker<S> {
     ///
     /// This generally doesn't need to be called manually unless `S` is not a sender.
     pub fn unpark(&self) {
-        let ptr = self.1.swap(unsafe { SKIP_PARKING.as_mut_ptr() }, Ordering::AcqRel);
-        if ptr != unsafe { SKIP_PARKING.as_mut_ptr() } {
+        let ptr = self.1.swap(skip_parking(), Ordering::AcqRel);
+        if ptr != skip_parking() {
             if let Some(th) = unsafe { ptr.as_ref() } {
                 th.clone().unpark();
             }
@@ -72,6 +79,10 @@ impl<S: super::Sender> super::Sender for Unparker<S> {
     fn may_send(&self) -> bool {
         self.0.may_send()
     }
+
+    fn finish_unsent(&mut self) {
+        self.0.finish_unsent();
+    }
 }
 
 impl Parker {
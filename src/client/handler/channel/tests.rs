@@ -80,7 +80,7 @@ fn oneshot_slow_send() {
     std::thread::spawn(move || {
         use super::Sender;
         std::thread::sleep(std::time::Duration::from_millis(200));
-        send.send(string);
+        let _ = send.send(string);
     });
     let string = recv.recv(&parker).expect("spurious failure in blocking recv");
     assert_eq!(string, "foobar");
@@ -92,9 +92,120 @@ fn oneshot_slow_recv() {
     let (mut send, parker) = super::parker::new(Some(send));
     std::thread::spawn(move || {
         use super::Sender;
-        send.send(string);
+        let _ = send.send(string);
     });
     std::thread::sleep(std::time::Duration::from_millis(200));
     let string = recv.recv(&parker).expect("spurious failure in blocking recv");
     assert_eq!(string, "foobar");
 }
+
+/// Sends `values` through a [`SenderRef`] the way the client does for handlers.
+fn send_all<T>(sender: &mut dyn super::Sender<Value = T>, values: Vec<T>) -> Vec<super::Sent> {
+    let mut flag = false;
+    let mut ended = None;
+    let mut sender = super::SenderRef { sender, flag: &mut flag, ended: &mut ended };
+    values.into_iter().map(|value| sender.send(value).into()).collect()
+}
+
+#[test]
+fn senderref_single_send() {
+    use super::{ChannelSpec, Sent, SyncChannels};
+    let (mut send, (recv, _)) = SyncChannels.new_oneshot();
+    assert_eq!(send_all(&mut *send, vec![1]), [Sent::Ok]);
+    assert_eq!(recv.recv_now(), Some(1));
+    let (mut send, recv) = SyncChannels.new_queue();
+    assert_eq!(send_all(&mut *send, vec![1, 2]), [Sent::Ok, Sent::Ok]);
+    assert_eq!(recv.try_iter().collect::<Vec<_>>(), [1, 2]);
+}
+
+#[test]
+fn senderref_double_send() {
+    use super::{ChannelSpec, Sent, SyncChannels};
+    let (mut send, (recv, _)) = SyncChannels.new_oneshot();
+    assert_eq!(send_all(&mut *send, vec![1, 2, 3]), [Sent::Ok, Sent::Closed, Sent::Closed]);
+    assert_eq!(recv.recv_now(), Some(1));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn senderref_tokio() {
+    use super::{ChannelSpec, Sent, TokioChannels};
+    let (mut send, mut recv) = TokioChannels.new_oneshot();
+    assert_eq!(send_all(&mut *send, vec![1, 2]), [Sent::Ok, Sent::Closed]);
+    assert_eq!(recv.try_recv(), Ok(1));
+    let (mut send, mut recv) = TokioChannels.new_queue();
+    assert_eq!(send_all(&mut *send, vec![1, 2]), [Sent::Ok, Sent::Ok]);
+    assert_eq!(recv.try_recv(), Ok(1));
+    assert_eq!(recv.try_recv(), Ok(2));
+}
+
+#[cfg(feature = "futures-channel")]
+#[test]
+fn senderref_futures() {
+    use super::{ChannelSpec, FuturesChannels, Sent};
+    let (mut send, mut recv) = FuturesChannels.new_oneshot();
+    assert_eq!(send_all(&mut *send, vec![1, 2]), [Sent::Ok, Sent::Closed]);
+    assert_eq!(recv.try_recv(), Ok(Some(1)));
+    let (mut send, mut recv) = FuturesChannels.new_queue();
+    assert_eq!(send_all(&mut *send, vec![1, 2]), [Sent::Ok, Sent::Ok]);
+    assert_eq!(recv.try_recv().ok(), Some(1));
+    assert_eq!(recv.try_recv().ok(), Some(2));
+}
+
+#[cfg(feature = "futures-channel")]
+#[test]
+fn senderref_futures_dropped() {
+    use super::{ChannelSpec, FuturesChannels, Sent};
+    let (mut send, recv) = FuturesChannels.new_oneshot::<i32>();
+    assert!(send.may_send());
+    drop(recv);
+    assert!(!send.may_send());
+    assert_eq!(send_all(&mut *send, vec![1]), [Sent::Closed]);
+    let (mut send, recv) = FuturesChannels.new_queue::<i32>();
+    assert!(send.may_send());
+    drop(recv);
+    assert!(!send.may_send());
+    assert_eq!(send_all(&mut *send, vec![1, 2]), [Sent::Closed, Sent::Closed]);
+}
+
+/// Sender that panics if it is sent more than one value.
+struct PanicsTwice(bool);
+
+impl super::Sender for PanicsTwice {
+    type Value = ();
+
+    fn send(&mut self, _: ()) -> std::ops::ControlFlow<super::Sent> {
+        assert!(!self.0, "sent twice");
+        self.0 = true;
+        std::ops::ControlFlow::Break(super::Sent::Ok)
+    }
+}
+
+#[test]
+fn senderref_double_send_not_forwarded() {
+    use super::Sent;
+    let mut send = PanicsTwice(false);
+    assert_eq!(send_all(&mut send, vec![(), ()]), [Sent::Ok, Sent::Closed]);
+}
+
+#[test]
+fn or_incomplete() {
+    use super::{ChannelSpec, Incomplete, OrIncomplete, Sender, SyncChannels};
+    let (send, (recv, _)) = SyncChannels.new_oneshot::<Result<(), Incomplete>>();
+    let mut send = OrIncomplete::new(send);
+    assert!(send.may_send());
+    send.finish_unsent();
+    assert!(!send.may_send());
+    assert_eq!(recv.recv_now(), Some(Err(Incomplete)));
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic = "after it finished"]
+fn or_incomplete_send_after_finish() {
+    use super::{ChannelSpec, Incomplete, OrIncomplete, Sender, SyncChannels};
+    let (send, _recv) = SyncChannels.new_oneshot::<Result<(), Incomplete>>();
+    let mut send = OrIncomplete::new(send);
+    send.finish_unsent();
+    let _ = send.send(Ok(()));
+}
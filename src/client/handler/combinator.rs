@@ -0,0 +1,456 @@
+//! [`Handler`]s that are made out of other handlers.
+//!
+//! Every combinator owns the senders it gives to the handlers it contains,
+//! passing their values on to its own channel as appropriate.
+//! They implement [`SelfMadeHandler`] using a queue channel, as they may yield
+//! any number of values.
+//!
+//! Handlers made using [`MakeHandler`][super::MakeHandler] can be combined
+//! by making them with [`Client::make_handler`][crate::client::Client::make_handler]
+//! instead of adding them directly.
+//!
+//! # Example
+//!
+//! Joining a channel once connection registration succeeds:
+//!
+//! ```no_run
+//! use vinezombie::client::{
+//!     auth::Clear, channel::SyncChannels, combinator::Seq, conn::Bidir,
+//!     register::{register_as_bot, Options}, Client, MakeHandler,
+//! };
+//! use vinezombie::{names::cmd::JOIN, string::Arg};
+//!
+//! let options: Options<Clear> = Options::new();
+//! let mut client = Client::new(Bidir(std::io::empty(), std::io::sink()), SyncChannels);
+//! let register = client.make_handler(&register_as_bot(), &options).unwrap();
+//! let register_then_join = Seq::new(register, |result, state, queue| {
+//!     result.ok()?;
+//!     JOIN.make_handler(state, queue, Arg::from_str("#vinezombie")).ok()
+//! });
+//! let (_, joined) = client.add((), register_then_join).unwrap();
+//! while client.needs_run() && client.run().is_ok() {}
+//! if let Ok(Ok(join)) = joined.try_recv() {
+//!     println!("joined {}", join.channel);
+//! }
+//! ```
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    channel::{ChannelSpec, ClosedSender, Sender, SenderRef, Sent},
+    Handler, SelfMadeHandler,
+};
+use crate::{
+    client::{queue::QueueEditGuard, ClientState},
+    ircmsg::ServerMsg,
+};
+use std::ops::ControlFlow;
+
+/// Creates a [`SenderRef`] for a contained handler and passes it to `f`.
+fn with_sender<T, R>(
+    sender: &mut dyn Sender<Value = T>,
+    ended: &mut Option<Sent>,
+    f: impl FnOnce(SenderRef<'_, T>) -> R,
+) -> R {
+    let mut flag = false;
+    f(SenderRef { sender, flag: &mut flag, ended })
+}
+
+/// [`Sender`] that passes values on to another channel.
+struct Forward<'a, 'b, T>(&'a mut SenderRef<'b, T>);
+
+impl<T> Sender for Forward<'_, '_, T> {
+    type Value = T;
+
+    fn send(&mut self, value: T) -> ControlFlow<Sent> {
+        self.0.send(value)
+    }
+
+    fn may_send(&self) -> bool {
+        self.0.may_send()
+    }
+}
+
+/// [`Sender`] that passes values on to another channel, transforming them first.
+struct MapSender<'a, 'b, T, U, F> {
+    channel: &'a mut SenderRef<'b, T>,
+    f: &'a mut F,
+    marker: std::marker::PhantomData<fn(U)>,
+}
+
+impl<'a, 'b, T, U, F: FnMut(U) -> T> MapSender<'a, 'b, T, U, F> {
+    fn new(channel: &'a mut SenderRef<'b, T>, f: &'a mut F) -> Self {
+        MapSender { channel, f, marker: std::marker::PhantomData }
+    }
+}
+
+impl<T, U, F: FnMut(U) -> T> Sender for MapSender<'_, '_, T, U, F> {
+    type Value = U;
+
+    fn send(&mut self, value: U) -> ControlFlow<Sent> {
+        self.channel.send((self.f)(value))
+    }
+
+    fn may_send(&self) -> bool {
+        self.channel.may_send()
+    }
+}
+
+/// [`Sender`] that keeps the last value sent to it.
+struct KeepLast<'a, T>(&'a mut Option<T>);
+
+impl<T> Sender for KeepLast<'_, T> {
+    type Value = T;
+
+    fn send(&mut self, value: T) -> ControlFlow<Sent> {
+        *self.0 = Some(value);
+        ControlFlow::Continue(())
+    }
+}
+
+/// [`Handler`] that transforms the values of another handler.
+pub struct Map<H, F> {
+    handler: H,
+    f: F,
+    ended: Option<Sent>,
+}
+
+impl<H, F> Map<H, F> {
+    /// Creates a handler that yields the values yielded by `handler`, transformed by `f`.
+    pub const fn new(handler: H, f: F) -> Self {
+        Map { handler, f, ended: None }
+    }
+}
+
+impl<T, H, F> Handler for Map<H, F>
+where
+    T: 'static,
+    H: Handler,
+    F: FnMut(H::Value) -> T + 'static + Send,
+{
+    type Value = T;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        let mut sender = MapSender::new(&mut channel, &mut self.f);
+        with_sender(&mut sender, &mut self.ended, |sr| self.handler.handle(msg, state, queue, sr))
+    }
+
+    fn wants_owning(&self) -> bool {
+        self.handler.wants_owning()
+    }
+
+    fn handle_timeout(
+        &mut self,
+        state: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        let mut sender = MapSender::new(&mut channel, &mut self.f);
+        with_sender(&mut sender, &mut self.ended, |sr| {
+            self.handler.handle_timeout(state, queue, sr)
+        })
+    }
+
+    fn cancel(&mut self, mut channel: SenderRef<'_, Self::Value>) {
+        let mut sender = MapSender::new(&mut channel, &mut self.f);
+        with_sender(&mut sender, &mut self.ended, |sr| self.handler.cancel(sr));
+    }
+
+    fn expire(&mut self, mut channel: SenderRef<'_, Self::Value>) {
+        let mut sender = MapSender::new(&mut channel, &mut self.f);
+        with_sender(&mut sender, &mut self.ended, |sr| self.handler.expire(sr));
+    }
+}
+
+/// The stages of a [`Seq`].
+enum SeqStage<A: Handler, B, F> {
+    First { handler: A, value: Option<A::Value>, make: F },
+    Second { handler: B, ended: Option<Sent> },
+    Done,
+}
+
+/// [`Handler`] that runs one handler to completion,
+/// then uses its value to make a second handler.
+///
+/// The first handler's values are not yielded; only the last one is kept.
+/// Once it finishes, the second handler is made by calling a function with that value,
+/// the client state, and the queue, and handles every message after that.
+/// This handler finishes without yielding anything if the first handler
+/// finishes without yielding anything or if the function returns `None`.
+///
+/// If cancelled or expired while the first handler is running,
+/// the first handler is cancelled or expired without a channel.
+pub struct Seq<A: Handler, B, F>(SeqStage<A, B, F>);
+
+impl<A: Handler, B, F> Seq<A, B, F>
+where
+    F: FnOnce(A::Value, &ClientState, QueueEditGuard<'_>) -> Option<B>,
+{
+    /// Creates a handler that runs `first`, then the handler made by `make`.
+    pub const fn new(first: A, make: F) -> Self {
+        Seq(SeqStage::First { handler: first, value: None, make })
+    }
+}
+
+impl<A, B, F> Seq<A, B, F>
+where
+    A: Handler,
+    A::Value: Send,
+    B: Handler,
+    F: FnOnce(A::Value, &ClientState, QueueEditGuard<'_>) -> Option<B> + 'static + Send,
+{
+    fn run(
+        &mut self,
+        state: &mut ClientState,
+        mut queue: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, B::Value>,
+        mut f_a: impl FnMut(
+            &mut A,
+            &mut ClientState,
+            QueueEditGuard<'_>,
+            SenderRef<'_, A::Value>,
+        ) -> ControlFlow<()>,
+        mut f_b: impl FnMut(
+            &mut B,
+            &mut ClientState,
+            QueueEditGuard<'_>,
+            SenderRef<'_, B::Value>,
+        ) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        match &mut self.0 {
+            SeqStage::First { handler, value, .. } => {
+                let mut sender = KeepLast(value);
+                let mut ended = None;
+                let result = with_sender(&mut sender, &mut ended, |sr| {
+                    f_a(handler, state, queue.edit(), sr)
+                });
+                if result.is_continue() {
+                    return ControlFlow::Continue(());
+                }
+                let SeqStage::First { value, make, .. } =
+                    std::mem::replace(&mut self.0, SeqStage::Done)
+                else {
+                    unreachable!()
+                };
+                match value.and_then(|value| make(value, state, queue)) {
+                    Some(handler) => {
+                        self.0 = SeqStage::Second { handler, ended: None };
+                        ControlFlow::Continue(())
+                    }
+                    None => ControlFlow::Break(()),
+                }
+            }
+            SeqStage::Second { handler, ended } => {
+                let mut sender = Forward(&mut channel);
+                with_sender(&mut sender, ended, |sr| f_b(handler, state, queue, sr))
+            }
+            SeqStage::Done => ControlFlow::Break(()),
+        }
+    }
+}
+
+impl<A, B, F> Handler for Seq<A, B, F>
+where
+    A: Handler,
+    A::Value: Send,
+    B: Handler,
+    F: FnOnce(A::Value, &ClientState, QueueEditGuard<'_>) -> Option<B> + 'static + Send,
+{
+    type Value = B::Value;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+        channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        self.run(
+            state,
+            queue,
+            channel,
+            |a, state, queue, sr| a.handle(msg, state, queue, sr),
+            |b, state, queue, sr| b.handle(msg, state, queue, sr),
+        )
+    }
+
+    fn wants_owning(&self) -> bool {
+        match &self.0 {
+            SeqStage::First { handler, .. } => handler.wants_owning(),
+            SeqStage::Second { handler, .. } => handler.wants_owning(),
+            SeqStage::Done => false,
+        }
+    }
+
+    fn handle_timeout(
+        &mut self,
+        state: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+        channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        self.run(
+            state,
+            queue,
+            channel,
+            |a, state, queue, sr| a.handle_timeout(state, queue, sr),
+            |b, state, queue, sr| b.handle_timeout(state, queue, sr),
+        )
+    }
+
+    fn cancel(&mut self, mut channel: SenderRef<'_, Self::Value>) {
+        match &mut self.0 {
+            SeqStage::First { handler, .. } => {
+                with_sender(&mut ClosedSender::default(), &mut None, |sr| handler.cancel(sr));
+            }
+            SeqStage::Second { handler, ended } => {
+                let mut sender = Forward(&mut channel);
+                with_sender(&mut sender, ended, |sr| handler.cancel(sr));
+            }
+            SeqStage::Done => (),
+        }
+    }
+
+    fn expire(&mut self, mut channel: SenderRef<'_, Self::Value>) {
+        match &mut self.0 {
+            SeqStage::First { handler, .. } => {
+                with_sender(&mut ClosedSender::default(), &mut None, |sr| handler.expire(sr));
+            }
+            SeqStage::Second { handler, ended } => {
+                let mut sender = Forward(&mut channel);
+                with_sender(&mut sender, ended, |sr| handler.expire(sr));
+            }
+            SeqStage::Done => (),
+        }
+    }
+}
+
+/// [`Handler`] that runs two handlers at once, finishing when either of them does.
+///
+/// Both handlers yield values to this handler's channel.
+/// Each message is handled by the first handler, then the second.
+/// Once one of them finishes, the other is cancelled without a channel,
+/// and does not handle the message that the first one finished on.
+pub struct Race<A, B> {
+    a: A,
+    b: B,
+    a_ended: Option<Sent>,
+    b_ended: Option<Sent>,
+}
+
+impl<A, B> Race<A, B> {
+    /// Creates a handler that runs `a` and `b` until either of them finishes.
+    pub const fn new(a: A, b: B) -> Self {
+        Race { a, b, a_ended: None, b_ended: None }
+    }
+}
+
+impl<T, A, B> Race<A, B>
+where
+    T: 'static,
+    A: Handler<Value = T>,
+    B: Handler<Value = T>,
+{
+    fn run(
+        &mut self,
+        state: &mut ClientState,
+        mut queue: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, T>,
+        mut f: impl FnMut(
+            &mut dyn Handler<Value = T>,
+            &mut ClientState,
+            QueueEditGuard<'_>,
+            SenderRef<'_, T>,
+        ) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        let mut sender = Forward(&mut channel);
+        let result = with_sender(&mut sender, &mut self.a_ended, |sr| {
+            f(&mut self.a, state, queue.edit(), sr)
+        });
+        if result.is_break() {
+            with_sender(&mut ClosedSender::default(), &mut None, |sr| self.b.cancel(sr));
+            return result;
+        }
+        let result =
+            with_sender(&mut sender, &mut self.b_ended, |sr| f(&mut self.b, state, queue, sr));
+        if result.is_break() {
+            with_sender(&mut ClosedSender::default(), &mut None, |sr| self.a.cancel(sr));
+        }
+        result
+    }
+}
+
+impl<T, A, B> Handler for Race<A, B>
+where
+    T: 'static,
+    A: Handler<Value = T>,
+    B: Handler<Value = T>,
+{
+    type Value = T;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+        channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        self.run(state, queue, channel, |h, state, queue, sr| h.handle(msg, state, queue, sr))
+    }
+
+    fn wants_owning(&self) -> bool {
+        self.a.wants_owning() || self.b.wants_owning()
+    }
+
+    fn handle_timeout(
+        &mut self,
+        state: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+        channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        self.run(state, queue, channel, |h, state, queue, sr| h.handle_timeout(state, queue, sr))
+    }
+
+    fn cancel(&mut self, mut channel: SenderRef<'_, Self::Value>) {
+        let mut sender = Forward(&mut channel);
+        with_sender(&mut sender, &mut self.a_ended, |sr| self.a.cancel(sr));
+        with_sender(&mut sender, &mut self.b_ended, |sr| self.b.cancel(sr));
+    }
+
+    fn expire(&mut self, mut channel: SenderRef<'_, Self::Value>) {
+        let mut sender = Forward(&mut channel);
+        with_sender(&mut sender, &mut self.a_ended, |sr| self.a.expire(sr));
+        with_sender(&mut sender, &mut self.b_ended, |sr| self.b.expire(sr));
+    }
+}
+
+macro_rules! self_made {
+    ($name:ident<$($param:ident),+> $(where $($bound:tt)+)?) => {
+        impl<$($param),+> SelfMadeHandler for $name<$($param),+>
+        where
+            $name<$($param),+>: Handler,
+            <$name<$($param),+> as Handler>::Value: Send,
+            $($($bound)+)?
+        {
+            type Receiver<Spec: ChannelSpec> = Spec::Queue<Self::Value>;
+
+            fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+            fn make_channel<Spec: ChannelSpec>(
+                spec: &Spec,
+            ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+                spec.new_queue()
+            }
+        }
+    };
+}
+
+self_made!(Map<H, F>);
+self_made!(Seq<A, B, F> where A: Handler);
+self_made!(Race<A, B>);
@@ -0,0 +1,119 @@
+use super::{Map, Race, Seq};
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef, SyncChannels},
+        conn::Bidir,
+        queue::QueueEditGuard,
+        Client, ClientState, Handler, SelfMadeHandler,
+    },
+    ircmsg::{ClientMsg, ServerMsg},
+    names::cmd::PING,
+    string::Arg,
+};
+use std::{io::Cursor, ops::ControlFlow};
+
+/// Handler that yields how many messages it has seen, finishing after `until` of them.
+struct Count {
+    seen: usize,
+    until: usize,
+    owning: bool,
+}
+
+fn count(until: usize) -> Count {
+    Count { seen: 0, until, owning: false }
+}
+
+impl Handler for Count {
+    type Value = usize;
+
+    fn handle(
+        &mut self,
+        _: &ServerMsg<'_>,
+        _: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        self.seen += 1;
+        let _ = channel.send(self.seen);
+        if self.seen >= self.until {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+
+    fn wants_owning(&self) -> bool {
+        self.owning
+    }
+
+    fn cancel(&mut self, mut channel: SenderRef<'_, Self::Value>) {
+        let _ = channel.send(0);
+    }
+}
+
+impl SelfMadeHandler for Count {
+    type Receiver<Spec: ChannelSpec> = Spec::Queue<Self::Value>;
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_queue()
+    }
+}
+
+fn client(lines: usize) -> Client<Bidir<Cursor<Vec<u8>>, Vec<u8>>, SyncChannels> {
+    let msgs = ":example.com NOTICE * :hello\r\n".repeat(lines);
+    Client::new(Bidir(Cursor::new(msgs.into_bytes()), Vec::new()), SyncChannels)
+}
+
+#[test]
+fn map() {
+    let mut client = client(5);
+    let (_, recv) = client.add((), Map::new(count(3), |n| n * 10)).unwrap();
+    while client.needs_run() && client.run().is_ok() {}
+    assert_eq!(recv.try_iter().collect::<Vec<_>>(), [10, 20, 30]);
+}
+
+#[test]
+fn seq() {
+    let mut client = client(5);
+    let first = client.make_handler((), count(2)).unwrap();
+    let seq = Seq::new(first, |last, _: &ClientState, mut queue: QueueEditGuard<'_>| {
+        assert_eq!(last, 2);
+        let mut msg = ClientMsg::new(PING);
+        msg.args.edit().add_word(Arg::from_str("second"));
+        queue.push(msg);
+        Some(count(2))
+    });
+    assert!(!seq.wants_owning());
+    let (_, recv) = client.add((), seq).unwrap();
+    while client.needs_run() && client.run().is_ok() {}
+    // Only the second handler yields, starting with the message after the first finished.
+    assert_eq!(recv.try_iter().collect::<Vec<_>>(), [1, 2]);
+    assert_eq!(client.take_conn().1, b"PING second\r\n");
+    // No second handler.
+    let mut client = self::client(5);
+    let seq = Seq::new(count(1), |_, _: &ClientState, _: QueueEditGuard<'_>| None::<Count>);
+    let (_, recv) = client.add((), seq).unwrap();
+    while client.needs_run() && client.run().is_ok() {}
+    assert!(recv.try_iter().next().is_none());
+}
+
+#[test]
+fn race() {
+    let mut client = client(5);
+    let a = Count { owning: true, ..count(3) };
+    let race = Race::new(a, Map::new(count(2), |n| n + 100));
+    assert!(race.wants_owning());
+    let (_, recv) = client.add((), race).unwrap();
+    while client.needs_run() && client.run().is_ok() {}
+    // The loser is cancelled without a channel, so its 0 is not yielded.
+    assert_eq!(recv.try_iter().collect::<Vec<_>>(), [1, 101, 2, 102]);
+    // Cancelling the race cancels both.
+    let mut client = self::client(0);
+    let (id, recv) = client.add((), Race::new(count(3), count(3))).unwrap();
+    client.cancel_handler(id);
+    assert_eq!(recv.try_iter().collect::<Vec<_>>(), [0, 0]);
+}
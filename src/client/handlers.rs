@@ -1,12 +1,31 @@
 //! Useful handler implementations.
 
 mod autoreply;
+mod away;
+mod batch;
+mod chanop;
+mod chathistory;
+mod echo;
+mod expect;
+mod join;
+mod list;
+mod monitor;
+mod nick;
+mod oper;
 mod ping;
+#[cfg(test)]
+mod tests;
 mod track;
+mod wait;
+mod whois;
+mod whox;
 
 use std::ops::ControlFlow;
 
-pub use {autoreply::*, ping::*, track::*};
+pub use {
+    autoreply::*, away::*, batch::*, chanop::*, chathistory::*, echo::*, expect::*, join::*,
+    list::*, monitor::*, nick::*, oper::*, ping::*, track::*, wait::*, whois::*, whox::*,
+};
 
 use super::{cf_discard, channel::SenderRef, queue::QueueEditGuard, Handler, SelfMadeHandler};
 use crate::{
@@ -4,6 +4,7 @@ use crate::{
         queue::QueueEditGuard,
         ClientState, Handler, SelfMadeHandler,
     },
+    info::SessionInfo,
     ircmsg::{ClientMsg, MaybeCtcp, ServerMsg},
     names::cmd::{NOTICE, PRIVMSG},
     string::{Line, Word},
@@ -13,31 +14,49 @@ use crate::{
 #[derive(Clone, Debug)]
 pub struct CtcpVersion {
     /// The response to the `VERSION` query, if non-empty.
+    ///
+    /// Placeholders are substituted using [`SessionInfo::render`] when replying.
     pub version: Line<'static>,
     /// The response to the `SOURCE` query, if non-empty.
     pub source: Line<'static>,
 }
 
 /// Creates a [`CtcpVersion`] handler using the current package's information.
+///
+/// The `VERSION` reply also includes this library's version and a summary of the connection
+/// as described by [`DEFAULT_TEMPLATE`][crate::info::DEFAULT_TEMPLATE].
 #[macro_export]
 macro_rules! ctcp_version_handler {
     () => {{
         use ::vinezombie::string::Line;
-        let version =
-            Line::from_bytes(concat!(env!("CARGO_PKG_NAME"), " v", env!("CARGO_PKG_VERSION")))
-                .unwrap_or_default();
+        let version = Line::from_bytes(concat!(
+            env!("CARGO_PKG_NAME"),
+            " v",
+            env!("CARGO_PKG_VERSION"),
+            " ({vinezombie}; {summary})"
+        ))
+        .unwrap_or_default();
         let source = Line::from_bytes(env!("CARGO_PKG_REPOSITORY")).unwrap_or_default();
         ::vinezombie::client::handlers::CtcpVersion { version, source }
     }};
 }
 
+/// Substitutes placeholders in `template`, if it has any.
+fn render(template: &Line<'static>, state: &ClientState) -> Line<'static> {
+    let Some(utf8) = template.to_utf8().filter(|t| t.contains('{')) else {
+        return template.clone();
+    };
+    let rendered = SessionInfo::capture(state).render(utf8);
+    Line::from_bytes(rendered).unwrap_or_else(|_| template.clone())
+}
+
 impl Handler for CtcpVersion {
     type Value = ();
 
     fn handle(
         &mut self,
         msg: &ServerMsg<'_>,
-        _: &mut ClientState,
+        state: &mut ClientState,
         mut queue: QueueEditGuard<'_>,
         _: crate::client::channel::SenderRef<'_, Self::Value>,
     ) -> std::ops::ControlFlow<()> {
@@ -55,7 +74,8 @@ impl Handler for CtcpVersion {
                 let mut msg = ClientMsg::new(NOTICE);
                 let mut args = msg.args.edit();
                 args.add_word(source.nick.clone().owning());
-                args.add(MaybeCtcp { cmd: Word::from_str("VERSION"), body: self.version.clone() });
+                let body = render(&self.version, state);
+                args.add(MaybeCtcp { cmd: Word::from_str("VERSION"), body });
                 queue.push(msg);
             }
             b"SOURCE" if !self.source.is_empty() => {
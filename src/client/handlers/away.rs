@@ -0,0 +1,201 @@
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef},
+        queue::QueueEditGuard,
+        state::{ISupport, SelfAway},
+        ClientState, Handler, MakeHandler, SelfMadeHandler,
+    },
+    ircmsg::{ClientMsg, ServerMsg, Source},
+    names::{cmd::AWAY, isupport::AWAYLEN},
+    string::{tf::WrapAt, Line},
+};
+use std::ops::ControlFlow;
+
+/// A change to the client's away status.
+///
+/// [`AWAY`] implements [`MakeHandler`] for this type and for `Option<Line>`s,
+/// the latter of which never truncates.
+/// The resulting handler sends `AWAY` and waits for `RPL_UNAWAY` (305) or `RPL_NOWAWAY` (306),
+/// then updates the [`SelfAway`] state and yields the client's new away message,
+/// or `None` if the client is no longer away.
+///
+/// If the server advertises an `AWAYLEN` ISUPPORT token,
+/// away messages longer than it are either truncated or cause [`AwayTooLong`] to be returned
+/// when making the handler.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct SetAway<'a> {
+    /// The away message, or `None` to mark the client as no longer away.
+    pub message: Option<Line<'a>>,
+    /// Whether to truncate away messages that are longer than `AWAYLEN` instead of erroring.
+    ///
+    /// Truncation never cuts a UTF-8 sequence in half.
+    pub truncate: bool,
+}
+
+impl<'a> SetAway<'a> {
+    /// Creates a `SetAway` that marks the client as away with the provided message.
+    pub fn away(message: impl Into<Line<'a>>) -> Self {
+        SetAway { message: Some(message.into()), truncate: false }
+    }
+    /// Creates a `SetAway` that marks the client as no longer away.
+    pub const fn back() -> Self {
+        SetAway { message: None, truncate: false }
+    }
+    /// Sets `self` to truncate overly-long away messages.
+    pub fn truncate(mut self) -> Self {
+        self.truncate = true;
+        self
+    }
+}
+
+/// Error for when an away message is longer than the server's `AWAYLEN`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct AwayTooLong {
+    /// The server's limit from the `AWAYLEN` ISUPPORT token.
+    pub limit: u16,
+    /// The length of the away message in bytes.
+    pub len: usize,
+}
+
+impl std::fmt::Display for AwayTooLong {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "away message is {} bytes long (server limit is {})", self.len, self.limit)
+    }
+}
+
+impl std::error::Error for AwayTooLong {}
+
+/// [`Handler`] that waits for the server to confirm a change in away status.
+struct AwayHandler(Option<Line<'static>>);
+
+impl Handler for AwayHandler {
+    type Value = Option<Line<'static>>;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        let away = match msg.kind.as_str() {
+            // RPL_UNAWAY
+            "305" => None,
+            // RPL_NOWAWAY
+            "306" => Some(self.0.take().unwrap_or_default()),
+            _ => return ControlFlow::Continue(()),
+        };
+        state.insert::<SelfAway>(away.clone());
+        let _ = channel.send(away);
+        ControlFlow::Break(())
+    }
+}
+
+impl<'a> MakeHandler<SetAway<'a>> for AWAY {
+    type Value = Option<Line<'static>>;
+
+    type Error = AwayTooLong;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        mut queue: QueueEditGuard<'_>,
+        set: SetAway<'a>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        let mut message = set.message.map(Line::owning);
+        let limit = state
+            .get::<ISupport>()
+            .and_then(|isupport| isupport.get_parsed(AWAYLEN)?.ok())
+            .map(std::num::NonZeroU16::get);
+        if let (Some(limit), Some(message)) = (limit, &mut message) {
+            if message.len() > limit as usize {
+                if !set.truncate {
+                    return Err(AwayTooLong { limit, len: message.len() });
+                }
+                message.transform(WrapAt { limit: limit as usize, prefer_space: false });
+            }
+        }
+        let mut msg = ClientMsg::new(AWAY);
+        if let Some(message) = &message {
+            msg.args.edit().add(message.clone());
+        }
+        queue.push(msg);
+        Ok(Box::new(AwayHandler(message)))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
+
+impl<'a> MakeHandler<Option<Line<'a>>> for AWAY {
+    type Value = Option<Line<'static>>;
+
+    type Error = AwayTooLong;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        queue: QueueEditGuard<'_>,
+        message: Option<Line<'a>>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        self.make_handler(state, queue, SetAway { message, truncate: false })
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
+
+/// [`Handler`] that yields changes in other users' away status.
+///
+/// Servers send these as `AWAY` messages to clients that have enabled
+/// the `away-notify` capability,
+/// for every user that shares a channel with the client.
+/// This handler yields the source of each such message along with the user's new
+/// away message, or `None` if they are no longer away, until its channel closes.
+/// It does not otherwise keep track of users.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AwayNotify;
+
+impl Handler for AwayNotify {
+    type Value = (Source<'static>, Option<Line<'static>>);
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        _: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        if msg.kind != AWAY {
+            return ControlFlow::Continue(());
+        }
+        let Some(source) = &msg.source else {
+            return ControlFlow::Continue(());
+        };
+        let message = msg.args.split_last().1.cloned().map(Line::owning);
+        let source = source.clone().owning_merged();
+        crate::client::cf_discard(channel.send((source, message)))
+    }
+}
+
+impl SelfMadeHandler for AwayNotify {
+    type Receiver<Spec: ChannelSpec> = Spec::Queue<Self::Value>;
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_queue()
+    }
+}
@@ -0,0 +1,191 @@
+use super::{Handler, SelfMadeHandler};
+use crate::{
+    client::{
+        cf_discard,
+        channel::{ChannelSpec, Sender, SenderRef},
+        queue::QueueEditGuard,
+        ClientState,
+    },
+    ircmsg::ServerMsg,
+    names::cmd::BATCH,
+    string::Arg,
+};
+use std::ops::ControlFlow;
+
+/// A collection of messages that were sent as part of one IRCv3 batch.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Batch {
+    /// The batch's type, such as `chathistory` or `netsplit`.
+    pub kind: Arg<'static>,
+    /// The parameters of the batch following its type.
+    pub params: Vec<Arg<'static>>,
+    /// The messages in this batch, in the order they were received.
+    ///
+    /// This does not include the `BATCH` messages that started or ended
+    /// this batch or any nested batches.
+    pub msgs: Vec<ServerMsg<'static>>,
+    /// Batches that were nested inside this one, in the order they ended.
+    pub nested: Vec<Batch>,
+}
+
+/// Error for when a [`CollectBatches`] handler was cancelled before a batch ended.
+///
+/// Contains whatever was collected of the batch.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct IncompleteBatch(pub Batch);
+
+impl std::fmt::Display for IncompleteBatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} batch did not end ({} messages collected)", self.0.kind, self.0.msgs.len())
+    }
+}
+
+impl std::error::Error for IncompleteBatch {}
+
+#[derive(Debug)]
+struct OpenBatch {
+    /// The reference tag, including the leading `+`.
+    reference: Arg<'static>,
+    /// The index of the batch this one is nested in.
+    parent: Option<usize>,
+    batch: Batch,
+}
+
+/// [`Handler`] that collects the messages of IRCv3 batches, yielding one [`Batch`] each.
+///
+/// Only outermost batches are yielded. Nested batches are available from [`Batch::nested`].
+/// Messages that are not part of a batch are ignored.
+/// If this handler is cancelled, every unfinished outermost batch is yielded
+/// as an [`IncompleteBatch`].
+///
+/// This handler runs until its channel closes.
+/// It is only useful if the `batch` capability is enabled, which it is not by default.
+#[derive(Debug, Default)]
+pub struct CollectBatches {
+    open: Vec<OpenBatch>,
+}
+
+impl CollectBatches {
+    /// Creates a new instance of this handler.
+    pub const fn new() -> Self {
+        CollectBatches { open: Vec::new() }
+    }
+
+    fn find(&self, reference: &[u8]) -> Option<usize> {
+        self.open.iter().rposition(|open| &open.reference.as_bytes()[1..] == reference)
+    }
+
+    fn is_nested_in(&self, mut idx: usize, ancestor: usize) -> bool {
+        while let Some(parent) = self.open[idx].parent {
+            if parent == ancestor {
+                return true;
+            }
+            idx = parent;
+        }
+        false
+    }
+
+    /// Removes an open batch and every batch nested in it.
+    ///
+    /// Returns the removed batch, with unfinished nested batches added to it,
+    /// and the index of its parent.
+    fn close(&mut self, idx: usize) -> (Batch, Option<usize>) {
+        // Nested batches can only start after their parents,
+        // so closing from the end finishes children before their parents.
+        for nested_idx in (idx + 1..self.open.len()).rev() {
+            if !self.is_nested_in(nested_idx, idx) {
+                continue;
+            }
+            let (nested, Some(parent)) = self.close_one(nested_idx) else { unreachable!() };
+            self.open[parent].batch.nested.push(nested);
+        }
+        self.close_one(idx)
+    }
+
+    fn close_one(&mut self, idx: usize) -> (Batch, Option<usize>) {
+        let OpenBatch { parent, batch, .. } = self.open.remove(idx);
+        for open in &mut self.open[idx..] {
+            if let Some(parent) = &mut open.parent {
+                if *parent > idx {
+                    *parent -= 1;
+                }
+            }
+        }
+        (batch, parent)
+    }
+
+    fn start(&mut self, msg: &ServerMsg<'_>) {
+        let Some([reference, kind, params @ ..]) = msg.args.all() else {
+            return;
+        };
+        let parent = msg.tags.get("batch").and_then(|tag| self.find(tag.as_bytes()));
+        let batch = Batch {
+            kind: kind.clone().owning(),
+            params: params.iter().map(|param| param.clone().owning()).collect(),
+            msgs: Vec::new(),
+            nested: Vec::new(),
+        };
+        self.open.push(OpenBatch { reference: reference.clone().owning(), parent, batch });
+    }
+}
+
+impl Handler for CollectBatches {
+    type Value = Result<Batch, IncompleteBatch>;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        _: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        if msg.kind == BATCH {
+            let Some(reference) = msg.args.words().first() else {
+                return ControlFlow::Continue(());
+            };
+            match reference.first() {
+                Some(b'+') if reference.len() > 1 => self.start(msg),
+                Some(b'-') => {
+                    let Some(idx) = self.find(&reference.as_bytes()[1..]) else {
+                        return ControlFlow::Continue(());
+                    };
+                    match self.close(idx) {
+                        (batch, Some(parent)) => self.open[parent].batch.nested.push(batch),
+                        (batch, None) => return cf_discard(channel.send(Ok(batch))),
+                    }
+                }
+                _ => (),
+            }
+        } else if let Some(tag) = msg.tags.get("batch") {
+            if let Some(idx) = self.find(tag.as_bytes()) {
+                self.open[idx].batch.msgs.push(msg.clone().owning());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn wants_owning(&self) -> bool {
+        !self.open.is_empty()
+    }
+
+    fn cancel(&mut self, mut channel: SenderRef<'_, Self::Value>) {
+        while let Some(idx) = self.open.iter().position(|open| open.parent.is_none()) {
+            let (batch, _) = self.close(idx);
+            if channel.send(Err(IncompleteBatch(batch))).is_break() {
+                break;
+            }
+        }
+    }
+}
+
+impl SelfMadeHandler for CollectBatches {
+    type Receiver<Spec: ChannelSpec> = Spec::Queue<Self::Value>;
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_queue()
+    }
+}
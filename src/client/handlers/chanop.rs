@@ -0,0 +1,329 @@
+use super::ExpectError;
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef},
+        queue::QueueEditGuard,
+        state::{ClientSource, ISupport},
+        ClientState, Handler, MakeHandler,
+    },
+    ircmsg::{ClientMsg, ServerMsg, ServerMsgKindRaw},
+    names::{
+        cmd::{INVITE, KICK, TOPIC},
+        isupport::{CHANNELLEN, KICKLEN, TOPICLEN},
+        ISupport as ISupportClass, NameMap, NameValued,
+    },
+    string::{tf::IrcCasemap, Arg, Channel, Line, Nick},
+};
+use std::{num::NonZeroU16, ops::ControlFlow};
+
+/// Error for when an argument is longer than the server allows.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct LimitExceeded {
+    /// The name of the ISUPPORT token that specifies the limit.
+    pub token: &'static str,
+    /// The server's limit.
+    pub limit: u16,
+    /// The length of the argument in bytes.
+    pub len: usize,
+}
+
+impl std::fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "argument is {} bytes long ({} is {})", self.len, self.token, self.limit)
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// Errors if `arg` is longer than the value of `N` in `isupport`, if any.
+fn check_len<N>(isupport: &NameMap<ISupportClass>, name: N, arg: &[u8]) -> Result<(), LimitExceeded>
+where
+    N: for<'a> NameValued<ISupportClass, Value<'a> = NonZeroU16> + std::fmt::Display,
+{
+    let Some(Ok(limit)) = isupport.get_parsed(name) else {
+        return Ok(());
+    };
+    let limit = limit.get();
+    if arg.len() > limit as usize {
+        let token = name.as_raw().to_utf8().unwrap_or_default();
+        return Err(LimitExceeded { token, limit, len: arg.len() });
+    }
+    Ok(())
+}
+
+/// A change to or query of a channel's topic.
+///
+/// [`TOPIC`] implements [`MakeHandler`] for this type.
+/// The resulting handler yields the server's `TOPIC` message if the topic was changed,
+/// `RPL_TOPIC` (332) or `RPL_NOTOPIC` (331) if the topic was queried,
+/// or [`ExpectError::Failed`] with the error numeric if the server refused.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Topic<'a> {
+    /// The channel whose topic to change or query.
+    pub channel: Channel<'a>,
+    /// The new topic, or `None` to query the current topic.
+    ///
+    /// An empty topic clears the channel's topic.
+    pub topic: Option<Line<'a>>,
+}
+
+impl<'a> Topic<'a> {
+    /// Creates a new `Topic` that sets `channel`'s topic.
+    pub fn set(channel: impl Into<Channel<'a>>, topic: impl Into<Line<'a>>) -> Self {
+        Topic { channel: channel.into(), topic: Some(topic.into()) }
+    }
+    /// Creates a new `Topic` that queries `channel`'s topic.
+    pub fn query(channel: impl Into<Channel<'a>>) -> Self {
+        Topic { channel: channel.into(), topic: None }
+    }
+    /// Checks `self` against the server's `CHANNELLEN` and `TOPICLEN`.
+    pub fn check(&self, isupport: &NameMap<ISupportClass>) -> Result<(), LimitExceeded> {
+        check_len(isupport, CHANNELLEN, &self.channel)?;
+        if let Some(topic) = &self.topic {
+            check_len(isupport, TOPICLEN, topic)?;
+        }
+        Ok(())
+    }
+}
+
+/// A removal of a user from a channel.
+///
+/// [`KICK`] implements [`MakeHandler`] for this type.
+/// The resulting handler yields the server's `KICK` message,
+/// or [`ExpectError::Failed`] with the error numeric if the server refused.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Kick<'a> {
+    /// The channel to remove the user from.
+    pub channel: Channel<'a>,
+    /// The nick of the user to remove.
+    pub nick: Nick<'a>,
+    /// The reason for the kick, if any.
+    pub reason: Option<Line<'a>>,
+}
+
+impl<'a> Kick<'a> {
+    /// Creates a new `Kick` without a reason.
+    pub fn new(channel: impl Into<Channel<'a>>, nick: Nick<'a>) -> Self {
+        Kick { channel: channel.into(), nick, reason: None }
+    }
+    /// Sets the reason for the kick.
+    pub fn with_reason(mut self, reason: impl Into<Line<'a>>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+    /// Checks `self` against the server's `CHANNELLEN` and `KICKLEN`.
+    pub fn check(&self, isupport: &NameMap<ISupportClass>) -> Result<(), LimitExceeded> {
+        check_len(isupport, CHANNELLEN, &self.channel)?;
+        if let Some(reason) = &self.reason {
+            check_len(isupport, KICKLEN, reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// An invitation of a user to a channel.
+///
+/// [`INVITE`] implements [`MakeHandler`] for this type.
+/// The resulting handler yields `RPL_INVITING` (341),
+/// or [`ExpectError::Failed`] with the error numeric if the server refused.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Invite<'a> {
+    /// The nick of the user to invite.
+    pub nick: Nick<'a>,
+    /// The channel to invite the user to.
+    pub channel: Channel<'a>,
+}
+
+impl<'a> Invite<'a> {
+    /// Creates a new `Invite`.
+    pub fn new(nick: Nick<'a>, channel: impl Into<Channel<'a>>) -> Self {
+        Invite { nick, channel: channel.into() }
+    }
+    /// Checks `self` against the server's `CHANNELLEN`.
+    pub fn check(&self, isupport: &NameMap<ISupportClass>) -> Result<(), LimitExceeded> {
+        check_len(isupport, CHANNELLEN, &self.channel)
+    }
+}
+
+/// Message kinds that the channel management handlers wait for,
+/// paired with the index of the argument that contains the channel name.
+type Replies = &'static [(&'static str, usize)];
+
+/// Errors shared by every channel management command.
+const COMMON_ERRORS: Replies = &[
+    // ERR_NOSUCHCHANNEL
+    ("403", 1),
+    // ERR_NOTONCHANNEL
+    ("442", 1),
+    // ERR_CHANOPRIVSNEEDED
+    ("482", 1),
+];
+
+/// [`Handler`] that waits for either a reply or an error about one channel.
+///
+/// Command replies are only accepted if they come from the client.
+struct AwaitChannelReply {
+    casemap: IrcCasemap,
+    channel: Arg<'static>,
+    success: Replies,
+    failure: Replies,
+}
+
+impl AwaitChannelReply {
+    fn new(state: &ClientState, channel: Arg<'static>, success: Replies, failure: Replies) -> Self {
+        let casemap = state.get::<ISupport>().map(IrcCasemap::from_isupport).unwrap_or_default();
+        AwaitChannelReply { casemap, channel, success, failure }
+    }
+
+    /// Returns `true` if `msg` is one of `kinds` and is about the channel.
+    fn matches(&self, msg: &ServerMsg<'_>, kinds: Replies) -> bool {
+        let Some((_, idx)) = kinds.iter().find(|(kind, _)| msg.kind.as_str() == *kind) else {
+            return false;
+        };
+        msg.args.iter().nth(*idx).is_some_and(|arg| arg.eq_ignore_case(&self.channel, self.casemap))
+    }
+}
+
+impl Handler for AwaitChannelReply {
+    type Value = Result<ServerMsg<'static>, ExpectError>;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        let result = if self.matches(msg, self.success) {
+            if let ServerMsgKindRaw::Cmd(_) = &msg.kind {
+                let is_self = match (state.get::<ClientSource>(), &msg.source) {
+                    (Some(me), Some(source)) => source.nick.eq_ignore_case(&me.nick, self.casemap),
+                    _ => true,
+                };
+                if !is_self {
+                    return ControlFlow::Continue(());
+                }
+            }
+            Ok(msg.clone().owning())
+        } else if self.matches(msg, self.failure) || self.matches(msg, COMMON_ERRORS) {
+            Err(ExpectError::Failed(Box::new(msg.clone().owning())))
+        } else {
+            return ControlFlow::Continue(());
+        };
+        let _ = channel.send(result);
+        ControlFlow::Break(())
+    }
+
+    fn expire(&mut self, mut channel: SenderRef<'_, Self::Value>) {
+        let _ = channel.send(Err(ExpectError::TimedOut));
+    }
+}
+
+impl<'a> MakeHandler<Topic<'a>> for TOPIC {
+    type Value = Result<ServerMsg<'static>, ExpectError>;
+
+    type Error = LimitExceeded;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        mut queue: QueueEditGuard<'_>,
+        topic: Topic<'a>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        if let Some(isupport) = state.get::<ISupport>() {
+            topic.check(isupport)?;
+        }
+        let chan = Arg::from(topic.channel).owning();
+        let mut msg = ClientMsg::new(TOPIC);
+        let mut args = msg.args.edit();
+        args.add_word(chan.clone());
+        if let Some(topic) = topic.topic {
+            args.add(topic.owning());
+        }
+        queue.push(msg);
+        // RPL_NOTOPIC and RPL_TOPIC.
+        let success = &[("TOPIC", 0), ("331", 1), ("332", 1)];
+        Ok(Box::new(AwaitChannelReply::new(state, chan, success, &[])))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
+
+impl<'a> MakeHandler<Kick<'a>> for KICK {
+    type Value = Result<ServerMsg<'static>, ExpectError>;
+
+    type Error = LimitExceeded;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        mut queue: QueueEditGuard<'_>,
+        kick: Kick<'a>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        if let Some(isupport) = state.get::<ISupport>() {
+            kick.check(isupport)?;
+        }
+        let chan = Arg::from(kick.channel).owning();
+        let mut msg = ClientMsg::new(KICK);
+        let mut args = msg.args.edit();
+        args.add_word(chan.clone());
+        args.add_word(kick.nick.owning());
+        if let Some(reason) = kick.reason {
+            args.add(reason.owning());
+        }
+        queue.push(msg);
+        // ERR_USERNOTINCHANNEL
+        let failure = &[("441", 2)];
+        Ok(Box::new(AwaitChannelReply::new(state, chan, &[("KICK", 0)], failure)))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
+
+impl<'a> MakeHandler<Invite<'a>> for INVITE {
+    type Value = Result<ServerMsg<'static>, ExpectError>;
+
+    type Error = LimitExceeded;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        mut queue: QueueEditGuard<'_>,
+        invite: Invite<'a>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        if let Some(isupport) = state.get::<ISupport>() {
+            invite.check(isupport)?;
+        }
+        let chan = Arg::from(invite.channel).owning();
+        let mut msg = ClientMsg::new(INVITE);
+        let mut args = msg.args.edit();
+        args.add_word(invite.nick.owning());
+        args.add_word(chan.clone());
+        queue.push(msg);
+        // RPL_INVITING
+        let success = &[("341", 2)];
+        // ERR_USERONCHANNEL
+        let failure = &[("443", 2)];
+        Ok(Box::new(AwaitChannelReply::new(state, chan, success, failure)))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
@@ -0,0 +1,251 @@
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef},
+        queue::QueueEditGuard,
+        state::{Caps, ISupport},
+        ClientState, Handler, MakeHandler, NoHandler,
+    },
+    ircmsg::{ClientMsg, ServerMsg},
+    names::{
+        cap::DRAFT_CHATHISTORY,
+        cmd::{BATCH, CHATHISTORY, FAIL},
+        Name,
+    },
+    string::{tf::IrcCasemap, Arg, Line, NoNul},
+};
+use std::{ops::ControlFlow, time::SystemTime};
+
+/// One end of the range of messages requested by a [`HistoryRequest`].
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum HistoryBound<'a> {
+    /// No bound, sent as `*`.
+    ///
+    /// This is only meaningful for [`HistoryQuery::Latest`].
+    Any,
+    /// The message with the provided `msgid`.
+    MsgId(Arg<'a>),
+    /// The provided time, as in the `server-time` tag.
+    ///
+    /// This is sent with millisecond precision.
+    Timestamp(SystemTime),
+}
+
+impl HistoryBound<'_> {
+    fn to_arg(&self) -> Option<Arg<'static>> {
+        let string = match self {
+            HistoryBound::Any => return Some(Arg::from_str("*")),
+            HistoryBound::MsgId(id) => format!("msgid={id}"),
+            HistoryBound::Timestamp(time) => {
+                format!("timestamp={}", crate::ircmsg::format_server_time(*time)?)
+            }
+        };
+        Arg::from_bytes(string).ok()
+    }
+}
+
+/// The `CHATHISTORY` subcommands for requesting messages and their bounds.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum HistoryQuery<'a> {
+    /// The most recent messages, optionally only those after the bound.
+    Latest(HistoryBound<'a>),
+    /// The messages before the bound.
+    Before(HistoryBound<'a>),
+    /// The messages after the bound.
+    After(HistoryBound<'a>),
+    /// The messages around the bound.
+    Around(HistoryBound<'a>),
+    /// The messages between the bounds, in either order.
+    Between(HistoryBound<'a>, HistoryBound<'a>),
+}
+
+/// A request for the message history of a target using `draft/chathistory`.
+///
+/// [`CHATHISTORY`] implements [`MakeHandler`] for this type,
+/// sending the request and yielding either the messages in the reply batch
+/// or a [`HistoryFail`].
+/// Making the handler fails with [`NoHandler`] if the `draft/chathistory` capability
+/// is not enabled in the client's [`Caps`].
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct HistoryRequest<'a> {
+    /// The channel or nick whose history to request.
+    pub target: Arg<'a>,
+    /// Which messages to request.
+    pub query: HistoryQuery<'a>,
+    /// The maximum number of messages to request.
+    ///
+    /// Servers advertise their own maximum in the `CHATHISTORY` ISUPPORT token.
+    pub limit: u16,
+}
+
+impl<'a> HistoryRequest<'a> {
+    /// Creates a request for up to `limit` of the latest messages sent to `target`.
+    pub fn latest(target: Arg<'a>, limit: u16) -> Self {
+        HistoryRequest { target, query: HistoryQuery::Latest(HistoryBound::Any), limit }
+    }
+    fn to_msg(&self) -> Option<ClientMsg<'static>> {
+        let (subcmd, bound, second) = match &self.query {
+            HistoryQuery::Latest(b) => ("LATEST", b, None),
+            HistoryQuery::Before(b) => ("BEFORE", b, None),
+            HistoryQuery::After(b) => ("AFTER", b, None),
+            HistoryQuery::Around(b) => ("AROUND", b, None),
+            HistoryQuery::Between(a, b) => ("BETWEEN", a, Some(b)),
+        };
+        let mut msg = ClientMsg::new(CHATHISTORY);
+        let mut args = msg.args.edit();
+        args.add_literal(subcmd);
+        args.add_word(self.target.clone().owning());
+        args.add_word(bound.to_arg()?);
+        if let Some(second) = second {
+            args.add_word(second.to_arg()?);
+        }
+        args.add_word(Arg::from_bytes(self.limit.to_string()).ok()?);
+        Some(msg)
+    }
+}
+
+/// Error yielded by the `CHATHISTORY` handler if the server sent a `FAIL CHATHISTORY`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HistoryFail {
+    /// The failure code, such as `INVALID_TARGET`.
+    pub code: Arg<'static>,
+    /// The context for the failure, typically the parameters that caused it.
+    pub context: Vec<Arg<'static>>,
+    /// The human-readable description of the failure.
+    pub description: Line<'static>,
+}
+
+impl std::fmt::Display for HistoryFail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "chathistory failed ({}): {}", self.code, self.description)
+    }
+}
+
+impl std::error::Error for HistoryFail {}
+
+/// [`Handler`] that collects the reply to one `CHATHISTORY` request.
+struct HistoryHandler {
+    casemap: IrcCasemap,
+    target: Arg<'static>,
+    label: Option<NoNul<'static>>,
+    /// The reference tags of the reply batch and the batches nested in it.
+    refs: Vec<Arg<'static>>,
+    msgs: Vec<ServerMsg<'static>>,
+}
+
+impl HistoryHandler {
+    fn is_reply_start(&self, msg: &ServerMsg<'_>) -> bool {
+        let Some([_, kind, target, ..]) = msg.args.all() else {
+            return false;
+        };
+        if kind.as_bytes() != b"chathistory" {
+            return false;
+        }
+        match &self.label {
+            Some(label) => msg.tags.get("label") == Some(label),
+            None => target.eq_ignore_case(&self.target, self.casemap),
+        }
+    }
+}
+
+impl Handler for HistoryHandler {
+    type Value = Result<Vec<ServerMsg<'static>>, HistoryFail>;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        _: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        if self.refs.is_empty() {
+            if msg.kind == FAIL {
+                let (args, description) = msg.args.split_last();
+                let [cmd, code, context @ ..] = args else {
+                    return ControlFlow::Continue(());
+                };
+                if cmd.as_bytes() != b"CHATHISTORY" {
+                    return ControlFlow::Continue(());
+                }
+                let fail = HistoryFail {
+                    code: code.clone().owning(),
+                    context: context.iter().map(|arg| arg.clone().owning()).collect(),
+                    description: description.cloned().unwrap_or_default().owning(),
+                };
+                let _ = channel.send(Err(fail));
+                return ControlFlow::Break(());
+            }
+            if msg.kind == BATCH && self.is_reply_start(msg) {
+                let Some((b'+', name)) = msg.args.words()[0].as_bytes().split_first() else {
+                    return ControlFlow::Continue(());
+                };
+                if let Ok(name) = Arg::from_bytes(name) {
+                    self.refs.push(name.owning());
+                }
+            }
+            return ControlFlow::Continue(());
+        }
+        let in_reply = msg.tags.get("batch").is_some_and(|tag| {
+            self.refs.iter().any(|reference| reference.as_bytes() == tag.as_bytes())
+        });
+        if msg.kind == BATCH {
+            let Some(reference) = msg.args.words().first() else {
+                return ControlFlow::Continue(());
+            };
+            let (sign, name) = reference.as_bytes().split_at(1);
+            if sign == b"-" && name == self.refs[0].as_bytes() {
+                let _ = channel.send(Ok(std::mem::take(&mut self.msgs)));
+                return ControlFlow::Break(());
+            }
+            if sign == b"+" && in_reply {
+                if let Ok(name) = Arg::from_bytes(name) {
+                    self.refs.push(name.owning());
+                }
+            }
+        }
+        if in_reply {
+            self.msgs.push(msg.clone().owning());
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn wants_owning(&self) -> bool {
+        !self.refs.is_empty()
+    }
+}
+
+impl<'a> MakeHandler<HistoryRequest<'a>> for CHATHISTORY {
+    type Value = Result<Vec<ServerMsg<'static>>, HistoryFail>;
+
+    type Error = NoHandler;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        mut queue: QueueEditGuard<'_>,
+        request: HistoryRequest<'a>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        let enabled = state
+            .get::<Caps>()
+            .and_then(|caps| caps.get_extra_raw(DRAFT_CHATHISTORY.as_raw()).copied())
+            .unwrap_or_default();
+        if !enabled {
+            return Err(NoHandler);
+        }
+        let msg = request.to_msg().ok_or(NoHandler)?;
+        let label = queue.push_labeled(msg);
+        if let Some(label) = &label {
+            queue.route_label(label.clone());
+        }
+        let casemap = state.get::<ISupport>().map(IrcCasemap::from_isupport).unwrap_or_default();
+        let target = request.target.owning();
+        Ok(Box::new(HistoryHandler { casemap, target, label, refs: Vec::new(), msgs: Vec::new() }))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
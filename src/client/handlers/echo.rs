@@ -0,0 +1,162 @@
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef},
+        queue::QueueEditGuard,
+        state::{Caps, ClientSource, ISupport},
+        ClientState, Handler, MakeHandler,
+    },
+    ircmsg::{ClientMsg, ServerMsg, ServerMsgKindRaw},
+    names::{
+        cap::ECHO_MESSAGE,
+        cmd::{NOTICE, PRIVMSG, TAGMSG},
+        Name,
+    },
+    string::{tf::IrcCasemap, Cmd, NoNul},
+};
+use std::ops::ControlFlow;
+
+/// Returns `true` if `msg` is a message the client sent to itself or had echoed back.
+///
+/// Only `PRIVMSG`, `NOTICE`, and `TAGMSG` are considered.
+/// The message's source nick is compared against the [`ClientSource`]
+/// using the server's casemapping.
+/// Messages without a source or received before the client source is known
+/// are never considered echoes.
+///
+/// This cannot tell which sent message an echo is for.
+/// Use [`EchoAwait`] for that.
+pub fn is_echo(msg: &ServerMsg<'_>, state: &ClientState) -> bool {
+    let ServerMsgKindRaw::Cmd(cmd) = &msg.kind else {
+        return false;
+    };
+    if !is_echoed_cmd(cmd) {
+        return false;
+    }
+    let (Some(me), Some(source)) = (state.get::<ClientSource>(), &msg.source) else {
+        return false;
+    };
+    let casemap = state.get::<ISupport>().map(IrcCasemap::from_isupport).unwrap_or_default();
+    source.nick.eq_ignore_case(&me.nick, casemap)
+}
+
+fn is_echoed_cmd(cmd: &Cmd<'_>) -> bool {
+    *cmd == PRIVMSG || *cmd == NOTICE || *cmd == TAGMSG
+}
+
+/// Error returned when creating an [`EchoAwait`] handler
+/// if there is no way to confirm delivery of the message.
+///
+/// This happens if `echo-message` is not enabled and the queue has no labeler.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct NoEchoError;
+
+impl std::fmt::Display for NoEchoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "neither echo-message nor a labeler is in use")
+    }
+}
+
+impl std::error::Error for NoEchoError {}
+
+/// Sends a message and waits for the server to confirm it.
+///
+/// [`EchoAwait`] implements [`MakeHandler`] for [`ClientMsg`]s,
+/// yielding the server's response to the message.
+///
+/// If the queue has a labeler, the message is labeled and
+/// the first message with that label is yielded.
+/// This is either the echo, an `ACK` if `echo-message` is not enabled,
+/// the `BATCH` start message of a labeled batch, or an error reply.
+/// Matching by label still works if the server rewrites the message in the echo.
+///
+/// Otherwise, `echo-message` must be enabled, and the first echo (see [`is_echo`])
+/// with the same command, (casemapped) target, and remaining arguments is yielded.
+/// Servers that alter the text of echoed messages will cause this to never complete,
+/// as will sending a message that the server rejects.
+/// Either way, the echo's `msgid` tag can be used to refer to the message later.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct EchoAwait;
+
+/// [`Handler`] for [`EchoAwait`].
+struct EchoAwaitHandler {
+    casemap: IrcCasemap,
+    label: Option<NoNul<'static>>,
+    sent: ClientMsg<'static>,
+}
+
+impl EchoAwaitHandler {
+    fn is_echo_of_sent(&self, msg: &ServerMsg<'_>, state: &ClientState) -> bool {
+        if !matches!(&msg.kind, ServerMsgKindRaw::Cmd(cmd) if *cmd == self.sent.cmd) {
+            return false;
+        }
+        if !is_echo(msg, state) || msg.args.len() != self.sent.args.len() {
+            return false;
+        }
+        let mut args = msg.args.iter();
+        let mut sent = self.sent.args.iter();
+        let same_target = match (args.next(), sent.next()) {
+            (Some(target), Some(sent)) => target.eq_ignore_case(sent.as_bytes(), self.casemap),
+            _ => true,
+        };
+        same_target && args.zip(sent).all(|(arg, sent)| arg.as_bytes() == sent.as_bytes())
+    }
+}
+
+impl Handler for EchoAwaitHandler {
+    type Value = ServerMsg<'static>;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        let is_response = if let Some(label) = &self.label {
+            msg.tags.get("label") == Some(label)
+        } else {
+            self.is_echo_of_sent(msg, state)
+        };
+        if !is_response {
+            return ControlFlow::Continue(());
+        }
+        let _ = channel.send(msg.clone().owning());
+        ControlFlow::Break(())
+    }
+}
+
+impl<'a> MakeHandler<ClientMsg<'a>> for EchoAwait {
+    type Value = ServerMsg<'static>;
+
+    type Error = NoEchoError;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        mut queue: QueueEditGuard<'_>,
+        msg: ClientMsg<'a>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        let echo_message = state
+            .get::<Caps>()
+            .and_then(|caps| caps.get_extra_raw(ECHO_MESSAGE.as_raw()).copied())
+            .unwrap_or_default();
+        if !echo_message && !queue.is_using_labeler() {
+            return Err(NoEchoError);
+        }
+        let msg = msg.owning();
+        let label = queue.push_labeled(msg.clone());
+        if let Some(label) = &label {
+            queue.route_label(label.clone());
+        }
+        let casemap = state.get::<ISupport>().map(IrcCasemap::from_isupport).unwrap_or_default();
+        Ok(Box::new(EchoAwaitHandler { casemap, label, sent: msg }))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef},
+        queue::QueueEditGuard,
+        ClientState, Handler, MakeHandler,
+    },
+    ircmsg::{ClientMsg, Numeric, ServerMsg, ServerMsgKindRaw},
+    names::{
+        cmd::{BATCH, FAIL},
+        ClientMsgKind, Name,
+    },
+    string::{Arg, Cmd, NoNul},
+};
+use std::ops::ControlFlow;
+
+/// Sends one message and waits for one of a set of numeric replies.
+///
+/// [`ExpectReply`] implements [`MakeHandler`] for `()`,
+/// yielding the first reply with one of the `success` numerics,
+/// or an [`ExpectError`] if a reply with one of the `failure` numerics
+/// or a `FAIL` for the sent command is received first.
+/// This is intended for simple queries such as `TIME` or `ADMIN`
+/// whose replies do not need to be collected into anything more useful;
+/// [`ExpectNumeric::expect`] is a convenient way to create one.
+///
+/// If the queue has a labeler, the message is labeled and only the response
+/// with that label is considered, including the contents of a labeled batch.
+/// Otherwise, the first matching reply from the server is yielded,
+/// even if it is actually a reply to some other message.
+///
+/// This handler never finishes on its own if the server does not reply.
+/// Consider adding it using [`Client::add_with_timeout`][crate::client::Client::add_with_timeout],
+/// in which case [`ExpectError::TimedOut`] is yielded if the timeout passes.
+#[derive(Clone, Debug)]
+pub struct ExpectReply {
+    /// The message to send.
+    pub msg: ClientMsg<'static>,
+    /// The numerics to yield as successful replies.
+    pub success: Vec<Numeric>,
+    /// The numerics to yield as errors.
+    pub failure: Vec<Numeric>,
+}
+
+impl ExpectReply {
+    /// Creates a new `ExpectReply` that sends `msg`.
+    pub fn new(
+        msg: ClientMsg<'static>,
+        success: impl IntoIterator<Item = Numeric>,
+        failure: impl IntoIterator<Item = Numeric>,
+    ) -> Self {
+        ExpectReply {
+            msg,
+            success: success.into_iter().collect(),
+            failure: failure.into_iter().collect(),
+        }
+    }
+    /// Adds another numeric to yield as a successful reply.
+    pub fn or(mut self, numeric: Numeric) -> Self {
+        self.success.push(numeric);
+        self
+    }
+    /// Adds a numeric to yield as an error.
+    pub fn fail_on(mut self, numeric: Numeric) -> Self {
+        self.failure.push(numeric);
+        self
+    }
+}
+
+/// Shorthand for creating an [`ExpectReply`] from a client message type.
+///
+/// This is implemented for every [`Name`] of a client message,
+/// allowing things like `client.add(TIME.expect(rpl_time), ())`.
+pub trait ExpectNumeric: Name<ClientMsgKind> {
+    /// Returns an [`ExpectReply`] that sends a message of this type with no arguments
+    /// and yields the first reply with the provided numeric.
+    fn expect(self, numeric: Numeric) -> ExpectReply {
+        ExpectReply::new(ClientMsg::new(self), [numeric], [])
+    }
+}
+
+impl<T: Name<ClientMsgKind>> ExpectNumeric for T {}
+
+/// Error yielded by the [`ExpectReply`] handler.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ExpectError {
+    /// The server replied with one of the failure numerics or a `FAIL` for the sent command.
+    Failed(Box<ServerMsg<'static>>),
+    /// The labeled response to the message did not contain any of the expected numerics.
+    NoReply,
+    /// The handler's deadline passed before the server replied.
+    TimedOut,
+}
+
+impl std::fmt::Display for ExpectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpectError::Failed(msg) => write!(f, "server replied with {}", msg.kind.as_str()),
+            ExpectError::NoReply => write!(f, "server response did not contain a reply"),
+            ExpectError::TimedOut => write!(f, "timed out waiting for a reply"),
+        }
+    }
+}
+
+impl std::error::Error for ExpectError {}
+
+/// [`Handler`] for [`ExpectReply`].
+struct ExpectReplyHandler {
+    cmd: Cmd<'static>,
+    success: Vec<Numeric>,
+    failure: Vec<Numeric>,
+    label: Option<NoNul<'static>>,
+    /// The reference tag of the labeled batch, including the leading `+`.
+    batch: Option<Arg<'static>>,
+}
+
+impl ExpectReplyHandler {
+    /// Returns whether `msg` is part of the labeled response,
+    /// or `None` if the labeled response has ended.
+    fn is_response(&mut self, msg: &ServerMsg<'_>) -> Option<bool> {
+        let Some(label) = &self.label else {
+            return Some(true);
+        };
+        let first = msg.args.words().first();
+        if let Some(batch) = &self.batch {
+            let reference = &batch.as_bytes()[1..];
+            if msg.tags.get("batch").is_some_and(|tag| tag.as_bytes() == reference) {
+                return Some(true);
+            }
+            let is_end =
+                msg.kind == BATCH && first.and_then(|r| r.strip_prefix(b"-")) == Some(reference);
+            return (!is_end).then_some(false);
+        }
+        if msg.tags.get("label") != Some(label) {
+            return Some(false);
+        }
+        if msg.kind == BATCH {
+            if let Some(reference) = first.filter(|r| r.first() == Some(&b'+')) {
+                self.batch = Some(reference.clone().owning());
+                return Some(false);
+            }
+        }
+        Some(true)
+    }
+
+    /// Returns `true` if `msg` is a `FAIL` for the sent command.
+    fn is_fail(&self, msg: &ServerMsg<'_>) -> bool {
+        msg.kind == FAIL
+            && msg.args.words().first().is_some_and(|cmd| cmd.as_bytes() == self.cmd.as_bytes())
+    }
+}
+
+impl Handler for ExpectReplyHandler {
+    type Value = Result<ServerMsg<'static>, ExpectError>;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        _: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        let Some(is_response) = self.is_response(msg) else {
+            let _ = channel.send(Err(ExpectError::NoReply));
+            return ControlFlow::Break(());
+        };
+        if !is_response {
+            return ControlFlow::Continue(());
+        }
+        let result = match &msg.kind {
+            ServerMsgKindRaw::Numeric(num) if self.success.contains(num) => {
+                Ok(msg.clone().owning())
+            }
+            ServerMsgKindRaw::Numeric(num) if self.failure.contains(num) => {
+                Err(ExpectError::Failed(Box::new(msg.clone().owning())))
+            }
+            ServerMsgKindRaw::Cmd(_) if self.is_fail(msg) => {
+                Err(ExpectError::Failed(Box::new(msg.clone().owning())))
+            }
+            // A labeled response that isn't a batch is the only response.
+            _ if self.label.is_some() && self.batch.is_none() => Err(ExpectError::NoReply),
+            _ => return ControlFlow::Continue(()),
+        };
+        let _ = channel.send(result);
+        ControlFlow::Break(())
+    }
+
+    fn expire(&mut self, mut channel: SenderRef<'_, Self::Value>) {
+        let _ = channel.send(Err(ExpectError::TimedOut));
+    }
+}
+
+impl MakeHandler<()> for ExpectReply {
+    type Value = Result<ServerMsg<'static>, ExpectError>;
+
+    type Error = std::convert::Infallible;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        _: &ClientState,
+        mut queue: QueueEditGuard<'_>,
+        _: (),
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        let cmd = self.msg.cmd.clone().owning();
+        let label = queue.push_labeled(self.msg);
+        if let Some(label) = &label {
+            queue.route_label(label.clone());
+        }
+        Ok(Box::new(ExpectReplyHandler {
+            cmd,
+            success: self.success,
+            failure: self.failure,
+            label,
+            batch: None,
+        }))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef},
+        queue::QueueEditGuard,
+        state::{ClientSource, ISupport},
+        ClientState, Handler, MakeHandler,
+    },
+    error::ParseError,
+    ircmsg::{ClientMsg, NamesMember, ServerMsg, ServerMsgKindRaw, Source},
+    names::cmd::JOIN,
+    state::{ModeSet, ServerChanModes, StatusModes},
+    string::{tf::IrcCasemap, Arg, Channel, Line, Word},
+};
+use std::ops::ControlFlow;
+
+/// The result of successfully joining a channel.
+///
+/// [`JOIN`] implements [`MakeHandler`] for channel names and pairs of a channel name and key,
+/// joining that channel and yielding either this or a [`JoinError`]
+/// once the server has sent the channel's member list.
+/// Channel names may be either [`Arg`]s or [`Channel`]s,
+/// the latter of which are first [validated][Channel::validate] against the server's ISUPPORT.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Join {
+    /// The name of the channel, as spelled by the server.
+    pub channel: Arg<'static>,
+    /// The channel's topic, from `RPL_TOPIC` (332).
+    pub topic: Option<Line<'static>>,
+    /// Who set the topic, from `RPL_TOPICWHOTIME` (333).
+    ///
+    /// This is usually a nick, but may also be a full source.
+    pub topic_setter: Option<Word<'static>>,
+    /// When the topic was set as a Unix timestamp, from `RPL_TOPICWHOTIME` (333).
+    pub topic_time: Option<u64>,
+    /// The channel's members along with their status modes,
+    /// from `RPL_NAMREPLY` (353).
+    ///
+    /// Status prefixes are parsed using the server's `PREFIX` ISUPPORT token.
+    /// Members only have usernames and hostnames if `userhost-in-names` is enabled.
+    pub members: Vec<(ModeSet, Source<'static>)>,
+}
+
+impl Join {
+    /// Creates a new empty result for the provided channel.
+    pub fn new(channel: Arg<'static>) -> Self {
+        Join { channel, topic: None, topic_setter: None, topic_time: None, members: Vec::new() }
+    }
+}
+
+/// Error yielded by the `JOIN` handler if the server refused to let the client join.
+///
+/// Each variant contains the name of the channel.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum JoinError {
+    /// `ERR_NOSUCHCHANNEL` (403): The channel name is invalid.
+    NoSuchChannel(Arg<'static>),
+    /// `ERR_CHANNELISFULL` (471): The channel has reached its user limit.
+    ChannelIsFull(Arg<'static>),
+    /// `ERR_INVITEONLYCHAN` (473): The channel is invite-only.
+    InviteOnly(Arg<'static>),
+    /// `ERR_BANNEDFROMCHAN` (474): The client is banned from the channel.
+    Banned(Arg<'static>),
+    /// `ERR_BADCHANNELKEY` (475): The provided key was missing or wrong.
+    BadKey(Arg<'static>),
+}
+
+impl JoinError {
+    /// Returns the name of the channel that could not be joined.
+    pub fn channel(&self) -> &Arg<'static> {
+        match self {
+            JoinError::NoSuchChannel(c)
+            | JoinError::ChannelIsFull(c)
+            | JoinError::InviteOnly(c)
+            | JoinError::Banned(c)
+            | JoinError::BadKey(c) => c,
+        }
+    }
+}
+
+impl std::fmt::Display for JoinError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            JoinError::NoSuchChannel(_) => "no such channel",
+            JoinError::ChannelIsFull(_) => "channel is full",
+            JoinError::InviteOnly(_) => "channel is invite-only",
+            JoinError::Banned(_) => "banned from channel",
+            JoinError::BadKey(_) => "bad channel key",
+        };
+        write!(f, "cannot join {}: {reason}", self.channel())
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/// [`Handler`] that waits for the result of one `JOIN`.
+struct JoinHandler {
+    casemap: IrcCasemap,
+    status: StatusModes,
+    joined: bool,
+    reply: Join,
+}
+
+impl JoinHandler {
+    fn is_target(&self, arg: Option<&Arg<'_>>) -> bool {
+        arg.is_some_and(|arg| arg.eq_ignore_case(&self.reply.channel, self.casemap))
+    }
+
+    fn add_members(&mut self, names: &Line<'_>) {
+        for name in names.as_bytes().split(|b| *b == b' ') {
+            let Ok(name) = Word::from_bytes(name.to_vec()) else {
+                continue;
+            };
+            if let Ok(member) = NamesMember::parse(name, &self.status) {
+                self.reply.members.push((member.modes(), member.source.owning()));
+            }
+        }
+    }
+}
+
+impl Handler for JoinHandler {
+    type Value = Result<Join, JoinError>;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        let (args, last) = msg.args.split_last();
+        match &msg.kind {
+            ServerMsgKindRaw::Cmd(cmd) if *cmd == JOIN => {
+                let is_self = match (state.get::<ClientSource>(), &msg.source) {
+                    (Some(me), Some(source)) => source.nick.eq_ignore_case(&me.nick, self.casemap),
+                    _ => true,
+                };
+                let target = msg.args.words().first();
+                if is_self && self.is_target(target) {
+                    if let Some(target) = target {
+                        self.reply.channel = target.clone().owning();
+                    }
+                    self.joined = true;
+                }
+                return ControlFlow::Continue(());
+            }
+            ServerMsgKindRaw::Numeric(_) => (),
+            _ => return ControlFlow::Continue(()),
+        }
+        let words = msg.args.words();
+        let error: fn(Arg<'static>) -> JoinError = match msg.kind.as_str() {
+            // RPL_TOPIC
+            "332" if self.joined && self.is_target(args.get(1)) => {
+                self.reply.topic = last.map(|topic| topic.clone().owning());
+                return ControlFlow::Continue(());
+            }
+            // RPL_TOPICWHOTIME
+            "333" if self.joined && self.is_target(words.get(1)) => {
+                self.reply.topic_setter = words.get(2).map(|s| Word::from(s.clone()).owning());
+                self.reply.topic_time = words
+                    .get(3)
+                    .and_then(|time| std::str::from_utf8(time.as_bytes()).ok()?.parse().ok());
+                return ControlFlow::Continue(());
+            }
+            // RPL_NAMREPLY
+            "353" if self.joined && self.is_target(args.get(2)) => {
+                if let Some(names) = last {
+                    self.add_members(names);
+                }
+                return ControlFlow::Continue(());
+            }
+            // RPL_ENDOFNAMES
+            "366" if self.joined && self.is_target(args.get(1)) => {
+                let empty = Join::new(self.reply.channel.clone());
+                let reply = std::mem::replace(&mut self.reply, empty);
+                let _ = channel.send(Ok(reply));
+                return ControlFlow::Break(());
+            }
+            // ERR_NOSUCHCHANNEL
+            "403" => JoinError::NoSuchChannel,
+            // ERR_CHANNELISFULL
+            "471" => JoinError::ChannelIsFull,
+            // ERR_INVITEONLYCHAN
+            "473" => JoinError::InviteOnly,
+            // ERR_BANNEDFROMCHAN
+            "474" => JoinError::Banned,
+            // ERR_BADCHANNELKEY
+            "475" => JoinError::BadKey,
+            _ => return ControlFlow::Continue(()),
+        };
+        if self.joined || !self.is_target(words.get(1)) {
+            return ControlFlow::Continue(());
+        }
+        let _ = channel.send(Err(error(self.reply.channel.clone())));
+        ControlFlow::Break(())
+    }
+}
+
+impl<'a, 'b> MakeHandler<(Arg<'a>, Option<Arg<'b>>)> for JOIN {
+    type Value = Result<Join, JoinError>;
+
+    type Error = std::convert::Infallible;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        mut queue: QueueEditGuard<'_>,
+        (chan, key): (Arg<'a>, Option<Arg<'b>>),
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        let chan = chan.owning();
+        let mut msg = ClientMsg::new(JOIN);
+        let mut args = msg.args.edit();
+        args.add_word(chan.clone());
+        if let Some(key) = key {
+            args.add_word(key.owning());
+        }
+        queue.push(msg);
+        let isupport = state.get::<ISupport>();
+        let casemap = isupport.map(IrcCasemap::from_isupport).unwrap_or_default();
+        let status = isupport
+            .map(|isupport| ServerChanModes::from_isupport(isupport).status().clone())
+            .filter(|status| *status != StatusModes::new())
+            .unwrap_or_else(|| StatusModes::parse(b"(ov)@+").unwrap_or_default());
+        Ok(Box::new(JoinHandler { casemap, status, joined: false, reply: Join::new(chan) }))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
+
+impl<'a> MakeHandler<Arg<'a>> for JOIN {
+    type Value = Result<Join, JoinError>;
+
+    type Error = std::convert::Infallible;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        queue: QueueEditGuard<'_>,
+        chan: Arg<'a>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        self.make_handler(state, queue, (chan, None::<Arg<'static>>))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
+
+impl<'a, 'b> MakeHandler<(Channel<'a>, Option<Arg<'b>>)> for JOIN {
+    type Value = Result<Join, JoinError>;
+
+    type Error = ParseError;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        queue: QueueEditGuard<'_>,
+        (chan, key): (Channel<'a>, Option<Arg<'b>>),
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        if let Some(isupport) = state.get::<ISupport>() {
+            chan.validate(isupport)?;
+        }
+        self.make_handler(state, queue, (Arg::from(chan), key)).map_err(|e| match e {})
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
+
+impl<'a> MakeHandler<Channel<'a>> for JOIN {
+    type Value = Result<Join, JoinError>;
+
+    type Error = ParseError;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        queue: QueueEditGuard<'_>,
+        chan: Channel<'a>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        self.make_handler(state, queue, (chan, None::<Arg<'static>>))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef},
+        queue::QueueEditGuard,
+        state::ISupport,
+        ClientState, Handler, MakeHandler,
+    },
+    ircmsg::{ClientMsg, ServerMsg},
+    names::{cmd::LIST, isupport::ELIST},
+    string::{Arg, Line},
+};
+use std::ops::ControlFlow;
+
+/// A condition for channels returned by `LIST`.
+///
+/// Servers indicate which of these they support using the `ELIST` ISUPPORT token.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ListFilter<'a> {
+    /// `>n`: Channels with more than `n` users.
+    MoreUsers(u32),
+    /// `<n`: Channels with fewer than `n` users.
+    FewerUsers(u32),
+    /// `C>n`: Channels created more than `n` minutes ago.
+    CreatedBefore(u32),
+    /// `C<n`: Channels created less than `n` minutes ago.
+    CreatedWithin(u32),
+    /// `T>n`: Channels whose topics were set more than `n` minutes ago.
+    TopicBefore(u32),
+    /// `T<n`: Channels whose topics were set less than `n` minutes ago.
+    TopicWithin(u32),
+    /// Channels whose names match a mask.
+    Mask(Arg<'a>),
+    /// `!mask`: Channels whose names do not match a mask.
+    NotMask(Arg<'a>),
+}
+
+impl<'a> ListFilter<'a> {
+    /// Returns the `ELIST` flag that indicates support for this filter.
+    pub const fn flag(&self) -> u8 {
+        match self {
+            ListFilter::MoreUsers(_) | ListFilter::FewerUsers(_) => b'U',
+            ListFilter::CreatedBefore(_) | ListFilter::CreatedWithin(_) => b'C',
+            ListFilter::TopicBefore(_) | ListFilter::TopicWithin(_) => b'T',
+            ListFilter::Mask(_) => b'M',
+            ListFilter::NotMask(_) => b'N',
+        }
+    }
+    /// Returns an owning version of this filter.
+    pub fn owning(self) -> ListFilter<'static> {
+        match self {
+            ListFilter::MoreUsers(n) => ListFilter::MoreUsers(n),
+            ListFilter::FewerUsers(n) => ListFilter::FewerUsers(n),
+            ListFilter::CreatedBefore(n) => ListFilter::CreatedBefore(n),
+            ListFilter::CreatedWithin(n) => ListFilter::CreatedWithin(n),
+            ListFilter::TopicBefore(n) => ListFilter::TopicBefore(n),
+            ListFilter::TopicWithin(n) => ListFilter::TopicWithin(n),
+            ListFilter::Mask(mask) => ListFilter::Mask(mask.owning()),
+            ListFilter::NotMask(mask) => ListFilter::NotMask(mask.owning()),
+        }
+    }
+}
+
+impl std::fmt::Display for ListFilter<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListFilter::MoreUsers(n) => write!(f, ">{n}"),
+            ListFilter::FewerUsers(n) => write!(f, "<{n}"),
+            ListFilter::CreatedBefore(n) => write!(f, "C>{n}"),
+            ListFilter::CreatedWithin(n) => write!(f, "C<{n}"),
+            ListFilter::TopicBefore(n) => write!(f, "T>{n}"),
+            ListFilter::TopicWithin(n) => write!(f, "T<{n}"),
+            ListFilter::Mask(mask) => write!(f, "{mask}"),
+            ListFilter::NotMask(mask) => write!(f, "!{mask}"),
+        }
+    }
+}
+
+/// Error for when a [`ListFilter`] is not supported by the server.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct UnsupportedFilter {
+    /// The `ELIST` flag that the server does not advertise.
+    pub flag: u8,
+}
+
+impl std::fmt::Display for UnsupportedFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "server does not support LIST filter `{}`", self.flag.escape_ascii())
+    }
+}
+
+impl std::error::Error for UnsupportedFilter {}
+
+/// A channel listing request.
+///
+/// [`LIST`] implements [`MakeHandler`] for this type.
+/// The resulting handler yields one [`ListEntry`] per `RPL_LIST` (322) as they arrive,
+/// closing its channel on `RPL_LISTEND` (323).
+/// Making the handler fails with [`UnsupportedFilter`] if any filter is not advertised
+/// in the server's `ELIST` ISUPPORT token.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct List<'a> {
+    /// The conditions that listed channels must meet.
+    ///
+    /// If empty, every visible channel is listed.
+    pub filters: Vec<ListFilter<'a>>,
+}
+
+impl<'a> List<'a> {
+    /// Creates a request for every visible channel.
+    pub const fn new() -> Self {
+        List { filters: Vec::new() }
+    }
+    /// Returns a version of `self` with the provided filter added.
+    pub fn with(mut self, filter: ListFilter<'a>) -> Self {
+        self.filters.push(filter);
+        self
+    }
+    /// Errors if the server does not advertise support for all of the filters.
+    pub fn check(&self, state: &ClientState) -> Result<(), UnsupportedFilter> {
+        let elist = state
+            .get::<ISupport>()
+            .and_then(|isupport| isupport.get_parsed(ELIST)?.ok())
+            .unwrap_or_default();
+        match self.filters.iter().find(|filter| !elist.contains(filter.flag())) {
+            Some(filter) => Err(UnsupportedFilter { flag: filter.flag() }),
+            None => Ok(()),
+        }
+    }
+    /// Returns the argument for `LIST` containing the filters, if any.
+    pub fn arg(&self) -> Option<Arg<'static>> {
+        let mut arg = Vec::new();
+        for filter in &self.filters {
+            if !arg.is_empty() {
+                arg.push(b',');
+            }
+            arg.extend_from_slice(filter.to_string().as_bytes());
+        }
+        Arg::from_bytes(arg).ok()
+    }
+}
+
+/// One channel from `RPL_LIST` (322).
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ListEntry {
+    /// The name of the channel.
+    pub channel: Arg<'static>,
+    /// The number of users visible in the channel.
+    pub users: u32,
+    /// The channel's topic, which may be empty.
+    ///
+    /// Some servers prefix this with the channel's modes in brackets.
+    pub topic: Line<'static>,
+}
+
+impl ListEntry {
+    /// Parses an entry from `RPL_LIST`, or returns `None` if `msg` is not a valid one.
+    pub fn parse(msg: &ServerMsg<'_>) -> Option<Self> {
+        if msg.kind.as_str() != "322" {
+            return None;
+        }
+        let (args, topic) = msg.args.split_last();
+        let channel = args.get(1)?.clone().owning();
+        let users = args.get(2)?.to_utf8()?.parse().ok()?;
+        let topic = topic.cloned().unwrap_or_default().owning();
+        Some(ListEntry { channel, users, topic })
+    }
+}
+
+/// [`Handler`] that yields the replies to `LIST`.
+struct ListHandler;
+
+impl Handler for ListHandler {
+    type Value = ListEntry;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        _: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        match msg.kind.as_str() {
+            // RPL_LIST
+            "322" => {
+                if let Some(entry) = ListEntry::parse(msg) {
+                    return crate::client::cf_discard(channel.send(entry));
+                }
+                ControlFlow::Continue(())
+            }
+            // RPL_LISTEND
+            "323" => ControlFlow::Break(()),
+            _ => ControlFlow::Continue(()),
+        }
+    }
+}
+
+impl<'a> MakeHandler<List<'a>> for LIST {
+    type Value = ListEntry;
+
+    type Error = UnsupportedFilter;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Queue<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        mut queue: QueueEditGuard<'_>,
+        list: List<'a>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        list.check(state)?;
+        let mut msg = ClientMsg::new(LIST);
+        if let Some(arg) = list.arg() {
+            msg.args.edit().add_word(arg);
+        }
+        queue.push(msg);
+        Ok(Box::new(ListHandler))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_queue()
+    }
+}
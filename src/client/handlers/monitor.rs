@@ -0,0 +1,351 @@
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef},
+        queue::QueueEditGuard,
+        state::ISupport,
+        ClientState, Handler, MakeHandler,
+    },
+    ircmsg::{Args, ClientMsg, ServerMsg, Source},
+    names::{cmd::MONITOR, isupport},
+    string::{tf::IrcCasemap, Arg, Cmd, Line, Nick},
+};
+use std::{
+    collections::BTreeMap,
+    ops::ControlFlow,
+    sync::mpsc::{Receiver, TryRecvError},
+};
+
+/// An event yielded by the `MONITOR` handler.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MonitorEvent {
+    /// `RPL_MONONLINE` (730): A monitored user is online.
+    ///
+    /// The source usually includes the user's username and hostname.
+    Online(Source<'static>),
+    /// `RPL_MONOFFLINE` (731): A monitored user is offline.
+    Offline(Nick<'static>),
+    /// `RPL_MONLIST` (732) and `RPL_ENDOFMONLIST` (733):
+    /// The list of monitored nicks as known by the server,
+    /// in response to [`MonitorControl::list`].
+    List(Vec<Nick<'static>>),
+    /// `ERR_MONLISTFULL` (734): The server refused to monitor these nicks.
+    ///
+    /// They are no longer considered monitored.
+    ListFull(Vec<Nick<'static>>),
+    /// These nicks were not added because doing so would have exceeded
+    /// the server's limit on monitored nicks.
+    Rejected(Vec<Nick<'static>>),
+}
+
+/// Error for when more nicks were provided to the `MONITOR` handler
+/// than the server allows.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct MonitorLimit {
+    /// The server's limit from the `MONITOR` ISUPPORT token.
+    pub limit: u32,
+    /// The number of nicks that were provided.
+    pub targets: usize,
+}
+
+impl std::fmt::Display for MonitorLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cannot monitor {} nicks (server limit is {})", self.targets, self.limit)
+    }
+}
+
+impl std::error::Error for MonitorLimit {}
+
+#[derive(Debug)]
+enum Command {
+    Add(Vec<Nick<'static>>),
+    Remove(Vec<Nick<'static>>),
+    Clear,
+    List,
+}
+
+/// Handle for changing the nicks monitored by a running `MONITOR` handler.
+///
+/// Changes take effect the next time the handler runs,
+/// which is either when a message is received or
+/// when [`Client::handle_timeout`][crate::client::Client::handle_timeout] is called.
+/// Every method returns `false` if the handler is no longer running.
+#[derive(Clone, Debug)]
+pub struct MonitorControl(std::sync::mpsc::Sender<Command>);
+
+impl MonitorControl {
+    /// Starts monitoring the provided nicks.
+    ///
+    /// If this would exceed the server's limit, no nicks are added
+    /// and a [`MonitorEvent::Rejected`] is yielded instead.
+    pub fn add(&self, nicks: impl IntoIterator<Item = Nick<'static>>) -> bool {
+        self.0.send(Command::Add(nicks.into_iter().collect())).is_ok()
+    }
+    /// Stops monitoring the provided nicks.
+    pub fn remove(&self, nicks: impl IntoIterator<Item = Nick<'static>>) -> bool {
+        self.0.send(Command::Remove(nicks.into_iter().collect())).is_ok()
+    }
+    /// Stops monitoring every nick.
+    pub fn clear(&self) -> bool {
+        self.0.send(Command::Clear).is_ok()
+    }
+    /// Asks the server for the list of monitored nicks,
+    /// which is yielded as a [`MonitorEvent::List`].
+    pub fn list(&self) -> bool {
+        self.0.send(Command::List).is_ok()
+    }
+}
+
+/// A set of nicks to monitor using the IRCv3 `MONITOR` command.
+///
+/// [`MONITOR`] implements [`MakeHandler`] for this type.
+/// The resulting handler sends `MONITOR +` for every nick,
+/// then yields a [`MonitorEvent`] for every monitor numeric it receives
+/// until its channel closes.
+/// The monitored nicks can be changed after the handler is added using
+/// the [`MonitorControl`] returned by [`control`][Monitor::control].
+///
+/// Making the handler fails if there are more nicks than the `MONITOR` ISUPPORT token allows.
+/// Like any other handler, it is cancelled by [`Client::reset`][crate::client::Client::reset],
+/// which forgets every monitored nick.
+///
+/// If the `extended-monitor` capability is enabled, the server will also send
+/// messages such as `AWAY` and `ACCOUNT` for monitored users.
+/// This handler does not handle those.
+#[derive(Debug)]
+pub struct Monitor {
+    nicks: Vec<Nick<'static>>,
+    send: std::sync::mpsc::Sender<Command>,
+    recv: Receiver<Command>,
+}
+
+impl Monitor {
+    /// Creates a new set of nicks to monitor.
+    pub fn new(nicks: impl IntoIterator<Item = Nick<'static>>) -> Self {
+        let (send, recv) = std::sync::mpsc::channel();
+        Monitor { nicks: nicks.into_iter().collect(), send, recv }
+    }
+    /// Returns a handle for changing the monitored nicks once the handler is running.
+    pub fn control(&self) -> MonitorControl {
+        MonitorControl(self.send.clone())
+    }
+}
+
+struct MonitorHandler {
+    /// Monitored nicks keyed by their casemapped forms.
+    targets: BTreeMap<Nick<'static>, Nick<'static>>,
+    casemap: IrcCasemap,
+    limit: Option<u32>,
+    commands: Receiver<Command>,
+    list: Vec<Nick<'static>>,
+}
+
+fn parse_nicks<'a>(list: Option<&'a Line<'_>>) -> impl Iterator<Item = Nick<'static>> + 'a {
+    let list = list.map(|list| list.as_bytes()).unwrap_or_default();
+    list.split(|b| *b == b',')
+        .filter_map(|nick| Nick::from_bytes(nick.to_vec()).ok())
+        .map(Nick::owning)
+}
+
+fn monitor_msg(op: &'static str) -> ClientMsg<'static> {
+    let mut msg = ClientMsg::new(MONITOR);
+    msg.args.edit().add_word(Arg::from_str(op));
+    msg
+}
+
+/// Sends `MONITOR <op>` with the provided nicks, splitting them over as many lines as needed.
+fn send_targets<'a>(
+    op: &'static str,
+    nicks: impl IntoIterator<Item = &'a Nick<'static>>,
+    queue: &mut QueueEditGuard<'_>,
+) {
+    let op = Arg::from_str(op);
+    let args = Args::new(std::slice::from_ref(&op), Some(Line::default()));
+    let room = crate::ircmsg::bytes_left(&Cmd::from(MONITOR), None, &args);
+    let room: usize = room.try_into().unwrap_or_default();
+    let mut line = Vec::new();
+    let mut flush = |line: &mut Vec<u8>| {
+        if line.is_empty() {
+            return;
+        }
+        let mut msg = ClientMsg::new(MONITOR);
+        let mut args = msg.args.edit();
+        args.add_word(op.clone());
+        args.add(Line::from_bytes(std::mem::take(line)).unwrap());
+        queue.push(msg);
+    };
+    for nick in nicks {
+        if !line.is_empty() && line.len() + 1 + nick.len() > room {
+            flush(&mut line);
+        }
+        if !line.is_empty() {
+            line.push(b',');
+        }
+        line.extend_from_slice(nick.as_bytes());
+    }
+    flush(&mut line);
+}
+
+impl MonitorHandler {
+    fn add(
+        &mut self,
+        nicks: Vec<Nick<'static>>,
+        queue: &mut QueueEditGuard<'_>,
+        channel: &mut SenderRef<'_, MonitorEvent>,
+    ) -> ControlFlow<()> {
+        let mut added = BTreeMap::new();
+        for nick in nicks {
+            let key = self.casemap.fold(nick.clone());
+            if !self.targets.contains_key(&key) {
+                added.insert(key, nick);
+            }
+        }
+        if let Some(limit) = self.limit {
+            if self.targets.len() + added.len() > limit as usize {
+                return crate::client::cf_discard(
+                    channel.send(MonitorEvent::Rejected(added.into_values().collect())),
+                );
+            }
+        }
+        send_targets("+", added.values(), queue);
+        self.targets.append(&mut added);
+        ControlFlow::Continue(())
+    }
+
+    fn run_commands(
+        &mut self,
+        mut queue: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, MonitorEvent>,
+    ) -> ControlFlow<()> {
+        loop {
+            let command = match self.commands.try_recv() {
+                Ok(command) => command,
+                // Every control being dropped only means the targets can no longer change.
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => {
+                    return ControlFlow::Continue(())
+                }
+            };
+            match command {
+                Command::Add(nicks) => self.add(nicks, &mut queue, &mut channel)?,
+                Command::Remove(nicks) => {
+                    let removed: Vec<_> = nicks
+                        .iter()
+                        .filter_map(|nick| self.targets.remove(&self.casemap.fold(nick.clone())))
+                        .collect();
+                    send_targets("-", &removed, &mut queue);
+                }
+                Command::Clear => {
+                    self.targets.clear();
+                    queue.push(monitor_msg("C"));
+                }
+                Command::List => queue.push(monitor_msg("L")),
+            }
+        }
+    }
+}
+
+impl Handler for MonitorHandler {
+    type Value = MonitorEvent;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        _: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        let last = msg.args.split_last().1;
+        match msg.kind.as_str() {
+            // RPL_MONONLINE
+            "730" => {
+                for source in
+                    last.map(|last| last.as_bytes()).unwrap_or_default().split(|b| *b == b',')
+                {
+                    let Ok(source) = crate::string::Word::from_bytes(source.to_vec()) else {
+                        continue;
+                    };
+                    if let Ok(source) = Source::parse(source) {
+                        crate::client::cf_discard(channel.send(MonitorEvent::Online(source)))?;
+                    }
+                }
+            }
+            // RPL_MONOFFLINE
+            "731" => {
+                for nick in parse_nicks(last) {
+                    crate::client::cf_discard(channel.send(MonitorEvent::Offline(nick)))?;
+                }
+            }
+            // RPL_MONLIST
+            "732" => self.list.extend(parse_nicks(last)),
+            // RPL_ENDOFMONLIST
+            "733" => {
+                let list = std::mem::take(&mut self.list);
+                crate::client::cf_discard(channel.send(MonitorEvent::List(list)))?;
+            }
+            // ERR_MONLISTFULL
+            "734" => {
+                let nicks = msg.args.words().get(2).map(|arg| Line::from(arg.clone()));
+                let nicks: Vec<_> = parse_nicks(nicks.as_ref()).collect();
+                for nick in &nicks {
+                    self.targets.remove(&self.casemap.fold(nick.clone()));
+                }
+                crate::client::cf_discard(channel.send(MonitorEvent::ListFull(nicks)))?;
+            }
+            _ => (),
+        }
+        self.run_commands(queue, channel)
+    }
+
+    fn handle_timeout(
+        &mut self,
+        _: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+        channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        self.run_commands(queue, channel)
+    }
+}
+
+impl MakeHandler<Monitor> for MONITOR {
+    type Value = MonitorEvent;
+
+    type Error = MonitorLimit;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Queue<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        mut queue: QueueEditGuard<'_>,
+        monitor: Monitor,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        let isupport = state.get::<ISupport>();
+        let limit = isupport
+            .and_then(|isupport| isupport.get_parsed(isupport::MONITOR)?.ok())
+            .flatten()
+            .map(std::num::NonZeroU32::get);
+        let casemap = isupport.map(IrcCasemap::from_isupport).unwrap_or_default();
+        let mut targets = BTreeMap::new();
+        for nick in monitor.nicks {
+            targets.insert(casemap.fold(nick.clone()), nick);
+        }
+        if let Some(limit) = limit {
+            if targets.len() > limit as usize {
+                return Err(MonitorLimit { limit, targets: targets.len() });
+            }
+        }
+        send_targets("+", targets.values(), &mut queue);
+        Ok(Box::new(MonitorHandler {
+            targets,
+            casemap,
+            limit,
+            commands: monitor.recv,
+            list: Vec::new(),
+        }))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_queue()
+    }
+}
@@ -0,0 +1,191 @@
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef},
+        nick::NickGen,
+        queue::QueueEditGuard,
+        state::{ClientSource, ISupport},
+        ClientState, Handler, MakeHandler,
+    },
+    ircmsg::{ClientMsg, ServerMsg, ServerMsgKindRaw, Source},
+    names::cmd::NICK,
+    string::{tf::IrcCasemap, Arg, Nick},
+};
+use std::ops::ControlFlow;
+
+/// Error yielded by the `NICK` handler if the server refused every nickname.
+///
+/// [`NICK`] implements [`MakeHandler`] for [`Nick`]s and boxed [`NickGen`]s,
+/// changing the client's nick and trying the next nick from the generator
+/// whenever the server refuses one.
+/// It yields the client's new nick once the server confirms a nick change,
+/// updating the [`ClientSource`] state and the assumed source length.
+/// Nick changes forced by the server also resolve the handler,
+/// in which case the yielded nick may not be one that was requested.
+///
+/// Each variant contains the last nickname that was attempted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum NickError {
+    /// `ERR_ERRONEUSNICKNAME` (432): The nickname is invalid.
+    Invalid(Nick<'static>),
+    /// `ERR_NICKNAMEINUSE` (433) or `ERR_NICKCOLLISION` (436): The nickname is in use.
+    InUse(Nick<'static>),
+}
+
+impl NickError {
+    /// Returns the last nickname that was attempted.
+    pub fn nick(&self) -> &Nick<'static> {
+        match self {
+            NickError::Invalid(n) | NickError::InUse(n) => n,
+        }
+    }
+}
+
+impl std::fmt::Display for NickError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            NickError::Invalid(_) => "invalid nickname",
+            NickError::InUse(_) => "nickname in use",
+        };
+        write!(f, "cannot change nick to {}: {reason}", self.nick())
+    }
+}
+
+impl std::error::Error for NickError {}
+
+/// [`Handler`] that changes the client's nick, falling back to other nicks on failure.
+struct NickHandler {
+    casemap: IrcCasemap,
+    /// The client's nick when the handler was created, if known.
+    old: Option<Nick<'static>>,
+    attempt: Nick<'static>,
+    nicks: Option<Box<dyn NickGen>>,
+}
+
+impl NickHandler {
+    fn is_attempt(&self, arg: Option<&Arg<'_>>) -> bool {
+        arg.is_some_and(|arg| arg.eq_ignore_case(&self.attempt, self.casemap))
+    }
+
+    fn send_nick(attempt: &Nick<'static>, queue: &mut QueueEditGuard<'_>) {
+        let mut msg = ClientMsg::new(NICK);
+        msg.args.edit().add_word(attempt.clone());
+        queue.push(msg);
+    }
+}
+
+impl Handler for NickHandler {
+    type Value = Result<Nick<'static>, NickError>;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        mut queue: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        match &msg.kind {
+            ServerMsgKindRaw::Cmd(cmd) if *cmd == NICK => {
+                let Some(Ok(nick)) = msg.args.words().first().map(|n| Nick::from_super(n.clone()))
+                else {
+                    return ControlFlow::Continue(());
+                };
+                // Any change of the client's nick resolves this handler,
+                // including ones forced by the server.
+                let is_self = match (&self.old, &msg.source) {
+                    (Some(old), Some(source)) => source.nick.eq_ignore_case(old, self.casemap),
+                    _ => nick.eq_ignore_case(&self.attempt, self.casemap),
+                };
+                if !is_self {
+                    return ControlFlow::Continue(());
+                }
+                let nick = nick.owning();
+                let userhost = state.get::<ClientSource>().and_then(|src| src.userhost.clone());
+                state.update(|txn| {
+                    txn.insert::<ClientSource>(Source { nick: nick.clone(), userhost });
+                    txn.update_source_len();
+                });
+                let _ = channel.send(Ok(nick));
+                return ControlFlow::Break(());
+            }
+            ServerMsgKindRaw::Numeric(_) => (),
+            _ => return ControlFlow::Continue(()),
+        }
+        let invalid = match msg.kind.as_str() {
+            // ERR_ERRONEUSNICKNAME
+            "432" => true,
+            // ERR_NICKNAMEINUSE, ERR_NICKCOLLISION
+            "433" | "436" => false,
+            _ => return ControlFlow::Continue(()),
+        };
+        if !self.is_attempt(msg.args.words().get(1)) {
+            return ControlFlow::Continue(());
+        }
+        let mut nicks = self.nicks.take();
+        if invalid {
+            nicks = nicks.and_then(|nicks| nicks.handle_invalid(&self.attempt));
+        }
+        let Some(nicks) = nicks else {
+            let attempt = self.attempt.clone();
+            let error =
+                if invalid { NickError::Invalid(attempt) } else { NickError::InUse(attempt) };
+            let _ = channel.send(Err(error));
+            return ControlFlow::Break(());
+        };
+        let (attempt, nicks) = nicks.next_nick();
+        Self::send_nick(&attempt, &mut queue);
+        self.attempt = attempt;
+        self.nicks = nicks;
+        ControlFlow::Continue(())
+    }
+}
+
+impl MakeHandler<Box<dyn NickGen>> for NICK {
+    type Value = Result<Nick<'static>, NickError>;
+
+    type Error = std::convert::Infallible;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        mut queue: QueueEditGuard<'_>,
+        nicks: Box<dyn NickGen>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        let (attempt, nicks) = nicks.next_nick();
+        NickHandler::send_nick(&attempt, &mut queue);
+        let casemap = state.get::<ISupport>().map(IrcCasemap::from_isupport).unwrap_or_default();
+        let old = state.get::<ClientSource>().map(|src| src.nick.clone());
+        Ok(Box::new(NickHandler { casemap, old, attempt, nicks }))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
+
+impl<'a> MakeHandler<Nick<'a>> for NICK {
+    type Value = Result<Nick<'static>, NickError>;
+
+    type Error = std::convert::Infallible;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        queue: QueueEditGuard<'_>,
+        nick: Nick<'a>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        let nicks: Box<dyn NickGen> = Box::new(nick.owning());
+        self.make_handler(state, queue, nicks)
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
@@ -0,0 +1,104 @@
+use super::{update_self_modes, ExpectError};
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef},
+        queue::QueueEditGuard,
+        state::SelfModes,
+        ClientState, Handler, MakeHandler,
+    },
+    ircmsg::{ClientMsg, ServerMsg},
+    names::cmd::OPER,
+    state::ModeSet,
+    string::{Arg, Line},
+};
+use std::ops::ControlFlow;
+
+/// Credentials for becoming an IRC operator.
+///
+/// [`OPER`] implements [`MakeHandler`] for this type.
+/// The resulting handler sends `OPER` and yields the client's user modes once the server
+/// replies with `RPL_YOUREOPER` (381), or [`ExpectError::Failed`] if the server replies with
+/// `ERR_PASSWDMISMATCH` (464) or `ERR_NOOPERHOST` (491).
+///
+/// Changes to the client's user modes that the server sends before `RPL_YOUREOPER`
+/// are applied to [`SelfModes`]. Servers that send them afterwards need
+/// [`TrackSelfModes`][super::TrackSelfModes] to keep `SelfModes` current.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Oper<'a> {
+    /// The operator name.
+    pub name: Arg<'a>,
+    /// The operator password.
+    ///
+    /// This is always secret, and so is neither printed in `Debug` output
+    /// nor included in logs.
+    pub password: Line<'a>,
+}
+
+impl<'a> Oper<'a> {
+    /// Creates a new `Oper` with the provided name and password.
+    ///
+    /// `password` is made secret if it is not already.
+    pub fn new(name: impl Into<Arg<'a>>, password: impl Into<Line<'a>>) -> Self {
+        Oper { name: name.into(), password: password.into().secret() }
+    }
+}
+
+/// [`Handler`] that waits for the result of `OPER`.
+struct OperHandler;
+
+impl Handler for OperHandler {
+    type Value = Result<ModeSet, ExpectError>;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        if update_self_modes(msg, state) {
+            return ControlFlow::Continue(());
+        }
+        let result = match msg.kind.as_str() {
+            // RPL_YOUREOPER
+            "381" => Ok(state.get::<SelfModes>().copied().unwrap_or_default()),
+            // ERR_PASSWDMISMATCH, ERR_NOOPERHOST
+            "464" | "491" => Err(ExpectError::Failed(Box::new(msg.clone().owning()))),
+            _ => return ControlFlow::Continue(()),
+        };
+        let _ = channel.send(result);
+        ControlFlow::Break(())
+    }
+
+    fn expire(&mut self, mut channel: SenderRef<'_, Self::Value>) {
+        let _ = channel.send(Err(ExpectError::TimedOut));
+    }
+}
+
+impl<'a> MakeHandler<Oper<'a>> for OPER {
+    type Value = Result<ModeSet, ExpectError>;
+
+    type Error = std::convert::Infallible;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        _: &ClientState,
+        mut queue: QueueEditGuard<'_>,
+        oper: Oper<'a>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        let mut msg = ClientMsg::new(OPER);
+        let mut args = msg.args.edit();
+        args.add_word(oper.name.owning());
+        args.add(oper.password.secret().owning());
+        queue.push(msg);
+        Ok(Box::new(OperHandler))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
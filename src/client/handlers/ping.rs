@@ -9,7 +9,7 @@ use crate::{
     ircmsg::{ClientMsg, ServerMsg},
     string::Arg,
 };
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 /// [`Handler`] that pings the server and yields the duration it took.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
@@ -45,7 +45,7 @@ impl Handler for Ping {
                 if hash == value {
                     let duration = Instant::now().saturating_duration_since(self.0);
                     let source = msg.source.clone().map(crate::ircmsg::SharedSource::owning_merged);
-                    channel.send((source, duration));
+                    let _ = channel.send((source, duration));
                     return std::ops::ControlFlow::Break(());
                 }
             }
@@ -105,7 +105,7 @@ impl Handler for AutoPong {
         mut queue: QueueEditGuard<'_>,
         _: SenderRef<'_, Self::Value>,
     ) -> std::ops::ControlFlow<()> {
-        pong(msg, &mut queue);
+        pong(msg, |reply| queue.push_urgent(reply));
         std::ops::ControlFlow::Continue(())
     }
 }
@@ -121,3 +121,133 @@ impl SelfMadeHandler for AutoPong {
         (Box::<crate::client::channel::ClosedSender<_>>::default(), ())
     }
 }
+
+/// Error yielded by [`Keepalive`] when the server did not answer a `PING` in time.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct PingTimeout {
+    /// How long the connection was idle before the `PING` was sent.
+    pub idle: Duration,
+    /// How long was spent waiting for the matching `PONG`.
+    pub waited: Duration,
+}
+
+impl std::fmt::Display for PingTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no reply to PING after {:?} (idle for {:?})", self.waited, self.idle)
+    }
+}
+
+impl std::error::Error for PingTimeout {}
+
+/// [`Handler`] that pings the server when the connection goes quiet
+/// and yields a [`PingTimeout`] if the server stops responding.
+///
+/// This handler can only notice that time has passed while no messages are being received
+/// if it is driven by [`Client::handle_timeout`][crate::client::Client::handle_timeout]
+/// whenever a `run` method returns `Ok(None)`.
+/// The client's read timeout should therefore be shorter than
+/// both the idle and timeout durations.
+///
+/// Each `PING` uses a new token, so `PONG`s in reply to earlier `PING`s
+/// (including ones sent by [`Ping`]) are not mistaken for replies to the current one.
+/// This handler finishes after yielding an error. It does not reply to `PING`s;
+/// use [`AutoPong`] for that.
+#[derive(Clone, Debug)]
+pub struct Keepalive {
+    idle: Duration,
+    timeout: Duration,
+    /// Distinguishes this handler's tokens from those of other handlers.
+    salt: u32,
+    count: u64,
+    last_recv: Instant,
+    /// The token of the unanswered `PING`, how long the connection was idle, and when it was sent.
+    pending: Option<(Arg<'static>, Duration, Instant)>,
+}
+
+impl Keepalive {
+    /// Creates a new `Keepalive` that sends a `PING` after `idle` passes without
+    /// receiving any messages, then gives up if no `PONG` is received within `timeout`.
+    pub fn new(idle: Duration, timeout: Duration) -> Self {
+        let now = Instant::now();
+        Keepalive {
+            idle,
+            timeout,
+            salt: crate::util::mangle(&now),
+            count: 0,
+            last_recv: now,
+            pending: None,
+        }
+    }
+
+    fn check(
+        &mut self,
+        now: Instant,
+        mut queue: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, PingTimeout>,
+    ) -> std::ops::ControlFlow<()> {
+        if let Some((_, idle, sent)) = &self.pending {
+            let waited = now.saturating_duration_since(*sent);
+            if waited >= self.timeout {
+                let _ = channel.send(PingTimeout { idle: *idle, waited });
+                return std::ops::ControlFlow::Break(());
+            }
+        } else {
+            let idle = now.saturating_duration_since(self.last_recv);
+            if idle >= self.idle {
+                self.count += 1;
+                let token = format!("{:x}.{}", self.salt, self.count);
+                let token: Arg<'static> = token.try_into().unwrap();
+                let mut msg = ClientMsg::new(PING);
+                msg.args.edit().add_word(token.clone());
+                // The timeout starts now, so don't wait behind rate-limited messages.
+                queue.push_urgent(msg);
+                self.pending = Some((token, idle, now));
+            }
+        }
+        std::ops::ControlFlow::Continue(())
+    }
+}
+
+impl Handler for Keepalive {
+    type Value = PingTimeout;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        _: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+        channel: SenderRef<'_, Self::Value>,
+    ) -> std::ops::ControlFlow<()> {
+        let now = Instant::now();
+        self.last_recv = now;
+        if msg.kind == PONG {
+            if let (Some((token, _, _)), Some(last)) = (&self.pending, msg.args.split_last().1) {
+                if last.as_bytes() == token.as_bytes() {
+                    self.pending = None;
+                }
+            }
+        }
+        self.check(now, queue, channel)
+    }
+
+    fn handle_timeout(
+        &mut self,
+        _: &mut ClientState,
+        queue: QueueEditGuard<'_>,
+        channel: SenderRef<'_, Self::Value>,
+    ) -> std::ops::ControlFlow<()> {
+        self.check(Instant::now(), queue, channel)
+    }
+}
+
+impl SelfMadeHandler for Keepalive {
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
@@ -0,0 +1,1372 @@
+use super::{
+    wait_for_state, wait_for_state_until, CollectBatches, Keepalive, PingTimeout, StateTimeout,
+};
+use crate::{
+    client::{
+        cap::{ServerMsgArgs, SubCmd},
+        channel::{
+            ChannelSpec, ClosedSender, Incomplete, OrIncomplete, Sender, SenderRef, SyncChannels,
+        },
+        conn::Bidir,
+        queue::QueueEditGuard,
+        state::Caps,
+        Client, ClientState, Handler, SelfMadeHandler,
+    },
+    ircmsg::ServerMsg,
+    names::{cap::ECHO_MESSAGE, cmd::CAP, Name, NameMap},
+    string::Word,
+};
+use std::{io::Cursor, ops::ControlFlow, time::Instant};
+
+/// Minimal capability tracker that updates [`Caps`] on `CAP NEW` and `CAP ACK`.
+struct TrackCaps;
+
+impl Handler for TrackCaps {
+    type Value = ();
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        _: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        if msg.kind != CAP {
+            return ControlFlow::Continue(());
+        }
+        let Ok(msg) = ServerMsgArgs::parse(&msg.args.clone().owning()) else {
+            return ControlFlow::Continue(());
+        };
+        let enabled = match msg.subcmd {
+            SubCmd::New => false,
+            SubCmd::Ack => true,
+            _ => return ControlFlow::Continue(()),
+        };
+        let mut caps = state.get::<Caps>().cloned().unwrap_or_else(NameMap::new);
+        for (cap, _) in msg.caps {
+            caps.edit().insert((cap, Word::default()), enabled);
+        }
+        state.insert::<Caps>(caps);
+        ControlFlow::Continue(())
+    }
+}
+
+impl SelfMadeHandler for TrackCaps {
+    type Receiver<Spec: ChannelSpec> = ();
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        _: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        (Box::<ClosedSender<_>>::default(), ())
+    }
+}
+
+fn echo_message(state: &ClientState) -> Option<()> {
+    let caps = state.get::<Caps>()?;
+    caps.get_extra_raw(ECHO_MESSAGE.as_raw()).copied().unwrap_or_default().then_some(())
+}
+
+#[test]
+fn wait_for_cap() {
+    let msgs = concat!(
+        ":example.com CAP me NEW :echo-message\r\n",
+        ":example.com CAP me ACK :echo-message\r\n",
+        ":example.com PING :example.com\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    client.add((), TrackCaps).unwrap();
+    let (id, result) = client.add((), wait_for_state(echo_message)).unwrap();
+    let mut lines = 0;
+    loop {
+        let (_, finished) = client.run().unwrap().unwrap();
+        lines += 1;
+        if finished.contains(&id) {
+            break;
+        }
+    }
+    assert!(lines <= 3);
+    assert_eq!(result.0.recv_now(), Some(Ok(())));
+}
+
+#[test]
+fn wait_for_state_deadline() {
+    let msgs = ":example.com CAP me NEW :echo-message\r\n";
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let (id, result) = client.add((), wait_for_state_until(echo_message, Instant::now())).unwrap();
+    let (_, finished) = client.run().unwrap().unwrap();
+    assert_eq!(finished, [id]);
+    assert_eq!(result.0.recv_now(), Some(Err(StateTimeout)));
+}
+
+#[test]
+fn collect_batches() {
+    let msgs = concat!(
+        ":example.com BATCH +outer chathistory #chan\r\n",
+        "@batch=outer :a!a@a PRIVMSG #chan :one\r\n",
+        ":b!b@b PRIVMSG #chan :not batched\r\n",
+        "@batch=outer :example.com BATCH +inner netsplit a.example.com b.example.com\r\n",
+        "@batch=inner :c!c@c QUIT :a.example.com b.example.com\r\n",
+        "@batch=outer :a!a@a PRIVMSG #chan :two\r\n",
+        ":example.com BATCH -inner\r\n",
+        ":example.com BATCH -outer\r\n",
+        "@batch=unknown :a!a@a PRIVMSG #chan :three\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let (_, batches) = client.add((), CollectBatches::new()).unwrap();
+    while client.run().is_ok() {}
+    let batch = batches.try_recv().unwrap().unwrap();
+    assert!(batches.try_recv().is_err());
+    assert_eq!(batch.kind, "chathistory");
+    assert_eq!(batch.params, ["#chan"]);
+    let lines: Vec<_> = batch.msgs.iter().map(|msg| msg.args.split_last().1.unwrap()).collect();
+    assert_eq!(lines, ["one", "two"]);
+    let [inner] = batch.nested.as_slice() else {
+        panic!("expected one nested batch, got {:?}", batch.nested);
+    };
+    assert_eq!(inner.kind, "netsplit");
+    assert_eq!(inner.params, ["a.example.com", "b.example.com"]);
+    assert_eq!(inner.msgs.len(), 1);
+}
+
+#[test]
+fn collect_batches_cancel() {
+    let msgs = concat!(
+        ":example.com BATCH +a chathistory #chan\r\n",
+        "@batch=a :example.com BATCH +b netjoin a.example.com b.example.com\r\n",
+        "@batch=b :c!c@c JOIN #chan\r\n",
+        ":example.com BATCH +c chathistory #other\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let (_, batches) = client.add((), CollectBatches::new()).unwrap();
+    while client.run().is_ok() {}
+    assert!(batches.try_recv().is_err());
+    client.reset();
+    let a = batches.try_recv().unwrap().unwrap_err().0;
+    assert_eq!(a.params, ["#chan"]);
+    assert_eq!(a.nested.len(), 1);
+    assert_eq!(a.nested[0].msgs.len(), 1);
+    let c = batches.try_recv().unwrap().unwrap_err().0;
+    assert_eq!(c.params, ["#other"]);
+    assert!(batches.recv().is_err());
+}
+
+/// Handler that finishes on the first message without sending anything.
+struct GiveUp;
+
+impl Handler for GiveUp {
+    type Value = ();
+
+    fn handle(
+        &mut self,
+        _: &ServerMsg<'_>,
+        _: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        _: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        ControlFlow::Break(())
+    }
+}
+
+impl SelfMadeHandler for GiveUp {
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<()>;
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
+
+#[test]
+fn finish_without_send() {
+    let msgs = ":example.com PING :example.com\r\n";
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let (id, (recv, parker)) = client.add((), GiveUp).unwrap();
+    let (_, finished) = client.run().unwrap().unwrap();
+    assert_eq!(finished, [id]);
+    // Would block forever if the channel were left open.
+    assert_eq!(recv.recv(&parker), None);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn finish_without_send_tokio() {
+    use crate::client::channel::TokioChannels;
+    let msgs = ":example.com PING :example.com\r\n";
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+    let mut client = Client::new(io, TokioChannels);
+    let (id, mut recv) = client.add((), GiveUp).unwrap();
+    let (_, finished) = client.run().unwrap().unwrap();
+    assert_eq!(finished, [id]);
+    assert_eq!(recv.try_recv(), Err(tokio::sync::oneshot::error::TryRecvError::Closed));
+}
+
+#[cfg(feature = "futures-channel")]
+#[test]
+fn finish_without_send_futures() {
+    use crate::client::channel::FuturesChannels;
+    let msgs = ":example.com PING :example.com\r\n";
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+    let mut client = Client::new(io, FuturesChannels);
+    let (id, mut recv) = client.add((), GiveUp).unwrap();
+    let (_, finished) = client.run().unwrap().unwrap();
+    assert_eq!(finished, [id]);
+    assert_eq!(recv.try_recv(), Err(futures_channel::oneshot::Canceled));
+}
+
+/// Handler that finishes on the first message, sending a value only if `.0` is true.
+///
+/// Its channel receives [`Incomplete`] if it finishes without sending.
+struct MaybeSend(bool);
+
+impl Handler for MaybeSend {
+    type Value = Result<u8, Incomplete>;
+
+    fn handle(
+        &mut self,
+        _: &ServerMsg<'_>,
+        _: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        if self.0 {
+            let _ = channel.send(Ok(1));
+        }
+        ControlFlow::Break(())
+    }
+}
+
+impl SelfMadeHandler for MaybeSend {
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Result<u8, Incomplete>>;
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        let (send, recv) = spec.new_oneshot();
+        (Box::new(OrIncomplete::new(send)), recv)
+    }
+}
+
+#[test]
+fn finish_incomplete() {
+    for (sends, expected) in [(false, Err(Incomplete)), (true, Ok(1))] {
+        let msgs = ":example.com PING :example.com\r\n";
+        let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+        let mut client = Client::new(io, SyncChannels);
+        let (id, (recv, parker)) = client.add((), MaybeSend(sends)).unwrap();
+        let (_, finished) = client.run().unwrap().unwrap();
+        assert_eq!(finished, [id]);
+        assert_eq!(recv.recv(&parker), Some(expected));
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn finish_incomplete_tokio() {
+    use crate::client::channel::TokioChannels;
+    for (sends, expected) in [(false, Err(Incomplete)), (true, Ok(1))] {
+        let msgs = ":example.com PING :example.com\r\n";
+        let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+        let mut client = Client::new(io, TokioChannels);
+        let (id, mut recv) = client.add((), MaybeSend(sends)).unwrap();
+        let (_, finished) = client.run().unwrap().unwrap();
+        assert_eq!(finished, [id]);
+        assert_eq!(recv.try_recv(), Ok(expected));
+    }
+}
+
+/// Handler that queues a message and finishes on the first message,
+/// pushing it to the urgent lane if `.0` is true.
+struct PushAndFinish(bool);
+
+impl Handler for PushAndFinish {
+    type Value = ();
+
+    fn handle(
+        &mut self,
+        _: &ServerMsg<'_>,
+        _: &mut ClientState,
+        mut queue: QueueEditGuard<'_>,
+        _: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        let msg = crate::ircmsg::ClientMsg::new(crate::names::cmd::QUIT);
+        if self.0 {
+            queue.push_urgent(msg);
+        } else {
+            queue.push(msg);
+        }
+        ControlFlow::Break(())
+    }
+}
+
+impl SelfMadeHandler for PushAndFinish {
+    type Receiver<Spec: ChannelSpec> = ();
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        _: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        (Box::<ClosedSender<()>>::default(), ())
+    }
+}
+
+#[test]
+fn push_urgent_and_finish() {
+    let msgs = ":example.com PING :example.com\r\n";
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    let (id, ()) = client.add((), PushAndFinish(true)).unwrap();
+    let (_, finished) = client.run().unwrap().unwrap();
+    assert_eq!(finished, [id]);
+    assert_eq!(client.take_conn().1, b"QUIT\r\n");
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic = "queued a non-urgent message in the call where it finished"]
+fn push_and_finish() {
+    let msgs = ":example.com PING :example.com\r\n";
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    client.add((), PushAndFinish(false)).unwrap();
+    let _ = client.run();
+}
+
+/// One end of an in-memory connection that reports timeouts instead of EOF.
+#[derive(Clone, Default)]
+struct Pipe(std::sync::Arc<std::sync::Mutex<std::collections::VecDeque<u8>>>);
+
+impl Pipe {
+    fn push(&self, data: &str) {
+        self.0.lock().unwrap().extend(data.as_bytes());
+    }
+    fn take(&self) -> String {
+        let data: Vec<u8> = self.0.lock().unwrap().drain(..).collect();
+        String::from_utf8(data).unwrap()
+    }
+}
+
+impl std::io::Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        if data.is_empty() {
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        data.read(buf)
+    }
+}
+
+impl std::io::Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl crate::client::conn::ReadTimeout for Pipe {
+    fn set_read_timeout(&mut self, _: Option<std::time::Duration>) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl crate::client::conn::WriteTimeout for Pipe {
+    fn set_write_timeout(&mut self, _: Option<std::time::Duration>) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn keepalive() {
+    const TICK: std::time::Duration = std::time::Duration::from_millis(20);
+    let (input, output) = (Pipe::default(), Pipe::default());
+    let io = Bidir(std::io::BufReader::new(input.clone()), output.clone());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1);
+    let (id, result) = client.add((), Keepalive::new(TICK * 2, TICK * 2)).unwrap();
+    let ping = |client: &mut Client<_, _>| {
+        std::thread::sleep(TICK * 3);
+        assert_eq!(client.handle_timeout(), (&[][..], &[][..]));
+        assert!(client.run().unwrap().is_none());
+        let sent = output.take();
+        let token = sent.strip_prefix("PING ").and_then(|token| token.strip_suffix("\r\n"));
+        token.expect("a PING should have been sent").to_owned()
+    };
+    // Nothing should be sent before the connection goes idle.
+    assert!(client.run().unwrap().is_none());
+    assert_eq!(client.handle_timeout(), (&[][..], &[][..]));
+    assert!(client.run().unwrap().is_none());
+    assert!(output.take().is_empty());
+    let first = ping(&mut client);
+    input.push(&format!(":example.com PONG example.com :{first}\r\n"));
+    assert!(client.run().unwrap().is_none());
+    let second = ping(&mut client);
+    assert_ne!(first, second);
+    // A stale PONG is not a reply.
+    input.push(&format!(":example.com PONG example.com :{first}\r\n"));
+    assert!(client.run().unwrap().is_none());
+    std::thread::sleep(TICK * 2);
+    assert_eq!(client.handle_timeout(), (&[id][..], &[id][..]));
+    let PingTimeout { idle, waited } = result.0.recv_now().unwrap();
+    assert!(idle >= TICK * 2);
+    assert!(waited >= TICK * 2);
+}
+
+#[test]
+fn keepalive_backlog() {
+    use crate::{
+        ircmsg::ClientMsg,
+        names::cmd::{PING, PRIVMSG},
+    };
+    const TICK: std::time::Duration = std::time::Duration::from_millis(20);
+    let io = Bidir(std::io::BufReader::new(Pipe::default()), Pipe::default());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(std::time::Duration::from_secs(60), 0);
+    for _ in 0..3 {
+        client.queue_mut().edit().push(ClientMsg::new(PRIVMSG));
+    }
+    client.add((), Keepalive::new(TICK, TICK * 100)).unwrap();
+    std::thread::sleep(TICK * 2);
+    assert_eq!(client.handle_timeout(), (&[][..], &[][..]));
+    // The PING skips the rate-limited backlog.
+    let ping = client.queue_mut().pop(|_| ()).expect("the PING should not be rate-limited");
+    assert_eq!(ping.cmd, PING);
+    assert!(client.queue_mut().pop(|_| ()).is_none());
+    assert_eq!(client.queue().len(), 3);
+}
+
+/// Handler that records its name when it sees a message, finishing on `QUIT`.
+struct Record(&'static str, std::sync::Arc<std::sync::Mutex<Vec<&'static str>>>);
+
+impl Handler for Record {
+    type Value = ();
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        _: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        self.1.lock().unwrap().push(self.0);
+        let _ = channel.send(());
+        if msg.kind == crate::names::cmd::QUIT && msg.args.split_last().1.unwrap() == self.0 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+impl SelfMadeHandler for Record {
+    type Receiver<Spec: ChannelSpec> = Spec::Queue<()>;
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_queue()
+    }
+}
+
+#[test]
+fn dispatch_order() {
+    let msgs = concat!(
+        ":example.com NOTICE * :first\r\n",
+        ":a QUIT :a\r\n",
+        ":example.com NOTICE * :third\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let record = |name| Record(name, seen.clone());
+    let (a, _a) = client.add((), record("a")).unwrap();
+    let (b, _b) = client.add((), record("b")).unwrap();
+    let (c, _c) = client.add((), record("c")).unwrap();
+    let (yielded, finished) = client.run().unwrap().unwrap();
+    assert_eq!(yielded, [a, b, c]);
+    assert!(finished.is_empty());
+    assert_eq!(std::mem::take(&mut *seen.lock().unwrap()), ["a", "b", "c"]);
+    // Removing the first handler does not reorder the others.
+    let (yielded, finished) = client.run().unwrap().unwrap();
+    assert_eq!((yielded, finished), (&[a, b, c][..], &[a][..]));
+    assert_eq!(std::mem::take(&mut *seen.lock().unwrap()), ["a", "b", "c"]);
+    // New handlers get new ids and run last,
+    // except for the one with a higher priority.
+    let (d, _d) = client.add((), record("d")).unwrap();
+    let (e, _e) = client.add_with_priority(1, (), record("e")).unwrap();
+    assert!(a < b && b < c && c < d && d < e);
+    let (yielded, _) = client.run().unwrap().unwrap();
+    assert_eq!(yielded, [b, c, d, e]);
+    assert_eq!(std::mem::take(&mut *seen.lock().unwrap()), ["e", "b", "c", "d"]);
+}
+
+/// Handler that sends a labeled `WHOIS` and yields the commands of the responses.
+struct Routed;
+
+impl Handler for Routed {
+    type Value = String;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        _: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        let _ = channel.send(msg.kind.as_str().to_owned());
+        let is_end = msg.args.words().first().is_some_and(|arg| arg.first() == Some(&b'-'));
+        if msg.kind == *"ACK" || is_end {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+impl SelfMadeHandler for Routed {
+    type Receiver<Spec: ChannelSpec> = Spec::Queue<String>;
+
+    fn queue_msgs(&self, _: &ClientState, mut queue: QueueEditGuard<'_>) {
+        let msg = crate::ircmsg::ClientMsg::new(crate::names::cmd::WHOIS);
+        let label = queue.push_labeled(msg).unwrap();
+        assert!(queue.route_label(label));
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_queue()
+    }
+}
+
+#[test]
+fn labeled_routing() {
+    let msgs = concat!(
+        "@label=1 :example.com BATCH +b labeled-response\r\n",
+        "@batch=b :example.com 311 Me Me me example.com * :Me\r\n",
+        ":a!a@a PRIVMSG Me :not routed\r\n",
+        "@batch=b :example.com 318 Me Me :End of WHOIS\r\n",
+        ":example.com BATCH -b\r\n",
+        "@label=3 :example.com NOTICE Me :unknown label\r\n",
+        "@label=2 :example.com ACK\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    let mut next = 0u32;
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1).use_labeler(move || {
+        next += 1;
+        crate::string::NoNul::from_bytes(next.to_string()).unwrap()
+    });
+    let (_, all) = client.add((), super::YieldAll).unwrap();
+    let (_, batched) = client.add((), Routed).unwrap();
+    let (_, acked) = client.add((), Routed).unwrap();
+    while client.run().is_ok() {}
+    let all: Vec<_> = all.try_iter().map(|msg| msg.kind.as_str().to_owned()).collect();
+    assert_eq!(all, ["PRIVMSG", "NOTICE"]);
+    // Routed handlers still see messages that aren't responses to a routed label.
+    let batched: Vec<_> = batched.try_iter().collect();
+    assert_eq!(batched, ["BATCH", "311", "PRIVMSG", "318", "BATCH"]);
+    assert_eq!(acked.try_iter().collect::<Vec<_>>(), ["PRIVMSG", "NOTICE", "ACK"]);
+}
+
+#[test]
+fn expect_reply() {
+    use super::{ExpectError, ExpectNumeric};
+    use crate::{
+        ircmsg::Numeric,
+        names::cmd::{ADMIN, LUSERS, TIME},
+    };
+    let num = |n| Numeric::from_int(n).unwrap();
+    let msgs = concat!(
+        ":example.com 251 me :There are 2 users\r\n",
+        ":example.com 391 me example.com :Thursday\r\n",
+        ":example.com 402 me nowhere :No such server\r\n",
+        ":example.com FAIL LUSERS TEMPORARILY_UNAVAILABLE :Try again later\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    let (_, time) = client.add(TIME.expect(num(391)), ()).unwrap();
+    let (_, admin) = client.add(ADMIN.expect(num(256)).fail_on(num(402)), ()).unwrap();
+    let (_, lusers) = client.add(LUSERS.expect(num(266)), ()).unwrap();
+    while client.needs_run() && client.run().is_ok() {}
+    assert_eq!(time.0.recv_now().unwrap().unwrap().args.words()[1], "example.com");
+    let Some(Err(ExpectError::Failed(msg))) = admin.0.recv_now() else { panic!() };
+    assert_eq!(msg.kind.as_str(), "402");
+    let Some(Err(ExpectError::Failed(msg))) = lusers.0.recv_now() else { panic!() };
+    assert_eq!(msg.kind.as_str(), "FAIL");
+}
+
+#[test]
+fn expect_reply_labeled() {
+    use super::{ExpectError, ExpectNumeric};
+    use crate::{
+        ircmsg::Numeric,
+        names::cmd::{ADMIN, LUSERS, TIME},
+    };
+    let num = |n| Numeric::from_int(n).unwrap();
+    let msgs = concat!(
+        ":example.com 391 me other.example.com :Wednesday\r\n",
+        "@label=1 :example.com 391 me example.com :Thursday\r\n",
+        "@label=3 :example.com BATCH +b labeled-response\r\n",
+        "@batch=b :example.com 251 me :There are 2 users\r\n",
+        ":example.com 266 me :Unrelated\r\n",
+        "@batch=b :example.com 266 me :Current global users: 2\r\n",
+        ":example.com BATCH -b\r\n",
+        "@label=2 :example.com ACK\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    let mut next = 0u32;
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1).use_labeler(move || {
+        next += 1;
+        crate::string::NoNul::from_bytes(next.to_string()).unwrap()
+    });
+    let (_, time) = client.add(TIME.expect(num(391)), ()).unwrap();
+    let (_, admin) = client.add(ADMIN.expect(num(256)), ()).unwrap();
+    let (_, lusers) = client.add(LUSERS.expect(num(266)), ()).unwrap();
+    while client.needs_run() && client.run().is_ok() {}
+    assert_eq!(time.0.recv_now().unwrap().unwrap().args.words()[1], "example.com");
+    assert_eq!(admin.0.recv_now(), Some(Err(ExpectError::NoReply)));
+    let msg = lusers.0.recv_now().unwrap().unwrap();
+    assert_eq!(msg.args.split_last().1.unwrap(), "Current global users: 2");
+}
+
+#[test]
+fn whois() {
+    use crate::{
+        names::cmd::WHOIS,
+        state::Mode,
+        string::{Arg, Nick},
+    };
+    let msgs = concat!(
+        ":example.com 311 me Someone user host.example.com * :Some One\r\n",
+        ":example.com 319 me someone :@#ops +#voiced #plain\r\n",
+        ":example.com NOTICE me :unrelated\r\n",
+        ":example.com 312 me someone irc.example.com :Example server\r\n",
+        ":example.com 338 me someone 192.0.2.1 :actually using host\r\n",
+        ":example.com 311 me other user host :Not the target\r\n",
+        ":example.com 317 me someone 42 1700000000 :seconds idle, signon time\r\n",
+        ":example.com 330 me someone acct :is logged in as\r\n",
+        ":example.com 671 me someone :is using a secure connection\r\n",
+        ":example.com 318 me someone :End of /WHOIS list.\r\n",
+        ":example.com 401 me nobody :No such nick/channel\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1);
+    let (_, found) = client.add(WHOIS, Nick::from_str("someone")).unwrap();
+    let (_, missing) = client.add(WHOIS, Nick::from_str("NoBody")).unwrap();
+    // Once for each handler.
+    client.run().unwrap();
+    client.run().unwrap();
+    let whois = found.0.recv_now().unwrap().unwrap();
+    assert_eq!(whois.nick, "Someone");
+    let userhost = whois.userhost.unwrap();
+    assert_eq!(userhost.user.unwrap(), "user");
+    assert_eq!(userhost.host, "host.example.com");
+    assert_eq!(whois.realname.unwrap(), "Some One");
+    assert_eq!(whois.server.unwrap(), "irc.example.com");
+    assert!(!whois.operator);
+    assert_eq!(whois.idle, Some(std::time::Duration::from_secs(42)));
+    assert_eq!(whois.signon, Some(1700000000));
+    let op = Mode::new(b'o').unwrap();
+    let voice = Mode::new(b'v').unwrap();
+    let channels: Vec<_> = whois
+        .channels
+        .iter()
+        .map(|(modes, chan)| (modes.contains(op), modes.contains(voice), chan.to_string()))
+        .collect();
+    assert_eq!(
+        channels,
+        [
+            (true, false, "#ops".to_owned()),
+            (false, true, "#voiced".to_owned()),
+            (false, false, "#plain".to_owned())
+        ]
+    );
+    assert_eq!(whois.account, Some(Arg::from_str("acct")));
+    assert!(whois.secure);
+    let [extra] = whois.extras.as_slice() else {
+        panic!("expected one extra numeric, got {:?}", whois.extras);
+    };
+    assert_eq!(extra.kind.as_str(), "338");
+    let error = missing.0.recv_now().unwrap().unwrap_err();
+    assert_eq!(error, super::WhoisError::NoSuchNick(Nick::from_str("NoBody")));
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    assert_eq!(sent, "WHOIS someone\r\nWHOIS NoBody\r\n");
+}
+
+#[test]
+fn monitor() {
+    use super::{Monitor, MonitorEvent, MonitorLimit};
+    use crate::{
+        client::state::ISupport,
+        names::{cmd::MONITOR, ISupport as ISupportClass},
+        string::{Key, Nick},
+    };
+    let msgs = concat!(
+        ":example.com 730 me :Alice!a@host.example\r\n",
+        ":example.com 731 me :bob\r\n",
+        ":example.com 732 me :alice,bob\r\n",
+        ":example.com 733 me :End of MONITOR list\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1);
+    let mut isupport = NameMap::<ISupportClass>::new();
+    isupport.edit().insert((Key::from_str("MONITOR"), Word::from_str("3")), ());
+    client.state_mut().insert::<ISupport>(isupport);
+    let nicks = ["alice", "bob", "carol", "dave"].map(Nick::from_str);
+    let Err(error) = client.add(MONITOR, Monitor::new(nicks.clone())) else {
+        panic!("monitoring should fail with too many nicks");
+    };
+    assert_eq!(error, MonitorLimit { limit: 3, targets: 4 });
+    let monitor = Monitor::new([Nick::from_str("Alice"), Nick::from_str("bob")]);
+    let control = monitor.control();
+    let (_, events) = client.add(MONITOR, monitor).unwrap();
+    assert!(control.add([Nick::from_str("ALICE"), nicks[2].clone(), nicks[3].clone()]));
+    assert!(control.add([nicks[2].clone()]));
+    assert!(control.remove([Nick::from_str("BOB")]));
+    assert!(control.list());
+    while client.run().is_ok() {}
+    let events: Vec<_> = events.try_iter().collect();
+    let MonitorEvent::Online(source) = &events[0] else {
+        panic!("expected online event, got {:?}", events[0]);
+    };
+    assert_eq!(source.to_string(), "Alice!a@host.example");
+    assert_eq!(
+        events[1..],
+        [
+            MonitorEvent::Rejected(vec![nicks[2].clone(), nicks[3].clone()]),
+            MonitorEvent::Offline(nicks[1].clone()),
+            MonitorEvent::List(vec![nicks[0].clone(), nicks[1].clone()]),
+        ]
+    );
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    assert_eq!(sent, "MONITOR + Alice,bob\r\nMONITOR + carol\r\nMONITOR - bob\r\nMONITOR L\r\n");
+    // Nicks are split over multiple lines when necessary.
+    let io = Bidir(Cursor::new(Vec::<u8>::new()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let nicks = (0..100).map(|n| Nick::from_bytes(format!("nickname{n:02}")).unwrap());
+    let _ = client.add(MONITOR, Monitor::new(nicks)).unwrap();
+    let queue = client.queue_mut();
+    queue.set_rate_limit(std::time::Duration::ZERO, 1);
+    let mut lines = 0;
+    while let Some(msg) = queue.pop(|_| ()) {
+        assert!(msg.bytes_left(None) >= 0);
+        lines += 1;
+    }
+    assert_eq!(lines, 3);
+}
+
+#[test]
+fn join() {
+    use crate::{
+        client::state::{ClientSource, ISupport},
+        ircmsg::Source,
+        names::{cmd::JOIN, ISupport as ISupportClass},
+        state::Mode,
+        string::{Arg, Channel, Key, Nick},
+    };
+    let msgs = concat!(
+        ":Me!user@host JOIN #Chan[1]\r\n",
+        ":example.com 332 me #chan{1} :The topic\r\n",
+        ":example.com 333 me #chan{1} someone 1700000000\r\n",
+        ":example.com 353 me = #other :@me\r\n",
+        ":example.com 473 me #other :Cannot join channel (+i)\r\n",
+        ":example.com 353 me = #chan{1} :@+me!user@host +other!u@h\r\n",
+        ":example.com 353 me = #chan{1} :plain!u@h\r\n",
+        ":example.com 366 me #chan{1} :End of /NAMES list.\r\n",
+        ":example.com 475 me #keyed :Cannot join channel (+k)\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::<u8>::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1);
+    let mut isupport = NameMap::<ISupportClass>::new();
+    isupport.edit().insert((Key::from_str("PREFIX"), Word::from_str("(qov)~@+")), ());
+    client.state_mut().insert::<ISupport>(isupport);
+    let me = Source { nick: Nick::from_str("me"), userhost: None };
+    client.state_mut().insert::<ClientSource>(me);
+    assert!(client.add(JOIN, Channel::from_str("+modeless")).is_err());
+    let (_, chan) = client.add(JOIN, Arg::from_str("#chan[1]")).unwrap();
+    let (_, other) = client.add(JOIN, Channel::from_str("#other")).unwrap();
+    let keyed = (Arg::from_str("#keyed"), Some(Arg::from_str("hunter2")));
+    let (_, keyed) = client.add(JOIN, keyed).unwrap();
+    // Once for each handler.
+    client.run().unwrap();
+    client.run().unwrap();
+    client.run().unwrap();
+    let join = chan.0.recv_now().unwrap().unwrap();
+    assert_eq!(join.channel, "#Chan[1]");
+    assert_eq!(join.topic.unwrap(), "The topic");
+    assert_eq!(join.topic_setter.unwrap(), "someone");
+    assert_eq!(join.topic_time, Some(1700000000));
+    let op = Mode::new(b'o').unwrap();
+    let voice = Mode::new(b'v').unwrap();
+    let members: Vec<_> = join
+        .members
+        .iter()
+        .map(|(modes, src)| (modes.contains(op), modes.contains(voice), src.to_string()))
+        .collect();
+    assert_eq!(
+        members,
+        [
+            (true, true, "me!user@host".to_owned()),
+            (false, true, "other!u@h".to_owned()),
+            (false, false, "plain!u@h".to_owned())
+        ]
+    );
+    let error = other.0.recv_now().unwrap().unwrap_err();
+    assert_eq!(error, super::JoinError::InviteOnly(Arg::from_str("#other")));
+    let error = keyed.0.recv_now().unwrap().unwrap_err();
+    assert_eq!(error.to_string(), "cannot join #keyed: bad channel key");
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    assert_eq!(sent, "JOIN #chan[1]\r\nJOIN #other\r\nJOIN #keyed hunter2\r\n");
+}
+
+#[test]
+fn track_client_source() {
+    use crate::{
+        client::state::{Account, ClientSource, Realname},
+        ircmsg::Source,
+    };
+    let msgs = concat!(
+        ":other!u@h CHGHOST user other.host\r\n",
+        ":ME!user@host CHGHOST ~user a.much.longer.host.example\r\n",
+        ":me!~user@a.much.longer.host.example SETNAME :New Name\r\n",
+        ":other!u@h ACCOUNT other\r\n",
+        ":me!~user@a.much.longer.host.example ACCOUNT acct\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let me = Source::parse(Word::from_str("me!user@host")).unwrap();
+    client.state_mut().insert::<ClientSource>(me);
+    client.state_mut().update_source_len();
+    let old_len = client.state().source_len().get();
+    client.add((), super::TrackClientSource::new()).unwrap();
+    while client.run().is_ok() {}
+    let state = client.state();
+    assert_eq!(
+        state.get::<ClientSource>().unwrap().to_string(),
+        "me!~user@a.much.longer.host.example"
+    );
+    assert_eq!(state.source_len().get(), old_len + 22);
+    assert_eq!(state.get::<Realname>().unwrap().as_ref().unwrap(), "New Name");
+    assert_eq!(state.get::<Account>().unwrap().as_ref().unwrap(), "acct");
+}
+
+#[test]
+fn track_isupport() {
+    use crate::{
+        client::state::ISupport,
+        names::{
+            isupport::{EXCEPTS, NETWORK, NICKLEN},
+            ISupport as ISupportClass,
+        },
+        string::Key,
+    };
+    let msgs = concat!(
+        ":example.com 005 me NICKLEN=30 -EXCEPTS NETWORK=Example :are supported by this server\r\n",
+        ":example.com 005 me -NETWORK=Bogus :are supported by this server\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    client.add((), super::TrackISupport::new()).unwrap();
+    let mut isupport = NameMap::<ISupportClass>::new();
+    let mut edit = isupport.edit();
+    edit.insert((Key::from_str("NICKLEN"), Word::from_str("9")), ());
+    edit.insert((Key::from_str("EXCEPTS"), Word::default()), ());
+    std::mem::drop(edit);
+    client.state_mut().insert::<ISupport>(isupport);
+    client.state_mut().update_source_len();
+    let old_len = client.state().source_len().get();
+    while client.run().is_ok() {}
+    let isupport = client.state().get::<ISupport>().unwrap();
+    assert_eq!(isupport.get_parsed(NICKLEN).unwrap().unwrap().get(), 30);
+    assert!(isupport.get_union(EXCEPTS).is_none());
+    // Removals with values are malformed and ignored.
+    assert_eq!(isupport.get_parsed(NETWORK).unwrap().unwrap(), "Example");
+    assert_eq!(client.state().source_len().get(), old_len + 21);
+}
+
+#[test]
+fn echo_await() {
+    use super::{is_echo, EchoAwait, NoEchoError};
+    use crate::{
+        client::state::ClientSource,
+        ircmsg::{ClientMsg, Source},
+        names::cmd::PRIVMSG,
+        string::{Arg, Line, Nick},
+    };
+    fn privmsg(target: &'static str, text: &'static str) -> ClientMsg<'static> {
+        let mut msg = ClientMsg::new(PRIVMSG);
+        let mut args = msg.args.edit();
+        args.add_word(Arg::from_str(target));
+        args.add(Line::from_str(text));
+        msg
+    }
+    let msgs = concat!(
+        ":someone!u@h PRIVMSG #chan :hello\r\n",
+        ":me!u@h PRIVMSG #chan :goodbye\r\n",
+        ":ME!u@h PRIVMSG #Chan :hello\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1);
+    let me = Source { nick: Nick::from_str("me"), userhost: None };
+    client.state_mut().insert::<ClientSource>(me);
+    assert_eq!(client.add(EchoAwait, privmsg("#chan", "hello")).unwrap_err(), NoEchoError);
+    let mut caps = NameMap::new();
+    caps.edit().insert((ECHO_MESSAGE.as_raw().clone(), Word::default()), true);
+    client.state_mut().insert::<Caps>(caps);
+    let (_, echo) = client.add(EchoAwait, privmsg("#chan", "hello")).unwrap();
+    client.run().unwrap();
+    let echo = echo.0.recv_now().unwrap();
+    assert_eq!(echo.source.as_ref().unwrap().nick, "ME");
+    assert!(is_echo(&echo, client.state()));
+    let other = ServerMsg::parse(":someone!u@h PRIVMSG #chan :hello").unwrap();
+    assert!(!is_echo(&other, client.state()));
+    // Labeled responses match even if the server rewrote the message.
+    let msgs = concat!(
+        "@label=1 :me!u@h PRIVMSG #chan :rewritten\r\n",
+        ":me!u@h PRIVMSG #chan :hello\r\n",
+        "@label=2 :example.com ACK\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    let mut next = 0u32;
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1).use_labeler(move || {
+        next += 1;
+        crate::string::NoNul::from_bytes(next.to_string()).unwrap()
+    });
+    let (_, echo) = client.add(EchoAwait, privmsg("#chan", "hello")).unwrap();
+    let (_, ack) = client.add(EchoAwait, privmsg("#chan", "hello")).unwrap();
+    // Once for each handler.
+    client.run().unwrap();
+    client.run().unwrap();
+    assert_eq!(echo.0.recv_now().unwrap().args.split_last().1.unwrap(), "rewritten");
+    assert_eq!(ack.0.recv_now().unwrap().kind.as_str(), "ACK");
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    assert!(sent.starts_with("@label=1 PRIVMSG #chan hello\r\n@label=2 PRIVMSG"));
+}
+
+#[test]
+fn nick() {
+    use crate::{
+        client::{nick::NickGen, state::ClientSource},
+        ircmsg::Source,
+        names::cmd::NICK,
+        string::Nick,
+    };
+    let msgs = concat!(
+        ":example.com 433 me taken :Nickname is already in use\r\n",
+        ":example.com 433 me busy :Nickname is already in use\r\n",
+        ":example.com 432 me bad :Erroneous nickname\r\n",
+        ":other!u@h NICK good\r\n",
+        ":me!user@host NICK good\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::<u8>::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1);
+    let me = Source::parse(Word::from_str("me!user@host")).unwrap();
+    client.state_mut().insert::<ClientSource>(me);
+    let nicks = ["taken", "bad", "good"].map(Nick::from_str);
+    let nicks: Box<dyn NickGen> = Box::new(crate::client::nick::from_iter(nicks).unwrap());
+    let (_, gen) = client.add(NICK, nicks).unwrap();
+    let (_, busy) = client.add(NICK, Nick::from_str("busy")).unwrap();
+    // Resolved by a nick change that it did not request.
+    let (_, forced) = client.add(NICK, Nick::from_str("wanted")).unwrap();
+    client.run().unwrap();
+    client.run().unwrap();
+    assert_eq!(busy.0.recv_now().unwrap(), Err(super::NickError::InUse(Nick::from_str("busy"))));
+    assert_eq!(gen.0.recv_now().unwrap().unwrap(), "good");
+    assert_eq!(forced.0.recv_now().unwrap().unwrap(), "good");
+    assert_eq!(client.state().get::<ClientSource>().unwrap().to_string(), "good!user@host");
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    assert_eq!(sent, "NICK taken\r\nNICK busy\r\nNICK wanted\r\nNICK bad\r\nNICK good\r\n");
+}
+
+#[test]
+fn cancel_handler() {
+    use crate::{names::cmd::NICK, string::Nick};
+    let io = Bidir(Cursor::new(Vec::<u8>::new()), Vec::<u8>::new());
+    let mut client = Client::new(io, SyncChannels);
+    let (id, recv) = client.add(NICK, Nick::from_str("foo")).unwrap();
+    assert!(client.cancel_handler(id));
+    assert!(!client.cancel_handler(id));
+    assert_eq!(recv.0.recv_now(), None);
+}
+
+#[test]
+fn handler_info() {
+    use crate::{names::cmd::NICK, string::Nick};
+    let io = Bidir(Cursor::new(Vec::<u8>::new()), Vec::<u8>::new());
+    let mut client = Client::new(io, SyncChannels);
+    assert_eq!(client.handler_info().count(), 0);
+    let (nick, _nick) = client.add(NICK, Nick::from_str("foo")).unwrap();
+    let (caps, _) = client.add_with_priority(1, (), TrackCaps).unwrap();
+    let info: Vec<_> = client.handler_info().collect();
+    assert_eq!(info.len(), 2);
+    assert_eq!(info[0], (caps, std::any::type_name::<TrackCaps>(), false));
+    assert_eq!(info[1].0, nick);
+    assert!(client.cancel_handler(caps));
+    assert_eq!(client.handler_info().map(|(id, _, _)| id).collect::<Vec<_>>(), [nick]);
+}
+
+#[test]
+fn handler_timeout() {
+    use crate::{names::cmd::NICK, string::Nick};
+    use std::time::Duration;
+    let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let conn = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    // Keep the server side open without sending anything.
+    let _server = listener.accept().unwrap();
+    let mut client = Client::new(std::io::BufReader::new(conn), SyncChannels);
+    client.set_read_timeout(Some(Duration::from_secs(30)));
+    let (_, forever) = client.add(NICK, Nick::from_str("foo")).unwrap();
+    let timeout = Duration::from_millis(50);
+    let (id, recv) = client.add_with_timeout(timeout, NICK, Nick::from_str("bar")).unwrap();
+    let start = Instant::now();
+    let (yielded, finished) = client.run().unwrap().unwrap();
+    assert!(start.elapsed() >= timeout);
+    assert!(start.elapsed() < Duration::from_secs(30));
+    assert!(yielded.is_empty());
+    assert_eq!(finished, [id]);
+    assert_eq!(recv.0.recv_now(), None);
+    assert!(forever.0.is_empty());
+}
+
+#[test]
+fn chathistory() {
+    use super::{HistoryBound, HistoryQuery, HistoryRequest};
+    use crate::{
+        client::NoHandler, names::cap::DRAFT_CHATHISTORY, names::cmd::CHATHISTORY, string::Arg,
+    };
+    use std::time::{Duration, SystemTime};
+    let msgs = concat!(
+        ":example.com BATCH +x chathistory #Chan\r\n",
+        "@batch=x :a!b@c PRIVMSG #chan :one\r\n",
+        ":a!b@c PRIVMSG #chan :live\r\n",
+        "@batch=x :example.com BATCH +y draft/multiline #chan\r\n",
+        "@batch=y :a!b@c PRIVMSG #chan :two\r\n",
+        "@batch=x :example.com BATCH -y\r\n",
+        ":example.com BATCH -x\r\n",
+        ":example.com FAIL CHATHISTORY INVALID_TARGET LATEST #nope :Messages could not be retrieved\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1);
+    let request = HistoryRequest {
+        target: Arg::from_str("#chan"),
+        query: HistoryQuery::Before(HistoryBound::Timestamp(
+            SystemTime::UNIX_EPOCH + Duration::from_millis(1_319_042_451_620),
+        )),
+        limit: 50,
+    };
+    assert!(matches!(client.add(CHATHISTORY, request.clone()), Err(NoHandler)));
+    let mut caps = NameMap::new();
+    caps.edit().insert((DRAFT_CHATHISTORY.as_raw().clone(), Word::default()), true);
+    client.state_mut().insert::<Caps>(caps);
+    let (_, history) = client.add(CHATHISTORY, request).unwrap();
+    client.run().unwrap();
+    let history = history.0.recv_now().unwrap().unwrap();
+    // Nested batches are included.
+    assert_eq!(history.len(), 4);
+    let texts: Vec<_> = history
+        .iter()
+        .filter(|msg| msg.kind == crate::names::cmd::PRIVMSG)
+        .map(|msg| msg.args.split_last().1.unwrap().to_string())
+        .collect();
+    assert_eq!(texts, ["one", "two"]);
+    let request = HistoryRequest::latest(Arg::from_str("#nope"), 10);
+    let (_, history) = client.add(CHATHISTORY, request).unwrap();
+    client.run().unwrap();
+    let fail = history.0.recv_now().unwrap().unwrap_err();
+    assert_eq!(fail.code, "INVALID_TARGET");
+    assert_eq!(fail.context, [Arg::from_str("LATEST"), Arg::from_str("#nope")]);
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    assert_eq!(
+        sent,
+        concat!(
+            "CHATHISTORY BEFORE #chan timestamp=2011-10-19T16:40:51.620Z 50\r\n",
+            "CHATHISTORY LATEST #nope * 10\r\n",
+        )
+    );
+}
+
+#[test]
+fn whox() {
+    use super::{Whox, WhoxField, WhoxFields};
+    use crate::{
+        client::{state::ISupport, NoHandler},
+        names::{cmd::WHO, ISupport as ISupportClass, NameMap},
+        state::Mode,
+        string::{Arg, Key},
+    };
+    use std::time::Duration;
+    let msgs = concat!(
+        // HexChat's layout, with a token from another query first.
+        ":example.com 354 me 7 #chan ~a host.a irc.example.com a H :Not ours\r\n",
+        ":example.com 354 me 152 #chan ~a host.a irc.example.com Alice G*@ 0 :Alice A\r\n",
+        ":example.com 354 me 152 #chan b host.b irc.example.com bob H+ bob :Bob\r\n",
+        ":example.com 315 me #other :End of /WHO list.\r\n",
+        ":example.com 315 me #Chan :End of /WHO list.\r\n",
+        // Layout with hopcount and idle time, without a token.
+        ":example.com 354 me 1.2.3.4 carol 0 300\r\n",
+        ":example.com 354 me 152 too many fields here\r\n",
+        ":example.com 315 me carol :End of /WHO list.\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(Duration::ZERO, 1);
+    let hexchat: WhoxFields = "chsunfra".bytes().filter_map(WhoxField::from_letter).collect();
+    let query = Whox::new(Arg::from_str("#chan"), hexchat).with_token(152);
+    assert_eq!(query.specifier().unwrap(), "%tcuhsnfar,152");
+    assert_eq!(client.add(WHO, query.clone()).err(), Some(NoHandler));
+    let mut isupport = NameMap::<ISupportClass>::new();
+    isupport.edit().insert((Key::from_str("WHOX"), Word::default()), ());
+    client.state_mut().insert::<ISupport>(isupport);
+    assert_eq!(client.add(WHO, query.clone().with_token(1000)).err(), Some(NoHandler));
+    let (_, chan) = client.add(WHO, query).unwrap();
+    let fields = WhoxFields::new()
+        .with(WhoxField::Ip)
+        .with(WhoxField::Nick)
+        .with(WhoxField::Hopcount)
+        .with(WhoxField::Idle);
+    let (_, nick) = client.add(WHO, Whox::new(Arg::from_str("carol"), fields)).unwrap();
+    client.run().unwrap();
+    client.run().unwrap();
+    let chan = chan.0.recv_now().unwrap();
+    assert_eq!(chan.len(), 2);
+    let alice = &chan[0];
+    assert_eq!(alice.channel.as_ref().unwrap(), "#chan");
+    assert_eq!(alice.user.as_ref().unwrap(), "~a");
+    assert_eq!(alice.host.as_ref().unwrap(), "host.a");
+    assert_eq!(alice.server.as_ref().unwrap(), "irc.example.com");
+    assert_eq!(alice.nick.as_ref().unwrap(), "Alice");
+    let flags = alice.flags.unwrap();
+    assert!(flags.away && flags.oper);
+    assert!(flags.status.contains(Mode::new(b'o').unwrap()));
+    assert_eq!(alice.account, None);
+    assert_eq!(alice.realname.as_ref().unwrap(), "Alice A");
+    assert_eq!(alice.ip, None);
+    let bob = &chan[1];
+    let flags = bob.flags.unwrap();
+    assert!(!flags.away && !flags.oper);
+    assert!(flags.status.contains(Mode::new(b'v').unwrap()));
+    assert_eq!(bob.account.as_ref().unwrap(), "bob");
+    let nick = nick.0.recv_now().unwrap();
+    assert_eq!(nick.len(), 1);
+    assert_eq!(nick[0].ip.as_ref().unwrap(), "1.2.3.4");
+    assert_eq!(nick[0].nick.as_ref().unwrap(), "carol");
+    assert_eq!(nick[0].hopcount, Some(0));
+    assert_eq!(nick[0].idle, Some(Duration::from_secs(300)));
+    assert_eq!(nick[0].realname, None);
+}
+
+#[test]
+fn away() {
+    use super::{AwayNotify, AwayTooLong, SetAway};
+    use crate::{
+        client::state::{ISupport, SelfAway},
+        names::{cmd::AWAY, ISupport as ISupportClass},
+        string::{Key, Line},
+    };
+    let msgs = concat!(
+        ":example.com 306 me :You have been marked as being away\r\n",
+        ":alice!a@host.example AWAY :out to lunch\r\n",
+        ":bob!b@host.example AWAY\r\n",
+        ":example.com 305 me :You are no longer marked as being away\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::<u8>::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1);
+    let mut isupport = NameMap::<ISupportClass>::new();
+    isupport.edit().insert((Key::from_str("AWAYLEN"), Word::from_str("8")), ());
+    client.state_mut().insert::<ISupport>(isupport);
+    let Err(error) = client.add(AWAY, Some(Line::from_str("gone fishing"))) else {
+        panic!("setting an overly-long away message should fail");
+    };
+    assert_eq!(error, AwayTooLong { limit: 8, len: 12 });
+    let (_, events) = client.add((), AwayNotify).unwrap();
+    // "ö" straddles the limit and must not be cut in half.
+    let (_, away) =
+        client.add(AWAY, SetAway::away(Line::from_str("gone\tfrö")).truncate()).unwrap();
+    client.run().unwrap();
+    assert_eq!(away.0.recv_now().unwrap().unwrap(), "gone\tfr");
+    assert_eq!(client.state().get::<SelfAway>().unwrap().as_ref().unwrap(), "gone\tfr");
+    let (_, back) = client.add(AWAY, SetAway::back()).unwrap();
+    while client.run().is_ok() {}
+    assert_eq!(back.0.recv_now().unwrap(), None);
+    assert_eq!(client.state().get::<SelfAway>(), Some(&None));
+    let events: Vec<_> = events.try_iter().map(|(src, msg)| (src.to_string(), msg)).collect();
+    assert_eq!(
+        events,
+        [
+            ("alice!a@host.example".to_owned(), Some(Line::from_str("out to lunch"))),
+            ("bob!b@host.example".to_owned(), None),
+        ]
+    );
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    assert_eq!(sent, "AWAY gone\tfr\r\nAWAY\r\n");
+}
+
+#[test]
+fn chanop() {
+    use super::{Invite, Kick, LimitExceeded, Topic};
+    use crate::{
+        client::{
+            handlers::ExpectError,
+            state::{ClientSource, ISupport},
+        },
+        ircmsg::Source,
+        names::{
+            cmd::{INVITE, KICK, TOPIC},
+            ISupport as ISupportClass,
+        },
+        string::{Channel, Key, Line, Nick},
+    };
+    let msgs = concat!(
+        ":alice!a@host.example TOPIC #Chan :hijacked\r\n",
+        ":Me!m@host.example TOPIC #Chan :new topic\r\n",
+        ":example.com 441 me bob #chan :They aren't on that channel\r\n",
+        ":example.com 341 me carol #other\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::<u8>::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1);
+    let mut isupport = NameMap::<ISupportClass>::new();
+    isupport.edit().insert((Key::from_str("TOPICLEN"), Word::from_str("9")), ());
+    client.state_mut().insert::<ISupport>(isupport);
+    let me = Source::parse(Word::from_str("me!m@host.example")).unwrap();
+    client.state_mut().insert::<ClientSource>(me);
+    let Err(error) = client
+        .add(TOPIC, Topic::set(Channel::from_str("#chan"), Line::from_str("too long a topic")))
+    else {
+        panic!("setting an overly-long topic should fail");
+    };
+    assert_eq!(error, LimitExceeded { token: "TOPICLEN", limit: 9, len: 16 });
+    let (_, topic) = client
+        .add(TOPIC, Topic::set(Channel::from_str("#chan"), Line::from_str("new topic")))
+        .unwrap();
+    let kick = Kick::new(Channel::from_str("#chan"), Nick::from_str("bob"))
+        .with_reason(Line::from_str("bye"));
+    let (_, kick) = client.add(KICK, kick).unwrap();
+    let (_, invite) = client
+        .add(INVITE, Invite::new(Nick::from_str("carol"), Channel::from_str("#other")))
+        .unwrap();
+    while client.needs_run() && client.run().is_ok() {}
+    let topic = topic.0.recv_now().unwrap().unwrap();
+    assert_eq!(topic.source.unwrap().nick, "Me");
+    let Err(ExpectError::Failed(kick)) = kick.0.recv_now().unwrap() else {
+        panic!("kick should have failed");
+    };
+    assert_eq!(kick.kind.as_str(), "441");
+    assert_eq!(invite.0.recv_now().unwrap().unwrap().kind.as_str(), "341");
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    assert_eq!(sent, "TOPIC #chan :new topic\r\nKICK #chan bob bye\r\nINVITE carol #other\r\n");
+}
+
+#[test]
+fn oper() {
+    use super::{Oper, TrackSelfModes};
+    use crate::{
+        client::state::{ClientSource, SelfModes},
+        ircmsg::Source,
+        names::cmd::OPER,
+        state::Mode,
+        string::{Arg, Line, Nick},
+    };
+    let msgs = concat!(
+        ":example.com 464 me :Password incorrect\r\n",
+        ":example.com 221 me +iw\r\n",
+        ":me MODE me :+o\r\n",
+        ":example.com 381 me :You are now an IRC operator\r\n",
+        ":other MODE other :+o\r\n",
+        ":me MODE Me -w+s +cF\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::<u8>::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(std::time::Duration::ZERO, 1);
+    let me = Source { nick: Nick::from_str("me"), userhost: None };
+    client.state_mut().insert::<ClientSource>(me);
+    let oper = Oper::new(Arg::from_str("admin"), Line::from_str("hunter2"));
+    assert!(!format!("{oper:?}").contains("hunter2"));
+    let (_, failed) = client.add(OPER, oper.clone()).unwrap();
+    client.run().unwrap();
+    assert!(failed.0.recv_now().unwrap().is_err());
+    client.add((), TrackSelfModes::new()).unwrap();
+    let (_, oper) = client.add(OPER, oper).unwrap();
+    client.run().unwrap();
+    let modes = oper.0.recv_now().unwrap().unwrap();
+    let mode = |letter| Mode::new(letter).unwrap();
+    assert!(modes.contains(mode(b'i')) && modes.contains(mode(b'o')));
+    while client.run().is_ok() {}
+    let modes = *client.state().get::<SelfModes>().unwrap();
+    assert!(modes.contains(mode(b'o')) && modes.contains(mode(b's')));
+    assert!(!modes.contains(mode(b'w')) && !modes.contains(mode(b'c')));
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    assert_eq!(sent, "OPER admin hunter2\r\nOPER admin hunter2\r\n");
+}
+
+#[test]
+fn list() {
+    use super::{List, ListEntry, ListFilter, UnsupportedFilter};
+    use crate::{
+        client::state::ISupport,
+        names::{cmd::LIST, ISupport as ISupportClass},
+        string::{Arg, Key, Line},
+    };
+    let msgs = concat!(
+        ":example.com 321 me Channel :Users  Name\r\n",
+        ":example.com 322 me #a 12 :[+nt] first\r\n",
+        ":example.com 322 me #b 7 :\r\n",
+        ":example.com 323 me :End of /LIST\r\n",
+        ":example.com 322 me #c 3 :late\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::<u8>::new());
+    let mut client = Client::new(io, SyncChannels);
+    let mut isupport = NameMap::<ISupportClass>::new();
+    isupport.edit().insert((Key::from_str("ELIST"), Word::from_str("mu")), ());
+    client.state_mut().insert::<ISupport>(isupport);
+    let Err(error) = client.add(LIST, List::new().with(ListFilter::TopicWithin(60))) else {
+        panic!("unsupported filters should be refused");
+    };
+    assert_eq!(error, UnsupportedFilter { flag: b'T' });
+    let list = List::new()
+        .with(ListFilter::MoreUsers(5))
+        .with(ListFilter::Mask(Arg::from_str("#*")))
+        .with(ListFilter::FewerUsers(100));
+    let (_, entries) = client.add(LIST, list).unwrap();
+    while client.needs_run() && client.run().is_ok() {}
+    let entries: Vec<_> = entries.try_iter().collect();
+    assert_eq!(
+        entries,
+        [
+            ListEntry {
+                channel: Arg::from_str("#a"),
+                users: 12,
+                topic: Line::from_str("[+nt] first")
+            },
+            ListEntry { channel: Arg::from_str("#b"), users: 7, topic: Line::default() },
+        ]
+    );
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    assert_eq!(sent, "LIST >5,#*,<100\r\n");
+}
@@ -4,13 +4,17 @@ use crate::{
     client::{
         channel::{ChannelSpec, ClosedSender, Sender},
         queue::QueueEditGuard,
-        state::ClientSource,
+        state::{Account, ClientSource, ISupport, Realname, SelfModes},
         ClientState, Handler, SelfMadeHandler,
     },
     error::ParseError,
-    ircmsg::{ClientMsg, Source, UserHost},
-    names::cmd::USERHOST,
-    string::{Arg, Nick, User, Word},
+    ircmsg::{ClientMsg, ServerMsg, Source, UserHost},
+    names::{
+        cmd::{MODE, USERHOST},
+        isupport::{HOSTLEN, NICKLEN, USERLEN},
+    },
+    state::{Mode, ModeSet},
+    string::{tf::IrcCasemap, Arg, Nick, User, Word},
 };
 
 /// Handler for automatically updating this client's [`ClientSource`].
@@ -21,6 +25,11 @@ use crate::{
 /// If the client's nick is known,
 /// this handler begins by sending a [`USERHOST`] message to query the client's [`UserHost`].
 /// Otherwise, it remains in the background and updates state.
+///
+/// Besides `RPL_USERHOST` (302), this handler watches for `NICK`, `CHGHOST`, `SETNAME`,
+/// and `ACCOUNT` messages whose source is the client (compared using the server's casemapping),
+/// updating the [`ClientSource`], [`Realname`], and [`Account`] respectively.
+/// The assumed source length is recalculated whenever the client source changes.
 #[derive(Default)]
 pub struct TrackClientSource {}
 
@@ -31,13 +40,30 @@ impl TrackClientSource {
     }
 }
 
-fn get_client_source(state: &mut ClientState) -> ControlFlow<(), &mut Source<'static>> {
-    match state.get_mut::<ClientSource>() {
+fn get_client_source(state: &ClientState) -> ControlFlow<(), &Source<'static>> {
+    match state.get::<ClientSource>() {
         Some(v) => ControlFlow::Continue(v),
         None => ControlFlow::Break(()),
     }
 }
 
+/// Returns `true` if `msg`'s source is the client.
+fn is_self(msg: &crate::ircmsg::ServerMsg<'_>, src: &Source<'_>, state: &ClientState) -> bool {
+    let Some(m_src) = msg.source.as_ref() else {
+        return false;
+    };
+    let casemap = state.get::<ISupport>().map(IrcCasemap::from_isupport).unwrap_or_default();
+    m_src.nick.eq_ignore_case(&src.nick, casemap)
+}
+
+/// Replaces the client's source and the assumed source length together.
+fn set_client_source(state: &mut ClientState, source: Source<'static>) {
+    state.update(|txn| {
+        txn.insert::<ClientSource>(source);
+        txn.update_source_len();
+    });
+}
+
 // TODO: Would be nice to extract RPL_USERHOST parsing.
 fn parse_userhost_item<'a>(
     item: Arg<'a>,
@@ -100,8 +126,9 @@ impl Handler for TrackClientSource {
                             // TODO: Log warning on ParseError?
                             Err(_) => continue,
                         };
-                        src.userhost = Some(userhost.owning());
-                        state.update_source_len();
+                        let src =
+                            Source { nick: src.nick.clone(), userhost: Some(userhost.owning()) };
+                        set_client_source(state, src);
                         break;
                     }
                 }
@@ -113,32 +140,40 @@ impl Handler for TrackClientSource {
                         // TODO: Log warning?
                         return ControlFlow::Continue(());
                     };
-                    match msg.source.as_ref() {
-                        Some(m_src) if m_src.nick == src.nick => {
-                            src.nick = nick.owning();
-                            state.update_source_len();
-                        }
-                        _ => (),
+                    if is_self(msg, src, state) {
+                        let src = Source { nick: nick.owning(), userhost: src.userhost.clone() };
+                        set_client_source(state, src);
                     }
                 }
             }
             "CHGHOST" => {
                 if let Some([user, host]) = msg.args.all() {
                     let src = get_client_source(state)?;
-                    match msg.source.as_ref() {
-                        Some(m_src) if m_src.nick == src.nick => {
-                            let user = match User::from_super(user.clone()) {
-                                Ok(u) => u.owning(),
-                                // TODO: Log warning?
-                                Err(_) => return ControlFlow::Continue(()),
-                            };
-                            src.userhost = Some(UserHost {
-                                user: Some(user),
-                                host: host.clone().owning().into(),
-                            });
-                            state.update_source_len();
-                        }
-                        _ => (),
+                    if is_self(msg, src, state) {
+                        let user = match User::from_super(user.clone()) {
+                            Ok(u) => u.owning(),
+                            // TODO: Log warning?
+                            Err(_) => return ControlFlow::Continue(()),
+                        };
+                        let userhost =
+                            UserHost { user: Some(user), host: host.clone().owning().into() };
+                        let src = Source { nick: src.nick.clone(), userhost: Some(userhost) };
+                        set_client_source(state, src);
+                    }
+                }
+            }
+            "SETNAME" => {
+                if let ([], Some(realname)) = msg.args.split_last() {
+                    if is_self(msg, get_client_source(state)?, state) {
+                        state.insert::<Realname>(Some(realname.clone().owning()));
+                    }
+                }
+            }
+            "ACCOUNT" => {
+                if let Some([account]) = msg.args.all() {
+                    if is_self(msg, get_client_source(state)?, state) {
+                        let account = Some(account).filter(|a| *a != "*");
+                        state.insert::<Account>(account.map(|a| a.clone().owning()));
                     }
                 }
             }
@@ -165,3 +200,164 @@ impl SelfMadeHandler for TrackClientSource {
         (Box::<ClosedSender<_>>::default(), ())
     }
 }
+
+/// Handler for keeping the server's [`ISupport`] tokens up to date after registration.
+///
+/// Servers may send `RPL_ISUPPORT` (005) at any time to add, change, or remove
+/// (using `-KEY`) tokens, such as after a client becomes an operator.
+/// This handler applies these changes to the [`ISupport`] state,
+/// recalculating the assumed source length if `NICKLEN`, `USERLEN`, or `HOSTLEN` change.
+/// It does nothing until the [`ISupport`] state has been set, usually by registration.
+#[derive(Default)]
+pub struct TrackISupport {}
+
+impl TrackISupport {
+    /// Creates a new `TrackISupport` handler.
+    pub fn new() -> Self {
+        TrackISupport {}
+    }
+}
+
+impl Handler for TrackISupport {
+    type Value = ();
+
+    fn handle(
+        &mut self,
+        msg: &crate::ircmsg::ServerMsg<'_>,
+        state: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        _: crate::client::channel::SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        if msg.kind != *"005" {
+            return ControlFlow::Continue(());
+        }
+        let Some((_, tokens)) = msg.args.words().split_first() else {
+            return ControlFlow::Continue(());
+        };
+        let Some(isupport) = state.get::<ISupport>() else {
+            return ControlFlow::Continue(());
+        };
+        let mut isupport = isupport.clone();
+        let lens = |isupport: &crate::names::NameMap<crate::names::ISupport>| {
+            (
+                isupport.get_union(NICKLEN).cloned(),
+                isupport.get_union(USERLEN).cloned(),
+                isupport.get_union(HOSTLEN).cloned(),
+            )
+        };
+        let old_lens = lens(&isupport);
+        crate::names::isupport::apply_tokens(&mut isupport, tokens);
+        let update_len = lens(&isupport) != old_lens;
+        state.update(|txn| {
+            txn.insert::<ISupport>(isupport);
+            if update_len {
+                txn.update_source_len();
+            }
+        });
+        ControlFlow::Continue(())
+    }
+}
+
+impl SelfMadeHandler for TrackISupport {
+    type Receiver<Spec: ChannelSpec> = ();
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        _spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        (Box::<ClosedSender<_>>::default(), ())
+    }
+}
+
+/// Applies a user mode string such as `+iw-x` to `modes`.
+fn apply_umodes(modes: &mut ModeSet, modestr: &[u8]) {
+    let mut adding = true;
+    for byte in modestr.iter().copied() {
+        match byte {
+            b'+' => adding = true,
+            b'-' => adding = false,
+            _ => {
+                if let Some(mode) = Mode::new(byte) {
+                    if adding {
+                        modes.set(mode);
+                    } else {
+                        modes.unset(mode);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Updates [`SelfModes`] if `msg` is a `MODE` for the client or `RPL_UMODEIS` (221).
+///
+/// Returns `true` if `SelfModes` was updated.
+pub(super) fn update_self_modes(msg: &ServerMsg<'_>, state: &mut ClientState) -> bool {
+    let mut modes = match msg.kind.as_str() {
+        // RPL_UMODEIS
+        "221" => ModeSet::new(),
+        _ if msg.kind == MODE => {
+            let (Some(me), Some(target)) = (state.get::<ClientSource>(), msg.args.words().first())
+            else {
+                return false;
+            };
+            let casemap =
+                state.get::<ISupport>().map(IrcCasemap::from_isupport).unwrap_or_default();
+            if !target.eq_ignore_case(&me.nick, casemap) {
+                return false;
+            }
+            state.get::<SelfModes>().copied().unwrap_or_default()
+        }
+        _ => return false,
+    };
+    let Some(modestr) = msg.args.iter().nth(1) else {
+        return false;
+    };
+    apply_umodes(&mut modes, modestr.as_bytes());
+    state.insert::<SelfModes>(modes);
+    true
+}
+
+/// Handler for keeping the client's [`SelfModes`] up to date.
+///
+/// This handler watches for `MODE` messages whose target is the client
+/// (compared using the server's casemapping), applying the changes to [`SelfModes`],
+/// and for `RPL_UMODEIS` (221), which replaces [`SelfModes`] outright.
+/// Mode parameters, such as server notice masks, are ignored.
+#[derive(Default)]
+pub struct TrackSelfModes {}
+
+impl TrackSelfModes {
+    /// Creates a new `TrackSelfModes` handler.
+    pub fn new() -> Self {
+        TrackSelfModes {}
+    }
+}
+
+impl Handler for TrackSelfModes {
+    type Value = ();
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        state: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        _: crate::client::channel::SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        update_self_modes(msg, state);
+        ControlFlow::Continue(())
+    }
+}
+
+impl SelfMadeHandler for TrackSelfModes {
+    type Receiver<Spec: ChannelSpec> = ();
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        _spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        (Box::<ClosedSender<_>>::default(), ())
+    }
+}
@@ -0,0 +1,108 @@
+use super::{Handler, SelfMadeHandler};
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef},
+        queue::QueueEditGuard,
+        ClientState,
+    },
+    ircmsg::ServerMsg,
+};
+use std::{ops::ControlFlow, time::Instant};
+
+/// Error for when a [`WaitForState`] handler's deadline passed.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct StateTimeout;
+
+impl std::fmt::Display for StateTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "deadline passed while waiting for state")
+    }
+}
+
+impl std::error::Error for StateTimeout {}
+
+/// [`Handler`] that waits until client state satisfies some condition.
+///
+/// After every message, this handler calls its function with the current [`ClientState`]
+/// and yields the first non-`None` value it returns.
+/// The function is only called if state has been [inserted][ClientState::insert]
+/// since the last time it was called, so changes made using [`ClientState::get_mut`]
+/// are only noticed once something else is inserted.
+/// Changes made by other handlers for the same message may not be noticed
+/// until the next message is received.
+///
+/// The function runs under the same constraints as handlers: it must not block,
+/// and should be cheap, as it may be called on many messages.
+///
+/// See [`wait_for_state`] and [`wait_for_state_until`].
+pub struct WaitForState<T, F> {
+    f: F,
+    deadline: Option<Instant>,
+    generation: Option<u64>,
+    marker: std::marker::PhantomData<fn() -> T>,
+}
+
+/// Creates a [`WaitForState`] handler that waits indefinitely.
+pub fn wait_for_state<T, F>(f: F) -> WaitForState<T, F>
+where
+    F: FnMut(&ClientState) -> Option<T> + 'static + Send,
+{
+    WaitForState { f, deadline: None, generation: None, marker: std::marker::PhantomData }
+}
+
+/// Creates a [`WaitForState`] handler that gives up after `deadline`.
+///
+/// The deadline is only checked when a message is received,
+/// so a read timeout should also be configured.
+pub fn wait_for_state_until<T, F>(f: F, deadline: Instant) -> WaitForState<T, F>
+where
+    F: FnMut(&ClientState) -> Option<T> + 'static + Send,
+{
+    WaitForState { deadline: Some(deadline), ..wait_for_state(f) }
+}
+
+impl<T, F> Handler for WaitForState<T, F>
+where
+    T: 'static + Send,
+    F: FnMut(&ClientState) -> Option<T> + 'static + Send,
+{
+    type Value = Result<T, StateTimeout>;
+
+    fn handle(
+        &mut self,
+        _: &ServerMsg<'_>,
+        state: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        let generation = state.current_generation();
+        if self.generation != Some(generation) {
+            self.generation = Some(generation);
+            if let Some(value) = (self.f)(state) {
+                let _ = channel.send(Ok(value));
+                return ControlFlow::Break(());
+            }
+        }
+        if self.deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+            let _ = channel.send(Err(StateTimeout));
+            return ControlFlow::Break(());
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+impl<T, F> SelfMadeHandler for WaitForState<T, F>
+where
+    T: 'static + Send,
+    F: FnMut(&ClientState) -> Option<T> + 'static + Send,
+{
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
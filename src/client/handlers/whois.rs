@@ -0,0 +1,224 @@
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef},
+        queue::QueueEditGuard,
+        state::ISupport,
+        ClientState, Handler, MakeHandler,
+    },
+    ircmsg::{ClientMsg, ServerMsg, ServerMsgKindRaw, UserHost},
+    names::{cmd::WHOIS, isupport::PREFIX},
+    state::{ModeSet, StatusModes},
+    string::{tf::IrcCasemap, Arg, Line, Nick, User, Word},
+};
+use std::{num::NonZeroU8, ops::ControlFlow, time::Duration};
+
+/// The parsed reply to a `WHOIS` query.
+///
+/// [`WHOIS`] implements [`MakeHandler`] for [`Nick`]s,
+/// sending a query about that nick and yielding either this or a [`WhoisError`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Whois {
+    /// The nickname of the user, as spelled by the server.
+    pub nick: Nick<'static>,
+    /// The user's username and hostname, from `RPL_WHOISUSER` (311).
+    pub userhost: Option<UserHost<'static>>,
+    /// The user's realname, from `RPL_WHOISUSER` (311).
+    pub realname: Option<Line<'static>>,
+    /// The name of the server the user is connected to, from `RPL_WHOISSERVER` (312).
+    pub server: Option<Word<'static>>,
+    /// Whether the user is an IRC operator, from `RPL_WHOISOPERATOR` (313).
+    pub operator: bool,
+    /// How long the user has been idle, from `RPL_WHOISIDLE` (317).
+    pub idle: Option<Duration>,
+    /// When the user connected as a Unix timestamp, from `RPL_WHOISIDLE` (317).
+    pub signon: Option<u64>,
+    /// The channels the user is in along with their status modes in each,
+    /// from `RPL_WHOISCHANNELS` (319).
+    ///
+    /// Status prefixes are parsed using the server's `PREFIX` ISUPPORT token.
+    pub channels: Vec<(ModeSet, Arg<'static>)>,
+    /// The account the user is logged into, from `RPL_WHOISACCOUNT` (330).
+    pub account: Option<Arg<'static>>,
+    /// Whether the user is using a secure connection, from `RPL_WHOISSECURE` (671).
+    pub secure: bool,
+    /// Every other numeric about the user that was received before the end of the reply.
+    pub extras: Vec<ServerMsg<'static>>,
+}
+
+impl Whois {
+    /// Creates a new empty reply about the provided nick.
+    pub fn new(nick: Nick<'static>) -> Self {
+        Whois {
+            nick,
+            userhost: None,
+            realname: None,
+            server: None,
+            operator: false,
+            idle: None,
+            signon: None,
+            channels: Vec::new(),
+            account: None,
+            secure: false,
+            extras: Vec::new(),
+        }
+    }
+}
+
+/// Error yielded by the `WHOIS` handler if the server could not answer the query.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum WhoisError {
+    /// `ERR_NOSUCHNICK` (401): There is no user with the queried nick.
+    NoSuchNick(Nick<'static>),
+    /// `ERR_NOSUCHSERVER` (402): The server the query was directed at does not exist.
+    NoSuchServer(Arg<'static>),
+}
+
+impl std::fmt::Display for WhoisError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WhoisError::NoSuchNick(nick) => write!(f, "no such nick: {nick}"),
+            WhoisError::NoSuchServer(server) => write!(f, "no such server: {server}"),
+        }
+    }
+}
+
+impl std::error::Error for WhoisError {}
+
+fn parse_number(arg: Option<&Arg<'_>>) -> Option<u64> {
+    std::str::from_utf8(arg?.as_bytes()).ok()?.parse().ok()
+}
+
+/// [`Handler`] that collects the reply to one `WHOIS` query.
+struct WhoisHandler {
+    casemap: IrcCasemap,
+    status: StatusModes,
+    reply: Whois,
+}
+
+impl WhoisHandler {
+    fn is_target(&self, arg: Option<&Arg<'_>>) -> bool {
+        arg.is_some_and(|arg| arg.eq_ignore_case(&self.reply.nick, self.casemap))
+    }
+
+    fn add_channels(&mut self, channels: &Line<'_>) {
+        for channel in channels.as_bytes().split(|b| *b == b' ') {
+            let mut modes = ModeSet::new();
+            let mut name = channel;
+            while let Some((prefix, rest)) = name.split_first() {
+                let Some(mode) = NonZeroU8::new(*prefix).and_then(|p| self.status.get_mode(p))
+                else {
+                    break;
+                };
+                modes.set(mode);
+                name = rest;
+            }
+            if let Ok(name) = Arg::from_bytes(name.to_vec()) {
+                self.reply.channels.push((modes, name));
+            }
+        }
+    }
+}
+
+impl Handler for WhoisHandler {
+    type Value = Result<Whois, WhoisError>;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        _: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        if !matches!(msg.kind, ServerMsgKindRaw::Numeric(_))
+            || !self.is_target(msg.args.words().get(1))
+        {
+            return ControlFlow::Continue(());
+        }
+        let (args, last) = msg.args.split_last();
+        match msg.kind.as_str() {
+            // RPL_WHOISUSER
+            "311" => {
+                if let [_, nick, user, host, ..] = args {
+                    if let Ok(nick) = Nick::from_super(nick.clone()) {
+                        self.reply.nick = nick.owning();
+                    }
+                    let user = User::from_super(user.clone()).ok().map(User::owning);
+                    let host = Word::from(host.clone()).owning();
+                    self.reply.userhost = Some(UserHost { user, host });
+                    self.reply.realname = last.map(|line| line.clone().owning());
+                }
+            }
+            // RPL_WHOISSERVER
+            "312" => self.reply.server = args.get(2).map(|s| Word::from(s.clone()).owning()),
+            // RPL_WHOISOPERATOR
+            "313" => self.reply.operator = true,
+            // RPL_WHOISIDLE
+            "317" => {
+                self.reply.idle = parse_number(args.get(2)).map(Duration::from_secs);
+                self.reply.signon = parse_number(args.get(3));
+            }
+            // RPL_ENDOFWHOIS
+            "318" => {
+                let empty = Whois::new(self.reply.nick.clone());
+                let reply = std::mem::replace(&mut self.reply, empty);
+                let _ = channel.send(Ok(reply));
+                return ControlFlow::Break(());
+            }
+            // RPL_WHOISCHANNELS
+            "319" => {
+                if let Some(channels) = last {
+                    self.add_channels(channels);
+                }
+            }
+            // RPL_WHOISACCOUNT
+            "330" => self.reply.account = args.get(2).map(|a| a.clone().owning()),
+            // RPL_WHOISSECURE
+            "671" => self.reply.secure = true,
+            // ERR_NOSUCHNICK
+            "401" => {
+                let _ = channel.send(Err(WhoisError::NoSuchNick(self.reply.nick.clone())));
+                return ControlFlow::Break(());
+            }
+            // ERR_NOSUCHSERVER
+            "402" => {
+                let server = msg.args.words()[1].clone().owning();
+                let _ = channel.send(Err(WhoisError::NoSuchServer(server)));
+                return ControlFlow::Break(());
+            }
+            _ => self.reply.extras.push(msg.clone().owning()),
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+impl<'a> MakeHandler<Nick<'a>> for WHOIS {
+    type Value = Result<Whois, WhoisError>;
+
+    type Error = std::convert::Infallible;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        mut queue: QueueEditGuard<'_>,
+        nick: Nick<'a>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        let nick = nick.owning();
+        let mut msg = ClientMsg::new(WHOIS);
+        msg.args.edit().add_word(nick.clone());
+        queue.push(msg);
+        let isupport = state.get::<ISupport>();
+        let casemap = isupport.map(IrcCasemap::from_isupport).unwrap_or_default();
+        let status = isupport
+            .and_then(|isupport| isupport.get_parsed(PREFIX)?.ok())
+            .unwrap_or_else(|| StatusModes::parse(b"(ov)@+").unwrap_or_default());
+        Ok(Box::new(WhoisHandler { casemap, status, reply: Whois::new(nick) }))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
@@ -0,0 +1,364 @@
+use crate::{
+    client::{
+        channel::{ChannelSpec, Sender, SenderRef},
+        queue::QueueEditGuard,
+        state::ISupport,
+        ClientState, Handler, MakeHandler, NoHandler,
+    },
+    ircmsg::{ClientMsg, ServerMsg},
+    names::{
+        cmd::WHO,
+        isupport::{PREFIX, WHOX},
+    },
+    state::{ModeSet, StatusModes},
+    string::{tf::IrcCasemap, Arg, Line, Nick, User, Word},
+};
+use std::{num::NonZeroU8, ops::ControlFlow, time::Duration};
+
+/// A field that can be requested in a WHOX query.
+///
+/// Fields are listed in the order that servers send them in `RPL_WHOSPCRPL` (354),
+/// after the query token.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[repr(u8)]
+pub enum WhoxField {
+    /// `c`: A channel the user is in, or `*`.
+    Channel,
+    /// `u`: The user's username.
+    User,
+    /// `i`: The user's IP address.
+    Ip,
+    /// `h`: The user's hostname.
+    Host,
+    /// `s`: The name of the server the user is connected to.
+    Server,
+    /// `n`: The user's nick.
+    Nick,
+    /// `f`: The user's flags, such as whether they are away.
+    Flags,
+    /// `d`: The number of hops to the user's server.
+    Hopcount,
+    /// `l`: How long the user has been idle, in seconds.
+    Idle,
+    /// `a`: The account the user is logged into, or `0`.
+    Account,
+    /// `o`: The user's op level in the channel.
+    OpLevel,
+    /// `r`: The user's realname.
+    Realname,
+}
+
+impl WhoxField {
+    /// All of the fields, in order.
+    pub const ALL: [WhoxField; 12] = [
+        WhoxField::Channel,
+        WhoxField::User,
+        WhoxField::Ip,
+        WhoxField::Host,
+        WhoxField::Server,
+        WhoxField::Nick,
+        WhoxField::Flags,
+        WhoxField::Hopcount,
+        WhoxField::Idle,
+        WhoxField::Account,
+        WhoxField::OpLevel,
+        WhoxField::Realname,
+    ];
+    /// Returns the letter used to request this field.
+    pub const fn letter(self) -> u8 {
+        b"cuihsnfdlaor"[self as usize]
+    }
+    /// Returns the field requested using the provided letter, if any.
+    pub fn from_letter(letter: u8) -> Option<Self> {
+        WhoxField::ALL.into_iter().find(|field| field.letter() == letter)
+    }
+    const fn mask(self) -> u16 {
+        1 << self as u8
+    }
+}
+
+/// A set of [`WhoxField`]s.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct WhoxFields(u16);
+
+impl WhoxFields {
+    /// Creates a new, empty `WhoxFields`.
+    pub const fn new() -> Self {
+        WhoxFields(0)
+    }
+    /// Returns a version of `self` with the provided field added.
+    pub const fn with(self, field: WhoxField) -> Self {
+        WhoxFields(self.0 | field.mask())
+    }
+    /// Tests if a field is in this set.
+    pub const fn contains(&self, field: WhoxField) -> bool {
+        self.0 & field.mask() != 0
+    }
+    /// Returns `true` if this set is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+    /// Returns the number of fields in this set.
+    pub const fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+    /// Returns an iterator over the fields in this set, in the order servers send them.
+    pub fn iter(&self) -> impl Iterator<Item = WhoxField> + '_ {
+        WhoxField::ALL.into_iter().filter(|field| self.contains(*field))
+    }
+}
+
+impl FromIterator<WhoxField> for WhoxFields {
+    fn from_iter<T: IntoIterator<Item = WhoxField>>(iter: T) -> Self {
+        iter.into_iter().fold(WhoxFields::new(), WhoxFields::with)
+    }
+}
+
+/// A `WHO` query using the WHOX extension.
+///
+/// [`WHO`] implements [`MakeHandler`] for this type,
+/// sending the query and yielding one [`WhoxReply`] per `RPL_WHOSPCRPL` (354)
+/// once `RPL_ENDOFWHO` (315) is received.
+/// Making the handler fails with [`NoHandler`] if the server does not advertise
+/// the `WHOX` ISUPPORT token or if the token is larger than 999.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Whox<'a> {
+    /// The mask to query, usually a channel or nick.
+    pub mask: Arg<'a>,
+    /// The fields to request.
+    pub fields: WhoxFields,
+    /// A number to identify replies to this query, at most 999.
+    ///
+    /// Without a token, replies to other WHOX queries with the same number of fields
+    /// cannot be told apart from replies to this one.
+    pub token: Option<u16>,
+}
+
+impl<'a> Whox<'a> {
+    /// Creates a query for the provided fields without a token.
+    pub fn new(mask: Arg<'a>, fields: WhoxFields) -> Self {
+        Whox { mask, fields, token: None }
+    }
+    /// Returns a version of `self` with the provided token.
+    pub fn with_token(mut self, token: u16) -> Self {
+        self.token = Some(token);
+        self
+    }
+    /// Returns the field specifier argument for this query, e.g. `%tnf,42`.
+    pub fn specifier(&self) -> Option<Arg<'static>> {
+        let mut spec = vec![b'%'];
+        if self.token.is_some() {
+            spec.push(b't');
+        }
+        spec.extend(self.fields.iter().map(WhoxField::letter));
+        if let Some(token) = self.token {
+            if token > 999 {
+                return None;
+            }
+            spec.extend_from_slice(format!(",{token}").as_bytes());
+        }
+        Arg::from_bytes(spec).ok()
+    }
+}
+
+/// The flags of a user in a [`WhoxReply`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct WhoFlags {
+    /// Whether the user is away (`G`) rather than here (`H`).
+    pub away: bool,
+    /// Whether the user is an IRC operator (`*`).
+    pub oper: bool,
+    /// The user's status modes in the channel of the reply.
+    ///
+    /// Status prefixes are parsed using the server's `PREFIX` ISUPPORT token.
+    pub status: ModeSet,
+}
+
+impl WhoFlags {
+    /// Parses flags from a `WHO` reply.
+    ///
+    /// Unknown flags are ignored.
+    pub fn parse(flags: &[u8], status: &StatusModes) -> Self {
+        let mut retval = WhoFlags::default();
+        for flag in flags {
+            match flag {
+                b'G' => retval.away = true,
+                b'*' => retval.oper = true,
+                flag => {
+                    if let Some(mode) = NonZeroU8::new(*flag).and_then(|p| status.get_mode(p)) {
+                        retval.status.set(mode);
+                    }
+                }
+            }
+        }
+        retval
+    }
+}
+
+/// One reply to a [`Whox`] query.
+///
+/// Fields that were not requested are `None`.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct WhoxReply {
+    /// The channel that the reply is about, unless it is `*`.
+    pub channel: Option<Arg<'static>>,
+    /// The user's username.
+    pub user: Option<User<'static>>,
+    /// The user's IP address.
+    pub ip: Option<Word<'static>>,
+    /// The user's hostname.
+    pub host: Option<Word<'static>>,
+    /// The name of the server the user is connected to.
+    pub server: Option<Word<'static>>,
+    /// The user's nick.
+    pub nick: Option<Nick<'static>>,
+    /// The user's flags.
+    pub flags: Option<WhoFlags>,
+    /// The number of hops to the user's server.
+    pub hopcount: Option<u32>,
+    /// How long the user has been idle.
+    pub idle: Option<Duration>,
+    /// The account the user is logged into.
+    ///
+    /// This is also `None` if the user is not logged in.
+    pub account: Option<Arg<'static>>,
+    /// The user's op level in the channel.
+    pub oplevel: Option<Arg<'static>>,
+    /// The user's realname.
+    pub realname: Option<Line<'static>>,
+}
+
+impl WhoxReply {
+    /// Parses the reply from the provided `RPL_WHOSPCRPL` (354),
+    /// or returns `None` if it was not sent in reply to the provided query.
+    ///
+    /// This checks the token (if any) and the number of fields.
+    pub fn parse(msg: &ServerMsg<'_>, query: &Whox<'_>, status: &StatusModes) -> Option<Self> {
+        if msg.kind.as_str() != "354" {
+            return None;
+        }
+        let (words, last) = msg.args.split_last();
+        let values = words.iter().map(|arg| arg.as_bytes()).chain(last.map(|line| line.as_bytes()));
+        // The first argument is the client's nick.
+        let values: Vec<_> = values.skip(1).collect();
+        if values.len() != query.fields.len() + query.token.is_some() as usize {
+            return None;
+        }
+        let mut values = values.into_iter();
+        if let Some(token) = query.token {
+            if values.next()? != token.to_string().as_bytes() {
+                return None;
+            }
+        }
+        fn parse<T>(value: &[u8]) -> Option<T>
+        where
+            T: TryFrom<Vec<u8>>,
+        {
+            T::try_from(value.to_vec()).ok()
+        }
+        fn number(value: &[u8]) -> Option<u32> {
+            std::str::from_utf8(value).ok()?.parse().ok()
+        }
+        let mut reply = WhoxReply::default();
+        for (field, value) in query.fields.iter().zip(values) {
+            match field {
+                WhoxField::Channel => reply.channel = parse(value).filter(|c: &Arg| c != "*"),
+                WhoxField::User => reply.user = parse(value),
+                WhoxField::Ip => reply.ip = parse(value),
+                WhoxField::Host => reply.host = parse(value),
+                WhoxField::Server => reply.server = parse(value),
+                WhoxField::Nick => reply.nick = parse(value),
+                WhoxField::Flags => reply.flags = Some(WhoFlags::parse(value, status)),
+                WhoxField::Hopcount => reply.hopcount = number(value),
+                WhoxField::Idle => {
+                    reply.idle = number(value).map(|s| Duration::from_secs(s.into()));
+                }
+                WhoxField::Account => reply.account = parse(value).filter(|a: &Arg| a != "0"),
+                WhoxField::OpLevel => reply.oplevel = parse(value),
+                WhoxField::Realname => reply.realname = parse(value),
+            }
+        }
+        Some(reply)
+    }
+}
+
+/// [`Handler`] that collects the replies to one WHOX query.
+struct WhoxHandler {
+    casemap: IrcCasemap,
+    status: StatusModes,
+    query: Whox<'static>,
+    replies: Vec<WhoxReply>,
+}
+
+impl Handler for WhoxHandler {
+    type Value = Vec<WhoxReply>;
+
+    fn handle(
+        &mut self,
+        msg: &ServerMsg<'_>,
+        _: &mut ClientState,
+        _: QueueEditGuard<'_>,
+        mut channel: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        match msg.kind.as_str() {
+            // RPL_WHOSPCRPL
+            "354" => {
+                if let Some(reply) = WhoxReply::parse(msg, &self.query, &self.status) {
+                    self.replies.push(reply);
+                }
+            }
+            // RPL_ENDOFWHO
+            "315" => {
+                let is_ours = msg
+                    .args
+                    .words()
+                    .get(1)
+                    .is_some_and(|mask| mask.eq_ignore_case(&self.query.mask, self.casemap));
+                if is_ours {
+                    let _ = channel.send(std::mem::take(&mut self.replies));
+                    return ControlFlow::Break(());
+                }
+            }
+            _ => (),
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+impl<'a> MakeHandler<Whox<'a>> for WHO {
+    type Value = Vec<WhoxReply>;
+
+    type Error = NoHandler;
+
+    type Receiver<Spec: ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        state: &ClientState,
+        mut queue: QueueEditGuard<'_>,
+        query: Whox<'a>,
+    ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+        let isupport = state.get::<ISupport>();
+        if isupport.and_then(|isupport| isupport.get_union(WHOX)).is_none() {
+            return Err(NoHandler);
+        }
+        let specifier = query.specifier().ok_or(NoHandler)?;
+        let query = Whox { mask: query.mask.owning(), fields: query.fields, token: query.token };
+        let mut msg = ClientMsg::new(WHO);
+        let mut args = msg.args.edit();
+        args.add_word(query.mask.clone());
+        args.add_word(specifier);
+        queue.push(msg);
+        let casemap = isupport.map(IrcCasemap::from_isupport).unwrap_or_default();
+        let status = isupport
+            .and_then(|isupport| isupport.get_parsed(PREFIX)?.ok())
+            .unwrap_or_else(|| StatusModes::parse(b"(ov)@+").unwrap_or_default());
+        Ok(Box::new(WhoxHandler { casemap, status, query, replies: Vec::new() }))
+    }
+
+    fn make_channel<Spec: ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        spec.new_oneshot()
+    }
+}
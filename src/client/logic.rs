@@ -1,12 +1,19 @@
-use std::{any::Any, num::NonZeroUsize};
+use std::{
+    any::{Any, TypeId},
+    num::NonZeroUsize,
+};
 
-use crate::ircmsg::Source;
+use crate::{
+    ircmsg::{ClientCodec, ClientMsg, OverlongPolicy, Source},
+    names::cmd::QUIT,
+    string::Line,
+};
 
 use super::{
     channel::{ChannelSpec, Sender},
     conn::TimeLimits,
     state::ClientStateKey,
-    Handlers, MakeHandler, Queue,
+    Handler, Handlers, MakeHandler, Queue,
 };
 
 /// The parts of client logic that are not dependent on the type of connection or channel spec.
@@ -20,6 +27,20 @@ pub struct ClientLogic {
     pub(super) state: ClientState,
     /// Collection of handlers.
     pub(super) handlers: Handlers,
+    /// Whether the client is intentionally disconnecting.
+    pub(super) shutdown: bool,
+    /// The reason from the last `ERROR` message sent by the server, if any.
+    pub(super) server_error: Option<Line<'static>>,
+    /// Counters for received lines.
+    pub(super) reads: super::conn::ReadStats,
+    /// How to handle overlong lines.
+    pub(super) overlong: OverlongPolicy,
+    /// Callback for dropped lines.
+    #[allow(clippy::type_complexity)]
+    pub(super) on_drop: Option<Box<dyn FnMut(&super::conn::DroppedLine<'_>) + Send>>,
+    /// Recent run loop timings.
+    #[cfg(feature = "diagnostics")]
+    pub(super) timings: super::diagnostics::LoopRecorder,
 }
 
 impl ClientLogic {
@@ -106,8 +127,103 @@ impl ClientLogic {
         make_handler: M,
         value: T,
     ) -> Result<usize, M::Error> {
-        let handler = make_handler.make_handler(&self.state, self.queue.edit(), value)?;
-        Ok(self.handlers.add(handler, sender))
+        self.add_with_sender_and_priority(sender, 0, make_handler, value)
+    }
+
+    /// Adds a handler using an existing channel and the provided priority.
+    ///
+    /// Handlers with higher priorities process each message before handlers with lower ones.
+    /// Handlers added using the other `add` methods have a priority of `0`.
+    /// See [`Handler`][super::Handler]'s documentation for more information on ordering.
+    ///
+    /// Returns the handler id.
+    pub fn add_with_sender_and_priority<T, M: MakeHandler<T>>(
+        &mut self,
+        sender: Box<dyn Sender<Value = M::Value> + Send>,
+        priority: i32,
+        make_handler: M,
+        value: T,
+    ) -> Result<usize, M::Error> {
+        let producer = super::queue::Producer::Handler(self.handlers.next_id());
+        let handler =
+            make_handler.make_handler(&self.state, self.queue.edit_as(producer), value)?;
+        #[cfg(feature = "diagnostics")]
+        let name = handler.name();
+        let id = self.handlers.add(handler, sender, priority);
+        #[cfg(feature = "diagnostics")]
+        self.timings.add_handler(id, name, |id| self.handlers.contains(id));
+        Ok(id)
+    }
+
+    /// Makes a handler without adding it, queueing its messages.
+    ///
+    /// This is intended for handlers that will be part of a
+    /// [combinator][super::combinator], which should be added
+    /// before any other handler is added.
+    /// Messages queued by the handler are attributed to the next added handler.
+    pub fn make_handler<T, M: MakeHandler<T>>(
+        &mut self,
+        make_handler: M,
+        value: T,
+    ) -> Result<Box<dyn Handler<Value = M::Value>>, M::Error> {
+        let producer = super::queue::Producer::Handler(self.handlers.next_id());
+        make_handler.make_handler(&self.state, self.queue.edit_as(producer), value)
+    }
+
+    /// Adds a handler that expires after `timeout` if it has not finished by then.
+    /// Creates a new channel using the provided [`ChannelSpec`].
+    ///
+    /// Expired handlers are reported as finished by the `run` methods
+    /// and have [`Handler::expire`][super::Handler::expire] called on them.
+    /// By default, this cancels them, closing their channels.
+    ///
+    /// Returns the handler id and the receiver half of the channel.
+    pub fn add_with_spec_timeout<T, M: MakeHandler<T>, S2: ChannelSpec>(
+        &mut self,
+        chanspec: &S2,
+        timeout: std::time::Duration,
+        make_handler: M,
+        value: T,
+    ) -> Result<(usize, M::Receiver<S2>), M::Error> {
+        let (id, recv) = self.add_with_spec(chanspec, make_handler, value)?;
+        let deadline = std::time::Instant::now().checked_add(timeout);
+        self.handlers.set_deadline(id, deadline);
+        Ok((id, recv))
+    }
+
+    /// Cancels the handler with the provided id.
+    ///
+    /// The handler's [`cancel`][super::Handler::cancel] method is called,
+    /// after which it is dropped along with its channel's sender, closing the channel.
+    /// Returns `false` if there is no handler with that id.
+    pub fn cancel_handler(&mut self, id: usize) -> bool {
+        let cancelled = self.handlers.cancel_id(id);
+        if cancelled {
+            self.queue.retire_handler(id);
+        }
+        cancelled
+    }
+    /// Cancels all handlers.
+    fn cancel_handlers(&mut self) {
+        self.handlers.cancel();
+        self.queue.retire_handlers();
+    }
+    /// Lets the queue forget about handlers that finished during the last run.
+    fn retire_finished(&mut self, finished_at: usize) -> usize {
+        for id in self.handlers.last_run_results(finished_at).1 {
+            self.queue.retire_handler(*id);
+        }
+        finished_at
+    }
+
+    /// Returns information about every active handler,
+    /// in the order that they process messages.
+    ///
+    /// Each item is a handler's id, its [name][super::Handler::name],
+    /// and whether it [wants owning messages][super::Handler::wants_owning].
+    /// This is intended for debugging, such as finding out which handlers are still running.
+    pub fn handler_info(&self) -> impl Iterator<Item = (usize, &str, bool)> + '_ {
+        self.handlers.iter_info()
     }
 
     /// Resets state to when the connection was just opened.
@@ -117,9 +233,132 @@ impl ClientLogic {
     /// Does not reset any state that is considered configuration,
     /// such as what the queue's rate limits are.
     pub fn reset(&mut self) {
-        self.handlers.cancel();
+        self.cancel_handlers();
         self.queue.reset();
         self.state.clear();
+        self.shutdown = false;
+        self.server_error = None;
+    }
+
+    /// Marks the client as intentionally disconnecting, with an optional reason.
+    ///
+    /// Once called, I/O errors and EOF returned by the `run` and `flush` methods are
+    /// converted into [`ConnectionClosed`][super::conn::ConnectionClosed] errors.
+    /// When this happens, all handlers are cancelled, closing their channels.
+    /// Handlers are not cancelled before then, so they can still process the server's replies.
+    /// The reason is stored as [`Shutdown`][super::state::Shutdown],
+    /// which can be used to tell these cancellations apart from others.
+    ///
+    /// This does not send anything to the server; see [`ClientLogic::quit`] for that.
+    pub fn begin_shutdown(&mut self, reason: Option<Line<'static>>) {
+        self.shutdown = true;
+        self.state.insert::<super::state::Shutdown>(reason);
+    }
+
+    /// Queues a QUIT message with an optional reason and [begins shutdown][Self::begin_shutdown].
+    pub fn quit(&mut self, reason: Option<Line<'static>>) {
+        let mut msg = ClientMsg::new(QUIT);
+        if let Some(reason) = &reason {
+            msg.args.edit().add(reason.clone());
+        }
+        self.queue.edit().push(msg);
+        self.begin_shutdown(reason);
+    }
+
+    /// Cancels all handlers, discards all queued messages,
+    /// and [begins shutdown][Self::begin_shutdown].
+    ///
+    /// Returns a QUIT message to be sent immediately, bypassing the queue.
+    pub(super) fn begin_disconnect(&mut self, reason: Option<Line<'static>>) -> ClientMsg<'static> {
+        self.cancel_handlers();
+        self.queue.clear();
+        let mut msg = ClientMsg::new(QUIT);
+        if let Some(reason) = &reason {
+            msg.args.edit().add(reason.clone());
+        }
+        self.begin_shutdown(reason);
+        msg
+    }
+
+    /// Returns how long to wait for the server to close the connection after a QUIT.
+    pub(super) fn disconnect_timeout(&self) -> std::time::Duration {
+        self.timeout.read_timeout().unwrap_or(std::time::Duration::from_secs(10))
+    }
+
+    /// Returns `true` if [`begin_shutdown`][Self::begin_shutdown] has been called
+    /// since the client was last reset.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown
+    }
+
+    /// Converts an I/O error into a [`ConnectionClosed`][super::conn::ConnectionClosed]
+    /// if the client is shutting down or the server sent `ERROR`, cancelling all handlers.
+    pub(super) fn filter_io_error(&mut self, e: std::io::Error) -> std::io::Error {
+        use super::conn::ConnectionClosed;
+        if ConnectionClosed::from_io(&e).is_some() {
+            return e;
+        }
+        let closed = if self.shutdown {
+            let reason = self.state.get::<super::state::Shutdown>().cloned().flatten();
+            ConnectionClosed { by_server: false, reason }
+        } else if let Some(reason) = self.server_error.take() {
+            ConnectionClosed { by_server: true, reason: Some(reason) }
+        } else {
+            return e;
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(target: "vinezombie::conn", "{closed}: {e}");
+        self.cancel_handlers();
+        closed.into()
+    }
+
+    /// Returns counters for the lines received from the server.
+    ///
+    /// These are not cleared by [`reset`][Self::reset].
+    pub fn read_stats(&self) -> &super::conn::ReadStats {
+        &self.reads
+    }
+
+    /// Sets the closure that is called for each line from the server that is dropped.
+    ///
+    /// Lines that are too long or cannot be parsed are dropped instead of causing
+    /// the `run` methods to fail. Dropped lines are always counted in
+    /// [`read_stats`][Self::read_stats], and are logged at the warn level
+    /// if the `tracing` feature is enabled.
+    ///
+    /// While this closure is set, the `run` methods always read borrowing messages
+    /// so that excerpts of dropped lines are available.
+    pub fn set_drop_fn(
+        &mut self,
+        f: Option<impl FnMut(&super::conn::DroppedLine<'_>) + 'static + Send>,
+    ) {
+        // Need the manual map for coersion into dyn.
+        if let Some(f) = f {
+            self.on_drop = Some(Box::new(f));
+        } else {
+            self.on_drop = None;
+        }
+    }
+
+    /// Sets how lines from the server that are longer than
+    /// [`ServerMsg::MAX_LEN`][crate::ircmsg::ServerMsg::MAX_LEN] are handled.
+    ///
+    /// With [`OverlongPolicy::Truncate`], such lines are parsed up to the limit,
+    /// the rest of each line is discarded, and they are counted as parsed.
+    /// Otherwise, they are dropped as described in [`set_drop_fn`][Self::set_drop_fn],
+    /// as the `run` methods never fail because of one line.
+    pub fn set_overlong_policy(&mut self, policy: OverlongPolicy) {
+        self.overlong = policy;
+    }
+
+    /// Returns the codec to use for reading messages in the `run` methods.
+    pub(super) fn read_codec(&self) -> ClientCodec {
+        let overlong = match self.overlong {
+            OverlongPolicy::Truncate => OverlongPolicy::Truncate,
+            // Skipping is handled by the caller so that dropped lines are reported.
+            OverlongPolicy::Error | OverlongPolicy::Skip => OverlongPolicy::Error,
+        };
+        ClientCodec { overlong, ..ClientCodec::new() }
     }
 
     /// Returns `true` if the client has handlers or queued messages.
@@ -127,10 +366,82 @@ impl ClientLogic {
         !self.handlers.is_empty() || !self.queue.is_empty()
     }
 
+    /// Returns a summary of how long recent iterations of the run loop took.
+    ///
+    /// See the [`diagnostics`][super::diagnostics] module for more information.
+    #[cfg(feature = "diagnostics")]
+    pub fn loop_timings(&self) -> super::diagnostics::LoopTimings {
+        self.timings.summarize()
+    }
+
+    /// Returns timing information for one handler, if any has been recorded for it.
+    ///
+    /// This is a cheaper alternative to [`loop_timings`][ClientLogic::loop_timings]
+    /// for checking on handlers listed by [`handler_info`][ClientLogic::handler_info].
+    #[cfg(feature = "diagnostics")]
+    pub fn handler_timings(&self, id: usize) -> Option<super::diagnostics::HandlerTimings> {
+        self.timings.summarize_handler(id)
+    }
+
+    /// Discards all recorded run loop timings.
+    #[cfg(feature = "diagnostics")]
+    pub fn clear_loop_timings(&mut self) {
+        self.timings.clear();
+    }
+
     /// Processes one message from the server.
     pub(super) fn run_once(&mut self, msg: &crate::ircmsg::ServerMsg<'_>) -> usize {
+        #[cfg(feature = "diagnostics")]
+        let start = std::time::Instant::now();
+        if msg.kind == crate::names::cmd::ERROR {
+            let reason = msg.args.split_last().1.cloned().unwrap_or_default();
+            self.server_error = Some(reason.owning());
+        }
         self.queue.adjust(msg);
-        self.handlers.handle(msg, &mut self.state, &mut self.queue)
+        let finished_at = self.handlers.handle(
+            msg,
+            &mut self.state,
+            &mut self.queue,
+            #[cfg(feature = "diagnostics")]
+            &mut self.timings,
+        );
+        #[cfg(feature = "diagnostics")]
+        self.timings.record(super::diagnostics::LoopPhase::Dispatch, start);
+        self.retire_finished(finished_at)
+    }
+
+    /// Expires handlers whose deadlines have passed.
+    pub(super) fn expire_handlers(&mut self) -> usize {
+        let finished_at = self.handlers.expire(std::time::Instant::now());
+        self.retire_finished(finished_at)
+    }
+
+    /// Shortens `wait_for` so that reads time out by the next handler deadline, if any.
+    pub(super) fn wait_for_deadline(
+        &self,
+        wait_for: Option<std::time::Duration>,
+    ) -> Option<std::time::Duration> {
+        let Some(deadline) = self.handlers.next_deadline() else {
+            return wait_for;
+        };
+        let until = deadline.saturating_duration_since(std::time::Instant::now());
+        match (wait_for, self.timeout.read_timeout()) {
+            (Some(wait_for), _) => Some(wait_for.min(until)),
+            // Let the read timeout apply as normal if it comes first.
+            (None, Some(read_timeout)) if read_timeout <= until => None,
+            (None, _) => Some(until),
+        }
+    }
+
+    /// Lets handlers act on a read timeout.
+    pub(super) fn run_timeout(&mut self) -> usize {
+        let finished_at = self.handlers.handle_timeout(
+            &mut self.state,
+            &mut self.queue,
+            #[cfg(feature = "diagnostics")]
+            &mut self.timings,
+        );
+        self.retire_finished(finished_at)
     }
 }
 
@@ -145,14 +456,26 @@ const DEFAULT_SOURCE_LEN: NonZeroUsize = unsafe { NonZeroUsize::new_unchecked(85
 /// add new elements of state at runtime.
 ///
 /// This type intentionally offers no way for state to be removed.
+///
+/// Every insertion is tagged with a generation number that increases monotonically,
+/// allowing code that polls this state to cheaply tell if something was replaced.
+/// Related pieces of state should be inserted together using [`ClientState::update`].
 pub struct ClientState {
     source_len: NonZeroUsize,
-    state: crate::util::FlatMap<(std::any::TypeId, Box<dyn Any + Send + Sync>)>,
+    generation: u64,
+    state: crate::util::FlatMap<(TypeId, StateEntry)>,
+}
+
+type BoxAny = Box<dyn Any + Send + Sync>;
+
+struct StateEntry {
+    generation: u64,
+    value: BoxAny,
 }
 
 macro_rules! lookup {
     ($this:ident.$getter:ident::<$key:ty>().$downcast:ident()) => {{
-        $this.state.$getter(&<$key>::default().type_id()).and_then(|v| v.1.$downcast())
+        $this.state.$getter(&<$key>::default().type_id()).and_then(|v| v.1.value.$downcast())
     }};
 }
 
@@ -203,7 +526,11 @@ fn calc_source_len(cs: &ClientState, source: Option<&Source>, trust_notilde: boo
 impl ClientState {
     /// Returns a new, empty `ClientState`.
     pub const fn new() -> ClientState {
-        ClientState { source_len: DEFAULT_SOURCE_LEN, state: crate::util::FlatMap::new() }
+        ClientState {
+            source_len: DEFAULT_SOURCE_LEN,
+            generation: 0,
+            state: crate::util::FlatMap::new(),
+        }
     }
     /// Gets a shared reference to the state denoted by `K`, if any.
     pub fn get<K: ClientStateKey>(&self) -> Option<&K::Value> {
@@ -213,11 +540,57 @@ impl ClientState {
     pub fn get_mut<K: ClientStateKey>(&mut self) -> Option<&mut K::Value> {
         lookup!(self.get_mut::<K>().downcast_mut())
     }
+    /// Returns the generation of the state denoted by `K`, if any.
+    ///
+    /// This value changes every time the state is inserted,
+    /// but not when it is modified using [`ClientState::get_mut`].
+    pub fn generation<K: ClientStateKey>(&self) -> Option<u64> {
+        self.state.get(&K::default().type_id()).map(|v| v.1.generation)
+    }
+    /// Returns the generation of the most-recently inserted state.
+    ///
+    /// If this value is unchanged, nothing has been inserted since.
+    pub fn current_generation(&self) -> u64 {
+        self.generation
+    }
     /// Sets the state denoted by `K` to `value`.
     ///
     /// This should be called infrequently. Prefer [`ClientState::get_mut`] for most updates.
+    /// If multiple related pieces of state need to be set at once,
+    /// use [`ClientState::update`] instead.
     pub fn insert<K: ClientStateKey>(&mut self, value: K::Value) {
-        self.state.edit().insert((K::default().type_id(), Box::new(value)));
+        self.generation += 1;
+        let entry = StateEntry { generation: self.generation, value: Box::new(value) };
+        self.state.edit().insert((K::default().type_id(), entry));
+    }
+    /// Updates multiple pieces of state at once.
+    ///
+    /// `f` is provided a [`ClientStateTxn`] into which changes are staged.
+    /// Once `f` returns, all of the staged changes are applied together,
+    /// followed by recalculating the assumed source length if requested.
+    /// All state inserted by one update shares the same generation.
+    ///
+    /// Updates cannot be nested, as `self` is exclusively borrowed for the duration of `f`.
+    pub fn update<T>(&mut self, f: impl FnOnce(&mut ClientStateTxn<'_>) -> T) -> T {
+        let mut txn =
+            ClientStateTxn { state: self, staged: Vec::new(), source_len: SourceLenUpdate::Keep };
+        let retval = f(&mut txn);
+        let ClientStateTxn { staged, source_len, .. } = txn;
+        if !staged.is_empty() {
+            self.generation += 1;
+            let mut edit = self.state.edit();
+            for (id, value) in staged {
+                edit.insert((id, StateEntry { generation: self.generation, value }));
+            }
+        }
+        match source_len {
+            SourceLenUpdate::Keep => (),
+            SourceLenUpdate::Set(len) => self.source_len = len,
+            SourceLenUpdate::Recalculate => {
+                self.update_source_len();
+            }
+        }
+        retval
     }
     /// Clears all state.
     pub(super) fn clear(&mut self) {
@@ -262,3 +635,55 @@ impl Default for ClientState {
         Self::new()
     }
 }
+
+#[derive(Clone, Copy, Debug)]
+enum SourceLenUpdate {
+    Keep,
+    Set(NonZeroUsize),
+    Recalculate,
+}
+
+/// A set of changes to [`ClientState`] that are applied all at once.
+///
+/// See [`ClientState::update`].
+pub struct ClientStateTxn<'a> {
+    state: &'a ClientState,
+    staged: Vec<(TypeId, BoxAny)>,
+    source_len: SourceLenUpdate,
+}
+
+impl ClientStateTxn<'_> {
+    /// Gets a shared reference to the state denoted by `K`, if any.
+    ///
+    /// Returns the staged value if one exists, otherwise returns the current value.
+    pub fn get<K: ClientStateKey>(&self) -> Option<&K::Value> {
+        let id = K::default().type_id();
+        if let Some((_, staged)) = self.staged.iter().rev().find(|(k, _)| *k == id) {
+            return staged.downcast_ref();
+        }
+        self.state.get::<K>()
+    }
+    /// Stages setting the state denoted by `K` to `value`.
+    pub fn insert<K: ClientStateKey>(&mut self, value: K::Value) {
+        let id = K::default().type_id();
+        let value: BoxAny = Box::new(value);
+        if let Some((_, staged)) = self.staged.iter_mut().find(|(k, _)| *k == id) {
+            *staged = value;
+        } else {
+            self.staged.push((id, value));
+        }
+    }
+    /// Stages setting the length that clients should assume for the length of their `source`.
+    ///
+    /// This overrides any previous call to [`update_source_len`][Self::update_source_len].
+    pub fn set_source_len(&mut self, len: NonZeroUsize) {
+        self.source_len = SourceLenUpdate::Set(len);
+    }
+    /// Requests that the assumed source length be recalculated
+    /// after all staged changes have been applied.
+    ///
+    /// See [`ClientState::update_source_len`].
+    pub fn update_source_len(&mut self) {
+        self.source_len = SourceLenUpdate::Recalculate;
+    }
+}
@@ -0,0 +1,98 @@
+//! Utilities for sending text messages.
+//!
+//! [`PRIVMSG`] and [`NOTICE`] implement [`MakeHandler`] for pairs of a target and text,
+//! allowing text messages to be sent using [`Client::add`][super::Client::add].
+//! The text is split over multiple messages as needed using [`split_text`].
+//! No handler is ever created, so [`NoHandler`] is returned on success.
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    channel::{ChannelSpec, ClosedSender, Sender},
+    queue::QueueEditGuard,
+    ClientMsgSink, ClientState, Handler, MakeHandler, NoHandler,
+};
+use crate::{
+    ircmsg::{Args, ClientMsg},
+    names::cmd::{NOTICE, PRIVMSG},
+    string::{Arg, Cmd, Line},
+};
+use std::num::NonZeroUsize;
+
+/// Returns the number of bytes of text that fit in one message
+/// with the provided command and target.
+///
+/// `source_len` is the assumed length of the client's source,
+/// as returned by [`ClientState::source_len`].
+pub fn text_room(cmd: &Cmd<'_>, target: &Arg<'_>, source_len: NonZeroUsize) -> usize {
+    let args = Args::new(std::slice::from_ref(target), Some(Line::default()));
+    let left = crate::ircmsg::bytes_left(cmd, Some(source_len), &args);
+    left.try_into().unwrap_or_default()
+}
+
+/// Splits `text` into pieces of at most `max` bytes.
+///
+/// This is [`Line::chunks`] except that one empty piece is returned if `text` is empty.
+/// Splits are made at the last run of ASCII whitespace that fits, which is removed,
+/// and never in the middle of a UTF-8 sequence.
+/// See [`WrapAt`][crate::string::tf::WrapAt] for details.
+pub fn split_text(text: &Line<'_>, max: usize) -> Vec<Line<'static>> {
+    if text.is_empty() {
+        return vec![Line::default()];
+    }
+    text.chunks(max).map(Line::owning).collect()
+}
+
+/// Sends `text` to `target` using `cmd`, splitting it over multiple messages if needed.
+///
+/// `source_len` is used to determine how much text fits in each message;
+/// see [`text_room`].
+pub fn send_text(
+    cmd: Cmd<'static>,
+    target: Arg<'_>,
+    text: &Line<'_>,
+    source_len: NonZeroUsize,
+    mut sink: impl ClientMsgSink<'static>,
+) {
+    let target = target.owning();
+    let max = text_room(&cmd, &target, source_len);
+    for piece in split_text(text, max) {
+        let mut msg = ClientMsg::new_cmd(cmd.clone());
+        let mut args = msg.args.edit();
+        args.add_word(target.clone());
+        args.add(piece);
+        sink.send(msg);
+    }
+}
+
+macro_rules! impl_send_text {
+    ($name:ident) => {
+        impl<'a, 'b> MakeHandler<(Arg<'a>, Line<'b>)> for $name {
+            type Value = ();
+
+            type Error = NoHandler;
+
+            type Receiver<Spec: ChannelSpec> = ();
+
+            fn make_handler(
+                self,
+                state: &ClientState,
+                mut queue: QueueEditGuard<'_>,
+                (target, text): (Arg<'a>, Line<'b>),
+            ) -> Result<Box<dyn Handler<Value = Self::Value>>, Self::Error> {
+                send_text(self.into(), target, &text, state.source_len(), &mut queue);
+                Err(NoHandler)
+            }
+
+            fn make_channel<Spec: ChannelSpec>(
+                _: &Spec,
+            ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+                (Box::<ClosedSender<_>>::default(), ())
+            }
+        }
+    };
+}
+
+impl_send_text!(PRIVMSG);
+impl_send_text!(NOTICE);
@@ -0,0 +1,88 @@
+use super::{send_text, split_text, text_room};
+use crate::{
+    client::{channel::SyncChannels, conn::Bidir, Client, NoHandler},
+    ircmsg::{ClientMsg, Source, UserHost},
+    names::cmd::PRIVMSG,
+    string::{Arg, Line, Nick, User, Word},
+};
+use std::{io::Cursor, num::NonZeroUsize};
+
+fn split(text: &str, max: usize) -> Vec<String> {
+    let text = Line::from_bytes(text.to_owned()).unwrap();
+    split_text(&text, max).into_iter().map(|piece| piece.to_string()).collect()
+}
+
+#[test]
+fn split_whitespace() {
+    assert_eq!(split("", 10), [""]);
+    assert_eq!(split("short", 10), ["short"]);
+    assert_eq!(split("hello there world", 11), ["hello there", "world"]);
+    assert_eq!(split("hello there world", 10), ["hello", "there", "world"]);
+    assert_eq!(split(" leading", 4), [" lea", "ding"]);
+    assert_eq!(split("abc   def", 4), ["abc", "def"]);
+}
+
+#[test]
+fn split_utf8() {
+    assert_eq!(split("aaaaaaaaaa", 4), ["aaaa", "aaaa", "aa"]);
+    // "é" is two bytes and "€" is three.
+    assert_eq!(split("ééééé", 5), ["éé", "éé", "é"]);
+    assert_eq!(split("a€b€", 3), ["a", "€", "b", "€"]);
+    assert_eq!(split("a€b€", 4), ["a€", "b€"]);
+    assert_eq!(split("€€", 1), ["€", "€"]);
+}
+
+#[test]
+fn split_non_utf8() {
+    let text = Line::from_bytes(vec![0x80u8; 1200]).unwrap();
+    let pieces = split_text(&text, 400);
+    assert!(pieces.iter().all(|piece| piece.len() <= 400), "piece over the limit");
+    assert_eq!(pieces.iter().map(|piece| piece.len()).sum::<usize>(), 1200);
+}
+
+#[test]
+fn send_long() {
+    let source = Source {
+        nick: Nick::from_str("SomeoneWithAVeryLongNickname"),
+        userhost: Some(UserHost {
+            user: Some(User::from_str("longusername")),
+            host: Word::from_str("long.hostname.example.com"),
+        }),
+    };
+    let source_len = source.len_nonzero();
+    let words: Vec<_> = (0..110).map(|i| format!("word{i:06}")).collect();
+    let text = Line::from_bytes(words.join(" ")).unwrap();
+    assert!(text.len() >= 1200);
+    let target = Arg::from_str("#channel");
+    let mut msgs = Vec::new();
+    send_text(PRIVMSG.into(), target, &text, source_len, |msg: ClientMsg<'static>| msgs.push(msg));
+    assert_eq!(msgs.len(), 3);
+    let mut rejoined = Vec::new();
+    for msg in &msgs {
+        assert!(msg.bytes_left(Some(&source)) >= 0, "message too long: {msg}");
+        let (words, Some(piece)) = msg.args.split_last() else {
+            panic!("message has no text: {msg}");
+        };
+        assert_eq!(words, [Arg::from_str("#channel")]);
+        rejoined.push(piece.to_string());
+    }
+    assert_eq!(rejoined.join(" "), text.to_string());
+}
+
+#[test]
+fn text_room_default_source() {
+    let source_len = NonZeroUsize::new(20).unwrap();
+    let room = text_room(PRIVMSG.as_cmd(), &Arg::from_str("#a"), source_len);
+    // ":" source " PRIVMSG #a :" text
+    assert_eq!(room, 510 - 1 - 20 - 1 - 7 - 1 - 2 - 2);
+}
+
+#[test]
+fn make_handler() {
+    let io = Bidir(Cursor::new(Vec::<u8>::new()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let text = Line::from_str("hello");
+    let result = client.add(PRIVMSG, (Arg::from_str("#channel"), text));
+    assert!(matches!(result, Err(NoHandler)));
+    assert_eq!(client.queue_mut().len(), 1);
+}
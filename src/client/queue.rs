@@ -5,33 +5,317 @@
 //! followed by one message every 2 seconds.
 //! The contents of this module enforce that recommendation by resticting how frequently
 //! messages can be removed from it.
+//! This is implemented as a token bucket, which can be configured using
+//! [`Queue::set_token_bucket`].
+//!
+//! Every message in the queue is attributed to the [`Producer`] that pushed it,
+//! allowing the number of queued messages from any one producer to be limited.
+//!
+//! Messages that must be sent promptly, such as `PONG`s,
+//! can be [pushed urgently][QueueEditGuard::push_urgent].
+//! These skip ahead of all other messages and do not wait for the rate limit,
+//! though they still count against it.
+//!
+//! Optionally, messages can be sorted by target using a [classifier][Queue::use_classifier].
+//! Targets then take turns, so that a backlog of messages for one channel does not delay
+//! messages for others, and each target can have its own
+//! [token bucket][Queue::set_target_token_bucket] under the queue-wide one.
+
+mod adjust;
+#[cfg(test)]
+mod tests;
 
-use crate::ircmsg::{ClientMsg, ServerMsg};
-use crate::string::{Key, NoNul, User};
-use std::collections::VecDeque;
+pub use adjust::*;
+
+use crate::ircmsg::{Args, ClientMsg, ServerMsg};
+use crate::names::{ISupport, NameMap};
+use crate::string::{Arg, Bytes, Key, Line, NoNul, User, Word};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::num::NonZeroU32;
 use std::time::{Duration, Instant};
 
+/// Something that pushes messages onto a [`Queue`].
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub enum Producer {
+    /// The application, using [`Queue::edit`].
+    #[default]
+    App,
+    /// The handler with the provided id.
+    ///
+    /// Handler ids are not reused, so statistics and quotas for a handler id
+    /// only ever apply to one handler.
+    /// Both are discarded once the handler has finished or been cancelled
+    /// and none of its messages are left in the queue.
+    Handler(usize),
+}
+
+impl std::fmt::Display for Producer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Producer::App => f.write_str("application"),
+            Producer::Handler(id) => write!(f, "handler {id}"),
+        }
+    }
+}
+
+/// Statistics about the messages pushed by one [`Producer`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct ProducerStats {
+    /// The number of messages currently in the queue.
+    pub queued: usize,
+    /// The number of messages that have been popped from the queue.
+    pub sent: u64,
+    /// The number of messages that were rejected for exceeding the producer's quota.
+    pub rejected: u64,
+}
+
+/// Error for when a [`Producer`] tried to push more messages than its quota allows.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct QuotaExceeded {
+    /// The producer that exceeded its quota.
+    pub producer: Producer,
+    /// The maximum number of messages that producer may have queued.
+    pub max_queued: usize,
+}
+
+impl std::fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} exceeded its quota of {} queued messages", self.producer, self.max_queued)
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+/// How a [`Queue`] handles outgoing messages whose arguments are not valid UTF-8.
+///
+/// Servers that advertise the `UTF8ONLY` ISUPPORT token may reject such messages
+/// or close the connection.
+/// See [`Queue::set_utf8_only_from`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Utf8Only {
+    /// Send messages as-is.
+    #[default]
+    Allow,
+    /// Discard messages that are not valid UTF-8 instead of sending them.
+    Discard,
+    /// Replace invalid byte sequences with the
+    /// [U+FFFD replacement character](std::char::REPLACEMENT_CHARACTER).
+    Lossy,
+}
+
+impl Utf8Only {
+    /// Applies this policy to `msg`, returning `false` if it should be discarded.
+    pub fn apply(self, msg: &mut ClientMsg<'_>) -> bool {
+        if self == Utf8Only::Allow || msg.args.iter().all(|arg| arg.to_utf8().is_some()) {
+            return true;
+        }
+        if self == Utf8Only::Discard {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(target: "vinezombie::queue", "discarding non-UTF-8 {} message", msg.cmd);
+            return false;
+        }
+        fn lossy<'a>(bytes: impl Into<Bytes<'a>>) -> Bytes<'a> {
+            bytes.into().into_utf8_lossy()
+        }
+        // Safety: Replacing invalid sequences with U+FFFD
+        // cannot add any of the bytes that `Arg`s and `Line`s forbid.
+        let words =
+            msg.args.words().iter().map(|w| unsafe { Arg::from_unchecked(lossy(w.clone())) });
+        let words: Vec<_> = words.collect();
+        let long = msg.args.is_last_long().then(|| msg.args.split_last().1).flatten();
+        let long = long.map(|l| unsafe { Line::from_unchecked(lossy(l.clone())) });
+        msg.args = Args::new(words, long);
+        true
+    }
+}
+
+/// How a [`QueueSnapshot`] handles messages that contain credentials.
+///
+/// See [`ClientMsg::has_secrets`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum SnapshotSecrets {
+    /// Leave such messages out of the snapshot, counting them in [`QueueSnapshot::skipped`].
+    #[default]
+    Skip,
+    /// Include such messages with their credentials [redacted][ClientMsg::redact].
+    ///
+    /// Redacted messages are unlikely to be useful to send.
+    Redact,
+}
+
+/// Messages that were in a [`Queue`], in a form that can be saved and queued again later.
+///
+/// Snapshots contain no rate limit state, and do not record which
+/// [`Producer`] pushed each message.
+/// Messages can be queued again by [extending][Extend] a queue with a snapshot,
+/// which attributes them to [`Producer::App`].
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize, serde_derive::Deserialize))]
+pub struct QueueSnapshot {
+    /// The messages, in the order they would have been sent.
+    pub msgs: Vec<ClientMsg<'static>>,
+    /// The number of messages that were left out because they contained credentials.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub skipped: usize,
+}
+
+impl QueueSnapshot {
+    /// Creates a snapshot from the provided messages.
+    ///
+    /// `label` tags are removed from every message, as the responses to labeled messages
+    /// are unlikely to be recognized on another connection.
+    pub fn new(
+        msgs: impl IntoIterator<Item = ClientMsg<'static>>,
+        secrets: SnapshotSecrets,
+    ) -> Self {
+        let mut skipped = 0usize;
+        let msgs = msgs.into_iter().filter_map(|mut msg| {
+            if msg.has_secrets() {
+                if secrets == SnapshotSecrets::Skip {
+                    skipped += 1;
+                    return None;
+                }
+                msg.redact();
+            }
+            msg.tags.edit().remove(Key::from_str("label"));
+            Some(msg)
+        });
+        let msgs = msgs.collect();
+        QueueSnapshot { msgs, skipped }
+    }
+}
+
+impl IntoIterator for QueueSnapshot {
+    type Item = ClientMsg<'static>;
+
+    type IntoIter = std::vec::IntoIter<ClientMsg<'static>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.msgs.into_iter()
+    }
+}
+
+/// Returns how long until a token bucket that will be full at `full_at` has a token.
+fn bucket_delay(
+    full_at: Instant,
+    capacity: NonZeroU32,
+    refill: Duration,
+    now: Instant,
+) -> Duration {
+    // The bucket has a token if it is less than one token away from being full.
+    let rest = refill.saturating_mul(capacity.get() - 1);
+    full_at.saturating_duration_since(now).saturating_sub(rest)
+}
+
+/// The classifier used by [`Queue::use_classifier_default`].
+/// Decrements the queued message count for `producer`, counting the message as sent if `sent`.
+///
+/// Forgets about retired handlers once they have no more queued messages.
+fn unqueue(
+    stats: &mut BTreeMap<Producer, ProducerStats>,
+    retired: &mut BTreeSet<usize>,
+    producer: Producer,
+    sent: bool,
+) {
+    let Some(entry) = stats.get_mut(&producer) else {
+        return;
+    };
+    entry.queued = entry.queued.saturating_sub(1);
+    entry.sent += sent as u64;
+    if let Producer::Handler(id) = producer {
+        if entry.queued == 0 && retired.remove(&id) {
+            stats.remove(&producer);
+        }
+    }
+}
+
+fn default_classifier(msg: &ClientMsg<'_>) -> Option<Word<'static>> {
+    use crate::names::cmd::{NOTICE, PRIVMSG};
+    if msg.cmd != PRIVMSG && msg.cmd != NOTICE {
+        return None;
+    }
+    msg.args.words().first().map(|target| Word::from(target.clone()).owning())
+}
+
+/// A function that returns the target of a message. See [`Queue::use_classifier`].
+type Classifier = dyn Fn(&ClientMsg<'_>) -> Option<Word<'static>> + Send;
+
+/// A non-urgent message in a [`Queue`].
+#[derive(Clone, Debug)]
+struct Queued {
+    /// How many non-urgent messages were pushed before this one.
+    seq: u64,
+    msg: ClientMsg<'static>,
+    producer: Producer,
+}
+
+/// The queued messages and the rate limit and fairness state of one message target.
+#[derive(Clone, Debug)]
+struct TargetState {
+    /// The messages for this target, in the order they were pushed.
+    msgs: VecDeque<Queued>,
+    /// The time at which the target's bucket will be full.
+    full_at: Instant,
+    /// When a message for this target was last popped, as a count of popped messages.
+    served: u64,
+}
+
+impl TargetState {
+    fn new() -> Self {
+        TargetState { msgs: VecDeque::new(), full_at: Instant::now(), served: 0 }
+    }
+}
+
 /// A rate-limited queue for client messages.
 ///
 /// See [module-level documentation][self] for more info.
 pub struct Queue {
-    queue: VecDeque<ClientMsg<'static>>,
-    delay: Duration,
-    sub: Duration,
-    timepoint: Instant,
+    /// Messages that are sent before any others without waiting for the rate limit.
+    urgent: VecDeque<(ClientMsg<'static>, Producer)>,
+    capacity: NonZeroU32,
+    refill: Duration,
+    /// The time at which the bucket will be full.
+    full_at: Instant,
     // TODO: Bespoke trait for this.
     labeler: Option<Box<dyn FnMut() -> NoNul<'static> + Send>>,
+    /// Labels whose responses should only go to one handler, not yet seen by the handlers.
+    routes: Vec<(NoNul<'static>, usize)>,
     adjuster: Option<Box<dyn Adjuster>>,
+    classifier: Option<Box<Classifier>>,
+    target_bucket: Option<(NonZeroU32, Duration)>,
+    /// Non-urgent messages by target, along with state for targets with partially-empty buckets.
+    /// Untargeted messages, and every message if there is no classifier, are under `None`.
+    targets: BTreeMap<Option<Word<'static>>, TargetState>,
+    /// The number of non-urgent messages.
+    len: usize,
+    /// The number of non-urgent messages ever pushed.
+    pushed: u64,
+    /// The number of non-urgent messages popped.
+    served: u64,
+    utf8_only: Utf8Only,
+    default_quota: Option<usize>,
+    quotas: BTreeMap<Producer, Option<usize>>,
+    stats: BTreeMap<Producer, ProducerStats>,
+    /// Ids of handlers that are gone but still have messages in the queue.
+    retired: BTreeSet<usize>,
 }
 
 impl std::fmt::Debug for Queue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut f = f.debug_struct("Queue");
-        f.field("queue", &self.queue)
-            .field("delay", &self.delay)
-            .field("sub", &self.sub)
-            .field("timepoint", &self.timepoint)
+        f.field("urgent", &self.urgent)
+            .field("capacity", &self.capacity)
+            .field("refill", &self.refill)
+            .field("full_at", &self.full_at)
             .field("labeler", &self.labeler.is_some())
+            .field("classifier", &self.classifier.is_some())
+            .field("target_bucket", &self.target_bucket)
+            .field("targets", &self.targets)
+            .field("utf8_only", &self.utf8_only)
+            .field("default_quota", &self.default_quota)
+            .field("quotas", &self.quotas)
+            .field("stats", &self.stats)
+            .field("retired", &self.retired)
             .finish()
     }
 }
@@ -63,23 +347,105 @@ impl Queue {
         Self::from_queue(VecDeque::with_capacity(4))
     }
     fn from_queue(queue: VecDeque<ClientMsg<'static>>) -> Self {
-        Queue {
-            queue,
-            delay: Duration::from_secs(2),
-            sub: Duration::from_secs(8),
-            timepoint: Instant::now(),
+        let mut stats = BTreeMap::new();
+        if !queue.is_empty() {
+            stats
+                .insert(Producer::App, ProducerStats { queued: queue.len(), ..Default::default() });
+        }
+        let mut retval = Queue {
+            urgent: VecDeque::new(),
+            capacity: NonZeroU32::new(5).unwrap(),
+            refill: Duration::from_secs(2),
+            full_at: Instant::now(),
             labeler: None,
+            routes: Vec::new(),
             adjuster: None,
+            classifier: None,
+            target_bucket: None,
+            targets: BTreeMap::new(),
+            len: 0,
+            pushed: 0,
+            served: 0,
+            utf8_only: Utf8Only::Allow,
+            default_quota: None,
+            quotas: BTreeMap::new(),
+            stats,
+            retired: BTreeSet::new(),
+        };
+        for msg in queue {
+            retval.push_back(msg, Producer::App);
         }
+        retval
+    }
+    /// Adds a non-urgent message to the back of the queue.
+    fn push_back(&mut self, msg: ClientMsg<'static>, producer: Producer) {
+        let seq = self.pushed;
+        self.pushed += 1;
+        self.len += 1;
+        self.enqueue(Queued { seq, msg, producer });
+    }
+    /// Adds a non-urgent message to the back of its target's messages.
+    fn enqueue(&mut self, queued: Queued) {
+        let target = self.classifier.as_ref().and_then(|classifier| classifier(&queued.msg));
+        self.targets.entry(target).or_insert_with(TargetState::new).msgs.push_back(queued);
+    }
+    /// Removes every non-urgent message, returning them in the order they were pushed.
+    fn take_queued(&mut self) -> Vec<Queued> {
+        let mut msgs: Vec<Queued> =
+            self.targets.values_mut().flat_map(|state| state.msgs.drain(..)).collect();
+        msgs.sort_unstable_by_key(|queued| queued.seq);
+        self.len = 0;
+        msgs
+    }
+    /// Classifies every non-urgent message again.
+    fn reclassify(&mut self) {
+        let msgs = self.take_queued();
+        self.len = msgs.len();
+        for queued in msgs {
+            self.enqueue(queued);
+        }
+    }
+    /// Decrements the queued message count for `producer`.
+    fn unqueue(&mut self, producer: Producer) {
+        unqueue(&mut self.stats, &mut self.retired, producer, false);
+    }
+    /// Discards the quota and statistics of a handler that finished or was cancelled.
+    ///
+    /// The statistics are kept until none of the handler's messages are left in the queue.
+    pub(crate) fn retire_handler(&mut self, id: usize) {
+        let producer = Producer::Handler(id);
+        self.quotas.remove(&producer);
+        if self.stats.get(&producer).map_or(0, |stats| stats.queued) == 0 {
+            self.stats.remove(&producer);
+        } else {
+            self.retired.insert(id);
+        }
+    }
+    /// [Retires][Queue::retire_handler] every handler.
+    pub(crate) fn retire_handlers(&mut self) {
+        self.quotas.retain(|producer, _| matches!(producer, Producer::App));
+        let retired = &mut self.retired;
+        self.stats.retain(|producer, stats| match producer {
+            Producer::App => true,
+            Producer::Handler(_) if stats.queued == 0 => false,
+            Producer::Handler(id) => {
+                retired.insert(*id);
+                true
+            }
+        });
     }
 
     /// Returns `true` if no messages in the queue.
     pub fn is_empty(&self) -> bool {
-        self.queue.is_empty()
+        self.len == 0 && self.urgent.is_empty()
     }
-    /// Returns how many messages are in the queue.
+    /// Returns how many messages are in the queue, including urgent ones.
     pub fn len(&self) -> usize {
-        self.queue.len()
+        self.len + self.urgent.len()
+    }
+    /// Returns how many [urgent][QueueEditGuard::push_urgent] messages are in the queue.
+    pub fn len_urgent(&self) -> usize {
+        self.urgent.len()
     }
 
     /// Changes the rate limit.
@@ -87,30 +453,158 @@ impl Queue {
     /// `delay` specifies how much time should pass between messages.
     /// `burst` specifies how many additional messages may be sent during an initial burst,
     /// e.g. a value of `4` results in a burst of five messages.
+    ///
+    /// This is equivalent to a [token bucket][Queue::set_token_bucket]
+    /// with a capacity of `burst + 1` that regains one token every `delay`,
+    /// except that the bucket starts empty.
     pub fn set_rate_limit(&mut self, delay: Duration, burst: u32) -> &mut Self {
-        self.delay = delay;
-        self.sub = delay.saturating_mul(burst);
+        let capacity = NonZeroU32::new(burst.saturating_add(1)).unwrap_or(NonZeroU32::MIN);
+        self.set_token_bucket(capacity, delay);
         // Pessimistically sets the next-message delay
         // to the longest possible under the new settings.
         let now = Instant::now();
-        self.timepoint = now.checked_add(self.sub.saturating_add(delay)).unwrap_or(now);
+        self.full_at = now.checked_add(delay.saturating_mul(capacity.get())).unwrap_or(now);
         self
     }
+    /// Changes the rate limit to a token bucket holding up to `capacity` tokens
+    /// that regains one token every `refill`.
+    ///
+    /// Every message popped from the queue consumes one token.
+    /// Tokens accumulate while no messages are being sent, up to `capacity`,
+    /// so a full burst is available again after enough idle time.
+    /// The bucket starts full.
+    ///
+    /// The default is a capacity of 5 and one token every 2 seconds.
+    pub fn set_token_bucket(&mut self, capacity: NonZeroU32, refill: Duration) -> &mut Self {
+        self.capacity = capacity;
+        self.refill = refill;
+        self.full_at = Instant::now();
+        self
+    }
+    /// Changes the rate limit based on a hint from the server's ISUPPORT tokens.
+    ///
+    /// There is no standard way for servers to advertise their rate limits.
+    /// This looks for the non-standard `MSGRATE` token with a value of the form
+    /// `<burst>:<seconds>`, e.g. `MSGRATE=5:2` for the RFC 1459 recommendation,
+    /// where `seconds` may be fractional.
+    /// If it is present and valid, this configures a [token bucket][Queue::set_token_bucket]
+    /// with a capacity of `burst` that regains one token every `seconds`.
+    ///
+    /// Returns `true` if the rate limit was changed.
+    pub fn set_rate_limit_from<T>(&mut self, isupport: &NameMap<ISupport, T>) -> bool {
+        let Some((_, value)) = isupport.get_union_raw(&Key::from_str("MSGRATE")) else {
+            return false;
+        };
+        let Some((capacity, secs)) = std::str::from_utf8(value.as_bytes())
+            .ok()
+            .and_then(|value| value.split_once(':'))
+            .and_then(|(burst, secs)| Some((burst.parse().ok()?, secs.parse::<f64>().ok()?)))
+        else {
+            return false;
+        };
+        if !secs.is_finite() || !(0.0..=3600.0).contains(&secs) {
+            return false;
+        }
+        self.set_token_bucket(capacity, Duration::from_secs_f64(secs));
+        true
+    }
+    /// Sets how messages that are not valid UTF-8 are handled when popped.
+    ///
+    /// The default is [`Utf8Only::Allow`].
+    pub fn set_utf8_only(&mut self, policy: Utf8Only) -> &mut Self {
+        self.utf8_only = policy;
+        self
+    }
+    /// Returns how messages that are not valid UTF-8 are handled when popped.
+    pub fn utf8_only(&self) -> Utf8Only {
+        self.utf8_only
+    }
+    /// Uses `policy` for messages that are not valid UTF-8
+    /// if the server's ISUPPORT tokens include `UTF8ONLY`.
+    ///
+    /// Returns `true` if the policy was changed.
+    pub fn set_utf8_only_from<T>(
+        &mut self,
+        isupport: &NameMap<ISupport, T>,
+        policy: Utf8Only,
+    ) -> bool {
+        if isupport.get_union(crate::names::isupport::UTF8ONLY).is_none() {
+            return false;
+        }
+        self.set_utf8_only(policy);
+        true
+    }
+    /// Returns the capacity of the token bucket and how often it regains a token.
+    pub fn token_bucket(&self) -> (NonZeroU32, Duration) {
+        (self.capacity, self.refill)
+    }
+    /// Returns how many messages can be popped from the queue right now
+    /// without waiting.
+    pub fn tokens(&self) -> u32 {
+        let capacity = self.capacity.get();
+        if self.refill.is_zero() {
+            return capacity;
+        }
+        let debt = self.full_at.saturating_duration_since(Instant::now());
+        // Partially-refilled tokens cannot be used, so round up.
+        let missing = (debt.as_nanos() + self.refill.as_nanos() - 1) / self.refill.as_nanos();
+        capacity.saturating_sub(missing.try_into().unwrap_or(u32::MAX))
+    }
     /// Retrieves a message from the queue, subject to rate limits.
     ///
+    /// Urgent messages are returned first and without waiting,
+    /// but still consume a token from the bucket.
+    ///
     /// If this function does not return a message,
     /// `timeout_fn` is called with the duration until the next message will be available,
     /// or `None` if the queue is empty.
     /// The duration is guaranteed to be non-zero. This can be used to adjust read timeouts.
     pub fn pop(&mut self, timeout_fn: impl FnOnce(Option<Duration>)) -> Option<ClientMsg<'static>> {
-        if let Some(value) = self.queue.pop_front() {
-            let mut delay = self.timepoint.saturating_duration_since(Instant::now());
-            delay = delay.saturating_sub(self.sub);
+        while let Some((mut value, producer)) = self.urgent.pop_front() {
+            if !self.utf8_only.apply(&mut value) {
+                self.unqueue(producer);
+                continue;
+            }
+            let now = Instant::now();
+            self.full_at = std::cmp::max(self.full_at, now) + self.refill;
+            unqueue(&mut self.stats, &mut self.retired, producer, true);
+            return Some(value);
+        }
+        if self.len != 0 {
+            let now = Instant::now();
+            let delay = bucket_delay(self.full_at, self.capacity, self.refill, now);
             if delay.is_zero() {
-                self.timepoint = std::cmp::max(self.timepoint, Instant::now()) + self.delay;
+                let (value, producer) = loop {
+                    // Forget about idle targets.
+                    self.targets.retain(|_, state| !state.msgs.is_empty() || state.full_at > now);
+                    let (targeted, state) =
+                        match Self::next_target(&mut self.targets, self.target_bucket, now) {
+                            Ok(next) => next,
+                            // Every remaining message was discarded or is for a limited target.
+                            Err(wait) => {
+                                timeout_fn(wait);
+                                return None;
+                            }
+                        };
+                    let Some(Queued { mut msg, producer, .. }) = state.msgs.pop_front() else {
+                        timeout_fn(None);
+                        return None;
+                    };
+                    self.len -= 1;
+                    if self.utf8_only.apply(&mut msg) {
+                        self.served += 1;
+                        state.served = self.served;
+                        if let Some((_, refill)) = self.target_bucket.filter(|_| targeted) {
+                            state.full_at = std::cmp::max(state.full_at, now) + refill;
+                        }
+                        break (msg, producer);
+                    }
+                    self.unqueue(producer);
+                };
+                self.full_at = std::cmp::max(self.full_at, now) + self.refill;
+                unqueue(&mut self.stats, &mut self.retired, producer, true);
                 Some(value)
             } else {
-                self.queue.push_front(value);
                 timeout_fn(Some(delay));
                 None
             }
@@ -120,11 +614,58 @@ impl Queue {
         }
     }
 
+    /// Returns the state of the target whose oldest message should be popped next
+    /// and whether it is a target other than `None`,
+    /// or how long until a message is available.
+    fn next_target<'a>(
+        targets: &'a mut BTreeMap<Option<Word<'static>>, TargetState>,
+        target_bucket: Option<(NonZeroU32, Duration)>,
+        now: Instant,
+    ) -> Result<(bool, &'a mut TargetState), Option<Duration>> {
+        // Prefer the least recently served target, then the one with the oldest message.
+        let key = |state: &TargetState| (state.served, state.msgs.front().map(|q| q.seq));
+        let mut next: Option<(bool, &'a mut TargetState)> = None;
+        let mut wait: Option<Duration> = None;
+        for (target, state) in targets.iter_mut() {
+            if state.msgs.is_empty() {
+                continue;
+            }
+            if let (Some(_), Some((capacity, refill))) = (target, target_bucket) {
+                let delay = bucket_delay(state.full_at, capacity, refill, now);
+                if !delay.is_zero() {
+                    wait = Some(wait.map_or(delay, |wait| wait.min(delay)));
+                    continue;
+                }
+            }
+            if next.as_ref().map_or(true, |(_, next)| key(state) < key(next)) {
+                next = Some((target.is_some(), state));
+            }
+        }
+        next.ok_or(wait)
+    }
+
     /// Updates messages in the queue based on an incoming message.
     pub fn adjust(&mut self, msg: &ServerMsg<'_>) {
         if let Some(adj) = self.adjuster.as_mut() {
             if adj.should_adjust(msg) {
-                self.queue.retain_mut(|cmsg| adj.update(cmsg));
+                let (stats, retired) = (&mut self.stats, &mut self.retired);
+                let mut update = |cmsg: &mut ClientMsg<'static>, producer: Producer| {
+                    let keep = adj.update(cmsg);
+                    if !keep {
+                        unqueue(stats, retired, producer, false);
+                    }
+                    keep
+                };
+                self.urgent.retain_mut(|(cmsg, producer)| update(cmsg, *producer));
+                self.len = 0;
+                for state in self.targets.values_mut() {
+                    state.msgs.retain_mut(|queued| update(&mut queued.msg, queued.producer));
+                    self.len += state.msgs.len();
+                }
+                // Adjusted messages may have new targets.
+                if self.classifier.is_some() {
+                    self.reclassify();
+                }
             }
         }
     }
@@ -133,6 +674,11 @@ impl Queue {
         self.adjuster = Some(Box::new(adjuster));
         self
     }
+    /// Uses the [default adjusters][MultiAdjuster::defaults] to update the queue
+    /// on incoming messages.
+    pub fn use_default_adjusters(&mut self, state: &crate::client::ClientState) -> &mut Self {
+        self.use_adjuster(MultiAdjuster::defaults(state))
+    }
     /// Removes the [`Adjuster`] for this queue.
     pub fn use_no_adjuster(&mut self) -> &mut Self {
         self.adjuster = None;
@@ -177,24 +723,182 @@ impl Queue {
         self.labeler.is_some()
     }
 
+    /// Sets the provided function as the classifier for this queue.
+    ///
+    /// The classifier returns the target of a message, if any.
+    /// Non-urgent messages are then popped in a round-robin fashion across targets,
+    /// with messages for the same target being popped in the order they were pushed.
+    /// Messages without a target are treated as having the same target.
+    /// Urgent messages are unaffected.
+    ///
+    /// Targets are compared byte-for-byte, so the classifier may need to casemap them.
+    /// Messages are classified when they are pushed, when the classifier is changed,
+    /// and after the queue's [adjuster][Queue::use_adjuster] updates them.
+    pub fn use_classifier(
+        &mut self,
+        classifier: impl Fn(&ClientMsg<'_>) -> Option<Word<'static>> + 'static + Send,
+    ) -> &mut Self {
+        self.classifier = Some(Box::new(classifier));
+        self.reclassify();
+        self
+    }
+    /// Uses a classifier that returns the first argument of `PRIVMSG` and `NOTICE` messages.
+    ///
+    /// See [`use_classifier`][Queue::use_classifier].
+    pub fn use_classifier_default(&mut self) -> &mut Self {
+        self.use_classifier(default_classifier)
+    }
+    /// Removes the classifier for this queue, returning to popping messages in order.
+    ///
+    /// This also disables the [per-target token bucket][Queue::set_target_token_bucket].
+    pub fn use_no_classifier(&mut self) -> &mut Self {
+        self.classifier = None;
+        self.reclassify();
+        self.targets.retain(|_, state| !state.msgs.is_empty());
+        self
+    }
+    /// Returns `true` if a classifier is present.
+    pub fn is_using_classifier(&self) -> bool {
+        self.classifier.is_some()
+    }
+    /// Limits the messages for each target to a token bucket holding up to `capacity` tokens
+    /// that regains one token every `refill`, in addition to the queue-wide rate limit.
+    ///
+    /// This only has an effect if a [classifier][Queue::use_classifier] is present,
+    /// and does not apply to messages without a target.
+    /// Each target's bucket starts full. By default, there is no per-target limit.
+    pub fn set_target_token_bucket(&mut self, capacity: NonZeroU32, refill: Duration) -> &mut Self {
+        self.target_bucket = Some((capacity, refill));
+        self
+    }
+    /// Removes the per-target token bucket.
+    pub fn clear_target_token_bucket(&mut self) -> &mut Self {
+        self.target_bucket = None;
+        self
+    }
+    /// Returns the capacity of the per-target token bucket and how often it regains a token,
+    /// if there is one.
+    pub fn target_token_bucket(&self) -> Option<(NonZeroU32, Duration)> {
+        self.target_bucket
+    }
+
+    /// Sets the maximum number of messages that any one producer may have queued at once.
+    /// `None` means no limit, which is the default.
+    ///
+    /// Quotas only limit how many messages a producer has queued,
+    /// not what share of the rate limit its messages use once queued.
+    /// A producer that stays under its quota can still delay other producers' messages.
+    ///
+    /// This can be overridden for specific producers using [`Queue::set_quota`].
+    pub fn set_default_quota(&mut self, max_queued: Option<usize>) -> &mut Self {
+        self.default_quota = max_queued;
+        self
+    }
+    /// Sets the maximum number of messages that `producer` may have queued at once,
+    /// overriding the [default quota][Queue::set_default_quota].
+    /// `None` means no limit.
+    ///
+    /// A handler's quota is discarded once it finishes or is cancelled.
+    pub fn set_quota(&mut self, producer: Producer, max_queued: Option<usize>) -> &mut Self {
+        self.quotas.insert(producer, max_queued);
+        self
+    }
+    /// Removes any quota override for `producer`, causing the default quota to be used.
+    pub fn clear_quota(&mut self, producer: Producer) -> &mut Self {
+        self.quotas.remove(&producer);
+        self
+    }
+    /// Returns the maximum number of messages that `producer` may have queued at once.
+    pub fn quota(&self, producer: Producer) -> Option<usize> {
+        self.quotas.get(&producer).copied().unwrap_or(self.default_quota)
+    }
+    /// Returns statistics for the messages pushed by `producer`.
+    pub fn stats(&self, producer: Producer) -> ProducerStats {
+        self.stats.get(&producer).copied().unwrap_or_default()
+    }
+    /// Returns an iterator over the statistics for every producer that has pushed messages
+    /// since the queue was last [reset][Queue::reset],
+    /// except for handlers that are gone and have no messages left in the queue.
+    pub fn all_stats(&self) -> impl Iterator<Item = (Producer, &ProducerStats)> {
+        self.stats.iter().map(|(producer, stats)| (*producer, stats))
+    }
+
     /// Create an interface for adding messages to the queue.
+    ///
+    /// Messages added using the returned guard are attributed to [`Producer::App`].
     pub fn edit(&mut self) -> QueueEditGuard<'_> {
-        let orig_len = self.queue.len();
-        QueueEditGuard { queue: self, orig_len }
+        self.edit_as(Producer::App)
+    }
+    /// Removes and returns the labels added by [`QueueEditGuard::route_label`].
+    pub(crate) fn take_routes(&mut self) -> Vec<(NoNul<'static>, usize)> {
+        std::mem::take(&mut self.routes)
+    }
+    /// Create an interface for adding messages to the queue on behalf of `producer`.
+    pub(crate) fn edit_as(&mut self, producer: Producer) -> QueueEditGuard<'_> {
+        let orig_len = self.len;
+        let orig_pushed = self.pushed;
+        let orig_urgent_len = self.urgent.len();
+        QueueEditGuard { queue: self, orig_len, orig_pushed, orig_urgent_len, producer }
+    }
+
+    /// Removes and returns every message from the queue in the order they would have been sent,
+    /// starting with urgent messages.
+    ///
+    /// This is useful for saving messages that were not sent before a connection closed.
+    /// See also [`Queue::snapshot`].
+    pub fn drain_unsent(&mut self) -> Vec<ClientMsg<'static>> {
+        let queued = self.take_queued().into_iter().map(|queued| queued.msg);
+        let msgs = self.urgent.drain(..).map(|(msg, _)| msg).chain(queued).collect();
+        self.clear();
+        msgs
+    }
+    /// Removes every message from the queue, returning them as a [`QueueSnapshot`].
+    ///
+    /// If any messages were skipped for containing credentials,
+    /// this logs a warning with how many.
+    pub fn snapshot(&mut self, secrets: SnapshotSecrets) -> QueueSnapshot {
+        let snapshot = QueueSnapshot::new(self.drain_unsent(), secrets);
+        #[cfg(feature = "tracing")]
+        if snapshot.skipped > 0 {
+            tracing::warn!(
+                target: "vinezombie::queue",
+                "left {} messages containing credentials out of snapshot",
+                snapshot.skipped
+            );
+        }
+        snapshot
     }
 
     /// Discards all messages from the queue.
     pub fn clear(&mut self) {
-        self.queue.clear();
+        for state in self.targets.values_mut() {
+            state.msgs.clear();
+        }
+        self.len = 0;
+        self.urgent.clear();
+        self.routes.clear();
+        for stats in self.stats.values_mut() {
+            stats.queued = 0;
+        }
+        for id in std::mem::take(&mut self.retired) {
+            self.stats.remove(&Producer::Handler(id));
+        }
     }
 
     /// Resets the queue's state.
     ///
-    /// Clears all messages, resets the message delay tracking, and unsets the labeler.
+    /// Clears all messages, resets the message delay tracking including for targets,
+    /// unsets the labeler, allows non-UTF-8 messages,
+    /// and discards all [producer statistics][Queue::stats].
     pub fn reset(&mut self) {
         self.clear();
+        self.stats.clear();
+        self.retired.clear();
+        self.targets.clear();
+        self.served = 0;
         self.use_no_labeler();
-        self.timepoint = Instant::now();
+        self.utf8_only = Utf8Only::Allow;
+        self.full_at = Instant::now();
         if let Some(adjuster) = self.adjuster.as_mut() {
             adjuster.reset();
         }
@@ -205,25 +909,137 @@ impl Queue {
 pub struct QueueEditGuard<'a> {
     queue: &'a mut Queue,
     orig_len: usize,
+    orig_pushed: u64,
+    orig_urgent_len: usize,
+    producer: Producer,
 }
 
 impl QueueEditGuard<'_> {
     /// Adds a message onto the end of a queue.
+    ///
+    /// If this would exceed the [quota][Queue::set_quota] of this guard's producer,
+    /// the message is discarded. Use [`try_push`][QueueEditGuard::try_push] to handle this case.
     pub fn push(&mut self, msg: ClientMsg<'static>) {
-        self.queue.queue.push_back(msg);
+        #[allow(unused_variables)]
+        if let Err(e) = self.try_push(msg) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(target: "vinezombie::queue", "discarding message: {e}");
+        }
+    }
+
+    /// Adds a message onto the end of a queue,
+    /// or returns an error if this would exceed the [quota][Queue::set_quota]
+    /// of this guard's producer.
+    pub fn try_push(&mut self, msg: ClientMsg<'static>) -> Result<(), QuotaExceeded> {
+        self.try_push_to(msg, false)
+    }
+
+    /// Adds a message onto the end of the queue's urgent lane.
+    ///
+    /// Urgent messages are sent before all non-urgent messages
+    /// without waiting for the rate limit. This is intended for replies that the server
+    /// expects promptly, such as `PONG` and `AUTHENTICATE`, and should be used sparingly.
+    ///
+    /// Quotas apply as for [`push`][QueueEditGuard::push].
+    pub fn push_urgent(&mut self, msg: ClientMsg<'static>) {
+        #[allow(unused_variables)]
+        if let Err(e) = self.try_push_to(msg, true) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(target: "vinezombie::queue", "discarding urgent message: {e}");
+        }
+    }
+
+    fn try_push_to(&mut self, msg: ClientMsg<'static>, urgent: bool) -> Result<(), QuotaExceeded> {
+        let producer = self.producer;
+        let max_queued = self.queue.quota(producer);
+        let stats = self.queue.stats.entry(producer).or_default();
+        if let Some(max_queued) = max_queued {
+            if stats.queued >= max_queued {
+                stats.rejected += 1;
+                return Err(QuotaExceeded { producer, max_queued });
+            }
+        }
+        stats.queued += 1;
+        if urgent {
+            self.queue.urgent.push_back((msg, producer));
+        } else {
+            self.queue.push_back(msg, producer);
+        }
+        Ok(())
+    }
+
+    /// Returns the producer that messages added using `self` are attributed to.
+    pub fn producer(&self) -> Producer {
+        self.producer
     }
 
     /// Labels a message and pushes it, returning the label (if any).
+    ///
+    /// The message is pushed without a label if adding one would make its tags
+    /// longer than [`Tags::MAX_CLIENT_LEN`][crate::ircmsg::Tags::MAX_CLIENT_LEN].
     pub fn push_labeled(&mut self, mut msg: ClientMsg<'static>) -> Option<NoNul<'static>> {
-        let label = self.queue.labeler.as_deref_mut().map(|labeler| {
-            let label = labeler();
-            msg.tags.edit().insert_pair(Key::from_str("label"), label.clone());
-            label
-        });
+        let label = self.label(&mut msg);
         self.push(msg);
         label
     }
 
+    /// Labels a message and [pushes it urgently][QueueEditGuard::push_urgent],
+    /// returning the label (if any).
+    ///
+    /// See [`push_labeled`][QueueEditGuard::push_labeled].
+    pub fn push_labeled_urgent(&mut self, mut msg: ClientMsg<'static>) -> Option<NoNul<'static>> {
+        let label = self.label(&mut msg);
+        self.push_urgent(msg);
+        label
+    }
+
+    fn label(&mut self, msg: &mut ClientMsg<'static>) -> Option<NoNul<'static>> {
+        self.queue.labeler.as_deref_mut().and_then(|labeler| {
+            use crate::ircmsg::Tags;
+            let label = labeler();
+            let mut tags = msg.tags.edit();
+            match tags.try_insert_pair(Key::from_str("label"), label.clone(), Tags::MAX_CLIENT_LEN)
+            {
+                Ok(_) => Some(label),
+                Err(_e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(target: "vinezombie::queue", "not labeling {} message: {_e}", msg.cmd);
+                    None
+                }
+            }
+        })
+    }
+
+    /// Routes every response to a message with the provided label
+    /// only to the handler that this guard's messages are attributed to.
+    ///
+    /// This is intended for use with labels returned by
+    /// [`push_labeled`][QueueEditGuard::push_labeled].
+    /// The response is either one message with the label, a batch whose `BATCH` start message
+    /// has the label (including every message in it), or an `ACK` if there is no response.
+    /// No other handlers will see these messages.
+    ///
+    /// Returns `false` and does nothing if this guard's producer is not a handler.
+    pub fn route_label(&mut self, label: NoNul<'static>) -> bool {
+        let Producer::Handler(id) = self.producer else {
+            return false;
+        };
+        self.queue.routes.push((label, id));
+        true
+    }
+
+    /// Sets how messages that are not valid UTF-8 are handled
+    /// if the server's ISUPPORT tokens include `UTF8ONLY`.
+    ///
+    /// See [`Queue::set_utf8_only_from`].
+    pub fn set_utf8_only_from<T>(
+        &mut self,
+        isupport: &NameMap<ISupport, T>,
+        policy: Utf8Only,
+    ) -> bool {
+        self.queue.set_utf8_only_from(isupport, policy)
+    }
+
     /// Returns `true` if a labeler is present.
     pub fn is_using_labeler(&self) -> bool {
         self.queue.labeler.is_some()
@@ -236,12 +1052,25 @@ impl QueueEditGuard<'_> {
 
     /// Returns how many messages have been added to the queue over `self`'s lifetime.
     pub fn len(&self) -> usize {
-        self.queue.queue.len() - self.orig_len
+        (self.queue.len - self.orig_len) + (self.queue.urgent.len() - self.orig_urgent_len)
     }
 
     /// Discard all messages that have been added using `self`.
     pub fn clear(&mut self) -> &mut Self {
-        self.queue.queue.truncate(self.orig_len);
+        let queue = &mut *self.queue;
+        for state in queue.targets.values_mut() {
+            while state.msgs.back().is_some_and(|queued| queued.seq >= self.orig_pushed) {
+                if let Some(queued) = state.msgs.pop_back() {
+                    unqueue(&mut queue.stats, &mut queue.retired, queued.producer, false);
+                    queue.len -= 1;
+                }
+            }
+        }
+        while self.queue.urgent.len() > self.orig_urgent_len {
+            if let Some((_, producer)) = self.queue.urgent.pop_back() {
+                self.queue.unqueue(producer);
+            }
+        }
         self
     }
 
@@ -249,14 +1078,16 @@ impl QueueEditGuard<'_> {
     ///
     /// After the guard is dropped, `Self`
     pub fn edit(&mut self) -> QueueEditGuard<'_> {
-        let orig_len = self.queue.len();
-        QueueEditGuard { queue: self.queue, orig_len }
+        self.queue.edit_as(self.producer)
     }
 }
 
 impl Extend<ClientMsg<'static>> for Queue {
     fn extend<T: IntoIterator<Item = ClientMsg<'static>>>(&mut self, iter: T) {
-        self.queue.extend(iter);
+        let mut edit = self.edit();
+        for msg in iter {
+            edit.push(msg);
+        }
     }
 }
 
@@ -296,6 +1127,13 @@ impl MultiAdjuster {
     pub fn new() -> MultiAdjuster {
         MultiAdjuster { adjusters: Vec::new() }
     }
+    /// Creates a `MultiAdjuster` containing [`RetargetNick`] and [`DropOnPart`].
+    pub fn defaults(state: &crate::client::ClientState) -> MultiAdjuster {
+        let mut retval = MultiAdjuster::new();
+        retval.add(RetargetNick::new(state));
+        retval.add(DropOnPart::new(state));
+        retval
+    }
     /// Adds an adjuster to the collection.
     ///
     /// Added adjusters will not update messages until at least the next call of `should_adjust`.
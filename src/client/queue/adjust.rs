@@ -0,0 +1,154 @@
+use super::Adjuster;
+use crate::{
+    client::{
+        state::{ClientSource, ISupport},
+        ClientState,
+    },
+    ircmsg::{ClientMsg, ServerMsg},
+    names::cmd::{NOTICE, PRIVMSG, TOPIC},
+    string::{tf::IrcCasemap, Arg, Nick},
+};
+
+/// The client's nick as seen by an [`Adjuster`].
+#[derive(Clone, Debug)]
+struct SelfNick {
+    nick: Option<Nick<'static>>,
+    casemap: IrcCasemap,
+}
+
+impl SelfNick {
+    fn new(state: &ClientState) -> Self {
+        let nick = state.get::<ClientSource>().map(|src| src.nick.clone());
+        let casemap = state.get::<ISupport>().map(IrcCasemap::from_isupport).unwrap_or_default();
+        SelfNick { nick, casemap }
+    }
+    fn is_me(&self, nick: &[u8]) -> bool {
+        self.nick.as_ref().is_some_and(|me| me.eq_ignore_case(nick, self.casemap))
+    }
+    /// Learns the client's nick from `RPL_WELCOME` (001) and its own `NICK` messages.
+    ///
+    /// Returns the old nick if `msg` changed it.
+    fn observe(&mut self, msg: &ServerMsg<'_>) -> Option<Nick<'static>> {
+        let new = match msg.kind.as_str() {
+            "001" => msg.args.words().first()?,
+            "NICK" if msg.source.as_ref().is_some_and(|src| self.is_me(&src.nick)) => {
+                msg.args.words().first()?
+            }
+            _ => return None,
+        };
+        let new = Nick::from_super(new.clone()).ok()?.owning();
+        self.nick.replace(new)
+    }
+    fn reset(&mut self) {
+        self.nick = None;
+    }
+}
+
+/// [`Adjuster`] that retargets queued messages when the client's nick changes.
+///
+/// After the client changes nick, or `RPL_WELCOME` (001) shows that the server assigned
+/// a different nick than expected, every queued message whose first argument is
+/// the client's old nick (compared using the server's casemapping)
+/// has it replaced with the new nick.
+/// This keeps messages such as self-targeted `MODE`s pointed at the client.
+///
+/// The client's nick is initially taken from [`ClientSource`].
+/// Resetting forgets it until the next `RPL_WELCOME` (001).
+#[derive(Clone, Debug)]
+pub struct RetargetNick {
+    me: SelfNick,
+    renamed: Option<(Nick<'static>, Nick<'static>)>,
+}
+
+impl RetargetNick {
+    /// Creates a new `RetargetNick` using the client's current nick and casemapping.
+    pub fn new(state: &ClientState) -> Self {
+        RetargetNick { me: SelfNick::new(state), renamed: None }
+    }
+}
+
+impl Adjuster for RetargetNick {
+    fn should_adjust(&mut self, msg: &ServerMsg<'_>) -> bool {
+        let old = self.me.observe(msg);
+        self.renamed = old.zip(self.me.nick.clone()).filter(|(old, new)| old != new);
+        self.renamed.is_some()
+    }
+
+    fn update(&mut self, msg: &mut ClientMsg<'_>) -> bool {
+        let Some((old, new)) = &self.renamed else {
+            return true;
+        };
+        let mut args = msg.args.edit();
+        if let Some(target) = args.words().first_mut() {
+            if target.eq_ignore_case(old, self.me.casemap) {
+                *target = new.clone().into();
+            }
+        }
+        true
+    }
+
+    fn reset(&mut self) {
+        self.me.reset();
+        self.renamed = None;
+    }
+}
+
+/// [`Adjuster`] that drops queued messages for channels the client has left.
+///
+/// When the client `PART`s a channel or is `KICK`ed from one,
+/// queued `PRIVMSG`, `NOTICE`, and `TOPIC` messages whose first argument is that channel
+/// (compared using the server's casemapping) are removed from the queue.
+///
+/// The client's nick is initially taken from [`ClientSource`] and is updated on `NICK`.
+/// Resetting forgets it until the next `RPL_WELCOME` (001).
+#[derive(Clone, Debug)]
+pub struct DropOnPart {
+    me: SelfNick,
+    /// The comma-separated list of channels the client just left.
+    left: Option<Arg<'static>>,
+}
+
+impl DropOnPart {
+    /// Creates a new `DropOnPart` using the client's current nick and casemapping.
+    pub fn new(state: &ClientState) -> Self {
+        DropOnPart { me: SelfNick::new(state), left: None }
+    }
+}
+
+impl Adjuster for DropOnPart {
+    fn should_adjust(&mut self, msg: &ServerMsg<'_>) -> bool {
+        self.left = match msg.kind.as_str() {
+            "PART" if msg.source.as_ref().is_some_and(|src| self.me.is_me(&src.nick)) => {
+                msg.args.words().first().cloned()
+            }
+            "KICK" => match msg.args.words() {
+                [chan, nick, ..] if self.me.is_me(nick) => Some(chan.clone()),
+                _ => None,
+            },
+            _ => {
+                self.me.observe(msg);
+                None
+            }
+        }
+        .map(Arg::owning);
+        self.left.is_some()
+    }
+
+    fn update(&mut self, msg: &mut ClientMsg<'_>) -> bool {
+        let Some(left) = &self.left else {
+            return true;
+        };
+        if msg.cmd != PRIVMSG && msg.cmd != NOTICE && msg.cmd != TOPIC {
+            return true;
+        }
+        let Some(target) = msg.args.words().first() else {
+            return true;
+        };
+        !left.split(|b| *b == b',').any(|chan| target.eq_ignore_case(chan, self.me.casemap))
+    }
+
+    fn reset(&mut self) {
+        self.me.reset();
+        self.left = None;
+    }
+}
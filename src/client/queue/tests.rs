@@ -0,0 +1,513 @@
+use super::{Producer, ProducerStats, Queue, QuotaExceeded};
+use crate::{
+    client::{
+        channel::{ChannelSpec, ClosedSender, Sender, SenderRef, SyncChannels},
+        conn::Bidir,
+        queue::QueueEditGuard,
+        Client, ClientState, Handler, SelfMadeHandler,
+    },
+    ircmsg::{ClientMsg, ServerMsg},
+    names::cmd::PRIVMSG,
+    string::{Arg, Line},
+};
+use std::{io::Cursor, num::NonZeroU32, ops::ControlFlow, time::Duration};
+
+fn privmsg(text: &'static str) -> ClientMsg<'static> {
+    let mut msg = ClientMsg::new(PRIVMSG);
+    let mut args = msg.args.edit();
+    args.add_word(Arg::from_str("#chan"));
+    args.add(Line::from_str(text));
+    msg
+}
+
+fn drain(queue: &mut Queue) -> Vec<String> {
+    let mut retval = Vec::new();
+    while let Some(msg) = queue.pop(|_| ()) {
+        retval.push(msg.args.split_last().1.unwrap().to_string());
+    }
+    retval
+}
+
+#[test]
+fn quota() {
+    let mut queue = Queue::new();
+    queue.set_rate_limit(Duration::ZERO, 1);
+    queue.set_default_quota(Some(2)).set_quota(Producer::Handler(1), None);
+    let mut edit = queue.edit_as(Producer::Handler(0));
+    assert_eq!(edit.try_push(privmsg("a")), Ok(()));
+    assert_eq!(edit.try_push(privmsg("a")), Ok(()));
+    let error = QuotaExceeded { producer: Producer::Handler(0), max_queued: 2 };
+    assert_eq!(edit.try_push(privmsg("a")), Err(error));
+    edit.push(privmsg("a"));
+    assert_eq!(edit.len(), 2);
+    let mut edit = queue.edit_as(Producer::Handler(1));
+    for _ in 0..3 {
+        edit.push(privmsg("b"));
+    }
+    assert_eq!(
+        queue.stats(Producer::Handler(0)),
+        ProducerStats { queued: 2, sent: 0, rejected: 2 }
+    );
+    assert_eq!(queue.stats(Producer::Handler(1)).queued, 3);
+    assert_eq!(drain(&mut queue), ["a", "a", "b", "b", "b"]);
+    assert_eq!(
+        queue.stats(Producer::Handler(0)),
+        ProducerStats { queued: 0, sent: 2, rejected: 2 }
+    );
+    // The quota applies to queued messages, not sent ones.
+    assert!(queue.edit_as(Producer::Handler(0)).try_push(privmsg("a")).is_ok());
+    queue.reset();
+    assert_eq!(queue.all_stats().count(), 0);
+}
+
+#[test]
+fn quota_clear() {
+    let mut queue = Queue::new();
+    queue.set_default_quota(Some(1));
+    let mut edit = queue.edit();
+    edit.push(privmsg("a"));
+    edit.clear();
+    assert!(edit.try_push(privmsg("a")).is_ok());
+    assert_eq!(queue.stats(Producer::App).queued, 1);
+}
+
+/// Handler that pushes many messages for every message it receives.
+struct Flood(&'static str, usize);
+
+impl Handler for Flood {
+    type Value = ();
+
+    fn handle(
+        &mut self,
+        _: &ServerMsg<'_>,
+        _: &mut ClientState,
+        mut queue: QueueEditGuard<'_>,
+        _: SenderRef<'_, Self::Value>,
+    ) -> ControlFlow<()> {
+        for _ in 0..self.1 {
+            queue.push(privmsg(self.0));
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+impl SelfMadeHandler for Flood {
+    type Receiver<Spec: ChannelSpec> = ();
+
+    fn queue_msgs(&self, _: &ClientState, _: QueueEditGuard<'_>) {}
+
+    fn make_channel<Spec: ChannelSpec>(
+        _: &Spec,
+    ) -> (Box<dyn Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>) {
+        (Box::<ClosedSender<_>>::default(), ())
+    }
+}
+
+#[test]
+fn handler_quota() {
+    let msgs = ":example.com NOTICE * :hello\r\n";
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let (flood_id, _) = client.add((), Flood("flood", 100)).unwrap();
+    let (quiet_id, _) = client.add((), Flood("quiet", 1)).unwrap();
+    client.queue_mut().set_quota(Producer::Handler(flood_id), Some(4));
+    // Dispatch the message without flushing anything.
+    client.logic.run_once(&ServerMsg::parse(msgs.trim_end()).unwrap());
+    let queue = client.queue_mut();
+    assert_eq!(queue.stats(Producer::Handler(flood_id)).rejected, 96);
+    assert_eq!(queue.stats(Producer::Handler(quiet_id)).queued, 1);
+    // The quiet handler's message is sent at most four messages later.
+    queue.set_rate_limit(Duration::ZERO, 1);
+    let sent = drain(queue);
+    assert_eq!(sent.len(), 5);
+    assert!(sent.contains(&"quiet".to_owned()));
+}
+
+#[test]
+fn handler_retired() {
+    let msgs = ":example.com NOTICE * :hello\r\n";
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    let (id, _) = client.add((), Flood("flood", 3)).unwrap();
+    client.queue_mut().set_quota(Producer::Handler(id), Some(10));
+    client.logic.run_once(&ServerMsg::parse(msgs.trim_end()).unwrap());
+    assert!(client.cancel_handler(id));
+    let queue = client.queue_mut();
+    assert_eq!(queue.quota(Producer::Handler(id)), None);
+    // Statistics are kept until the handler's messages are gone.
+    assert_eq!(queue.stats(Producer::Handler(id)).queued, 3);
+    queue.set_rate_limit(Duration::ZERO, 1);
+    assert_eq!(drain(queue).len(), 3);
+    assert_eq!(queue.all_stats().count(), 0);
+}
+
+#[test]
+fn token_bucket() {
+    const REFILL: Duration = Duration::from_millis(50);
+    let mut queue = Queue::new();
+    queue.set_token_bucket(NonZeroU32::new(3).unwrap(), REFILL);
+    assert_eq!(queue.tokens(), 3);
+    queue.extend((0..5).map(|_| privmsg("a")));
+    assert_eq!(drain(&mut queue).len(), 3);
+    assert_eq!(queue.tokens(), 0);
+    let mut wait = None;
+    assert!(queue.pop(|timeout| wait = timeout).is_none());
+    let wait = wait.expect("queue should report how long to wait");
+    assert!(!wait.is_zero() && wait <= REFILL);
+    // Tokens accumulate while idle, but not past the capacity.
+    std::thread::sleep(REFILL * 5);
+    assert_eq!(queue.tokens(), 3);
+    assert_eq!(drain(&mut queue).len(), 2);
+    assert_eq!(queue.tokens(), 1);
+}
+
+#[test]
+fn rate_limit_starts_empty() {
+    let mut queue = Queue::new();
+    queue.set_rate_limit(Duration::from_secs(2), 4);
+    assert_eq!(queue.token_bucket(), (NonZeroU32::new(5).unwrap(), Duration::from_secs(2)));
+    assert_eq!(queue.tokens(), 0);
+    queue.edit().push(privmsg("a"));
+    let mut wait = None;
+    assert!(queue.pop(|timeout| wait = timeout).is_none());
+    assert!(wait.is_some_and(|wait| wait > Duration::from_secs(1)));
+}
+
+#[test]
+fn rate_limit_from_isupport() {
+    use crate::{
+        names::{ISupport, NameMap},
+        string::{Key, Word},
+    };
+    let mut queue = Queue::new();
+    let mut isupport = NameMap::<ISupport>::new();
+    assert!(!queue.set_rate_limit_from(&isupport));
+    for bad in ["10", "0:1", "10:-1", "10:NaN", "ten:1"] {
+        isupport.edit().insert((Key::from_str("MSGRATE"), Word::from_str(bad)), ());
+        assert!(!queue.set_rate_limit_from(&isupport), "{bad} should be rejected");
+    }
+    assert_eq!(queue.token_bucket(), (NonZeroU32::new(5).unwrap(), Duration::from_secs(2)));
+    isupport.edit().insert((Key::from_str("MSGRATE"), Word::from_str("10:0.5")), ());
+    assert!(queue.set_rate_limit_from(&isupport));
+    assert_eq!(queue.token_bucket(), (NonZeroU32::new(10).unwrap(), Duration::from_millis(500)));
+    assert_eq!(queue.tokens(), 10);
+}
+
+#[test]
+fn utf8_only() {
+    use super::Utf8Only;
+    let latin1 = || {
+        let mut msg = ClientMsg::new(PRIVMSG);
+        let mut args = msg.args.edit();
+        args.add_word(Arg::from_str("#chan"));
+        args.add(Line::from_bytes(b"caf\xe9 au lait".to_vec()).unwrap());
+        msg
+    };
+    let mut queue = Queue::new();
+    queue.set_rate_limit(Duration::ZERO, 1);
+    queue.edit().push(latin1());
+    let msg = queue.pop(|_| ()).unwrap();
+    assert_eq!(msg.args.split_last().1.unwrap().as_bytes(), b"caf\xe9 au lait");
+    queue.set_utf8_only(Utf8Only::Discard);
+    queue.edit().push(latin1());
+    queue.edit().push(privmsg("caf\u{e9}"));
+    queue.edit().push(latin1());
+    assert_eq!(drain(&mut queue), ["caf\u{e9}"]);
+    let stats = queue.stats(Producer::App);
+    assert_eq!((stats.queued, stats.sent), (0, 2));
+    queue.set_utf8_only(Utf8Only::Lossy);
+    queue.edit().push(latin1());
+    let msg = queue.pop(|_| ()).unwrap();
+    assert_eq!(msg.args.split_last().1.unwrap().to_utf8(), Some("caf\u{fffd} au lait"));
+    assert!(msg.args.is_last_long());
+    queue.reset();
+    assert_eq!(queue.utf8_only(), Utf8Only::Allow);
+}
+
+#[test]
+fn utf8_only_from_isupport() {
+    use super::Utf8Only;
+    use crate::{
+        names::{ISupport, NameMap},
+        string::{Key, Word},
+    };
+    let mut queue = Queue::new();
+    let mut isupport = NameMap::<ISupport>::new();
+    assert!(!queue.set_utf8_only_from(&isupport, Utf8Only::Discard));
+    assert_eq!(queue.utf8_only(), Utf8Only::Allow);
+    isupport.edit().insert((Key::from_str("UTF8ONLY"), Word::default()), ());
+    assert!(queue.set_utf8_only_from(&isupport, Utf8Only::Discard));
+    assert_eq!(queue.utf8_only(), Utf8Only::Discard);
+}
+
+#[test]
+fn label_budget() {
+    use crate::{
+        ircmsg::Tags,
+        string::{Key, NoNul},
+    };
+    let mut queue = Queue::new();
+    queue.set_rate_limit(Duration::ZERO, 1);
+    queue.use_labeler(|| NoNul::from_str("abc"));
+    let mut edit = queue.edit();
+    assert_eq!(edit.push_labeled(privmsg("a")).unwrap(), "abc");
+    // Leave room for the key but not the label.
+    let mut msg = privmsg("b");
+    let value = "v".repeat(Tags::MAX_CLIENT_LEN - "@+aa=;label".len());
+    msg.tags.edit().insert_pair(Key::from_str("+aa"), NoNul::from_bytes(value).unwrap());
+    assert_eq!(edit.push_labeled(msg), None);
+    assert!(queue.pop(|_| ()).unwrap().tags.get("label").is_some());
+    let msg = queue.pop(|_| ()).unwrap();
+    assert!(msg.tags.get("label").is_none());
+    assert_eq!(msg.tags.len(), 1);
+}
+
+#[test]
+fn urgent() {
+    let mut queue = Queue::new();
+    queue.set_token_bucket(NonZeroU32::new(2).unwrap(), Duration::from_secs(60));
+    queue.use_labeler(|| crate::string::NoNul::from_str("abc"));
+    queue.extend(["a", "b", "c"].map(privmsg));
+    let mut edit = queue.edit();
+    edit.push_urgent(privmsg("u1"));
+    assert_eq!(edit.push_labeled_urgent(privmsg("u2")).unwrap(), "abc");
+    assert_eq!(edit.len(), 2);
+    assert_eq!(queue.len(), 5);
+    assert_eq!(queue.len_urgent(), 2);
+    assert_eq!(queue.stats(Producer::App).queued, 5);
+    // Urgent messages skip ahead of the backlog and do not wait,
+    // but still consume tokens.
+    assert_eq!(queue.pop(|_| ()).unwrap().args.split_last().1.unwrap(), "u1");
+    let msg = queue.pop(|_| ()).unwrap();
+    assert_eq!(msg.args.split_last().1.unwrap(), "u2");
+    assert!(msg.tags.get("label").is_some());
+    assert_eq!(queue.tokens(), 0);
+    let mut wait = None;
+    assert!(queue.pop(|timeout| wait = timeout).is_none());
+    assert!(wait.is_some());
+    queue.edit().push_urgent(privmsg("u3"));
+    assert_eq!(queue.pop(|_| ()).unwrap().args.split_last().1.unwrap(), "u3");
+    assert_eq!(queue.len(), 3);
+    // Both lanes are cleared.
+    let mut edit = queue.edit();
+    edit.push_urgent(privmsg("u4"));
+    edit.push(privmsg("d"));
+    edit.clear();
+    assert_eq!(queue.len(), 3);
+    queue.edit().push_urgent(privmsg("u5"));
+    queue.clear();
+    assert!(queue.is_empty());
+    assert_eq!(queue.stats(Producer::App).queued, 0);
+    assert_eq!(queue.stats(Producer::App).sent, 3);
+}
+
+fn privmsg_to(target: &'static str, text: &'static str) -> ClientMsg<'static> {
+    let mut msg = ClientMsg::new(PRIVMSG);
+    let mut args = msg.args.edit();
+    args.add_word(Arg::from_str(target));
+    args.add(Line::from_str(text));
+    msg
+}
+
+#[test]
+fn classifier_fairness() {
+    let mut queue = Queue::new();
+    queue.set_rate_limit(Duration::ZERO, 1);
+    queue.use_classifier_default();
+    queue.extend((0..100).map(|_| privmsg_to("#a", "a")));
+    queue.extend([privmsg_to("#b", "b1"), privmsg_to("#b", "b2")]);
+    let sent = drain(&mut queue);
+    assert_eq!(sent.len(), 102);
+    assert_eq!(sent[..4], ["a", "b1", "a", "b2"]);
+    // Without a classifier, messages are sent in order.
+    queue.use_no_classifier();
+    queue.extend((0..100).map(|_| privmsg_to("#a", "a")));
+    queue.extend([privmsg_to("#b", "b1"), privmsg_to("#b", "b2")]);
+    assert_eq!(drain(&mut queue)[100..], ["b1", "b2"]);
+}
+
+#[test]
+fn classifier_once() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut queue = Queue::new();
+    queue.set_rate_limit(Duration::ZERO, 1);
+    let calls2 = calls.clone();
+    queue.use_classifier(move |msg| {
+        calls2.fetch_add(1, Ordering::Relaxed);
+        super::default_classifier(msg)
+    });
+    queue.extend((0..50).map(|_| privmsg_to("#a", "a")));
+    queue.extend((0..50).map(|_| privmsg_to("#b", "b")));
+    let mut edit = queue.edit();
+    edit.push(privmsg_to("#b", "discarded"));
+    edit.push(privmsg_to("#c", "discarded"));
+    edit.clear();
+    assert_eq!(queue.len(), 100);
+    let sent = drain(&mut queue);
+    assert_eq!(sent.len(), 100);
+    assert_eq!(sent[..4], ["a", "b", "a", "b"]);
+    assert_eq!(calls.load(Ordering::Relaxed), 102);
+}
+
+#[test]
+fn classifier_adjust() {
+    use super::Adjuster;
+    use crate::string::Word;
+    /// Adjuster that removes every message for `#a`.
+    struct Part;
+    impl Adjuster for Part {
+        fn update(&mut self, msg: &mut ClientMsg<'_>) -> bool {
+            msg.args.words().first().map_or(true, |target| *target != "#a")
+        }
+        fn reset(&mut self) {}
+    }
+    let mut queue = Queue::new();
+    queue.set_rate_limit(Duration::ZERO, 1);
+    queue.use_adjuster(Part);
+    queue.use_classifier(|msg| msg.args.words().first().map(|w| Word::from(w.clone()).owning()));
+    queue.extend([privmsg_to("#a", "a"), privmsg_to("#b", "b"), privmsg_to("#a", "a")]);
+    queue.adjust(&ServerMsg::parse(":a PART #a").unwrap());
+    assert_eq!(drain(&mut queue), ["b"]);
+}
+
+#[test]
+fn target_token_bucket() {
+    const REFILL: Duration = Duration::from_secs(60);
+    let mut queue = Queue::new();
+    queue.set_rate_limit(Duration::ZERO, 1);
+    queue.use_classifier_default();
+    queue.set_target_token_bucket(NonZeroU32::new(2).unwrap(), REFILL);
+    queue.extend((0..4).map(|_| privmsg_to("#a", "a")));
+    let mut away = ClientMsg::new(crate::names::cmd::AWAY);
+    away.args.edit().add(Line::from_str("untargeted"));
+    queue.extend([privmsg_to("#b", "b"), away.clone(), away]);
+    let sent = drain(&mut queue);
+    assert_eq!(sent, ["a", "b", "untargeted", "a", "untargeted"]);
+    assert_eq!(queue.len(), 2);
+    let mut wait = None;
+    assert!(queue.pop(|timeout| wait = timeout).is_none());
+    assert!(wait.is_some_and(|wait| wait > Duration::from_secs(59) && wait <= REFILL));
+    queue.reset();
+    assert!(queue.is_using_classifier());
+    assert_eq!(queue.target_token_bucket(), Some((NonZeroU32::new(2).unwrap(), REFILL)));
+}
+
+#[test]
+fn snapshot() {
+    use super::{QueueSnapshot, SnapshotSecrets};
+    use crate::names::cmd::OPER;
+    let oper = || {
+        let mut msg = ClientMsg::new(OPER);
+        let mut args = msg.args.edit();
+        args.add_word(Arg::from_str("name"));
+        args.add(Line::from_str("hunter2"));
+        msg
+    };
+    let secret = ClientMsg::new(PRIVMSG)
+        .with_args([Arg::from_str("#chan")], Some(Line::from_str("secret").secret()));
+    let mut queue = Queue::new();
+    queue.use_labeler(|| crate::string::NoNul::from_str("abc"));
+    let mut edit = queue.edit();
+    edit.push(privmsg("a"));
+    edit.push_labeled(privmsg("b"));
+    edit.push(oper());
+    edit.push(secret);
+    edit.push_urgent(privmsg("u"));
+    let snapshot = queue.snapshot(SnapshotSecrets::Skip);
+    assert!(queue.is_empty());
+    assert_eq!(queue.stats(Producer::App).queued, 0);
+    assert_eq!(snapshot.skipped, 2);
+    let texts: Vec<_> =
+        snapshot.msgs.iter().map(|msg| msg.args.split_last().1.unwrap().to_string()).collect();
+    assert_eq!(texts, ["u", "a", "b"]);
+    assert!(snapshot.msgs.iter().all(|msg| msg.tags.is_empty()));
+    // Snapshots can be queued again.
+    queue.extend(snapshot);
+    assert_eq!(queue.len(), 3);
+    // Redacting instead of skipping.
+    let snapshot = QueueSnapshot::new([oper(), privmsg("a")], SnapshotSecrets::Redact);
+    assert_eq!(snapshot.skipped, 0);
+    assert_eq!(snapshot.msgs[0].to_string(), "OPER name <?>");
+    assert_eq!(snapshot.msgs[1], privmsg("a"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn snapshot_serde() {
+    use super::{QueueSnapshot, SnapshotSecrets};
+    let snapshot = QueueSnapshot::new([privmsg("a"), privmsg("b")], SnapshotSecrets::Skip);
+    let json = serde_json::to_string(&snapshot).unwrap();
+    assert_eq!(serde_json::from_str::<QueueSnapshot>(&json).unwrap(), snapshot);
+    let old: QueueSnapshot = serde_json::from_str(r#"{"msgs":[]}"#).unwrap();
+    assert_eq!(old, QueueSnapshot::default());
+}
+
+/// Returns a state for a client with the nick `Me`.
+fn state_as_me() -> ClientState {
+    use crate::{client::state::ClientSource, ircmsg::Source, string::Nick};
+    let mut state = ClientState::new();
+    state.insert::<ClientSource>(Source::new_server(Nick::from_str("Me")));
+    state
+}
+
+fn drain_msgs(queue: &mut Queue) -> Vec<String> {
+    let mut retval = Vec::new();
+    while let Some(msg) = queue.pop(|_| ()) {
+        retval.push(msg.to_string());
+    }
+    retval
+}
+
+#[test]
+fn retarget_nick() {
+    use super::RetargetNick;
+    let mut queue = Queue::new();
+    queue.set_rate_limit(Duration::ZERO, 1);
+    queue.use_adjuster(RetargetNick::new(&state_as_me()));
+    let msgs = ["MODE me +i", "WHOIS Me", "PRIVMSG #chan Me"];
+    queue.extend(msgs.map(|msg| ClientMsg::parse(msg).unwrap()));
+    // Someone else's nick change does nothing.
+    queue.adjust(&ServerMsg::parse(":Them!u@h NICK Other").unwrap());
+    queue.adjust(&ServerMsg::parse(":ME!u@h NICK New").unwrap());
+    assert_eq!(drain_msgs(&mut queue), ["MODE New +i", "WHOIS New", "PRIVMSG #chan Me"]);
+    // The new nick is tracked.
+    queue.extend([ClientMsg::parse("MODE New +i").unwrap()]);
+    queue.adjust(&ServerMsg::parse(":New!u@h NICK Newer").unwrap());
+    assert_eq!(drain_msgs(&mut queue), ["MODE Newer +i"]);
+    // After a reset, the nick is learned from RPL_WELCOME.
+    queue.reset();
+    queue.set_rate_limit(Duration::ZERO, 1);
+    queue.extend([ClientMsg::parse("MODE Newer +i").unwrap()]);
+    queue.adjust(&ServerMsg::parse(":Newer!u@h NICK Newest").unwrap());
+    queue.adjust(&ServerMsg::parse(":example.com 001 Newer :Welcome").unwrap());
+    queue.adjust(&ServerMsg::parse(":Newer!u@h NICK Newest").unwrap());
+    assert_eq!(drain_msgs(&mut queue), ["MODE Newest +i"]);
+}
+
+#[test]
+fn drop_on_part() {
+    let mut queue = Queue::new();
+    queue.set_rate_limit(Duration::ZERO, 1);
+    queue.use_default_adjusters(&state_as_me());
+    let msgs = [
+        "PRIVMSG #a hi",
+        "TOPIC #b :new topic",
+        "JOIN #a",
+        "NOTICE #A hi",
+        "PRIVMSG #c hi",
+        "PRIVMSG #d hi",
+        "MODE Me +i",
+    ];
+    queue.extend(msgs.map(|msg| ClientMsg::parse(msg).unwrap()));
+    // Someone else leaving does nothing.
+    queue.adjust(&ServerMsg::parse(":Them!u@h PART #a,#b").unwrap());
+    queue.adjust(&ServerMsg::parse(":Op!u@h KICK #c Them :bye").unwrap());
+    queue.adjust(&ServerMsg::parse(":Me!u@h PART #a,#B :bye").unwrap());
+    queue.adjust(&ServerMsg::parse(":Me!u@h NICK You").unwrap());
+    queue.adjust(&ServerMsg::parse(":Op!u@h KICK #c you :bye").unwrap());
+    assert_eq!(drain_msgs(&mut queue), ["JOIN #a", "PRIVMSG #d hi", "MODE You +i"]);
+}
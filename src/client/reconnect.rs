@@ -0,0 +1,329 @@
+//! Automatic reconnection with exponential backoff.
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    channel::{ChannelSpec, SyncChannels},
+    conn::ServerAddr,
+    register::{HandlerError, Register},
+    Client,
+};
+use std::time::Duration;
+
+/// The number of consecutive redirects that are followed without waiting.
+const MAX_REDIRECTS: u8 = 5;
+
+/// How long to wait between failed connection attempts.
+///
+/// The wait after the `n`th consecutive failure is `initial * 2^(n-1)`, capped at `max`,
+/// plus a pseudorandom duration of up to `jitter`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize, serde_derive::Deserialize))]
+pub struct Backoff {
+    /// How long to wait after the first failure.
+    pub initial: Duration,
+    /// The longest to wait between attempts, excluding jitter.
+    pub max: Duration,
+    /// The upper bound on the random duration added to each wait.
+    pub jitter: Duration,
+}
+
+impl Backoff {
+    /// Returns the default backoff policy.
+    ///
+    /// This waits 2 seconds after the first failure, up to 5 minutes,
+    /// with up to 2 seconds of jitter.
+    pub const fn new() -> Self {
+        Backoff {
+            initial: Duration::from_secs(2),
+            max: Duration::from_secs(300),
+            jitter: Duration::from_secs(2),
+        }
+    }
+    /// Returns a backoff policy that never waits.
+    pub const fn none() -> Self {
+        Backoff { initial: Duration::ZERO, max: Duration::ZERO, jitter: Duration::ZERO }
+    }
+    /// Returns how long to wait after `failures` consecutive failures,
+    /// excluding jitter.
+    pub fn delay(&self, failures: u32) -> Duration {
+        let Some(exp) = failures.checked_sub(1) else {
+            return Duration::ZERO;
+        };
+        let factor = 1u32.checked_shl(exp).unwrap_or(u32::MAX);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::new()
+    }
+}
+
+/// Repeatedly connects and registers to a list of servers until one succeeds.
+///
+/// Addresses are tried in order, starting from the one that last worked.
+/// Each failed attempt moves on to the next address and waits according to the [`Backoff`].
+/// Redirects from the server ([`HandlerError::Redirect`]) are followed by inserting
+/// the target at the front of the address list and trying it immediately.
+/// Likewise, an [`StsUpgrade`][HandlerError::StsUpgrade] is followed by inserting
+/// a TLS version of the current address.
+///
+/// Registration failures that would fail again on any server are returned as errors
+/// wrapped in an [`std::io::Error`]. These are [`NoAccess`][HandlerError::NoAccess],
+/// [`NoLogin`][HandlerError::NoLogin], and [`MissingCaps`][HandlerError::MissingCaps].
+/// All other failures, including I/O errors, are retried.
+pub struct Reconnector<O> {
+    addrs: Vec<ServerAddr<'static>>,
+    /// How long to wait between failed attempts.
+    pub backoff: Backoff,
+    register: Register<O>,
+    /// The index of the address to try next.
+    next: usize,
+    last_connected: Option<ServerAddr<'static>>,
+    failures: u32,
+    redirects: u8,
+    rng: u32,
+}
+
+/// The ways a single connection attempt can fail.
+enum Failure {
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    Io(std::io::Error),
+    Register(HandlerError),
+}
+
+impl<O> Reconnector<O> {
+    /// Creates a new `Reconnector` with the default [`Backoff`].
+    ///
+    /// [`Register::suspend`] and [`Register::suspend_sasl`] are cleared,
+    /// as suspended registration cannot be resumed.
+    /// [`Register::tls`] is set for each attempt based on [`ServerAddr::tls`].
+    pub fn new(addrs: Vec<ServerAddr<'static>>, mut register: Register<O>) -> Self {
+        use std::time::SystemTime;
+        register.suspend = false;
+        register.suspend_sasl = false;
+        Reconnector {
+            addrs,
+            backoff: Backoff::new(),
+            register,
+            next: 0,
+            last_connected: None,
+            failures: 0,
+            redirects: 0,
+            rng: crate::util::mangle(&SystemTime::now()),
+        }
+    }
+    /// Returns the list of addresses, including ones added by redirects.
+    pub fn addrs(&self) -> &[ServerAddr<'static>] {
+        &self.addrs
+    }
+    /// Returns a mutable reference to the list of addresses.
+    ///
+    /// If the list is changed, the next attempt starts from the first address.
+    pub fn addrs_mut(&mut self) -> &mut Vec<ServerAddr<'static>> {
+        self.next = 0;
+        &mut self.addrs
+    }
+    /// Returns the [`Register`] used for connection registration.
+    pub fn register(&self) -> &Register<O> {
+        &self.register
+    }
+    /// Returns the address that will be tried next, if any.
+    pub fn next_addr(&self) -> Option<&ServerAddr<'static>> {
+        self.addrs.get(self.next)
+    }
+    /// Returns the address of the last successful connection, if any.
+    pub fn last_connected(&self) -> Option<&ServerAddr<'static>> {
+        self.last_connected.as_ref()
+    }
+    /// Returns the number of consecutive failed attempts.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+    fn current(&self) -> std::io::Result<ServerAddr<'static>> {
+        self.next_addr().cloned().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "no addresses to connect to")
+        })
+    }
+    fn succeed(&mut self, addr: ServerAddr<'static>) {
+        self.failures = 0;
+        self.redirects = 0;
+        self.last_connected = Some(addr);
+    }
+    /// Moves to the next address after a failure.
+    ///
+    /// Returns how long to wait before the next attempt, or an error if it is fatal.
+    fn fail(&mut self, addr: &ServerAddr<'static>, failure: Failure) -> std::io::Result<Duration> {
+        #[cfg(feature = "tracing")]
+        {
+            let e: &dyn std::fmt::Display = match &failure {
+                Failure::Io(e) => e,
+                Failure::Register(e) => e,
+            };
+            tracing::warn!(target: "vinezombie::reconnect", "{}: {e}", addr.to_word());
+        }
+        let redirect = match failure {
+            Failure::Io(_) => None,
+            Failure::Register(
+                e @ (HandlerError::NoAccess(_)
+                | HandlerError::NoLogin
+                | HandlerError::MissingCaps(_)),
+            ) => return Err(e.into()),
+            Failure::Register(HandlerError::Redirect(address, port, _)) => {
+                let connect_timeout = addr.connect_timeout;
+                Some(ServerAddr { address, tls: addr.tls, port: Some(port), connect_timeout })
+            }
+            Failure::Register(HandlerError::StsUpgrade(port)) => {
+                let address = addr.address.clone();
+                let connect_timeout = addr.connect_timeout;
+                Some(ServerAddr { address, tls: true, port: Some(port), connect_timeout })
+            }
+            Failure::Register(_) => None,
+        };
+        if let Some(target) = redirect.filter(|_| self.redirects < MAX_REDIRECTS) {
+            self.redirects += 1;
+            self.addrs.retain(|a| *a != target);
+            self.addrs.insert(0, target);
+            self.next = 0;
+            return Ok(Duration::ZERO);
+        }
+        self.redirects = 0;
+        self.failures = self.failures.saturating_add(1);
+        self.next = (self.next + 1) % self.addrs.len().max(1);
+        Ok(self.backoff.delay(self.failures) + self.jitter())
+    }
+    fn jitter(&mut self) -> Duration {
+        if self.backoff.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        self.rng = self.rng.wrapping_mul(1664525).wrapping_add(1013904223);
+        let frac = (self.rng >> 8) as f64 / (1u32 << 24) as f64;
+        self.backoff.jitter.mul_f64(frac)
+    }
+}
+
+impl<O> Reconnector<O> {
+    /// Connects and registers, retrying until either succeeds or a fatal error occurs.
+    ///
+    /// `connect` is called with each address to try
+    /// and should return a new [`Client`] connected to it,
+    /// configured as necessary (e.g. with timeouts or rate limits) for registration.
+    /// The client should have a [read timeout][Client::set_read_timeout] configured,
+    /// as otherwise an unresponsive server may stall registration indefinitely.
+    /// A read timeout during registration is considered a failure.
+    /// Failing to load the secrets needed for registration is fatal.
+    ///
+    /// Blocks the current thread while waiting between attempts.
+    pub fn next_connection<C: super::conn::Connection, S: ChannelSpec>(
+        &mut self,
+        opts: &O,
+        mut connect: impl FnMut(&ServerAddr<'static>) -> std::io::Result<Client<C, S>>,
+    ) -> std::io::Result<Client<C, S>> {
+        loop {
+            let addr = self.current()?;
+            self.register.tls = Some(addr.tls);
+            let result = match connect(&addr) {
+                Ok(mut client) => {
+                    let (send, recv) = SyncChannels.new_oneshot();
+                    // Failing to load secrets is not the server's fault.
+                    let id = client.add_with_sender(send, &self.register, opts)?;
+                    loop {
+                        let done = match client.run() {
+                            Ok(Some((_, finished))) => finished.contains(&id),
+                            Ok(None) => break Err(Failure::Io(registration_timeout())),
+                            Err(e) => break Err(Failure::Io(e)),
+                        };
+                        if done {
+                            break finish(client, recv.0.recv_now());
+                        }
+                    }
+                }
+                Err(e) => Err(Failure::Io(e)),
+            };
+            match result {
+                Ok(client) => {
+                    self.succeed(addr);
+                    return Ok(client);
+                }
+                Err(failure) => {
+                    let wait = self.fail(&addr, failure)?;
+                    if !wait.is_zero() {
+                        std::thread::sleep(wait);
+                    }
+                }
+            }
+        }
+    }
+    /// Asynchronously connects and registers,
+    /// retrying until either succeeds or a fatal error occurs.
+    ///
+    /// `connect` is called with each address to try.
+    /// See [`next_connection`][Self::next_connection] for more information.
+    #[cfg(feature = "tokio")]
+    pub async fn next_connection_tokio<C, S, F>(
+        &mut self,
+        opts: &O,
+        mut connect: impl FnMut(ServerAddr<'static>) -> F,
+    ) -> std::io::Result<Client<C, S>>
+    where
+        C: super::conn::ConnectionTokio,
+        S: ChannelSpec,
+        F: std::future::Future<Output = std::io::Result<Client<C, S>>>,
+    {
+        loop {
+            let addr = self.current()?;
+            self.register.tls = Some(addr.tls);
+            let result = match connect(addr.clone()).await {
+                Ok(mut client) => {
+                    let (send, recv) = SyncChannels.new_oneshot();
+                    // Failing to load secrets is not the server's fault.
+                    let id = client.add_with_sender(send, &self.register, opts)?;
+                    loop {
+                        let done = match client.run_tokio().await {
+                            Ok(Some((_, finished))) => finished.contains(&id),
+                            Ok(None) => break Err(Failure::Io(registration_timeout())),
+                            Err(e) => break Err(Failure::Io(e)),
+                        };
+                        if done {
+                            break finish(client, recv.0.recv_now());
+                        }
+                    }
+                }
+                Err(e) => Err(Failure::Io(e)),
+            };
+            match result {
+                Ok(client) => {
+                    self.succeed(addr);
+                    return Ok(client);
+                }
+                Err(failure) => {
+                    let wait = self.fail(&addr, failure)?;
+                    if !wait.is_zero() {
+                        tokio::time::sleep(wait).await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn registration_timeout() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::TimedOut, "registration timed out")
+}
+
+fn finish<C, S>(
+    client: Client<C, S>,
+    result: Option<Result<(), HandlerError>>,
+) -> Result<Client<C, S>, Failure> {
+    match result {
+        Some(Ok(())) => Ok(client),
+        Some(Err(e)) => Err(Failure::Register(e)),
+        None => Err(Failure::Io(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "registration ended without a result",
+        ))),
+    }
+}
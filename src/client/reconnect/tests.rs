@@ -0,0 +1,95 @@
+use super::{Backoff, Reconnector};
+use crate::client::{
+    auth::Clear,
+    channel::SyncChannels,
+    conn::{Bidir, ServerAddr},
+    register::{register_as_bot, HandlerError, Options},
+    Client,
+};
+use std::{
+    io::{Cursor, ErrorKind, Sink},
+    time::Duration,
+};
+
+type TestClient = Client<Bidir<Cursor<Vec<u8>>, Sink>, SyncChannels>;
+
+const WELCOME: &str = concat!(
+    ":example.com 001 Me :Hi, we're glad to have you.\r\n",
+    ":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n",
+);
+
+fn client(msgs: &str) -> std::io::Result<TestClient> {
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(Duration::ZERO, 1);
+    Ok(client)
+}
+
+fn options() -> Options<Clear> {
+    let mut options = Options::new();
+    options.nicks = vec![crate::string::Nick::from_str("Me")];
+    options
+}
+
+#[test]
+fn backoff_delay() {
+    let backoff = Backoff {
+        initial: Duration::from_secs(1),
+        max: Duration::from_secs(5),
+        jitter: Duration::ZERO,
+    };
+    assert_eq!(backoff.delay(0), Duration::ZERO);
+    assert_eq!(backoff.delay(1), Duration::from_secs(1));
+    assert_eq!(backoff.delay(3), Duration::from_secs(4));
+    assert_eq!(backoff.delay(4), Duration::from_secs(5));
+    assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(5));
+}
+
+#[test]
+fn reconnect_failover() {
+    let addrs =
+        vec![ServerAddr::from_host_str("a.example"), ServerAddr::from_host_str("b.example")];
+    let mut reconnector = Reconnector::new(addrs, register_as_bot());
+    reconnector.backoff = Backoff::none();
+    let mut tried = Vec::new();
+    let result = reconnector.next_connection(&options(), |addr| {
+        tried.push(addr.address.clone());
+        match addr.address.as_bytes() {
+            // The connection closes before registration completes.
+            b"a.example" => client(""),
+            b"b.example" => client(":b.example 010 Me c.example 6697 :We moved\r\n"),
+            b"c.example" => client(WELCOME),
+            _ => panic!("unexpected address {}", addr.address),
+        }
+    });
+    assert!(result.is_ok());
+    assert_eq!(tried, ["a.example", "b.example", "c.example"]);
+    let last = reconnector.last_connected().unwrap();
+    assert_eq!(last.address, "c.example");
+    assert_eq!(last.port_num(), 6697);
+    assert_eq!(reconnector.addrs().len(), 3);
+    assert_eq!(reconnector.next_addr(), Some(last));
+    assert_eq!(reconnector.failures(), 0);
+}
+
+#[test]
+fn reconnect_fatal() {
+    let addrs =
+        vec![ServerAddr::from_host_str("a.example"), ServerAddr::from_host_str("b.example")];
+    let mut reconnector = Reconnector::new(addrs, register_as_bot());
+    reconnector.backoff = Backoff::none();
+    let mut attempts = 0;
+    let result = reconnector.next_connection(&options(), |_| {
+        attempts += 1;
+        client(":example.com 465 Me :You are banned from this server\r\n")
+    });
+    let e = result.err().expect("registration should fail");
+    assert_eq!(e.kind(), ErrorKind::ConnectionRefused);
+    let e = e.get_ref().and_then(|e| e.downcast_ref::<HandlerError>());
+    assert!(matches!(e, Some(HandlerError::NoAccess(_))));
+    assert_eq!(attempts, 1);
+    assert_eq!(reconnector.last_connected(), None);
+    let mut empty = Reconnector::new(Vec::new(), register_as_bot());
+    let e = empty.next_connection(&options(), |_| client(WELCOME)).err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+}
@@ -29,7 +29,9 @@ use crate::{
 #[derive(Clone)]
 pub struct Register<O> {
     /// Returns the server password, if any.
-    pub password: fn(&O) -> Option<Line<'static>>,
+    ///
+    /// Errors if the password could not be loaded.
+    pub password: fn(&O) -> std::io::Result<Option<Line<'static>>>,
     /// Returns the username to use for connection.
     pub username: fn(&O) -> User<'static>,
     /// Returns the value used for the first unused USER parameter.
@@ -56,7 +58,81 @@ pub struct Register<O> {
     pub caps: fn(&O) -> Box<dyn CapFn>,
     /// Returns a [`SaslQueue`] to attempt
     /// and whether to close the connection on non-authentication.
-    pub auth: fn(&O) -> (SaslQueue, bool),
+    ///
+    /// Errors if the secrets needed for authentication could not be loaded.
+    pub auth: fn(&O) -> std::io::Result<(SaslQueue, bool)>,
+    /// Returns the away message to set during registration, if any.
+    ///
+    /// See [`Options::initial_away`] for how this message is sent.
+    pub away: fn(&O) -> Option<Line<'static>>,
+    /// Bounds on how much of the server's behavior to tolerate before giving up.
+    pub limits: Limits,
+    /// Whether to suspend registration instead of failing in some recoverable cases.
+    ///
+    /// See [`HandlerError::Suspended`].
+    pub suspend: bool,
+    /// Whether to suspend registration before SASL authentication starts.
+    ///
+    /// See [`Suspension::Sasl`].
+    pub suspend_sasl: bool,
+    /// Whether the connection uses TLS, if known.
+    ///
+    /// If `None`, this is determined from the [`ConnectTimes`][crate::client::state::ConnectTimes]
+    /// client state when the handler is added to a client, if it is set.
+    /// This determines how the server's STS policy is handled.
+    /// If `Some(false)` and the server advertises an STS policy with a port,
+    /// registration fails with [`HandlerError::StsUpgrade`].
+    pub tls: Option<bool>,
+    /// Returns the length of `AUTHENTICATE` chunks to send and expect during SASL.
+    ///
+    /// This should almost always be [`DEFAULT_CHUNK_LEN`][crate::client::auth::DEFAULT_CHUNK_LEN],
+    /// but some servers accept longer chunks.
+    pub sasl_chunk_len: fn(&O) -> usize,
+    /// How to handle outgoing messages that are not valid UTF-8
+    /// if the server advertises the `UTF8ONLY` ISUPPORT token.
+    ///
+    /// When registration completes, this is set on the client's queue if it is not
+    /// [`Utf8Only::Allow`][crate::client::queue::Utf8Only::Allow] and the token is present.
+    /// See [`Queue::set_utf8_only_from`][crate::client::queue::Queue::set_utf8_only_from].
+    pub utf8_only: crate::client::queue::Utf8Only,
+}
+
+/// Upper bounds on various parts of connection registration.
+///
+/// These exist to prevent broken or malicious servers from stalling registration indefinitely.
+/// Exceeding any of these causes registration to fail with [`HandlerError::Limit`].
+/// The defaults are generous enough for any reasonable server.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Limits {
+    /// The maximum number of `CAP LS` lines, including continuation lines.
+    pub cap_ls: u16,
+    /// The maximum number of `AUTHENTICATE` messages accepted for each SASL mechanism.
+    pub sasl_rounds: u16,
+    /// The maximum size in bytes of each decoded SASL challenge.
+    pub sasl_len: usize,
+    /// The maximum number of nicknames to attempt, including the first one.
+    pub nicks: u16,
+    /// The maximum number of messages to receive before registration completes.
+    pub msgs: u32,
+}
+
+impl Limits {
+    /// Returns the default limits.
+    pub const fn new() -> Self {
+        Limits {
+            cap_ls: 32,
+            sasl_rounds: crate::client::auth::DEFAULT_MAX_ROUNDS,
+            sasl_len: crate::client::auth::DEFAULT_MAX_LEN,
+            nicks: 32,
+            msgs: 8192,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits::new()
+    }
 }
 
 impl<O> Register<O> {
@@ -64,14 +140,15 @@ impl<O> Register<O> {
     /// Also returns the nickname used and a generator for fallback nicknames.
     ///
     /// # Errors
-    /// Errors only if retrieving the server password errors.
+    /// Errors only if retrieving the server password errors,
+    /// in which case nothing is sent.
     pub fn register_msgs(
         &self,
         opts: &O,
         mut sink: impl ClientMsgSink<'static>,
-    ) -> (Nick<'static>, Option<Box<dyn NickGen>>) {
+    ) -> std::io::Result<(Nick<'static>, Option<Box<dyn NickGen>>)> {
         use crate::names::cmd::{CAP, NICK, PASS, USER};
-        if let Some(pass) = (self.password)(opts) {
+        if let Some(pass) = (self.password)(opts)? {
             let pass = pass;
             let mut msg = ClientMsg::new(PASS);
             msg.args.edit().add(pass);
@@ -98,36 +175,52 @@ impl<O> Register<O> {
         let (nick, nickgen) = nicks.next_nick();
         msg.args.edit().add_word(nick.clone());
         sink.send(msg);
-        (nick, nickgen)
+        Ok((nick, nickgen))
     }
 }
 
 impl<O> Register<O> {
     /// Sends the initial burst of messages for connection registration.
     /// Also returns a [`Handler`] to perform the rest of the connection registration.
-    pub fn handler(&self, opts: &O, sink: impl ClientMsgSink<'static>) -> Handler {
-        let nicks = self.register_msgs(opts, sink);
+    ///
+    /// # Errors
+    /// Errors if retrieving the server password or building the [`SaslQueue`] errors,
+    /// in which case nothing is sent.
+    pub fn handler(&self, opts: &O, sink: impl ClientMsgSink<'static>) -> std::io::Result<Handler> {
+        let (auths, mut needs_auth) = (self.auth)(opts)?;
+        let nicks = self.register_msgs(opts, sink)?;
         let caps = (self.caps)(opts);
-        let (auths, mut needs_auth) = (self.auth)(opts);
         needs_auth &= auths.is_empty();
-        Handler::new(nicks, caps, needs_auth, auths)
+        let mut handler =
+            Handler::new(nicks, caps, needs_auth, auths, (self.away)(opts), self.limits);
+        handler.suspend = self.suspend;
+        handler.suspend_sasl = self.suspend_sasl;
+        handler.tls = self.tls;
+        handler.sasl_chunk_len = (self.sasl_chunk_len)(opts);
+        handler.utf8_only = self.utf8_only;
+        Ok(handler)
     }
 }
 
 impl<'a, O> MakeHandler<&'a O> for &'a Register<O> {
     type Value = Result<(), HandlerError>;
 
-    type Error = std::convert::Infallible;
+    type Error = std::io::Error;
 
     type Receiver<Spec: super::channel::ChannelSpec> = Spec::Oneshot<Self::Value>;
 
     fn make_handler(
         self,
-        _: &crate::client::ClientState,
+        state: &crate::client::ClientState,
         mut queue: super::queue::QueueEditGuard<'_>,
         opts: &'a O,
     ) -> Result<Box<dyn crate::client::Handler<Value = Self::Value>>, Self::Error> {
-        Ok(Box::new(self.handler(opts, &mut queue)))
+        let mut handler = self.handler(opts, &mut queue)?;
+        if handler.tls.is_none() {
+            use crate::client::state::ConnectTimes;
+            handler.tls = state.get::<ConnectTimes>().map(|connect| connect.tls.is_some());
+        }
+        Ok(Box::new(handler))
     }
 
     fn make_channel<Spec: super::channel::ChannelSpec>(
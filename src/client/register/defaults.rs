@@ -1,4 +1,4 @@
-use super::{CapFn, Register};
+use super::{CapFn, Limits, Register};
 use crate::{
     client::{
         auth::{AnySasl, LoadSecret, Sasl, SaslQueue, Secret},
@@ -39,15 +39,38 @@ pub struct Options<S, A = AnySasl<S>> {
     pub allow_sasl_fail: bool,
     /// Additional capabilities to request, on top of what the client supports.
     pub caps: BTreeSet<Key<'static>>,
+    /// An away message to set during connection registration.
+    ///
+    /// If the server supports `draft/pre-away`, the client is marked as away before
+    /// registration completes, and `*` may be used to keep an away message stored by a bouncer.
+    /// Otherwise, the away message is set once registration completes,
+    /// and `*` is ignored.
+    /// [`SelfAway`][crate::client::state::SelfAway] is only updated once the server confirms
+    /// the away message, and without `draft/pre-away`, registration waits for this confirmation.
+    pub initial_away: Option<Line<'static>>,
+    /// The length of `AUTHENTICATE` chunks to send and expect during SASL.
+    ///
+    /// This should almost always be [`DEFAULT_CHUNK_LEN`][crate::client::auth::DEFAULT_CHUNK_LEN],
+    /// but some servers accept longer chunks.
+    pub sasl_chunk_len: usize,
 }
 
 impl<S, A: Sasl> Options<S, A> {
     /// Returns a [`SaslQueue`] and whether SASL is required,
     /// as used by [`Register`][super::Register].
-    pub fn auths(&self) -> (SaslQueue, bool) {
-        let queue: SaslQueue = self.sasl.iter().collect();
+    ///
+    /// This is when [deferred][LoadSecret::defer] SASL secrets are loaded.
+    pub fn auths(&self) -> std::io::Result<(SaslQueue, bool)> {
+        let queue = SaslQueue::try_from_iter(&self.sasl)?;
         let require_sasl = !(self.allow_sasl_fail || queue.is_empty());
-        (queue, require_sasl)
+        Ok((queue, require_sasl))
+    }
+}
+
+impl<S: LoadSecret + Clone, A> Options<S, A> {
+    /// Returns the server password, loading it if it was [deferred][LoadSecret::defer].
+    pub fn pass(&self) -> std::io::Result<Option<Line<'static>>> {
+        self.pass.as_ref().map(|pass| Ok(pass.get()?.into_owned())).transpose()
     }
 }
 
@@ -68,6 +91,8 @@ impl<S, A> Options<S, A> {
             sasl: Vec::new(),
             allow_sasl_fail: false,
             caps: BTreeSet::new(),
+            initial_away: None,
+            sasl_chunk_len: crate::client::auth::DEFAULT_CHUNK_LEN,
         }
     }
 }
@@ -94,12 +119,12 @@ impl<S, A: Sasl> Options<S, A> {
 
 /// Returns a [`Register`] with sensible functions.
 pub fn register_as_custom<O>(
-    password: fn(&O) -> Option<Line<'static>>,
+    password: fn(&O) -> std::io::Result<Option<Line<'static>>>,
     username: fn(&O) -> User<'static>,
     realname: fn(&O) -> Line<'static>,
     nicks: fn(&O) -> Box<dyn crate::client::nick::NickGen>,
     caps: fn(&O) -> Box<dyn CapFn>,
-    auth: fn(&O) -> (SaslQueue, bool),
+    auth: fn(&O) -> std::io::Result<(SaslQueue, bool)>,
 ) -> Register<O> {
     Register {
         password,
@@ -110,6 +135,13 @@ pub fn register_as_custom<O>(
         nicks,
         caps,
         auth,
+        away: |_| None,
+        limits: Limits::new(),
+        suspend: false,
+        suspend_sasl: false,
+        tls: None,
+        sasl_chunk_len: |_| crate::client::auth::DEFAULT_CHUNK_LEN,
+        utf8_only: crate::client::queue::Utf8Only::Allow,
     }
 }
 
@@ -117,30 +149,40 @@ pub fn register_as_custom<O>(
 ///
 /// The capability set is treated as a set of capabilities to soft-request, on top of an
 /// intersect of the available caps and a reasonable set of defaults (see [`default_caps`]).
-pub fn register_as_client<S: LoadSecret, A: Sasl>() -> Register<Options<S, A>> {
-    register_as_custom(
-        |opts| opts.pass.clone().map(Secret::into_inner),
+pub fn register_as_client<S: LoadSecret + Clone, A: Sasl>() -> Register<Options<S, A>> {
+    let reg = register_as_custom(
+        Options::pass,
         |opts| default_client_username(opts.username.as_ref()),
         |opts| default_client_realname(opts.realname.as_ref()),
         |opts| default_client_nicks(opts.nicks.clone()),
         |opts| default_caps(opts.caps.clone(), true, false),
         Options::auths,
-    )
+    );
+    Register {
+        away: |opts| opts.initial_away.clone(),
+        sasl_chunk_len: |opts| opts.sasl_chunk_len,
+        ..reg
+    }
 }
 
 /// Returns a [`Register`] with sensible functions for bots.
 ///
 /// The capability set is treated as a list of capabilities to request,
 /// or error if not present.
-pub fn register_as_bot<S: LoadSecret, A: Sasl>() -> Register<Options<S, A>> {
-    register_as_custom(
-        |opts| opts.pass.clone().map(Secret::into_inner),
+pub fn register_as_bot<S: LoadSecret + Clone, A: Sasl>() -> Register<Options<S, A>> {
+    let reg = register_as_custom(
+        Options::pass,
         |opts| default_bot_username(opts.username.as_ref()),
         |opts| default_bot_realname(opts.realname.as_ref()),
         |opts| default_bot_nicks(opts.nicks.clone()),
         |opts| default_caps(opts.caps.clone(), false, true),
         Options::auths,
-    )
+    );
+    Register {
+        away: |opts| opts.initial_away.clone(),
+        sasl_chunk_len: |opts| opts.sasl_chunk_len,
+        ..reg
+    }
 }
 
 static DEFAULT_CAPS: std::sync::OnceLock<BTreeSet<Key<'static>>> = std::sync::OnceLock::new();
@@ -1,17 +1,23 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
 
-use super::CapFn;
+use super::{CapFn, Limits};
 use crate::{
     client::{
         auth::{self, SaslQueue},
+        conn::ConnectTimings,
         nick::NickGen,
+        state::ConnectTimes,
         ClientMsgSink,
     },
     ircmsg::{ClientMsg, ServerMsg, SharedSource, Source, UserHost},
     names::{
-        cmd::{CAP, NICK},
+        cmd::{AUTHENTICATE, AWAY, CAP, NICK},
         Cap, ISupport, NameMap,
     },
+    state::{Mode, ModeSet, StsPolicy},
     string::{Arg, Key, Line, Nick, Splitter, Word},
 };
 
@@ -37,8 +43,25 @@ pub struct Registration {
     pub caps: NameMap<Cap, bool>,
     /// The server version string, if any.
     pub version: Option<Arg<'static>>,
+    /// The user modes advertised in `RPL_MYINFO` (004).
+    pub usermodes: ModeSet,
+    /// The channel modes advertised in `RPL_MYINFO` (004),
+    /// including the ones that take parameters if listed separately.
+    pub chanmodes: ModeSet,
     /// Information about the server.
     pub isupport: NameMap<ISupport>,
+    /// The server's STS policy, if it advertised a valid one.
+    ///
+    /// If the connection uses TLS and this has a duration,
+    /// it should be persisted so that future connections also use TLS.
+    pub sts: Option<StsPolicy>,
+    /// The away message that was set during registration, if any.
+    ///
+    /// This is only set once the server confirms it with `RPL_NOWAWAY` (306),
+    /// and is `None` if the client asked to keep an away message stored by a bouncer.
+    pub away: Option<Line<'static>>,
+    /// How long each part of registration took, if measured.
+    pub timings: Option<RegistrationTimings>,
 }
 
 impl Registration {
@@ -51,39 +74,62 @@ impl Registration {
             source: None,
             caps: NameMap::new(),
             version: None,
+            usermodes: ModeSet::new(),
+            chanmodes: ModeSet::new(),
             isupport: NameMap::new(),
+            sts: None,
+            away: None,
+            timings: None,
         }
     }
     /// Saves registration to a [`ClientState`][crate::client::ClientState].
     pub fn save(self, state: &mut crate::client::ClientState) {
         use crate::client::state::*;
         let source = Source { nick: self.nick, userhost: self.userhost };
-        state.update_source_len_from(Some(&source), true);
-        state.insert::<ClientSource>(source);
-        state.insert::<Account>(self.account);
-        state.insert::<Caps>(self.caps);
-        state.insert::<ISupport>(self.isupport);
-        if let Some(server_source) = self.source {
-            state.insert::<ServerSource>(server_source);
-        }
-        if let Some(version) = self.version {
-            state.insert::<ServerVersion>(version);
-        }
+        state.update(|txn| {
+            txn.insert::<ClientSource>(source);
+            txn.insert::<Account>(self.account);
+            txn.insert::<Caps>(self.caps);
+            txn.insert::<ISupport>(self.isupport);
+            txn.insert::<SelfAway>(self.away);
+            txn.insert::<UserModes>(self.usermodes);
+            txn.insert::<ChanModes>(self.chanmodes);
+            if let Some(timings) = self.timings {
+                txn.insert::<RegTimings>(timings);
+            }
+            if let Some(server_source) = self.source {
+                txn.insert::<ServerSource>(server_source);
+            }
+            if let Some(version) = self.version {
+                txn.insert::<ServerVersion>(version);
+            }
+            if let Some(sts) = self.sts {
+                txn.insert::<Sts>(sts);
+            }
+            // Done last so that the new ISUPPORT tokens are taken into account.
+            txn.update_source_len();
+        });
     }
 }
 
 impl Registration {
     /// Updates from a `RPL_MYINFO` (004) message.
     ///
-    /// Currently ignores mode info.
+    /// Characters in the mode lists that are not valid mode letters are skipped.
     pub fn parse_myinfo(&mut self, args: &[Arg<'_>]) {
+        fn modes(arg: &Arg<'_>) -> ModeSet {
+            arg.as_bytes().iter().copied().filter_map(Mode::new).collect()
+        }
         let mut args = args.iter().skip(2);
         // ^ client, servername
         let Some(version) = args.next() else {
             return;
         };
         self.version = Some(version.clone().owning());
-        // TODO: Modes.
+        if let Some(usermodes) = args.next() {
+            self.usermodes = modes(usermodes);
+        }
+        self.chanmodes = args.take(2).map(modes).fold(ModeSet::new(), ModeSet::union);
     }
 }
 
@@ -93,6 +139,124 @@ impl Default for Registration {
     }
 }
 
+/// How long each part of connection registration took.
+///
+/// Every duration other than [`total`][RegistrationTimings::total] is `None`
+/// if that part of registration did not happen.
+/// These are measured by the registration handler as it processes messages,
+/// so they include time spent waiting on the connection and for other handlers.
+///
+/// The durations of connection setup are copied from the [`ConnectTimes`] client state
+/// if it is set when registration finishes, and are otherwise `None`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct RegistrationTimings {
+    /// How long it took to resolve the server's hostname.
+    pub resolve: Option<Duration>,
+    /// How long it took to establish a TCP connection.
+    pub tcp: Option<Duration>,
+    /// How long the TLS handshake took.
+    pub tls: Option<Duration>,
+    /// From the start of registration to the last `CAP LS` line.
+    pub cap_ls: Option<Duration>,
+    /// From the last `CAP LS` line to `CAP END`, not including SASL.
+    pub cap_negotiation: Option<Duration>,
+    /// From the first `AUTHENTICATE` message to the end of SASL.
+    pub sasl: Option<Duration>,
+    /// From `CAP END` (or the start of registration, if it was not sent) to `RPL_WELCOME` (001).
+    pub welcome_wait: Option<Duration>,
+    /// From the first `RPL_ISUPPORT` (005) to the end of registration.
+    pub isupport_to_end: Option<Duration>,
+    /// From the start of registration to the end of registration.
+    pub total: Duration,
+}
+
+impl RegistrationTimings {
+    /// Fills in the durations of connection setup from `connect`.
+    pub fn set_connect(&mut self, connect: &ConnectTimings) {
+        self.resolve = connect.resolve;
+        self.tcp = Some(connect.tcp);
+        self.tls = connect.tls;
+    }
+}
+
+impl std::fmt::Display for RegistrationTimings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let phases = [
+            ("resolve", self.resolve),
+            ("tcp", self.tcp),
+            ("tls", self.tls),
+            ("cap_ls", self.cap_ls),
+            ("cap_negotiation", self.cap_negotiation),
+            ("sasl", self.sasl),
+            ("welcome_wait", self.welcome_wait),
+            ("isupport_to_end", self.isupport_to_end),
+        ];
+        for (name, duration) in phases {
+            if let Some(duration) = duration {
+                write!(f, "{name}={duration:?} ")?;
+            }
+        }
+        write!(f, "total={:?}", self.total)
+    }
+}
+
+/// When the registration handler reached each part of registration.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Marks {
+    start: Instant,
+    cap_ls: Option<Instant>,
+    sasl_start: Option<Instant>,
+    sasl_end: Option<Instant>,
+    cap_end: Option<Instant>,
+    welcome: Option<Instant>,
+    isupport: Option<Instant>,
+}
+
+impl Marks {
+    /// Returns the current time.
+    #[cfg(not(test))]
+    fn now() -> Instant {
+        Instant::now()
+    }
+    /// Returns the current time according to the tests' virtual clock.
+    #[cfg(test)]
+    fn now() -> Instant {
+        super::tests::virtual_now()
+    }
+    fn new(start: Instant) -> Self {
+        Marks {
+            start,
+            cap_ls: None,
+            sasl_start: None,
+            sasl_end: None,
+            cap_end: None,
+            welcome: None,
+            isupport: None,
+        }
+    }
+    fn mark(mark: &mut Option<Instant>) {
+        mark.get_or_insert_with(Self::now);
+    }
+    fn finish(&self, end: Instant) -> RegistrationTimings {
+        let between =
+            |a: Option<Instant>, b: Option<Instant>| Some(b?.saturating_duration_since(a?));
+        let sasl = between(self.sasl_start, self.sasl_end);
+        let cap_negotiation =
+            between(self.cap_ls, self.cap_end).map(|d| d.saturating_sub(sasl.unwrap_or_default()));
+        RegistrationTimings {
+            resolve: None,
+            tcp: None,
+            tls: None,
+            cap_ls: between(Some(self.start), self.cap_ls),
+            cap_negotiation,
+            sasl,
+            welcome_wait: between(Some(self.cap_end.unwrap_or(self.start)), self.welcome),
+            isupport_to_end: between(self.isupport, Some(end)),
+            total: end.saturating_duration_since(self.start),
+        }
+    }
+}
+
 /// All the possible errors that can occur during registration.
 #[derive(Debug)]
 pub enum HandlerError {
@@ -106,15 +270,122 @@ pub enum HandlerError {
     Redirect(Word<'static>, u16, Line<'static>),
     /// The server sent a reply indicating an error that cannot be handled.
     ServerError(Box<ServerMsg<'static>>),
-    /// The server sent an invalid message.
-    Broken(Box<dyn std::error::Error + Send + Sync>),
+    /// The server sent an invalid message, or otherwise violated the protocol.
+    ///
+    /// Contains what went wrong and, if one was responsible, the offending message.
+    Broken(Box<dyn std::error::Error + Send + Sync>, Option<Box<ServerMsg<'static>>>),
+    /// The following required capabilities are not present on the server.
+    MissingCaps(BTreeSet<Key<'static>>),
+    /// The server exceeded one of the registration [`Limits`].
+    Limit(Limit),
+    /// Registration was suspended and can be resumed using the provided [`ResumeToken`].
+    ///
+    /// This is only returned if [`Register::suspend`][super::Register::suspend] is `true`.
+    Suspended(Suspension, ResumeToken),
+    /// The server has an STS policy and the connection does not use TLS.
+    ///
+    /// The connection should be closed and re-established using TLS on the provided port.
+    /// This is only returned if [`Register::tls`][super::Register::tls] is `Some(false)`.
+    StsUpgrade(u16),
+}
+
+/// The reasons connection registration may be suspended.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Suspension {
+    /// Every available nickname was rejected.
+    ///
+    /// Can be resumed with [`Resume::Nicks`].
+    NoNicks,
+    /// SASL authentication is about to start using this mechanism.
+    ///
+    /// This is only returned if [`Register::suspend_sasl`][super::Register::suspend_sasl]
+    /// is `true`. Can be resumed with [`Resume::Continue`].
+    Sasl(Arg<'static>),
     /// The following required capabilities are not present on the server.
+    ///
+    /// Can be resumed with [`Resume::WithoutCaps`].
     MissingCaps(BTreeSet<Key<'static>>),
 }
 
+/// How to resume suspended connection registration.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Resume {
+    /// Attempt these nicknames.
+    Nicks(Vec<Nick<'static>>),
+    /// Continue without the missing capabilities.
+    WithoutCaps,
+    /// Continue as normal.
+    Continue,
+}
+
+/// Messages received while registration is suspended.
+type Buffered = std::sync::Arc<std::sync::Mutex<Vec<ServerMsg<'static>>>>;
+
+/// Suspended connection registration.
+///
+/// Registration can be resumed by [adding][crate::client::Client::add] this
+/// with a [`Resume`] appropriate for the [`Suspension`] it was returned with.
+/// If the `Resume` is not appropriate, adding this returns the same suspension.
+///
+/// While registration is suspended, the server is still waiting on the client.
+/// The suspended handler keeps running to answer PINGs and to keep other messages,
+/// which are processed once registration is resumed.
+/// At most [`Limits::msgs`] messages are kept this way.
+pub struct ResumeToken {
+    reason: Suspension,
+    handler: std::sync::Mutex<Box<Handler>>,
+    buffered: Buffered,
+}
+
+impl std::fmt::Debug for ResumeToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResumeToken").field("reason", &self.reason).finish_non_exhaustive()
+    }
+}
+
+impl ResumeToken {
+    fn suspend(reason: Suspension, handler: Box<Handler>, buffered: Buffered) -> HandlerError {
+        let token = ResumeToken { reason: reason.clone(), handler: handler.into(), buffered };
+        HandlerError::Suspended(reason, token)
+    }
+}
+
+/// The individual bounds in [`Limits`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Limit {
+    /// [`Limits::cap_ls`].
+    CapLs,
+    /// [`Limits::sasl_rounds`].
+    SaslRounds,
+    /// [`Limits::sasl_len`].
+    SaslLen,
+    /// [`Limits::nicks`].
+    Nicks,
+    /// [`Limits::msgs`].
+    Msgs,
+}
+
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::CapLs => write!(f, "CAP LS lines"),
+            Limit::SaslRounds => write!(f, "SASL challenges"),
+            Limit::SaslLen => write!(f, "SASL challenge length"),
+            Limit::Nicks => write!(f, "nickname attempts"),
+            Limit::Msgs => write!(f, "messages"),
+        }
+    }
+}
+
 impl HandlerError {
     pub(self) fn broken(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> HandlerError {
-        HandlerError::Broken(e.into())
+        HandlerError::Broken(e.into(), None)
+    }
+    pub(self) fn broken_by(
+        e: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+        msg: &ServerMsg<'_>,
+    ) -> HandlerError {
+        HandlerError::Broken(e.into(), Some(Box::new(msg.clone().owning())))
     }
 }
 
@@ -125,8 +396,20 @@ impl std::fmt::Display for HandlerError {
             HandlerError::NoNicks => write!(f, "no fallback nicks remaining"),
             HandlerError::NoLogin => write!(f, "failed to log in"),
             HandlerError::ServerError(e) => write!(f, "server error: {e}"),
-            HandlerError::Broken(e) => write!(f, "invalid message: {e}"),
+            HandlerError::Broken(e, None) => write!(f, "invalid message: {e}"),
+            HandlerError::Broken(e, Some(msg)) => write!(f, "invalid message: {e}: {msg}"),
             HandlerError::Redirect(s, p, i) => write!(f, "redirected to {s}:{p}: {i}"),
+            HandlerError::Limit(l) => write!(f, "too many {l}"),
+            HandlerError::StsUpgrade(p) => write!(f, "server requires TLS on port {p}"),
+            HandlerError::Suspended(Suspension::NoNicks, _) => {
+                write!(f, "suspended: no fallback nicks remaining")
+            }
+            HandlerError::Suspended(Suspension::MissingCaps(_), _) => {
+                write!(f, "suspended: missing required capabilities")
+            }
+            HandlerError::Suspended(Suspension::Sasl(mech), _) => {
+                write!(f, "suspended: about to authenticate using {mech}")
+            }
             HandlerError::MissingCaps(c) => {
                 let caps = c
                     .iter()
@@ -145,7 +428,7 @@ impl std::fmt::Display for HandlerError {
 
 impl std::error::Error for HandlerError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        if let HandlerError::Broken(e) = self {
+        if let HandlerError::Broken(e, _) = self {
             Some(e.as_ref())
         } else {
             None
@@ -160,7 +443,7 @@ impl From<HandlerError> for std::io::Error {
             HandlerError::NoAccess(e) => {
                 Error::new(ErrorKind::ConnectionRefused, HandlerError::NoAccess(e))
             }
-            HandlerError::Broken(e) => Error::new(ErrorKind::InvalidData, e),
+            v @ HandlerError::Broken(..) => Error::new(ErrorKind::InvalidData, v),
             v => Error::new(ErrorKind::Other, v),
         }
     }
@@ -174,20 +457,23 @@ pub(super) enum HandlerState {
     Ack(BTreeSet<Key<'static>>, SaslQueue),
     #[cfg(feature = "base64")]
     Sasl(crate::client::auth::Handler),
+    Suspended(BTreeSet<Key<'static>>, SaslQueue),
     CapEnd,
     AwaitWelcome,
     AwaitEnd,
+    AwaitAway,
 }
 
 impl HandlerState {
     /// Handle an ACK, NAK, or DEL.
     ///
-    /// Also sends the initial AUTHENTICATE message for SASL.
+    /// Also prepares for SASL, but does not send the initial AUTHENTICATE message.
     pub fn ack(
         &mut self,
         ack: bool,
         caps: &BTreeMap<Key<'_>, Word<'_>>,
-        mut sink: impl ClientMsgSink<'static>,
+        limits: &Limits,
+        sasl_chunk_len: usize,
     ) -> Result<(), HandlerError> {
         if let HandlerState::Ack(ackd, queue) = self {
             let caps = caps.keys().map(|k| k.clone().owning()).collect();
@@ -202,13 +488,19 @@ impl HandlerState {
             };
             if ackd.is_empty() {
                 #[cfg(feature = "base64")]
-                if let Some(handler) = auth::Handler::from_queue(std::mem::take(queue)) {
-                    // If we're here, SASL was acked,
-                    // as the queue was nonempty and we request "sasl" when so.
-                    sink.send(handler.auth_msg());
-                    *self = HandlerState::Sasl(handler);
-                    return Ok(());
+                {
+                    let queue = std::mem::take(queue);
+                    if let Some(mut handler) = auth::Handler::from_queue(queue, sasl_chunk_len) {
+                        handler.set_max_rounds(limits.sasl_rounds);
+                        handler.set_max_len(limits.sasl_len);
+                        // If we're here, SASL was acked,
+                        // as the queue was nonempty and we request "sasl" when so.
+                        *self = HandlerState::Sasl(handler);
+                        return Ok(());
+                    }
                 }
+                #[cfg(not(feature = "base64"))]
+                let _ = (queue, limits, sasl_chunk_len);
                 *self = HandlerState::CapEnd;
             }
         }
@@ -221,6 +513,21 @@ pub struct Handler {
     pub(super) nicks: Option<Box<dyn NickGen>>,
     pub(super) state: HandlerState,
     pub(super) needs_auth: bool,
+    pub(super) suspend: bool,
+    pub(super) suspend_sasl: bool,
+    /// If this handler is standing in for suspended registration,
+    /// where to keep messages and how many to keep.
+    pub(super) buffering: Option<(std::sync::Weak<std::sync::Mutex<Vec<ServerMsg<'static>>>>, u32)>,
+    pub(super) tls: Option<bool>,
+    pub(super) sasl_chunk_len: usize,
+    pub(super) utf8_only: crate::client::queue::Utf8Only,
+    pub(super) away: Option<Line<'static>>,
+    pub(super) sent_away: Option<Line<'static>>,
+    pub(super) limits: Limits,
+    pub(super) nick_attempts: u16,
+    pub(super) cap_ls_lines: u16,
+    pub(super) msgs: u32,
+    pub(super) marks: Marks,
     pub(super) reg: Registration,
 }
 
@@ -230,12 +537,27 @@ impl Handler {
         caps: Box<dyn CapFn>,
         needs_auth: bool,
         auths: SaslQueue,
+        away: Option<Line<'static>>,
+        limits: Limits,
     ) -> Self {
         let (nick, nicks) = nicks;
         Handler {
             nicks,
             state: HandlerState::Req(caps, auths),
             needs_auth,
+            suspend: false,
+            suspend_sasl: false,
+            buffering: None,
+            tls: None,
+            sasl_chunk_len: auth::DEFAULT_CHUNK_LEN,
+            utf8_only: crate::client::queue::Utf8Only::Allow,
+            away,
+            sent_away: None,
+            limits,
+            nick_attempts: 1,
+            cap_ls_lines: 0,
+            msgs: 0,
+            marks: Marks::new(Marks::now()),
             reg: Registration::new(nick),
         }
     }
@@ -244,6 +566,10 @@ impl Handler {
         msg: &ServerMsg<'_>,
         mut sink: impl ClientMsgSink<'static>,
     ) -> Result<Option<Registration>, HandlerError> {
+        if self.msgs >= self.limits.msgs {
+            return Err(HandlerError::Limit(Limit::Msgs));
+        }
+        self.msgs += 1;
         if self.reg.source.is_none() {
             self.reg.source = msg.source.clone().map(SharedSource::owning_merged);
         }
@@ -251,20 +577,30 @@ impl Handler {
             return Ok(None);
         }
         // Ignore errors related to SASL.
-        let mut ignore_sasl = false;
+        #[cfg(feature = "base64")]
+        let ignore_sasl = matches!(self.state, HandlerState::Sasl(_));
+        #[cfg(not(feature = "base64"))]
+        let ignore_sasl = false;
         #[cfg(feature = "base64")]
         if let HandlerState::Sasl(sasl) = &mut self.state {
-            ignore_sasl = true;
             match sasl.handle(msg, sink.borrow_mut()) {
                 Ok(false) => (),
                 Ok(true) => {
+                    Marks::mark(&mut self.marks.sasl_end);
                     self.state = HandlerState::CapEnd;
                 }
+                Err(auth::HandlerError::TooManyRounds(_)) => {
+                    return Err(HandlerError::Limit(Limit::SaslRounds));
+                }
+                Err(auth::HandlerError::TooLong(_)) => {
+                    return Err(HandlerError::Limit(Limit::SaslLen));
+                }
                 Err(_e) => {
                     // Auth failed irrecoverably.
                     // May still be able to continue depending on needs_auth.
                     #[cfg(feature = "tracing")]
                     tracing::error!("{_e}");
+                    Marks::mark(&mut self.marks.sasl_end);
                     self.state = HandlerState::CapEnd;
                 }
             }
@@ -284,6 +620,7 @@ impl Handler {
                 if let Some(nick) = nick {
                     self.reg.nick = nick;
                 }
+                Marks::mark(&mut self.marks.welcome);
                 if let Some(source) = &msg.source {
                     use std::ops::Deref;
                     if !self.reg.source.as_ref().is_some_and(|src| src == source.deref()) {
@@ -298,46 +635,44 @@ impl Handler {
                 Ok(None)
             }
             "005" if matches!(self.state, HandlerState::AwaitEnd) => {
+                Marks::mark(&mut self.marks.isupport);
                 let Some((_, isupports)) = msg.args.words().split_first() else {
                     // Bad ISUPPORT message, but let's be forgiving.
                     return Ok(None);
                 };
-                let mut ism = self.reg.isupport.edit();
-                for isupport in isupports {
-                    let mut splitter = Splitter::new(isupport.clone().owning());
-                    let Ok(key) = splitter.string::<Key>(false) else {
-                        continue;
-                    };
-                    let value: Word<'static> = if splitter.next_byte().is_some_and(|b| b != b'=') {
-                        // Weirdness in an ISUPPORT tag. Bail.
-                        // TODO: Log.
-                        continue;
-                    } else {
-                        splitter.rest_or_default::<Word>()
-                    };
-                    ism.insert((key, value), ());
-                }
+                crate::names::isupport::apply_tokens(&mut self.reg.isupport, isupports);
                 Ok(None)
             }
             "004" => {
                 // We actually care about 001 because it's where we get some basic info.
                 // and we'd rather non-compliant severs skip 004 in favor of 005.
-                Err(HandlerError::Broken("004 sent before 001".into()))
+                Err(HandlerError::broken_by("004 sent before 001", msg))
             }
             "005" => {
                 // We probably have an RFC2819 RPL_BOUNCE. Try parsing it.
                 // Error either way.
                 let Some(last) = msg.args.split_last().1 else {
-                    return Err(HandlerError::Broken("empty 005 message".into()));
+                    return Err(HandlerError::broken_by("empty 005 message", msg));
                 };
                 let split = || {
-                    let mut splitter = last.splitn(2, |c| *c == b',');
-                    let server = splitter.next()?.rsplit(|c| !c.is_ascii_graphic()).next()?;
-                    let port = splitter.next()?.rsplit(|c| !c.is_ascii_digit()).next()?;
-                    // We don't care about this being performant.
-                    // This path is so cold it's arctic.
-                    let server = Word::from_bytes(server).ok()?;
-                    let port = std::str::from_utf8(port).ok()?.parse().ok()?;
+                    let mut splitter = Splitter::new(last.clone());
+                    // The port is the trailing number, and must come after a comma.
+                    let port: Word = splitter
+                        .save(true, false)
+                        .until_byte_from_end(|c| !c.is_ascii_digit())
+                        .string_from_end(true)
+                        .ok()?;
+                    let port = std::str::from_utf8(port.as_bytes()).ok()?.parse().ok()?;
+                    let len = splitter.len();
+                    if splitter.until_byte_eq(b',').len() == len {
+                        return None;
+                    }
+                    // The server is the last word before the first comma.
+                    let server: Word = splitter
+                        .save(true, false)
+                        .until_byte_from_end(|c| !c.is_ascii_graphic())
+                        .string_from_end(true)
+                        .ok()?;
                     Some((server, port))
                 };
                 if let Some((server, port)) = split() {
@@ -360,8 +695,9 @@ impl Handler {
                             port,
                             info.clone().owning(),
                         )),
-                        Err(e) => Err(HandlerError::Broken(
-                            format!("not a valid port `{port}`: {e}").into(),
+                        Err(e) => Err(HandlerError::broken_by(
+                            format!("not a valid port `{port}`: {e}"),
+                            msg,
                         )),
                     }
                 } else {
@@ -370,11 +706,31 @@ impl Handler {
             }
             "376" | "422" if matches!(self.state, HandlerState::AwaitEnd) => {
                 // End of/no MOTD. We're done.
+                // If we didn't get to set our away message during registration, do so now.
+                // "*" only has meaning with pre-away, so don't send it here.
+                self.reg.timings = Some(self.marks.finish(Marks::now()));
+                if let Some(away) = self.away.take().filter(|a| *a != b"*") {
+                    self.send_away(away, sink.borrow_mut());
+                    self.state = HandlerState::AwaitAway;
+                    return Ok(None);
+                }
                 Ok(Some(std::mem::take(&mut self.reg)))
             }
+            "306" => {
+                // RPL_NOWAWAY. The away message was actually set.
+                if let Some(away) = self.sent_away.take() {
+                    // "*" keeps whatever a bouncer has stored, which we don't know.
+                    self.reg.away = Some(away).filter(|a| *a != b"*");
+                }
+                if matches!(self.state, HandlerState::AwaitAway) {
+                    Ok(Some(std::mem::take(&mut self.reg)))
+                } else {
+                    Ok(None)
+                }
+            }
             "376" | "422" => {
                 // If we're here, we did NOT see 004.
-                Err(HandlerError::Broken("unexpected MOTD message".into()))
+                Err(HandlerError::broken_by("unexpected MOTD message", msg))
             }
             "432" => {
                 // Invalid nick.
@@ -397,8 +753,8 @@ impl Handler {
                 if let Some((account, args)) = args.split_last() {
                     self.reg.account = Some(account.clone().owning());
                     if let Some(whoami) = args.last() {
-                        let whoami =
-                            Source::parse(whoami.clone().owning()).map_err(HandlerError::broken)?;
+                        let whoami = Source::parse(whoami.clone().owning())
+                            .map_err(|e| HandlerError::broken_by(e, msg))?;
                         self.reg.nick = whoami.nick;
                         self.reg.userhost = whoami.userhost;
                     }
@@ -408,8 +764,8 @@ impl Handler {
             "901" => {
                 self.reg.account = None;
                 if let Some(whoami) = msg.args.clone().split_last().0.last() {
-                    let whoami =
-                        Source::parse(whoami.clone().owning()).map_err(HandlerError::broken)?;
+                    let whoami = Source::parse(whoami.clone().owning())
+                        .map_err(|e| HandlerError::broken_by(e, msg))?;
                     self.reg.nick = whoami.nick;
                     self.reg.userhost = whoami.userhost;
                 }
@@ -419,23 +775,29 @@ impl Handler {
             "CAP" => {
                 use crate::client::cap;
                 let cap_msg = cap::ServerMsgArgs::parse(&msg.args.clone().owning())
-                    .map_err(HandlerError::broken)?;
+                    .map_err(|e| HandlerError::broken_by(e, msg))?;
+                if cap_msg.subcmd == cap::SubCmd::Ls {
+                    if self.cap_ls_lines >= self.limits.cap_ls {
+                        return Err(HandlerError::Limit(Limit::CapLs));
+                    }
+                    self.cap_ls_lines += 1;
+                }
                 match cap_msg.subcmd {
                     cap::SubCmd::Ls if cap_msg.is_last => {
-                        let mut caps = self.reg.caps.edit();
-                        for (key, value) in cap_msg.caps {
-                            caps.try_insert((key, value), false);
-                        }
-                        std::mem::drop(caps);
+                        Marks::mark(&mut self.marks.cap_ls);
+                        self.add_caps(cap_msg.caps)?;
                         let state = std::mem::take(&mut self.state);
                         if let HandlerState::Req(reqs, mut auths) = state {
-                            use crate::names::cap::{SASL, STS};
-                            // Filter SASL mechanisms.
-                            match self.reg.caps.get_parsed(SASL) {
-                                Some(Ok(mechs)) => {
-                                    auths.retain(&|mech| mechs.contains(mech.as_bytes()));
+                            use crate::names::cap::SASL;
+                            // Filter and order SASL mechanisms.
+                            match self.reg.caps.get_union_raw(&SASL::NAME) {
+                                Some((_, raw)) => {
+                                    let mechs = SASL::mechs_in_order(raw);
+                                    auths.retain(&|mech| {
+                                        mechs.iter().any(|m| m.as_bytes() == mech.as_bytes())
+                                    });
+                                    auths.sort_by_server_preference(&mechs);
                                 }
-                                Some(_) => (),
                                 None => auths.clear(),
                             }
                             // Check the set of capabilities.
@@ -448,50 +810,38 @@ impl Handler {
                             }
                             let diff: BTreeSet<_> = reqs.difference(&avail).cloned().collect();
                             if !diff.is_empty() {
+                                if self.suspend {
+                                    self.state = HandlerState::Suspended(reqs, auths);
+                                }
                                 return Err(HandlerError::MissingCaps(diff));
                             }
-                            // "sts" is purely informative and must never be requested.
-                            reqs.remove(&STS::NAME);
-                            self.state = if reqs.is_empty() {
-                                HandlerState::CapEnd
-                            } else {
-                                cap::req(
-                                    reqs.iter().cloned(),
-                                    Some(self.reg.nick.clone().into_super()),
-                                    self.reg.source.as_ref(),
-                                    sink.borrow_mut(),
-                                );
-                                HandlerState::Ack(reqs, auths)
-                            };
+                            self.req_caps(reqs, auths, sink.borrow_mut());
                         } else {
                             self.state = state;
                         }
                     }
-                    cap::SubCmd::Ls | cap::SubCmd::New => {
-                        let mut caps = self.reg.caps.edit();
-                        for (key, value) in cap_msg.caps {
-                            caps.try_insert((key, value), false);
-                        }
-                    }
+                    cap::SubCmd::Ls | cap::SubCmd::New => self.add_caps(cap_msg.caps)?,
                     cap::SubCmd::Ack => {
                         let mut caps = self.reg.caps.edit();
-                        self.state.ack(true, &cap_msg.caps, sink.borrow_mut())?;
+                        self.state.ack(true, &cap_msg.caps, &self.limits, self.sasl_chunk_len)?;
                         // Assume that every ACK is a positive ACK without actually checking.
                         for (key, value) in cap_msg.caps {
                             caps.insert_or_update((key, value), true);
                         }
                     }
                     cap::SubCmd::Nak => {
-                        self.state.ack(false, &cap_msg.caps, sink.borrow_mut())?;
+                        self.state.ack(false, &cap_msg.caps, &self.limits, self.sasl_chunk_len)?;
                     }
                     cap::SubCmd::Del => {
                         let mut caps = self.reg.caps.edit();
                         cap_msg.caps.keys().for_each(|cap| {
                             caps.remove_raw(cap);
                         });
-                        self.state.ack(false, &cap_msg.caps, sink.borrow_mut())?;
+                        self.state.ack(false, &cap_msg.caps, &self.limits, self.sasl_chunk_len)?;
+                    }
+                    cap::SubCmd::List => {
+                        return Err(HandlerError::broken_by("unexpected CAP LIST", msg))
                     }
-                    cap::SubCmd::List => return Err(HandlerError::broken("unexpected CAP LIST")),
                 }
                 Ok(None)
             }
@@ -502,18 +852,197 @@ impl Handler {
                 Ok(None)
             }
         }?;
+        #[cfg(feature = "base64")]
+        if !self.suspend_sasl {
+            self.start_sasl(sink.borrow_mut());
+        }
+        self.cap_end(sink)?;
+        Ok(retval)
+    }
+    /// Adds capabilities from `CAP LS` or `CAP NEW`, then updates the STS policy.
+    ///
+    /// Later values replace earlier ones, as a capability may be advertised
+    /// more than once over a multiline `CAP LS` reply. Whether it is enabled is kept.
+    /// Errors if the connection needs to be upgraded to TLS.
+    fn add_caps(
+        &mut self,
+        caps: BTreeMap<Key<'static>, Word<'static>>,
+    ) -> Result<(), HandlerError> {
+        use crate::names::cap::STS;
+        let mut edit = self.reg.caps.edit();
+        for (key, value) in caps {
+            let enabled = edit.get_extra_raw(&key).copied().unwrap_or_default();
+            edit.insert((key, value), enabled);
+        }
+        std::mem::drop(edit);
+        self.reg.sts = self.reg.caps.get_parsed(STS).and_then(Result::ok);
+        if self.tls == Some(false) {
+            if let Some(port) = self.reg.sts.and_then(|sts| sts.port) {
+                return Err(HandlerError::StsUpgrade(port));
+            }
+        }
+        Ok(())
+    }
+    /// Requests capabilities after the last `CAP LS` line.
+    fn req_caps(
+        &mut self,
+        mut reqs: BTreeSet<Key<'static>>,
+        auths: SaslQueue,
+        sink: impl ClientMsgSink<'static>,
+    ) {
+        use crate::names::cap::{DRAFT_PRE_AWAY, STS};
+        // "sts" is purely informative and must never be requested.
+        reqs.remove(&STS::NAME);
+        // Opportunistically request pre-away if we want to be away.
+        if self.away.is_some() && self.reg.caps.get_extra(DRAFT_PRE_AWAY).is_some() {
+            reqs.insert(DRAFT_PRE_AWAY::NAME);
+        }
+        self.state = if reqs.is_empty() {
+            HandlerState::CapEnd
+        } else {
+            crate::client::cap::req(
+                reqs.iter().cloned(),
+                Some(self.reg.nick.clone().into_super()),
+                self.reg.source.as_ref(),
+                sink,
+            );
+            HandlerState::Ack(reqs, auths)
+        };
+    }
+    /// Ends capability negotiation if it's ready to be ended.
+    fn cap_end(&mut self, mut sink: impl ClientMsgSink<'static>) -> Result<(), HandlerError> {
         if matches!(self.state, HandlerState::CapEnd) {
             if self.needs_auth && self.reg.account.is_none() {
                 return Err(HandlerError::NoLogin);
             }
+            // With pre-away, the away message can be set before registration completes.
+            if self.reg.caps.get_extra(crate::names::cap::DRAFT_PRE_AWAY) == Some(&true) {
+                if let Some(away) = self.away.take() {
+                    self.send_away(away, sink.borrow_mut());
+                }
+            }
             let mut msg = crate::ircmsg::ClientMsg::new(CAP);
             msg.args.edit().add_literal("END");
             sink.send(msg);
+            Marks::mark(&mut self.marks.cap_end);
             self.state = HandlerState::AwaitWelcome;
         }
-        Ok(retval)
+        Ok(())
+    }
+    /// Sends the initial `AUTHENTICATE` message if SASL is ready to start.
+    #[cfg(feature = "base64")]
+    fn start_sasl(&mut self, mut sink: impl ClientMsgSink<'static>) {
+        if let HandlerState::Sasl(sasl) = &self.state {
+            if self.marks.sasl_start.is_none() {
+                sink.send(sasl.auth_msg());
+                Marks::mark(&mut self.marks.sasl_start);
+            }
+        }
+    }
+    /// Returns why registration should be suspended even though nothing went wrong.
+    fn pending_suspension(&self) -> Option<Suspension> {
+        #[cfg(feature = "base64")]
+        if let HandlerState::Sasl(sasl) = &self.state {
+            if self.marks.sasl_start.is_none() {
+                return Some(Suspension::Sasl(sasl.mechanism()));
+            }
+        }
+        None
+    }
+    /// Converts recoverable errors into suspensions if suspension is enabled.
+    fn suspend_on(&mut self, e: HandlerError, buffered: Buffered) -> HandlerError {
+        let reason = match e {
+            HandlerError::NoNicks if self.suspend => Suspension::NoNicks,
+            HandlerError::MissingCaps(caps)
+                if matches!(self.state, HandlerState::Suspended(..)) =>
+            {
+                Suspension::MissingCaps(caps)
+            }
+            e => return e,
+        };
+        self.suspend_with(reason, buffered)
+    }
+    /// Moves `self` into a [`ResumeToken`],
+    /// leaving behind a handler that keeps messages in `buffered`.
+    fn suspend_with(&mut self, reason: Suspension, buffered: Buffered) -> HandlerError {
+        let mut handler = Handler::new(
+            (crate::names::STAR, None),
+            Box::new(|_: &BTreeSet<Key<'_>>| BTreeSet::new()),
+            false,
+            SaslQueue::new(),
+            None,
+            Limits::new(),
+        );
+        let max = self.limits.msgs.saturating_sub(self.msgs);
+        handler.buffering = Some((std::sync::Arc::downgrade(&buffered), max));
+        ResumeToken::suspend(reason, Box::new(std::mem::replace(self, handler)), buffered)
+    }
+    /// Processes the messages that were received while suspended.
+    fn replay(
+        &mut self,
+        buffered: &Buffered,
+        mut sink: impl ClientMsgSink<'static>,
+    ) -> Result<(), HandlerError> {
+        let msgs = std::mem::take(
+            &mut *buffered.lock().unwrap_or_else(std::sync::PoisonError::into_inner),
+        );
+        for msg in msgs {
+            if self.handle(&msg, sink.borrow_mut())?.is_some() {
+                // The server should have been waiting on us.
+                return Err(HandlerError::broken_by(
+                    "registration completed while suspended",
+                    &msg,
+                ));
+            }
+        }
+        Ok(())
+    }
+    fn resume(
+        &mut self,
+        reason: &Suspension,
+        resume: Resume,
+        buffered: &Buffered,
+        mut sink: impl ClientMsgSink<'static>,
+    ) -> Result<(), Option<HandlerError>> {
+        match (reason, resume) {
+            (Suspension::NoNicks, Resume::Nicks(nicks)) => {
+                let Some(nicks) = crate::client::nick::from_iter(nicks) else {
+                    return Err(None);
+                };
+                self.replay(buffered, sink.borrow_mut()).map_err(Some)?;
+                self.nicks = Some(Box::new(nicks));
+                self.next_nick(sink).map_err(Some)
+            }
+            (Suspension::MissingCaps(_), Resume::WithoutCaps) => {
+                self.replay(buffered, sink.borrow_mut()).map_err(Some)?;
+                let HandlerState::Suspended(mut reqs, auths) = std::mem::take(&mut self.state)
+                else {
+                    return Err(Some(HandlerError::broken("inconsistent suspension state")));
+                };
+                reqs.retain(|cap| self.reg.caps.get_extra_raw(cap).is_some());
+                self.req_caps(reqs, auths, sink.borrow_mut());
+                self.cap_end(sink).map_err(Some)
+            }
+            (Suspension::Sasl(_), Resume::Continue) => {
+                self.replay(buffered, sink.borrow_mut()).map_err(Some)?;
+                #[cfg(feature = "base64")]
+                self.start_sasl(sink);
+                Ok(())
+            }
+            _ => Err(None),
+        }
+    }
+    fn send_away(&mut self, away: Line<'static>, mut sink: impl ClientMsgSink<'static>) {
+        let mut msg = ClientMsg::new(AWAY);
+        msg.args.edit().add(away.clone());
+        sink.send(msg);
+        self.sent_away = Some(away);
     }
     fn next_nick(&mut self, mut sink: impl ClientMsgSink<'static>) -> Result<(), HandlerError> {
+        if self.nick_attempts >= self.limits.nicks {
+            return Err(HandlerError::Limit(Limit::Nicks));
+        }
+        self.nick_attempts += 1;
         let Some(nicks) = self.nicks.take() else { return Err(HandlerError::NoNicks) };
         let (nick, nicks) = nicks.next_nick();
         let mut msg = ClientMsg::new(NICK);
@@ -535,17 +1064,55 @@ impl crate::client::Handler for Handler {
         mut queue: crate::client::queue::QueueEditGuard<'_>,
         mut channel: crate::client::channel::SenderRef<'_, Self::Value>,
     ) -> std::ops::ControlFlow<()> {
-        match self.handle(msg, &mut queue) {
-            Ok(Some(v)) => {
-                v.save(state);
-                channel.send(Ok(()));
-                std::ops::ControlFlow::Break(())
+        use std::ops::ControlFlow;
+        if let Some((buffered, max)) = &self.buffering {
+            // Registration is suspended. Stop once the ResumeToken is used or dropped.
+            let Some(buffered) = buffered.upgrade() else {
+                return ControlFlow::Break(());
+            };
+            if !crate::client::handlers::pong(msg, &mut queue) {
+                let mut buffered =
+                    buffered.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+                if buffered.len() < *max as usize {
+                    buffered.push(msg.clone().owning());
+                }
             }
-            Ok(None) => std::ops::ControlFlow::Continue(()),
-            Err(e) => {
-                channel.send(Err(e));
-                std::ops::ControlFlow::Break(())
+            return ControlFlow::Continue(());
+        }
+        // SASL messages are urgent, which also lets failing SASL abort as registration ends.
+        let sink = |msg: ClientMsg<'static>| {
+            if msg.cmd == AUTHENTICATE {
+                queue.push_urgent(msg);
+            } else {
+                queue.push(msg);
             }
+        };
+        let e = match self.handle(msg, sink) {
+            Ok(Some(mut v)) => {
+                if let (Some(timings), Some(connect)) =
+                    (&mut v.timings, state.get::<ConnectTimes>())
+                {
+                    timings.set_connect(connect);
+                }
+                if self.utf8_only != crate::client::queue::Utf8Only::Allow {
+                    queue.set_utf8_only_from(&v.isupport, self.utf8_only);
+                }
+                v.save(state);
+                let _ = channel.send(Ok(()));
+                return ControlFlow::Break(());
+            }
+            Ok(None) => match self.pending_suspension() {
+                Some(reason) => self.suspend_with(reason, Buffered::default()),
+                None => return ControlFlow::Continue(()),
+            },
+            Err(e) => self.suspend_on(e, Buffered::default()),
+        };
+        let suspended = matches!(e, HandlerError::Suspended(..));
+        let _ = channel.send(Err(e));
+        if suspended {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(())
         }
     }
 
@@ -553,3 +1120,36 @@ impl crate::client::Handler for Handler {
         matches!(self.state, HandlerState::AwaitWelcome)
     }
 }
+
+impl crate::client::MakeHandler<Resume> for ResumeToken {
+    type Value = Result<(), HandlerError>;
+
+    type Error = HandlerError;
+
+    type Receiver<Spec: crate::client::channel::ChannelSpec> = Spec::Oneshot<Self::Value>;
+
+    fn make_handler(
+        self,
+        _: &crate::client::ClientState,
+        mut queue: crate::client::queue::QueueEditGuard<'_>,
+        resume: Resume,
+    ) -> Result<Box<dyn crate::client::Handler<Value = Self::Value>>, Self::Error> {
+        let ResumeToken { reason, handler, buffered } = self;
+        let mut handler = handler.into_inner().unwrap_or_else(std::sync::PoisonError::into_inner);
+        match handler.resume(&reason, resume, &buffered, &mut queue) {
+            Ok(()) => match handler.pending_suspension() {
+                Some(reason) => Err(handler.suspend_with(reason, buffered)),
+                None => Ok(handler),
+            },
+            Err(Some(e)) => Err(handler.suspend_on(e, buffered)),
+            Err(None) => Err(ResumeToken::suspend(reason, handler, buffered)),
+        }
+    }
+
+    fn make_channel<Spec: crate::client::channel::ChannelSpec>(
+        spec: &Spec,
+    ) -> (Box<dyn crate::client::channel::Sender<Value = Self::Value> + Send>, Self::Receiver<Spec>)
+    {
+        spec.new_oneshot()
+    }
+}
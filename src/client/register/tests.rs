@@ -1,6 +1,6 @@
 use std::{io::Cursor, time::Duration};
 
-use super::{register_as_bot, HandlerError, Options};
+use super::{register_as_bot, HandlerError, Limit, Limits, Options, Register};
 use crate::{
     client::{
         auth::Clear,
@@ -9,7 +9,7 @@ use crate::{
         state::{Caps, ISupport},
         Client, ClientState,
     },
-    string::{Key, Nick},
+    string::{Key, Line, Nick},
 };
 
 /// Test registration while ignoring the messages the handler sends.
@@ -78,6 +78,94 @@ fn ircv3_reg_simple() {
     assert_eq!(netname, b"example.com");
 }
 
+#[test]
+fn myinfo_modes() {
+    use crate::{
+        client::state::{ChanModes, UserModes},
+        state::{Mode, ModeSet},
+    };
+    let state = static_register(
+        concat!(
+            ":example.com 001 Me :Hi, we're glad to have you.\r\n",
+            ":example.com 004 Me example.com the-latest-one iwB# bnt kl\r\n",
+            ":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n",
+        )
+        .as_bytes(),
+    )
+    .expect("reg failed");
+    let modes = |s: &[u8]| s.iter().copied().filter_map(Mode::new).collect::<ModeSet>();
+    assert_eq!(state.get::<UserModes>().copied(), Some(modes(b"iwB")));
+    assert_eq!(state.get::<ChanModes>().copied(), Some(modes(b"bntkl")));
+}
+
+#[cfg(feature = "base64")]
+#[test]
+fn cap_ls_multiline() {
+    use crate::client::auth::sasl::External;
+    // The last value for a capability should be used, even across LS lines.
+    let msgs = concat!(
+        ":example.com CAP * LS * :sasl=PLAIN message-tags\r\n",
+        ":example.com CAP * LS * :labeled-response\r\n",
+        ":example.com CAP * LS :sasl=PLAIN,EXTERNAL\r\n",
+        ":example.com CAP * ACK :sasl\r\n",
+        "AUTHENTICATE +\r\n",
+        ":example.com 903 Me :SASL authentication successful\r\n",
+        ":example.com 001 Me :Hi, we're glad to have you.\r\n",
+        ":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n",
+    );
+    let mut options: Options<Clear, External> = Options::new();
+    options.nicks = vec![Nick::from_str("Me")];
+    options.add_sasl(External::default());
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(Duration::ZERO, 1);
+    let (_, reg) = client.add(&register_as_bot(), &options).unwrap();
+    client.run().unwrap();
+    reg.0.recv_now().expect("Handler should send on channel after success").unwrap();
+    let caps = client.state().get::<Caps>().expect("Handler should set Caps on success");
+    let (_, value) = caps.get_union_raw(&Key::from_str("sasl")).expect("sasl should be listed");
+    assert_eq!(value.as_bytes(), b"PLAIN,EXTERNAL");
+    assert_eq!(caps.get_extra_raw(&Key::from_str("sasl")).copied(), Some(true));
+    assert_eq!(caps.len(), 3);
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    assert!(sent.contains("AUTHENTICATE EXTERNAL\r\n"), "{sent}");
+}
+
+#[cfg(all(feature = "crypto", feature = "base64"))]
+#[test]
+fn sasl_server_order() {
+    use crate::client::auth::{
+        sasl::{External, Password},
+        AnySasl, Secret,
+    };
+    let msgs = concat!(
+        ":example.com CAP * LS :sasl=EXTERNAL,SCRAM-SHA-256\r\n",
+        ":example.com CAP * ACK :sasl\r\n",
+        ":example.com 904 Me :SASL authentication failed\r\n",
+        ":example.com 904 Me :SASL authentication failed\r\n",
+        ":example.com 001 Me :Hi, we're glad to have you.\r\n",
+        ":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n",
+    );
+    let mut options: Options<Clear, AnySasl<Clear>> = Options::new();
+    options.nicks = vec![Nick::from_str("Me")];
+    options.allow_sasl_fail = true;
+    let passwd = Secret::new(crate::string::NoNul::from_str("hunter2"));
+    options.add_sasl(Password::new(crate::string::NoNul::from_str("Me"), passwd));
+    options.add_sasl(External::default());
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(Duration::ZERO, 1);
+    let (_, reg) = client.add(&register_as_bot(), &options).unwrap();
+    client.run().unwrap();
+    reg.0.recv_now().expect("Handler should send on channel after success").unwrap();
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    let external = sent.find("AUTHENTICATE EXTERNAL\r\n").expect(&sent);
+    let scram = sent.find("AUTHENTICATE SCRAM-SHA-256\r\n").expect(&sent);
+    assert!(external < scram, "{sent}");
+    assert!(!sent.contains("PLAIN"), "{sent}");
+    assert!(!sent.contains("SCRAM-SHA-512"), "{sent}");
+}
+
 #[test]
 fn bounce() {
     let testcases = [
@@ -97,3 +185,620 @@ fn bounce() {
         }
     }
 }
+
+#[test]
+fn broken_keeps_msg() {
+    let line = ":example.com 004 Me example.com v1 iw bnt";
+    let Err(e) = static_register(format!("{line}\r\n").as_bytes()) else {
+        panic!("connection registration somehow succeeded");
+    };
+    let HandlerError::Broken(_, Some(msg)) = &e else {
+        panic!("wrong error: {e}");
+    };
+    assert_eq!(msg.to_string(), line);
+    assert!(e.to_string().ends_with(line), "{e}");
+    let e = std::io::Error::from(e);
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    let e = e.get_ref().and_then(|e| e.downcast_ref::<HandlerError>());
+    assert!(matches!(e, Some(HandlerError::Broken(..))));
+}
+
+#[test]
+fn save_is_atomic() {
+    use crate::client::state::{Account, ClientSource};
+    // The source length should be calculated using the ISUPPORT tokens from registration,
+    // which were not yet present in the state if the source was saved first.
+    let state = static_register(
+        concat!(
+            ":example.com 001 Me :Hi, we're glad to have you.\r\n",
+            ":example.com 005 Me USERLEN=5 HOSTLEN=20 :are supported by this server\r\n",
+            ":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n",
+        )
+        .as_bytes(),
+    )
+    .expect("registration failed");
+    assert_eq!(state.source_len().get(), 2 + 5 + 20 + 2);
+    let generation = state.generation::<ClientSource>().expect("ClientSource should be set");
+    assert_eq!(state.generation::<ISupport>(), Some(generation));
+    assert_eq!(state.generation::<Caps>(), Some(generation));
+    assert_eq!(state.generation::<Account>(), Some(generation));
+}
+
+/// Test registration, returning the messages the handler sent.
+fn register_away(msg: &[u8], away: &'static str) -> (ClientState, String) {
+    let mut options: Options<Clear> = Options::new();
+    options.nicks = vec![Nick::from_str("Me")];
+    options.initial_away = Some(Line::from_str(away));
+    let reg = register_as_bot();
+    let io = Bidir(Cursor::new(msg.to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(Duration::ZERO, 1);
+    let (_, reg) = client.add(&reg, &options).unwrap();
+    client.run().unwrap();
+    reg.0.recv_now().expect("Handler should send on channel after success").unwrap();
+    client.run().unwrap();
+    let state = std::mem::take(client.state_mut());
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    (state, sent)
+}
+
+#[test]
+fn pre_away() {
+    use crate::client::state::SelfAway;
+    let (state, sent) = register_away(
+        concat!(
+            ":example.com CAP * LS :draft/pre-away\r\n",
+            ":example.com CAP * ACK :draft/pre-away\r\n",
+            ":example.com 306 * :You have been marked as being away\r\n",
+            ":example.com 001 Me :Hi, we're glad to have you.\r\n",
+            ":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n",
+        )
+        .as_bytes(),
+        "gone fishing",
+    );
+    let away = sent.find("AWAY :gone fishing\r\n").expect("AWAY should be sent");
+    let cap_end = sent.find("CAP END\r\n").expect("CAP END should be sent");
+    assert!(away < cap_end, "AWAY should be sent before CAP END:\n{sent}");
+    assert_eq!(sent.matches("AWAY").count(), 1);
+    let away = state.get::<SelfAway>().expect("Handler should set SelfAway on success");
+    assert_eq!(away.as_ref().map(|a| a.as_bytes()), Some(b"gone fishing".as_slice()));
+}
+
+#[test]
+fn away_fallback() {
+    use crate::client::state::SelfAway;
+    let (state, sent) = register_away(
+        concat!(
+            ":example.com CAP * LS :labeled-response\r\n",
+            ":example.com 001 Me :Hi, we're glad to have you.\r\n",
+            ":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n",
+            ":example.com 306 Me :You have been marked as being away\r\n",
+        )
+        .as_bytes(),
+        "gone fishing",
+    );
+    let cap_end = sent.find("CAP END\r\n").expect("CAP END should be sent");
+    let away = sent.find("AWAY :gone fishing\r\n").expect("AWAY should be sent");
+    assert!(cap_end < away, "AWAY should be sent after registration:\n{sent}");
+    assert!(!sent.contains("draft/pre-away"));
+    let away = state.get::<SelfAway>().expect("Handler should set SelfAway on success");
+    assert_eq!(away.as_ref().map(|a| a.as_bytes()), Some(b"gone fishing".as_slice()));
+}
+
+#[test]
+fn pre_away_star() {
+    use crate::client::state::SelfAway;
+    let (state, sent) = register_away(
+        concat!(
+            ":example.com CAP * LS :draft/pre-away\r\n",
+            ":example.com CAP * ACK :draft/pre-away\r\n",
+            ":example.com 306 * :You have been marked as being away\r\n",
+            ":example.com 001 Me :Hi, we're glad to have you.\r\n",
+            ":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n",
+        )
+        .as_bytes(),
+        "*",
+    );
+    assert!(sent.contains("AWAY *\r\n"), "AWAY should be sent:\n{sent}");
+    assert_eq!(state.get::<SelfAway>(), Some(&None));
+}
+
+#[test]
+fn pre_away_unconfirmed() {
+    use crate::client::state::SelfAway;
+    let (state, sent) = register_away(
+        concat!(
+            ":example.com CAP * LS :draft/pre-away\r\n",
+            ":example.com CAP * ACK :draft/pre-away\r\n",
+            ":example.com 001 Me :Hi, we're glad to have you.\r\n",
+            ":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n",
+        )
+        .as_bytes(),
+        "gone fishing",
+    );
+    assert!(sent.contains("AWAY :gone fishing\r\n"), "AWAY should be sent:\n{sent}");
+    assert_eq!(state.get::<SelfAway>(), Some(&None));
+}
+
+/// Test registration against a misbehaving server, using the provided limits.
+fn limited_register(msg: &[u8], limits: Limits, sasl: bool) -> Result<ClientState, HandlerError> {
+    use crate::client::auth::sasl::External;
+    let mut options: Options<Clear, External> = Options::new();
+    options.nicks = vec![Nick::from_str("Me")];
+    options.allow_sasl_fail = true;
+    if sasl {
+        options.add_sasl(External::default());
+    }
+    let reg = Register { limits, ..register_as_bot() };
+    let io = Bidir::<Cursor<Vec<u8>>, _>(Cursor::new(msg.to_vec()), std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(Duration::ZERO, 1);
+    let (_, reg) = client.add(&reg, &options).unwrap();
+    client.run().unwrap();
+    reg.0.recv_now().expect("Handler should send on channel after success")?;
+    Ok(std::mem::take(client.state_mut()))
+}
+
+fn repeat_then(line: &str, count: usize, end: &str) -> Vec<u8> {
+    let mut msgs = line.repeat(count);
+    msgs.push_str(end);
+    msgs.into_bytes()
+}
+
+const WELCOME: &str = concat!(
+    ":example.com 001 Me :Hi, we're glad to have you.\r\n",
+    ":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n",
+);
+
+#[test]
+fn limit_cap_ls() {
+    let limits = Limits { cap_ls: 4, ..Limits::new() };
+    let line = ":example.com CAP * LS * :labeled-response\r\n";
+    let end = format!(":example.com CAP * LS :message-tags\r\n{WELCOME}");
+    limited_register(&repeat_then(line, 3, &end), limits, false)
+        .expect("registration should succeed within the limit");
+    match limited_register(&repeat_then(line, 4, &end), limits, false) {
+        Err(HandlerError::Limit(Limit::CapLs)) => (),
+        Err(e) => panic!("wrong error: {e}"),
+        Ok(_) => panic!("registration should have exceeded the limit"),
+    }
+}
+
+#[cfg(feature = "base64")]
+#[test]
+fn limit_sasl_rounds() {
+    let limits = Limits { sasl_rounds: 3, ..Limits::new() };
+    let start =
+        ":example.com CAP * LS :sasl=EXTERNAL\r\n:example.com CAP * ACK :sasl\r\nAUTHENTICATE +\r\n";
+    let line = "AUTHENTICATE +\r\n";
+    let end = format!(":example.com 903 Me :SASL authentication successful\r\n{WELCOME}");
+    let ok = [start.as_bytes(), &repeat_then(line, 2, &end)].concat();
+    limited_register(&ok, limits, true).expect("registration should succeed within the limit");
+    let bad = [start.as_bytes(), &repeat_then(line, 3, &end)].concat();
+    match limited_register(&bad, limits, true) {
+        Err(HandlerError::Limit(Limit::SaslRounds)) => (),
+        Err(e) => panic!("wrong error: {e}"),
+        Ok(_) => panic!("registration should have exceeded the limit"),
+    }
+}
+
+#[cfg(feature = "base64")]
+#[test]
+fn limit_sasl_len() {
+    let limits = Limits { sasl_len: 3, ..Limits::new() };
+    let start = ":example.com CAP * LS :sasl=EXTERNAL\r\n:example.com CAP * ACK :sasl\r\n";
+    let end = format!(":example.com 903 Me :SASL authentication successful\r\n{WELCOME}");
+    let ok = format!("{start}AUTHENTICATE AAAA\r\n{end}");
+    limited_register(ok.as_bytes(), limits, true)
+        .expect("registration should succeed within the limit");
+    let bad = format!("{start}AUTHENTICATE AAAAAAAA\r\n{end}");
+    match limited_register(bad.as_bytes(), limits, true) {
+        Err(HandlerError::Limit(Limit::SaslLen)) => (),
+        Err(e) => panic!("wrong error: {e}"),
+        Ok(_) => panic!("registration should have exceeded the limit"),
+    }
+}
+
+#[test]
+fn limit_nicks() {
+    let limits = Limits { nicks: 4, ..Limits::new() };
+    let line = ":example.com 433 * Me :Nickname is already in use.\r\n";
+    limited_register(&repeat_then(line, 3, WELCOME), limits, false)
+        .expect("registration should succeed within the limit");
+    match limited_register(&repeat_then(line, 4, WELCOME), limits, false) {
+        Err(HandlerError::Limit(Limit::Nicks)) => (),
+        Err(e) => panic!("wrong error: {e}"),
+        Ok(_) => panic!("registration should have exceeded the limit"),
+    }
+}
+
+#[test]
+fn limit_msgs() {
+    let limits = Limits { msgs: 10, ..Limits::new() };
+    let line = ":example.com NOTICE * :Looking up your hostname...\r\n";
+    limited_register(&repeat_then(line, 8, WELCOME), limits, false)
+        .expect("registration should succeed within the limit");
+    match limited_register(&repeat_then(line, 9, WELCOME), limits, false) {
+        Err(HandlerError::Limit(Limit::Msgs)) => (),
+        Err(e) => panic!("wrong error: {e}"),
+        Ok(_) => panic!("registration should have exceeded the limit"),
+    }
+}
+
+/// Runs `client` until the handler with the provided id finishes.
+///
+/// The handler left behind by suspended registration finishes once registration is resumed,
+/// which may cause [`Client::run`] to return early.
+fn run_until_finished<C: crate::client::conn::Connection, S>(client: &mut Client<C, S>, id: usize) {
+    while !client.run().unwrap().is_some_and(|(_, finished)| finished.contains(&id)) {}
+}
+
+#[test]
+fn suspend_no_nicks() {
+    use super::{Resume, Suspension};
+    use crate::client::state::ClientSource;
+    let mut options: Options<Clear> = Options::new();
+    options.nicks = vec![Nick::from_str("Me")];
+    let reg = Register {
+        nicks: |opts: &Options<Clear>| {
+            Box::new(crate::client::nick::from_iter(opts.nicks.clone()).unwrap())
+        },
+        suspend: true,
+        ..register_as_bot()
+    };
+    let msgs = concat!(
+        ":example.com 433 * Me :Nickname is already in use.\r\n",
+        ":example.com 433 * Alt1 :Nickname is already in use.\r\n",
+        ":example.com 001 Alt2 :Hi, we're glad to have you.\r\n",
+        ":example.com 422 Alt2 :Nobody reads MOTDs anyway these days.\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(Duration::ZERO, 1);
+    let (_, result) = client.add(&reg, &options).unwrap();
+    client.run().unwrap();
+    let Some(Err(HandlerError::Suspended(Suspension::NoNicks, token))) = result.0.recv_now() else {
+        panic!("registration should have been suspended");
+    };
+    // The wrong kind of resumption should hand the suspension back.
+    let Err(HandlerError::Suspended(Suspension::NoNicks, token)) =
+        client.add(token, Resume::WithoutCaps)
+    else {
+        panic!("mismatched resume should return the same suspension");
+    };
+    let nicks = vec![Nick::from_str("Alt1"), Nick::from_str("Alt2")];
+    let (id, result) = client.add(token, Resume::Nicks(nicks)).unwrap();
+    run_until_finished(&mut client, id);
+    result.0.recv_now().expect("Handler should send on channel after success").unwrap();
+    let nick = &client.state().get::<ClientSource>().unwrap().nick;
+    assert_eq!(nick.as_bytes(), b"Alt2");
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    let alt1 = sent.find("NICK Alt1\r\n").expect("Alt1 should be attempted");
+    let alt2 = sent.find("NICK Alt2\r\n").expect("Alt2 should be attempted");
+    assert!(alt1 < alt2);
+}
+
+/// Reader that times out at each `None`, as if the server had nothing to send yet.
+struct Pausing(std::collections::VecDeque<Option<&'static str>>);
+
+impl std::io::Read for Pausing {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.pop_front() {
+            None => Ok(0),
+            Some(None) => Err(std::io::ErrorKind::TimedOut.into()),
+            Some(Some(chunk)) => {
+                buf[..chunk.len()].copy_from_slice(chunk.as_bytes());
+                Ok(chunk.len())
+            }
+        }
+    }
+}
+
+impl crate::client::conn::ReadTimeout for Pausing {
+    fn set_read_timeout(&mut self, _: Option<Duration>) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn suspend_buffers_msgs() {
+    use super::{Resume, Suspension};
+    use crate::client::state::{Account, ClientSource};
+    let mut options: Options<Clear> = Options::new();
+    options.nicks = vec![Nick::from_str("Me")];
+    let reg = Register {
+        nicks: |opts: &Options<Clear>| {
+            Box::new(crate::client::nick::from_iter(opts.nicks.clone()).unwrap())
+        },
+        suspend: true,
+        ..register_as_bot()
+    };
+    let script = [
+        Some(":example.com 433 * Me :Nickname is already in use.\r\n"),
+        // Received while suspended.
+        Some(":example.com PING :keepalive\r\n"),
+        Some(":example.com 900 * Me!me@example.com acct :You are now logged in as acct\r\n"),
+        None,
+        Some(":example.com 001 Alt :Hi, we're glad to have you.\r\n"),
+        Some(":example.com 422 Alt :Nobody reads MOTDs anyway these days.\r\n"),
+    ];
+    let io = Bidir(std::io::BufReader::new(Pausing(script.into())), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(Duration::ZERO, 1);
+    let (_, result) = client.add(&reg, &options).unwrap();
+    client.run().unwrap();
+    let Some(Err(HandlerError::Suspended(Suspension::NoNicks, token))) = result.0.recv_now() else {
+        panic!("registration should have been suspended");
+    };
+    assert!(client.run().unwrap().is_none(), "nothing should finish while suspended");
+    let (id, result) = client.add(token, Resume::Nicks(vec![Nick::from_str("Alt")])).unwrap();
+    run_until_finished(&mut client, id);
+    result.0.recv_now().expect("Handler should send on channel after success").unwrap();
+    assert_eq!(client.state().get::<ClientSource>().unwrap().nick, "Alt");
+    let account = client.state().get::<Account>().unwrap();
+    assert_eq!(account.as_ref().map(|a| a.as_bytes()), Some(b"acct".as_slice()));
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    assert_eq!(sent.matches("PONG").count(), 1, "PING should be answered once:\n{sent}");
+    let pong = sent.find("PONG keepalive\r\n").unwrap();
+    let nick = sent.find("NICK Alt\r\n").expect("Alt should be attempted");
+    assert!(pong < nick, "PING should be answered while suspended:\n{sent}");
+}
+
+#[cfg(feature = "base64")]
+#[test]
+fn suspend_sasl() {
+    use super::{Resume, Suspension};
+    use crate::client::{auth::sasl::External, state::Account};
+    let mut options: Options<Clear, External> = Options::new();
+    options.nicks = vec![Nick::from_str("Me")];
+    options.add_sasl(External::default());
+    let reg = Register { suspend_sasl: true, ..register_as_bot() };
+    let script = [
+        Some(":example.com CAP * LS :sasl=EXTERNAL\r\n"),
+        Some(":example.com CAP * ACK :sasl\r\n"),
+        // Received while suspended.
+        Some(":example.com PING :keepalive\r\n"),
+        None,
+        Some("AUTHENTICATE +\r\n"),
+        Some(":example.com 900 * Me!me@example.com acct :You are now logged in as acct\r\n"),
+        Some(":example.com 903 * :SASL authentication successful\r\n"),
+        Some(":example.com 001 Me :Hi, we're glad to have you.\r\n"),
+        Some(":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n"),
+    ];
+    let io = Bidir(std::io::BufReader::new(Pausing(script.into())), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(Duration::ZERO, 1);
+    let (_, result) = client.add(&reg, &options).unwrap();
+    client.run().unwrap();
+    let Some(Err(HandlerError::Suspended(Suspension::Sasl(mech), token))) = result.0.recv_now()
+    else {
+        panic!("registration should have been suspended");
+    };
+    assert_eq!(mech, "EXTERNAL");
+    assert!(client.run().unwrap().is_none(), "nothing should finish while suspended");
+    let (id, result) = client.add(token, Resume::Continue).unwrap();
+    run_until_finished(&mut client, id);
+    result.0.recv_now().expect("Handler should send on channel after success").unwrap();
+    let account = client.state().get::<Account>().unwrap();
+    assert_eq!(account.as_ref().map(|a| a.as_bytes()), Some(b"acct".as_slice()));
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    let pong = sent.find("PONG keepalive\r\n").expect("PING should be answered");
+    let auth = sent.find("AUTHENTICATE EXTERNAL\r\n").expect("SASL should start");
+    let cap_end = sent.find("CAP END\r\n").expect("CAP END should be sent");
+    assert!(pong < auth, "SASL should not start while suspended:\n{sent}");
+    assert!(auth < cap_end);
+}
+
+thread_local! {
+    /// The current time on this thread's virtual clock, if it is being used.
+    static CLOCK: std::cell::Cell<Option<std::time::Instant>> = const { std::cell::Cell::new(None) };
+}
+
+/// Returns the current time on this thread's virtual clock,
+/// or the real time if the virtual clock is not in use.
+pub(super) fn virtual_now() -> std::time::Instant {
+    CLOCK.with(std::cell::Cell::get).unwrap_or_else(std::time::Instant::now)
+}
+
+/// Reader that advances the virtual clock before returning each of its chunks.
+struct Scripted(std::collections::VecDeque<(Duration, &'static str)>);
+
+impl std::io::Read for Scripted {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let Some((delay, chunk)) = self.0.pop_front() else {
+            return Ok(0);
+        };
+        CLOCK.with(|clock| clock.set(Some(virtual_now() + delay)));
+        buf[..chunk.len()].copy_from_slice(chunk.as_bytes());
+        Ok(chunk.len())
+    }
+}
+
+impl crate::client::conn::ReadTimeout for Scripted {
+    fn set_read_timeout(&mut self, _: Option<Duration>) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn timings() {
+    use crate::client::{
+        auth::sasl::External,
+        conn::ConnectTimings,
+        state::{ConnectTimes, RegTimings},
+    };
+    const TICK: Duration = Duration::from_millis(20);
+    CLOCK.with(|clock| clock.set(Some(std::time::Instant::now())));
+    let script = [
+        (TICK, ":example.com CAP * LS :sasl=EXTERNAL\r\n"),
+        (TICK, ":example.com CAP * ACK :sasl\r\n"),
+        (TICK, "AUTHENTICATE +\r\n"),
+        (TICK, ":example.com 903 Me :SASL authentication successful\r\n"),
+        (TICK * 2, ":example.com 001 Me :Hi, we're glad to have you.\r\n"),
+        (Duration::ZERO, ":example.com 005 Me NETWORK=example.com :are supported\r\n"),
+        (TICK * 3, ":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n"),
+    ];
+    let connect = ConnectTimings { resolve: Some(TICK), tcp: TICK * 2, tls: Some(TICK * 3) };
+    let mut options: Options<Clear, External> = Options::new();
+    options.nicks = vec![Nick::from_str("Me")];
+    options.add_sasl(External::default());
+    let reader = std::io::BufReader::new(Scripted(script.into_iter().collect()));
+    let io = Bidir(reader, std::io::sink());
+    let mut client = Client::new(io, SyncChannels);
+    client.state_mut().insert::<ConnectTimes>(connect);
+    client.queue_mut().set_rate_limit(Duration::ZERO, 1);
+    let (_, reg) = client.add(&register_as_bot(), &options).unwrap();
+    client.run().unwrap();
+    reg.0.recv_now().unwrap().unwrap();
+    let timings = *client.state().get::<RegTimings>().expect("timings should be saved");
+    let within_tick = |duration: Option<Duration>, ticks: u32| {
+        let duration = duration.expect("phase should be measured");
+        let expected = TICK * ticks;
+        let error = duration.max(expected) - duration.min(expected);
+        assert!(error <= TICK, "{duration:?} is not {ticks} ticks in {timings}");
+    };
+    within_tick(timings.resolve, 1);
+    within_tick(timings.tcp, 2);
+    within_tick(timings.tls, 3);
+    within_tick(timings.cap_ls, 1);
+    within_tick(timings.cap_negotiation, 1);
+    within_tick(timings.sasl, 2);
+    within_tick(timings.welcome_wait, 2);
+    within_tick(timings.isupport_to_end, 3);
+    within_tick(Some(timings.total), 9);
+}
+
+#[test]
+fn timings_without_caps() {
+    let state = static_register(WELCOME.as_bytes()).unwrap();
+    let timings = state.get::<crate::client::state::RegTimings>().unwrap();
+    assert_eq!(timings.tcp, None);
+    assert_eq!(timings.cap_ls, None);
+    assert_eq!(timings.sasl, None);
+    assert_eq!(timings.isupport_to_end, None);
+    assert!(timings.welcome_wait.is_some());
+}
+
+/// Registers with a server that has an STS policy.
+///
+/// `tls` is used for [`Register::tls`],
+/// and `conn_tls` for whether the saved connection timings include a TLS handshake.
+fn sts_register(
+    tls: Option<bool>,
+    conn_tls: Option<bool>,
+) -> (Result<(), HandlerError>, ClientState) {
+    use crate::client::{conn::ConnectTimings, state::ConnectTimes};
+    let mut options: Options<Clear> = Options::new();
+    options.nicks = vec![Nick::from_str("Me")];
+    let reg = Register { tls, ..register_as_bot() };
+    let msgs = concat!(
+        ":example.com CAP * LS :sts=port=6697,duration=2592000,preload,foo=bar\r\n",
+        ":example.com 001 Me :Hi, we're glad to have you.\r\n",
+        ":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n",
+    );
+    let io = Bidir(Cursor::new(msgs.as_bytes().to_vec()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    if let Some(conn_tls) = conn_tls {
+        let tls = conn_tls.then_some(Duration::ZERO);
+        let connect = ConnectTimings { tls, ..Default::default() };
+        client.state_mut().insert::<ConnectTimes>(connect);
+    }
+    client.queue_mut().set_rate_limit(Duration::ZERO, 1);
+    let (_, result) = client.add(&reg, &options).unwrap();
+    client.run().unwrap();
+    let result = result.0.recv_now().expect("Handler should send on channel");
+    (result, std::mem::take(client.state_mut()))
+}
+
+#[test]
+fn sts_secure() {
+    use crate::{client::state::Sts, state::StsPolicy};
+    let (result, state) = sts_register(Some(true), None);
+    result.expect("registration over TLS should succeed");
+    let expected =
+        StsPolicy { duration: Some(Duration::from_secs(2592000)), port: Some(6697), preload: true };
+    assert_eq!(state.get::<Sts>(), Some(&expected));
+}
+
+#[test]
+fn sts_insecure() {
+    let (result, state) = sts_register(Some(false), None);
+    match result {
+        Err(HandlerError::StsUpgrade(6697)) => (),
+        Err(e) => panic!("wrong error: {e}"),
+        Ok(()) => panic!("registration over plaintext should require an upgrade"),
+    }
+    assert!(state.get::<Caps>().is_none());
+}
+
+#[test]
+fn sts_tls_from_connection() {
+    let (result, _) = sts_register(None, Some(false));
+    assert!(matches!(result, Err(HandlerError::StsUpgrade(6697))));
+    sts_register(None, Some(true)).0.expect("registration over TLS should succeed");
+    sts_register(None, None).0.expect("registration with unknown TLS should succeed");
+    // The connection's TLS state is only a default.
+    sts_register(Some(true), Some(false)).0.expect("Register::tls should take precedence");
+}
+
+#[test]
+fn utf8_only() {
+    use crate::client::queue::Utf8Only;
+    let msgs = concat!(
+        ":example.com 001 Me :Hi, we're glad to have you.\r\n",
+        ":example.com 005 Me UTF8ONLY :are supported by this server\r\n",
+        ":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n",
+    );
+    let mut options: Options<Clear> = Options::new();
+    options.nicks = vec![Nick::from_str("Me")];
+    for policy in [Utf8Only::Allow, Utf8Only::Lossy] {
+        let reg = Register { utf8_only: policy, ..register_as_bot() };
+        let io =
+            Bidir::<Cursor<Vec<u8>>, _>(Cursor::new(msgs.as_bytes().to_vec()), std::io::sink());
+        let mut client = Client::new(io, SyncChannels);
+        client.queue_mut().set_rate_limit(Duration::ZERO, 1);
+        let (_, reg) = client.add(&reg, &options).unwrap();
+        client.run().unwrap();
+        reg.0.recv_now().unwrap().unwrap();
+        assert_eq!(client.queue().utf8_only(), policy);
+    }
+}
+
+#[test]
+fn cap_req_multiline() {
+    let caps: Vec<String> = (0..40).map(|i| format!("vendor.example/cap-{i:02}")).collect();
+    let mut msgs = String::new();
+    for chunk in caps.chunks(10) {
+        msgs.push_str(&format!(":example.com CAP * LS * :{}\r\n", chunk.join(" ")));
+    }
+    msgs.push_str(":example.com CAP * LS :\r\n");
+    for chunk in caps.chunks(20) {
+        msgs.push_str(&format!(":example.com CAP Me ACK :{}\r\n", chunk.join(" ")));
+    }
+    msgs.push_str(":example.com 001 Me :Hi, we're glad to have you.\r\n");
+    msgs.push_str(":example.com 422 Me :Nobody reads MOTDs anyway these days.\r\n");
+    let mut options: Options<Clear> = Options::new();
+    options.nicks = vec![Nick::from_str("Me")];
+    let keys: Vec<_> = caps.iter().map(|cap| Key::from_bytes(cap.clone()).unwrap()).collect();
+    options.caps = keys.iter().cloned().collect();
+    let io = Bidir(Cursor::new(msgs.into_bytes()), Vec::new());
+    let mut client = Client::new(io, SyncChannels);
+    client.queue_mut().set_rate_limit(Duration::ZERO, 1);
+    let (_, reg) = client.add(&register_as_bot(), &options).unwrap();
+    client.run().unwrap();
+    reg.0.recv_now().expect("Handler should send on channel after success").unwrap();
+    let state = client.state().get::<Caps>().expect("Handler should set Caps on success");
+    for key in &keys {
+        assert_eq!(state.get_extra_raw(key).copied(), Some(true), "{key}");
+    }
+    let sent = String::from_utf8(client.take_conn().1).unwrap();
+    let reqs: Vec<_> = sent.lines().filter(|line| line.starts_with("CAP REQ")).collect();
+    assert!(reqs.len() >= 2, "{sent}");
+    // The server's reply adds its source and our nick, and must still fit in 510 bytes.
+    let reply_extra = ":example.com  Me".len();
+    assert!(reqs.iter().all(|line| line.len() + reply_extra <= 510), "{sent}");
+    let requested = reqs.iter().flat_map(|line| line[9..].split(' ')).count();
+    assert_eq!(requested, caps.len());
+    let req_end = sent.rfind("CAP REQ").unwrap();
+    assert!(sent[req_end..].contains("CAP END\r\n"), "{sent}");
+}
@@ -3,7 +3,8 @@
 use crate::{
     ircmsg::Source,
     names::{Cap, NameMap},
-    string::Arg,
+    state::ModeSet,
+    string::{Arg, Line},
 };
 use std::any::Any;
 
@@ -31,3 +32,13 @@ csk!(Caps: NameMap<Cap, bool> = "Map of the server's capabilities to whether the
 csk!(ISupport: NameMap<crate::names::ISupport> = "The server's ISUPPORT tokens.");
 csk!(ServerVersion: Arg<'static> = "The client's source.");
 csk!(Account: Option<Arg<'static>> = "The client's source.");
+csk!(UserModes: ModeSet = "The user modes the server advertised in `RPL_MYINFO`.");
+csk!(ChanModes: ModeSet = "The channel modes the server advertised in `RPL_MYINFO`.");
+csk!(SelfModes: ModeSet = "The client's current user modes.");
+csk!(Realname: Option<Line<'static>> = "The client's realname, if known.");
+csk!(SelfAway: Option<Line<'static>> = "The client's away message, if it is marked as away.");
+csk!(Shutdown: Option<Line<'static>> = "The reason the client is intentionally disconnecting.");
+csk!(Sts: crate::state::StsPolicy = "The STS policy the server advertised during registration.");
+csk!(RegTimings: super::register::RegistrationTimings = "How long connection registration took.");
+csk!(ConnectTimes: super::conn::ConnectTimings = "How long it took to establish the connection.");
+csk!(Sources: crate::ircmsg::SourceCache = "A cache for sharing identical message sources.");
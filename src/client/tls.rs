@@ -1,5 +1,11 @@
 //! Helpers for creating TLS connections.
 
+mod cert;
+#[cfg(all(test, feature = "crypto"))]
+mod tests;
+
+pub use cert::*;
+
 use rustls::{
     pki_types::{CertificateDer, PrivateKeyDer},
     ClientConfig, RootCertStore,
@@ -23,7 +29,12 @@ pub enum Trust {
     Also(Vec<PathBuf>),
     /// Disables server identity verification.
     ///
-    /// This is usually a bad idea, but may be necessary to connect to some servers.
+    /// This is usually a bad idea, but may be necessary to connect to some servers,
+    /// such as test networks with self-signed certificates.
+    ///
+    /// # Security
+    /// Connections made with this option are vulnerable to interception.
+    /// Anything sent over them, including passwords, may be read by a third party.
     NoVerify,
 }
 
@@ -73,7 +84,7 @@ impl rustls::client::danger::ServerCertVerifier for NoVerifier {
         cert: &CertificateDer<'_>,
         dss: &rustls::DigitallySignedStruct,
     ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        rustls::crypto::verify_tls12_signature(
+        rustls::crypto::verify_tls13_signature(
             message,
             cert,
             dss,
@@ -95,60 +106,178 @@ pub type TlsConfig = Arc<ClientConfig>;
 pub struct TlsConfigOptions {
     /// Options for validating the server's identity.
     pub trust: Trust,
-    /// An optional path to a PEM-encoded file containing
-    /// one `PKCS#8` private key and client certificate chain.
+    /// An optional path to a PEM-encoded file containing a client certificate chain,
+    /// and its private key unless [`key`][TlsConfigOptions::key] is set.
     ///
-    /// Used for networks that support CertFP.
+    /// Used for networks that support CertFP or SASL EXTERNAL.
     pub cert: Option<PathBuf>,
+    /// An optional path to a PEM-encoded file containing the private key
+    /// for [`cert`][TlsConfigOptions::cert], if it is not in the same file.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub key: Option<PathBuf>,
+}
+
+/// Error for when a [`TlsConfig`] or [`ClientCert`] could not be created from files.
+///
+/// This is returned wrapped in an [`std::io::Error`] with the kind of the underlying I/O error
+/// for [`Read`][TlsConfigError::Read], or [`InvalidData`][std::io::ErrorKind::InvalidData]
+/// otherwise.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TlsConfigError {
+    /// A file could not be opened or read, such as because it does not exist.
+    Read(PathBuf, std::io::Error),
+    /// A file is not valid PEM, or it is missing something it was expected to contain.
+    BadPem(PathBuf, &'static str),
+    /// A certificate in a file could not be used.
+    BadCert(PathBuf, rustls::Error),
+    /// The client certificate and private key could not be used together,
+    /// usually because the key does not match the certificate.
+    KeyMismatch(rustls::Error),
 }
 
-fn load_pem(path: &Path, certs: &mut RootCertStore) -> std::io::Result<()> {
-    let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
-    for cert in rustls_pemfile::certs(&mut file) {
-        let cert = cert?;
-        certs.add(cert).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+impl TlsConfigError {
+    /// Returns the `TlsConfigError` wrapped in `error`, if any.
+    pub fn from_io(error: &std::io::Error) -> Option<&Self> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl std::fmt::Display for TlsConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsConfigError::Read(path, e) => write!(f, "cannot read {}: {e}", path.display()),
+            TlsConfigError::BadPem(path, what) => {
+                write!(f, "invalid PEM file {}: {what}", path.display())
+            }
+            TlsConfigError::BadCert(path, e) => {
+                write!(f, "unusable certificate in {}: {e}", path.display())
+            }
+            TlsConfigError::KeyMismatch(e) => {
+                write!(f, "client certificate cannot be used with its key: {e}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TlsConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TlsConfigError::Read(_, e) => Some(e),
+            TlsConfigError::BadCert(_, e) | TlsConfigError::KeyMismatch(e) => Some(e),
+            TlsConfigError::BadPem(..) => None,
+        }
+    }
+}
+
+impl From<TlsConfigError> for std::io::Error {
+    fn from(value: TlsConfigError) -> Self {
+        let kind = match &value {
+            TlsConfigError::Read(_, e) => e.kind(),
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, value)
+    }
+}
+
+/// Calls `f` with every item in the PEM file at `path`.
+fn read_pem(path: &Path, mut f: impl FnMut(rustls_pemfile::Item)) -> Result<(), TlsConfigError> {
+    let file = std::fs::File::open(path).map_err(|e| TlsConfigError::Read(path.into(), e))?;
+    let mut file = std::io::BufReader::new(file);
+    loop {
+        match rustls_pemfile::read_one(&mut file) {
+            Ok(Some(item)) => f(item),
+            Ok(None) => return Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                return Err(TlsConfigError::BadPem(path.into(), "malformed PEM section"));
+            }
+            Err(e) => return Err(TlsConfigError::Read(path.into(), e)),
+        }
+    }
+}
+
+fn load_pem(path: &Path, certs: &mut RootCertStore) -> Result<(), TlsConfigError> {
+    let mut found = Vec::new();
+    read_pem(path, |item| {
+        if let rustls_pemfile::Item::X509Certificate(cert) = item {
+            found.push(cert);
+        }
+    })?;
+    if found.is_empty() {
+        return Err(TlsConfigError::BadPem(path.into(), "no certificates"));
+    }
+    for cert in found {
+        certs.add(cert).map_err(|e| TlsConfigError::BadCert(path.into(), e))?;
     }
     Ok(())
 }
 
+/// Loads a client certificate chain from `path`,
+/// and its private key from `key_path` if provided or `path` otherwise.
 fn load_client_cert(
     path: &Path,
-) -> std::io::Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
-    let mut key = Option::<PrivateKeyDer>::None;
-    let mut certs = Vec::<CertificateDer>::new();
-    let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
-    while let Some(item) = rustls_pemfile::read_one(&mut file)? {
+    key_path: Option<&Path>,
+) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), TlsConfigError> {
+    fn read_key(item: rustls_pemfile::Item, key: &mut Option<PrivateKeyDer<'static>>) {
         match item {
-            rustls_pemfile::Item::X509Certificate(c) => certs.push(c),
-            rustls_pemfile::Item::Pkcs8Key(k) => {
-                key = Some(PrivateKeyDer::from(k));
-            }
+            rustls_pemfile::Item::Pkcs1Key(k) => *key = Some(k.into()),
+            rustls_pemfile::Item::Pkcs8Key(k) => *key = Some(k.into()),
+            rustls_pemfile::Item::Sec1Key(k) => *key = Some(k.into()),
             _ => (),
         }
     }
-    key.map(|k| (certs, k))
-        .ok_or(std::io::Error::new(std::io::ErrorKind::InvalidData, "missing PKCS#8 private key"))
+    let mut key = Option::<PrivateKeyDer>::None;
+    let mut certs = Vec::<CertificateDer>::new();
+    read_pem(path, |item| match item {
+        rustls_pemfile::Item::X509Certificate(c) => certs.push(c),
+        item if key_path.is_none() => read_key(item, &mut key),
+        _ => (),
+    })?;
+    if certs.is_empty() {
+        return Err(TlsConfigError::BadPem(path.into(), "no certificates"));
+    }
+    if let Some(key_path) = key_path {
+        read_pem(key_path, |item| read_key(item, &mut key))?;
+    }
+    let key = key
+        .ok_or_else(|| TlsConfigError::BadPem(key_path.unwrap_or(path).into(), "no private key"))?;
+    Ok((certs, key))
 }
 
 impl TlsConfigOptions {
     /// Builds a [`TlsConfig`] from `self`.
     ///
     /// This is an expensive operation. It should ideally be done only once per network.
+    /// Errors relating to the files named in `self` are [`TlsConfigError`]s.
     pub fn build(&self) -> std::io::Result<TlsConfig> {
-        let cli_auth =
-            if let Some(path) = self.cert.as_ref() { Some(load_client_cert(path)?) } else { None };
+        let cli_auth = if let Some(path) = self.cert.as_ref() {
+            ClientAuth::Fixed(load_client_cert(path, self.key.as_deref())?)
+        } else {
+            ClientAuth::None
+        };
+        self.build_impl(cli_auth)
+    }
+
+    /// Builds a [`TlsConfig`] from `self` that uses client certificates from `source`,
+    /// ignoring [`cert`][TlsConfigOptions::cert] and [`key`][TlsConfigOptions::key].
+    ///
+    /// Unlike with [`build`][TlsConfigOptions::build], the client certificate
+    /// can change without the config needing to be rebuilt.
+    /// See [`ClientCertSource`] for more information.
+    ///
+    /// Session resumption is disabled, as resumed sessions would continue to use
+    /// whatever certificate was used for the session being resumed.
+    pub fn build_with_cert_source(
+        &self,
+        source: Arc<dyn ClientCertSource>,
+    ) -> std::io::Result<TlsConfig> {
+        self.build_impl(ClientAuth::Source(source))
+    }
+
+    fn build_impl(&self, cli_auth: ClientAuth) -> std::io::Result<TlsConfig> {
         let builder = ClientConfig::builder();
-        let config = if matches!(&self.trust, Trust::NoVerify) {
-            let builder = builder
-                .dangerous()
-                .with_custom_certificate_verifier(Arc::new(NoVerifier::default()));
-            if let Some((certs, key)) = cli_auth {
-                builder
-                    .with_client_auth_cert(certs, key)
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
-            } else {
-                builder.with_no_client_auth()
-            }
+        let builder = if matches!(&self.trust, Trust::NoVerify) {
+            builder.dangerous().with_custom_certificate_verifier(Arc::new(NoVerifier::default()))
         } else {
             let mut certs = RootCertStore { roots: Vec::new() };
             if matches!(&self.trust, Trust::Default | Trust::Also(_)) {
@@ -161,15 +290,28 @@ impl TlsConfigOptions {
                     load_pem(path.as_ref(), &mut certs)?;
                 }
             }
-            let builder = builder.with_root_certificates(certs);
-            if let Some((certs, key)) = cli_auth {
-                builder
-                    .with_client_auth_cert(certs, key)
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
-            } else {
-                builder.with_no_client_auth()
+            builder.with_root_certificates(certs)
+        };
+        let config = match cli_auth {
+            ClientAuth::None => builder.with_no_client_auth(),
+            ClientAuth::Fixed((certs, key)) => {
+                builder.with_client_auth_cert(certs, key).map_err(TlsConfigError::KeyMismatch)?
+            }
+            ClientAuth::Source(source) => {
+                let provider = builder.crypto_provider().clone();
+                let resolver = Arc::new(SourceResolver::new(source, provider));
+                let mut config = builder.with_client_cert_resolver(resolver);
+                // Resumed sessions keep the client identity of the session they resume.
+                config.resumption = rustls::client::Resumption::disabled();
+                config
             }
         };
         Ok(Arc::new(config))
     }
 }
+
+enum ClientAuth {
+    None,
+    Fixed((Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)),
+    Source(Arc<dyn ClientCertSource>),
+}
@@ -0,0 +1,263 @@
+use super::load_client_cert;
+use rustls::{
+    client::ResolvesClientCert,
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, PrivateKeyDer},
+    sign::CertifiedKey,
+    SignatureScheme,
+};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+/// A client certificate chain and its private key.
+#[derive(Debug)]
+pub struct ClientCert {
+    /// The certificate chain, starting with the client's certificate.
+    pub chain: Vec<CertificateDer<'static>>,
+    /// The private key for the first certificate in the chain.
+    pub key: PrivateKeyDer<'static>,
+}
+
+impl Clone for ClientCert {
+    fn clone(&self) -> Self {
+        ClientCert { chain: self.chain.clone(), key: self.key.clone_key() }
+    }
+}
+
+impl ClientCert {
+    /// Loads a certificate chain and private key from a PEM-encoded file.
+    ///
+    /// Errors relating to the file's contents are [`TlsConfigError`][super::TlsConfigError]s.
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let (chain, key) = load_client_cert(path.as_ref(), None)?;
+        Ok(ClientCert { chain, key })
+    }
+
+    /// Returns when the client's certificate stops being valid.
+    ///
+    /// Returns `None` if the chain is empty or the certificate could not be parsed.
+    pub fn not_after(&self) -> Option<SystemTime> {
+        not_after(self.chain.first()?)
+    }
+
+    /// Returns how long until the client's certificate stops being valid,
+    /// or [`Duration::ZERO`] if it already has.
+    ///
+    /// Returns `None` under the same conditions as [`not_after`][ClientCert::not_after].
+    pub fn expires_in(&self) -> Option<Duration> {
+        let not_after = self.not_after()?;
+        Some(not_after.duration_since(SystemTime::now()).unwrap_or_default())
+    }
+}
+
+/// Sources of client certificates that may change over the lifetime of a [`TlsConfig`].
+///
+/// The source is consulted during every handshake that requests a client certificate,
+/// so a new certificate takes effect on the next connection
+/// without the [`TlsConfig`] needing to be rebuilt.
+///
+/// This is implemented for `Arc<ClientCert>` (which never changes), for [`CertFile`],
+/// and for closures.
+///
+/// [`TlsConfig`]: super::TlsConfig
+pub trait ClientCertSource: Send + Sync {
+    /// Returns the certificate to use for the next handshake, if any.
+    fn current(&self) -> Option<Arc<ClientCert>>;
+}
+
+impl ClientCertSource for Arc<ClientCert> {
+    fn current(&self) -> Option<Arc<ClientCert>> {
+        Some(self.clone())
+    }
+}
+
+impl<F: Fn() -> Option<Arc<ClientCert>> + Send + Sync> ClientCertSource for F {
+    fn current(&self) -> Option<Arc<ClientCert>> {
+        self()
+    }
+}
+
+/// [`ClientCertSource`] that loads a PEM-encoded file,
+/// reloading it whenever its modification time changes.
+///
+/// If reloading fails, the previously-loaded certificate continues to be used.
+#[derive(Debug)]
+pub struct CertFile {
+    path: PathBuf,
+    loaded: Mutex<Option<(SystemTime, Arc<ClientCert>)>>,
+}
+
+impl CertFile {
+    /// Creates a new source for the file at `path`.
+    ///
+    /// The file is not read until it is first needed.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        CertFile { path: path.into(), loaded: Mutex::new(None) }
+    }
+
+    /// Returns the path of the file this source reads from.
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Loads the file if it has changed since it was last loaded.
+    ///
+    /// Returns the current certificate on success.
+    pub fn reload(&self) -> std::io::Result<Arc<ClientCert>> {
+        let mtime = std::fs::metadata(&self.path)?.modified()?;
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((loaded_mtime, cert)) = loaded.as_ref() {
+            if *loaded_mtime == mtime {
+                return Ok(cert.clone());
+            }
+        }
+        let cert = Arc::new(ClientCert::load(&self.path)?);
+        *loaded = Some((mtime, cert.clone()));
+        Ok(cert)
+    }
+}
+
+impl ClientCertSource for CertFile {
+    fn current(&self) -> Option<Arc<ClientCert>> {
+        match self.reload() {
+            Ok(cert) => Some(cert),
+            Err(_e) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("failed to load client certificate from {:?}: {}", self.path, _e);
+                let loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+                loaded.as_ref().map(|(_, cert)| cert.clone())
+            }
+        }
+    }
+}
+
+/// `ResolvesClientCert` that asks a [`ClientCertSource`] for the certificate to use.
+pub(super) struct SourceResolver {
+    source: Arc<dyn ClientCertSource>,
+    provider: Arc<CryptoProvider>,
+    /// The most recently-used certificate and its signing key.
+    cached: Mutex<Option<(Arc<ClientCert>, Arc<CertifiedKey>)>>,
+}
+
+impl SourceResolver {
+    pub fn new(source: Arc<dyn ClientCertSource>, provider: Arc<CryptoProvider>) -> Self {
+        SourceResolver { source, provider, cached: Mutex::new(None) }
+    }
+}
+
+impl std::fmt::Debug for SourceResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SourceResolver").finish_non_exhaustive()
+    }
+}
+
+impl ResolvesClientCert for SourceResolver {
+    fn resolve(&self, _: &[&[u8]], sigschemes: &[SignatureScheme]) -> Option<Arc<CertifiedKey>> {
+        let cert = self.source.current()?;
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        let key = match cached.as_ref() {
+            Some((cached_cert, key)) if Arc::ptr_eq(cached_cert, &cert) => key.clone(),
+            _ => {
+                let signer = match self.provider.key_provider.load_private_key(cert.key.clone_key())
+                {
+                    Ok(signer) => signer,
+                    Err(_e) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!("unusable client certificate key: {}", _e);
+                        return None;
+                    }
+                };
+                let key = Arc::new(CertifiedKey::new(cert.chain.clone(), signer));
+                *cached = Some((cert, key.clone()));
+                key
+            }
+        };
+        key.key.choose_scheme(sigschemes)?;
+        Some(key)
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+/// Reads one DER element, returning its tag and contents.
+fn der_next<'a>(input: &mut &'a [u8]) -> Option<(u8, &'a [u8])> {
+    let &[tag, len, ref rest @ ..] = *input else {
+        return None;
+    };
+    let (len, rest) = if len & 0x80 == 0 {
+        (len as usize, rest)
+    } else {
+        let count = (len & 0x7F) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count].iter().fold(0usize, |len, b| len << 8 | *b as usize);
+        (len, &rest[count..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    *input = &rest[len..];
+    Some((tag, &rest[..len]))
+}
+
+/// Extracts the end of the validity period from a DER-encoded X.509 certificate.
+fn not_after(cert: &[u8]) -> Option<SystemTime> {
+    const SEQUENCE: u8 = 0x30;
+    let mut input = cert;
+    let (SEQUENCE, mut cert) = der_next(&mut input)? else {
+        return None;
+    };
+    let (SEQUENCE, mut tbs) = der_next(&mut cert)? else {
+        return None;
+    };
+    // The version is optional and explicitly tagged.
+    if der_next(&mut tbs)?.0 == 0xA0 {
+        der_next(&mut tbs)?; // serialNumber
+    }
+    der_next(&mut tbs)?; // signature
+    der_next(&mut tbs)?; // issuer
+    let (SEQUENCE, mut validity) = der_next(&mut tbs)? else {
+        return None;
+    };
+    der_next(&mut validity)?; // notBefore
+    let (tag, time) = der_next(&mut validity)?;
+    parse_time(tag, time)
+}
+
+/// Parses an ASN.1 UTCTime (`tag == 0x17`) or GeneralizedTime (`tag == 0x18`)
+/// in the forms required by RFC 5280.
+fn parse_time(tag: u8, time: &[u8]) -> Option<SystemTime> {
+    let time = std::str::from_utf8(time).ok()?;
+    let (year, rest) = match tag {
+        0x17 => {
+            let year: u64 = time.get(..2)?.parse().ok()?;
+            (if year >= 50 { 1900 + year } else { 2000 + year }, &time[2..])
+        }
+        0x18 => (time.get(..4)?.parse().ok()?, &time[4..]),
+        _ => return None,
+    };
+    let rest = rest.strip_suffix('Z')?;
+    if rest.len() != 10 || !rest.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |idx: usize| rest[idx..idx + 2].parse::<u64>().unwrap_or_default();
+    let (month, day) = (field(0), field(2));
+    let (hour, min, sec) = (field(4), field(6), field(8));
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // Days since the epoch for a date in the proleptic Gregorian calendar.
+    let year = if month <= 2 { year - 1 } else { year };
+    let (era, year_of_era) = (year / 400, year % 400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146097 + day_of_era).checked_sub(719468)?;
+    let secs = days * 86400 + hour * 3600 + min * 60 + sec;
+    SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
@@ -0,0 +1,191 @@
+use super::{
+    CertFile, ClientCert, ClientCertSource, TlsConfig, TlsConfigError, TlsConfigOptions, Trust,
+};
+use rustls::{
+    pki_types::{CertificateDer, ServerName},
+    server::WebPkiClientVerifier,
+    ClientConnection, Connection, RootCertStore, ServerConfig, ServerConnection,
+};
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+fn testdata(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "src", "client", "tls", "testdata", name].iter().collect()
+}
+
+fn client_options() -> TlsConfigOptions {
+    TlsConfigOptions { trust: Trust::Only(vec![testdata("ca.pem")]), cert: None, key: None }
+}
+
+fn server_config() -> Arc<ServerConfig> {
+    let mut roots = RootCertStore::empty();
+    let ca = std::fs::read(testdata("ca.pem")).unwrap();
+    for cert in rustls_pemfile::certs(&mut ca.as_slice()) {
+        roots.add(cert.unwrap()).unwrap();
+    }
+    let verifier =
+        WebPkiClientVerifier::builder(Arc::new(roots)).allow_unauthenticated().build().unwrap();
+    let ClientCert { chain, key } = ClientCert::load(testdata("server.pem")).unwrap();
+    let config = ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(chain, key)
+        .unwrap();
+    Arc::new(config)
+}
+
+fn transfer(from: &mut Connection, to: &mut Connection) {
+    let mut buf = Vec::new();
+    while from.wants_write() {
+        from.write_tls(&mut buf).unwrap();
+    }
+    let mut buf = buf.as_slice();
+    while !buf.is_empty() {
+        to.read_tls(&mut buf).unwrap();
+        to.process_new_packets().unwrap();
+    }
+}
+
+/// Performs a handshake in memory, returning the certificates the server received.
+fn handshake(client: &TlsConfig, server: &Arc<ServerConfig>) -> Vec<CertificateDer<'static>> {
+    let name = ServerName::try_from("localhost").unwrap();
+    let mut client = Connection::from(ClientConnection::new(client.clone(), name).unwrap());
+    let mut server = Connection::from(ServerConnection::new(server.clone()).unwrap());
+    for _ in 0..16 {
+        if !client.is_handshaking() && !server.is_handshaking() {
+            break;
+        }
+        transfer(&mut client, &mut server);
+        transfer(&mut server, &mut client);
+    }
+    assert!(!client.is_handshaking() && !server.is_handshaking(), "handshake did not finish");
+    server.peer_certificates().map(<[_]>::to_vec).unwrap_or_default()
+}
+
+#[test]
+fn not_after() {
+    let utc_time = ClientCert::load(testdata("client2.pem")).unwrap();
+    let expected = SystemTime::UNIX_EPOCH + Duration::from_secs(2483342727);
+    assert_eq!(utc_time.not_after(), Some(expected));
+    let generalized_time = ClientCert::load(testdata("client1.pem")).unwrap();
+    let expected = SystemTime::UNIX_EPOCH + Duration::from_secs(4945742720);
+    assert_eq!(generalized_time.not_after(), Some(expected));
+    assert!(generalized_time.expires_in().unwrap() > utc_time.expires_in().unwrap());
+    let empty = ClientCert { chain: Vec::new(), key: generalized_time.key.clone_key() };
+    assert_eq!(empty.not_after(), None);
+}
+
+#[test]
+fn fixed_cert() {
+    let server = server_config();
+    let mut options = client_options();
+    assert!(handshake(&options.build().unwrap(), &server).is_empty());
+    options.cert = Some(testdata("client1.pem"));
+    let expected = ClientCert::load(testdata("client1.pem")).unwrap().chain;
+    assert_eq!(handshake(&options.build().unwrap(), &server)[..1], expected[..1]);
+}
+
+#[test]
+fn separate_key() {
+    let server = server_config();
+    let mut options = client_options();
+    options.cert = Some(testdata("client1.crt"));
+    options.key = Some(testdata("client1.key"));
+    let expected = ClientCert::load(testdata("client1.pem")).unwrap().chain;
+    assert_eq!(handshake(&options.build().unwrap(), &server)[..1], expected[..1]);
+}
+
+#[test]
+fn no_verify() {
+    let server = server_config();
+    let options = TlsConfigOptions { trust: Trust::NoVerify, ..client_options() };
+    assert!(handshake(&options.build().unwrap(), &server).is_empty());
+}
+
+#[test]
+fn config_errors() {
+    let error = |options: TlsConfigOptions| {
+        let e = options.build().unwrap_err();
+        let kind = e.kind();
+        let Some(e) = e.into_inner().and_then(|e| e.downcast::<TlsConfigError>().ok()) else {
+            panic!("error should be a TlsConfigError");
+        };
+        (kind, *e)
+    };
+    let missing = testdata("missing.pem");
+    let (kind, e) =
+        error(TlsConfigOptions { trust: Trust::Only(vec![missing.clone()]), ..client_options() });
+    assert_eq!(kind, std::io::ErrorKind::NotFound);
+    assert!(matches!(e, TlsConfigError::Read(path, _) if path == missing));
+    let mut options = client_options();
+    options.cert = Some(testdata("client1.key"));
+    let (kind, e) = error(options.clone());
+    assert_eq!(kind, std::io::ErrorKind::InvalidData);
+    assert!(matches!(e, TlsConfigError::BadPem(_, "no certificates")));
+    options.cert = Some(testdata("client1.crt"));
+    let (_, e) = error(options.clone());
+    assert!(
+        matches!(e, TlsConfigError::BadPem(path, "no private key") if path == testdata("client1.crt"))
+    );
+    options.key = Some(testdata("ca.pem"));
+    let (_, e) = error(options.clone());
+    assert!(
+        matches!(e, TlsConfigError::BadPem(path, "no private key") if path == testdata("ca.pem"))
+    );
+    options.key = Some(testdata("client2.pem"));
+    let (kind, e) = error(options);
+    assert_eq!(kind, std::io::ErrorKind::InvalidData);
+    assert!(matches!(e, TlsConfigError::KeyMismatch(_)));
+}
+
+#[test]
+fn cert_source_closure() {
+    let server = server_config();
+    let certs = [
+        Arc::new(ClientCert::load(testdata("client1.pem")).unwrap()),
+        Arc::new(ClientCert::load(testdata("client2.pem")).unwrap()),
+    ];
+    let expected: Vec<_> = certs.iter().map(|cert| cert.chain[0].clone()).collect();
+    let idx = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let idx2 = idx.clone();
+    let source = move || {
+        let idx = idx2.load(std::sync::atomic::Ordering::Relaxed);
+        certs.get(idx).cloned()
+    };
+    let config = client_options().build_with_cert_source(Arc::new(source)).unwrap();
+    assert_eq!(handshake(&config, &server)[0], expected[0]);
+    assert_eq!(handshake(&config, &server)[0], expected[0]);
+    idx.store(1, std::sync::atomic::Ordering::Relaxed);
+    assert_eq!(handshake(&config, &server)[0], expected[1]);
+    idx.store(2, std::sync::atomic::Ordering::Relaxed);
+    assert!(handshake(&config, &server).is_empty());
+}
+
+#[test]
+fn cert_file_rotation() {
+    let server = server_config();
+    let path = std::env::temp_dir().join(format!("vinezombie-cert-{}.pem", std::process::id()));
+    std::fs::copy(testdata("client1.pem"), &path).unwrap();
+    let source = Arc::new(CertFile::new(&path));
+    let config = client_options().build_with_cert_source(source.clone()).unwrap();
+    let client1 = ClientCert::load(testdata("client1.pem")).unwrap();
+    let client2 = ClientCert::load(testdata("client2.pem")).unwrap();
+    assert_eq!(handshake(&config, &server)[0], client1.chain[0]);
+    // Make sure the modification time actually changes.
+    let old_mtime = std::fs::metadata(&path).unwrap().modified().unwrap();
+    loop {
+        std::fs::copy(testdata("client2.pem"), &path).unwrap();
+        if std::fs::metadata(&path).unwrap().modified().unwrap() != old_mtime {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(handshake(&config, &server)[0], client2.chain[0]);
+    // Failing to reload keeps the old certificate.
+    std::fs::remove_file(&path).unwrap();
+    assert!(source.reload().is_err());
+    assert_eq!(source.current().unwrap().chain[0], client2.chain[0]);
+    assert_eq!(handshake(&config, &server)[0], client2.chain[0]);
+}
@@ -58,6 +58,128 @@ impl From<ParseError> for std::io::Error {
         std::io::Error::new(std::io::ErrorKind::InvalidData, value)
     }
 }
+
+/// The part of an IRC message that failed to parse.
+///
+/// Tags and arguments are parsed leniently and never cause errors.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub enum MsgPart {
+    /// The message as a whole, such as when it contains a newline.
+    Line,
+    /// The message's source.
+    Source,
+    /// The message's command or numeric reply code.
+    Kind,
+}
+
+impl std::fmt::Display for MsgPart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MsgPart::Line => write!(f, "line"),
+            MsgPart::Source => write!(f, "source"),
+            MsgPart::Kind => write!(f, "kind"),
+        }
+    }
+}
+
+/// A [`ParseError`] along with where in the message it occurred.
+#[derive(Debug)]
+pub struct ParseErrorAt {
+    /// The byte offset into the message of the failing part,
+    /// or of the invalid byte for [`MsgPart::Line`].
+    pub offset: usize,
+    /// Which part of the message failed to parse.
+    pub part: MsgPart,
+    /// The error.
+    pub error: ParseError,
+}
+
+impl std::fmt::Display for ParseErrorAt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (in {} at byte {})", self.error, self.part, self.offset)
+    }
+}
+
+impl std::error::Error for ParseErrorAt {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<ParseErrorAt> for ParseError {
+    fn from(value: ParseErrorAt) -> Self {
+        value.error
+    }
+}
+
+/// Error from encoding an IRC message that is longer than an encoder allows.
+///
+/// This is returned wrapped in an [`std::io::Error`] with the kind
+/// [`InvalidInput`][std::io::ErrorKind::InvalidInput].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct EncodeTooLong {
+    /// The length of the encoded message including the CRLF.
+    pub len: usize,
+    /// The maximum permitted length of the encoded message including the CRLF.
+    pub max_len: usize,
+}
+
+impl EncodeTooLong {
+    /// Returns the `EncodeTooLong` wrapped in `error`, if any.
+    pub fn from_io(error: &std::io::Error) -> Option<&Self> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl std::fmt::Display for EncodeTooLong {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "message is {} bytes long, exceeding the limit of {}", self.len, self.max_len)
+    }
+}
+
+impl std::error::Error for EncodeTooLong {}
+
+impl From<EncodeTooLong> for std::io::Error {
+    fn from(value: EncodeTooLong) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, value)
+    }
+}
+
+/// Error indicating that inserting a tag would exceed a length budget.
+///
+/// See [`TagsEditGuard::try_insert_pair`][crate::ircmsg::TagsEditGuard::try_insert_pair].
+/// This is converted into an [`std::io::Error`] with the kind
+/// [`InvalidInput`][std::io::ErrorKind::InvalidInput].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TagBudgetError {
+    /// The length the tags would have been after the insertion.
+    pub len: usize,
+    /// The maximum permitted length of the tags.
+    pub budget: usize,
+}
+
+impl TagBudgetError {
+    /// Returns the `TagBudgetError` wrapped in `error`, if any.
+    pub fn from_io(error: &std::io::Error) -> Option<&Self> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl std::fmt::Display for TagBudgetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "tags would be {} bytes long, exceeding the budget of {}", self.len, self.budget)
+    }
+}
+
+impl std::error::Error for TagBudgetError {}
+
+impl From<TagBudgetError> for std::io::Error {
+    fn from(value: TagBudgetError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, value)
+    }
+}
+
 /// Error indicating that the invariant of a [`Bytes`][crate::string::Bytes] newtype
 /// has been violated.
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
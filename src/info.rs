@@ -0,0 +1,237 @@
+//! Information about this library and client sessions, suitable for bug reports.
+//!
+//! Nothing in this module includes hostnames, nicknames, or account names
+//! unless explicitly asked for.
+
+#[cfg(all(test, feature = "client"))]
+mod tests;
+
+/// Information about how this library was built.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BuildInfo {
+    /// The version of this library.
+    pub version: &'static str,
+    /// The names of the enabled feature flags, sorted.
+    pub features: &'static [&'static str],
+}
+
+impl std::fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "vinezombie {}", self.version)
+    }
+}
+
+/// The names of the enabled feature flags, sorted.
+pub const FEATURES: &[&str] = &[
+    #[cfg(feature = "arbitrary")]
+    "arbitrary",
+    #[cfg(feature = "base64")]
+    "base64",
+    #[cfg(feature = "client")]
+    "client",
+    #[cfg(feature = "crypto")]
+    "crypto",
+    #[cfg(feature = "diagnostics")]
+    "diagnostics",
+    #[cfg(feature = "futures-channel")]
+    "futures-channel",
+    #[cfg(feature = "idna")]
+    "idna",
+    #[cfg(feature = "serde")]
+    "serde",
+    #[cfg(feature = "testutils")]
+    "testutils",
+    #[cfg(feature = "tls")]
+    "tls",
+    #[cfg(feature = "tls-tokio")]
+    "tls-tokio",
+    #[cfg(feature = "tokio")]
+    "tokio",
+    #[cfg(feature = "tokio-codec")]
+    "tokio-codec",
+    #[cfg(feature = "tracing")]
+    "tracing",
+    #[cfg(feature = "websocket-tokio")]
+    "websocket-tokio",
+    #[cfg(feature = "whoami")]
+    "whoami",
+];
+
+/// Returns information about how this library was built.
+pub const fn build_info() -> BuildInfo {
+    BuildInfo { version: env!("CARGO_PKG_VERSION"), features: FEATURES }
+}
+
+/// The default template for [`SessionInfo::render`].
+#[cfg(feature = "client")]
+pub const DEFAULT_TEMPLATE: &str = "{vinezombie}; {summary}";
+
+/// Capabilities that are worth mentioning in a [`SessionInfo`] summary.
+#[cfg(feature = "client")]
+static NOTABLE_CAPS: [crate::string::Key<'static>; 6] = {
+    use crate::names::cap::*;
+    [
+        ECHO_MESSAGE::NAME,
+        LABELED_RESPONSE::NAME,
+        MESSAGE_TAGS::NAME,
+        SASL::NAME,
+        SERVER_TIME::NAME,
+        STANDARD_REPLIES::NAME,
+    ]
+};
+
+/// A summary of a registered connection.
+///
+/// The [`Display`][std::fmt::Display] impl of this type writes a compact single-line summary
+/// that does not include any of the fields that may identify the user or server.
+#[cfg(feature = "client")]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct SessionInfo {
+    /// The server software's version, as sent in `RPL_MYINFO` (004).
+    pub server_version: Option<crate::string::Arg<'static>>,
+    /// The number of enabled capabilities.
+    pub caps: usize,
+    /// The enabled capabilities that are notable, sorted.
+    pub notable_caps: Vec<crate::string::Key<'static>>,
+    /// The value of the `NETWORK` ISUPPORT token.
+    pub network: Option<crate::string::Word<'static>>,
+    /// The value of the `CASEMAPPING` ISUPPORT token.
+    pub casemapping: Option<crate::string::Word<'static>>,
+    /// The value of the `NICKLEN` ISUPPORT token.
+    pub nicklen: Option<std::num::NonZeroU16>,
+    /// The client's nickname.
+    pub nick: Option<crate::string::Nick<'static>>,
+    /// The client's account name.
+    pub account: Option<crate::string::Arg<'static>>,
+    /// The client's hostname.
+    pub host: Option<crate::string::Word<'static>>,
+    /// The server's name.
+    pub server_name: Option<crate::string::Nick<'static>>,
+}
+
+#[cfg(feature = "client")]
+impl SessionInfo {
+    /// Captures information from the provided [`ClientState`][crate::client::ClientState].
+    pub fn capture(state: &crate::client::ClientState) -> Self {
+        use crate::{client::state::*, names::isupport::NICKLEN, string::Key};
+        let mut caps = 0;
+        let mut notable_caps = Vec::new();
+        if let Some(map) = state.get::<Caps>() {
+            for cap in map.keys() {
+                if map.get_extra_raw(cap) == Some(&true) {
+                    caps += 1;
+                    if NOTABLE_CAPS.contains(cap) {
+                        notable_caps.push(cap.clone());
+                    }
+                }
+            }
+        }
+        let isupport = state.get::<ISupport>();
+        let raw = |name: &'static str| {
+            let (_, value) = isupport?.get_union_raw(&Key::from_str(name))?;
+            Some(value.clone())
+        };
+        let source = state.get::<ClientSource>();
+        SessionInfo {
+            server_version: state.get::<ServerVersion>().cloned(),
+            caps,
+            notable_caps,
+            network: raw("NETWORK"),
+            casemapping: raw("CASEMAPPING"),
+            nicklen: isupport.and_then(|map| map.get_parsed(NICKLEN)?.ok()),
+            nick: source.map(|source| source.nick.clone()),
+            account: state.get::<Account>().cloned().flatten(),
+            host: source.and_then(|source| Some(source.userhost.as_ref()?.host.clone())),
+            server_name: state.get::<ServerSource>().map(|source| source.nick.clone()),
+        }
+    }
+
+    /// Substitutes placeholders in `template`.
+    ///
+    /// The following placeholders are substituted:
+    /// - `{vinezombie}`: The name and version of this library.
+    /// - `{features}`: This library's enabled feature flags, comma-separated.
+    /// - `{summary}`: The output of this type's `Display` impl.
+    /// - `{server}`: The server software's version.
+    /// - `{caps}`: The number of enabled capabilities.
+    /// - `{network}`, `{casemapping}`, `{nicklen}`: The values of those ISUPPORT tokens.
+    ///
+    /// The following placeholders may identify the user or server,
+    /// and are only substituted if explicitly included in the template:
+    /// - `{nick}`: The client's nickname.
+    /// - `{account}`: The client's account name.
+    /// - `{host}`: The client's hostname.
+    /// - `{server_name}`: The server's name.
+    ///
+    /// Missing values are substituted with `*`.
+    /// Unknown placeholders are left as-is.
+    pub fn render(&self, template: &str) -> String {
+        fn or_star(value: Option<impl std::fmt::Display>) -> String {
+            value.map_or_else(|| "*".to_owned(), |v| v.to_string())
+        }
+        let mut retval = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            retval.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let Some(end) = rest.find('}') else {
+                break;
+            };
+            let value = match &rest[1..end] {
+                "vinezombie" => build_info().to_string(),
+                "features" => FEATURES.join(","),
+                "summary" => self.to_string(),
+                "server" => or_star(self.server_version.as_ref()),
+                "caps" => self.caps.to_string(),
+                "network" => or_star(self.network.as_ref()),
+                "casemapping" => or_star(self.casemapping.as_ref()),
+                "nicklen" => or_star(self.nicklen),
+                "nick" => or_star(self.nick.as_ref()),
+                "account" => or_star(self.account.as_ref()),
+                "host" => or_star(self.host.as_ref()),
+                "server_name" => or_star(self.server_name.as_ref()),
+                _ => {
+                    retval.push('{');
+                    rest = &rest[1..];
+                    continue;
+                }
+            };
+            retval.push_str(&value);
+            rest = &rest[end + 1..];
+        }
+        retval.push_str(rest);
+        retval
+    }
+}
+
+#[cfg(feature = "client")]
+impl std::fmt::Display for SessionInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(version) = &self.server_version {
+            write!(f, "server={version}")?;
+        } else {
+            f.write_str("server=*")?;
+        }
+        write!(f, " caps={}", self.caps)?;
+        if !self.notable_caps.is_empty() {
+            f.write_str("(")?;
+            for (idx, cap) in self.notable_caps.iter().enumerate() {
+                if idx != 0 {
+                    f.write_str(",")?;
+                }
+                write!(f, "{cap}")?;
+            }
+            f.write_str(")")?;
+        }
+        if let Some(network) = &self.network {
+            write!(f, " network={network}")?;
+        }
+        if let Some(casemapping) = &self.casemapping {
+            write!(f, " casemapping={casemapping}")?;
+        }
+        if let Some(nicklen) = self.nicklen {
+            write!(f, " nicklen={nicklen}")?;
+        }
+        Ok(())
+    }
+}
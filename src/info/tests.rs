@@ -0,0 +1,67 @@
+use super::{SessionInfo, DEFAULT_TEMPLATE};
+use crate::{
+    client::{state::*, ClientState},
+    ircmsg::{Source, UserHost},
+    names::{cap, Cap, NameMap},
+    string::{Arg, Key, Nick, Word},
+};
+
+fn fixture() -> ClientState {
+    let mut caps = NameMap::<Cap, bool>::new();
+    let mut edit = caps.edit();
+    edit.insert((cap::SASL::NAME, Word::default()), true);
+    edit.insert((cap::MESSAGE_TAGS::NAME, Word::default()), true);
+    edit.insert((cap::ECHO_MESSAGE::NAME, Word::default()), false);
+    edit.insert((cap::ACCOUNT_NOTIFY::NAME, Word::default()), true);
+    std::mem::drop(edit);
+    let mut isupport = NameMap::<crate::names::ISupport>::new();
+    let mut edit = isupport.edit();
+    edit.insert((Key::from_str("CASEMAPPING"), Word::from_str("rfc1459")), ());
+    edit.insert((Key::from_str("NETWORK"), Word::from_str("Example")), ());
+    edit.insert((Key::from_str("NICKLEN"), Word::from_str("16")), ());
+    std::mem::drop(edit);
+    let userhost = UserHost { user: None, host: Word::from_str("host.example.com") };
+    let source = Source { nick: Nick::from_str("someone"), userhost: Some(userhost) };
+    let mut state = ClientState::new();
+    state.update(|txn| {
+        txn.insert::<Caps>(caps);
+        txn.insert::<ISupport>(isupport);
+        txn.insert::<ServerVersion>(Arg::from_str("solanum-1.0"));
+        txn.insert::<ClientSource>(source);
+        txn.insert::<Account>(Some(Arg::from_str("someacct")));
+        txn.insert::<ServerSource>(Source::new_server(Nick::from_str("irc.example.com")));
+    });
+    state
+}
+
+#[test]
+fn default_template() {
+    let info = SessionInfo::capture(&fixture());
+    assert_eq!(
+        info.render(DEFAULT_TEMPLATE),
+        format!(
+            "vinezombie {}; server=solanum-1.0 caps=3(message-tags,sasl) \
+            network=Example casemapping=rfc1459 nicklen=16",
+            env!("CARGO_PKG_VERSION")
+        )
+    );
+}
+
+#[test]
+fn redaction() {
+    let info = SessionInfo::capture(&fixture());
+    let rendered = info.render("{vinezombie} {features} {summary} {server} {caps} {unknown}");
+    for secret in ["someone", "someacct", "host.example.com", "irc.example.com"] {
+        assert!(!rendered.contains(secret), "{secret} in {rendered}");
+    }
+    assert!(rendered.ends_with(" 3 {unknown}"));
+    let rendered = info.render("{nick} {account} {host} {server_name}");
+    assert_eq!(rendered, "someone someacct host.example.com irc.example.com");
+}
+
+#[test]
+fn empty_state() {
+    let info = SessionInfo::capture(&ClientState::new());
+    assert_eq!(info.to_string(), "server=* caps=0");
+    assert_eq!(info.render("{nick}/{account}/{casemapping}"), "*/*/*");
+}
@@ -1,19 +1,26 @@
 #![doc = include_str!("../doc/rustdoc/ircmsg.md")]
 
+#[cfg(feature = "arbitrary")]
+mod arbitrary;
 mod args;
 mod client;
 mod codec;
 mod ctcp;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+mod namesreply;
 mod numeric;
+mod redact;
 mod server;
 mod servermsgkind;
 mod source;
+mod tagpolicy;
 mod tags;
 mod targeted;
 #[cfg(test)]
 mod tests;
 
 pub use self::{
-    args::*, client::*, codec::*, ctcp::*, numeric::*, server::*, servermsgkind::*, source::*,
-    tags::*, targeted::*,
+    args::*, client::*, codec::*, ctcp::*, namesreply::*, numeric::*, redact::*, server::*,
+    servermsgkind::*, source::*, tagpolicy::*, tags::*, targeted::*,
 };
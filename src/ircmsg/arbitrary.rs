@@ -0,0 +1,98 @@
+//! Implementations of [`Arbitrary`] for IRC messages and their parts.
+//!
+//! Generated values always uphold the invariants of their types,
+//! and are built out of owned strings.
+
+use super::{
+    Args, ClientMsg, Numeric, ServerMsg, ServerMsgKindRaw, SharedSource, Source, Tags, UserHost,
+};
+use crate::string::{Arg, Bytes, BytesNewtype, Cmd, Key, Line, Nick, NoNul, User};
+use ::arbitrary::{Arbitrary, Result, Unstructured};
+
+/// Generates a `T` out of arbitrary bytes, skipping any bytes that are invalid for `T`.
+///
+/// `default` is used instead if the remaining bytes are still not a valid `T`,
+/// such as when `T` cannot be empty or start with a colon.
+fn string<T>(u: &mut Unstructured<'_>, default: &'static str) -> Result<T>
+where
+    T: BytesNewtype<'static> + TryFrom<Bytes<'static>>,
+{
+    let bytes: &[u8] = u.arbitrary()?;
+    let bytes: Vec<u8> = bytes.iter().copied().filter(|b| !T::is_invalid(b)).collect();
+    T::try_from(bytes.into())
+        .or_else(|_| T::try_from(Bytes::from_str(default)))
+        .map_err(|_| ::arbitrary::Error::IncorrectFormat)
+}
+
+fn cmd(u: &mut Unstructured<'_>) -> Result<Cmd<'static>> {
+    let len = u.int_in_range(1..=12)?;
+    let mut bytes = Vec::with_capacity(len);
+    for _ in 0..len {
+        bytes.push(u.int_in_range(b'A'..=b'Z')?);
+    }
+    Cmd::from_bytes(bytes).map_err(|_| ::arbitrary::Error::IncorrectFormat)
+}
+
+impl<'a> Arbitrary<'a> for Tags<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut tags = Tags::new();
+        let mut edit = tags.edit();
+        for _ in 0..u.int_in_range(0..=8)? {
+            let key: Key<'static> = string(u, "key")?;
+            let value: NoNul<'static> = string(u, "")?;
+            edit.insert_pair(key, value);
+        }
+        std::mem::drop(edit);
+        Ok(tags)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Source<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let nick: Nick<'static> = string(u, "nick")?;
+        let userhost = if u.arbitrary()? {
+            let user: Option<User<'static>> =
+                if u.arbitrary()? { Some(string(u, "user")?) } else { None };
+            // Hosts cannot be empty.
+            let host: Arg<'static> = string(u, "host")?;
+            Some(UserHost { user, host: host.into() })
+        } else {
+            None
+        };
+        Ok(Source { nick, userhost })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Args<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut words = Vec::new();
+        for _ in 0..u.int_in_range(0..=14)? {
+            words.push(string::<Arg<'static>>(u, "arg")?);
+        }
+        let last: Option<Line<'static>> = if u.arbitrary()? { Some(string(u, "")?) } else { None };
+        Ok(Args::new(words, last))
+    }
+}
+
+impl<'a> Arbitrary<'a> for ClientMsg<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let tags = u.arbitrary()?;
+        let cmd = cmd(u)?;
+        let args = u.arbitrary()?;
+        Ok(ClientMsg { tags, cmd, args })
+    }
+}
+
+impl<'a> Arbitrary<'a> for ServerMsg<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let tags = u.arbitrary()?;
+        let source = u.arbitrary::<Option<Source<'static>>>()?.map(SharedSource::new);
+        let kind = if u.arbitrary()? {
+            ServerMsgKindRaw::Numeric(Numeric::from_int(u.int_in_range(0..=999)?).unwrap())
+        } else {
+            ServerMsgKindRaw::Cmd(cmd(u)?)
+        };
+        let args = u.arbitrary()?;
+        Ok(ServerMsg { tags, source, kind, args })
+    }
+}
@@ -16,6 +16,88 @@ pub struct Args<'a> {
     long: Option<Line<'a>>,
 }
 
+/// A reference to one argument in an [`Args`].
+///
+/// Dereferences to [`Line`], and ultimately to a byte slice.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ArgRef<'a, 'b> {
+    /// A single-word argument.
+    Word(&'b Arg<'a>),
+    /// A final argument that is more than one word long.
+    Long(&'b Line<'a>),
+}
+
+impl<'a, 'b> ArgRef<'a, 'b> {
+    /// Returns true if this is a long argument.
+    pub const fn is_long(&self) -> bool {
+        matches!(self, ArgRef::Long(_))
+    }
+    /// Returns the argument as an [`Arg`], or `None` if it is long.
+    pub const fn as_arg(&self) -> Option<&'b Arg<'a>> {
+        match self {
+            ArgRef::Word(arg) => Some(arg),
+            ArgRef::Long(_) => None,
+        }
+    }
+    /// Returns the argument as a [`Line`].
+    pub fn as_line(&self) -> &'b Line<'a> {
+        match self {
+            ArgRef::Word(arg) => arg,
+            ArgRef::Long(line) => line,
+        }
+    }
+}
+
+impl<'a> std::ops::Deref for ArgRef<'a, '_> {
+    type Target = Line<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_line()
+    }
+}
+
+impl std::fmt::Display for ArgRef<'_, '_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_line().fmt(f)
+    }
+}
+
+/// Iterator over every argument in an [`Args`], including the long argument.
+///
+/// Created by [`Args::iter`].
+#[derive(Clone, Debug)]
+pub struct ArgsIter<'a, 'b> {
+    words: std::slice::Iter<'b, Arg<'a>>,
+    long: Option<&'b Line<'a>>,
+}
+
+impl<'a, 'b> Iterator for ArgsIter<'a, 'b> {
+    type Item = ArgRef<'a, 'b>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.words.next().map(ArgRef::Word).or_else(|| self.long.take().map(ArgRef::Long))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for ArgsIter<'_, '_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.long.take().map(ArgRef::Long).or_else(|| self.words.next_back().map(ArgRef::Word))
+    }
+}
+
+impl ExactSizeIterator for ArgsIter<'_, '_> {
+    fn len(&self) -> usize {
+        self.words.len() + self.long.is_some() as usize
+    }
+}
+
+impl std::iter::FusedIterator for ArgsIter<'_, '_> {}
+
 /// Guard for editing [`Args`].
 #[derive(PartialEq, Eq, Hash, Debug)]
 pub struct ArgsEditGuard<'a, 'b>(&'b mut Vec<Arg<'a>>, &'b mut Option<Line<'a>>);
@@ -41,6 +123,36 @@ impl<'a> ArgsEditGuard<'a, '_> {
         }
         self.0.push(s);
     }
+    /// Adds the decimal representation of an integer to the argument list
+    /// as [`add_word`][ArgsEditGuard::add_word].
+    ///
+    /// See [`Arg::from_int`] for which integer types can be used.
+    pub fn add_int(&mut self, int: impl Into<i128>) {
+        self.add_word(Arg::from_int(int));
+    }
+    /// Joins `items` with `sep` into one argument and adds it to the argument list
+    /// as [`add_word`][ArgsEditGuard::add_word],
+    /// such as for comma-separated lists of channels.
+    ///
+    /// Errors without changing the argument list if there are no items or if `sep` cannot
+    /// be part of an [`Arg`].
+    /// This does not limit the number of items;
+    /// split them according to the server's `TARGMAX` ISUPPORT token beforehand if needed.
+    pub fn add_join<'b: 'a>(
+        &mut self,
+        sep: u8,
+        items: impl IntoIterator<Item = Arg<'b>>,
+    ) -> Result<(), InvalidString> {
+        let mut joined = Vec::new();
+        for item in items {
+            if !joined.is_empty() {
+                joined.push(sep);
+            }
+            joined.extend_from_slice(item.as_bytes());
+        }
+        self.add_word(Arg::from_bytes(joined)?);
+        Ok(())
+    }
     /// Adds a string to the end of this argument list.
     ///
     /// If the last string in the argument list is long,
@@ -141,7 +253,7 @@ impl<'a> Args<'a> {
         let mut words = Vec::with_capacity(2);
         let mut long = None;
         loop {
-            line.consume_whitespace();
+            line.consume_spaces();
             match line.string::<Arg>(false) {
                 Ok(arg) => words.push(arg),
                 Err(InvalidString::Colon) => {
@@ -207,6 +319,20 @@ impl<'a> Args<'a> {
             (&[], None)
         }
     }
+    /// Returns an iterator over all of the arguments in order, including the long argument.
+    pub fn iter(&self) -> ArgsIter<'a, '_> {
+        ArgsIter { words: self.words.iter(), long: self.long.as_ref() }
+    }
+    /// Returns the argument at index `idx`, if any.
+    ///
+    /// If the last argument is long, it is at index `self.len() - 1`.
+    pub fn get(&self, idx: usize) -> Option<ArgRef<'a, '_>> {
+        match self.words.get(idx) {
+            Some(word) => Some(ArgRef::Word(word)),
+            None if idx == self.words.len() => self.long.as_ref().map(ArgRef::Long),
+            None => None,
+        }
+    }
     /// Sets `self` to the provided arguments.
     pub fn set(
         &mut self,
@@ -238,21 +364,28 @@ impl<'a> From<Vec<Arg<'a>>> for Args<'a> {
     }
 }
 
+impl<'a, 'b> IntoIterator for &'b Args<'a> {
+    type Item = ArgRef<'a, 'b>;
+
+    type IntoIter = ArgsIter<'a, 'b>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 impl std::fmt::Display for Args<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if let Some(long) = &self.long {
-            for arg in self.words.iter() {
-                write!(f, "{} ", arg)?;
+        for (idx, arg) in self.iter().enumerate() {
+            if idx != 0 {
+                f.write_str(" ")?;
             }
-            write!(f, ":{}", long)
-        } else if let Some((last, rest)) = self.words.split_last() {
-            for arg in rest {
-                write!(f, "{} ", arg)?;
+            if arg.is_long() {
+                f.write_str(":")?;
             }
-            write!(f, "{}", last)
-        } else {
-            Ok(())
+            write!(f, "{arg}")?;
         }
+        Ok(())
     }
 }
 
@@ -264,11 +397,8 @@ impl<'a> serde::Serialize for Args<'a> {
     {
         use serde::ser::SerializeSeq;
         let mut seq = ser.serialize_seq(Some(self.len()))?;
-        for word in self.words.iter() {
-            seq.serialize_element(word)?;
-        }
-        if let Some(long) = &self.long {
-            seq.serialize_element(long)?;
+        for arg in self.iter() {
+            seq.serialize_element(arg.as_line())?;
         }
         seq.end()
     }
@@ -1,14 +1,18 @@
-use super::{Args, Source, Tags};
+use super::{Args, ServerMsg, ServerMsgKindRaw, Source, Tags};
 use crate::{
-    error::{InvalidString, ParseError},
+    error::{InvalidString, ParseError, ParseErrorAt},
     names::{ClientMsgKind, Name, NameValued},
-    string::{Cmd, Line},
+    string::{Arg, Cmd, Line},
 };
-use std::io::Write;
+use std::{io::Write, num::NonZeroUsize};
 
 /// An IRC message sent by a client.
+///
+/// When serialized using `serde`, this type includes a `version` field
+/// containing [`ClientMsg::SCHEMA_VERSION`].
+/// Deserialization fails if this field has any other value,
+/// but succeeds if it is absent.
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
-#[cfg_attr(feature = "serde", derive(serde_derive::Serialize, serde_derive::Deserialize))]
 pub struct ClientMsg<'a> {
     /// This message's tags, if any.
     pub tags: Tags<'a>,
@@ -54,10 +58,14 @@ impl ClientMsg<'static> {
 }
 
 impl<'a> ClientMsg<'a> {
+    /// The version of the format used to (de)serialize this type using `serde`.
+    ///
+    /// This will change if the format changes in an incompatible way.
+    pub const SCHEMA_VERSION: u32 = 1;
     /// Uses the provided message arguments for `self`.
     pub fn with_args(
         mut self,
-        args: impl IntoIterator<Item = crate::string::Arg<'a>>,
+        args: impl IntoIterator<Item = Arg<'a>>,
         last: Option<Line<'a>>,
     ) -> Self {
         self.args.set(args, last);
@@ -108,15 +116,53 @@ impl<'a> ClientMsg<'a> {
     }
     /// The length of the longest permissible client message.
     pub const MAX_LEN: usize = 4608;
+    /// The length of the longest permissible tags on a client message,
+    /// including the leading `'@'` and the space after the tags.
+    pub const MAX_TAGS_LEN: usize = 4094;
     /// Creates a new `ClientMsg` with the provided command.
     pub const fn new_cmd(cmd: Cmd<'a>) -> Self {
         ClientMsg { tags: Tags::new(), cmd, args: Args::empty() }
     }
+    /// Creates a new `ClientMsg` with the provided command and arguments.
+    ///
+    /// Equivalent to `ClientMsg::new_cmd(cmd).with_args(args, None)`.
+    pub fn new_cmd_args(cmd: Cmd<'a>, args: impl IntoIterator<Item = Arg<'a>>) -> Self {
+        Self::new_cmd(cmd).with_args(args, None)
+    }
+    /// Converts a [`ServerMsg`] into a `ClientMsg`, discarding its source.
+    ///
+    /// The tags and arguments are moved as-is without copying.
+    /// Fails with [`ParseError::InvalidKind`] if the message is a numeric reply,
+    /// as clients cannot send those.
+    pub fn from_server(msg: ServerMsg<'a>) -> Result<ClientMsg<'a>, ParseError> {
+        let ServerMsg { tags, kind, args, .. } = msg;
+        match kind {
+            ServerMsgKindRaw::Cmd(cmd) => Ok(ClientMsg { tags, cmd, args }),
+            ServerMsgKindRaw::Numeric(num) => {
+                Err(ParseError::InvalidKind(InvalidString::Byte(num.as_bytes()[0])))
+            }
+        }
+    }
     /// Parses a message from a [`Line`].
     pub fn parse(
         msg: impl TryInto<Line<'a>, Error = impl Into<InvalidString>>,
     ) -> Result<ClientMsg<'a>, ParseError> {
         let msg = msg.try_into().map_err(|e| ParseError::InvalidLine(e.into()))?;
+        Ok(Self::parse_impl(msg)?)
+    }
+    /// Parses a message from a string,
+    /// reporting where in the string parsing failed on error.
+    ///
+    /// This is otherwise the same as [`parse`][ClientMsg::parse].
+    /// The [`Display`][std::fmt::Display] impl is its inverse.
+    pub fn parse_detailed<M, E>(msg: M) -> Result<ClientMsg<'a>, ParseErrorAt>
+    where
+        M: AsRef<[u8]> + TryInto<Line<'a>, Error = E>,
+        E: Into<InvalidString>,
+    {
+        Self::parse_impl(super::parse_line(msg)?)
+    }
+    fn parse_impl(msg: Line<'a>) -> Result<ClientMsg<'a>, ParseErrorAt> {
         let (tags, _, cmd, args) = super::parse(
             msg,
             |_| Ok(()),
@@ -135,6 +181,33 @@ impl<'a> ClientMsg<'a> {
     pub fn bytes_left(&self, source: Option<&Source>) -> isize {
         super::bytes_left(&self.cmd, source.map(Source::len_nonzero), &self.args)
     }
+    /// Returns the length in bytes of this message's tags and of the rest of the message.
+    ///
+    /// The tag length includes the leading `'@'` and the space after the tags,
+    /// and is zero if there are no tags.
+    /// The length of the rest of the message does not include the trailing CRLF
+    /// and pessimistically counts the last argument as having a colon.
+    /// Neither length accounts for a source, which servers add when forwarding messages.
+    pub fn len_bytes(&self) -> (usize, usize) {
+        let tags = if self.tags.is_empty() { 0 } else { self.tags.len_bytes() + 1 };
+        let mut rest = self.cmd.len();
+        if !self.args.is_empty() {
+            rest += self.args.len_bytes() + 1;
+        }
+        (tags, rest)
+    }
+    /// Returns `true` if this message will fit within the limits that servers enforce,
+    /// even after a source is added to it.
+    ///
+    /// `source_len` is the assumed length of the client's source,
+    /// as returned by [`ClientState::source_len`][crate::client::ClientState::source_len].
+    /// The tags must be at most [`MAX_TAGS_LEN`][ClientMsg::MAX_TAGS_LEN] bytes long,
+    /// and the rest of the message plus the source must fit in 512 bytes including CRLF.
+    pub fn fits(&self, source_len: NonZeroUsize) -> bool {
+        let (tags, rest) = self.len_bytes();
+        // 512 minus CRLF, and the source's leading colon and trailing space.
+        tags <= Self::MAX_TAGS_LEN && rest.saturating_add(source_len.get()) <= 508
+    }
     #[deprecated = "Moved to `ClientCodec` in 0.4."]
     /// Writes self to the provided [`Write`] WITHOUT a trailing CRLF.
     ///
@@ -176,6 +249,14 @@ impl<'a> ClientMsg<'a> {
     }
 }
 
+impl<'a> TryFrom<ServerMsg<'a>> for ClientMsg<'a> {
+    type Error = ParseError;
+
+    fn try_from(value: ServerMsg<'a>) -> Result<Self, Self::Error> {
+        ClientMsg::from_server(value)
+    }
+}
+
 impl std::fmt::Display for ClientMsg<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if !self.tags.is_empty() {
@@ -189,3 +270,53 @@ impl std::fmt::Display for ClientMsg<'_> {
         Ok(())
     }
 }
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ClientMsg<'_> {
+    fn serialize<S>(&self, ser: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+        let mut msg = ser.serialize_struct("ClientMsg", 4)?;
+        msg.serialize_field("version", &Self::SCHEMA_VERSION)?;
+        msg.serialize_field("tags", &self.tags)?;
+        msg.serialize_field("cmd", &self.cmd)?;
+        msg.serialize_field("args", &self.args)?;
+        msg.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+fn default_schema_version() -> u32 {
+    ClientMsg::SCHEMA_VERSION
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde_derive::Deserialize)]
+#[serde(rename = "ClientMsg")]
+struct ClientMsgRepr<'a> {
+    #[serde(default = "default_schema_version")]
+    version: u32,
+    tags: Tags<'a>,
+    cmd: Cmd<'a>,
+    args: Args<'a>,
+}
+
+#[cfg(feature = "serde")]
+impl<'a, 'de> serde::Deserialize<'de> for ClientMsg<'a> {
+    fn deserialize<D>(de: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+        let ClientMsgRepr { version, tags, cmd, args } = ClientMsgRepr::deserialize(de)?;
+        if version != Self::SCHEMA_VERSION {
+            return Err(D::Error::custom(format!(
+                "unsupported ClientMsg schema version {version} (expected {})",
+                Self::SCHEMA_VERSION
+            )));
+        }
+        Ok(ClientMsg { tags, cmd, args })
+    }
+}
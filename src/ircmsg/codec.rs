@@ -1,12 +1,18 @@
 use super::{Args, ClientMsg, ServerMsg, Source, Tags};
-use crate::error::{InvalidString, ParseError};
+use crate::error::{EncodeTooLong, InvalidString, MsgPart, ParseError, ParseErrorAt};
 use crate::string::{Line, Splitter, Word};
 use std::io::Write;
 use std::num::NonZeroUsize;
 
 macro_rules! read_msg {
     (
-        $limit:path, $buf:ident, $read:ident: $read_type:ident, $read_expr:expr, $parse_expr:expr
+        $limit:expr,
+        $overlong:expr,
+        $buf:ident,
+        $read:ident: $read_type:ident,
+        $read_expr:expr,
+        $skip_expr:expr,
+        $parse_expr:expr
     ) => {{
         use std::io::{Error, ErrorKind};
         let mut $read = $read_type::take($read, 1);
@@ -19,13 +25,34 @@ macro_rules! read_msg {
                     return Err(Error::from(ErrorKind::UnexpectedEof));
                 }
             }
-            read_buf!(|$buf| $parse_expr)
+            read_buf!(
+                |$buf| $parse_expr,
+                match $overlong {
+                    OverlongPolicy::Error => return Err(ParseError::TooLong.into()),
+                    OverlongPolicy::Truncate => {
+                        // The rest of the line is skipped first so that `buf` still holds
+                        // the partial line if skipping is interrupted.
+                        $skip_expr?;
+                        while $buf.last() == Some(&b'\r') {
+                            $buf.truncate($buf.len() - 1);
+                        }
+                        return match $parse_expr {
+                            Ok(msg) => Ok(msg),
+                            Err(e) => Err(e.into()),
+                        };
+                    }
+                    OverlongPolicy::Skip => {
+                        $skip_expr?;
+                        $buf.clear();
+                    }
+                }
+            )
         }
     }};
 }
 
 macro_rules! read_buf {
-    (|$buf:ident| $parse_expr:expr) => {{
+    (|$buf:ident| $parse_expr:expr, $overlong_expr:expr) => {{
         use crate::error::InvalidString;
         let mut found_newline = false;
         while let Some(c) = $buf.last() {
@@ -37,7 +64,7 @@ macro_rules! read_buf {
                 b'\r' if found_newline => {
                     $buf.truncate($buf.len() - 1);
                 }
-                b'\r' | b'\0' => {
+                b'\r' | b'\0' if found_newline => {
                     return Err(ParseError::InvalidLine(InvalidString::Byte(*c)).into())
                 }
                 _ if found_newline => {
@@ -48,29 +75,94 @@ macro_rules! read_buf {
                 }
                 // We stumbled into a non-newline character at the end of a read that
                 // was supposed to read up until the newline or the max msg len.
-                _ => return Err(ParseError::TooLong.into()),
+                _ => $overlong_expr,
             }
         }
     }};
 }
 
+/// How to handle received lines that are longer than a codec's read limit.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum OverlongPolicy {
+    /// Return an error wrapping [`ParseError::TooLong`].
+    ///
+    /// The rest of the line is not consumed,
+    /// and will be read as though it were the start of the next line.
+    #[default]
+    Error,
+    /// Parse the line up to the read limit and discard the rest of it.
+    Truncate,
+    /// Discard the line and read the next one.
+    Skip,
+}
+
+/// Discards input from `read` up to and including the next line feed or EOF.
+fn skip_rest(read: &mut (impl std::io::BufRead + ?Sized)) -> std::io::Result<()> {
+    loop {
+        let buf = read.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(());
+        }
+        if let Some(idx) = buf.iter().position(|b| *b == b'\n') {
+            read.consume(idx + 1);
+            return Ok(());
+        }
+        let len = buf.len();
+        read.consume(len);
+    }
+}
+
+/// Asynchronously discards input from `read` up to and including the next line feed or EOF.
+#[cfg(feature = "tokio")]
+async fn skip_rest_tokio(
+    read: &mut (impl tokio::io::AsyncBufRead + ?Sized + Unpin),
+) -> std::io::Result<()> {
+    use tokio::io::AsyncBufReadExt;
+    loop {
+        let buf = read.fill_buf().await?;
+        if buf.is_empty() {
+            return Ok(());
+        }
+        if let Some(idx) = buf.iter().position(|b| *b == b'\n') {
+            read.consume(idx + 1);
+            return Ok(());
+        }
+        let len = buf.len();
+        read.consume(len);
+    }
+}
+
+/// Converts `msg` into a [`Line`], reporting the offset of the first invalid byte on failure.
+pub(crate) fn parse_line<'a, M, E>(msg: M) -> Result<Line<'a>, ParseErrorAt>
+where
+    M: AsRef<[u8]> + TryInto<Line<'a>, Error = E>,
+    E: Into<InvalidString>,
+{
+    let bad = msg.as_ref().iter().position(|b| matches!(b, b'\0' | b'\r' | b'\n'));
+    msg.try_into().map_err(|e| ParseErrorAt {
+        offset: bad.unwrap_or_default(),
+        part: MsgPart::Line,
+        error: ParseError::InvalidLine(e.into()),
+    })
+}
+
 #[inline(always)]
 pub(crate) fn parse<'a, S: 'a, K: 'a>(
     msg: Line<'a>,
     parse_source: impl Fn(Word<'a>) -> Result<S, ParseError>,
     parse_kind: impl FnOnce(Word<'a>) -> Result<K, ParseError>,
-) -> Result<(Tags<'a>, Option<S>, K, Args<'a>), ParseError> {
+) -> Result<(Tags<'a>, Option<S>, K, Args<'a>), ParseErrorAt> {
+    let at = |offset, part| move |error| ParseErrorAt { offset, part, error };
+    let total = msg.len();
     let mut tags = Tags::new();
     let mut source = None;
     let mut expect_tags = true;
     let mut expect_source = true;
     let mut msg = Splitter::new(msg);
-    let kind = loop {
-        msg.consume_whitespace();
+    let (kind, kind_offset) = loop {
+        msg.consume_spaces();
+        let offset = total - msg.len();
         let word: Word = msg.string_or_default(false);
-        if word.is_empty() {
-            return Err(ParseError::InvalidKind(InvalidString::Empty));
-        }
         match word.first() {
             Some(b'@') if expect_tags => {
                 let mut word = Splitter::new(word);
@@ -85,13 +177,17 @@ pub(crate) fn parse<'a, S: 'a, K: 'a>(
                 word.next_byte();
                 // Maybe not quiet failure here?
                 // Non-parsed sources can sometimes still be useful.
-                source = Some(parse_source(word.rest_or_default())?);
+                let parsed = parse_source(word.rest_or_default());
+                source = Some(parsed.map_err(at(offset + 1, MsgPart::Source))?);
+            }
+            Some(_) => break (word, offset),
+            None => {
+                let error = ParseError::InvalidKind(InvalidString::Empty);
+                return Err(at(offset, MsgPart::Kind)(error));
             }
-            Some(_) => break word,
-            None => return Err(ParseError::InvalidKind(InvalidString::Empty)),
         }
     };
-    let kind = parse_kind(kind)?;
+    let kind = parse_kind(kind).map_err(at(kind_offset, MsgPart::Kind))?;
     let args = Args::parse(msg.rest_or_default::<Line>());
     Ok((tags, source, kind, args))
 }
@@ -144,65 +240,262 @@ fn write_to(
     Ok(())
 }
 
-/// Stateless encoder/decoder for raw IRC messages on a client.
+/// Checks that a serialized message without its trailing CRLF
+/// is no longer than `max_len` bytes including the CRLF and contains no line breaks or nulls.
+fn check_line(line: &[u8], max_len: usize) -> Result<(), ParseError> {
+    if line.len().saturating_add(2) > max_len {
+        return Err(ParseError::TooLong);
+    }
+    if let Some(byte) = line.iter().copied().find(|b| matches!(b, b'\r' | b'\n' | b'\0')) {
+        return Err(ParseError::InvalidLine(InvalidString::Byte(byte)));
+    }
+    Ok(())
+}
+
+/// Serializes a message into `buf` using `write_fn` followed by a CRLF,
+/// erroring if the result would be longer than `max_len`.
+///
+/// On failure, `buf` is returned to its previous length.
+fn write_limited(
+    buf: &mut Vec<u8>,
+    max_len: usize,
+    write_fn: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let start = buf.len();
+    write_fn(buf)?;
+    let len = buf.len() - start + 2;
+    if len > max_len {
+        buf.truncate(start);
+        return Err(EncodeTooLong { len, max_len }.into());
+    }
+    buf.extend_from_slice(b"\r\n");
+    Ok(())
+}
+
+/// Serializes a message into `buf` using `write_fn` and checks it using [`check_line`].
+///
+/// On failure, `buf` is returned to its previous length.
+fn write_checked(
+    buf: &mut Vec<u8>,
+    max_len: usize,
+    write_fn: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let start = buf.len();
+    write_fn(buf)?;
+    if let Err(e) = check_line(&buf[start..], max_len) {
+        buf.truncate(start);
+        return Err(e.into());
+    }
+    buf.extend_from_slice(b"\r\n");
+    Ok(())
+}
+
+/// Encoder/decoder for raw IRC messages on a client.
 ///
 /// Encodes [`ClientMsg`]s and decodes [`ServerMsg`]s.
 ///
 /// If the `tokio-codec` feature is enabled, this type implements
 /// [`Decoder`][tokio_util::codec::Decoder] and [`Encoder`][tokio_util::codec::Encoder].
-#[derive(Clone, Copy, Debug, Default)]
-pub struct ClientCodec;
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct ClientCodec {
+    /// The maximum length of read messages including the CRLF,
+    /// or `None` to use [`ServerMsg::MAX_LEN`].
+    pub max_len_read: Option<usize>,
+    /// The maximum length of written messages including the CRLF,
+    /// or `None` to use [`ClientMsg::MAX_LEN`].
+    ///
+    /// This is only enforced by [`send`][ClientCodec::send],
+    /// [`send_tokio`][ClientCodec::send_tokio], and the [`Encoder`][tokio_util::codec::Encoder] implementation.
+    pub max_len_write: Option<usize>,
+    /// How to handle received lines that are longer than the read limit.
+    pub overlong: OverlongPolicy,
+}
 
-/// Stateless encoder/decoder for raw IRC messages on a server.
+/// Encoder/decoder for raw IRC messages on a server.
 ///
 /// Encodes [`ServerMsg`]s and decodes [`ClientMsg`]s.
 ///
 /// If the `tokio-codec` feature is enabled, this type implements
 /// [`Decoder`][tokio_util::codec::Decoder] and [`Encoder`][tokio_util::codec::Encoder].
-#[derive(Clone, Copy, Debug, Default)]
-pub struct ServerCodec;
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct ServerCodec {
+    /// The maximum length of read messages including the CRLF,
+    /// or `None` to use [`ClientMsg::MAX_LEN`].
+    pub max_len_read: Option<usize>,
+    /// The maximum length of written messages including the CRLF,
+    /// or `None` to use [`ServerMsg::MAX_LEN`].
+    ///
+    /// This is only enforced by [`send`][ServerCodec::send],
+    /// [`send_tokio`][ServerCodec::send_tokio], and the [`Encoder`][tokio_util::codec::Encoder] implementation.
+    pub max_len_write: Option<usize>,
+    /// How to handle received lines that are longer than the read limit.
+    pub overlong: OverlongPolicy,
+}
 
 impl ClientCodec {
-    /// Reads an owning server message from `read`.
+    /// Creates a new `ClientCodec` that uses the default length limits.
+    pub const fn new() -> Self {
+        ClientCodec { max_len_read: None, max_len_write: None, overlong: OverlongPolicy::Error }
+    }
+    /// Returns the maximum length of read messages, including the CRLF.
+    pub const fn read_limit(&self) -> usize {
+        match self.max_len_read {
+            Some(len) => len,
+            None => ServerMsg::MAX_LEN,
+        }
+    }
+    /// Returns the maximum length of written messages, including the CRLF.
+    pub const fn write_limit(&self) -> usize {
+        match self.max_len_write {
+            Some(len) => len,
+            None => ClientMsg::MAX_LEN,
+        }
+    }
+    /// Reads an owning server message from `read`, up to [`read_limit`][Self::read_limit] bytes long.
     /// This function may block.
     ///
     /// `buf` must either be empty or contain a partial message from
     /// a previous call to this function that errored due to
     /// non-blocking I/O or unexpected EOF.
     /// Other errors may leave `buf` in an invalid state for future calls.
-    pub fn read_owning_from<'a>(
+    pub fn read_owning<'a>(
+        &self,
         read: &mut (impl std::io::BufRead + ?Sized),
         buf: &mut Vec<u8>,
     ) -> std::io::Result<ServerMsg<'a>> {
         use std::io::{BufRead, Read};
         read_msg!(
-            ServerMsg::MAX_LEN,
+            self.read_limit(),
+            self.overlong,
             buf,
             read: Read,
             read.read_until(b'\n', buf),
+            skip_rest(read.get_mut()),
             ServerMsg::parse(std::mem::take(buf))
         )
     }
-    /// Asynchronously reads an owning server message from `read`.
+    /// Asynchronously reads an owning server message from `read`,
+    /// up to [`read_limit`][Self::read_limit] bytes long.
     ///
-    /// `buf` must either be empty or contain a partial message from
-    /// a previous call to this function that errored due to
-    /// non-blocking I/O or unexpected EOF.
-    /// Other errors may leave `buf` in an invalid state for future calls.
+    /// See [`read_owning`][Self::read_owning] for the requirements on `buf`.
     #[cfg(feature = "tokio")]
-    pub async fn read_owning_from_tokio<'a>(
+    pub async fn read_owning_tokio<'a>(
+        &self,
         read: &mut (impl tokio::io::AsyncBufReadExt + ?Sized + Unpin),
         buf: &mut Vec<u8>,
     ) -> std::io::Result<ServerMsg<'a>> {
         use tokio::io::{AsyncBufReadExt, AsyncReadExt};
         read_msg!(
-            ServerMsg::MAX_LEN,
+            self.read_limit(),
+            self.overlong,
             buf,
             read: AsyncReadExt,
             read.read_until(b'\n', buf).await,
+            skip_rest_tokio(read.get_mut()).await,
             ServerMsg::parse(std::mem::take(buf))
         )
     }
+    /// Reads a server message from `read`, up to [`read_limit`][Self::read_limit] bytes long.
+    /// This function may block.
+    ///
+    /// See [`read_owning`][Self::read_owning] for the requirements on `buf`.
+    pub fn read_borrowing<'a>(
+        &self,
+        read: &mut (impl std::io::BufRead + ?Sized),
+        buf: &'a mut Vec<u8>,
+    ) -> std::io::Result<ServerMsg<'a>> {
+        use std::io::{BufRead, Read};
+        read_msg!(
+            self.read_limit(),
+            self.overlong,
+            buf,
+            read: Read,
+            read.read_until(b'\n', buf),
+            skip_rest(read.get_mut()),
+            ServerMsg::parse(buf.as_slice())
+        )
+    }
+    /// Asynchronously reads a server message from `read`,
+    /// up to [`read_limit`][Self::read_limit] bytes long.
+    ///
+    /// See [`read_owning`][Self::read_owning] for the requirements on `buf`.
+    #[cfg(feature = "tokio")]
+    pub async fn read_borrowing_tokio<'a>(
+        &self,
+        read: &mut (impl tokio::io::AsyncBufReadExt + ?Sized + Unpin),
+        buf: &'a mut Vec<u8>,
+    ) -> std::io::Result<ServerMsg<'a>> {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+        read_msg!(
+            self.read_limit(),
+            self.overlong,
+            buf,
+            read: AsyncReadExt,
+            read.read_until(b'\n', buf).await,
+            skip_rest_tokio(read.get_mut()).await,
+            ServerMsg::parse(buf.as_slice())
+        )
+    }
+    /// Writes a client message to `write` WITH a trailing CRLF,
+    /// using the provided buffer to minimize the necessary number of writes to `write`.
+    ///
+    /// If the message is longer than [`write_limit`][Self::write_limit] including the CRLF,
+    /// nothing is written, `buf` is left as it was before this call,
+    /// and the returned error wraps an [`EncodeTooLong`].
+    /// Otherwise, behaves as [`send_to`][Self::send_to].
+    pub fn send(
+        &self,
+        msg: &ClientMsg<'_>,
+        write: &mut (impl Write + ?Sized),
+        buf: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        write_limited(buf, self.write_limit(), |buf| Self::write_to(msg, buf))?;
+        write.write_all(buf)?;
+        buf.clear();
+        Ok(())
+    }
+    /// Asynchronously writes a client message to `write` WITH a trailing CRLF,
+    /// using the provided buffer to minimize the necessary number of writes to `write`.
+    ///
+    /// See [`send`][Self::send] for how the message's length is checked.
+    #[cfg(feature = "tokio")]
+    pub async fn send_tokio(
+        &self,
+        msg: &ClientMsg<'_>,
+        write: &mut (impl tokio::io::AsyncWriteExt + ?Sized + Unpin),
+        buf: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        write_limited(buf, self.write_limit(), |buf| Self::write_to(msg, buf))?;
+        write.write_all(buf).await?;
+        buf.clear();
+        Ok(())
+    }
+    /// Reads an owning server message from `read`.
+    /// This function may block.
+    ///
+    /// `buf` must either be empty or contain a partial message from
+    /// a previous call to this function that errored due to
+    /// non-blocking I/O or unexpected EOF.
+    /// Other errors may leave `buf` in an invalid state for future calls.
+    pub fn read_owning_from<'a>(
+        read: &mut (impl std::io::BufRead + ?Sized),
+        buf: &mut Vec<u8>,
+    ) -> std::io::Result<ServerMsg<'a>> {
+        Self::new().read_owning(read, buf)
+    }
+    /// Asynchronously reads an owning server message from `read`.
+    ///
+    /// `buf` must either be empty or contain a partial message from
+    /// a previous call to this function that errored due to
+    /// non-blocking I/O or unexpected EOF.
+    /// Other errors may leave `buf` in an invalid state for future calls.
+    #[cfg(feature = "tokio")]
+    pub async fn read_owning_from_tokio<'a>(
+        read: &mut (impl tokio::io::AsyncBufReadExt + ?Sized + Unpin),
+        buf: &mut Vec<u8>,
+    ) -> std::io::Result<ServerMsg<'a>> {
+        Self::new().read_owning_tokio(read, buf).await
+    }
     /// Reads a server message from `read`.
     /// This function may block.
     ///
@@ -217,14 +510,7 @@ impl ClientCodec {
         read: &mut (impl std::io::BufRead + ?Sized),
         buf: &'a mut Vec<u8>,
     ) -> std::io::Result<ServerMsg<'a>> {
-        use std::io::{BufRead, Read};
-        read_msg!(
-            ServerMsg::MAX_LEN,
-            buf,
-            read: Read,
-            read.read_until(b'\n', buf),
-            ServerMsg::parse(buf.as_slice())
-        )
+        Self::new().read_borrowing(read, buf)
     }
     /// Asynchronously reads a server message from `read`.
     ///
@@ -240,14 +526,7 @@ impl ClientCodec {
         read: &mut (impl tokio::io::AsyncBufReadExt + ?Sized + Unpin),
         buf: &'a mut Vec<u8>,
     ) -> std::io::Result<ServerMsg<'a>> {
-        use tokio::io::{AsyncBufReadExt, AsyncReadExt};
-        read_msg!(
-            ServerMsg::MAX_LEN,
-            buf,
-            read: AsyncReadExt,
-            read.read_until(b'\n', buf).await,
-            ServerMsg::parse(buf.as_slice())
-        )
+        Self::new().read_borrowing_tokio(read, buf).await
     }
     /// Writes a client message to the provided [`Write`] WITHOUT a trailing CRLF.
     ///
@@ -288,49 +567,207 @@ impl ClientCodec {
         buf.clear();
         Ok(())
     }
+    /// Like [`send_to`][ClientCodec::send_to], but validates the message before sending it.
+    ///
+    /// The serialized message must fit within [`ClientMsg::MAX_LEN`] including the CRLF,
+    /// and must not contain any CR, LF, or NUL bytes,
+    /// which can only happen if a string type's invariants have been broken,
+    /// such as by misuse of `from_unchecked`.
+    /// If either check fails, nothing is written to `write`,
+    /// `buf` is left as it was before this call,
+    /// and the returned error wraps either [`ParseError::TooLong`] or
+    /// [`ParseError::InvalidLine`].
+    pub fn send_to_checked(
+        msg: &ClientMsg<'_>,
+        write: &mut (impl Write + ?Sized),
+        buf: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        write_checked(buf, ClientMsg::MAX_LEN, |buf| Self::write_to(msg, buf))?;
+        write.write_all(buf)?;
+        buf.clear();
+        Ok(())
+    }
+    /// Like [`send_to_tokio`][ClientCodec::send_to_tokio],
+    /// but validates the message before sending it.
+    ///
+    /// See [`send_to_checked`][ClientCodec::send_to_checked] for what is checked.
+    #[cfg(feature = "tokio")]
+    pub async fn send_to_tokio_checked(
+        msg: &ClientMsg<'_>,
+        write: &mut (impl tokio::io::AsyncWriteExt + ?Sized + Unpin),
+        buf: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        write_checked(buf, ClientMsg::MAX_LEN, |buf| Self::write_to(msg, buf))?;
+        write.write_all(buf).await?;
+        buf.clear();
+        Ok(())
+    }
 }
 
 impl ServerCodec {
-    /// Reads an owning client message from `read`.
+    /// Creates a new `ServerCodec` that uses the default length limits.
+    pub const fn new() -> Self {
+        ServerCodec { max_len_read: None, max_len_write: None, overlong: OverlongPolicy::Error }
+    }
+    /// Returns the maximum length of read messages, including the CRLF.
+    pub const fn read_limit(&self) -> usize {
+        match self.max_len_read {
+            Some(len) => len,
+            None => ClientMsg::MAX_LEN,
+        }
+    }
+    /// Returns the maximum length of written messages, including the CRLF.
+    pub const fn write_limit(&self) -> usize {
+        match self.max_len_write {
+            Some(len) => len,
+            None => ServerMsg::MAX_LEN,
+        }
+    }
+    /// Reads an owning client message from `read`, up to [`read_limit`][Self::read_limit] bytes long.
     /// This function may block.
     ///
     /// `buf` must either be empty or contain a partial message from
     /// a previous call to this function that errored due to
     /// non-blocking I/O or unexpected EOF.
     /// Other errors may leave `buf` in an invalid state for future calls.
-    pub fn read_owning_from<'a>(
+    pub fn read_owning<'a>(
+        &self,
         read: &mut (impl std::io::BufRead + ?Sized),
         buf: &mut Vec<u8>,
     ) -> std::io::Result<ClientMsg<'a>> {
         use std::io::{BufRead, Read};
         read_msg!(
-            ClientMsg::MAX_LEN,
+            self.read_limit(),
+            self.overlong,
             buf,
             read: Read,
             read.read_until(b'\n', buf),
+            skip_rest(read.get_mut()),
             ClientMsg::parse(std::mem::take(buf))
         )
     }
-    /// Asynchronously reads an owning client message from `read`.
+    /// Asynchronously reads an owning client message from `read`,
+    /// up to [`read_limit`][Self::read_limit] bytes long.
     ///
-    /// `buf` must either be empty or contain a partial message from
-    /// a previous call to this function that errored due to
-    /// non-blocking I/O or unexpected EOF.
-    /// Other errors may leave `buf` in an invalid state for future calls.
+    /// See [`read_owning`][Self::read_owning] for the requirements on `buf`.
     #[cfg(feature = "tokio")]
-    pub async fn read_owning_from_tokio<'a>(
+    pub async fn read_owning_tokio<'a>(
+        &self,
         read: &mut (impl tokio::io::AsyncBufReadExt + ?Sized + Unpin),
         buf: &mut Vec<u8>,
     ) -> std::io::Result<ClientMsg<'a>> {
         use tokio::io::{AsyncBufReadExt, AsyncReadExt};
         read_msg!(
-            ClientMsg::MAX_LEN,
+            self.read_limit(),
+            self.overlong,
             buf,
             read: AsyncReadExt,
             read.read_until(b'\n', buf).await,
+            skip_rest_tokio(read.get_mut()).await,
             ClientMsg::parse(std::mem::take(buf))
         )
     }
+    /// Reads a client message from `read`, up to [`read_limit`][Self::read_limit] bytes long.
+    /// This function may block.
+    ///
+    /// See [`read_owning`][Self::read_owning] for the requirements on `buf`.
+    pub fn read_borrowing<'a>(
+        &self,
+        read: &mut (impl std::io::BufRead + ?Sized),
+        buf: &'a mut Vec<u8>,
+    ) -> std::io::Result<ClientMsg<'a>> {
+        use std::io::{BufRead, Read};
+        read_msg!(
+            self.read_limit(),
+            self.overlong,
+            buf,
+            read: Read,
+            read.read_until(b'\n', buf),
+            skip_rest(read.get_mut()),
+            ClientMsg::parse(buf.as_slice())
+        )
+    }
+    /// Asynchronously reads a client message from `read`,
+    /// up to [`read_limit`][Self::read_limit] bytes long.
+    ///
+    /// See [`read_owning`][Self::read_owning] for the requirements on `buf`.
+    #[cfg(feature = "tokio")]
+    pub async fn read_borrowing_tokio<'a>(
+        &self,
+        read: &mut (impl tokio::io::AsyncBufReadExt + ?Sized + Unpin),
+        buf: &'a mut Vec<u8>,
+    ) -> std::io::Result<ClientMsg<'a>> {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+        read_msg!(
+            self.read_limit(),
+            self.overlong,
+            buf,
+            read: AsyncReadExt,
+            read.read_until(b'\n', buf).await,
+            skip_rest_tokio(read.get_mut()).await,
+            ClientMsg::parse(buf.as_slice())
+        )
+    }
+    /// Writes a server message to `write` WITH a trailing CRLF,
+    /// using the provided buffer to minimize the necessary number of writes to `write`.
+    ///
+    /// If the message is longer than [`write_limit`][Self::write_limit] including the CRLF,
+    /// nothing is written, `buf` is left as it was before this call,
+    /// and the returned error wraps an [`EncodeTooLong`].
+    /// Otherwise, behaves as [`send_to`][Self::send_to].
+    pub fn send(
+        &self,
+        msg: &ServerMsg<'_>,
+        write: &mut (impl Write + ?Sized),
+        buf: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        write_limited(buf, self.write_limit(), |buf| Self::write_to(msg, buf))?;
+        write.write_all(buf)?;
+        buf.clear();
+        Ok(())
+    }
+    /// Asynchronously writes a server message to `write` WITH a trailing CRLF,
+    /// using the provided buffer to minimize the necessary number of writes to `write`.
+    ///
+    /// See [`send`][Self::send] for how the message's length is checked.
+    #[cfg(feature = "tokio")]
+    pub async fn send_tokio(
+        &self,
+        msg: &ServerMsg<'_>,
+        write: &mut (impl tokio::io::AsyncWriteExt + ?Sized + Unpin),
+        buf: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        write_limited(buf, self.write_limit(), |buf| Self::write_to(msg, buf))?;
+        write.write_all(buf).await?;
+        buf.clear();
+        Ok(())
+    }
+    /// Reads an owning client message from `read`.
+    /// This function may block.
+    ///
+    /// `buf` must either be empty or contain a partial message from
+    /// a previous call to this function that errored due to
+    /// non-blocking I/O or unexpected EOF.
+    /// Other errors may leave `buf` in an invalid state for future calls.
+    pub fn read_owning_from<'a>(
+        read: &mut (impl std::io::BufRead + ?Sized),
+        buf: &mut Vec<u8>,
+    ) -> std::io::Result<ClientMsg<'a>> {
+        Self::new().read_owning(read, buf)
+    }
+    /// Asynchronously reads an owning client message from `read`.
+    ///
+    /// `buf` must either be empty or contain a partial message from
+    /// a previous call to this function that errored due to
+    /// non-blocking I/O or unexpected EOF.
+    /// Other errors may leave `buf` in an invalid state for future calls.
+    #[cfg(feature = "tokio")]
+    pub async fn read_owning_from_tokio<'a>(
+        read: &mut (impl tokio::io::AsyncBufReadExt + ?Sized + Unpin),
+        buf: &mut Vec<u8>,
+    ) -> std::io::Result<ClientMsg<'a>> {
+        Self::new().read_owning_tokio(read, buf).await
+    }
     /// Reads a client message from `read`.
     /// This function may block.
     ///
@@ -345,14 +782,7 @@ impl ServerCodec {
         read: &mut (impl std::io::BufRead + ?Sized),
         buf: &'a mut Vec<u8>,
     ) -> std::io::Result<ClientMsg<'a>> {
-        use std::io::{BufRead, Read};
-        read_msg!(
-            ClientMsg::MAX_LEN,
-            buf,
-            read: Read,
-            read.read_until(b'\n', buf),
-            ClientMsg::parse(buf.as_slice())
-        )
+        Self::new().read_borrowing(read, buf)
     }
     /// Asynchronously reads a client message from `read`.
     ///
@@ -368,14 +798,7 @@ impl ServerCodec {
         read: &mut (impl tokio::io::AsyncBufReadExt + ?Sized + Unpin),
         buf: &'a mut Vec<u8>,
     ) -> std::io::Result<ClientMsg<'a>> {
-        use tokio::io::{AsyncBufReadExt, AsyncReadExt};
-        read_msg!(
-            ClientMsg::MAX_LEN,
-            buf,
-            read: AsyncReadExt,
-            read.read_until(b'\n', buf).await,
-            ClientMsg::parse(buf.as_slice())
-        )
+        Self::new().read_borrowing_tokio(read, buf).await
     }
     /// Writes a server message to the provided [`Write`] WITHOUT a trailing CRLF.
     ///
@@ -420,8 +843,9 @@ impl ServerCodec {
 
 #[cfg(feature = "tokio-codec")]
 pub(super) mod tokio_codec {
-    use super::{ClientCodec, ServerCodec};
+    use super::{ClientCodec, OverlongPolicy, ServerCodec};
     use crate::{
+        error::{EncodeTooLong, ParseError},
         ircmsg::{ClientMsg, ServerMsg},
         string::Line,
     };
@@ -435,21 +859,34 @@ pub(super) mod tokio_codec {
         type Error = std::io::Error;
 
         fn encode(&mut self, item: ClientMsg<'_>, dst: &mut BytesMut) -> Result<(), Self::Error> {
-            Self::write_to(&item, &mut dst.writer())?;
-            dst.extend_from_slice(b"\r\n");
-            Ok(())
+            encode_limited(dst, self.write_limit(), |dst| Self::write_to(&item, &mut dst.writer()))
         }
     }
     impl Encoder<ServerMsg<'_>> for ServerCodec {
         type Error = std::io::Error;
 
         fn encode(&mut self, item: ServerMsg<'_>, dst: &mut BytesMut) -> Result<(), Self::Error> {
-            Self::write_to(&item, &mut dst.writer())?;
-            dst.extend_from_slice(b"\r\n");
-            Ok(())
+            encode_limited(dst, self.write_limit(), |dst| Self::write_to(&item, &mut dst.writer()))
         }
     }
 
+    /// As [`write_limited`][super::write_limited], but for [`BytesMut`].
+    fn encode_limited(
+        dst: &mut BytesMut,
+        max_len: usize,
+        write_fn: impl FnOnce(&mut BytesMut) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        let start = dst.len();
+        write_fn(dst)?;
+        let len = dst.len() - start + 2;
+        if len > max_len {
+            dst.truncate(start);
+            return Err(EncodeTooLong { len, max_len }.into());
+        }
+        dst.extend_from_slice(b"\r\n");
+        Ok(())
+    }
+
     pub fn scroll_buf(buf: &mut BytesMut, limit: usize) -> Option<NonZeroUsize> {
         let mut leading_ws = true;
         let mut trimmed_ws = 0usize;
@@ -472,16 +909,65 @@ pub(super) mod tokio_codec {
         None
     }
 
+    /// Splits the next line without its trailing newline off of `src`,
+    /// handling lines longer than `limit` according to `overlong`.
+    ///
+    /// Returns `None` if more input is needed.
+    fn next_line(
+        src: &mut BytesMut,
+        limit: usize,
+        overlong: OverlongPolicy,
+    ) -> std::io::Result<Option<BytesMut>> {
+        loop {
+            let Some(split_at) = scroll_buf(src, limit) else {
+                src.reserve(limit.saturating_sub(src.len()));
+                return Ok(None);
+            };
+            let split_at = split_at.get();
+            if split_at < limit || matches!(src[split_at - 1], b'\n' | b'\0') {
+                let mut line = src.split_to(split_at);
+                trim_newline(&mut line);
+                return Ok(Some(line));
+            }
+            if overlong == OverlongPolicy::Error {
+                src.advance(split_at);
+                return Err(ParseError::TooLong.into());
+            }
+            let Some(newline) = src[split_at..].iter().position(|b| *b == b'\n') else {
+                // Only the start of the line is needed once its end arrives.
+                src.truncate(split_at);
+                src.reserve(limit);
+                return Ok(None);
+            };
+            let mut line = src.split_to(split_at);
+            src.advance(newline + 1);
+            if overlong == OverlongPolicy::Truncate {
+                while line.last() == Some(&b'\r') {
+                    line.truncate(line.len() - 1);
+                }
+                return Ok(Some(line));
+            }
+        }
+    }
+
+    /// Removes the trailing newline and any carriage returns before it from `line`.
+    fn trim_newline(line: &mut BytesMut) {
+        if line.last() == Some(&b'\n') {
+            line.truncate(line.len() - 1);
+            while line.last() == Some(&b'\r') {
+                line.truncate(line.len() - 1);
+            }
+        }
+    }
+
     impl Decoder for ClientCodec {
         type Item = ServerMsg<'static>;
         type Error = std::io::Error;
 
         fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-            let Some(split_at) = scroll_buf(src, ServerMsg::MAX_LEN) else {
-                src.reserve(ServerMsg::MAX_LEN.saturating_sub(src.len()));
+            let Some(line_raw) = next_line(src, self.read_limit(), self.overlong)? else {
                 return Ok(None);
             };
-            let line_raw = src.split_to(split_at.get());
             let line = Line::from_bytes(line_raw.as_ref())?;
             Ok(Some(ServerMsg::parse(line.owning())?))
         }
@@ -491,11 +977,9 @@ pub(super) mod tokio_codec {
         type Error = std::io::Error;
 
         fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-            let Some(split_at) = scroll_buf(src, ClientMsg::MAX_LEN) else {
-                src.reserve(ClientMsg::MAX_LEN.saturating_sub(src.len()));
+            let Some(line_raw) = next_line(src, self.read_limit(), self.overlong)? else {
                 return Ok(None);
             };
-            let line_raw = src.split_to(split_at.get());
             let line = Line::from_bytes(line_raw.as_ref())?;
             Ok(Some(ClientMsg::parse(line.owning())?))
         }
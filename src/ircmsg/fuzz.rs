@@ -0,0 +1,100 @@
+//! Round-trip checks for fuzzing message parsing.
+//!
+//! Each function panics if the property it checks does not hold,
+//! making them usable as the bodies of fuzz targets or property tests.
+//! The `parse_*` functions accept arbitrary bytes and check that parsing never panics
+//! and that anything that parses is unchanged by writing it and parsing it again.
+//!
+//! Arbitrary messages for the `*_roundtrip` functions can be generated using the
+//! [`Arbitrary`][::arbitrary::Arbitrary] impls on [`ServerMsg`] and [`ClientMsg`].
+//! The `fuzz` directory of this crate's repository contains `cargo fuzz` targets using these.
+
+use super::{Args, ClientCodec, ClientMsg, ServerCodec, ServerMsg, Tags};
+use crate::string::{Line, Word};
+
+/// Writes `args` the way they are written as part of a message.
+fn write_args(args: &Args<'_>, buf: &mut Vec<u8>) {
+    let (words, last) = args.split_last();
+    for (idx, word) in words.iter().enumerate() {
+        if idx != 0 {
+            buf.push(b' ');
+        }
+        buf.extend_from_slice(word);
+    }
+    if let Some(last) = last {
+        if !words.is_empty() {
+            buf.push(b' ');
+        }
+        if args.is_last_long() {
+            buf.push(b':');
+        }
+        buf.extend_from_slice(last);
+    }
+}
+
+/// Checks that writing `msg` and parsing the result yields `msg`.
+pub fn server_msg_roundtrip(msg: &ServerMsg<'_>) {
+    let mut buf = Vec::new();
+    ServerCodec::write_to(msg, &mut buf).unwrap();
+    match ServerMsg::parse(buf.as_slice()) {
+        Ok(parsed) => {
+            let (parsed, msg) = (parsed.owning(), msg.clone().owning());
+            assert_eq!(parsed, msg, "`{}` did not round-trip", buf.escape_ascii());
+        }
+        Err(e) => panic!("`{}` failed to parse: {e}", buf.escape_ascii()),
+    }
+}
+
+/// Checks that writing `msg` and parsing the result yields `msg`.
+pub fn client_msg_roundtrip(msg: &ClientMsg<'_>) {
+    let mut buf = Vec::new();
+    ClientCodec::write_to(msg, &mut buf).unwrap();
+    match ClientMsg::parse(buf.as_slice()) {
+        Ok(parsed) => {
+            let (parsed, msg) = (parsed.owning(), msg.clone().owning());
+            assert_eq!(parsed, msg, "`{}` did not round-trip", buf.escape_ascii());
+        }
+        Err(e) => panic!("`{}` failed to parse: {e}", buf.escape_ascii()),
+    }
+}
+
+/// Parses `bytes` as a [`ServerMsg`] and, if successful, checks that it round-trips.
+pub fn parse_server_msg(bytes: &[u8]) {
+    if let Ok(msg) = ServerMsg::parse(bytes) {
+        server_msg_roundtrip(&msg);
+    }
+}
+
+/// Parses `bytes` as a [`ClientMsg`] and, if successful, checks that it round-trips.
+pub fn parse_client_msg(bytes: &[u8]) {
+    if let Ok(msg) = ClientMsg::parse(bytes) {
+        client_msg_roundtrip(&msg);
+    }
+}
+
+/// Parses `bytes` as [`Tags`] if they are a valid [`Word`],
+/// then checks that writing and re-parsing them yields the same tags.
+pub fn parse_tags(bytes: &[u8]) {
+    let Ok(word) = Word::from_bytes(bytes) else {
+        return;
+    };
+    let tags = Tags::parse(word);
+    let mut buf = Vec::new();
+    tags.write_to(&mut buf).unwrap();
+    // Strip the leading '@'.
+    let word = Word::from_bytes(buf.get(1..).unwrap_or_default()).unwrap();
+    assert_eq!(Tags::parse(word), tags, "`{}` did not round-trip", buf.escape_ascii());
+}
+
+/// Parses `bytes` as [`Args`] if they are a valid [`Line`],
+/// then checks that writing and re-parsing them yields the same arguments.
+pub fn parse_args(bytes: &[u8]) {
+    let Ok(line) = Line::from_bytes(bytes) else {
+        return;
+    };
+    let args = Args::parse(line);
+    let mut buf = Vec::new();
+    write_args(&args, &mut buf);
+    let line = Line::from_bytes(buf.as_slice()).unwrap();
+    assert_eq!(Args::parse(line), args, "`{}` did not round-trip", buf.escape_ascii());
+}
@@ -0,0 +1,168 @@
+use super::{ServerMsg, Source};
+use crate::{
+    error::ParseError,
+    state::{Mode, ModeSet, ServerChanModes, StatusModes},
+    string::{Arg, Word},
+};
+use std::num::NonZeroU8;
+
+/// The visibility of a channel, as indicated in `RPL_NAMREPLY` (353).
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum ChannelVisibility {
+    /// `=`: A public channel.
+    #[default]
+    Public,
+    /// `*`: A private channel.
+    Private,
+    /// `@`: A secret channel.
+    Secret,
+}
+
+impl ChannelVisibility {
+    /// Returns the visibility indicated by the provided symbol.
+    pub const fn from_symbol(symbol: u8) -> Option<Self> {
+        match symbol {
+            b'=' => Some(ChannelVisibility::Public),
+            b'*' => Some(ChannelVisibility::Private),
+            b'@' => Some(ChannelVisibility::Secret),
+            _ => None,
+        }
+    }
+    /// Returns the symbol that indicates this visibility.
+    pub const fn symbol(self) -> u8 {
+        match self {
+            ChannelVisibility::Public => b'=',
+            ChannelVisibility::Private => b'*',
+            ChannelVisibility::Secret => b'@',
+        }
+    }
+}
+
+/// One member of a channel from `RPL_NAMREPLY` (353).
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct NamesMember<'a> {
+    /// The member's status modes along with their prefixes, in the order they were sent.
+    ///
+    /// Servers only send more than one of these if `multi-prefix` is enabled.
+    pub status: Vec<(Mode, NonZeroU8)>,
+    /// The member's source.
+    ///
+    /// This only has a username and hostname if `userhost-in-names` is enabled.
+    pub source: Source<'a>,
+    /// Whether the member's nick starts with what appears to be a status prefix
+    /// that is not in the server's `PREFIX` ISUPPORT token.
+    ///
+    /// Such prefixes are left as part of the nick.
+    pub unknown_prefix: bool,
+}
+
+impl<'a> NamesMember<'a> {
+    /// Parses one space-delimited token of the list of names in `RPL_NAMREPLY`.
+    pub fn parse(word: Word<'a>, status: &StatusModes) -> Result<Self, ParseError> {
+        let mut modes = Vec::new();
+        let mut idx = 0usize;
+        while let Some(prefix) = word.get(idx).copied().and_then(NonZeroU8::new) {
+            let Some(mode) = status.get_mode(prefix) else {
+                break;
+            };
+            modes.push((mode, prefix));
+            idx += 1;
+        }
+        let unknown_prefix = word.get(idx).copied().is_some_and(is_unknown_prefix);
+        let name = Word::from_bytes(word.slice(idx..)).map_err(ParseError::InvalidNick)?;
+        let source = Source::parse(name)?;
+        Ok(NamesMember { status: modes, source, unknown_prefix })
+    }
+    /// Returns the member's status modes as a [`ModeSet`].
+    pub fn modes(&self) -> ModeSet {
+        let mut set = ModeSet::new();
+        for (mode, _) in &self.status {
+            set.set(*mode);
+        }
+        set
+    }
+    /// Returns an owning version of this member.
+    pub fn owning(self) -> NamesMember<'static> {
+        let NamesMember { status, source, unknown_prefix } = self;
+        NamesMember { status, source: source.owning(), unknown_prefix }
+    }
+}
+
+impl std::fmt::Display for NamesMember<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (_, prefix) in &self.status {
+            write!(f, "{}", prefix.get() as char)?;
+        }
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Returns `true` if `byte` looks like a status prefix rather than the start of a nick.
+fn is_unknown_prefix(byte: u8) -> bool {
+    byte.is_ascii_punctuation() && !b"[]\\`_^{|}".contains(&byte)
+}
+
+/// A parsed `RPL_NAMREPLY` (353).
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct NamesReply<'a> {
+    /// The name of the channel.
+    pub channel: Arg<'a>,
+    /// The visibility of the channel.
+    pub visibility: ChannelVisibility,
+    /// The members of the channel that were included in this reply.
+    ///
+    /// Channels with many members usually span multiple replies.
+    pub members: Vec<NamesMember<'a>>,
+}
+
+impl<'a> NamesReply<'a> {
+    /// Parses a `RPL_NAMREPLY` using the status modes in `chanmodes`.
+    ///
+    /// Status prefixes are parsed regardless of whether `multi-prefix` is enabled,
+    /// and members are parsed as full sources regardless of whether
+    /// `userhost-in-names` is enabled.
+    pub fn parse(msg: &ServerMsg<'a>, chanmodes: &ServerChanModes) -> Result<Self, ParseError> {
+        if msg.kind.as_str() != "353" {
+            return Err(ParseError::InvalidField("kind".into(), "not RPL_NAMREPLY (353)".into()));
+        }
+        let (args, names) = msg.args.split_last();
+        let Some(symbol) = args.get(1) else {
+            return Err(ParseError::MissingField("visibility".into()));
+        };
+        let visibility = match symbol.as_bytes() {
+            [symbol] => ChannelVisibility::from_symbol(*symbol),
+            _ => None,
+        };
+        let Some(visibility) = visibility else {
+            return Err(ParseError::InvalidField(
+                "visibility".into(),
+                format!("unknown symbol `{symbol}`").into(),
+            ));
+        };
+        let Some(channel) = args.get(2).cloned() else {
+            return Err(ParseError::MissingField("channel".into()));
+        };
+        let mut members = Vec::new();
+        if let Some(names) = names {
+            let mut start = 0usize;
+            for token in names.split(|b| *b == b' ') {
+                let end = start + token.len();
+                if !token.is_empty() {
+                    let word = Word::from_bytes(names.slice(start..end))
+                        .map_err(ParseError::InvalidNick)?;
+                    members.push(NamesMember::parse(word, chanmodes.status())?);
+                }
+                start = end + 1;
+            }
+        }
+        Ok(NamesReply { channel, visibility, members })
+    }
+    /// Returns an owning version of this reply.
+    pub fn owning(self) -> NamesReply<'static> {
+        NamesReply {
+            channel: self.channel.owning(),
+            visibility: self.visibility,
+            members: self.members.into_iter().map(NamesMember::owning).collect(),
+        }
+    }
+}
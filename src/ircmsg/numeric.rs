@@ -1,7 +1,35 @@
+use crate::string::Cmd;
 use std::{io::Write, num::NonZeroU8};
 
 // Don't repr(transparent) Numeric.
 
+/// A broad classification of what a reply is about.
+///
+/// Returned by [`Numeric::category`] and
+/// [`ServerMsgKindRaw::category`][super::ServerMsgKindRaw::category].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub enum ReplyCategory {
+    /// Replies that start connection registration (001-004).
+    Welcome,
+    /// `RPL_ISUPPORT` (005).
+    Isupport,
+    /// Message of the day replies, including `ERR_NOMOTD` (422).
+    Motd,
+    /// Replies to `WHOIS` and `WHOWAS`.
+    Whois,
+    /// Replies about channels, such as topics, member lists, and mode lists.
+    Channel,
+    /// `MONITOR` replies (730-734).
+    Monitor,
+    /// SASL and account replies (900-908), including SASL errors.
+    Sasl,
+    /// Any other error.
+    Error,
+    /// Anything else.
+    Unknown,
+}
+
 /// A three-digit numeric reply code.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "serde", derive(serde_derive::Serialize, serde_derive::Deserialize))]
@@ -87,7 +115,7 @@ impl Numeric {
     /// The three bytes must be ASCII digits,
     /// or else undefined behavior may result from calling other functions on this type.
     pub const unsafe fn from_bytes_unchecked(bytes: [u8; 3]) -> Numeric {
-        Numeric(std::mem::transmute::<[u8; 3], [std::num::NonZero<u8>; 3]>(bytes))
+        Numeric(std::mem::transmute::<[u8; 3], [NonZeroU8; 3]>(bytes))
     }
     /// Attempts to convert the provided integer into a `Numeric`.
     /// Returns `None` if the integer is not less than `1000`.
@@ -130,6 +158,54 @@ impl Numeric {
     pub fn write_to(&self, write: &mut (impl Write + ?Sized)) -> std::io::Result<()> {
         write.write_all(self.as_bytes())
     }
+    /// Returns what kind of reply `self` is.
+    ///
+    /// More specific categories take precedence over [`ReplyCategory::Error`],
+    /// e.g. `ERR_SASLFAIL` (904) is categorized as [`ReplyCategory::Sasl`].
+    /// Errors are determined using [`is_error`][Numeric::is_error].
+    pub const fn category(&self) -> ReplyCategory {
+        match self.into_int() {
+            1..=4 => ReplyCategory::Welcome,
+            5 => ReplyCategory::Isupport,
+            372 | 375 | 376 | 422 => ReplyCategory::Motd,
+            276 | 307 | 311..=314 | 317..=320 | 330 | 338 | 369 | 378 | 379 | 671 => {
+                ReplyCategory::Whois
+            }
+            321..=324 | 329 | 331..=333 | 341 | 346..=349 | 353 | 366..=368 => {
+                ReplyCategory::Channel
+            }
+            730..=734 => ReplyCategory::Monitor,
+            900..=908 => ReplyCategory::Sasl,
+            _ => match self.is_error() {
+                Some(true) => ReplyCategory::Error,
+                _ => ReplyCategory::Unknown,
+            },
+        }
+    }
+    /// Returns `true` if `self` is the last reply the server sends in response to `cmd`.
+    ///
+    /// Only replies that end a successful response are considered,
+    /// such as `RPL_ENDOFWHOIS` (318) for `WHOIS` or `RPL_ENDOFNAMES` (366) for `NAMES`.
+    /// Errors may or may not be followed by further replies depending on the command.
+    pub fn is_final_for(&self, cmd: &Cmd<'_>) -> bool {
+        let num = self.into_int();
+        match cmd.as_bytes() {
+            b"INFO" => num == 374,
+            b"JOIN" | b"NAMES" => num == 366,
+            b"HELP" => num == 706,
+            b"LINKS" => num == 365,
+            b"LIST" => num == 323,
+            b"MONITOR" => num == 733,
+            b"MOTD" => num == 376 || num == 422,
+            b"STATS" => num == 219,
+            b"TIME" => num == 391,
+            b"USERHOST" => num == 302,
+            b"WHO" => num == 315,
+            b"WHOIS" => num == 318,
+            b"WHOWAS" => num == 369,
+            _ => false,
+        }
+    }
     /// Returns `Some(true)` if `self` represents an error,
     /// `Some(false)` if it does not, or `None` if it's unknown.
     ///
@@ -0,0 +1,183 @@
+use super::{Args, ClientMsg, ServerMsg};
+use crate::string::{Arg, Cmd, Line, NoNul, DISPLAY_PLACEHOLDER};
+use std::sync::RwLock;
+
+/// Commands whose arguments carry credentials,
+/// paired with the index of the first argument to redact.
+///
+/// Additional commands can be registered using [`redact_cmd`].
+/// `PRIVMSG`s to `NickServ` starting with `IDENTIFY` are also redacted.
+pub static REDACTED_CMDS: &[(&str, usize)] = &[("AUTHENTICATE", 0), ("OPER", 1), ("PASS", 0)];
+
+static EXTRA_REDACTED_CMDS: RwLock<Vec<(Cmd<'static>, usize)>> = RwLock::new(Vec::new());
+
+/// Registers a command whose arguments carry credentials,
+/// starting from the argument at index `from`.
+///
+/// This affects [`Redacted`] for the remainder of the program.
+/// Registering a command that is already registered replaces its index.
+pub fn redact_cmd(cmd: Cmd<'static>, from: usize) {
+    let Ok(mut extra) = EXTRA_REDACTED_CMDS.write() else {
+        return;
+    };
+    if let Some(entry) = extra.iter_mut().find(|(c, _)| *c == cmd) {
+        entry.1 = from;
+    } else {
+        extra.push((cmd, from));
+    }
+}
+
+/// Returns `true` if `args` are those of a `PRIVMSG` to `NickServ` that identifies with a password.
+fn is_nickserv_identify(args: &Args<'_>) -> bool {
+    let (Some(target), Some(text)) = (args.words().first(), args.iter().nth(1)) else {
+        return false;
+    };
+    let nick = target.split(|b| *b == b'@').next().unwrap_or_default();
+    let Some(command) = text.get(..8) else {
+        return false;
+    };
+    nick.eq_ignore_ascii_case(b"NickServ")
+        && command.eq_ignore_ascii_case(b"IDENTIFY")
+        && matches!(text.get(8), None | Some(b' '))
+}
+
+/// Returns the index of the first argument to redact for a message of kind `cmd`, if any.
+fn redact_from(cmd: &[u8], args: &Args<'_>) -> Option<usize> {
+    if cmd.eq_ignore_ascii_case(b"PRIVMSG") {
+        return is_nickserv_identify(args).then_some(1);
+    }
+    if let Some((_, from)) =
+        REDACTED_CMDS.iter().find(|(c, _)| cmd.eq_ignore_ascii_case(c.as_bytes()))
+    {
+        return Some(*from);
+    }
+    let extra = EXTRA_REDACTED_CMDS.read().ok()?;
+    extra.iter().find(|(c, _)| cmd.eq_ignore_ascii_case(c.as_bytes())).map(|(_, from)| *from)
+}
+
+fn fmt_args(
+    f: &mut std::fmt::Formatter<'_>,
+    args: &Args<'_>,
+    from: Option<usize>,
+) -> std::fmt::Result {
+    for (idx, arg) in args.iter().enumerate() {
+        f.write_str(" ")?;
+        if arg.is_long() {
+            f.write_str(":")?;
+        }
+        if from.is_some_and(|from| idx >= from) {
+            f.write_str(DISPLAY_PLACEHOLDER)?;
+        } else {
+            write!(f, "{arg}")?;
+        }
+    }
+    Ok(())
+}
+
+/// [`Display`][std::fmt::Display] wrapper for messages that redacts credentials.
+///
+/// Arguments of the commands in [`REDACTED_CMDS`] or registered using [`redact_cmd`]
+/// are replaced with [`DISPLAY_PLACEHOLDER`], even if they are not secret strings.
+/// Created by [`ClientMsg::redacted`] and [`ServerMsg::redacted`].
+pub struct Redacted<'a, T: ?Sized>(&'a T);
+
+impl std::fmt::Display for Redacted<'_, ClientMsg<'_>> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = self.0;
+        if !msg.tags.is_empty() {
+            write!(f, "{} ", msg.tags)?;
+        }
+        write!(f, "{}", msg.cmd)?;
+        fmt_args(f, &msg.args, redact_from(&msg.cmd, &msg.args))
+    }
+}
+
+impl std::fmt::Display for Redacted<'_, ServerMsg<'_>> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = self.0;
+        if !msg.tags.is_empty() {
+            write!(f, "{} ", msg.tags)?;
+        }
+        if let Some(ref src) = msg.source {
+            write!(f, ":{} ", src)?;
+        }
+        let kind = msg.kind.as_arg();
+        write!(f, "{kind}")?;
+        fmt_args(f, &msg.args, redact_from(&kind, &msg.args))
+    }
+}
+
+impl ClientMsg<'_> {
+    /// Returns a wrapper around `self` whose [`Display`][std::fmt::Display] impl
+    /// redacts credentials.
+    ///
+    /// See [`Redacted`].
+    pub fn redacted(&self) -> Redacted<'_, Self> {
+        Redacted(self)
+    }
+    /// Formats `self` as a string with credentials redacted.
+    ///
+    /// See [`Redacted`].
+    pub fn to_redacted_string(&self) -> String {
+        self.redacted().to_string()
+    }
+    /// Returns `true` if `self` contains credentials.
+    ///
+    /// This is the case if any tag value or argument is a secret string,
+    /// or if [`Redacted`] would redact any of its arguments.
+    pub fn has_secrets(&self) -> bool {
+        let from = redact_from(&self.cmd, &self.args);
+        from.is_some_and(|from| from < self.args.len())
+            || self.args.iter().any(|arg| arg.is_secret())
+            || self.tags.iter().any(|(_, value)| value.is_secret())
+    }
+    /// Replaces every secret tag value and argument of `self` with [`DISPLAY_PLACEHOLDER`],
+    /// along with every argument that [`Redacted`] would redact.
+    pub fn redact(&mut self) {
+        let keys: Vec<_> = self
+            .tags
+            .iter()
+            .filter(|(_, value)| value.is_secret())
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in keys {
+            if let Some(value) = self.tags.get_mut(key) {
+                *value = NoNul::from_str(DISPLAY_PLACEHOLDER);
+            }
+        }
+        let from = redact_from(&self.cmd, &self.args).unwrap_or(usize::MAX);
+        let words = self.args.words().iter().enumerate().map(|(idx, arg)| {
+            if arg.is_secret() || idx >= from {
+                Arg::from_str(DISPLAY_PLACEHOLDER)
+            } else {
+                arg.clone()
+            }
+        });
+        let words: Vec<_> = words.collect();
+        let long = self.args.is_last_long().then(|| self.args.split_last().1).flatten();
+        let long = long.map(|long| {
+            if long.is_secret() || words.len() >= from {
+                Line::from_str(DISPLAY_PLACEHOLDER)
+            } else {
+                long.clone()
+            }
+        });
+        self.args = Args::new(words, long);
+    }
+}
+
+impl ServerMsg<'_> {
+    /// Returns a wrapper around `self` whose [`Display`][std::fmt::Display] impl
+    /// redacts credentials.
+    ///
+    /// See [`Redacted`].
+    pub fn redacted(&self) -> Redacted<'_, Self> {
+        Redacted(self)
+    }
+    /// Formats `self` as a string with credentials redacted.
+    ///
+    /// See [`Redacted`].
+    pub fn to_redacted_string(&self) -> String {
+        self.redacted().to_string()
+    }
+}
@@ -1,10 +1,16 @@
-use super::{Args, Numeric, ServerMsgKindRaw, SharedSource, Source, Tags};
+use super::{Args, ClientMsg, Numeric, ServerMsgKindRaw, SharedSource, Source, Tags};
 use crate::{
-    error::{InvalidString, ParseError},
-    names::{Name, NameValued, ServerMsgKind},
-    string::{Cmd, Line, Nick},
+    error::{InvalidString, ParseError, ParseErrorAt},
+    names::{
+        tag::{ACCOUNT, MSGID, TIME},
+        MsgTag, Name, NameValued, ServerMsgKind,
+    },
+    string::{Arg, Cmd, Line, Nick, NoNul},
+};
+use std::{
+    io::Write,
+    time::{Duration, SystemTime},
 };
-use std::io::Write;
 
 /// An IRC message sent by a server.
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
@@ -70,6 +76,55 @@ impl<'a> ServerMsg<'a> {
         self.args.set(args, last);
         self
     }
+    /// Returns `true` if this message is a numeric reply.
+    pub const fn is_numeric(&self) -> bool {
+        matches!(self.kind, ServerMsgKindRaw::Numeric(_))
+    }
+    /// Returns this message's numeric reply code, if it is a numeric reply.
+    pub const fn numeric(&self) -> Option<Numeric> {
+        match self.kind {
+            ServerMsgKindRaw::Numeric(num) => Some(num),
+            ServerMsgKindRaw::Cmd(_) => None,
+        }
+    }
+    /// Returns and parses the value of the message tag `tag`, if any.
+    ///
+    /// See [`names::tag`][crate::names::tag] for tags that can be parsed this way.
+    pub fn tag_parsed<T: NameValued<MsgTag>>(
+        &self,
+        tag: T,
+    ) -> Option<Result<T::Value<'a>, ParseError>> {
+        self.tags.get_parsed(tag)
+    }
+    /// Returns the account of this message's sender from the `account` tag, if any.
+    ///
+    /// This tag is sent to clients that have enabled `account-tag`.
+    /// Returns `None` if the value is not a valid [`Arg`].
+    pub fn account_tag(&self) -> Option<Arg<'a>> {
+        self.tag_parsed(ACCOUNT)?.ok()
+    }
+    /// Returns this message's ID from the `msgid` tag, if any.
+    ///
+    /// Returns `None` if the value is not a valid [`Arg`].
+    pub fn msgid(&self) -> Option<Arg<'a>> {
+        self.tag_parsed(MSGID)?.ok()
+    }
+    /// Returns when this message was sent from the `time` tag, if any.
+    ///
+    /// This tag is sent to clients that have enabled `server-time`,
+    /// and is in the form `YYYY-MM-DDThh:mm:ss.sssZ`.
+    /// The fractional seconds are optional and may have up to 9 digits.
+    /// A seconds value of `60`, as used for leap seconds, is treated as the start of the
+    /// next minute.
+    /// Returns `None` if the value is malformed or names an invalid date or time;
+    /// use [`server_time_raw`][Self::server_time_raw] to get the value regardless.
+    pub fn server_time(&self) -> Option<SystemTime> {
+        self.tag_parsed(TIME)?.ok()
+    }
+    /// Returns the raw value of the `time` tag, if any.
+    pub fn server_time_raw(&self) -> Option<&NoNul<'a>> {
+        self.tags.get(TIME)
+    }
     /// Attempts to parse this message further into a higher-level message type.
     ///
     /// Does not check if the message kind matches, assuming such a check has been done earlier.
@@ -124,6 +179,24 @@ impl<'a> ServerMsg<'a> {
             args: Args::new(vec![target.into()], None),
         }
     }
+    /// Creates a new `ServerMsg` with the provided numeric reply code, source, target,
+    /// and trailing text.
+    ///
+    /// Additional arguments can be inserted between the target and the text
+    /// using [`add_before_last`][super::ArgsEditGuard::add_before_last].
+    pub fn new_reply(
+        num: Numeric,
+        source: SharedSource<'a>,
+        target: Nick<'a>,
+        text: impl Into<Line<'a>>,
+    ) -> Self {
+        ServerMsg {
+            tags: Tags::new(),
+            source: Some(source),
+            kind: ServerMsgKindRaw::Numeric(num),
+            args: Args::new(vec![target.into()], Some(text.into())),
+        }
+    }
     /// Creates a new `ServerMsg` with the provided command.
     pub const fn new_cmd(cmd: Cmd<'a>) -> Self {
         ServerMsg {
@@ -133,11 +206,33 @@ impl<'a> ServerMsg<'a> {
             args: Args::empty(),
         }
     }
+    /// Converts a [`ClientMsg`] into a `ServerMsg` with the provided source.
+    ///
+    /// The tags and arguments are moved as-is without copying.
+    /// This is useful for servers relaying messages from one client to others.
+    pub fn from_client(msg: ClientMsg<'a>, source: Option<SharedSource<'a>>) -> Self {
+        ServerMsg { tags: msg.tags, source, kind: ServerMsgKindRaw::Cmd(msg.cmd), args: msg.args }
+    }
     /// Parses a message from a [`Line`].
     pub fn parse(
         msg: impl TryInto<Line<'a>, Error = impl Into<InvalidString>>,
     ) -> Result<ServerMsg<'a>, ParseError> {
         let msg = msg.try_into().map_err(|e| ParseError::InvalidLine(e.into()))?;
+        Ok(Self::parse_impl(msg)?)
+    }
+    /// Parses a message from a string,
+    /// reporting where in the string parsing failed on error.
+    ///
+    /// This is otherwise the same as [`parse`][ServerMsg::parse].
+    /// The [`Display`][std::fmt::Display] impl is its inverse.
+    pub fn parse_detailed<M, E>(msg: M) -> Result<ServerMsg<'a>, ParseErrorAt>
+    where
+        M: AsRef<[u8]> + TryInto<Line<'a>, Error = E>,
+        E: Into<InvalidString>,
+    {
+        Self::parse_impl(super::parse_line(msg)?)
+    }
+    fn parse_impl(msg: Line<'a>) -> Result<ServerMsg<'a>, ParseErrorAt> {
         let (tags, source, kind, args) = super::parse(msg, Source::parse, |kind| {
             Ok(if let Some(num) = Numeric::from_bytes(&kind) {
                 num.into()
@@ -159,6 +254,23 @@ impl<'a> ServerMsg<'a> {
             &self.args,
         )
     }
+    /// Returns the length in bytes of this message's tags and of the rest of the message.
+    ///
+    /// The tag length includes the leading `'@'` and the space after the tags,
+    /// and is zero if there are no tags.
+    /// The length of the rest of the message includes the source but not the trailing CRLF,
+    /// and pessimistically counts the last argument as having a colon.
+    pub fn len_bytes(&self) -> (usize, usize) {
+        let tags = if self.tags.is_empty() { 0 } else { self.tags.len_bytes() + 1 };
+        let mut rest = self.kind.as_arg().len();
+        if let Some(source) = &self.source {
+            rest += source.len() + 2;
+        }
+        if !self.args.is_empty() {
+            rest += self.args.len_bytes() + 1;
+        }
+        (tags, rest)
+    }
     #[deprecated = "Moved to `ServerCodec` in 0.4."]
     /// Writes self to the provided [`Write`] WITHOUT a trailing CRLF.
     ///
@@ -196,16 +308,31 @@ impl<'a> ServerMsg<'a> {
 
     /// Converts `self` into a version that owns its data.
     pub fn owning(self) -> ServerMsg<'static> {
-        let source = self.source.map(|src| SharedSource::new(src.owning()));
         ServerMsg {
             tags: self.tags.owning(),
-            source,
+            source: self.source.map(SharedSource::owning),
+            kind: self.kind.owning(),
+            args: self.args.owning(),
+        }
+    }
+    /// As [`owning`][ServerMsg::owning], but shares the source's allocation
+    /// with identical sources in `cache`.
+    pub fn owning_cached(self, cache: &mut super::SourceCache) -> ServerMsg<'static> {
+        ServerMsg {
+            tags: self.tags.owning(),
+            source: self.source.map(|src| cache.intern(src)),
             kind: self.kind.owning(),
             args: self.args.owning(),
         }
     }
 }
 
+impl<'a> From<ClientMsg<'a>> for ServerMsg<'a> {
+    fn from(value: ClientMsg<'a>) -> Self {
+        ServerMsg::from_client(value, None)
+    }
+}
+
 impl std::fmt::Display for ServerMsg<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if !self.tags.is_empty() {
@@ -222,3 +349,94 @@ impl std::fmt::Display for ServerMsg<'_> {
         Ok(())
     }
 }
+
+/// Parses a `server-time` timestamp, `YYYY-MM-DDThh:mm:ss[.fff]Z`.
+pub(crate) fn parse_server_time(value: &[u8]) -> Option<SystemTime> {
+    fn number(digits: &[u8]) -> Option<u64> {
+        if digits.is_empty() {
+            return None;
+        }
+        digits
+            .iter()
+            .try_fold(0u64, |acc, d| d.is_ascii_digit().then(|| acc * 10 + u64::from(d - b'0')))
+    }
+    let value = value.strip_suffix(b"Z")?;
+    let (datetime, frac) = match value.iter().position(|b| *b == b'.') {
+        Some(idx) => (&value[..idx], Some(&value[idx + 1..])),
+        None => (value, None),
+    };
+    let &[y0, y1, y2, y3, b'-', m0, m1, b'-', d0, d1, b'T', h0, h1, b':', i0, i1, b':', s0, s1] =
+        datetime
+    else {
+        return None;
+    };
+    let year = number(&[y0, y1, y2, y3])?;
+    let (month, day) = (number(&[m0, m1])?, number(&[d0, d1])?);
+    let (hour, min, sec) = (number(&[h0, h1])?, number(&[i0, i1])?, number(&[s0, s1])?);
+    let leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_len = match month {
+        2 if leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if !(1..=month_len).contains(&day) || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+    let nanos = match frac {
+        Some(frac) if frac.len() <= 9 => number(frac)? * 10u64.pow(9 - frac.len() as u32),
+        Some(_) => return None,
+        None => 0,
+    };
+    // Days since the epoch for a date in the proleptic Gregorian calendar.
+    let year = year as i64 - i64::from(month <= 2);
+    let (era, year_of_era) = (year.div_euclid(400), year.rem_euclid(400));
+    let day_of_year = ((153 * ((month + 9) % 12) + 2) / 5 + day - 1) as i64;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    let secs = days * 86400 + (hour * 3600 + min * 60 + sec) as i64;
+    let nanos = Duration::from_nanos(nanos);
+    if secs >= 0 {
+        SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64) + nanos)
+    } else {
+        SystemTime::UNIX_EPOCH
+            .checked_sub(Duration::from_secs(secs.unsigned_abs()))?
+            .checked_add(nanos)
+    }
+}
+
+/// Formats a timestamp as in a `server-time` tag, `YYYY-MM-DDThh:mm:ss.fffZ`.
+///
+/// Returns `None` if the year is not representable in four digits.
+#[cfg(feature = "client")]
+pub(crate) fn format_server_time(time: SystemTime) -> Option<String> {
+    let (secs, nanos) = match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(dur) => (i64::try_from(dur.as_secs()).ok()?, dur.subsec_nanos()),
+        Err(e) => {
+            let dur = e.duration();
+            let secs = i64::try_from(dur.as_secs()).ok()?;
+            match dur.subsec_nanos() {
+                0 => (-secs, 0),
+                nanos => (-secs - 1, 1_000_000_000 - nanos),
+            }
+        }
+    };
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // Inverse of the days-since-the-epoch calculation in `parse_server_time`.
+    let days = days.checked_add(719468)?;
+    let (era, day_of_era) = (days.div_euclid(146097), days.rem_euclid(146097));
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = era * 400 + year_of_era + i64::from(month <= 2);
+    if !(0..=9999).contains(&year) {
+        return None;
+    }
+    let (hour, min, sec) = (secs / 3600, secs / 60 % 60, secs % 60);
+    let millis = nanos / 1_000_000;
+    Some(format!("{year:04}-{month:02}-{day:02}T{hour:02}:{min:02}:{sec:02}.{millis:03}Z"))
+}
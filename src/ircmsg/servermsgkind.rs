@@ -1,4 +1,4 @@
-use super::Numeric;
+use super::{Numeric, ReplyCategory};
 use crate::string::{Arg, Cmd};
 
 /// Either an alphabetic command or a numeric reply.
@@ -128,4 +128,26 @@ impl<'a> ServerMsgKindRaw<'a> {
             },
         }
     }
+    /// Returns what kind of reply `self` is.
+    ///
+    /// See [`Numeric::category`]. Of the alphabetic commands,
+    /// only those that [represent errors][Self::is_error] have a category.
+    pub const fn category(&self) -> ReplyCategory {
+        match self {
+            ServerMsgKindRaw::Numeric(n) => n.category(),
+            ServerMsgKindRaw::Cmd(_) => match self.is_error() {
+                Some(true) => ReplyCategory::Error,
+                _ => ReplyCategory::Unknown,
+            },
+        }
+    }
+    /// Returns `true` if `self` is the last reply the server sends in response to `cmd`.
+    ///
+    /// See [`Numeric::is_final_for`].
+    pub fn is_final_for(&self, cmd: &Cmd<'_>) -> bool {
+        match self {
+            ServerMsgKindRaw::Numeric(n) => n.is_final_for(cmd),
+            ServerMsgKindRaw::Cmd(_) => false,
+        }
+    }
 }
@@ -117,6 +117,10 @@ impl<'a> Source<'a> {
     pub fn owning(self) -> Source<'static> {
         Source { nick: self.nick.owning(), userhost: self.userhost.map(UserHost::owning) }
     }
+    /// Returns `true` if none of `self`'s strings are borrowing their data.
+    pub fn is_owning(&self) -> bool {
+        self.nick.is_owning() && self.userhost.as_ref().map_or(true, UserHost::is_owning)
+    }
     /// Merges the values of `self` into one buffer,
     /// then creates a new `Source` from the shared buffer.
     ///
@@ -179,6 +183,10 @@ impl<'a> UserHost<'a> {
     pub fn owning(self) -> UserHost<'static> {
         UserHost { host: self.host.owning(), user: self.user.map(User::owning) }
     }
+    /// Returns `true` if none of `self`'s strings are borrowing their data.
+    pub fn is_owning(&self) -> bool {
+        self.host.is_owning() && self.user.as_ref().map_or(true, User::is_owning)
+    }
     /// Returns `false` if `self.user` is `Some` and starts with a tilde.
     ///
     /// Many IRC networks use a leading `~` to indicate a lack of ident response.
@@ -245,13 +253,19 @@ impl<'a> SharedSource<'a> {
         Self(std::sync::Arc::new(source))
     }
     /// As [`Source::owning`].
-    pub fn owning(self) -> Source<'static> {
-        // TODO: Check if everything in Source is owning,
-        // and if so just return self.
-        match std::sync::Arc::try_unwrap(self.0) {
+    ///
+    /// If the source already owns all of its data, this only extends its lifetime
+    /// and the allocation is shared with `self`'s clones.
+    pub fn owning(self) -> SharedSource<'static> {
+        if self.is_owning() {
+            // Lifetime extension. Every string in the source owns its data.
+            return unsafe { std::mem::transmute::<SharedSource<'a>, SharedSource<'static>>(self) };
+        }
+        let source = match std::sync::Arc::try_unwrap(self.0) {
             Ok(src) => src.owning(),
             Err(arc) => (*arc).clone().owning(),
-        }
+        };
+        SharedSource::new(source)
     }
     /// As [`Source::owning_merged`].
     pub fn owning_merged(self) -> Source<'static> {
@@ -275,3 +289,83 @@ impl std::fmt::Display for SharedSource<'_> {
         write!(f, "{}", self.0)
     }
 }
+
+/// A bounded cache of [`SharedSource`]s,
+/// allowing identical sources from different messages to share one allocation.
+///
+/// Sources are evicted least-recently-used first.
+/// This is intended to be small, as lookups are linear in the number of cached sources.
+#[derive(Clone, Debug)]
+pub struct SourceCache {
+    /// Cached sources, most recently used last.
+    sources: std::collections::VecDeque<SharedSource<'static>>,
+    capacity: usize,
+}
+
+impl SourceCache {
+    /// The capacity of a cache created using [`SourceCache::default`].
+    pub const DEFAULT_CAPACITY: usize = 32;
+
+    /// Creates a new empty cache that holds up to `capacity` sources.
+    pub fn new(capacity: usize) -> Self {
+        SourceCache { sources: std::collections::VecDeque::with_capacity(capacity), capacity }
+    }
+    /// Returns the maximum number of sources in this cache.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    /// Returns the number of sources in this cache.
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+    /// Returns `true` if this cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+    /// Removes every source from this cache.
+    pub fn clear(&mut self) {
+        self.sources.clear();
+    }
+    /// Returns an owning version of `source`,
+    /// sharing an allocation with an identical cached source if there is one.
+    ///
+    /// If there is not, an owning version of `source` is cached,
+    /// evicting the least-recently-used source if the cache is full.
+    pub fn get(&mut self, source: &Source<'_>) -> SharedSource<'static> {
+        if let Some(idx) = self.sources.iter().rposition(|cached| **cached == *source) {
+            let cached = self.sources.remove(idx).unwrap();
+            self.sources.push_back(cached.clone());
+            return cached;
+        }
+        let shared = SharedSource::new(source.clone().owning_merged());
+        self.insert(shared.clone());
+        shared
+    }
+    /// As [`get`][SourceCache::get], but reuses `source`'s allocation
+    /// if it owns its data and there is no identical cached source.
+    pub fn intern(&mut self, source: SharedSource<'_>) -> SharedSource<'static> {
+        if let Some(idx) = self.sources.iter().rposition(|cached| **cached == *source) {
+            let cached = self.sources.remove(idx).unwrap();
+            self.sources.push_back(cached.clone());
+            return cached;
+        }
+        let shared = source.owning();
+        self.insert(shared.clone());
+        shared
+    }
+    fn insert(&mut self, source: SharedSource<'static>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.sources.len() >= self.capacity {
+            self.sources.pop_front();
+        }
+        self.sources.push_back(source);
+    }
+}
+
+impl Default for SourceCache {
+    fn default() -> Self {
+        SourceCache::new(Self::DEFAULT_CAPACITY)
+    }
+}
@@ -0,0 +1,103 @@
+use super::Tags;
+use crate::{
+    names::{cap, Cap, NameMap},
+    string::Key,
+};
+
+const MESSAGE_TAGS: u8 = 1;
+const SERVER_TIME: u8 = 1 << 1;
+const ACCOUNT_TAG: u8 = 1 << 2;
+const BATCH: u8 = 1 << 3;
+const LABELED_RESPONSE: u8 = 1 << 4;
+
+/// Capabilities that affect which tags may be sent, and their bits.
+static CAPS: [(Key<'static>, u8); 5] = [
+    (cap::ACCOUNT_TAG::NAME, ACCOUNT_TAG),
+    (cap::BATCH::NAME, BATCH),
+    (cap::LABELED_RESPONSE::NAME, LABELED_RESPONSE),
+    (cap::MESSAGE_TAGS::NAME, MESSAGE_TAGS),
+    (cap::SERVER_TIME::NAME, SERVER_TIME),
+];
+
+/// Tags that are enabled by a specific capability, sorted.
+///
+/// Any tag not in this list requires `message-tags`.
+const TAGS: [(&[u8], u8); 5] = [
+    (b"account", ACCOUNT_TAG),
+    (b"batch", BATCH),
+    (b"label", LABELED_RESPONSE),
+    (b"msgid", MESSAGE_TAGS),
+    (b"time", SERVER_TIME),
+];
+
+/// Which message tags a peer may receive, based on the capabilities enabled for it.
+///
+/// Tags that are specified alongside their own capability (such as `time` for `server-time`)
+/// are allowed if that capability is enabled.
+/// All other tags, including client-only tags and vendor-specific tags,
+/// are only allowed if `message-tags` is enabled.
+///
+/// The default policy allows no tags.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct TagPolicy(u8);
+
+impl TagPolicy {
+    /// A policy that allows no tags.
+    pub const NONE: TagPolicy = TagPolicy(0);
+    /// A policy that allows every tag.
+    pub const ALL: TagPolicy =
+        TagPolicy(MESSAGE_TAGS | SERVER_TIME | ACCOUNT_TAG | BATCH | LABELED_RESPONSE);
+
+    /// Creates a policy for a peer with the provided capabilities enabled.
+    pub fn from_caps<'a, 'b: 'a>(caps: impl IntoIterator<Item = &'a Key<'b>>) -> Self {
+        let mut retval = TagPolicy::NONE;
+        for cap in caps {
+            retval.enable(cap);
+        }
+        retval
+    }
+    /// Creates a policy from a map of capabilities to whether they're enabled,
+    /// such as the one stored by connection registration.
+    pub fn from_cap_map(caps: &NameMap<Cap, bool>) -> Self {
+        TagPolicy::from_caps(caps.keys().filter(|cap| caps.get_extra_raw(cap) == Some(&true)))
+    }
+    /// Updates this policy for a newly-enabled capability.
+    ///
+    /// Returns `true` if this capability affects which tags are allowed.
+    pub fn enable(&mut self, cap: &Key<'_>) -> bool {
+        let Some((_, bit)) = CAPS.iter().find(|(name, _)| name == cap) else {
+            return false;
+        };
+        self.0 |= bit;
+        true
+    }
+    /// Updates this policy for a newly-disabled capability.
+    ///
+    /// Returns `true` if this capability affects which tags are allowed.
+    pub fn disable(&mut self, cap: &Key<'_>) -> bool {
+        let Some((_, bit)) = CAPS.iter().find(|(name, _)| name == cap) else {
+            return false;
+        };
+        self.0 &= !bit;
+        true
+    }
+    /// Returns `true` if the tag with the provided key may be sent.
+    pub fn allows(&self, key: &Key<'_>) -> bool {
+        let bit = match TAGS.binary_search_by(|(name, _)| name.cmp(&key.as_bytes())) {
+            Ok(idx) => TAGS[idx].1,
+            Err(_) => MESSAGE_TAGS,
+        };
+        self.0 & bit != 0
+    }
+    /// Removes every tag from `tags` that may not be sent.
+    pub fn filter(&self, tags: &mut Tags<'_>) {
+        if *self == TagPolicy::ALL {
+            return;
+        }
+        if *self == TagPolicy::NONE {
+            tags.edit().clear();
+            return;
+        }
+        tags.retain(|key, _| self.allows(key));
+    }
+}
@@ -1,23 +1,35 @@
 //! Stuctures and utilities for IRCv3 message tags.
 
 use crate::{
-    names::{MsgTag, NameExtractor},
+    error::{ParseError, TagBudgetError},
+    names::{MsgTag, NameExtractor, NameValued},
     string::{
-        tf::{escape, unescape},
-        Key, NoNul, Splitter,
+        tf::{escape, escape_byte, unescape},
+        Key, Line, NoNul, Splitter, Word,
     },
     util::{FlatMap, FlatMapEditGuard},
 };
-use std::borrow::Borrow;
+use std::{borrow::Borrow, sync::OnceLock};
+
+type Pairs<'a> = FlatMap<((Key<'a>, NoNul<'a>), ()), NameExtractor<'a, MsgTag>>;
 
 /// Collection mapping tag keys to bytes.
 ///
 /// IRCv3 requires that tag values be valid UTF-8,
 /// however server implementations may be non-compliant.
-#[repr(transparent)]
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+///
+/// Values are stored unescaped.
+/// They are escaped by [`write_to`][Tags::write_to] and the `Display` impl,
+/// and unescaped by [`parse`][Tags::parse].
+///
+/// Tags created by [`parse`][Tags::parse] are not actually parsed
+/// until they are first accessed or made [`owning`][Tags::owning],
+/// so messages whose tags are never looked at do not pay for parsing them.
+#[derive(Clone, Default)]
 pub struct Tags<'a> {
-    pairs: FlatMap<((Key<'a>, NoNul<'a>), ()), NameExtractor<'a, MsgTag>>,
+    /// The tags as they appear in a message. Ignored once `pairs` is initialized.
+    unparsed: TagsRef<'a>,
+    pairs: OnceLock<Pairs<'a>>,
 }
 
 /// Guard for editing [`Tags`].
@@ -26,15 +38,51 @@ pub struct TagsEditGuard<'a, 'b>(
     FlatMapEditGuard<'b, ((Key<'a>, NoNul<'a>), ()), NameExtractor<'a, MsgTag>>,
 );
 
+/// Returns the length of a key-value pair as written by [`Tags::write_to`],
+/// including the leading `'@'` or `';'`.
+fn pair_len(key: &Key<'_>, value: &NoNul<'_>) -> usize {
+    let mut count = key.len() + 1;
+    if !value.is_empty() {
+        let escapes = value.iter().filter(|b| escape_byte(b).is_some()).count();
+        count += value.len() + escapes + 1;
+    }
+    count
+}
+
 impl<'a> Tags<'a> {
+    /// The length of the longest permissible tags on a message
+    /// as returned by [`len_bytes`][Tags::len_bytes].
+    ///
+    /// This is one less than the limit in the IRCv3 spec,
+    /// which includes the space after the tags.
+    pub const MAX_LEN: usize = 8190;
+    /// The length of the longest permissible tags on a message sent by a client
+    /// as returned by [`len_bytes`][Tags::len_bytes].
+    ///
+    /// See also [`ClientMsg::MAX_TAGS_LEN`][super::ClientMsg::MAX_TAGS_LEN].
+    pub const MAX_CLIENT_LEN: usize = super::ClientMsg::MAX_TAGS_LEN - 1;
     /// Creates a new empty `Tags`.
     pub const fn new() -> Self {
-        Tags { pairs: FlatMap::new() }
+        Tags { unparsed: TagsRef(Word::empty()), pairs: OnceLock::new() }
+    }
+    fn from_pairs(pairs: Pairs<'a>) -> Self {
+        Tags { unparsed: TagsRef::default(), pairs: OnceLock::from(pairs) }
+    }
+    /// Returns the key-value pairs, parsing them if they have not been already.
+    fn pairs(&self) -> &Pairs<'a> {
+        self.pairs.get_or_init(|| self.unparsed.parse_pairs())
+    }
+    fn pairs_mut(&mut self) -> &mut Pairs<'a> {
+        self.pairs();
+        self.unparsed = TagsRef::default();
+        self.pairs.get_mut().unwrap()
     }
     /// Converts `self` into a version that owns its data.
+    ///
+    /// This parses `self` if it has not been already.
     pub fn owning<'b>(mut self) -> Tags<'b> {
         use crate::owning::MakeOwning;
-        for ((key, value), _) in self.pairs.as_slice_mut() {
+        for ((key, value), _) in self.pairs_mut().as_slice_mut() {
             key.make_owning();
             value.make_owning();
         }
@@ -42,16 +90,51 @@ impl<'a> Tags<'a> {
     }
     /// Returns a guard that allows editing of `self`.
     pub fn edit(&mut self) -> TagsEditGuard<'a, '_> {
-        TagsEditGuard(self.pairs.edit())
+        TagsEditGuard(self.pairs_mut().edit())
+    }
+    /// Returns `true` if `self` contains no key-value pairs.
+    pub fn is_empty(&self) -> bool {
+        self.pairs().is_empty()
+    }
+    /// Returns the number of key-value pairs in `self`.
+    pub fn len(&self) -> usize {
+        self.pairs().len()
     }
-    collection_methods!(pairs);
     /// Returns a shared reference to the value associated with the provided key, if any.
     pub fn get(&self, key: impl TryInto<Key<'a>>) -> Option<&NoNul<'a>> {
-        self.pairs.get(key.try_into().ok()?.borrow()).map(|((_, v), _)| v)
+        self.pairs().get(key.try_into().ok()?.borrow()).map(|((_, v), _)| v)
+    }
+    /// Returns and parses the value associated with `tag`, if any.
+    ///
+    /// See [`names::tag`][crate::names::tag] for tags that can be parsed this way.
+    pub fn get_parsed<T: NameValued<MsgTag>>(
+        &self,
+        tag: T,
+    ) -> Option<Result<T::Value<'a>, ParseError>> {
+        let (union, _) = self.pairs().get(tag.as_raw().borrow())?;
+        Some(T::from_union(union))
     }
     /// Returns a mutable reference to the value associated with the provided key, if any.
     pub fn get_mut(&mut self, key: impl TryInto<Key<'a>>) -> Option<&mut NoNul<'a>> {
-        self.pairs.get_mut(key.try_into().ok()?.borrow()).map(|((_, v), _)| v)
+        self.pairs_mut().get_mut(key.try_into().ok()?.borrow()).map(|((_, v), _)| v)
+    }
+    /// Returns the value associated with the provided key, if any,
+    /// escaped as it would be in a message.
+    pub fn get_escaped(&self, key: impl TryInto<Key<'a>>) -> Option<Word<'a>> {
+        self.get(key).map(|value| escape(value.clone()))
+    }
+    /// Returns an iterator over the key-value pairs in `self`.
+    pub fn iter(&self) -> impl Iterator<Item = (&Key<'a>, &NoNul<'a>)> + '_ {
+        self.pairs().as_slice().iter().map(|((key, value), _)| (key, value))
+    }
+    /// Removes all key-value pairs for which `f` returns `false`.
+    pub fn retain(&mut self, mut f: impl FnMut(&Key<'a>, &NoNul<'a>) -> bool) {
+        self.pairs_mut().retain(|((k, v), _)| f(k, v));
+    }
+    /// Returns the length of `self` in bytes as written by [`write_to`][Tags::write_to],
+    /// including the leading `'@'` if non-empty.
+    pub fn len_bytes(&self) -> usize {
+        self.pairs().as_slice().iter().map(|((key, value), _)| pair_len(key, value)).sum()
     }
     /// Writes `self`, including a leading `'@'` if non-empty,
     /// to the provided [`Write`][std::io::Write].
@@ -59,7 +142,7 @@ impl<'a> Tags<'a> {
     /// This function makes many small writes. Buffering is strongly recommended.
     pub fn write_to(&self, w: &mut (impl std::io::Write + ?Sized)) -> std::io::Result<()> {
         let mut prefix = b"@";
-        for ((key, value), _) in self.pairs.as_slice() {
+        for ((key, value), _) in self.pairs().as_slice() {
             w.write_all(prefix)?;
             w.write_all(key.as_ref())?;
             if !value.is_empty() {
@@ -73,36 +156,170 @@ impl<'a> Tags<'a> {
     /// Parses the provided semicolon-delimited list of tag strings.
     ///
     /// The provided word should NOT contain the leading '@'.
+    /// This does not allocate; the tags are parsed when first accessed.
     pub fn parse(word: impl Into<crate::string::Word<'a>>) -> Self {
-        let word = word.into();
-        if word.is_empty() {
-            return Tags::new();
+        TagsRef::new(word).into()
+    }
+}
+
+impl PartialEq for Tags<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.pairs() == other.pairs()
+    }
+}
+
+impl Eq for Tags<'_> {}
+
+impl PartialOrd for Tags<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Tags<'_> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.pairs().cmp(other.pairs())
+    }
+}
+
+impl std::hash::Hash for Tags<'_> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.pairs().hash(state);
+    }
+}
+
+impl std::fmt::Debug for Tags<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tags").field("pairs", self.pairs()).finish()
+    }
+}
+
+/// Borrowing view of unparsed tags, as they appear in a message.
+///
+/// Unlike [`Tags`], this does not allocate when created.
+/// Key-value pairs are parsed and unescaped on demand,
+/// which is cheaper than [`Tags::parse`] if only a few tags are ever looked up.
+/// Lookups are linear in the length of the tags.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct TagsRef<'a>(Word<'a>);
+
+impl<'a> TagsRef<'a> {
+    /// Creates a view of the provided semicolon-delimited list of tag strings.
+    ///
+    /// The provided word should NOT contain the leading '@'.
+    pub fn new(word: impl Into<Word<'a>>) -> Self {
+        TagsRef(word.into())
+    }
+    /// Creates a view of the tags at the start of an unparsed message, if any.
+    pub fn from_msg(msg: &Line<'a>) -> Self {
+        let mut splitter = Splitter::new(msg.clone());
+        splitter.consume_spaces();
+        if splitter.next_byte() != Some(b'@') {
+            return TagsRef::default();
         }
-        let mut size_hint = 1usize;
-        for c in word.as_bytes() {
-            size_hint += (*c == b';') as usize;
+        TagsRef(splitter.string_or_default(false))
+    }
+    /// Returns the tags as they appear in a message, without the leading `'@'`.
+    pub fn as_word(&self) -> &Word<'a> {
+        &self.0
+    }
+    /// Returns `true` if there are no tags.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// Returns an iterator over the unescaped key-value pairs, in the order they appear.
+    ///
+    /// Invalid keys are skipped. Duplicate keys are not.
+    pub fn iter(&self) -> TagsRefIter<'a> {
+        TagsRefIter(Splitter::new(self.0.clone()))
+    }
+    /// Returns the unescaped value associated with the provided key, if any.
+    ///
+    /// If the key appears more than once, the last value is returned, as in [`Tags`].
+    pub fn get(&self, key: impl TryInto<Key<'a>>) -> Option<NoNul<'a>> {
+        let key = key.try_into().ok()?;
+        let mut iter = self.iter();
+        let mut found = None;
+        while let Some((k, v)) = iter.next_escaped() {
+            if k == key {
+                found = Some(v);
+            }
+        }
+        found.map(unescape)
+    }
+    /// Parses all of the tags into a [`Tags`].
+    ///
+    /// Unlike converting `self` into [`Tags`], this parses the tags immediately.
+    pub fn to_tags(&self) -> Tags<'a> {
+        Tags::from_pairs(self.parse_pairs())
+    }
+    fn parse_pairs(&self) -> Pairs<'a> {
+        if self.0.is_empty() {
+            return FlatMap::new();
         }
-        let mut splitter = Splitter::new(word);
+        let size_hint = 1 + self.0.iter().filter(|c| **c == b';').count();
         let mut tags = Vec::with_capacity(size_hint);
+        tags.extend(self.iter().map(|pair| (pair, ())));
+        FlatMap::from_vec(tags)
+    }
+}
+
+/// Creates a [`Tags`] that is parsed when first accessed.
+impl<'a> From<TagsRef<'a>> for Tags<'a> {
+    fn from(value: TagsRef<'a>) -> Self {
+        Tags { unparsed: value, pairs: OnceLock::new() }
+    }
+}
+
+impl<'a> IntoIterator for &TagsRef<'a> {
+    type Item = (Key<'a>, NoNul<'a>);
+
+    type IntoIter = TagsRefIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the key-value pairs in a [`TagsRef`].
+#[derive(Clone, Debug)]
+pub struct TagsRefIter<'a>(Splitter<Word<'a>>);
+
+impl<'a> TagsRefIter<'a> {
+    /// Returns the next key and its still-escaped value.
+    fn next_escaped(&mut self) -> Option<(Key<'a>, NoNul<'a>)> {
+        let splitter = &mut self.0;
         // TODO: Tag bytes available.
         while !splitter.is_empty() {
             let Ok(key) = splitter.string::<Key>(false) else {
-                splitter.consume_invalid::<Key>();
+                // Skip the whole pair, as the key may be empty or start with a colon.
+                splitter.save_end().until_byte_eq(b';').rest_or_default::<Word>();
+                splitter.next_byte();
                 continue;
             };
             let value = if matches!(splitter.next_byte(), Some(b'=')) {
                 let value = splitter.save_end().until_byte_eq(b';').rest::<NoNul>().unwrap();
                 splitter.next_byte();
-                unescape(value)
+                value
             } else {
                 NoNul::default()
             };
-            tags.push(((key, value), ()));
+            return Some((key, value));
         }
-        Tags { pairs: FlatMap::from_vec(tags) }
+        None
     }
 }
 
+impl<'a> Iterator for TagsRefIter<'a> {
+    type Item = (Key<'a>, NoNul<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_escaped().map(|(key, value)| (key, unescape(value)))
+    }
+}
+
+impl std::iter::FusedIterator for TagsRefIter<'_> {}
+
 impl<'a> TagsEditGuard<'a, '_> {
     // Present throughout: `Some(expr?.1)` which could be a map, but field extraction on tuples
     // is not particularly nice either way.
@@ -123,6 +340,37 @@ impl<'a> TagsEditGuard<'a, '_> {
     ) -> Option<NoNul<'a>> {
         Some(self.0.insert(((key.into(), value.into()), ()))?.0 .1)
     }
+    /// As [`insert_pair`][Self::insert_pair], but fails without changing anything
+    /// if the insertion would make [`len_bytes`][Self::len_bytes] exceed `budget`.
+    ///
+    /// [`Tags::MAX_LEN`] and [`Tags::MAX_CLIENT_LEN`] are the budgets from the IRCv3 spec.
+    pub fn try_insert_pair(
+        &mut self,
+        key: impl Into<Key<'a>>,
+        value: impl Into<NoNul<'a>>,
+        budget: usize,
+    ) -> Result<Option<NoNul<'a>>, TagBudgetError> {
+        let (key, value) = (key.into(), value.into());
+        let old_len = self.0.get(key.borrow()).map_or(0, |((k, v), _)| pair_len(k, v));
+        let len = self.len_bytes() - old_len + pair_len(&key, &value);
+        if len > budget {
+            return Err(TagBudgetError { len, budget });
+        }
+        Ok(self.insert_pair(key, value))
+    }
+    /// Returns the length of the tags as [`Tags::len_bytes`].
+    pub fn len_bytes(&self) -> usize {
+        self.0.as_slice().iter().map(|((key, value), _)| pair_len(key, value)).sum()
+    }
+    /// Unescapes a value as it would be in a message, then inserts it with the provided key,
+    /// returning the old value if present.
+    pub fn insert_escaped(
+        &mut self,
+        key: impl Into<Key<'a>>,
+        value: impl Into<NoNul<'a>>,
+    ) -> Option<NoNul<'a>> {
+        self.insert_pair(key, unescape(value))
+    }
     /// Inserts a key with no value into this map.
     ///
     /// This is equivalent to inserting a key-value pair with an empty value.
@@ -143,7 +391,7 @@ impl<'a> TagsEditGuard<'a, '_> {
 impl std::fmt::Display for Tags<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut prefix = '@';
-        for ((key, value), _) in self.pairs.as_slice() {
+        for ((key, value), _) in self.pairs().as_slice() {
             if !value.is_empty() {
                 let value = escape(value.clone());
                 write!(f, "{prefix}{key}={value}")?;
@@ -176,7 +424,7 @@ impl<'a> serde::Serialize for Tags<'a> {
     {
         use serde::ser::SerializeMap;
         let mut map = ser.serialize_map(Some(self.len()))?;
-        for ((key, value), _) in self.pairs.as_slice() {
+        for ((key, value), _) in self.pairs().as_slice() {
             map.serialize_entry(key, value)?;
         }
         map.end()
@@ -192,6 +440,6 @@ impl<'a, 'de> serde::Deserialize<'de> for Tags<'a> {
         use std::collections::BTreeMap;
         let tags = BTreeMap::<Key<'a>, NoNul<'a>>::deserialize(de)?;
         let pairs = tags.into_iter().map(|v| (v, ())).collect();
-        Ok(Tags { pairs })
+        Ok(Tags::from_pairs(pairs))
     }
 }
@@ -1,4 +1,4 @@
-use super::{MaybeCtcp, ServerMsg};
+use super::{Args, MaybeCtcp, ServerMsg, TagPolicy};
 use crate::string::Line;
 
 macro_rules! irc_msg {
@@ -57,6 +57,66 @@ pub fn parse_args_long() {
     assert_eq!(last, "Hello world");
 }
 
+#[test]
+pub fn args_iter() {
+    let empty = Args::empty();
+    assert_eq!(empty.iter().len(), 0);
+    assert!(empty.iter().next().is_none());
+    assert!(empty.get(0).is_none());
+    assert_eq!(empty.to_string(), "");
+
+    let long = Args::parse(Line::from_str(":Hello world"));
+    let all: Vec<_> = long.iter().collect();
+    assert_eq!(all.len(), 1);
+    assert!(all[0].is_long());
+    assert_eq!(*all[0], "Hello world");
+    assert_eq!(long.get(0).unwrap().as_line(), "Hello world");
+    assert!(long.get(1).is_none());
+    assert_eq!(long.to_string(), ":Hello world");
+
+    let mixed = Args::parse(Line::from_str("#foo #bar :Hello world"));
+    let mut iter = mixed.iter();
+    assert_eq!(iter.len(), 3);
+    assert_eq!(iter.next_back().unwrap().as_line(), "Hello world");
+    assert_eq!(iter.next().unwrap().as_arg().unwrap(), "#foo");
+    assert_eq!(iter.len(), 1);
+    assert_eq!(iter.next().unwrap().as_arg().unwrap(), "#bar");
+    assert!(iter.next().is_none());
+    assert!(iter.next_back().is_none());
+    let rev: Vec<&[u8]> = mixed.iter().rev().map(|arg| arg.as_line().as_bytes()).collect();
+    assert_eq!(rev, [&b"Hello world"[..], b"#bar", b"#foo"]);
+    assert_eq!(mixed.get(1).unwrap().as_arg().unwrap(), "#bar");
+    assert!(mixed.get(2).unwrap().is_long());
+    assert!(mixed.get(3).is_none());
+    assert_eq!(mixed.to_string(), "#foo #bar :Hello world");
+
+    let words = Args::parse(Line::from_str("#foo :bar"));
+    assert!(words.iter().all(|arg| !arg.is_long()));
+    assert_eq!(words.get(1).unwrap().as_arg().unwrap(), "bar");
+    assert_eq!(words.to_string(), "#foo bar");
+}
+
+#[test]
+pub fn args_int_join() {
+    use crate::{error::InvalidString, string::Arg};
+    let mut args = Args::empty();
+    let mut edit = args.edit();
+    edit.add_literal("#chan");
+    edit.add_literal("+l");
+    edit.add_int(50u8);
+    edit.add_int(i64::MIN);
+    edit.add_int(u64::MAX);
+    edit.add_int(0i32);
+    let chans = ["#a", "#b", "&c"].map(Arg::from_str);
+    edit.add_join(b',', chans.clone()).unwrap();
+    assert_eq!(edit.add_join(b' ', chans), Err(InvalidString::Byte(b' ')));
+    assert_eq!(edit.add_join(b',', []), Err(InvalidString::Empty));
+    edit.add_join(b',', [Arg::from_str("#solo")]).unwrap();
+    let string = args.to_string();
+    assert_eq!(string, "#chan +l 50 -9223372036854775808 18446744073709551615 0 #a,#b,&c #solo");
+    assert_eq!(Args::parse(Line::from_bytes(string).unwrap()), args);
+}
+
 #[test]
 pub fn parse_tag_any() {
     let msg = irc_msg!("@tag TAGMSG");
@@ -79,6 +139,11 @@ pub fn parse_tag_keys() {
     assert!(tags.get("foo").is_some());
     assert!(tags.get("bar").is_some());
     assert_eq!(tags.len(), 2);
+    // Pairs with invalid keys are skipped whole.
+    let tags = irc_msg!("@:foo=baz;=bar;bar TAGMSG").tags;
+    assert!(tags.get("bar").is_some());
+    assert_eq!(tags.len(), 1);
+    assert_eq!(super::Tags::parse(crate::string::Word::from_str(":")).len(), 0);
 }
 
 #[test]
@@ -128,7 +193,7 @@ pub fn bytes_left() {
     ];
     for case in cases {
         let testlen = 510 - irc_msg!(case).bytes_left();
-        let caselen = case.as_bytes().len() as isize;
+        let caselen = case.len() as isize;
         assert_eq!(testlen, caselen, "wrong length calculation for: {}", case);
     }
 }
@@ -175,4 +240,791 @@ mod tokio_codec {
             assert_eq!(line_raw.as_ref(), b"foo bar\r\n", "partial split on case {case_id}");
         }
     }
+
+    #[test]
+    fn decode() {
+        use crate::ircmsg::{ClientCodec, ServerCodec};
+        use tokio_util::codec::Decoder;
+        let mut buf = tokio_util::bytes::BytesMut::from("PING :foo\r\nPONG bar\n");
+        let msg = ClientCodec::new().decode(&mut buf).unwrap().unwrap();
+        assert_eq!(msg.args.split_last().1.unwrap(), "foo");
+        let msg = ServerCodec::new().decode(&mut buf).unwrap().unwrap();
+        assert_eq!(msg.args.split_last().1.unwrap(), "bar");
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn decode_overlong() {
+        use crate::ircmsg::{ClientCodec, OverlongPolicy, ServerMsg};
+        use tokio_util::{bytes::BytesMut, codec::Decoder};
+        let stream = super::overlong_stream();
+        let mut codec = ClientCodec::new();
+        let mut buf = BytesMut::from(stream.as_slice());
+        assert!(codec.decode(&mut buf).is_err());
+        let truncated = ServerMsg::MAX_LEN - b":a PRIVMSG #c :".len();
+        for (overlong, expected) in
+            [(OverlongPolicy::Skip, vec![5]), (OverlongPolicy::Truncate, vec![truncated, 5])]
+        {
+            codec.overlong = overlong;
+            // Feed the stream in pieces, as it would arrive.
+            let mut buf = BytesMut::new();
+            let mut msgs = Vec::new();
+            for chunk in stream.chunks(4096) {
+                buf.extend_from_slice(chunk);
+                while let Some(msg) = codec.decode(&mut buf).unwrap() {
+                    msgs.push(msg.args.split_last().1.unwrap().len());
+                }
+                assert!(buf.len() <= 4096 + ServerMsg::MAX_LEN);
+            }
+            assert_eq!(msgs, expected, "{overlong:?}");
+        }
+    }
+}
+
+fn filtered_tags(caps: &[&'static str]) -> Vec<String> {
+    use crate::string::Key;
+    let caps: Vec<Key<'static>> = caps.iter().map(|cap| Key::from_str(cap)).collect();
+    let policy = TagPolicy::from_caps(&caps);
+    let mut tags = irc_msg!(
+        "@+draft/reply=1;account=acct;batch=b1;example.com/vendor=x;label=l;msgid=m;time=t \
+        PRIVMSG #chan :hi"
+    )
+    .tags;
+    policy.filter(&mut tags);
+    let mut keys: Vec<String> = tags
+        .to_string()
+        .trim_start_matches('@')
+        .split(';')
+        .map(|pair| pair.split('=').next().unwrap().to_owned())
+        .filter(|key| !key.is_empty())
+        .collect();
+    keys.sort();
+    keys
+}
+
+#[test]
+pub fn tag_policy() {
+    assert!(filtered_tags(&[]).is_empty());
+    assert!(filtered_tags(&["echo-message", "sasl"]).is_empty());
+    assert_eq!(filtered_tags(&["server-time"]), ["time"]);
+    assert_eq!(filtered_tags(&["account-tag", "server-time"]), ["account", "time"]);
+    assert_eq!(filtered_tags(&["batch", "labeled-response"]), ["batch", "label"]);
+    assert_eq!(filtered_tags(&["message-tags"]), ["+draft/reply", "example.com/vendor", "msgid"]);
+    assert_eq!(
+        filtered_tags(&["message-tags", "server-time"]),
+        ["+draft/reply", "example.com/vendor", "msgid", "time"]
+    );
+    let all = ["message-tags", "server-time", "account-tag", "batch", "labeled-response"];
+    assert_eq!(
+        filtered_tags(&all),
+        ["+draft/reply", "account", "batch", "example.com/vendor", "label", "msgid", "time"]
+    );
+}
+
+#[test]
+pub fn tag_policy_toggle() {
+    use crate::string::Key;
+    let mut policy = TagPolicy::from_caps(&[Key::from_str("message-tags")]);
+    assert!(policy.allows(&Key::from_str("+typing")));
+    assert!(!policy.allows(&Key::from_str("time")));
+    assert!(policy.enable(&Key::from_str("server-time")));
+    assert!(policy.allows(&Key::from_str("time")));
+    assert!(!policy.enable(&Key::from_str("sasl")));
+    assert!(policy.disable(&Key::from_str("message-tags")));
+    assert!(!policy.allows(&Key::from_str("+typing")));
+    assert_eq!(policy, TagPolicy::from_caps(&[Key::from_str("server-time")]));
+}
+
+#[cfg(feature = "serde")]
+mod serde {
+    use crate::{
+        ircmsg::ClientMsg,
+        string::{Arg, Cmd, Key, Line, NoNul},
+    };
+    use proptest::prelude::*;
+
+    fn client_msg() -> impl Strategy<Value = ClientMsg<'static>> {
+        let tags =
+            proptest::collection::vec(("\\+?[a-z][a-z0-9./-]{0,8}", "[^\0\r\n]{0,10}"), 0..4);
+        let words = proptest::collection::vec("[^\0\r\n :][^\0\r\n ]{0,8}", 0..4);
+        let last = proptest::option::of("[^\0\r\n]{0,20}");
+        ("[A-Z]{1,12}", tags, words, last).prop_map(|(cmd, tags, words, last)| {
+            let mut msg = ClientMsg::new_cmd(Cmd::from_bytes(cmd).unwrap());
+            let mut edit = msg.tags.edit();
+            for (key, value) in tags {
+                let key = Key::from_bytes(key).unwrap();
+                edit.insert_pair(key, NoNul::from_bytes(value).unwrap());
+            }
+            std::mem::drop(edit);
+            let words = words.into_iter().map(|word| Arg::from_bytes(word).unwrap());
+            let last = last.map(|last| Line::from_bytes(last).unwrap());
+            msg.with_args(words, last)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn clientmsg_json_roundtrip(msg in client_msg()) {
+            let json = serde_json::to_string(&msg).unwrap();
+            let msg2: ClientMsg<'static> = serde_json::from_str(&json).unwrap();
+            prop_assert_eq!(msg.to_string(), msg2.to_string());
+            prop_assert_eq!(json, serde_json::to_string(&msg2).unwrap());
+        }
+
+        #[test]
+        fn clientmsg_postcard_roundtrip(msg in client_msg()) {
+            let bytes = postcard::to_allocvec(&msg).unwrap();
+            let msg2: ClientMsg<'static> = postcard::from_bytes(&bytes).unwrap();
+            prop_assert_eq!(msg.to_string(), msg2.to_string());
+            prop_assert_eq!(bytes, postcard::to_allocvec(&msg2).unwrap());
+        }
+    }
+
+    #[test]
+    fn clientmsg_json_format() {
+        let msg = ClientMsg::parse("@+draft/reply=abc;time PRIVMSG #chan :hello world").unwrap();
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"version":1,"tags":{"+draft/reply":"abc","time":""},"#,
+                r##""cmd":"PRIVMSG","args":["#chan","hello world"]}"##
+            )
+        );
+    }
+
+    #[test]
+    fn clientmsg_json_validation() {
+        let parse = |json: &str| serde_json::from_str::<ClientMsg<'static>>(json);
+        assert!(parse(r#"{"tags":{},"cmd":"PING","args":["x"]}"#).is_ok());
+        let error = parse(r#"{"version":2,"tags":{},"cmd":"PING","args":[]}"#).unwrap_err();
+        assert!(error.to_string().contains("schema version 2"));
+        assert!(parse(r#"{"version":1,"tags":{},"cmd":"PI NG","args":[]}"#).is_err());
+        assert!(parse(r#"{"version":1,"tags":{"a=b":""},"cmd":"PING","args":[]}"#).is_err());
+        assert!(parse(r#"{"version":1,"tags":{},"cmd":"PING","args":["a b","c"]}"#).is_err());
+    }
+}
+
+#[test]
+pub fn tag_escapes() {
+    use super::Tags;
+    use crate::string::{Key, NoNul};
+    let tags =
+        irc_msg!(r"@a=one\stwo;b=semi\:colon;c=cr\rlf\n;d=back\\slash;e=lone\x;f=end\ TAGMSG").tags;
+    assert_eq!(tags.get("a").unwrap(), "one two");
+    assert_eq!(tags.get("b").unwrap(), "semi;colon");
+    assert_eq!(tags.get("c").unwrap(), "cr\rlf\n");
+    assert_eq!(tags.get("d").unwrap(), r"back\slash");
+    assert_eq!(tags.get("e").unwrap(), "lonex");
+    assert_eq!(tags.get("f").unwrap(), "end");
+    assert_eq!(tags.get_escaped("a").unwrap(), r"one\stwo");
+    assert_eq!(tags.get_escaped("e").unwrap(), "lonex");
+    let mut tags = Tags::new();
+    let mut edit = tags.edit();
+    let value = NoNul::from_bytes("a; b\r\n\\").unwrap();
+    edit.insert_pair(Key::from_str("+draft/reply"), value.clone());
+    edit.insert_escaped(Key::from_str("+typing"), NoNul::from_str(r"active\s\:"));
+    std::mem::drop(edit);
+    assert_eq!(tags.get("+typing").unwrap(), "active ;");
+    let mut written = Vec::new();
+    tags.write_to(&mut written).unwrap();
+    let written = String::from_utf8(written).unwrap();
+    assert_eq!(written, r"@+draft/reply=a\:\sb\r\n\\;+typing=active\s\:");
+    assert_eq!(written, tags.to_string());
+    let parsed = Tags::parse(crate::string::Word::from_bytes(&written[1..]).unwrap());
+    assert_eq!(parsed.get("+draft/reply").unwrap(), &value);
+    assert_eq!(parsed, tags);
+}
+
+#[test]
+pub fn tags_ref() {
+    use super::{Tags, TagsRef};
+    use crate::string::{Key, Word};
+    let line = Line::from_str(r"@+a=1;time=now;+a=b\sc;+flag :nick PRIVMSG #chan :hi");
+    let tags = TagsRef::from_msg(&line);
+    assert_eq!(tags.as_word(), r"+a=1;time=now;+a=b\sc;+flag");
+    assert_eq!(tags.get("time").unwrap(), "now");
+    // Duplicates are resolved the same way as in Tags.
+    assert_eq!(tags.get("+a").unwrap(), "b c");
+    assert_eq!(tags.get("+flag").unwrap(), "");
+    assert!(tags.get("+missing").is_none());
+    let keys: Vec<Key> = tags.iter().map(|(k, _)| k).collect();
+    assert_eq!(keys, ["+a", "time", "+a", "+flag"]);
+    let parsed = Tags::parse(tags.as_word().clone());
+    assert_eq!(tags.to_tags(), parsed);
+    assert_eq!(parsed.get("+a").unwrap(), "b c");
+    assert!(TagsRef::from_msg(&Line::from_str("PRIVMSG #chan :@hi")).is_empty());
+    assert!(TagsRef::new(Word::default()).to_tags().is_empty());
+}
+
+#[test]
+pub fn tags_lazy_owning() {
+    use super::Tags;
+    use crate::string::Word;
+    let buf = br"+a=b\sc;time=now".to_vec();
+    let tags = Tags::parse(Word::from_bytes(buf.as_slice()).unwrap());
+    let tags = tags.owning();
+    std::mem::drop(buf);
+    assert_eq!(tags.get("+a").unwrap(), "b c");
+    assert_eq!(tags.len(), 2);
+    let mut tags = Tags::parse(Word::from_str("x=1;y=2"));
+    tags.retain(|k, _| k == "y");
+    assert_eq!(tags.to_string(), "@y=2");
+    assert_eq!(tags, Tags::parse(Word::from_str("y=2")));
+}
+
+#[test]
+pub fn len_bytes() {
+    use super::ClientMsg;
+    use crate::string::NoNul;
+    use std::num::NonZeroUsize;
+    let cases = [
+        "PING",
+        "PRIVMSG #chan word",
+        "PRIVMSG #chan :some words",
+        "@+typing=active TAGMSG #chan",
+        r"@+draft/reply=abc;+example=a\sb\:c;+flag PRIVMSG #chan :reply",
+    ];
+    for case in cases {
+        let msg = ClientMsg::parse(case).unwrap();
+        let (tags, rest) = msg.len_bytes();
+        // The last argument is always counted with a colon.
+        let expected = if case.contains(" :") || msg.args.is_empty() { 0 } else { 1 };
+        assert_eq!(tags + rest, case.len() + expected, "wrong length for: {case}");
+        assert_eq!(tags, case.find(' ').filter(|_| case.starts_with('@')).map_or(0, |i| i + 1));
+    }
+    let msg = irc_msg!("@time=now :nick!user@host PRIVMSG #chan :hello world");
+    assert_eq!(msg.len_bytes(), (10, 42));
+    // Boundaries.
+    let source_len = NonZeroUsize::new(20).unwrap();
+    let base = ClientMsg::parse("PRIVMSG #chan :a").unwrap().len_bytes().1 - 1;
+    let with_text = |len: usize| ClientMsg::parse(format!("PRIVMSG #chan :{}", "a".repeat(len)));
+    let msg = with_text(508 - 20 - base).unwrap();
+    assert_eq!(msg.len_bytes(), (0, 488));
+    assert!(msg.fits(source_len));
+    assert!(!with_text(509 - 20 - base).unwrap().fits(source_len));
+    let mut msg = ClientMsg::parse("TAGMSG #chan").unwrap();
+    // The @, the key, the =, and the trailing space.
+    let value = "v".repeat(ClientMsg::MAX_TAGS_LEN - 6);
+    let key = crate::string::Key::from_str("+aa");
+    msg.tags.edit().insert_pair(key.clone(), NoNul::from_bytes(value.clone()).unwrap());
+    assert_eq!(msg.len_bytes().0, ClientMsg::MAX_TAGS_LEN);
+    assert!(msg.fits(source_len));
+    // Escaping the semicolon takes two bytes.
+    let value = NoNul::from_bytes(value + ";").unwrap();
+    msg.tags.edit().insert_pair(key, value);
+    assert_eq!(msg.len_bytes().0, ClientMsg::MAX_TAGS_LEN + 2);
+    assert!(!msg.fits(source_len));
+}
+
+#[test]
+pub fn tags_budget() {
+    use super::Tags;
+    use crate::{error::TagBudgetError, string::Key};
+    let mut tags = Tags::new();
+    let mut edit = tags.edit();
+    // "@+a=b\sc" is 8 bytes long.
+    assert_eq!(edit.try_insert_pair(Key::from_str("+a"), Line::from_str("b c"), 8), Ok(None));
+    assert_eq!(edit.len_bytes(), 8);
+    let err = edit.try_insert_pair(Key::from_str("+d"), Line::from_str(""), 10);
+    assert_eq!(err, Err(TagBudgetError { len: 11, budget: 10 }));
+    // Replacing a value only counts the difference.
+    let old = edit.try_insert_pair(Key::from_str("+a"), Line::from_str("bcd"), 8).unwrap();
+    assert_eq!(old.unwrap(), "b c");
+    assert!(edit.try_insert_pair(Key::from_str("+a"), Line::from_str("bcdef"), 8).is_err());
+    std::mem::drop(edit);
+    assert_eq!(tags.len_bytes(), 7);
+    assert_eq!(tags.get("+a").unwrap(), "bcd");
+    assert_eq!(tags.len(), 1);
+}
+
+#[test]
+pub fn reply_category() {
+    use super::{Numeric, ReplyCategory as C, ServerMsgKindRaw};
+    use crate::string::Cmd;
+    let table = [
+        (1, C::Welcome),
+        (4, C::Welcome),
+        (5, C::Isupport),
+        (10, C::Unknown),
+        (302, C::Unknown),
+        (311, C::Whois),
+        (312, C::Whois),
+        (313, C::Whois),
+        (317, C::Whois),
+        (318, C::Whois),
+        (319, C::Whois),
+        (330, C::Whois),
+        (338, C::Whois),
+        (671, C::Whois),
+        (332, C::Channel),
+        (333, C::Channel),
+        (353, C::Channel),
+        (366, C::Channel),
+        (376, C::Motd),
+        (422, C::Motd),
+        (401, C::Error),
+        (403, C::Error),
+        (433, C::Error),
+        (464, C::Error),
+        (475, C::Error),
+        (730, C::Monitor),
+        (734, C::Monitor),
+        (900, C::Sasl),
+        (904, C::Sasl),
+        (908, C::Sasl),
+        (999, C::Unknown),
+    ];
+    for (num, category) in table {
+        let num = Numeric::from_int(num).unwrap();
+        assert_eq!(num.category(), category, "wrong category for {num}");
+    }
+    let fail = ServerMsgKindRaw::Cmd(Cmd::from_str("FAIL"));
+    assert_eq!(fail.category(), C::Error);
+    let privmsg = ServerMsgKindRaw::Cmd(Cmd::from_str("PRIVMSG"));
+    assert_eq!(privmsg.category(), C::Unknown);
+    assert!(!privmsg.is_final_for(&Cmd::from_str("PRIVMSG")));
+}
+
+#[test]
+pub fn reply_is_final_for() {
+    use super::Numeric;
+    use crate::string::Cmd;
+    let table = [
+        ("WHOIS", 318, true),
+        ("WHOIS", 311, false),
+        ("WHOIS", 401, false),
+        ("WHOWAS", 369, true),
+        ("NAMES", 366, true),
+        ("JOIN", 366, true),
+        ("JOIN", 353, false),
+        ("STATS", 219, true),
+        ("WHO", 315, true),
+        ("MONITOR", 733, true),
+        ("MOTD", 422, true),
+        ("PRIVMSG", 318, false),
+    ];
+    for (cmd, num, is_final) in table {
+        let num = Numeric::from_int(num).unwrap();
+        assert_eq!(num.is_final_for(&Cmd::from_str(cmd)), is_final, "{num} for {cmd}");
+    }
+}
+
+#[test]
+pub fn send_to_checked() {
+    use super::{ClientCodec, ClientMsg};
+    use crate::{
+        error::{InvalidString, ParseError},
+        string::{Bytes, Cmd, Key, NoNul},
+    };
+    let parse_error =
+        |e: std::io::Error| *e.into_inner().unwrap().downcast::<ParseError>().unwrap();
+    let mut buf = b"PING :x\r\n".to_vec();
+    let mut sent = Vec::new();
+    let mut msg = ClientMsg::new_cmd(Cmd::from_str("TAGMSG"));
+    msg.args.edit().add_word(crate::string::Arg::from_str("#chan"));
+    ClientCodec::send_to_checked(&msg, &mut sent, &mut buf).unwrap();
+    assert_eq!(sent, b"PING :x\r\nTAGMSG #chan\r\n");
+    assert!(buf.is_empty());
+    // Over-long tags.
+    let value = NoNul::from_bytes("a".repeat(ClientMsg::MAX_LEN)).unwrap();
+    msg.tags.edit().insert_pair(Key::from_str("+example"), value);
+    let error = ClientCodec::send_to_checked(&msg, &mut sent, &mut buf).unwrap_err();
+    assert!(matches!(parse_error(error), ParseError::TooLong));
+    assert!(buf.is_empty());
+    // Line breaks smuggled into an argument.
+    let mut msg = ClientMsg::new_cmd(Cmd::from_str("PRIVMSG"));
+    let injected = unsafe { Line::from_unchecked(Bytes::from_str("hi\r\nQUIT :bye")) };
+    msg.args.edit().add_word(crate::string::Arg::from_str("#chan"));
+    msg.args.edit().add(injected);
+    buf.extend_from_slice(b"PING :x\r\n");
+    let error = ClientCodec::send_to_checked(&msg, &mut sent, &mut buf).unwrap_err();
+    assert!(matches!(parse_error(error), ParseError::InvalidLine(InvalidString::Byte(b'\r'))));
+    assert_eq!(buf, b"PING :x\r\n");
+    assert_eq!(sent, b"PING :x\r\nTAGMSG #chan\r\n");
+}
+
+#[test]
+pub fn codec_max_len() {
+    use super::{ClientCodec, ClientMsg, ServerCodec, ServerMsg};
+    use crate::{error::EncodeTooLong, string::Cmd};
+    let long = format!(":irc.example.com NOTICE * :{}\r\n", "a".repeat(ServerMsg::MAX_LEN));
+    let mut buf = Vec::new();
+    let error = ClientCodec::read_owning_from(&mut long.as_bytes(), &mut buf).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    let codec = ClientCodec { max_len_read: Some(long.len()), ..ClientCodec::new() };
+    buf.clear();
+    let msg = codec.read_borrowing(&mut long.as_bytes(), &mut buf).unwrap();
+    assert_eq!(msg.args.split_last().1.unwrap().len(), ServerMsg::MAX_LEN);
+    // Writing.
+    let codec = ServerCodec { max_len_write: Some(16), ..ServerCodec::new() };
+    let mut msg = ServerMsg::new_cmd(Cmd::from_str("PING"));
+    msg.args.edit().add_word(crate::string::Arg::from_str("0123456789"));
+    let (mut sent, mut buf) = (Vec::new(), Vec::new());
+    let error = codec.send(&msg, &mut sent, &mut buf).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    let error = EncodeTooLong::from_io(&error).unwrap();
+    assert_eq!(*error, EncodeTooLong { len: 17, max_len: 16 });
+    assert!(buf.is_empty() && sent.is_empty());
+    msg.args.edit().clear();
+    msg.args.edit().add_word(crate::string::Arg::from_str("012345678"));
+    codec.send(&msg, &mut sent, &mut buf).unwrap();
+    assert_eq!(sent, b"PING 012345678\r\n");
+    assert_eq!(ServerCodec::new().write_limit(), ServerMsg::MAX_LEN);
+    assert_eq!(ClientCodec::default().write_limit(), ClientMsg::MAX_LEN);
+}
+
+fn overlong_stream() -> Vec<u8> {
+    let mut stream = b":a PRIVMSG #c :".to_vec();
+    stream.extend(std::iter::repeat(b'x').take(20_000));
+    stream.extend_from_slice(b"\r\n:a PRIVMSG #c :after\r\n");
+    stream
+}
+
+#[test]
+pub fn codec_overlong() {
+    use super::{ClientCodec, OverlongPolicy};
+    use crate::error::ParseError;
+    let parse_error =
+        |e: std::io::Error| *e.into_inner().unwrap().downcast::<ParseError>().unwrap();
+    let stream = overlong_stream();
+    let mut buf = Vec::new();
+    let mut read = stream.as_slice();
+    let codec = ClientCodec::new();
+    let error = codec.read_owning(&mut read, &mut buf).unwrap_err();
+    assert!(matches!(parse_error(error), ParseError::TooLong));
+    let codec = ClientCodec { overlong: OverlongPolicy::Skip, ..ClientCodec::new() };
+    let (mut read, mut buf) = (stream.as_slice(), Vec::new());
+    let msg = codec.read_owning(&mut read, &mut buf).unwrap();
+    assert_eq!(msg.args.split_last().1.unwrap(), "after");
+    let codec = ClientCodec { overlong: OverlongPolicy::Truncate, ..ClientCodec::new() };
+    let (mut read, mut buf) = (stream.as_slice(), Vec::new());
+    let msg = codec.read_borrowing(&mut read, &mut buf).unwrap();
+    let text = msg.args.split_last().1.unwrap();
+    assert_eq!(text.len(), ServerMsg::MAX_LEN - b":a PRIVMSG #c :".len());
+    assert!(text.iter().all(|b| *b == b'x'));
+    buf.clear();
+    let msg = codec.read_borrowing(&mut read, &mut buf).unwrap();
+    assert_eq!(msg.args.split_last().1.unwrap(), "after");
+}
+
+#[test]
+pub fn server_reply() {
+    use super::{ClientMsg, Numeric, SharedSource, Source};
+    use crate::string::{Arg, Cmd, Nick};
+    let source = SharedSource::new(Source::new_server(Nick::from_str("irc.example.com")));
+    let num = Numeric::from_int(332).unwrap();
+    let mut msg =
+        ServerMsg::new_reply(num, source.clone(), Nick::from_str("me"), Line::from_str("A topic"));
+    msg.args.edit().add_before_last(Arg::from_str("#chan"));
+    assert!(msg.is_numeric());
+    assert_eq!(msg.numeric(), Some(num));
+    assert_eq!(msg.to_string(), ":irc.example.com 332 me #chan :A topic");
+    assert_eq!(msg.args.split_last().0, ["me", "#chan"]);
+    // One-word trailing text is still the last argument.
+    let num = Numeric::from_int(1).unwrap();
+    let msg = ServerMsg::new_reply(num, source, Nick::from_str("me"), Line::from_str("Welcome"));
+    assert_eq!(msg.to_string(), ":irc.example.com 001 me Welcome");
+    assert_eq!(msg.args.split_last().1.unwrap(), "Welcome");
+    let msg = ServerMsg::new_cmd(Cmd::from_str("PING"));
+    assert!(!msg.is_numeric());
+    assert_eq!(msg.numeric(), None);
+    let args = ["#a", "#b"].map(Arg::from_str);
+    let msg = ClientMsg::new_cmd_args(Cmd::from_str("JOIN"), args);
+    assert_eq!(msg.to_string(), "JOIN #a #b");
+}
+
+#[test]
+pub fn source_sharing() {
+    use super::{SharedSource, Source, SourceCache};
+    use crate::string::Word;
+    let owned = Source::parse(Word::from_bytes("nick!user@host".to_owned()).unwrap()).unwrap();
+    assert!(owned.is_owning());
+    let shared = SharedSource::new(owned);
+    let extended = shared.clone().owning();
+    assert!(std::ptr::eq(&*shared, &*extended));
+    let borrowed = Source::parse(Word::from_str("nick!user@host")).unwrap();
+    assert!(!borrowed.is_owning());
+    let msg = ServerMsg::parse(":nick!user@host PRIVMSG #chan :hi").unwrap();
+    let mut cache = SourceCache::new(2);
+    let a = msg.clone().owning_cached(&mut cache).source.unwrap();
+    let b = msg.owning_cached(&mut cache).source.unwrap();
+    assert!(std::ptr::eq(&*a, &*b));
+    assert!(std::ptr::eq(&*a, &*cache.get(&borrowed)));
+    assert_eq!(cache.len(), 1);
+    for name in ["x", "y"] {
+        cache.get(&Source::new_server(crate::string::Nick::from_str(name)));
+    }
+    assert_eq!(cache.len(), 2);
+    assert!(!std::ptr::eq(&*a, &*cache.get(&borrowed)));
+    let mut cache = SourceCache::new(0);
+    cache.get(&borrowed);
+    assert!(cache.is_empty());
+}
+
+#[test]
+pub fn parse_detailed() {
+    use super::ClientMsg;
+    use crate::error::{MsgPart, ParseError};
+    let err = ServerMsg::parse_detailed("@a=b :nick!user@ CMD arg").unwrap_err();
+    assert_eq!((err.offset, err.part), (6, MsgPart::Source));
+    assert!(matches!(err.error, ParseError::InvalidHost(_)));
+    let err = ServerMsg::parse_detailed(":nick  C!D arg").unwrap_err();
+    assert_eq!((err.offset, err.part), (7, MsgPart::Kind));
+    let err = ServerMsg::parse_detailed("@a=b :nick ").unwrap_err();
+    assert_eq!((err.offset, err.part), (11, MsgPart::Kind));
+    let err = ClientMsg::parse_detailed("PRIVMSG #a :b\r\nQUIT".as_bytes()).unwrap_err();
+    assert_eq!((err.offset, err.part), (13, MsgPart::Line));
+    assert!(matches!(err.error, ParseError::InvalidLine(_)));
+    // Client messages ignore sources.
+    assert!(ClientMsg::parse_detailed(":nick!user@ CMD").is_ok());
+}
+
+mod wire {
+    use crate::{
+        ircmsg::{ClientMsg, ServerMsg, SharedSource, Source, Tags},
+        string::{Arg, Cmd, Key, Line, NoNul, Word},
+    };
+    use proptest::prelude::*;
+
+    fn tags() -> impl Strategy<Value = Tags<'static>> {
+        let tags =
+            proptest::collection::vec(("\\+?[a-z][a-z0-9./-]{0,8}", "[^\0\r\n]{0,10}"), 0..4);
+        tags.prop_map(|tags| {
+            let mut retval = Tags::new();
+            let mut edit = retval.edit();
+            for (key, value) in tags {
+                edit.insert_pair(Key::from_bytes(key).unwrap(), NoNul::from_bytes(value).unwrap());
+            }
+            std::mem::drop(edit);
+            retval
+        })
+    }
+
+    fn args() -> impl Strategy<Value = (Vec<Arg<'static>>, Option<Line<'static>>)> {
+        let words = proptest::collection::vec("[^\0\r\n :][^\0\r\n ]{0,8}", 0..4);
+        let last = proptest::option::of("[^\0\r\n]{0,20}");
+        (words, last).prop_map(|(words, last)| {
+            let words = words.into_iter().map(|word| Arg::from_bytes(word).unwrap()).collect();
+            (words, last.map(|last| Line::from_bytes(last).unwrap()))
+        })
+    }
+
+    fn source() -> impl Strategy<Value = Option<SharedSource<'static>>> {
+        let userhost = proptest::option::of(("[a-z~]{1,8}", "[a-z0-9.:/]{1,12}"));
+        let source = ("[A-Za-z][A-Za-z0-9^|-]{0,8}", userhost).prop_map(|(nick, userhost)| {
+            let source = match userhost {
+                Some((user, host)) => format!("{nick}!{user}@{host}"),
+                None => nick,
+            };
+            SharedSource::new(Source::parse(Word::from_bytes(source).unwrap()).unwrap().owning())
+        });
+        proptest::option::of(source)
+    }
+
+    proptest! {
+        #[test]
+        fn clientmsg_display_roundtrip(cmd in "[A-Z]{1,12}", tags in tags(), args in args()) {
+            let mut msg = ClientMsg::new_cmd(Cmd::from_bytes(cmd).unwrap());
+            msg.tags = tags;
+            let msg = msg.with_args(args.0, args.1);
+            let string = msg.to_string();
+            prop_assert_eq!(ClientMsg::parse_detailed(string.as_str()).unwrap().owning(), msg);
+        }
+
+        #[test]
+        fn servermsg_display_roundtrip(
+            kind in "[A-Z]{1,12}|[0-9]{3}",
+            source in source(),
+            tags in tags(),
+            args in args(),
+        ) {
+            let mut msg = ServerMsg::parse(kind).unwrap().owning();
+            msg.source = source;
+            msg.tags = tags;
+            let msg = msg.with_args(args.0, args.1);
+            let string = msg.to_string();
+            prop_assert_eq!(ServerMsg::parse_detailed(string.as_str()).unwrap().owning(), msg);
+        }
+    }
+}
+
+#[test]
+pub fn well_known_tags() {
+    use super::ServerMsg;
+    use std::time::{Duration, SystemTime};
+    let msg = ServerMsg::parse(
+        "@account=alice;msgid=abc123;time=2024-02-29T12:34:56.789Z :a!b@c PRIVMSG #x :hi",
+    )
+    .unwrap();
+    assert_eq!(msg.account_tag().unwrap(), "alice");
+    assert_eq!(msg.msgid().unwrap(), "abc123");
+    let expected = SystemTime::UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+    assert_eq!(msg.server_time(), Some(expected));
+    let msg = ServerMsg::parse("@account=a\\sb;time=bogus PING x").unwrap();
+    assert_eq!(msg.account_tag(), None);
+    assert_eq!(msg.msgid(), None);
+    assert_eq!(msg.server_time(), None);
+    assert_eq!(msg.server_time_raw().unwrap(), "bogus");
+}
+
+#[test]
+pub fn server_time() {
+    use super::server::parse_server_time;
+    use std::time::{Duration, SystemTime};
+    let at = |secs: u64, nanos: u32| Some(SystemTime::UNIX_EPOCH + Duration::new(secs, nanos));
+    assert_eq!(parse_server_time(b"1970-01-01T00:00:00.000Z"), at(0, 0));
+    // Missing or differently-precise fractional seconds.
+    assert_eq!(parse_server_time(b"2000-01-01T00:00:00Z"), at(946_684_800, 0));
+    assert_eq!(parse_server_time(b"2000-01-01T00:00:00.5Z"), at(946_684_800, 500_000_000));
+    assert_eq!(parse_server_time(b"2000-01-01T00:00:00.000000001Z"), at(946_684_800, 1));
+    // Leap seconds roll over into the next minute.
+    assert_eq!(parse_server_time(b"2016-12-31T23:59:60.000Z"), at(1_483_228_800, 0));
+    assert_eq!(parse_server_time(b"2016-12-31T23:59:59.999Z"), at(1_483_228_799, 999_000_000));
+    // Before the epoch.
+    let before = SystemTime::UNIX_EPOCH - Duration::from_millis(500);
+    assert_eq!(parse_server_time(b"1969-12-31T23:59:59.500Z"), Some(before));
+    for bad in [
+        "2023-02-29T00:00:00.000Z",
+        "1900-02-29T00:00:00.000Z",
+        "2024-13-01T00:00:00.000Z",
+        "2024-00-01T00:00:00.000Z",
+        "2024-04-31T00:00:00.000Z",
+        "2024-01-01T24:00:00.000Z",
+        "2024-01-01T00:60:00.000Z",
+        "2024-01-01T00:00:61.000Z",
+        "2024-01-01T00:00:00.000",
+        "2024-01-01T00:00:00.Z",
+        "2024-01-01T00:00:00.0000000000Z",
+        "2024-01-01 00:00:00.000Z",
+        "2024-1-01T00:00:00.000Z",
+        "+024-01-01T00:00:00.000Z",
+    ] {
+        assert_eq!(parse_server_time(bad.as_bytes()), None, "{bad}");
+    }
+    assert!(parse_server_time(b"2000-02-29T00:00:00Z").is_some());
+}
+
+#[test]
+fn redacted() {
+    use super::{redact_cmd, ClientMsg};
+    use crate::{names::cmd::AUTHENTICATE, string::Cmd};
+    let mut msg = ClientMsg::new(AUTHENTICATE);
+    msg.args.edit().add_word(crate::string::Arg::from_str("aHVudGVyMgBodW50ZXIyAGh1bnRlcjI="));
+    let redacted = msg.to_redacted_string();
+    assert_eq!(redacted, "AUTHENTICATE <?>");
+    assert!(!redacted.contains("aHVudGVy"));
+    let msg = irc_msg!(":server AUTHENTICATE aHVudGVyMg==");
+    assert_eq!(msg.to_redacted_string(), ":server AUTHENTICATE <?>");
+    let msg = ClientMsg::parse("OPER admin :hunter2").unwrap();
+    assert_eq!(msg.to_redacted_string(), "OPER admin <?>");
+    let msg = ClientMsg::parse("PRIVMSG nickserv :IDENTIFY me hunter2").unwrap();
+    assert_eq!(msg.to_redacted_string(), "PRIVMSG nickserv :<?>");
+    let msg = ClientMsg::parse("PRIVMSG #chan :identify yourself").unwrap();
+    assert_eq!(msg.to_redacted_string(), msg.to_string());
+    let msg = ClientMsg::parse("NS REGISTER hunter2 me@example.com").unwrap();
+    assert_eq!(msg.to_redacted_string(), msg.to_string());
+    redact_cmd(Cmd::from_str("NS"), 1);
+    assert_eq!(msg.to_redacted_string(), "NS REGISTER <?> <?>");
+}
+
+#[test]
+fn convert_client_server() {
+    use super::{ClientMsg, SharedSource, Source};
+    use crate::{error::ParseError, string::Nick};
+    let client = ClientMsg::parse("@+draft/reply=a PRIVMSG #chan :hello world").unwrap();
+    let ptr = client.args.words()[0].as_bytes().as_ptr();
+    let source = SharedSource::new(Source::new_server(Nick::from_str("nick")));
+    let server = ServerMsg::from_client(client.clone(), Some(source));
+    assert_eq!(server.to_string(), "@+draft/reply=a :nick PRIVMSG #chan :hello world");
+    assert_eq!(server.args.words()[0].as_bytes().as_ptr(), ptr);
+    let back = ClientMsg::from_server(server).unwrap();
+    assert_eq!(back, client);
+    assert_eq!(back.args.words()[0].as_bytes().as_ptr(), ptr);
+    let server: ServerMsg<'_> = client.clone().into();
+    assert_eq!(server, ServerMsg::parse("@+draft/reply=a PRIVMSG #chan :hello world").unwrap());
+    assert_eq!(ClientMsg::try_from(server).unwrap(), client);
+    let numeric = irc_msg!(":server 001 nick :Welcome");
+    assert!(matches!(ClientMsg::from_server(numeric), Err(ParseError::InvalidKind(_))));
+}
+
+#[test]
+fn names_reply() {
+    use super::{ChannelVisibility, NamesReply};
+    use crate::{
+        names::NameMap,
+        state::{Mode, ServerChanModes},
+        string::{Key, Word},
+    };
+    let mut isupport = NameMap::new();
+    isupport.edit().insert((Key::from_str("PREFIX"), Word::from_str("(ov)@+")), ());
+    let chanmodes = ServerChanModes::from_isupport(&isupport);
+    let line = ":example.com 353 me @ #chan :@+alice +bob!b@host.example ~carol  dave";
+    let msg = irc_msg!(line);
+    let reply = NamesReply::parse(&msg, &chanmodes).unwrap();
+    assert_eq!(reply.channel, "#chan");
+    assert_eq!(reply.visibility, ChannelVisibility::Secret);
+    let [alice, bob, carol, dave] = reply.members.as_slice() else {
+        panic!("wrong number of members: {:?}", reply.members);
+    };
+    let modes: Vec<_> = alice.status.iter().map(|(mode, _)| *mode).collect();
+    assert_eq!(modes, [Mode::new(b'o').unwrap(), Mode::new(b'v').unwrap()]);
+    assert!(alice.modes().contains(Mode::new(b'v').unwrap()));
+    assert_eq!(bob.source.userhost.as_ref().unwrap().host, "host.example");
+    assert!(carol.unknown_prefix);
+    assert_eq!(carol.source.nick, "~carol");
+    assert!(!dave.unknown_prefix && dave.status.is_empty());
+    let names: Vec<_> = reply.members.iter().map(ToString::to_string).collect();
+    assert_eq!(names.join(" "), "@+alice +bob!b@host.example ~carol dave");
+    let msg = irc_msg!(":example.com 366 me #chan :End of /NAMES list.");
+    assert!(NamesReply::parse(&msg, &chanmodes).is_err());
+}
+
+#[cfg(feature = "arbitrary")]
+mod fuzz {
+    use crate::ircmsg::{fuzz, ClientMsg, ServerMsg};
+    use arbitrary::{Arbitrary, Unstructured};
+    use proptest::prelude::*;
+
+    /// Bytes that are mostly made out of message syntax.
+    fn msg_bytes() -> impl Strategy<Value = Vec<u8>> {
+        prop_oneof![
+            "[@:;=!. a-zA-Z0-9\\\\\r\n\0\u{e9}]{0,40}".prop_map(String::into_bytes),
+            proptest::collection::vec(any::<u8>(), 0..40),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn parse_servermsg(bytes in msg_bytes()) {
+            fuzz::parse_server_msg(&bytes);
+        }
+
+        #[test]
+        fn parse_clientmsg(bytes in msg_bytes()) {
+            fuzz::parse_client_msg(&bytes);
+        }
+
+        #[test]
+        fn parse_tags(bytes in msg_bytes()) {
+            fuzz::parse_tags(&bytes);
+        }
+
+        #[test]
+        fn parse_args(bytes in msg_bytes()) {
+            fuzz::parse_args(&bytes);
+        }
+
+        #[test]
+        fn arbitrary_servermsg(data in proptest::collection::vec(any::<u8>(), 0..512)) {
+            let msg = ServerMsg::arbitrary(&mut Unstructured::new(&data)).unwrap();
+            fuzz::server_msg_roundtrip(&msg);
+        }
+
+        #[test]
+        fn arbitrary_clientmsg(data in proptest::collection::vec(any::<u8>(), 0..512)) {
+            let msg = ClientMsg::arbitrary(&mut Unstructured::new(&data)).unwrap();
+            fuzz::client_msg_roundtrip(&msg);
+        }
+    }
 }
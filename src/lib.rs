@@ -16,7 +16,7 @@
 #![deny(rustdoc::invalid_rust_codeblocks)]
 #![deny(rustdoc::private_intra_doc_links)]
 #![deny(unused_unsafe)]
-#![cfg_attr(doc_unstable, feature(doc_auto_cfg))]
+#![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
 #[macro_use]
 mod macros;
@@ -24,10 +24,15 @@ mod macros;
 #[cfg(feature = "client")]
 pub mod client;
 pub mod error;
+pub mod info;
 pub mod ircmsg;
 pub mod names;
 pub mod owning;
 pub mod state;
 pub mod string;
+#[cfg(any(test, feature = "testutils"))]
+pub mod testutils;
 
 pub(crate) mod util;
+
+pub use info::build_info;
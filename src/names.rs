@@ -13,6 +13,9 @@
 pub mod cap;
 pub mod cmd;
 pub mod isupport;
+pub mod tag;
+#[cfg(test)]
+mod tests;
 mod types;
 
 pub use types::*;
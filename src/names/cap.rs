@@ -62,8 +62,11 @@ macro_rules! defn_cap {
 
 defn_cap!(ACCOUNT_NOTIFY = "account-notify");
 defn_cap!(ACCOUNT_TAG = "account-tag");
+defn_cap!(AWAY_NOTIFY = "away-notify");
 defn_cap!(BATCH = "batch");
 defn_cap!(CHGHOST = "chghost");
+defn_cap!(DRAFT_CHATHISTORY = "draft/chathistory");
+defn_cap!(DRAFT_PRE_AWAY = "draft/pre-away");
 defn_cap!(ECHO_MESSAGE = "echo-message");
 defn_cap!(EXTENDED_JOIN = "extended-join");
 defn_cap!(EXTENDED_MONITOR = "extended-monitor");
@@ -79,24 +82,45 @@ defn_cap!(STANDARD_REPLIES = "standard-replies");
 defn_cap!(STS = "sts");
 defn_cap!(USERHOST_IN_NAMES = "userhost-in-names");
 
-impl NameValued<Cap> for SASL {
-    type Value<'a> = BTreeSet<Word<'a>>;
-
-    fn from_union<'a>(
-        input: &<Cap as super::NameClass>::Union<'a>,
-    ) -> Result<Self::Value<'a>, crate::error::ParseError> {
+impl SASL {
+    /// Parses the mechanisms listed in a value of this capability,
+    /// keeping the order in which the server listed them.
+    ///
+    /// Mechanism names are uppercased, and empty names are skipped.
+    pub fn mechs_in_order<'a>(mechs_raw: &Word<'a>) -> Vec<Word<'a>> {
         use crate::string::tf::AsciiCasemap;
-        let (_, mechs_raw) = input;
         let mut splitter = Splitter::new(mechs_raw.clone());
-        let mut names = BTreeSet::new();
+        let mut names = Vec::new();
         while !splitter.is_empty() {
             let mut name = splitter.save_end().until_byte_eq(b',').rest_or_default::<Word>();
             if !name.is_empty() {
                 name.transform(AsciiCasemap::<true>);
-                names.insert(name);
+                names.push(name);
             }
             splitter.next_byte();
         }
-        Ok(names)
+        names
+    }
+}
+
+impl NameValued<Cap> for SASL {
+    type Value<'a> = BTreeSet<Word<'a>>;
+
+    fn from_union<'a>(
+        input: &<Cap as super::NameClass>::Union<'a>,
+    ) -> Result<Self::Value<'a>, crate::error::ParseError> {
+        let (_, mechs_raw) = input;
+        Ok(SASL::mechs_in_order(mechs_raw).into_iter().collect())
+    }
+}
+
+impl NameValued<Cap> for STS {
+    type Value<'a> = crate::state::StsPolicy;
+
+    fn from_union<'a>(
+        input: &<Cap as super::NameClass>::Union<'a>,
+    ) -> Result<Self::Value<'a>, crate::error::ParseError> {
+        let (_, policy_raw) = input;
+        crate::state::StsPolicy::parse(policy_raw)
     }
 }
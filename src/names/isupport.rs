@@ -4,13 +4,46 @@
 
 use std::num::{NonZeroU16, NonZeroU32};
 
-use super::{ISupport, Name, NameValued};
-use crate::state::{Mode, ModeTypes, StatusModes};
+use super::{ISupport, Name, NameMap, NameValued};
+use crate::state::{
+    ByteSet, ExtBans, ISupportValue, KeyValueList, ListLimits, Mode, ModeSet, ModeTypes,
+    StatusModes, TargetLimits,
+};
 use crate::{
     error::ParseError,
-    string::{Bytes, Key, Word},
+    string::{tf::IrcCasemap, Arg, Bytes, Key, Splitter, Word},
 };
 
+/// Applies the tokens from an `RPL_ISUPPORT` (005) message to `isupport`.
+///
+/// `tokens` should be the arguments of the message after the client's nick.
+/// Tokens of the form `KEY` or `KEY=value` are added, replacing any existing value,
+/// while tokens of the form `-KEY` remove `KEY`. Malformed tokens are skipped.
+pub fn apply_tokens(isupport: &mut NameMap<ISupport>, tokens: &[Arg<'_>]) {
+    let mut edit = isupport.edit();
+    for token in tokens {
+        let mut splitter = Splitter::new(token.clone().owning());
+        let remove = splitter.peek_byte() == Some(b'-');
+        if remove {
+            splitter.next_byte();
+        }
+        let Ok(key) = splitter.string::<Key>(false) else {
+            continue;
+        };
+        if remove {
+            if splitter.is_empty() {
+                edit.remove_raw(&key);
+            }
+            continue;
+        }
+        if splitter.next_byte().is_some_and(|b| b != b'=') {
+            // Weirdness in an ISUPPORT token. Skip it.
+            continue;
+        }
+        edit.insert((key, splitter.rest_or_default::<Word>()), ());
+    }
+}
+
 macro_rules! defn_isupport {
     ($key:ident: $value:ty = |$arg:ident| $parse:expr $(, $doc:literal)*) => {
         #[doc = concat!("The `", stringify!($key), "` ISUPPORT token.")]
@@ -78,7 +111,7 @@ macro_rules! defn_isupport {
                 match do_parse(raw) {
                     Ok(rv) => Ok(rv),
                     Err(e) => Err(ParseError::InvalidField(
-                        format!("{} value", stringify!($name)).into(),
+                        format!("{} value", stringify!($key)).into(),
                         e,
                     )),
                 }
@@ -173,6 +206,73 @@ isupport_mode! {
     INVEX = b'I'
 }
 
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+fn split_entry(entry: &[u8]) -> Result<(&[u8], &[u8]), BoxError> {
+    let Some(idx) = entry.iter().position(|b| *b == b':') else {
+        return Err(format!("missing ':' in `{}`", entry.escape_ascii()).into());
+    };
+    Ok((&entry[..idx], &entry[idx + 1..]))
+}
+
+defn_isupport!(
+    CASEMAPPING: IrcCasemap = |arg| {
+        IrcCasemap::from_name(arg.as_bytes())
+            .ok_or_else(|| format!("unknown casemapping `{}`", arg.as_bytes().escape_ascii()).into())
+    },
+    "",
+    "Only the casemappings supported by [`IrcCasemap`] can be parsed."
+);
+defn_isupport!(
+    CHANLIMIT: KeyValueList<ByteSet, Option<NonZeroU32>> = |arg| KeyValueList::try_from_bytes(arg),
+    "",
+    "Each key is a set of channel types that share the limit.",
+    "Channel types that are listed without a limit may be joined without limit."
+);
+defn_isupport!(
+    CHANTYPES: ByteSet = |arg| ByteSet::try_from_bytes(arg),
+    "",
+    "An empty value means that the server does not support channels."
+);
+defn_isupport!(
+    ELIST: ByteSet = |arg| {
+        let mut retval = ByteSet::new();
+        for byte in arg.iter().copied() {
+            if !byte.is_ascii_alphabetic() {
+                return Err(format!("invalid flag `{}`", byte.escape_ascii()).into());
+            }
+            retval.insert(byte.to_ascii_uppercase());
+        }
+        Ok(retval)
+    },
+    "",
+    "The flags are normalized to uppercase."
+);
+defn_isupport!(
+    MAXLIST: ListLimits = |arg| {
+        let mut retval = ListLimits::new();
+        for entry in arg.split(|b| *b == b',').filter(|e| !e.is_empty()) {
+            let (modes, limit) = split_entry(entry)?;
+            let mut set = ModeSet::new();
+            for mode in modes.iter().copied() {
+                let Some(mode) = Mode::new(mode) else {
+                    return Err(format!("invalid mode letter `{}`", mode.escape_ascii()).into());
+                };
+                set.set(mode);
+            }
+            let limit = std::str::from_utf8(limit)?.parse()?;
+            retval.insert(set, limit);
+        }
+        Ok(retval)
+    }
+);
+defn_isupport!(EXTBAN: ExtBans = |arg| ExtBans::try_from_bytes(arg));
+defn_isupport!(STATUSMSG: ByteSet = |arg| ByteSet::try_from_bytes(arg));
+defn_isupport!(
+    TARGMAX: TargetLimits = |arg| TargetLimits::try_from_bytes(arg),
+    "",
+    "Commands that are listed without a limit accept any number of targets."
+);
 defn_isupport!(NETWORK: Word<'static> = |arg| Ok(arg.clone().owning()));
 defn_isupport!(CHANMODES: ModeTypes = |arg| Ok(ModeTypes::parse(arg.as_bytes()).0));
 defn_isupport!(PREFIX: StatusModes = |arg| Ok(StatusModes::parse(arg.as_bytes())?));
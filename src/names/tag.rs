@@ -0,0 +1,154 @@
+//! IRCv3 message tags.
+//!
+//! To maintain consistency, these names are all uppercased
+//! from their official versions.
+//!
+//! Tags not defined here can be parsed by implementing [`Name`] and [`NameValued`]
+//! for [`MsgTag`] on a new zero-sized type.
+
+use super::{MsgTag, Name, NameValued};
+use crate::{
+    error::ParseError,
+    string::{Arg, Bytes, Key, NoNul},
+};
+use std::time::SystemTime;
+
+macro_rules! defn_tag {
+    ($key:ident = $value:literal: $value_ty:ty = |$arg:ident| $parse:expr $(, $doc:literal)*) => {
+        #[doc = concat!("The `", $value, "` message tag.")]
+        $(#[doc = $doc])*
+        #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+        pub struct $key;
+        impl $key {
+            /// The tag key `self` stands in for as a [`Key`].
+            #[allow(clippy::declare_interior_mutable_const)]
+            pub const NAME: Key<'static> = unsafe { Key::from_unchecked(Bytes::from_str($value)) };
+            /// Returns a reference to a static [`Key`] representing `self`'s name.
+            pub fn as_key<'a>(&self) -> &'static Key<'a> {
+                static VALUE: Key<'static> = $key::NAME;
+                &VALUE
+            }
+        }
+        impl std::fmt::Display for $key {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                stringify!($key).fmt(f)
+            }
+        }
+        impl std::hash::Hash for $key {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                self.as_key().hash(state)
+            }
+        }
+        impl<'a> From<$key> for Key<'a> {
+            fn from(v: $key) -> Key<'a> {
+                v.as_key().clone()
+            }
+        }
+        impl<'a> PartialEq<Key<'a>> for $key {
+            fn eq(&self, other: &Key<'a>) -> bool {
+                *self.as_key() == *other
+            }
+        }
+        impl<'a> PartialEq<$key> for Key<'a> {
+            fn eq(&self, other: &$key) -> bool {
+                *other == *self
+            }
+        }
+        impl<'a> std::borrow::Borrow<Key<'a>> for $key {
+            fn borrow(&self) -> &Key<'a> {
+                self.as_key()
+            }
+        }
+        impl Name<MsgTag> for $key {
+            fn as_raw(&self) -> &'static <MsgTag as super::NameClass>::Raw<'static> {
+                self.as_key()
+            }
+        }
+        impl NameValued<MsgTag> for $key {
+            type Value<'a> = $value_ty;
+
+            fn from_union<'a>(
+                input: &<MsgTag as super::NameClass>::Union<'a>,
+            ) -> Result<Self::Value<'a>, ParseError> {
+                use std::error::Error;
+                let (_, raw) = input;
+                #[inline(always)]
+                fn do_parse<'a>(
+                    $arg: &NoNul<'a>,
+                ) -> Result<$value_ty, Box<dyn Error + Send + Sync>> {
+                    $parse
+                }
+                do_parse(raw)
+                    .map_err(|e| ParseError::InvalidField(concat!($value, " tag").into(), e))
+            }
+        }
+    };
+}
+
+macro_rules! tag_arg {
+    ($($key:ident = $value:literal $(, $doc:literal)*;)+) => {
+        $(
+            defn_tag!($key = $value: Arg<'a> = |arg| Ok(Arg::from_bytes(arg.clone())?) $(, $doc)*);
+        )+
+    }
+}
+
+tag_arg! {
+    ACCOUNT = "account",
+        "",
+        "Sent with `account-tag` and contains the account of the message's sender.";
+    BATCH = "batch",
+        "",
+        "Sent with `batch` and contains the reference tag of the batch a message is part of.";
+    LABEL = "label",
+        "",
+        "Sent with `labeled-response` and contains the label of the message being responded to.";
+    MSGID = "msgid",
+        "",
+        "Contains a unique ID for the message.";
+}
+
+defn_tag!(TIME = "time": SystemTime = |arg| {
+    crate::ircmsg::parse_server_time(arg.as_bytes()).ok_or_else(|| "invalid timestamp".into())
+}, "", "Sent with `server-time` and contains when the message was sent.");
+
+defn_tag!(TYPING = "+typing": Typing = |arg| {
+    Typing::from_bytes(arg.as_bytes()).ok_or_else(|| "unknown typing state".into())
+}, "", "A client tag indicating the sender's typing state.");
+
+/// Typing states, as sent in the `+typing` client tag.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Typing {
+    /// `active`: The user is typing a message.
+    Active,
+    /// `paused`: The user has typed a message, but has not typed recently.
+    Paused,
+    /// `done`: The user has stopped typing without sending a message.
+    Done,
+}
+
+impl Typing {
+    /// Parses a typing state from the value of the `+typing` tag.
+    pub const fn from_bytes(value: &[u8]) -> Option<Self> {
+        match value {
+            b"active" => Some(Typing::Active),
+            b"paused" => Some(Typing::Paused),
+            b"done" => Some(Typing::Done),
+            _ => None,
+        }
+    }
+    /// Returns the value of the `+typing` tag for this typing state.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Typing::Active => "active",
+            Typing::Paused => "paused",
+            Typing::Done => "done",
+        }
+    }
+}
+
+impl std::fmt::Display for Typing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
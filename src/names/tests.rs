@@ -0,0 +1,107 @@
+use super::{
+    cap::{BATCH, SASL, STS},
+    Cap, NameMap,
+};
+use crate::string::{Key, Word};
+
+fn caps(caps: &[(&'static str, &'static str, bool)]) -> NameMap<Cap, bool> {
+    let mut map = NameMap::new();
+    let mut edit = map.edit();
+    for (key, value, enabled) in caps.iter().copied() {
+        edit.insert((Key::from_str(key), Word::from_str(value)), enabled);
+    }
+    std::mem::drop(edit);
+    map
+}
+
+#[test]
+fn namemap_iter() {
+    let map = caps(&[("sts", "port=6697", false), ("batch", "", true), ("sasl", "PLAIN", true)]);
+    let entries: Vec<_> = map
+        .iter()
+        .map(|((key, value), enabled)| (key.to_string(), value.to_string(), *enabled))
+        .collect();
+    assert_eq!(
+        entries,
+        [("batch", "", true), ("sasl", "PLAIN", true), ("sts", "port=6697", false)]
+            .map(|(key, value, enabled)| (key.to_owned(), value.to_owned(), enabled))
+    );
+    assert_eq!(map.iter().len(), 3);
+    let ((last, _), _) = map.iter().next_back().unwrap();
+    assert_eq!(*last, STS);
+}
+
+#[test]
+fn namemap_merge_retain() {
+    let mut map = caps(&[("batch", "", true), ("sasl", "PLAIN", true)]);
+    // CAP NEW with an updated value.
+    map.merge(caps(&[("sasl", "PLAIN,EXTERNAL", true), ("sts", "duration=300", false)]));
+    assert_eq!(map.len(), 3);
+    let mechs = map.get_parsed(SASL).unwrap().unwrap();
+    assert!(mechs.contains("EXTERNAL".as_bytes()));
+    // CAP DEL.
+    map.retain(|(key, _), _| *key != SASL);
+    assert!(map.get_union(SASL).is_none());
+    assert_eq!(map.get_extra(BATCH), Some(&true));
+    // Interleaved within one edit.
+    let mut edit = map.edit();
+    edit.remove(BATCH);
+    edit.merge(caps(&[("message-tags", "", true), ("batch", "", false)]));
+    edit.retain(|_, enabled| *enabled);
+    edit.merge(caps(&[("account-tag", "", false)]));
+    let keys: Vec<_> = edit.iter().map(|((key, _), _)| key.to_string()).collect();
+    assert_eq!(keys.len(), 2);
+    assert!(keys.iter().any(|key| key == "message-tags"));
+    assert!(keys.iter().any(|key| key == "account-tag"));
+    assert!(edit.get_union_raw(&Key::from_str("message-tags")).is_some());
+    std::mem::drop(edit);
+    let keys: Vec<_> = map.keys().map(Key::to_string).collect();
+    assert_eq!(keys, ["account-tag", "message-tags"]);
+    assert!(map.get_union(STS).is_none());
+    assert_eq!(map.get_extra_raw(&Key::from_str("account-tag")), Some(&false));
+}
+
+#[test]
+fn namemap_prefix() {
+    let mut map = caps(&[("draft/a", "", true), ("batch", "", true), ("draft/c", "", false)]);
+    fn keys<'a>(
+        iter: impl Iterator<Item = (&'a (Key<'static>, Word<'static>), &'a bool)>,
+    ) -> Vec<String> {
+        iter.map(|((key, _), _)| key.to_string()).collect()
+    }
+    assert_eq!(keys(map.iter_prefix(b"draft/")), ["draft/a", "draft/c"]);
+    assert_eq!(keys(map.iter_prefix(b"")), ["batch", "draft/a", "draft/c"]);
+    assert!(map.iter_prefix(b"draft/z").next().is_none());
+    assert!(map.iter_prefix(b"zzz").next().is_none());
+    let range = (std::ops::Bound::Included(&b"c"[..]), std::ops::Bound::Excluded(&b"draft/c"[..]));
+    assert_eq!(keys(map.range(range)), ["draft/a"]);
+    let mut edit = map.edit();
+    // Unsorted tail.
+    edit.insert((Key::from_str("draft/b"), Word::default()), true);
+    edit.insert((Key::from_str("drafts"), Word::default()), true);
+    assert_eq!(keys(edit.iter_prefix(b"draft/")), ["draft/a", "draft/c", "draft/b"]);
+    assert_eq!(keys(edit.range(range)), ["draft/a", "draft/b"]);
+    std::mem::drop(edit);
+    assert_eq!(keys(map.iter_prefix(b"draft/")), ["draft/a", "draft/b", "draft/c"]);
+}
+
+#[test]
+fn tag_parsed() {
+    use super::tag::{Typing, BATCH, LABEL, MSGID, TIME, TYPING};
+    use crate::ircmsg::ServerMsg;
+    use std::time::{Duration, SystemTime};
+    let msg = ServerMsg::parse(
+        "@batch=yXNAbvnRHTRBv;label=123;+typing=paused;time=2024-02-29T12:34:56.789Z \
+        :a!b@c TAGMSG #x",
+    )
+    .unwrap();
+    assert_eq!(msg.tag_parsed(BATCH).unwrap().unwrap(), "yXNAbvnRHTRBv");
+    assert_eq!(msg.tag_parsed(LABEL).unwrap().unwrap(), "123");
+    assert_eq!(msg.tag_parsed(TYPING).unwrap().unwrap(), Typing::Paused);
+    let expected = SystemTime::UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+    assert_eq!(msg.tag_parsed(TIME).unwrap().unwrap(), expected);
+    assert!(msg.tag_parsed(MSGID).is_none());
+    let msg = ServerMsg::parse("@+typing=thinking;time=bogus :a!b@c TAGMSG #x").unwrap();
+    assert!(msg.tag_parsed(TYPING).unwrap().is_err());
+    assert!(msg.tags.get_parsed(TIME).unwrap().is_err());
+}
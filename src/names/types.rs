@@ -1,8 +1,12 @@
-use std::{borrow::Borrow, iter::FusedIterator};
+use std::{
+    borrow::Borrow,
+    iter::FusedIterator,
+    ops::{Bound, RangeBounds},
+};
 
 use crate::{
     error::ParseError,
-    util::{FlatMap, FlatMapEditGuard},
+    util::{sorted_range, FlatMap, FlatMapEditGuard},
 };
 
 /// Markers indicating distinct types of tags (as in discriminants in tagged unions).
@@ -150,6 +154,54 @@ impl<K: NameClass, V: 'static> NameMap<K, V> {
     pub fn keys(&self) -> NameMapIter<'_, K, V, true> {
         NameMapIter { slice: self.map.as_slice() }
     }
+
+    /// Returns an iterator over the unions and extra values in this map.
+    ///
+    /// This iterator is sorted by key.
+    pub fn iter(&self) -> NameMapIter<'_, K, V, true, false, false> {
+        NameMapIter { slice: self.map.as_slice() }
+    }
+
+    /// Returns an iterator over the entries whose keys are within `range`.
+    ///
+    /// This iterator is sorted by key.
+    pub fn range(
+        &self,
+        range: impl RangeBounds<[u8]>,
+    ) -> NameMapIter<'_, K, V, true, false, false> {
+        NameMapIter { slice: self.map.range(range) }
+    }
+
+    /// Returns an iterator over the entries whose keys start with `prefix`,
+    /// such as every capability starting with `draft/`.
+    ///
+    /// This iterator is sorted by key.
+    pub fn iter_prefix(&self, prefix: &[u8]) -> NameMapIter<'_, K, V, true, false, false> {
+        NameMapIter { slice: prefix_slice::<K, V>(self.map.as_slice(), prefix) }
+    }
+
+    /// Removes every entry for which `f` returns `false`.
+    pub fn retain(&mut self, mut f: impl FnMut(&K::Union<'static>, &V) -> bool) {
+        self.map.retain(|(union, extra)| f(union, extra));
+    }
+
+    /// Inserts every entry from `other`, replacing any existing entries with the same keys.
+    pub fn merge(&mut self, other: NameMap<K, V>) {
+        self.edit().merge(other);
+    }
+}
+
+/// Returns the entries of `sorted` whose keys start with `prefix`.
+fn prefix_slice<'a, K: NameClass, V: 'static>(
+    sorted: &'a [(K::Union<'static>, V)],
+    prefix: &[u8],
+) -> &'a [(K::Union<'static>, V)] {
+    let from = sorted_range::<_, NameExtractor<'static, K, V>>(
+        sorted,
+        &(Bound::Included(prefix), Bound::Unbounded),
+    );
+    let len = from.partition_point(|(u, _)| K::get_tag(u).borrow().starts_with(prefix));
+    &from[..len]
 }
 
 /// Iterator over entries in a [`NameMap`].
@@ -199,6 +251,40 @@ impl<'a, const SORTED: bool, K: NameClass, V: 'static> ExactSizeIterator
 {
 }
 
+impl<'a, const SORTED: bool, K: NameClass, V: 'static> Iterator
+    for NameMapIter<'a, K, V, SORTED, false, false>
+{
+    type Item = (&'a K::Union<'static>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (next, rest) = self.slice.split_first()?;
+        self.slice = rest;
+        Some((&next.0, &next.1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let size = self.slice.len();
+        (size, Some(size))
+    }
+}
+impl<'a, const SORTED: bool, K: NameClass, V: 'static> DoubleEndedIterator
+    for NameMapIter<'a, K, V, SORTED, false, false>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (next, rest) = self.slice.split_last()?;
+        self.slice = rest;
+        Some((&next.0, &next.1))
+    }
+}
+impl<'a, const SORTED: bool, K: NameClass, V: 'static> FusedIterator
+    for NameMapIter<'a, K, V, SORTED, false, false>
+{
+}
+impl<'a, const SORTED: bool, K: NameClass, V: 'static> ExactSizeIterator
+    for NameMapIter<'a, K, V, SORTED, false, false>
+{
+}
+
 /// Edit guard for a [`NameMap`].
 #[derive(Debug)]
 pub struct NameMapEditGuard<'a, K: NameClass, V: 'static>(
@@ -255,6 +341,51 @@ impl<'a, K: NameClass, V: 'static> NameMapEditGuard<'a, K, V> {
     pub fn remove_raw(&mut self, tag: &K::Raw<'_>) -> Option<(K::Union<'static>, V)> {
         self.0.remove(tag.borrow())
     }
+
+    /// Returns an iterator over the unions and extra values in this map.
+    ///
+    /// Unlike [`NameMap::iter`], this iterator is not necessarily sorted.
+    pub fn iter(&self) -> NameMapIter<'_, K, V, false, false, false> {
+        NameMapIter { slice: self.0.as_slice() }
+    }
+
+    /// Returns an iterator over the entries whose keys are within `range`.
+    ///
+    /// Like [`iter`][NameMapEditGuard::iter], this iterator is not necessarily sorted.
+    pub fn range<R: RangeBounds<[u8]>>(
+        &self,
+        range: R,
+    ) -> impl Iterator<Item = (&K::Union<'static>, &V)> {
+        self.0.range(range).map(|(union, extra)| (union, extra))
+    }
+
+    /// Returns an iterator over the entries whose keys start with `prefix`.
+    ///
+    /// Like [`iter`][NameMapEditGuard::iter], this iterator is not necessarily sorted.
+    pub fn iter_prefix<'b>(
+        &'b self,
+        prefix: &'b [u8],
+    ) -> impl Iterator<Item = (&'b K::Union<'static>, &'b V)> {
+        let (sorted, unsorted) = self.0.split_sorted();
+        let unsorted =
+            unsorted.iter().filter(move |(u, _)| K::get_tag(u).borrow().starts_with(prefix));
+        prefix_slice::<K, V>(sorted, prefix)
+            .iter()
+            .chain(unsorted)
+            .map(|(union, extra)| (union, extra))
+    }
+
+    /// Removes every entry for which `f` returns `false`.
+    pub fn retain(&mut self, mut f: impl FnMut(&K::Union<'static>, &V) -> bool) {
+        self.0.retain(|(union, extra)| f(union, extra));
+    }
+
+    /// Inserts every entry from `other`, replacing any existing entries with the same keys.
+    pub fn merge(&mut self, other: NameMap<K, V>) {
+        for elem in other.map.into_vec() {
+            self.0.insert(elem);
+        }
+    }
 }
 
 impl<K: NameClass> Default for NameMap<K> {
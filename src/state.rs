@@ -1,7 +1,13 @@
 //! Definitions for IRC state tracking.
 
+mod isupport;
+pub mod mask;
 mod mode;
+pub mod serverinfo;
+mod sts;
 #[cfg(test)]
 mod tests;
 
+pub use isupport::*;
 pub use mode::*;
+pub use sts::*;
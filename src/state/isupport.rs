@@ -0,0 +1,328 @@
+use super::{Mode, ModeSet};
+use crate::string::{Cmd, Word};
+use std::num::{NonZeroU16, NonZeroU32};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Types that can be parsed from all or part of the value of an ISUPPORT token.
+///
+/// This is implemented for common value types and for the adapters
+/// [`CommaList`] and [`KeyValueList`], which allow the values of most list-like tokens
+/// to be parsed without any splitting code.
+/// `Option<T>` parses empty values as `None`.
+pub trait ISupportValue: Sized {
+    /// Parses a value from `bytes`.
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, BoxError>;
+}
+
+macro_rules! isupport_value_strparse {
+    ($($ty:ty)+) => {
+        $(
+            impl ISupportValue for $ty {
+                fn try_from_bytes(bytes: &[u8]) -> Result<Self, BoxError> {
+                    Ok(std::str::from_utf8(bytes)?.parse()?)
+                }
+            }
+        )+
+    };
+}
+
+isupport_value_strparse! {
+    u16
+    u32
+    usize
+    NonZeroU16
+    NonZeroU32
+}
+
+impl ISupportValue for Word<'static> {
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, BoxError> {
+        Ok(Word::from_bytes(bytes.to_vec())?)
+    }
+}
+
+/// Command names are normalized to uppercase.
+impl ISupportValue for Cmd<'static> {
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, BoxError> {
+        Ok(Cmd::from_bytes(bytes.to_ascii_uppercase())?)
+    }
+}
+
+impl ISupportValue for ByteSet {
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, BoxError> {
+        let mut retval = ByteSet::new();
+        for byte in bytes.iter().copied() {
+            if !byte.is_ascii() {
+                return Err(format!("non-ASCII byte `{}`", byte.escape_ascii()).into());
+            }
+            retval.insert(byte);
+        }
+        Ok(retval)
+    }
+}
+
+impl ISupportValue for Mode {
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, BoxError> {
+        match bytes {
+            [letter] => Mode::new(*letter).ok_or_else(|| "invalid mode letter".into()),
+            [] => Err("missing mode letter".into()),
+            _ => Err("expected one mode letter".into()),
+        }
+    }
+}
+
+impl<T: ISupportValue> ISupportValue for Option<T> {
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, BoxError> {
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        T::try_from_bytes(bytes).map(Some)
+    }
+}
+
+/// Splits a comma-separated list, skipping empty elements but counting them for indices.
+fn split_commas(bytes: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    bytes.split(|b| *b == b',').enumerate().filter(|(_, elem)| !elem.is_empty())
+}
+
+/// A comma-separated list of values, such as `a,b,c`.
+///
+/// Empty elements are skipped.
+/// Parse errors include the index of the element that failed to parse.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct CommaList<T>(pub Vec<T>);
+
+impl<T: ISupportValue> ISupportValue for CommaList<T> {
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, BoxError> {
+        let mut retval = Vec::new();
+        for (idx, elem) in split_commas(bytes) {
+            let value = T::try_from_bytes(elem).map_err(|e| format!("element {idx}: {e}"))?;
+            retval.push(value);
+        }
+        Ok(CommaList(retval))
+    }
+}
+
+/// A comma-separated list of `key:value` pairs, such as `#&:50,+:`.
+///
+/// Empty elements are skipped.
+/// The value is everything after the first colon, and may be empty;
+/// use `Option<V>` as the value type to parse empty values as `None`.
+/// Parse errors include the index of the element that failed to parse.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct KeyValueList<K, V>(pub Vec<(K, V)>);
+
+impl<K, V> Default for KeyValueList<K, V> {
+    fn default() -> Self {
+        KeyValueList(Vec::new())
+    }
+}
+
+impl<K: PartialEq, V> KeyValueList<K, V> {
+    /// Returns the value of the first pair with the provided key.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+impl<K: ISupportValue, V: ISupportValue> ISupportValue for KeyValueList<K, V> {
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, BoxError> {
+        let mut retval = Vec::new();
+        for (idx, elem) in split_commas(bytes) {
+            let Some(colon) = elem.iter().position(|b| *b == b':') else {
+                return Err(
+                    format!("element {idx}: missing ':' in `{}`", elem.escape_ascii()).into()
+                );
+            };
+            let key =
+                K::try_from_bytes(&elem[..colon]).map_err(|e| format!("element {idx}: {e}"))?;
+            let value =
+                V::try_from_bytes(&elem[colon + 1..]).map_err(|e| format!("element {idx}: {e}"))?;
+            retval.push((key, value));
+        }
+        Ok(KeyValueList(retval))
+    }
+}
+
+/// A set of ASCII bytes.
+///
+/// Used for ISUPPORT tokens whose values are sets of single-character flags or prefixes,
+/// such as `CHANTYPES`, `STATUSMSG`, and `ELIST`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ByteSet(u128);
+
+impl ByteSet {
+    /// Creates a new, empty `ByteSet`.
+    pub const fn new() -> ByteSet {
+        ByteSet(0)
+    }
+    /// Adds a byte to the set.
+    ///
+    /// Returns `true` if there was a change.
+    /// Non-ASCII bytes cannot be added.
+    pub fn insert(&mut self, byte: u8) -> bool {
+        if !byte.is_ascii() {
+            return false;
+        }
+        let old = self.0;
+        self.0 |= 1u128 << byte;
+        old != self.0
+    }
+    /// Tests if a byte is in the set.
+    pub const fn contains(&self, byte: u8) -> bool {
+        byte.is_ascii() && (self.0 & (1u128 << byte)) != 0
+    }
+    /// Returns false if this set is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+    /// Returns the number of bytes in this set.
+    pub const fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+    /// Returns an iterator over the bytes in this set in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0u8..128).filter(|b| self.contains(*b))
+    }
+}
+
+impl std::fmt::Display for ByteSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for byte in self.iter() {
+            write!(f, "{}", byte.escape_ascii())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for ByteSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ByteSet(\"{self}\")")
+    }
+}
+
+impl FromIterator<u8> for ByteSet {
+    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
+        let mut retval = ByteSet::new();
+        for byte in iter {
+            retval.insert(byte);
+        }
+        retval
+    }
+}
+
+/// The maximum number of targets each command accepts,
+/// as specified by the `TARGMAX` ISUPPORT token.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct TargetLimits(Vec<(Cmd<'static>, Option<NonZeroU32>)>);
+
+impl TargetLimits {
+    /// Creates a new empty `TargetLimits`.
+    pub const fn new() -> Self {
+        TargetLimits(Vec::new())
+    }
+    /// Returns the target limit for the provided command.
+    ///
+    /// Returns `None` if the command is not listed,
+    /// or `Some(None)` if it is listed without a limit.
+    pub fn get(&self, cmd: &Cmd<'_>) -> Option<Option<NonZeroU32>> {
+        self.0.iter().find(|(c, _)| c == cmd).map(|(_, limit)| *limit)
+    }
+    /// Sets the target limit for the provided command, replacing any existing one.
+    pub fn insert(&mut self, cmd: Cmd<'static>, limit: Option<NonZeroU32>) {
+        if let Some((_, old)) = self.0.iter_mut().find(|(c, _)| *c == cmd) {
+            *old = limit;
+        } else {
+            self.0.push((cmd, limit));
+        }
+    }
+    /// Returns an iterator over every listed command and its limit.
+    pub fn iter(&self) -> impl Iterator<Item = (&Cmd<'static>, Option<NonZeroU32>)> {
+        self.0.iter().map(|(cmd, limit)| (cmd, *limit))
+    }
+    /// Returns `true` if no commands are listed.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The maximum number of entries in each list mode,
+/// as specified by the `MAXLIST` ISUPPORT token.
+///
+/// Modes listed together share one limit across all of their lists.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct ListLimits(Vec<(ModeSet, u32)>);
+
+impl ListLimits {
+    /// Creates a new empty `ListLimits`.
+    pub const fn new() -> Self {
+        ListLimits(Vec::new())
+    }
+    /// Returns the limit for the provided mode along with the set of modes that share it.
+    pub fn get(&self, mode: Mode) -> Option<(ModeSet, u32)> {
+        self.0.iter().find(|(modes, _)| modes.contains(mode)).copied()
+    }
+    /// Adds a limit shared by the provided modes.
+    ///
+    /// Modes that already have a limit are removed from their old sets.
+    pub fn insert(&mut self, modes: ModeSet, limit: u32) {
+        for (old, _) in self.0.iter_mut() {
+            *old = old.difference(modes);
+        }
+        self.0.retain(|(old, _)| !old.is_empty());
+        if !modes.is_empty() {
+            self.0.push((modes, limit));
+        }
+    }
+    /// Returns an iterator over every set of modes and their shared limit.
+    pub fn iter(&self) -> impl Iterator<Item = (ModeSet, u32)> + '_ {
+        self.0.iter().copied()
+    }
+    /// Returns `true` if no modes are listed.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<KeyValueList<Cmd<'static>, Option<NonZeroU32>>> for TargetLimits {
+    fn from(value: KeyValueList<Cmd<'static>, Option<NonZeroU32>>) -> Self {
+        let mut retval = TargetLimits::new();
+        for (cmd, limit) in value.0 {
+            retval.insert(cmd, limit);
+        }
+        retval
+    }
+}
+
+impl ISupportValue for TargetLimits {
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, BoxError> {
+        KeyValueList::try_from_bytes(bytes).map(Self::from)
+    }
+}
+
+/// The extended ban types a server supports,
+/// as specified by the `EXTBAN` ISUPPORT token.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct ExtBans {
+    /// The character that extended bans start with, if any.
+    ///
+    /// If `None`, extended bans start with their type.
+    pub prefix: Option<u8>,
+    /// The supported extended ban types.
+    pub types: ByteSet,
+}
+
+impl ISupportValue for ExtBans {
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self, BoxError> {
+        let Some(comma) = bytes.iter().position(|b| *b == b',') else {
+            return Err("missing ','".into());
+        };
+        let prefix = match &bytes[..comma] {
+            [] => None,
+            [prefix] if prefix.is_ascii_graphic() => Some(*prefix),
+            prefix => return Err(format!("invalid prefix `{}`", prefix.escape_ascii()).into()),
+        };
+        let types = ByteSet::try_from_bytes(&bytes[comma + 1..])?;
+        Ok(ExtBans { prefix, types })
+    }
+}
@@ -0,0 +1,177 @@
+//! Matching of sources against ban masks.
+//!
+//! Masks are of the form `nick!user@host`, where `*` matches any number of bytes
+//! and `?` matches exactly one byte.
+//! Masks are matched against a source as one string, as most servers do,
+//! after completing masks that are missing parts.
+
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    ircmsg::Source,
+    string::{tf::IrcCasemap, Builder, Word},
+};
+
+/// A string made of several byte slices, which are indexed as if they were concatenated.
+#[derive(Clone, Copy)]
+struct Joined<'a, const N: usize> {
+    parts: [&'a [u8]; N],
+    len: usize,
+}
+
+impl<'a, const N: usize> Joined<'a, N> {
+    fn new(parts: [&'a [u8]; N]) -> Self {
+        let len = parts.iter().map(|part| part.len()).sum();
+        Joined { parts, len }
+    }
+    fn at(&self, mut idx: usize) -> u8 {
+        for part in self.parts {
+            if let Some(byte) = part.get(idx) {
+                return *byte;
+            }
+            idx -= part.len();
+        }
+        0
+    }
+}
+
+fn glob<const P: usize, const S: usize>(
+    pattern: Joined<'_, P>,
+    subject: Joined<'_, S>,
+    casemap: IrcCasemap,
+) -> bool {
+    let (mut p, mut s) = (0usize, 0usize);
+    // The position of the last `*` in the pattern and where in the subject it began matching.
+    let mut star: Option<(usize, usize)> = None;
+    while s < subject.len {
+        if p < pattern.len {
+            match pattern.at(p) {
+                b'*' => {
+                    star = Some((p, s));
+                    p += 1;
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    s += 1;
+                    continue;
+                }
+                byte if casemap.map_byte(byte) == casemap.map_byte(subject.at(s)) => {
+                    p += 1;
+                    s += 1;
+                    continue;
+                }
+                _ => (),
+            }
+        }
+        // Backtrack, having the last `*` match one more byte.
+        let Some((star_p, star_s)) = star else {
+            return false;
+        };
+        p = star_p + 1;
+        s = star_s + 1;
+        star = Some((star_p, s));
+    }
+    while p < pattern.len && pattern.at(p) == b'*' {
+        p += 1;
+    }
+    p == pattern.len
+}
+
+/// Returns `true` if `subject` matches the glob `pattern` under `casemap`.
+///
+/// `*` matches any number of bytes, including none, and `?` matches exactly one byte.
+/// Every other byte matches itself under `casemap`.
+/// There is no way to escape `*` or `?`.
+pub fn glob_match(pattern: &[u8], subject: &[u8], casemap: IrcCasemap) -> bool {
+    glob(Joined::new([pattern]), Joined::new([subject]), casemap)
+}
+
+/// Returns `true` if `mask` looks like an extended ban rather than a `nick!user@host` mask.
+///
+/// This is the case if it starts with `$`, which no nickname can,
+/// or if it has a `:` before any `!` or `@`, such as `~a:account` or `R:account`.
+/// Use the `EXTBAN` ISUPPORT token ([`ExtBans`][crate::state::ExtBans])
+/// to find which extended bans a server actually supports.
+pub fn is_extban(mask: &[u8]) -> bool {
+    if matches!(mask.first(), Some(b'$')) {
+        return true;
+    }
+    mask.iter().take_while(|b| !matches!(b, b'!' | b'@')).any(|b| *b == b':')
+}
+
+/// Which parts of a [`Source`] a ban mask created by [`Source::to_banmask`] matches on.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub enum BanStyle {
+    /// `*!*@host`.
+    #[default]
+    Host,
+    /// `*!user@host`.
+    UserHost,
+    /// `nick!*@*`.
+    Nick,
+}
+
+impl Source<'_> {
+    /// Returns `true` if `self` matches the ban mask `mask` under `casemap`.
+    ///
+    /// Masks missing parts are completed the way servers complete them:
+    /// `nick` is matched as `nick!*@*`, `user@host` as `*!user@host`,
+    /// and `nick!user` as `nick!user@*`.
+    /// Sources without a username are matched as though they had an empty one,
+    /// while sources without a hostname, such as servers, never match.
+    ///
+    /// Extended bans (see [`is_extban`]) depend on information that sources lack,
+    /// and never match.
+    pub fn matches_mask(&self, mask: &Word<'_>, casemap: IrcCasemap) -> bool {
+        let mask: &[u8] = mask.as_ref();
+        if is_extban(mask) {
+            return false;
+        }
+        let has_bang = mask.contains(&b'!');
+        let has_at = mask.contains(&b'@');
+        let pattern = match (has_bang, has_at) {
+            (true, true) => Joined::new([b"", mask, b""]),
+            (false, true) => Joined::new([b"*!", mask, b""]),
+            (true, false) => Joined::new([b"", mask, b"@*"]),
+            (false, false) => Joined::new([b"", mask, b"!*@*"]),
+        };
+        let nick: &[u8] = self.nick.as_ref();
+        let subject = match &self.userhost {
+            Some(userhost) => {
+                let user: &[u8] = userhost.user.as_ref().map_or(b"", |user| user.as_ref());
+                Joined::new([nick, b"!", user, b"@", userhost.host.as_ref()])
+            }
+            None => Joined::new([nick, b"", b"", b"", b""]),
+        };
+        glob(pattern, subject, casemap)
+    }
+    /// Returns a ban mask matching `self` in the provided style.
+    ///
+    /// Parts of `self` that are missing are replaced with `*`.
+    pub fn to_banmask(&self, style: BanStyle) -> Word<'static> {
+        let mut mask = Builder::<Word>::new(Word::default());
+        match style {
+            BanStyle::Host => mask.append(Word::from_str("*!*@")),
+            BanStyle::UserHost => {
+                mask.append(Word::from_str("*!"));
+                match self.user() {
+                    Some(user) => mask.append(user.clone()),
+                    None => mask.append(Word::from_str("*")),
+                }
+                mask.append(Word::from_str("@"));
+            }
+            BanStyle::Nick => {
+                mask.append(self.nick.clone());
+                mask.append(Word::from_str("!*@*"));
+                return mask.build().owning();
+            }
+        }
+        match self.host() {
+            Some(host) => mask.append(host.clone()),
+            None => mask.append(Word::from_str("*")),
+        }
+        mask.build().owning()
+    }
+}
@@ -0,0 +1,94 @@
+use super::{glob_match, is_extban, BanStyle};
+use crate::{
+    ircmsg::Source,
+    string::{tf::IrcCasemap, Word},
+};
+
+#[test]
+fn glob() {
+    let cases: &[(&str, &str, bool)] = &[
+        ("", "", true),
+        ("", "a", false),
+        ("*", "", true),
+        ("*", "anything", true),
+        ("?", "", false),
+        ("?", "a", true),
+        ("a*b", "ab", true),
+        ("a*b", "axxb", true),
+        ("a*b", "axxbc", false),
+        ("a*b*c", "abbbc", true),
+        ("*a", "baaa", true),
+        ("**?", "a", true),
+        ("a?c", "abbc", false),
+        ("*bc", "abcbc", true),
+    ];
+    for (pattern, subject, expected) in cases {
+        let matched = glob_match(pattern.as_bytes(), subject.as_bytes(), IrcCasemap::Ascii);
+        assert_eq!(matched, *expected, "{pattern} against {subject}");
+    }
+    assert!(glob_match(b"NICK[A]", b"nick{a}", IrcCasemap::Rfc1459));
+    assert!(!glob_match(b"NICK[A]", b"nick{a}", IrcCasemap::Ascii));
+}
+
+#[test]
+fn extban() {
+    for mask in ["$a:account", "~q:*!*@host", "R:account", "$o"] {
+        assert!(is_extban(mask.as_bytes()), "{mask}");
+    }
+    for mask in ["nick", "*!*@*", "*!*@2001:db8::1", "nick!~user@host", "~user@host"] {
+        assert!(!is_extban(mask.as_bytes()), "{mask}");
+    }
+}
+
+#[test]
+fn matches_mask() {
+    let user = Source::parse(Word::from_str("Nick!~user@host.example")).unwrap();
+    let no_user = Source::parse(Word::from_str("Nick@host.example")).unwrap();
+    let server = Source::parse(Word::from_str("irc.example")).unwrap();
+    let cases: &[(&str, [bool; 3])] = &[
+        ("*!*@*", [true, true, false]),
+        ("*", [true, true, false]),
+        ("nick", [true, true, false]),
+        // Servers have no user or host, so masks never match them.
+        ("irc.example", [false, false, false]),
+        ("*.example", [false, false, false]),
+        ("*@host.example", [true, true, false]),
+        ("~user@host.example", [true, false, false]),
+        ("*!~user", [true, false, false]),
+        ("*!@host.example", [false, true, false]),
+        ("*!*@HOST.*", [true, true, false]),
+        ("nick!*user@*", [true, false, false]),
+        ("n*!*r@*e", [true, false, false]),
+        ("*k!~*", [true, false, false]),
+        ("?ick!?user@host.exampl?", [true, false, false]),
+        ("nick!~user@host", [false, false, false]),
+        ("$a:nick", [false, false, false]),
+        ("~q:*!*@*", [false, false, false]),
+    ];
+    for (mask, expected) in cases {
+        let mask = Word::from_str(mask);
+        for (source, expected) in [&user, &no_user, &server].into_iter().zip(expected) {
+            let matched = source.matches_mask(&mask, IrcCasemap::Rfc1459);
+            assert_eq!(matched, *expected, "{source} against {mask}");
+        }
+    }
+}
+
+#[test]
+fn to_banmask() {
+    let user = Source::parse(Word::from_str("nick!~user@host.example")).unwrap();
+    let no_user = Source::parse(Word::from_str("nick@host.example")).unwrap();
+    let server = Source::parse(Word::from_str("irc.example")).unwrap();
+    let cases = [
+        (BanStyle::Host, ["*!*@host.example", "*!*@host.example", "*!*@*"]),
+        (BanStyle::UserHost, ["*!~user@host.example", "*!*@host.example", "*!*@*"]),
+        (BanStyle::Nick, ["nick!*@*", "nick!*@*", "irc.example!*@*"]),
+    ];
+    for (style, expected) in cases {
+        for (source, expected) in [&user, &no_user, &server].into_iter().zip(expected) {
+            let mask = source.to_banmask(style);
+            assert_eq!(mask, expected);
+            assert!(source.matches_mask(&mask, IrcCasemap::Rfc1459) || source.host().is_none());
+        }
+    }
+}
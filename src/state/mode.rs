@@ -7,6 +7,7 @@ use std::{
 use crate::{
     error::ParseError,
     names::{ISupport, NameMap},
+    string::Arg,
 };
 
 /// A single mode letter.
@@ -404,7 +405,24 @@ impl ModeTypes {
     }
 }
 
-// TODO: PartialOrd
+impl PartialOrd for ModeTypes {
+    /// Compares by subset, treating each [`ModeType`]'s set separately.
+    ///
+    /// `self` is less than `b` if every one of its sets is a subset of `b`'s set of the same type.
+    fn partial_cmp(&self, b: &Self) -> Option<std::cmp::Ordering> {
+        use std::cmp::Ordering;
+        let mut retval = Ordering::Equal;
+        for (set_a, set_b) in self.0.iter().zip(b.0.iter()) {
+            match (retval, set_a.partial_cmp(set_b)?) {
+                (_, Ordering::Equal) => (),
+                (Ordering::Equal, ord) => retval = ord,
+                (prev, ord) if prev == ord => (),
+                _ => return None,
+            }
+        }
+        Some(retval)
+    }
+}
 
 impl std::fmt::Display for ModeTypes {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -499,9 +517,68 @@ impl StatusModes {
     pub fn get_mode(&self, prefix: NonZeroU8) -> Option<Mode> {
         self.map.iter().find(|(_, p)| *p == prefix.get()).map(|pair| pair.0)
     }
-    // TODO: Iter, ordering lookup methods.
+    /// Returns an iterator over the modes and their prefixes,
+    /// from highest to lowest precedence.
+    pub fn iter(&self) -> StatusModesIter<'_> {
+        StatusModesIter(self.map.iter())
+    }
+    /// Returns the status prefixes, from highest to lowest precedence.
+    ///
+    /// Modes without prefixes are skipped.
+    pub fn prefixes(&self) -> Vec<u8> {
+        self.map.iter().map(|(_, p)| *p).filter(|p| *p != 0).collect()
+    }
+    /// Returns how `a` ranks relative to `b`,
+    /// where `Greater` means that `a` has higher precedence.
+    ///
+    /// Returns `None` if either mode is not a status mode.
+    pub fn cmp_modes(&self, a: Mode, b: Mode) -> Option<std::cmp::Ordering> {
+        let a = self.map.iter().position(|(m, _)| *m == a)?;
+        let b = self.map.iter().position(|(m, _)| *m == b)?;
+        // Lower indices have higher precedence.
+        Some(b.cmp(&a))
+    }
+    /// Returns the highest-precedence status mode in the provided set, if any.
+    pub fn highest(&self, set: ModeSet) -> Option<Mode> {
+        self.map.iter().map(|(m, _)| *m).find(|m| set.contains(*m))
+    }
+}
+
+impl<'a> IntoIterator for &'a StatusModes {
+    type Item = (Mode, Option<NonZeroU8>);
+
+    type IntoIter = StatusModesIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the modes and prefixes in a [`StatusModes`].
+#[derive(Clone, Debug)]
+pub struct StatusModesIter<'a>(std::slice::Iter<'a, (Mode, u8)>);
+
+impl Iterator for StatusModesIter<'_> {
+    type Item = (Mode, Option<NonZeroU8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(m, p)| (*m, NonZeroU8::new(*p)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for StatusModesIter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(m, p)| (*m, NonZeroU8::new(*p)))
+    }
 }
 
+impl FusedIterator for StatusModesIter<'_> {}
+impl ExactSizeIterator for StatusModesIter<'_> {}
+
 /// The available channel modes on a server.
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
 pub struct ServerChanModes {
@@ -543,6 +620,10 @@ impl ServerChanModes {
         };
         ServerChanModes { nonstatus, status, overlap, extra }
     }
+    /// Returns the server's status modes and their prefixes.
+    pub fn status(&self) -> &StatusModes {
+        &self.status
+    }
     /// Returns the [`ModeType`] the provided mode, if known.
     pub fn get(&self, mode: Mode) -> Option<ModeType> {
         if self.status.contains(mode) {
@@ -553,4 +634,204 @@ impl ServerChanModes {
     }
 }
 
-// TODO: ModeMap.
+/// A single change made by a mode string.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ModeChange {
+    /// Whether the mode was set (`true`) or unset (`false`).
+    pub set: bool,
+    /// The mode that was changed.
+    pub mode: Mode,
+    /// The argument the mode was changed with, if any.
+    pub arg: Option<Arg<'static>>,
+}
+
+impl std::fmt::Display for ModeChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_char(if self.set { '+' } else { '-' })?;
+        f.write_char(self.mode.into_char())?;
+        if let Some(arg) = &self.arg {
+            write!(f, " {arg}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The modes that are applied to a channel or user, along with their parameters.
+///
+/// Status modes apply to channel members rather than the channel itself,
+/// so they are not stored here.
+///
+/// The [`Display`][std::fmt::Display] impl of this type writes the non-list modes
+/// in alphabetical order followed by their parameters,
+/// in the same form as `RPL_CHANNELMODEIS` (324), e.g. `+klnt key 10`.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct ModeMap {
+    /// Type D modes.
+    flags: ModeSet,
+    /// Type B and C modes, sorted by mode.
+    params: Vec<(Mode, Arg<'static>)>,
+    /// Type A modes, sorted by mode. Never contains empty lists.
+    lists: Vec<(Mode, Vec<Arg<'static>>)>,
+}
+
+impl ModeMap {
+    /// Creates a new, empty `ModeMap`.
+    pub const fn new() -> Self {
+        ModeMap { flags: ModeSet::new(), params: Vec::new(), lists: Vec::new() }
+    }
+    /// Returns `true` if no modes are set.
+    pub fn is_empty(&self) -> bool {
+        self.flags.is_empty() && self.params.is_empty() && self.lists.is_empty()
+    }
+    /// Returns `true` if the provided mode is set, or for list modes, if the list is non-empty.
+    pub fn contains(&self, mode: Mode) -> bool {
+        self.flags.contains(mode) || self.param(mode).is_some() || !self.list(mode).is_empty()
+    }
+    /// Returns the set of parameterless modes that are set.
+    pub const fn flags(&self) -> ModeSet {
+        self.flags
+    }
+    /// Returns the parameter of a type B or C mode, if it is set.
+    pub fn param(&self, mode: Mode) -> Option<&Arg<'static>> {
+        let idx = self.params.binary_search_by_key(&mode, |(m, _)| *m).ok()?;
+        Some(&self.params[idx].1)
+    }
+    /// Returns the entries of a list mode, in the order they were added.
+    pub fn list(&self, mode: Mode) -> &[Arg<'static>] {
+        match self.lists.binary_search_by_key(&mode, |(m, _)| *m) {
+            Ok(idx) => &self.lists[idx].1,
+            Err(_) => &[],
+        }
+    }
+    /// Applies a mode string, such as the arguments of a `MODE` message.
+    ///
+    /// `modes` is the mode string (e.g. `+ov-k`) and `args` are the arguments following it.
+    /// `chanmodes` is used to determine which modes take arguments.
+    /// Modes it does not know about are assumed to be type D modes.
+    /// Mode changes that are missing required arguments are skipped.
+    ///
+    /// Returns the changes that took effect, in order.
+    /// Because status modes are not tracked, changes to them are always returned.
+    pub fn apply(
+        &mut self,
+        modes: &[u8],
+        args: &[Arg<'_>],
+        chanmodes: &ServerChanModes,
+    ) -> Vec<ModeChange> {
+        let mut retval = Vec::new();
+        let mut args = args.iter();
+        let mut set = true;
+        for byte in modes.iter().copied() {
+            let mode = match byte {
+                b'+' => {
+                    set = true;
+                    continue;
+                }
+                b'-' => {
+                    set = false;
+                    continue;
+                }
+                _ => match Mode::new(byte) {
+                    Some(mode) => mode,
+                    None => continue,
+                },
+            };
+            let mode_type = chanmodes.get(mode).unwrap_or_default();
+            let needs_arg =
+                if set { mode_type.needs_arg_to_set() } else { mode_type.needs_arg_to_unset() };
+            let arg = if needs_arg {
+                let Some(arg) = args.next() else {
+                    continue;
+                };
+                Some(arg.clone().owning())
+            } else {
+                None
+            };
+            let changed = match mode_type {
+                ModeType::TypeA => self.apply_list(set, mode, arg.as_ref()),
+                ModeType::TypeB | ModeType::TypeC => self.apply_param(set, mode, arg.as_ref()),
+                ModeType::TypeD if set => self.flags.set(mode),
+                ModeType::TypeD => self.flags.unset(mode),
+                ModeType::Status => true,
+            };
+            if changed {
+                retval.push(ModeChange { set, mode, arg });
+            }
+        }
+        retval
+    }
+    fn apply_list(&mut self, set: bool, mode: Mode, arg: Option<&Arg<'static>>) -> bool {
+        let Some(arg) = arg else {
+            return false;
+        };
+        match (self.lists.binary_search_by_key(&mode, |(m, _)| *m), set) {
+            (Ok(idx), true) => {
+                let list = &mut self.lists[idx].1;
+                if list.contains(arg) {
+                    return false;
+                }
+                list.push(arg.clone());
+            }
+            (Err(idx), true) => self.lists.insert(idx, (mode, vec![arg.clone()])),
+            (Ok(idx), false) => {
+                let list = &mut self.lists[idx].1;
+                let Some(pos) = list.iter().position(|entry| entry == arg) else {
+                    return false;
+                };
+                list.remove(pos);
+                if list.is_empty() {
+                    self.lists.remove(idx);
+                }
+            }
+            (Err(_), false) => return false,
+        }
+        true
+    }
+    fn apply_param(&mut self, set: bool, mode: Mode, arg: Option<&Arg<'static>>) -> bool {
+        match (self.params.binary_search_by_key(&mode, |(m, _)| *m), set) {
+            (Ok(idx), true) => {
+                let Some(arg) = arg else {
+                    return false;
+                };
+                if self.params[idx].1 == *arg {
+                    return false;
+                }
+                self.params[idx].1 = arg.clone();
+            }
+            (Err(idx), true) => {
+                let Some(arg) = arg else {
+                    return false;
+                };
+                self.params.insert(idx, (mode, arg.clone()));
+            }
+            (Ok(idx), false) => {
+                self.params.remove(idx);
+            }
+            (Err(_), false) => return false,
+        }
+        true
+    }
+}
+
+impl std::fmt::Display for ModeMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_char('+')?;
+        let mut flags = self.flags.into_iter().peekable();
+        let mut params = self.params.iter().peekable();
+        loop {
+            let mode = match (flags.peek(), params.peek()) {
+                (Some(flag), Some((param, _))) if flag < param => flags.next(),
+                (Some(_), Some(_)) | (None, Some(_)) => params.next().map(|(mode, _)| *mode),
+                (Some(_), None) => flags.next(),
+                (None, None) => break,
+            };
+            if let Some(mode) = mode {
+                f.write_char(mode.into_char())?;
+            }
+        }
+        for (_, param) in &self.params {
+            write!(f, " {param}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+//! Comparison of ISUPPORT tokens across servers.
+//!
+//! This is mostly useful for software that connects to many networks
+//! and needs to know which of them lack some feature.
+
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    error::ParseError,
+    names::{ISupport, NameMap, NameValued},
+    string::{Key, Word},
+};
+use std::collections::BTreeMap;
+
+/// The ISUPPORT tokens of one server, as stored by connection registration.
+///
+/// This is the same type as the [`ISupport`][crate::client::state::ISupport] client state,
+/// and stores tokens as they were sent by the server.
+/// Typed values for known tokens can be obtained using
+/// [`get_parsed`][NameMap::get_parsed] with the types in [`names::isupport`][crate::names::isupport].
+/// Use [`parse_errors`] to find tokens that cannot be parsed.
+pub type ServerInfo = NameMap<ISupport>;
+
+/// Parses the value of `name` in `info`, returning the error if any.
+fn check<N: NameValued<ISupport>>(
+    info: &ServerInfo,
+    name: N,
+) -> Option<(Key<'static>, ParseError)> {
+    let error = info.get_parsed(name)?.err()?;
+    Some((name.as_raw().clone(), error))
+}
+
+/// Parses the value of every known token in `info` and returns the failures, sorted by key.
+///
+/// Tokens that do not have a type in [`names::isupport`][crate::names::isupport]
+/// are never included.
+pub fn parse_errors(info: &ServerInfo) -> Vec<(Key<'static>, ParseError)> {
+    use crate::names::isupport::*;
+    let mut retval: Vec<_> = [
+        check(info, AWAYLEN),
+        check(info, BOT),
+        check(info, CALLERID),
+        check(info, CASEMAPPING),
+        check(info, CHANLIMIT),
+        check(info, CHANMODES),
+        check(info, CHANNELLEN),
+        check(info, CHANTYPES),
+        check(info, ELIST),
+        check(info, EXCEPTS),
+        check(info, EXTBAN),
+        check(info, HOSTLEN),
+        check(info, INVEX),
+        check(info, KICKLEN),
+        check(info, MAXLIST),
+        check(info, MODES),
+        check(info, MONITOR),
+        check(info, NETWORK),
+        check(info, NICKLEN),
+        check(info, PREFIX),
+        check(info, SILENCE),
+        check(info, STATUSMSG),
+        check(info, TARGMAX),
+        check(info, TOPICLEN),
+        check(info, USERLEN),
+        check(info, USERMODES),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    retval.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    retval
+}
+
+/// The value of an ISUPPORT token, parsed into a comparable form.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+pub enum Value {
+    /// The token has no value.
+    Flag,
+    /// The token has a numeric value.
+    Number(u32),
+    /// The token has a value that is compared as-is.
+    Raw(Word<'static>),
+}
+
+impl Value {
+    /// Parses the value of the provided token.
+    ///
+    /// Tokens with known numeric values are parsed as numbers if possible.
+    pub fn parse(key: &Key<'_>, value: &Word<'_>) -> Self {
+        use crate::names::isupport::*;
+        if value.is_empty() {
+            return Value::Flag;
+        }
+        let numeric = [
+            AWAYLEN::NAME,
+            CHANNELLEN::NAME,
+            HOSTLEN::NAME,
+            KICKLEN::NAME,
+            MODES::NAME,
+            MONITOR::NAME,
+            NICKLEN::NAME,
+            SILENCE::NAME,
+            TOPICLEN::NAME,
+            USERLEN::NAME,
+        ];
+        if numeric.contains(key) {
+            if let Some(no) = value.to_utf8().and_then(|v| v.parse().ok()) {
+                return Value::Number(no);
+            }
+        }
+        Value::Raw(value.clone().owning())
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Flag => f.write_str("(set)"),
+            Value::Number(no) => write!(f, "{no}"),
+            Value::Raw(word) => write!(f, "{word}"),
+        }
+    }
+}
+
+/// One of the two servers being compared by [`diff`].
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+pub enum Side {
+    /// The first server.
+    A,
+    /// The second server.
+    B,
+}
+
+/// One difference between the ISUPPORT tokens of two servers.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+pub enum IsupportDiff {
+    /// The token is only present on one server.
+    OnlyIn(Side, Key<'static>, Value),
+    /// The token is present on both servers with different values.
+    Differs(Key<'static>, Value, Value),
+}
+
+impl IsupportDiff {
+    /// Returns the name of the token that differs.
+    pub fn key(&self) -> &Key<'static> {
+        match self {
+            IsupportDiff::OnlyIn(_, key, _) | IsupportDiff::Differs(key, _, _) => key,
+        }
+    }
+}
+
+impl std::fmt::Display for IsupportDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IsupportDiff::OnlyIn(Side::A, key, value) => write!(f, "{key}: {value} | (unset)"),
+            IsupportDiff::OnlyIn(Side::B, key, value) => write!(f, "{key}: (unset) | {value}"),
+            IsupportDiff::Differs(key, a, b) => write!(f, "{key}: {a} | {b}"),
+        }
+    }
+}
+
+/// Returns the parsed value of every token in `info`, sorted by key.
+fn values(info: &ServerInfo) -> impl Iterator<Item = (&Key<'static>, Value)> {
+    info.keys().filter_map(|key| {
+        let (_, value) = info.get_union_raw(key)?;
+        Some((key, Value::parse(key, value)))
+    })
+}
+
+/// Compares the ISUPPORT tokens of two servers.
+///
+/// The returned differences are sorted by key.
+pub fn diff(a: &ServerInfo, b: &ServerInfo) -> Vec<IsupportDiff> {
+    let mut retval = Vec::new();
+    let mut a = values(a).peekable();
+    let mut b = values(b).peekable();
+    loop {
+        let order = match (a.peek(), b.peek()) {
+            (Some((ka, _)), Some((kb, _))) => ka.cmp(kb),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => break,
+        };
+        match order {
+            std::cmp::Ordering::Less => {
+                let (key, value) = a.next().unwrap();
+                retval.push(IsupportDiff::OnlyIn(Side::A, key.clone(), value));
+            }
+            std::cmp::Ordering::Greater => {
+                let (key, value) = b.next().unwrap();
+                retval.push(IsupportDiff::OnlyIn(Side::B, key.clone(), value));
+            }
+            std::cmp::Ordering::Equal => {
+                let (key, va) = a.next().unwrap();
+                let (_, vb) = b.next().unwrap();
+                if va != vb {
+                    retval.push(IsupportDiff::Differs(key.clone(), va, vb));
+                }
+            }
+        }
+    }
+    retval
+}
+
+/// A table of ISUPPORT tokens across multiple named servers.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+pub struct FeatureMatrix {
+    servers: Vec<String>,
+    rows: BTreeMap<Key<'static>, Vec<Option<Value>>>,
+}
+
+impl FeatureMatrix {
+    /// Returns the names of the servers in this table, in column order.
+    pub fn servers(&self) -> &[String] {
+        &self.servers
+    }
+    /// Returns the values of one token for every server, in column order.
+    ///
+    /// Returns `None` if no server has the token.
+    pub fn row(&self, key: &Key<'_>) -> Option<&[Option<Value>]> {
+        self.rows.get(key.as_bytes()).map(Vec::as_slice)
+    }
+    /// Returns an iterator over every token and its values, sorted by key.
+    pub fn rows(&self) -> impl Iterator<Item = (&Key<'static>, &[Option<Value>])> {
+        self.rows.iter().map(|(k, v)| (k, v.as_slice()))
+    }
+    /// Returns the names of the servers that lack the provided token.
+    pub fn lacking<'a>(&'a self, key: &Key<'_>) -> impl Iterator<Item = &'a str> {
+        let row = self.rows.get(key.as_bytes());
+        self.servers.iter().enumerate().filter_map(move |(idx, name)| {
+            row.map_or(true, |row| row[idx].is_none()).then_some(name.as_str())
+        })
+    }
+}
+
+/// Builds a [`FeatureMatrix`] from a collection of named servers.
+pub fn summarize<'a, S: Into<String>>(
+    servers: impl IntoIterator<Item = (S, &'a ServerInfo)>,
+) -> FeatureMatrix {
+    let mut matrix = FeatureMatrix::default();
+    for (idx, (name, info)) in servers.into_iter().enumerate() {
+        matrix.servers.push(name.into());
+        for (key, value) in values(info) {
+            let row = matrix.rows.entry(key.clone()).or_default();
+            row.resize(idx, None);
+            row.push(Some(value));
+        }
+    }
+    let len = matrix.servers.len();
+    for row in matrix.rows.values_mut() {
+        row.resize(len, None);
+    }
+    matrix
+}
@@ -0,0 +1,85 @@
+use super::{diff, parse_errors, summarize, IsupportDiff, ServerInfo, Side, Value};
+use crate::string::{Key, Splitter, Word};
+
+fn parse(tokens: &'static str) -> ServerInfo {
+    let mut info = ServerInfo::new();
+    let mut edit = info.edit();
+    for token in tokens.split(' ') {
+        let mut splitter = Splitter::new(Word::from_str(token));
+        let key = splitter.string::<Key>(false).unwrap();
+        splitter.next_byte();
+        edit.insert((key, splitter.rest_or_default::<Word>()), ());
+    }
+    std::mem::drop(edit);
+    info
+}
+
+fn libera() -> ServerInfo {
+    parse("CHANTYPES=# MONITOR=100 NETWORK=Libera.Chat NICKLEN=16 WHOX")
+}
+
+fn oftc() -> ServerInfo {
+    parse("CHANTYPES=# NETWORK=OFTC NICKLEN=30 SILENCE=15")
+}
+
+fn ergo() -> ServerInfo {
+    parse("CHANTYPES=# MONITOR=100 NETWORK=ErgoTest NICKLEN=0016 UTF8ONLY WHOX")
+}
+
+#[test]
+fn diff_libera_oftc() {
+    let diffs: Vec<String> = diff(&libera(), &oftc()).iter().map(ToString::to_string).collect();
+    assert_eq!(
+        diffs,
+        [
+            "MONITOR: 100 | (unset)",
+            "NETWORK: Libera.Chat | OFTC",
+            "NICKLEN: 16 | 30",
+            "SILENCE: (unset) | 15",
+            "WHOX: (set) | (unset)",
+        ]
+    );
+}
+
+#[test]
+fn diff_typed() {
+    // NICKLEN=16 and NICKLEN=0016 are the same number.
+    let diffs = diff(&libera(), &ergo());
+    assert_eq!(
+        diffs,
+        [
+            IsupportDiff::Differs(
+                Key::from_str("NETWORK"),
+                Value::Raw(Word::from_str("Libera.Chat")),
+                Value::Raw(Word::from_str("ErgoTest"))
+            ),
+            IsupportDiff::OnlyIn(Side::B, Key::from_str("UTF8ONLY"), Value::Flag),
+        ]
+    );
+    assert!(diff(&oftc(), &oftc()).is_empty());
+}
+
+#[test]
+fn matrix() {
+    let (libera, oftc, ergo) = (libera(), oftc(), ergo());
+    let matrix = summarize([("libera", &libera), ("oftc", &oftc), ("ergo", &ergo)]);
+    assert_eq!(matrix.servers(), ["libera", "oftc", "ergo"]);
+    let monitor = Key::from_str("MONITOR");
+    assert_eq!(
+        matrix.row(&monitor).unwrap(),
+        [Some(Value::Number(100)), None, Some(Value::Number(100))]
+    );
+    assert_eq!(matrix.lacking(&monitor).collect::<Vec<_>>(), ["oftc"]);
+    assert_eq!(matrix.row(&Key::from_str("UTF8ONLY")).unwrap(), [None, None, Some(Value::Flag)]);
+    assert_eq!(matrix.lacking(&Key::from_str("CHANTYPES")).count(), 0);
+    assert_eq!(matrix.lacking(&Key::from_str("ETRACE")).count(), 3);
+    assert_eq!(matrix.rows().count(), 7);
+}
+
+#[test]
+fn parse_errors_sorted() {
+    assert!(parse_errors(&libera()).is_empty());
+    let info = parse("NICKLEN=0 CASEMAPPING=foo TOPICLEN=300 EXCEPTS FOO=bar");
+    let keys: Vec<String> = parse_errors(&info).iter().map(|(k, _)| k.to_string()).collect();
+    assert_eq!(keys, ["CASEMAPPING", "NICKLEN"]);
+}
@@ -0,0 +1,57 @@
+use crate::error::ParseError;
+use std::time::Duration;
+
+/// A strict transport security policy, as advertised by the `sts` capability.
+///
+/// Which keys are meaningful depends on the connection the policy was received over.
+/// Over plaintext, only [`port`][StsPolicy::port] is used,
+/// and clients should reconnect to it using TLS.
+/// Over TLS, [`duration`][StsPolicy::duration] is how long clients should
+/// persist the policy and refuse to connect to the server without TLS.
+/// See the [IRCv3 specification](https://ircv3.net/specs/extensions/sts) for details.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct StsPolicy {
+    /// How long the policy should be persisted for, from the `duration` key.
+    ///
+    /// A duration of zero means that any persisted policy should be removed.
+    pub duration: Option<Duration>,
+    /// The port on which the server accepts TLS connections, from the `port` key.
+    pub port: Option<u16>,
+    /// Whether the server consents to being on STS preload lists, from the `preload` key.
+    pub preload: bool,
+}
+
+impl StsPolicy {
+    /// Creates a new empty `StsPolicy`.
+    pub const fn new() -> Self {
+        StsPolicy { duration: None, port: None, preload: false }
+    }
+    /// Parses a policy from the value of the `sts` capability.
+    ///
+    /// Unknown keys are ignored, as the specification requires.
+    pub fn parse(value: &[u8]) -> Result<Self, ParseError> {
+        fn number<T: std::str::FromStr>(name: &'static str, value: &[u8]) -> Result<T, ParseError>
+        where
+            T::Err: std::error::Error + Send + Sync + 'static,
+        {
+            let value = std::str::from_utf8(value)
+                .map_err(|e| ParseError::InvalidField(name.into(), e.into()))?;
+            value.parse().map_err(|e: T::Err| ParseError::InvalidField(name.into(), e.into()))
+        }
+        let mut policy = StsPolicy::new();
+        for pair in value.split(|b| *b == b',').filter(|pair| !pair.is_empty()) {
+            let mut split = pair.splitn(2, |b| *b == b'=');
+            let key = split.next().unwrap_or_default();
+            let value = split.next().unwrap_or_default();
+            match key {
+                b"duration" => {
+                    policy.duration = Some(Duration::from_secs(number("sts duration", value)?));
+                }
+                b"port" => policy.port = Some(number("sts port", value)?),
+                b"preload" => policy.preload = true,
+                _ => (),
+            }
+        }
+        Ok(policy)
+    }
+}
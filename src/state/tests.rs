@@ -1,6 +1,10 @@
 use crate::state::StatusModes;
 
-use super::{Mode, ModeSet, ModeType, ModeTypes};
+use super::{Mode, ModeChange, ModeMap, ModeSet, ModeType, ModeTypes, ServerChanModes};
+use crate::{
+    names::{ISupport, NameMap},
+    string::{Arg, Key, Word},
+};
 
 static MODE_O: Mode = unsafe { Mode::new_unchecked(b'o') };
 static MODE_RL: Mode = unsafe { Mode::new_unchecked(b'r') };
@@ -73,3 +77,219 @@ fn statusmodes_basic() {
     assert_eq!(classic.get_mode(NonZeroU8::new(b'+').unwrap()), Some(MODE_V));
     assert_eq!(classic.get_mode(NonZeroU8::new(b'@').unwrap()), Some(MODE_O));
 }
+
+#[test]
+fn modetypes_cmp() {
+    use std::cmp::Ordering;
+    let small = ModeTypes::parse(b"b,k,l,nt").0;
+    let big = ModeTypes::parse(b"be,k,l,mnt").0;
+    let other = ModeTypes::parse(b"b,k,l,ns").0;
+    let moved = ModeTypes::parse(b"b,,kl,nt").0;
+    assert_eq!(small.partial_cmp(&small), Some(Ordering::Equal));
+    assert_eq!(small.partial_cmp(&big), Some(Ordering::Less));
+    assert_eq!(big.partial_cmp(&small), Some(Ordering::Greater));
+    assert_eq!(small.partial_cmp(&ModeTypes::new()), Some(Ordering::Greater));
+    assert_eq!(small.partial_cmp(&other), None);
+    assert_eq!(small.partial_cmp(&moved), None);
+}
+
+#[test]
+fn statusmodes_order() {
+    use std::{cmp::Ordering, num::NonZeroU8};
+    let modes = StatusModes::parse(b"(qaohv)~&@%+").unwrap();
+    let (q, h, v) = (Mode::new(b'q').unwrap(), Mode::new(b'h').unwrap(), MODE_V);
+    assert_eq!(modes.prefixes(), b"~&@%+");
+    let order: Vec<_> = modes.iter().map(|(m, p)| (u8::from(m), p.map(NonZeroU8::get))).collect();
+    assert_eq!(order[0], (b'q', Some(b'~')));
+    assert_eq!(order[4], (b'v', Some(b'+')));
+    assert_eq!(modes.iter().len(), 5);
+    assert_eq!(modes.cmp_modes(q, v), Some(Ordering::Greater));
+    assert_eq!(modes.cmp_modes(v, h), Some(Ordering::Less));
+    assert_eq!(modes.cmp_modes(h, h), Some(Ordering::Equal));
+    assert_eq!(modes.cmp_modes(h, MODE_RL), None);
+    // Is a user with the NAMES prefix "%+" at least halfop?
+    let set: ModeSet =
+        b"%+".iter().filter_map(|p| modes.get_mode(NonZeroU8::new(*p).unwrap())).collect();
+    let highest = modes.highest(set).unwrap();
+    assert_eq!(highest, h);
+    assert_ne!(modes.cmp_modes(highest, h), Some(Ordering::Less));
+    assert_eq!(modes.highest(ModeSet::new()), None);
+    let unprefixed = StatusModes::parse(b"(ov)@").unwrap();
+    assert_eq!(unprefixed.prefixes(), b"@");
+    assert_eq!(unprefixed.iter().nth(1), Some((MODE_V, None)));
+}
+
+fn chanmodes() -> ServerChanModes {
+    let mut isupport = NameMap::<ISupport>::new();
+    let mut edit = isupport.edit();
+    edit.insert((Key::from_str("CHANMODES"), Word::from_str("b,k,l,imnst")), ());
+    edit.insert((Key::from_str("PREFIX"), Word::from_str("(ov)@+")), ());
+    std::mem::drop(edit);
+    ServerChanModes::from_isupport(&isupport)
+}
+
+fn mode(letter: u8) -> Mode {
+    Mode::new(letter).unwrap()
+}
+
+fn args(args: &[&'static str]) -> Vec<Arg<'static>> {
+    args.iter().map(|arg| Arg::from_str(arg)).collect()
+}
+
+#[test]
+fn modemap_apply() {
+    let chanmodes = chanmodes();
+    let mut map = ModeMap::new();
+    assert_eq!(map.to_string(), "+");
+    let changes = map.apply(b"+ntk-s+lob", &args(&["key", "10", "nick", "*!*@host"]), &chanmodes);
+    let changes: Vec<_> = changes.iter().map(ModeChange::to_string).collect();
+    assert_eq!(changes, ["+n", "+t", "+k key", "+l 10", "+o nick", "+b *!*@host"]);
+    assert_eq!(map.to_string(), "+klnt key 10");
+    assert_eq!(map.param(mode(b'k')), Some(&Arg::from_str("key")));
+    assert_eq!(map.list(mode(b'b')), args(&["*!*@host"]));
+    assert!(map.contains(mode(b'b')));
+    assert!(!map.contains(mode(b'o')));
+    // Changes that do nothing are not returned, but still consume arguments.
+    let changes = map.apply(b"+tb-lv+k", &args(&["*!*@host", "nick", "key"]), &chanmodes);
+    let changes: Vec<_> = changes.iter().map(ModeChange::to_string).collect();
+    assert_eq!(changes, ["-l", "-v nick"]);
+    assert_eq!(map.to_string(), "+knt key");
+    // Missing arguments skip the change.
+    let changes = map.apply(b"-kb+k", &args(&["key", "*!*@host"]), &chanmodes);
+    let changes: Vec<_> = changes.iter().map(ModeChange::to_string).collect();
+    assert_eq!(changes, ["-k key", "-b *!*@host"]);
+    assert!(map.list(mode(b'b')).is_empty());
+    assert!(!map.contains(mode(b'b')));
+    assert_eq!(map.to_string(), "+nt");
+    // Unknown modes are assumed to not take arguments.
+    let changes = map.apply(b"-nt+X", &[], &chanmodes);
+    assert_eq!(changes.len(), 3);
+    assert_eq!(map.to_string(), "+X");
+}
+
+fn make_isupport(tokens: &[(&'static str, &'static str)]) -> NameMap<ISupport> {
+    let mut isupport = NameMap::<ISupport>::new();
+    let mut edit = isupport.edit();
+    for (key, value) in tokens {
+        edit.insert((Key::from_str(key), Word::from_str(value)), ());
+    }
+    std::mem::drop(edit);
+    isupport
+}
+
+#[test]
+fn isupport_sets() {
+    use crate::{names::isupport::*, string::tf::IrcCasemap};
+    let isupport = make_isupport(&[
+        ("CASEMAPPING", "rfc1459-strict"),
+        ("CHANTYPES", "#&"),
+        ("ELIST", "cMntu"),
+        ("STATUSMSG", "@+"),
+    ]);
+    let casemap = isupport.get_parsed(CASEMAPPING).unwrap().unwrap();
+    assert_eq!(casemap, IrcCasemap::Rfc1459Strict);
+    let chantypes = isupport.get_parsed(CHANTYPES).unwrap().unwrap();
+    assert_eq!(chantypes.to_string(), "#&");
+    assert!(chantypes.contains(b'#'));
+    assert!(!chantypes.contains(b'!'));
+    let elist = isupport.get_parsed(ELIST).unwrap().unwrap();
+    assert_eq!(elist.to_string(), "CMNTU");
+    let statusmsg = isupport.get_parsed(STATUSMSG).unwrap().unwrap();
+    assert_eq!(statusmsg.iter().collect::<Vec<_>>(), b"+@");
+    let isupport = make_isupport(&[("CASEMAPPING", "rfc7613"), ("CHANTYPES", ""), ("ELIST", "C1")]);
+    let error = isupport.get_parsed(CASEMAPPING).unwrap().unwrap_err();
+    assert!(error.to_string().contains("CASEMAPPING"));
+    assert!(isupport.get_parsed(CHANTYPES).unwrap().unwrap().is_empty());
+    assert!(isupport.get_parsed(ELIST).unwrap().is_err());
+}
+
+#[test]
+fn isupport_targmax() {
+    use crate::{names::isupport::TARGMAX, string::Cmd};
+    use std::num::NonZeroU32;
+    let isupport = make_isupport(&[("TARGMAX", "PRIVMSG:4,JOIN:,whois:1")]);
+    let targmax = isupport.get_parsed(TARGMAX).unwrap().unwrap();
+    assert_eq!(targmax.get(&Cmd::from_str("PRIVMSG")), Some(NonZeroU32::new(4)));
+    assert_eq!(targmax.get(&Cmd::from_str("JOIN")), Some(None));
+    assert_eq!(targmax.get(&Cmd::from_str("WHOIS")), Some(NonZeroU32::new(1)));
+    assert_eq!(targmax.get(&Cmd::from_str("NOTICE")), None);
+    assert_eq!(targmax.iter().count(), 3);
+    let isupport = make_isupport(&[("TARGMAX", "")]);
+    assert!(isupport.get_parsed(TARGMAX).unwrap().unwrap().is_empty());
+    for bad in ["PRIVMSG", "PRIVMSG:0", "PRIVMSG:x", ":4"] {
+        let isupport = make_isupport(&[("TARGMAX", bad)]);
+        let error = isupport.get_parsed(TARGMAX).unwrap().unwrap_err();
+        assert!(error.to_string().starts_with("invalid field TARGMAX value"), "{bad}: {error}");
+    }
+}
+
+#[test]
+fn isupport_chanlimit_extban() {
+    use crate::names::isupport::{CHANLIMIT, EXTBAN};
+    use std::num::NonZeroU32;
+    let isupport = make_isupport(&[("CHANLIMIT", "#&:50,+:"), ("EXTBAN", "~,qjr")]);
+    let chanlimit = isupport.get_parsed(CHANLIMIT).unwrap().unwrap();
+    let keys: Vec<_> = chanlimit.0.iter().map(|(types, _)| types.to_string()).collect();
+    assert_eq!(keys, ["#&", "+"]);
+    assert_eq!(chanlimit.0[0].1, NonZeroU32::new(50));
+    assert_eq!(chanlimit.0[1].1, None);
+    let extban = isupport.get_parsed(EXTBAN).unwrap().unwrap();
+    assert_eq!(extban.prefix, Some(b'~'));
+    assert_eq!(extban.types.to_string(), "jqr");
+    let isupport = make_isupport(&[("CHANLIMIT", "#:5,&:x"), ("EXTBAN", ",ABC")]);
+    let error = isupport.get_parsed(CHANLIMIT).unwrap().unwrap_err();
+    assert!(error.to_string().contains("element 1:"), "{error}");
+    let extban = isupport.get_parsed(EXTBAN).unwrap().unwrap();
+    assert_eq!(extban.prefix, None);
+    assert_eq!(extban.types.to_string(), "ABC");
+    for bad in ["~", "~~,a"] {
+        let isupport = make_isupport(&[("EXTBAN", bad)]);
+        assert!(isupport.get_parsed(EXTBAN).unwrap().is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn isupport_value_lists() {
+    use super::{CommaList, ISupportValue, KeyValueList};
+    use crate::string::Word;
+    let list = CommaList::<u32>::try_from_bytes(b"1,,2,3").unwrap();
+    assert_eq!(list.0, [1, 2, 3]);
+    let error = CommaList::<u32>::try_from_bytes(b"1,2,x").unwrap_err();
+    assert!(error.to_string().starts_with("element 2:"), "{error}");
+    let pairs = KeyValueList::<Word<'static>, Option<u16>>::try_from_bytes(b"a:1,b:").unwrap();
+    assert_eq!(pairs.get(&Word::from_str("a")), Some(&Some(1)));
+    assert_eq!(pairs.get(&Word::from_str("b")), Some(&None));
+    assert_eq!(pairs.get(&Word::from_str("c")), None);
+    let error = KeyValueList::<Word<'static>, u16>::try_from_bytes(b"a:1,b").unwrap_err();
+    assert!(error.to_string().starts_with("element 1: missing ':'"), "{error}");
+}
+
+#[test]
+fn isupport_maxlist() {
+    use crate::names::isupport::MAXLIST;
+    let isupport = make_isupport(&[("MAXLIST", "beI:100,q:50")]);
+    let maxlist = isupport.get_parsed(MAXLIST).unwrap().unwrap();
+    let (shared, limit) = maxlist.get(mode(b'e')).unwrap();
+    assert_eq!(limit, 100);
+    assert!(shared.contains(mode(b'b')) && shared.contains(mode(b'I')));
+    assert_eq!(maxlist.get(mode(b'q')), Some((ModeSet::new().with(mode(b'q')), 50)));
+    assert_eq!(maxlist.get(mode(b'Z')), None);
+    for bad in ["b", "b:x", "b-:5"] {
+        let isupport = make_isupport(&[("MAXLIST", bad)]);
+        assert!(isupport.get_parsed(MAXLIST).unwrap().is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn sts_policy() {
+    use super::StsPolicy;
+    use std::time::Duration;
+    assert_eq!(StsPolicy::parse(b"").unwrap(), StsPolicy::new());
+    let policy = StsPolicy::parse(b"duration=300,unknown,port=6697").unwrap();
+    assert_eq!(policy.duration, Some(Duration::from_secs(300)));
+    assert_eq!(policy.port, Some(6697));
+    assert!(!policy.preload);
+    assert!(StsPolicy::parse(b"preload").unwrap().preload);
+    assert!(StsPolicy::parse(b"port=66970").is_err());
+    assert!(StsPolicy::parse(b"duration=").is_err());
+}
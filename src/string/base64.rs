@@ -107,6 +107,26 @@ impl ChunkDecoder {
         Self(Vec::new(), chunk_len)
     }
 
+    /// Returns the chunk length this decoder was created with.
+    pub const fn chunk_len(&self) -> usize {
+        self.1
+    }
+
+    /// Returns the number of base64-encoded bytes added since the last decode.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if no data has been added since the last decode.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Discards the data already added to the decoder.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
     /// Adds a chunk of base64-encoded data.
     ///
     /// If `chunk` is shorter than the chunk length the decoder was provided,
@@ -242,6 +242,55 @@ impl<'a> Bytes<'a> {
         let ownership = if value.is_empty() { None } else { self.ownership.clone() };
         Bytes { value, ownership, utf8: utf8.into(), secret: self.secret }
     }
+    /// Returns the bytes of `self` within `range`, sharing ownership of `self`'s data.
+    ///
+    /// The range is in bytes, not `char`s, and may cut a UTF-8 sequence in half.
+    /// The result is only known to be UTF-8 if `self` is known to be UTF-8
+    /// and neither end of the range is in the middle of a UTF-8 sequence.
+    /// The result is secret if `self` is secret.
+    ///
+    /// # Panics
+    /// Panics if `range` is out of bounds, like slice indexing.
+    pub fn slice(&self, range: impl std::ops::RangeBounds<usize>) -> Bytes<'a> {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        // Safety: self.value[range] is a subslice of self.value.
+        let value = unsafe { &self.as_bytes_unsafe()[range] };
+        let start = value.as_ptr() as usize - self.value.as_ptr() as usize;
+        let end = start + value.len();
+        let is_boundary = |idx: usize| self.value.get(idx).map_or(true, |b| (*b as i8) >= -0x40);
+        let utf8 = if is_boundary(start) && is_boundary(end) {
+            Utf8Policy::Preserve
+        } else {
+            Utf8Policy::Recheck
+        };
+        // Safety: Same as above, utf8 is correct per the boundary checks.
+        unsafe { self.using_value(value, utf8) }
+    }
+    /// Returns the index of the first occurrence of `needle` in `self`, if any.
+    ///
+    /// An empty `needle` is found at index 0.
+    pub fn find(&self, needle: &[u8]) -> Option<usize> {
+        if needle.is_empty() {
+            return Some(0);
+        }
+        self.value.windows(needle.len()).position(|window| window == needle)
+    }
+    /// Splits `self` into the bytes before and after the first occurrence of `byte`,
+    /// excluding `byte` itself.
+    ///
+    /// Both halves share ownership of `self`'s data.
+    /// If `byte` is ASCII, both halves are known to be UTF-8 if `self` is.
+    pub fn split_once(&self, byte: u8) -> Option<(Bytes<'a>, Bytes<'a>)> {
+        let idx = self.value.iter().position(|b| *b == byte)?;
+        Some((self.slice(..idx), self.slice(idx + 1..)))
+    }
+    /// Returns `true` if `self` and `other` are equal under the provided casemapping.
+    ///
+    /// This is available on every string type, including [`Nick`][super::Nick]s
+    /// and channel names.
+    pub fn eq_ignore_case(&self, other: &[u8], casemap: super::tf::IrcCasemap) -> bool {
+        casemap.eq_bytes(self, other)
+    }
     /// Updates `self` using the provided [`Transform`].
     pub fn transform<T: Transform>(&mut self, tf: T) -> T::Value {
         let tfed = tf.transform(self);
@@ -77,6 +77,9 @@ impl Range {
         }
         if start && end {
             self.encoding = saved.encoding;
+        } else if start || end {
+            // The encoding may have been checked for a narrower range than the restored one.
+            self.encoding = std::cmp::min(self.encoding, saved.encoding);
         }
     }
     pub fn constrain<'a, T>(&self, slice: &'a [T]) -> &'a [T] {
@@ -185,6 +188,15 @@ impl<T: AsRef<[u8]>> Splitter<T> {
             self.range.start = self.range.end;
         }
     }
+    /// Removes leading ASCII spaces, but not other whitespace.
+    pub fn consume_spaces(&mut self) {
+        let slice = self.range.constrain(self.as_ref());
+        if let Some(idx) = slice.iter().position(|c| *c != b' ') {
+            self.range.start += idx;
+        } else {
+            self.range.start = self.range.end;
+        }
+    }
     /// Truncates the slice after and including the first byte for which `f` returns true.
     pub fn until_byte<F: FnMut(&u8) -> bool>(&mut self, f: F) -> &mut Self {
         if let Some(idx) = self.as_slice().iter().position(f) {
@@ -207,6 +219,30 @@ impl<T: AsRef<[u8]>> Splitter<T> {
         self.range.consume(len, true);
         self
     }
+    /// Truncates the slice before and including the last byte for which `f` returns true.
+    ///
+    /// This is the reverse of [`until_byte`][Splitter::until_byte],
+    /// and is typically used with a guard from [`save(true, false)`][Splitter::save].
+    pub fn until_byte_from_end<F: FnMut(&u8) -> bool>(&mut self, f: F) -> &mut Self {
+        if let Some(idx) = self.as_slice().iter().rposition(f) {
+            self.range.start += idx + 1;
+            if self.range.encoding == Encoding::Utf8 {
+                self.range.encoding = Encoding::Unknown;
+            }
+        }
+        self
+    }
+    /// Removes up to `count` bytes from the end of the slice.
+    ///
+    /// Returns how many bytes were removed.
+    pub fn consume_back(&mut self, count: usize) -> usize {
+        let count = std::cmp::min(count, self.range.len());
+        self.range.end -= count;
+        if count > 0 && self.range.encoding == Encoding::Utf8 {
+            self.range.encoding = Encoding::Unknown;
+        }
+        count
+    }
 }
 
 impl<'a, T: BytesNewtype<'a>> Splitter<T> {
@@ -271,6 +307,34 @@ impl<'a, T: BytesNewtype<'a>> Splitter<T> {
             Ok(U::from_unchecked(bytes))
         }
     }
+    /// Gets the previous string back to the last byte that is invalid for `U`
+    /// without consuming it.
+    ///
+    /// If `require_rest` is true, errors if this string does not contain all the remaining
+    /// bytes in `self.`
+    pub fn peek_string_from_end<U: BytesNewtype<'a>>(
+        &self,
+        require_rest: bool,
+    ) -> Result<U, InvalidString> {
+        unsafe {
+            let mut slice = self.as_slice_unsafe();
+            // Safety: We trust that U invalidity only happens on UTF-8 character boundries.
+            slice = if let Some(idx) = slice.iter().rposition(U::is_invalid) {
+                if !require_rest {
+                    &slice[idx + 1..]
+                } else {
+                    return Err(InvalidString::Byte(slice[idx]));
+                }
+            } else {
+                slice
+            };
+            if let Some(e) = U::check_others(slice) {
+                return Err(e);
+            }
+            let bytes = self.string.using_value(slice, self.is_utf8_lazy()).into_bytes();
+            Ok(U::from_unchecked(bytes))
+        }
+    }
     /// Gets the rest of the string.
     ///
     /// On success, this splitter will be empty.
@@ -303,6 +367,19 @@ impl<'a, T: BytesNewtype<'a>> Splitter<T> {
         self.range.start += next.as_ref().len();
         Ok(next)
     }
+    /// Gets the previous string back to the last byte that is invalid for `U`,
+    /// consuming it from the end of `self`.
+    ///
+    /// If `require_rest` is true, errors if this string does not contain all the remaining
+    /// bytes in `self.`
+    pub fn string_from_end<U: BytesNewtype<'a>>(
+        &mut self,
+        require_rest: bool,
+    ) -> Result<U, InvalidString> {
+        let prev = self.peek_string_from_end::<U>(require_rest)?;
+        self.range.end -= prev.as_ref().len();
+        Ok(prev)
+    }
     /// Gets the next string up to the next byte that is invalid for `U`, or default.
     ///
     /// If `require_rest` is true, returns the default if the string would not
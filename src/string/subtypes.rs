@@ -212,6 +212,142 @@ conversions!(User: Line);
 conversions!(User: Word);
 conversions!(User: Arg);
 
+#[inline(always)]
+const fn is_invalid_for_channel<const CHAIN: bool>(byte: &u8) -> bool {
+    matches!(*byte, b',' | b'\x07') || if CHAIN { is_invalid_for_word::<true>(byte) } else { false }
+}
+
+impl_subtype! {
+    "An [`Arg`] that does not contain `,` or BELL.\nIntended for use with channel names."
+    Channel: Arg
+    ChannelSafe: ArgSafe
+    is_invalid_for_channel::<true>;
+    arg_first_check;
+    |bytes| {
+        check_bytes!(bytes, is_invalid_for_channel::<false>)
+    }
+}
+conversions!(Channel: NoNul);
+conversions!(Channel: Line);
+conversions!(Channel: Word);
+conversions!(Channel: Arg);
+
+#[inline(always)]
+const fn is_invalid_for_host<const CHAIN: bool>(byte: &u8) -> bool {
+    !matches!(*byte, b'0'..=b'9' | b'A'..=b'Z' | b'a'..=b'z' | b'-' | b'.' | b':' | b'[' | b']')
+        || if CHAIN { is_invalid_for_word::<true>(byte) } else { false }
+}
+
+/// Checks the structure of an IPv6 address literal in `bytes[start..end]`.
+///
+/// This is not a full validation, but rejects anything that cannot be an IPv6 address.
+const fn check_ipv6(bytes: &[u8], start: usize, end: usize) -> Option<InvalidString> {
+    let (mut i, mut colons, mut compressed, mut group_len) = (start, 0usize, false, 0usize);
+    while i < end {
+        let byte = bytes[i];
+        match byte {
+            b':' => {
+                if i > start && bytes[i - 1] == b':' {
+                    if compressed {
+                        return Some(InvalidString::Byte(b':'));
+                    }
+                    compressed = true;
+                }
+                colons += 1;
+                group_len = 0;
+            }
+            // Embedded IPv4 addresses.
+            b'.' => group_len = 0,
+            b'0'..=b'9' | b'A'..=b'F' | b'a'..=b'f' => {
+                group_len += 1;
+                if group_len > 4 {
+                    return Some(InvalidString::Byte(byte));
+                }
+            }
+            _ => return Some(InvalidString::Byte(byte)),
+        }
+        i += 1;
+    }
+    if colons < 2 || colons > 7 {
+        return Some(InvalidString::Byte(b':'));
+    }
+    None
+}
+
+/// Checks that `bytes` is a hostname made of RFC 1123 labels or an IP address literal.
+const fn host_check(bytes: &[u8]) -> Option<InvalidString> {
+    let len = bytes.len();
+    if len == 0 {
+        return Some(InvalidString::Empty);
+    }
+    if bytes[0] == b'[' {
+        if len < 4 || bytes[len - 1] != b']' {
+            return Some(InvalidString::Byte(b'['));
+        }
+        return check_ipv6(bytes, 1, len - 1);
+    }
+    let mut i = 0usize;
+    while i < len {
+        if bytes[i] == b':' {
+            return check_ipv6(bytes, 0, len);
+        }
+        i += 1;
+    }
+    // A trailing dot denotes the root and does not count towards the length limit.
+    let max_len = if bytes[len - 1] == b'.' { 254 } else { 253 };
+    if len > max_len {
+        return Some(InvalidString::Byte(bytes[max_len]));
+    }
+    let (mut i, mut label_len) = (0usize, 0usize);
+    while i < len {
+        let byte = bytes[i];
+        match byte {
+            b'.' => {
+                if label_len == 0 {
+                    return Some(InvalidString::Byte(b'.'));
+                }
+                if bytes[i - 1] == b'-' {
+                    return Some(InvalidString::Byte(b'-'));
+                }
+                label_len = 0;
+            }
+            b'-' if label_len == 0 => return Some(InvalidString::Byte(b'-')),
+            b'0'..=b'9' | b'A'..=b'Z' | b'a'..=b'z' | b'-' => {
+                label_len += 1;
+                if label_len > 63 {
+                    return Some(InvalidString::Byte(byte));
+                }
+            }
+            _ => return Some(InvalidString::Byte(byte)),
+        }
+        i += 1;
+    }
+    if bytes[len - 1] == b'-' {
+        return Some(InvalidString::Byte(b'-'));
+    }
+    None
+}
+
+impl_subtype! {
+    "A [`Word`] that is a valid hostname or IP address literal.\n\
+    Hostnames consist of dot-separated labels of up to 63 ASCII letters, digits, and hyphens,\n\
+    which may not begin or end with a hyphen, as specified by RFC 1123.\n\
+    IPv6 addresses may optionally be enclosed in square brackets."
+    Host: Word
+    HostSafe: WordSafe
+    is_invalid_for_host::<true>;
+    host_check;
+    |bytes| {
+        if let Some(e) = host_check(bytes) {
+            return Some(e);
+        }
+        check_bytes!(bytes, is_invalid_for_host::<false>)
+    }
+}
+conversions!(Host: NoNul);
+conversions!(Host: Line);
+conversions!(Host: Word);
+
 #[inline(always)]
 const fn cmd_byte_check(byte: &u8) -> bool {
     !byte.is_ascii_uppercase()
@@ -14,6 +14,83 @@ impl Line<'static> {
     }
 }
 
+impl<'a> Line<'a> {
+    /// Returns an iterator over chunks of `self` that are at most `limit` bytes long,
+    /// such as for splitting a long message over several `PRIVMSG`s.
+    ///
+    /// Chunks are split at ASCII whitespace where possible
+    /// and never in the middle of a UTF-8 sequence.
+    /// See [`WrapAt`][crate::string::tf::WrapAt] for details.
+    /// Yields nothing if `self` is empty.
+    pub fn chunks(&self, limit: usize) -> crate::string::tf::LineChunks<'a> {
+        crate::string::tf::LineChunks::new(self.clone(), limit)
+    }
+}
+
+impl<'a> Host<'a> {
+    /// Returns the IP address this host is a literal for, if any.
+    pub fn ip_addr(&self) -> Option<std::net::IpAddr> {
+        let bytes = self.as_bytes();
+        let bytes = bytes.strip_prefix(b"[").and_then(|b| b.strip_suffix(b"]")).unwrap_or(bytes);
+        // Hosts are always ASCII.
+        std::str::from_utf8(bytes).ok()?.parse().ok()
+    }
+    /// Returns `self` as a `str` suitable for name resolution,
+    /// without square brackets around IPv6 addresses.
+    pub fn as_unbracketed_str(&self) -> &str {
+        let bytes = self.as_bytes();
+        let bytes = bytes.strip_prefix(b"[").and_then(|b| b.strip_suffix(b"]")).unwrap_or(bytes);
+        // Hosts are always ASCII.
+        unsafe { std::str::from_utf8_unchecked(bytes) }
+    }
+}
+
+impl Host<'static> {
+    /// Converts an internationalized domain name into its ASCII form using IDNA,
+    /// e.g. `"libera.中国"` into `"libera.xn--fiqs8s"`.
+    ///
+    /// If the name cannot be converted, errors with its first non-ASCII byte, if any.
+    #[cfg(feature = "idna")]
+    pub fn from_unicode(name: &str) -> Result<Self, InvalidString> {
+        let Ok(ascii) = idna::domain_to_ascii(name) else {
+            let byte = name.bytes().find(|b| !b.is_ascii()).unwrap_or(b'.');
+            return Err(InvalidString::Byte(byte));
+        };
+        Host::from_bytes(ascii)
+    }
+}
+
+impl Channel<'_> {
+    /// Checks `self` against the server's `CHANTYPES` and `CHANNELLEN` ISUPPORT tokens.
+    ///
+    /// If the server does not advertise `CHANTYPES`, channels are assumed to begin with
+    /// either `#` or `&`.
+    pub fn validate(
+        &self,
+        isupport: &crate::names::NameMap<crate::names::ISupport>,
+    ) -> Result<(), crate::error::ParseError> {
+        use crate::error::ParseError;
+        use crate::names::isupport::{CHANNELLEN, CHANTYPES};
+        // SAFE: Channel is non-empty.
+        let prefix = unsafe { *self.0.get_unchecked(0) };
+        let valid_prefix = match isupport.get_parsed(CHANTYPES) {
+            Some(Ok(chantypes)) => chantypes.contains(prefix),
+            _ => matches!(prefix, b'#' | b'&'),
+        };
+        if !valid_prefix {
+            let msg = format!("`{}` is not a channel prefix", prefix.escape_ascii());
+            return Err(ParseError::InvalidField("channel".into(), msg.into()));
+        }
+        if let Some(Ok(limit)) = isupport.get_parsed(CHANNELLEN) {
+            if self.len() > limit.get() as usize {
+                let msg = format!("longer than CHANNELLEN ({limit})");
+                return Err(ParseError::InvalidField("channel".into(), msg.into()));
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Key<'_> {
     /// Returns `true` if this string could be a client tag.
     pub fn is_client_tag(&self) -> bool {
@@ -60,6 +137,35 @@ impl User<'static> {
     }
 }
 
+impl Arg<'static> {
+    /// Creates a new [`Arg`] containing the decimal representation of `int`,
+    /// such as for channel limits or ports.
+    ///
+    /// Any integer up to 64 bits wide can be used.
+    /// `usize` and `isize` values need to be converted to one of these first.
+    pub fn from_int(int: impl Into<i128>) -> Self {
+        let int: i128 = int.into();
+        // Long enough for any i128, including the sign.
+        let mut buf = [0u8; 40];
+        let mut idx = buf.len();
+        let mut rest = int.unsigned_abs();
+        loop {
+            idx -= 1;
+            buf[idx] = b'0' + (rest % 10) as u8;
+            rest /= 10;
+            if rest == 0 {
+                break;
+            }
+        }
+        if int < 0 {
+            idx -= 1;
+            buf[idx] = b'-';
+        }
+        // SAFE: Decimal digits with an optional leading `-` are always a valid Arg.
+        unsafe { Arg::from_unchecked(buf[idx..].to_vec().into()) }
+    }
+}
+
 impl<'a> Cmd<'a> {
     /// Tries to convert `word` into an instance of this type, uppercasing where necessary.
     pub fn from_word(word: impl Into<Word<'a>>) -> Result<Self, InvalidString> {
@@ -1,4 +1,4 @@
-use super::{Arg, Line, Word};
+use super::{Arg, Channel, Host, Line, Word};
 
 #[test]
 pub fn line() {
@@ -28,3 +28,63 @@ pub fn arg() {
     assert!(Arg::from_bytes("").is_err());
     assert!(Arg::from_bytes(":foo").is_err());
 }
+
+#[test]
+pub fn channel() {
+    use crate::{
+        names::{ISupport, NameMap},
+        string::Key,
+    };
+    assert!(Channel::from_bytes("#foo").is_ok());
+    assert!(Channel::from_bytes("").is_err());
+    assert!(Channel::from_bytes("#foo,#bar").is_err());
+    assert!(Channel::from_bytes("#foo\x07").is_err());
+    assert!(Channel::from_bytes("#foo bar").is_err());
+    let mut isupport = NameMap::<ISupport>::new();
+    assert!(Channel::from_str("&local").validate(&isupport).is_ok());
+    assert!(Channel::from_str("!chan").validate(&isupport).is_err());
+    isupport.edit().insert((Key::from_str("CHANTYPES"), Word::from_str("#!")), ());
+    isupport.edit().insert((Key::from_str("CHANNELLEN"), Word::from_str("5")), ());
+    assert!(Channel::from_str("!chan").validate(&isupport).is_ok());
+    assert!(Channel::from_str("&chan").validate(&isupport).is_err());
+    assert!(Channel::from_str("#chann").validate(&isupport).is_err());
+}
+
+#[test]
+pub fn host() {
+    use crate::error::InvalidString;
+    for valid in ["irc.libera.chat", "localhost", "127.0.0.1", "a-b.c.", "::1", "[2001:db8::1]"] {
+        assert!(Host::from_bytes(valid).is_ok(), "{valid}");
+    }
+    let long_label = format!("{}.com", "a".repeat(64));
+    let cases = [
+        ("", InvalidString::Empty),
+        ("-irc.example", InvalidString::Byte(b'-')),
+        ("irc-.example", InvalidString::Byte(b'-')),
+        ("irc..example", InvalidString::Byte(b'.')),
+        (".example", InvalidString::Byte(b'.')),
+        ("irc_example", InvalidString::Byte(b'_')),
+        ("irc.example/foo", InvalidString::Byte(b'/')),
+        ("[::1", InvalidString::Byte(b'[')),
+        ("[irc.example]", InvalidString::Byte(b'i')),
+        ("1:2", InvalidString::Byte(b':')),
+        ("1::2::3", InvalidString::Byte(b':')),
+        ("12345::", InvalidString::Byte(b'5')),
+        (long_label.as_str(), InvalidString::Byte(b'a')),
+    ];
+    for (invalid, error) in cases {
+        assert_eq!(Host::from_bytes(invalid).unwrap_err(), error, "{invalid}");
+    }
+    assert!(Host::from_super(Word::from_str("caf\u{e9}.example")).is_err());
+    let host = Host::from_str("[2001:db8::1]");
+    assert_eq!(host.as_unbracketed_str(), "2001:db8::1");
+    assert!(host.ip_addr().unwrap().is_ipv6());
+    assert!(Host::from_str("irc.example").ip_addr().is_none());
+}
+
+#[cfg(feature = "idna")]
+#[test]
+pub fn host_idna() {
+    assert_eq!(Host::from_unicode("libera.\u{4e2d}\u{56fd}").unwrap(), "libera.xn--fiqs8s");
+    assert_eq!(Host::from_unicode("IRC.Example").unwrap(), "irc.example");
+}
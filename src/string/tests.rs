@@ -53,6 +53,26 @@ fn builder() {
     assert_eq!(built.is_utf8_lazy(), Some(true));
 }
 
+#[test]
+fn bytes_slice() {
+    let bytes = Bytes::from("key=vålue".to_owned());
+    assert_eq!(bytes.find(b"=v"), Some(3));
+    assert_eq!(bytes.find(b"=x"), None);
+    let (key, value) = bytes.split_once(b'=').unwrap();
+    assert_eq!(key, "key");
+    assert_eq!(value, "vålue");
+    assert!(key.is_owning() && value.is_owning());
+    assert_eq!(key.as_ptr(), bytes.as_ptr());
+    assert_eq!(value.is_utf8_lazy(), Some(true));
+    // Cuts "å" in half.
+    let cut = bytes.slice(4..6);
+    assert_eq!(cut, b"v\xc3");
+    assert_eq!(cut.is_utf8_lazy(), None);
+    assert_eq!(bytes.slice(..0).len(), 0);
+    assert!(bytes.split_once(b' ').is_none());
+    assert!(bytes.secret().slice(1..).is_secret());
+}
+
 #[test]
 fn splitter_basic() {
     let mut splitter = Splitter::new(Line::from_str("foo  bar baz"));
@@ -73,6 +93,67 @@ fn splitter_until() {
     assert_eq!(splitter.next_byte(), Some(b'.'));
 }
 
+#[test]
+fn splitter_from_end() {
+    let mut splitter = Splitter::new(Line::from_str("foo bar.baz 123"));
+    let word = splitter.string::<Word<'static>>(false).unwrap();
+    assert_eq!(word, "foo");
+    let word = splitter.string_from_end::<Word<'static>>(false).unwrap();
+    assert_eq!(word, "123");
+    assert_eq!(splitter.consume_back(1), 1);
+    assert!(splitter.string_from_end::<Word<'static>>(true).is_err());
+    let word: Word = splitter
+        .save(true, false)
+        .until_byte_from_end(|b| *b == b'.')
+        .string_from_end(true)
+        .unwrap();
+    assert_eq!(word, "baz");
+    assert_eq!(splitter.rnext_byte(), Some(b'.'));
+    // Saved indices restore only what they were asked to.
+    let word: Word = splitter.save(false, true).string(false).unwrap();
+    assert_eq!(word, "");
+    splitter.next_byte();
+    splitter.save(true, true).consume_back(2);
+    assert_eq!(splitter.as_slice(), b"bar");
+    splitter.save(false, true).consume_back(2);
+    assert_eq!(splitter.as_slice(), b"bar");
+    splitter.save(true, false).consume_back(2);
+    assert_eq!(splitter.as_slice(), b"b");
+    assert_eq!(splitter.consume_back(5), 1);
+    assert!(splitter.is_empty());
+}
+
+#[test]
+fn splitter_from_end_utf8() {
+    let mut splitter = Splitter::new(Line::from_str("a\u{e9} b\u{e9}"));
+    assert!(splitter.is_utf8_lazy());
+    let word = splitter.string_from_end::<Word<'static>>(false).unwrap();
+    assert_eq!(word, "b\u{e9}");
+    assert!(splitter.is_utf8_lazy());
+    splitter.consume_back(1);
+    assert!(!splitter.is_utf8_lazy());
+    assert!(splitter.check_encoding().is_ok());
+    assert!(splitter.is_utf8_lazy());
+    splitter.consume_back(1);
+    assert!(!splitter.is_utf8_lazy());
+    assert!(splitter.check_encoding().is_err());
+}
+
+#[test]
+fn splitter_restore_utf8() {
+    let mut splitter = Splitter::new(Word::from_bytes(b"a\xff".as_slice()).unwrap());
+    {
+        let mut splitter = splitter.save_end();
+        splitter.until_count(1);
+        assert!(splitter.check_encoding().is_ok());
+        assert!(splitter.is_utf8_lazy());
+    }
+    // Only the prefix was checked.
+    assert!(!splitter.is_utf8_lazy());
+    let rest: Word = splitter.rest().unwrap();
+    assert_eq!(rest.to_utf8(), None);
+}
+
 #[test]
 fn map_bytes() {
     fn minus_to_plus(byte: &u8) -> u8 {
@@ -164,3 +245,230 @@ mod base64 {
         assert_eq!(encoder.next(), None);
     }
 }
+
+#[test]
+fn casemap_compare() {
+    use super::{tf::IrcCasemap, Nick};
+    use std::cmp::Ordering;
+    let a = Nick::from_str("Foo[Bar]~");
+    let b = Nick::from_str("foo{bar}^");
+    assert!(a.eq_ignore_case(&b, IrcCasemap::Rfc1459));
+    assert!(!a.eq_ignore_case(&b, IrcCasemap::Rfc1459Strict));
+    assert!(!a.eq_ignore_case(&b, IrcCasemap::Ascii));
+    assert!(a.eq_ignore_case(b"FOO[BAR]~", IrcCasemap::Ascii));
+    assert!(!a.eq_ignore_case(b"foo", IrcCasemap::Rfc1459));
+    let chan = Word::from_str("#Chan\\");
+    assert!(chan.eq_ignore_case(b"#chan|", IrcCasemap::default()));
+    assert_eq!(IrcCasemap::Rfc1459.cmp_bytes(&a, &b), Ordering::Equal);
+    assert_eq!(IrcCasemap::Ascii.cmp_bytes(&a, &b), Ordering::Less);
+    assert_eq!(IrcCasemap::Ascii.cmp_bytes(b"B", b"a"), Ordering::Greater);
+    assert_eq!(IrcCasemap::Rfc1459.fold(a.clone()), "foo{bar}^");
+    assert_eq!(IrcCasemap::Rfc1459Strict.fold(a.clone()), "foo{bar}~");
+    assert_eq!(IrcCasemap::Ascii.fold(a), "foo[bar]~");
+}
+
+#[test]
+fn casemap_isupport() {
+    use super::{tf::IrcCasemap, Key};
+    use crate::names::{ISupport, NameMap};
+    let mut isupport = NameMap::<ISupport>::new();
+    assert_eq!(IrcCasemap::from_isupport(&isupport), IrcCasemap::Rfc1459);
+    isupport.edit().insert((Key::from_str("CASEMAPPING"), Word::from_str("ascii")), ());
+    assert_eq!(IrcCasemap::from_isupport(&isupport), IrcCasemap::Ascii);
+    isupport.edit().insert((Key::from_str("CASEMAPPING"), Word::from_str("rfc7613")), ());
+    assert_eq!(IrcCasemap::from_isupport(&isupport), IrcCasemap::Rfc1459);
+}
+
+#[test]
+fn secretbuf_grow() {
+    use super::SecretBuf;
+    let mut buf = SecretBuf::with_capacity(4);
+    buf.push_slice(b"abcd");
+    buf.push_slice(&[b'e'; 100]);
+    buf.push_cstr(b"f");
+    assert_eq!(buf.as_bytes().len(), 106);
+    assert!(buf.as_bytes().starts_with(b"abcde"));
+    assert!(buf.as_bytes().ends_with(b"ef\0"));
+}
+
+#[test]
+fn wrap_at() {
+    use super::tf::WrapAt;
+    let mut line = Line::from_str("hello there world");
+    let rest = line.transform(WrapAt { limit: 13, prefer_space: true });
+    assert_eq!(line, "hello there");
+    assert_eq!(rest, 12);
+    let mut line = Line::from_str("hello there world");
+    let rest = line.transform(WrapAt { limit: 13, prefer_space: false });
+    assert_eq!(line, "hello there w");
+    assert_eq!(rest, 13);
+    let mut line = Line::from_str("short");
+    assert_eq!(line.transform(WrapAt { limit: 13, prefer_space: true }), 5);
+    assert_eq!(line, "short");
+}
+
+#[test]
+fn line_chunks() {
+    // A single long word is cut at the limit.
+    let word = "a".repeat(600);
+    let line = Line::from_bytes(word.as_str()).unwrap();
+    let chunks: Vec<_> = line.chunks(256).collect();
+    assert_eq!(chunks.iter().map(Line::len).collect::<Vec<_>>(), [256, 256, 88]);
+    // Multi-byte characters straddling the limit are kept whole.
+    let line = Line::from_str("ab\u{00E9}\u{1F600}cd");
+    let chunks: Vec<_> = line.chunks(4).collect();
+    assert_eq!(chunks, ["ab\u{00E9}", "\u{1F600}", "cd"]);
+    assert!(chunks.iter().all(|chunk| chunk.to_utf8().is_some()));
+    // Characters longer than the limit are not split.
+    let chunks: Vec<_> = Line::from_str("\u{1F600}\u{1F600}").chunks(2).collect();
+    assert_eq!(chunks, ["\u{1F600}", "\u{1F600}"]);
+    // Whitespace at the split points and at the end is dropped.
+    let chunks: Vec<_> = Line::from_str("one two  three   ").chunks(5).collect();
+    assert_eq!(chunks, ["one", "two", "three"]);
+    let chunks: Vec<_> = Line::from_str("one two  ").chunks(20).collect();
+    assert_eq!(chunks, ["one two  "]);
+    let chunks: Vec<_> = Line::from_str("  leading").chunks(4).collect();
+    assert_eq!(chunks, ["  le", "adin", "g"]);
+    assert_eq!(Line::default().chunks(4).next(), None);
+    // Continuation lines can have a prefix.
+    let chunks: Vec<_> =
+        Line::from_str("to be or not to be").chunks(8).with_prefix(Line::from_str("> ")).collect();
+    assert_eq!(chunks, ["to be or", "> not to", "> be"]);
+    let chunks: Vec<_> =
+        Line::from_str("abcdef").chunks(2).with_prefix(Line::from_str(">>")).collect();
+    assert_eq!(chunks, ["ab", ">>c", ">>d", ">>e", ">>f"]);
+}
+
+mod utf8 {
+    use crate::string::{Bytes, Splitter, Word};
+    use proptest::prelude::*;
+
+    /// Mostly-UTF-8 strings with some spaces and dots to split on.
+    fn bytes() -> impl Strategy<Value = Vec<u8>> {
+        prop_oneof![
+            "[a .\u{e9}\u{1F600}]{0,12}".prop_map(String::into_bytes),
+            proptest::collection::vec(prop_oneof![Just(b' '), Just(b'.'), any::<u8>()], 0..12),
+        ]
+    }
+
+    fn new_bytes(value: Vec<u8>, check: bool) -> Bytes<'static> {
+        let bytes = Bytes::from(value);
+        if check {
+            bytes.to_utf8();
+        }
+        bytes
+    }
+
+    /// Asserts that `bytes`'s cached UTF-8 validity, if any, is correct.
+    fn assert_cache(bytes: &Bytes<'_>) {
+        if let Some(utf8) = bytes.is_utf8_lazy() {
+            assert_eq!(utf8, std::str::from_utf8(bytes).is_ok(), "wrong cache for {bytes:?}");
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        NextByte,
+        RNextByte,
+        ConsumeBack(usize),
+        ConsumeSpaces,
+        UntilCount(usize),
+        UntilByteEq(u8),
+        UntilByteFromEnd(u8),
+        CheckEncoding,
+        String,
+        StringFromEnd,
+        Rest,
+        /// Saves the indices while performing the next `n` operations.
+        Save(bool, bool, usize),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        let byte = prop_oneof![Just(b' '), Just(b'.'), Just(0xc3u8), any::<u8>()];
+        prop_oneof![
+            1 => Just(Op::NextByte),
+            1 => Just(Op::RNextByte),
+            1 => (0usize..4).prop_map(Op::ConsumeBack),
+            1 => Just(Op::ConsumeSpaces),
+            2 => (0usize..12).prop_map(Op::UntilCount),
+            1 => byte.clone().prop_map(Op::UntilByteEq),
+            1 => byte.prop_map(Op::UntilByteFromEnd),
+            3 => Just(Op::CheckEncoding),
+            1 => Just(Op::String),
+            1 => Just(Op::StringFromEnd),
+            1 => Just(Op::Rest),
+            3 => (any::<bool>(), any::<bool>(), 1usize..4).prop_map(|(s, e, n)| Op::Save(s, e, n)),
+        ]
+    }
+
+    /// Performs `ops` on `splitter`, checking the UTF-8 cache of it and everything it returns.
+    fn run<'a>(splitter: &mut Splitter<Bytes<'a>>, ops: &mut impl Iterator<Item = Op>) {
+        let Some(op) = ops.next() else {
+            return;
+        };
+        match op {
+            Op::NextByte => drop(splitter.next_byte()),
+            Op::RNextByte => drop(splitter.rnext_byte()),
+            Op::ConsumeBack(n) => drop(splitter.consume_back(n)),
+            Op::ConsumeSpaces => splitter.consume_spaces(),
+            Op::UntilCount(n) => drop(splitter.until_count(n)),
+            Op::UntilByteEq(b) => drop(splitter.until_byte_eq(b)),
+            Op::UntilByteFromEnd(b) => drop(splitter.until_byte_from_end(|c| *c == b)),
+            Op::CheckEncoding => drop(splitter.check_encoding()),
+            Op::String => {
+                if let Ok(word) = splitter.string::<Word>(false) {
+                    assert_cache(&word.into());
+                }
+            }
+            Op::StringFromEnd => {
+                if let Ok(word) = splitter.string_from_end::<Word>(false) {
+                    assert_cache(&word.into());
+                }
+            }
+            Op::Rest => assert_cache(&splitter.rest::<Bytes>().unwrap()),
+            Op::Save(start, end, n) => {
+                let mut guard = splitter.save(start, end);
+                let mut inner = ops.take(n).collect::<Vec<_>>().into_iter();
+                while inner.len() > 0 {
+                    run(&mut guard, &mut inner);
+                }
+            }
+        }
+        if splitter.is_utf8_lazy() {
+            let slice = splitter.as_slice();
+            assert!(std::str::from_utf8(slice).is_ok(), "wrong cache for {slice:?}");
+        }
+        assert_cache(&splitter.peek_rest::<Bytes>().unwrap());
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(1024))]
+
+        #[test]
+        fn slice_utf8(value in bytes(), check: bool, start in 0usize..12, len in 0usize..12) {
+            let bytes = new_bytes(value, check);
+            let start = std::cmp::min(start, bytes.len());
+            let end = std::cmp::min(start + len, bytes.len());
+            let slice = bytes.slice(start..end);
+            assert_cache(&slice);
+            prop_assert_eq!(slice.to_utf8(), std::str::from_utf8(&bytes[start..end]).ok());
+            if let Some((a, b)) = bytes.split_once(b'.') {
+                assert_cache(&a);
+                assert_cache(&b);
+            }
+        }
+
+        #[test]
+        fn splitter_utf8(
+            value in bytes(),
+            check: bool,
+            ops in proptest::collection::vec(op(), 0..16),
+        ) {
+            let mut splitter = Splitter::new(new_bytes(value, check));
+            let mut ops = ops.into_iter();
+            while ops.len() > 0 {
+                run(&mut splitter, &mut ops);
+            }
+        }
+    }
+}
@@ -3,8 +3,9 @@
 mod casemap;
 mod escape;
 mod trim;
+mod wrap;
 
-pub use {casemap::*, escape::*, trim::*};
+pub use {casemap::*, escape::*, trim::*, wrap::*};
 
 use super::{Transformation, Utf8Policy};
 
@@ -1,6 +1,9 @@
-use crate::string::{
-    ArgSafe, Bytes, CmdSafe, KeySafe, LineSafe, NickSafe, NoNulSafe, Transform, Transformation,
-    UserSafe, Utf8Policy, WordSafe,
+use crate::{
+    names::{isupport::CASEMAPPING, ISupport, NameMap},
+    string::{
+        ArgSafe, Bytes, CmdSafe, KeySafe, LineSafe, Nick, NickSafe, NoNulSafe, Transform,
+        Transformation, UserSafe, Utf8Policy, WordSafe,
+    },
 };
 
 /// ASCII casemapping, generic over whether it's uppercase or lowercase.
@@ -37,7 +40,7 @@ unsafe impl CmdSafe for AsciiCasemap<true> {}
 ///
 /// Does not map UTF-8 characters, but preserves UTF-8 validity.
 /// Maps from uppercase to lowercase.
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
 #[non_exhaustive]
 pub enum IrcCasemap {
     /// ASCII lowercase mapping.
@@ -45,12 +48,16 @@ pub enum IrcCasemap {
     /// ASCII casemapping, plus `[\]` are mapped to `{|}`.
     Rfc1459Strict,
     /// RFC-1459 strict casemapping, plus `~` is mapped to `^`.
+    ///
+    /// This is the default, as it is what servers are assumed to use
+    /// if they do not specify otherwise.
+    #[default]
     Rfc1459,
 }
 
 impl IrcCasemap {
     /// Creates a casemap from the given name.
-    pub fn from_name(name: &'static [u8]) -> Option<IrcCasemap> {
+    pub fn from_name(name: &[u8]) -> Option<IrcCasemap> {
         match name {
             b"ascii" => Some(IrcCasemap::Ascii),
             b"rfc1459" => Some(IrcCasemap::Rfc1459),
@@ -58,6 +65,36 @@ impl IrcCasemap {
             _ => None,
         }
     }
+    /// Returns the casemap specified by the `CASEMAPPING` ISUPPORT token.
+    ///
+    /// Returns the default of [`IrcCasemap::Rfc1459`]
+    /// if the token is missing or names an unsupported casemapping.
+    pub fn from_isupport<T>(isupport: &NameMap<ISupport, T>) -> Self {
+        isupport.get_parsed(CASEMAPPING).and_then(Result::ok).unwrap_or_default()
+    }
+    /// Maps one byte.
+    pub fn map_byte(self, byte: u8) -> u8 {
+        match self {
+            IrcCasemap::Ascii => byte.to_ascii_lowercase(),
+            IrcCasemap::Rfc1459Strict => rfc1459_strict(&byte),
+            IrcCasemap::Rfc1459 => rfc1459(&byte),
+        }
+    }
+    /// Returns `true` if the two strings are equal under this casemapping.
+    pub fn eq_bytes(self, a: &[u8], b: &[u8]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| self.map_byte(*a) == self.map_byte(*b))
+    }
+    /// Compares the two strings under this casemapping.
+    pub fn cmp_bytes(self, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+        let a = a.iter().map(|a| self.map_byte(*a));
+        let b = b.iter().map(|b| self.map_byte(*b));
+        a.cmp(b)
+    }
+    /// Returns the normalized form of the provided nick under this casemapping.
+    pub fn fold(self, mut nick: Nick<'_>) -> Nick<'static> {
+        nick.transform(self);
+        nick.owning()
+    }
 }
 
 fn rfc1459_strict(byte: &u8) -> u8 {
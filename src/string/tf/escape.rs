@@ -54,6 +54,9 @@ pub fn escape<'a>(tag_value: impl Into<NoNul<'a>>) -> Word<'a> {
 }
 
 /// Returns an unescaped form of the provided tag value.
+///
+/// Unknown escape codes are unescaped to the escaped byte, dropping the backslash,
+/// and a trailing backslash is removed.
 pub fn unescape<'a>(tag_value: impl Into<NoNul<'a>>) -> NoNul<'a> {
     let tag_value = tag_value.into();
     let Some(first_idx) = tag_value.iter().position(|c| *c == b'\\') else {
@@ -61,14 +64,13 @@ pub fn unescape<'a>(tag_value: impl Into<NoNul<'a>>) -> NoNul<'a> {
     };
     let (mut new_bytes, rest) = unsafe {
         let (no_escape, rest) = tag_value.as_bytes_unsafe().split_at(first_idx);
-        // rest contains at least one byte because first_idx is a valid index.
-        let (first, rest) = rest.split_first().unwrap_unchecked();
+        // rest starts with the first backslash, which begins an escape code.
+        let rest = rest.get_unchecked(1..);
         let mut new_bytes = Vec::with_capacity(tag_value.len() - 1);
         new_bytes.extend_from_slice(no_escape);
-        new_bytes.push(unescape_byte(first));
         (new_bytes, rest)
     };
-    let mut esc = false;
+    let mut esc = true;
     for byte in rest {
         if esc {
             new_bytes.push(unescape_byte(byte));
@@ -0,0 +1,160 @@
+use crate::string::{
+    Bytes, BytesNewtype, Line, LineSafe, NoNulSafe, Transform, Transformation, Utf8Policy,
+};
+use std::borrow::Cow;
+
+/// Truncates a string to at most `limit` bytes, for splitting long lines into several.
+///
+/// The string is never cut in the middle of a UTF-8 sequence.
+/// If `prefer_space` is `true`, the string is cut at the last run of ASCII whitespace
+/// that keeps it within the limit, and that whitespace is removed.
+/// If there is no such whitespace, the string is cut as close to the limit as possible.
+///
+/// If the first character of the string is longer than `limit`,
+/// that character is kept in its entirety, making the string exceed the limit.
+///
+/// Yields the index in the original string where the rest of the string starts,
+/// which is the original string's length if nothing was cut.
+/// See [`Line::chunks`] for an iterator built on this transform.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct WrapAt {
+    /// The maximum length of the transformed string, in bytes.
+    pub limit: usize,
+    /// Whether to prefer cutting at ASCII whitespace.
+    pub prefer_space: bool,
+}
+
+impl WrapAt {
+    /// Returns the `(end, rest)` indices for cutting `bytes`.
+    fn cut(self, bytes: &[u8]) -> (usize, usize) {
+        if bytes.len() <= self.limit {
+            return (bytes.len(), bytes.len());
+        }
+        // Back up to the start of a UTF-8 sequence.
+        // At most 3 bytes are skipped so that invalid UTF-8 still makes progress.
+        let mut end = self.limit;
+        while end > self.limit.saturating_sub(3) && end > 0 && is_continuation(bytes[end]) {
+            end -= 1;
+        }
+        if end == 0 {
+            end = 1;
+            while end < bytes.len().min(4) && is_continuation(bytes[end]) {
+                end += 1;
+            }
+            if end == bytes.len() {
+                return (end, end);
+            }
+        }
+        if self.prefer_space {
+            let space_end = if bytes[end].is_ascii_whitespace() {
+                Some(end)
+            } else {
+                bytes[..end].iter().rposition(u8::is_ascii_whitespace)
+            };
+            if let Some(space_end) = space_end {
+                let chunk_end = bytes[..space_end]
+                    .iter()
+                    .rposition(|b| !b.is_ascii_whitespace())
+                    .map_or(0, |idx| idx + 1);
+                if chunk_end > 0 {
+                    let rest = bytes[space_end..]
+                        .iter()
+                        .position(|b| !b.is_ascii_whitespace())
+                        .map_or(bytes.len(), |idx| space_end + idx);
+                    return (chunk_end, rest);
+                }
+            }
+        }
+        (end, end)
+    }
+}
+
+fn is_continuation(byte: u8) -> bool {
+    byte & 0xC0 == 0x80
+}
+
+unsafe impl Transform for WrapAt {
+    type Value = usize;
+
+    fn transform<'a>(self, bytes: &Bytes<'a>) -> Transformation<'a, Self::Value> {
+        let slice = unsafe { bytes.as_bytes_unsafe() };
+        let (end, rest) = self.cut(slice);
+        Transformation {
+            value: rest,
+            transformed: Cow::Borrowed(&slice[..end]),
+            utf8: Utf8Policy::Preserve,
+        }
+    }
+}
+unsafe impl NoNulSafe for WrapAt {}
+unsafe impl LineSafe for WrapAt {}
+
+/// Iterator over chunks of a [`Line`], as returned by [`Line::chunks`].
+#[derive(Clone, Debug)]
+pub struct LineChunks<'a> {
+    rest: Line<'a>,
+    wrap: WrapAt,
+    prefix: Line<'a>,
+    first: bool,
+}
+
+impl<'a> LineChunks<'a> {
+    pub(crate) fn new(line: Line<'a>, limit: usize) -> Self {
+        LineChunks {
+            rest: line,
+            wrap: WrapAt { limit, prefer_space: true },
+            prefix: Line::default(),
+            first: true,
+        }
+    }
+    /// Sets whether to prefer splitting at ASCII whitespace. Defaults to `true`.
+    ///
+    /// See [`WrapAt::prefer_space`].
+    pub fn prefer_space(mut self, prefer_space: bool) -> Self {
+        self.wrap.prefer_space = prefer_space;
+        self
+    }
+    /// Sets a prefix to add to every chunk after the first, such as `"> "` for quoted text.
+    ///
+    /// The prefix counts towards the limit.
+    /// Chunks after the first will always contain at least one character after the prefix,
+    /// even if the prefix alone reaches the limit.
+    pub fn with_prefix(mut self, prefix: Line<'a>) -> Self {
+        self.prefix = prefix;
+        self
+    }
+    /// Returns the part of the line that has not been yielded yet.
+    pub fn rest(&self) -> &Line<'a> {
+        &self.rest
+    }
+}
+
+impl<'a> Iterator for LineChunks<'a> {
+    type Item = Line<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let mut wrap = self.wrap;
+        if !self.first {
+            wrap.limit = wrap.limit.saturating_sub(self.prefix.len());
+        }
+        let mut chunk = self.rest.clone();
+        let rest_idx = chunk.transform(wrap);
+        self.rest = unsafe {
+            let rest = &self.rest.as_bytes_unsafe()[rest_idx..];
+            self.rest.using_value(rest, self.rest.is_utf8_lazy())
+        };
+        if std::mem::take(&mut self.first) || self.prefix.is_empty() {
+            return Some(chunk);
+        }
+        let mut prefixed = Vec::with_capacity(self.prefix.len() + chunk.len());
+        prefixed.extend_from_slice(self.prefix.as_bytes());
+        prefixed.extend_from_slice(chunk.as_bytes());
+        // Concatenating two Lines results in a Line.
+        Some(unsafe { Line::from_unchecked(prefixed.into()) })
+    }
+}
+
+impl std::iter::FusedIterator for LineChunks<'_> {}
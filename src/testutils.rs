@@ -0,0 +1,248 @@
+//! Utilities for testing IRC software against a local mock server.
+//!
+//! [`MockServer`] (and [`MockServerTokio`] with the `tokio` feature)
+//! listens on an ephemeral port on the loopback interface
+//! and accepts one plaintext connection.
+//! Messages can then be exchanged with the client one at a time,
+//! or a canned registration exchange can be played back
+//! using [`play_registration`][MockServer::play_registration].
+
+use crate::{
+    ircmsg::{ClientMsg, ServerCodec, ServerMsg},
+    names::cmd::{CAP, NICK, USER},
+    string::Nick,
+};
+use std::{
+    io::{BufReader, Error, ErrorKind},
+    net::{Ipv4Addr, SocketAddr},
+};
+
+/// The server name used as the source of messages sent by mock servers.
+pub const SERVER_NAME: &str = "mock.server";
+
+/// Returns the messages a server sends upon successful registration as `nick`.
+///
+/// These are `RPL_WELCOME` (001), one `RPL_ISUPPORT` (005) line,
+/// and a one-line MOTD.
+pub fn welcome_burst(nick: &Nick<'_>) -> Vec<ServerMsg<'static>> {
+    [
+        format!(":{SERVER_NAME} 001 {nick} :Welcome to the mock network, {nick}"),
+        format!(":{SERVER_NAME} 005 {nick} CHANTYPES=# NETWORK=Mock :are supported by this server"),
+        format!(":{SERVER_NAME} 375 {nick} :- {SERVER_NAME} Message of the day -"),
+        format!(":{SERVER_NAME} 372 {nick} :- This server is not real."),
+        format!(":{SERVER_NAME} 376 {nick} :End of /MOTD command."),
+    ]
+    .into_iter()
+    .map(|line| ServerMsg::parse(line).unwrap())
+    .collect()
+}
+
+/// Transport-agnostic state for a canned registration exchange.
+#[derive(Default)]
+struct Registration {
+    nick: Option<Nick<'static>>,
+    user: bool,
+    negotiating: bool,
+    received: Vec<ClientMsg<'static>>,
+}
+
+impl Registration {
+    /// Processes a message from the client, returning the replies to send.
+    ///
+    /// Capability negotiation is supported, but no capabilities are offered.
+    fn handle(&mut self, msg: ClientMsg<'static>) -> Vec<ServerMsg<'static>> {
+        let mut replies = Vec::new();
+        if msg.cmd == CAP {
+            match msg.args.words().first().map(|arg| arg.as_bytes()) {
+                Some(b"LS") => {
+                    self.negotiating = true;
+                    replies.push(format!(":{SERVER_NAME} CAP * LS :"));
+                }
+                Some(b"REQ") => {
+                    let caps = msg.args.split_last().1.cloned().unwrap_or_default();
+                    replies.push(format!(":{SERVER_NAME} CAP * NAK :{caps}"));
+                }
+                Some(b"END") => self.negotiating = false,
+                _ => (),
+            }
+        } else if msg.cmd == NICK {
+            let nick =
+                msg.args.split_last().1.and_then(|nick| Nick::from_bytes(nick.as_bytes()).ok());
+            self.nick = nick.map(Nick::owning);
+        } else if msg.cmd == USER {
+            self.user = true;
+        }
+        self.received.push(msg);
+        let mut replies: Vec<_> =
+            replies.into_iter().map(|line| ServerMsg::parse(line).unwrap()).collect();
+        if let Some(nick) = self.nick.as_ref().filter(|_| self.is_done()) {
+            replies.extend(welcome_burst(nick));
+        }
+        replies
+    }
+    fn is_done(&self) -> bool {
+        self.nick.is_some() && self.user && !self.negotiating
+    }
+}
+
+fn not_accepted() -> Error {
+    Error::new(ErrorKind::NotConnected, "no connection has been accepted")
+}
+
+fn loopback() -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::LOCALHOST, 0))
+}
+
+/// A minimal IRC server that accepts one connection, for testing clients.
+///
+/// See the [module-level documentation][self] for more information.
+pub struct MockServer {
+    listener: std::net::TcpListener,
+    conn: Option<BufReader<std::net::TcpStream>>,
+    /// The codec used for reading and writing messages.
+    pub codec: ServerCodec,
+    buf_i: Vec<u8>,
+    buf_o: Vec<u8>,
+}
+
+impl MockServer {
+    /// Binds to an ephemeral port on `127.0.0.1`.
+    pub fn bind() -> std::io::Result<Self> {
+        Ok(MockServer {
+            listener: std::net::TcpListener::bind(loopback())?,
+            conn: None,
+            codec: ServerCodec::new(),
+            buf_i: Vec::new(),
+            buf_o: Vec::new(),
+        })
+    }
+    /// Returns the address this server is listening on.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+    /// Returns a plaintext [`ServerAddr`][crate::client::conn::ServerAddr] for this server.
+    #[cfg(feature = "client")]
+    pub fn server_addr(&self) -> std::io::Result<crate::client::conn::ServerAddr<'static>> {
+        let port = self.local_addr()?.port();
+        let mut addr = crate::client::conn::ServerAddr::from_host_str("127.0.0.1");
+        addr.tls = false;
+        addr.port = Some(port);
+        Ok(addr)
+    }
+    /// Waits for a client to connect, replacing any previous connection.
+    pub fn accept(&mut self) -> std::io::Result<()> {
+        let (sock, _) = self.listener.accept()?;
+        self.conn = Some(BufReader::new(sock));
+        self.buf_i.clear();
+        Ok(())
+    }
+    /// Returns the accepted connection, if any.
+    pub fn conn(&self) -> Option<&std::net::TcpStream> {
+        self.conn.as_ref().map(BufReader::get_ref)
+    }
+    /// Receives one message from the client.
+    ///
+    /// Errors with [`UnexpectedEof`][ErrorKind::UnexpectedEof] if the client disconnected
+    /// or [`NotConnected`][ErrorKind::NotConnected] if no connection was accepted.
+    pub fn recv(&mut self) -> std::io::Result<ClientMsg<'static>> {
+        let conn = self.conn.as_mut().ok_or_else(not_accepted)?;
+        self.codec.read_owning(conn, &mut self.buf_i)
+    }
+    /// Sends one message to the client.
+    pub fn send(&mut self, msg: &ServerMsg<'_>) -> std::io::Result<()> {
+        let conn = self.conn.as_mut().ok_or_else(not_accepted)?;
+        self.codec.send(msg, conn.get_mut(), &mut self.buf_o)
+    }
+    /// Plays back a successful registration exchange with the client.
+    ///
+    /// Messages are received until the client has sent `NICK` and `USER`
+    /// and has ended capability negotiation, if it began any.
+    /// `CAP LS` is answered with no capabilities and every `CAP REQ` is rejected.
+    /// The [`welcome_burst`] for the last nick sent is then sent to the client.
+    ///
+    /// Returns every message received from the client.
+    pub fn play_registration(&mut self) -> std::io::Result<Vec<ClientMsg<'static>>> {
+        let mut reg = Registration::default();
+        while !reg.is_done() {
+            let msg = self.recv()?;
+            for reply in reg.handle(msg) {
+                self.send(&reply)?;
+            }
+        }
+        Ok(reg.received)
+    }
+}
+
+/// Asynchronous version of [`MockServer`] using Tokio.
+#[cfg(feature = "tokio")]
+pub struct MockServerTokio {
+    listener: tokio::net::TcpListener,
+    conn: Option<tokio::io::BufReader<tokio::net::TcpStream>>,
+    /// The codec used for reading and writing messages.
+    pub codec: ServerCodec,
+    buf_i: Vec<u8>,
+    buf_o: Vec<u8>,
+}
+
+#[cfg(feature = "tokio")]
+impl MockServerTokio {
+    /// Binds to an ephemeral port on `127.0.0.1`.
+    pub async fn bind() -> std::io::Result<Self> {
+        Ok(MockServerTokio {
+            listener: tokio::net::TcpListener::bind(loopback()).await?,
+            conn: None,
+            codec: ServerCodec::new(),
+            buf_i: Vec::new(),
+            buf_o: Vec::new(),
+        })
+    }
+    /// Returns the address this server is listening on.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+    /// Returns a plaintext [`ServerAddr`][crate::client::conn::ServerAddr] for this server.
+    #[cfg(feature = "client")]
+    pub fn server_addr(&self) -> std::io::Result<crate::client::conn::ServerAddr<'static>> {
+        let port = self.local_addr()?.port();
+        let mut addr = crate::client::conn::ServerAddr::from_host_str("127.0.0.1");
+        addr.tls = false;
+        addr.port = Some(port);
+        Ok(addr)
+    }
+    /// Waits for a client to connect, replacing any previous connection.
+    pub async fn accept(&mut self) -> std::io::Result<()> {
+        let (sock, _) = self.listener.accept().await?;
+        self.conn = Some(tokio::io::BufReader::new(sock));
+        self.buf_i.clear();
+        Ok(())
+    }
+    /// Returns the accepted connection, if any.
+    pub fn conn(&self) -> Option<&tokio::net::TcpStream> {
+        self.conn.as_ref().map(tokio::io::BufReader::get_ref)
+    }
+    /// Receives one message from the client.
+    ///
+    /// See [`MockServer::recv`].
+    pub async fn recv(&mut self) -> std::io::Result<ClientMsg<'static>> {
+        let conn = self.conn.as_mut().ok_or_else(not_accepted)?;
+        self.codec.read_owning_tokio(conn, &mut self.buf_i).await
+    }
+    /// Sends one message to the client.
+    pub async fn send(&mut self, msg: &ServerMsg<'_>) -> std::io::Result<()> {
+        let conn = self.conn.as_mut().ok_or_else(not_accepted)?;
+        self.codec.send_tokio(msg, conn.get_mut(), &mut self.buf_o).await
+    }
+    /// Plays back a successful registration exchange with the client.
+    ///
+    /// See [`MockServer::play_registration`].
+    pub async fn play_registration(&mut self) -> std::io::Result<Vec<ClientMsg<'static>>> {
+        let mut reg = Registration::default();
+        while !reg.is_done() {
+            let msg = self.recv().await?;
+            for reply in reg.handle(msg) {
+                self.send(&reply).await?;
+            }
+        }
+        Ok(reg.received)
+    }
+}
@@ -17,6 +17,7 @@ pub unsafe trait DynSized {
         Self: 'a;
     unsafe fn layout(meta: &Self::Metadata) -> Layout;
     unsafe fn make_ref(ptr: NonNull<u8>, meta: &Self::Metadata) -> Self::Ref<'_>;
+    #[allow(dead_code)]
     unsafe fn make_ref_mut(ptr: NonNull<u8>, meta: &Self::Metadata) -> Self::RefMut<'_>;
     unsafe fn drop_in_place(ptr: NonNull<u8>, meta: &Self::Metadata);
 }
@@ -32,6 +33,7 @@ unsafe impl<T: Sized> DynSized for T {
     unsafe fn make_ref(ptr: NonNull<u8>, _: &Self::Metadata) -> Self::Ref<'_> {
         ptr.cast::<T>().as_ref()
     }
+    #[allow(dead_code)]
     unsafe fn make_ref_mut(ptr: NonNull<u8>, _: &Self::Metadata) -> Self::RefMut<'_> {
         ptr.cast::<T>().as_mut()
     }
@@ -51,6 +53,7 @@ unsafe impl<T: Sized> DynSized for [T] {
     unsafe fn make_ref(ptr: NonNull<u8>, count: &Self::Metadata) -> Self::Ref<'_> {
         std::slice::from_raw_parts(ptr.cast::<T>().as_ptr(), *count)
     }
+    #[allow(dead_code)]
     unsafe fn make_ref_mut(ptr: NonNull<u8>, count: &Self::Metadata) -> Self::RefMut<'_> {
         std::slice::from_raw_parts_mut(ptr.cast::<T>().as_ptr(), *count)
     }
@@ -1,4 +1,7 @@
-use std::borrow::Borrow;
+use std::{
+    borrow::Borrow,
+    ops::{Bound, RangeBounds},
+};
 
 pub trait KeyExtractor<T> {
     type Key: Ord + Borrow<Self::KeyBorrowed>;
@@ -138,7 +141,6 @@ fn get_impl<E, X: KeyExtractor<E>>(
     let (sorted, unsorted) = pairs.split_at(sorted_until);
     match sorted.binary_search_by(|v| X::extract_key(v).borrow().cmp(key)) {
         Ok(key) => Some(key),
-        Err(v) if v < sorted_until => None,
         Err(_) => unsorted
             .iter()
             .position(|v| X::extract_key(v).borrow() == key)
@@ -146,6 +148,27 @@ fn get_impl<E, X: KeyExtractor<E>>(
     }
 }
 
+/// Returns the subslice of `sorted` whose keys are within `range`.
+///
+/// `sorted` must be sorted by key.
+pub(crate) fn sorted_range<'a, E, X: KeyExtractor<E>>(
+    sorted: &'a [E],
+    range: &impl RangeBounds<X::KeyBorrowed>,
+) -> &'a [E] {
+    let start = match range.start_bound() {
+        Bound::Included(start) => sorted.partition_point(|e| X::extract_key(e).borrow() < start),
+        Bound::Excluded(start) => sorted.partition_point(|e| X::extract_key(e).borrow() <= start),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(end) => sorted.partition_point(|e| X::extract_key(e).borrow() <= end),
+        Bound::Excluded(end) => sorted.partition_point(|e| X::extract_key(e).borrow() < end),
+        Bound::Unbounded => sorted.len(),
+    };
+    // Ranges whose start is after their end are empty.
+    sorted.get(start..end).unwrap_or_default()
+}
+
 impl<E, X: KeyExtractor<E>> FlatMap<E, X> {
     pub const fn new() -> Self {
         FlatMap(Vec::new(), std::marker::PhantomData)
@@ -173,6 +196,10 @@ impl<E, X: KeyExtractor<E>> FlatMap<E, X> {
     pub fn as_slice(&self) -> &[E] {
         self.0.as_slice()
     }
+    /// Returns the elements whose keys are within `range`, in sorted order.
+    pub fn range(&self, range: impl RangeBounds<X::KeyBorrowed>) -> &[E] {
+        sorted_range::<E, X>(self.0.as_slice(), &range)
+    }
     /// Returns a mutable slice. Improper use of this can violate an internal invariant
     /// that keys remain in sorted order so long as this value is not mutably borrowed.
     pub fn as_slice_mut(&mut self) -> &mut [E] {
@@ -181,16 +208,50 @@ impl<E, X: KeyExtractor<E>> FlatMap<E, X> {
     pub fn clear(&mut self) {
         self.0.clear();
     }
+    /// Removes all elements for which `f` returns `false`, preserving order.
+    pub fn retain(&mut self, f: impl FnMut(&E) -> bool) {
+        self.0.retain(f);
+    }
+    /// Returns the elements of this map in sorted order.
+    pub fn into_vec(self) -> Vec<E> {
+        self.0
+    }
 }
 
 impl<E, X: KeyExtractor<E>> FlatMapEditGuard<'_, E, X> {
-    collection_methods!(src);
+    /// Returns the number of elements in `self`, sorted and otherwise.
+    pub fn len(&self) -> usize {
+        self.real_len
+    }
+    /// Returns true if `self` contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.real_len == 0
+    }
 
     /// Return a slice of all the elements in the `Vec`, sorted and otherwise.
     pub fn as_slice(&self) -> &[E] {
         let ptr = self.src.as_ptr();
         unsafe { std::slice::from_raw_parts(ptr, self.real_len) }
     }
+    /// Returns the sorted and unsorted portions of the `Vec`.
+    pub fn split_sorted(&self) -> (&[E], &[E]) {
+        self.as_slice().split_at(self.src.len())
+    }
+    /// Returns an iterator over the elements whose keys are within `range`.
+    ///
+    /// Elements from the sorted portion are yielded first in sorted order,
+    /// followed by the matching elements that were added during this edit.
+    pub fn range<R: RangeBounds<X::KeyBorrowed>>(&self, range: R) -> impl Iterator<Item = &E> {
+        let (sorted, unsorted) = self.split_sorted();
+        let sorted = sorted_range::<E, X>(sorted, &range);
+        let unsorted = unsorted.iter().filter(move |e| range.contains(X::extract_key(e).borrow()));
+        sorted.iter().chain(unsorted)
+    }
+    /// Return a mutable slice of all the elements in the `Vec`, sorted and otherwise.
+    fn as_slice_mut(&mut self) -> &mut [E] {
+        let ptr = self.src.as_mut_ptr();
+        unsafe { std::slice::from_raw_parts_mut(ptr, self.real_len) }
+    }
     /// Return the index of a given element in the full `Vec`.
     fn get_idx(&self, key: &X::KeyBorrowed) -> Option<usize> {
         let sorted_until = self.src.len();
@@ -198,11 +259,11 @@ impl<E, X: KeyExtractor<E>> FlatMapEditGuard<'_, E, X> {
     }
     pub fn get<'a>(&'a self, key: &X::KeyBorrowed) -> Option<&'a E> {
         let idx = self.get_idx(key)?;
-        Some(unsafe { self.src.get_unchecked(idx) })
+        Some(unsafe { self.as_slice().get_unchecked(idx) })
     }
     pub fn get_mut<'a>(&'a mut self, key: &X::KeyBorrowed) -> Option<&'a mut E> {
         let idx = self.get_idx(key)?;
-        Some(unsafe { self.src.get_unchecked_mut(idx) })
+        Some(unsafe { self.as_slice_mut().get_unchecked_mut(idx) })
     }
     fn push(&mut self, elem: E) -> &mut E {
         let key = X::extract_key(&elem);
@@ -228,7 +289,7 @@ impl<E, X: KeyExtractor<E>> FlatMapEditGuard<'_, E, X> {
         unsafe { self.src.set_len(sorted_until) };
         let mut ptr = self.src.as_mut_ptr();
         unsafe {
-            ptr = ptr.add(self.real_len);
+            ptr = ptr.add(self.real_len - 1);
             ptr.as_mut().unwrap_unchecked()
         }
     }
@@ -236,7 +297,7 @@ impl<E, X: KeyExtractor<E>> FlatMapEditGuard<'_, E, X> {
         let kb = X::extract_key(&elem).borrow();
         let idx = self.get_idx(kb);
         if let Some(idx) = idx {
-            let old_elem = unsafe { &mut self.src.get_unchecked_mut(idx) };
+            let old_elem = unsafe { self.as_slice_mut().get_unchecked_mut(idx) };
             Some(std::mem::replace(old_elem, elem))
         } else {
             self.push(elem);
@@ -258,7 +319,7 @@ impl<E, X: KeyExtractor<E>> FlatMapEditGuard<'_, E, X> {
         let kb = X::extract_key(&elem).borrow();
         let idx = self.get_idx(kb);
         if let Some(idx) = idx {
-            (unsafe { self.src.get_unchecked_mut(idx) }, Some(elem))
+            (unsafe { self.as_slice_mut().get_unchecked_mut(idx) }, Some(elem))
         } else {
             (self.push(elem), None)
         }
@@ -268,9 +329,11 @@ impl<E, X: KeyExtractor<E>> FlatMapEditGuard<'_, E, X> {
         // That said, removal should be infrequent, so it's probably
         // not worth adding some sort of tombstoning to the edit guard.
         let idx = self.get_idx(key)?;
+        let sorted_until = std::cmp::min(self.src.len(), idx);
+        unsafe { self.src.set_len(self.real_len) };
         let retval = self.src.swap_remove(idx);
         self.real_len -= 1;
-        unsafe { self.src.set_len(idx) };
+        unsafe { self.src.set_len(sorted_until) };
         Some(retval)
     }
     /// Removes all key-value pairs.
@@ -278,6 +341,28 @@ impl<E, X: KeyExtractor<E>> FlatMapEditGuard<'_, E, X> {
         self.src.clear();
         self.real_len = 0;
     }
+    /// Removes all elements for which `f` returns `false`, preserving order.
+    pub fn retain(&mut self, mut f: impl FnMut(&E) -> bool) {
+        let sorted_until = self.src.len();
+        unsafe { self.src.set_len(self.real_len) };
+        // Take the Vec so that a panic in `f` cannot leave the guard
+        // believing that there are more elements than there are.
+        let mut vec = std::mem::take(self.src);
+        self.real_len = 0;
+        let mut idx = 0usize;
+        let mut new_sorted_until = 0usize;
+        vec.retain(|elem| {
+            let keep = f(elem);
+            if keep && idx < sorted_until {
+                new_sorted_until += 1;
+            }
+            idx += 1;
+            keep
+        });
+        self.real_len = vec.len();
+        *self.src = vec;
+        unsafe { self.src.set_len(new_sorted_until) };
+    }
 }
 
 impl<E, X: KeyExtractor<E>> FromIterator<E> for FlatMap<E, X> {
@@ -65,3 +65,66 @@ fn flatmap_guard_forget() {
     std::mem::forget(guard);
     assert_eq!(map.len(), 2);
 }
+
+#[test]
+fn flatmap_guard_insert_unsorted() {
+    let mut map = FlatMap::<(u8, u8)>::from_vec(vec![(2, b'a')]);
+    let mut guard = map.edit();
+    // Inserted out of order, so lands in the unsorted portion.
+    guard.insert((0, b'b'));
+    assert_eq!(guard.insert((0, b'c')), Some((0, b'b')));
+    std::mem::drop(guard);
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get(&0), Some(&(0, b'c')));
+}
+
+#[test]
+fn flatmap_guard_remove_unsorted() {
+    let mut map = FlatMap::<(u8, u8)>::from_vec(vec![(2, b'a'), (3, b'b')]);
+    let mut guard = map.edit();
+    guard.insert((1, b'c'));
+    guard.insert((0, b'd'));
+    assert_eq!(guard.remove(&1), Some((1, b'c')));
+    assert_eq!(guard.get(&0), Some(&(0, b'd')));
+    std::mem::drop(guard);
+    assert_eq!(map.as_slice(), &[(0, b'd'), (2, b'a'), (3, b'b')]);
+}
+
+#[test]
+fn flatmap_guard_retain() {
+    let mut map = FlatMap::<(u8, u8)>::from_vec(vec![(1, b'a'), (3, b'b'), (5, b'c')]);
+    let mut guard = map.edit();
+    guard.insert((0, b'd'));
+    guard.insert((4, b'e'));
+    guard.retain(|(k, _)| *k != 3 && *k != 4);
+    assert_eq!(guard.len(), 3);
+    assert_eq!(guard.get(&0), Some(&(0, b'd')));
+    assert_eq!(guard.get(&5), Some(&(5, b'c')));
+    assert!(guard.get(&3).is_none());
+    guard.insert((2, b'f'));
+    std::mem::drop(guard);
+    assert_eq!(map.as_slice(), &[(0, b'd'), (1, b'a'), (2, b'f'), (5, b'c')]);
+}
+
+#[test]
+fn flatmap_range() {
+    let mut map = FlatMap::<(u8, u8)>::from_vec(vec![(1, b'a'), (3, b'b'), (5, b'c'), (7, b'd')]);
+    assert_eq!(map.range(3..6), &[(3, b'b'), (5, b'c')]);
+    assert_eq!(map.range(..=3), &[(1, b'a'), (3, b'b')]);
+    assert_eq!(map.range(6..), &[(7, b'd')]);
+    assert!(map.range(8..).is_empty());
+    assert!(map.range(4..5).is_empty());
+    #[allow(clippy::reversed_empty_ranges)]
+    let reversed = map.range(5..3);
+    assert!(reversed.is_empty());
+    let mut guard = map.edit();
+    // Unsorted tail.
+    guard.insert((4, b'e'));
+    guard.insert((0, b'f'));
+    let range: Vec<_> = guard.range(2..6).copied().collect();
+    assert_eq!(range, [(3, b'b'), (5, b'c'), (4, b'e')]);
+    assert_eq!(guard.range(..1).copied().collect::<Vec<_>>(), [(0, b'f')]);
+    assert_eq!(guard.range(8..).count(), 0);
+    std::mem::drop(guard);
+    assert_eq!(map.range(2..6), &[(3, b'b'), (4, b'e'), (5, b'c')]);
+}
@@ -0,0 +1,87 @@
+//! Drives the `vzdebug` example against a mock server.
+
+use std::{
+    io::{BufRead, BufReader, Lines, Write},
+    path::PathBuf,
+    process::{Child, ChildStdout, Command, Stdio},
+    time::Duration,
+};
+use vinezombie::{
+    ircmsg::ServerMsg,
+    names::cmd::{PRIVMSG, QUIT},
+    testutils::{MockServer, SERVER_NAME},
+};
+
+/// Returns the path to the `vzdebug` example, which `cargo test` builds alongside this test.
+fn vzdebug_path() -> PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    path.push("examples");
+    path.push(format!("vzdebug{}", std::env::consts::EXE_SUFFIX));
+    assert!(path.exists(), "{} does not exist; run this test using `cargo test`", path.display());
+    path
+}
+
+/// Kills the child process if the test fails.
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+    }
+}
+
+/// Reads lines from standard output until one contains `needle`.
+fn wait_for(stdout: &mut Lines<BufReader<ChildStdout>>, needle: &str) -> String {
+    for line in stdout.by_ref() {
+        let line = line.unwrap();
+        if line.contains(needle) {
+            return line;
+        }
+    }
+    panic!("vzdebug exited without printing {needle:?}");
+}
+
+#[test]
+fn vzdebug() {
+    let mut server = MockServer::bind().unwrap();
+    let addr = format!("irc://{}", server.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        server.accept().unwrap();
+        server.conn().unwrap().set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        server.play_registration().unwrap();
+        let msg = server.recv().unwrap();
+        assert_eq!(msg.cmd, PRIVMSG);
+        assert_eq!(msg.args.to_string(), "#test :hello world");
+        let reply = format!(":{SERVER_NAME} NOTICE Tester :you said hello");
+        server.send(&ServerMsg::parse(reply).unwrap()).unwrap();
+        assert_eq!(server.recv().unwrap().cmd, QUIT);
+    });
+    let child = Command::new(vzdebug_path())
+        .arg(addr)
+        .env("VZ_NICK", "Tester")
+        .env("NO_COLOR", "1")
+        .env_remove("VZ_SASL_USER")
+        .env_remove("VZ_SASL_PASS")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut child = KillOnDrop(child);
+    let mut stdin = child.0.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.0.stdout.take().unwrap()).lines();
+    assert!(wait_for(&mut stdout, "features:").contains("vinezombie"));
+    wait_for(&mut stdout, "<- :mock.server 001 Tester");
+    wait_for(&mut stdout, "registered as Tester");
+    writeln!(stdin, "PRIVMSG #test :hello world").unwrap();
+    assert!(wait_for(&mut stdout, " -> PRIVMSG").ends_with("#test :hello world"));
+    assert!(wait_for(&mut stdout, "NOTICE Tester")
+        .ends_with(":mock.server NOTICE Tester :you said hello"));
+    std::mem::drop(stdin);
+    wait_for(&mut stdout, " -> QUIT");
+    server.join().unwrap();
+    assert!(child.0.wait().unwrap().success());
+}